@@ -117,6 +117,8 @@ rust () {
     ./dev_scripts/utils.sh ensure_redis
 
     cargo nextest run --manifest-path ./rust/Cargo.toml --all-features $@
+    # Debug logs are only stripped from release builds:
+    cargo nextest run --manifest-path ./rust/Cargo.toml --release --features strip-debug-logs test_log_strip_debug_logs
 }
 
 rust_bench () {
@@ -1,9 +1,18 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use super::{errs::ShellErr, shell::Shell, BashErr, BashOut};
+use super::{
+    dry_run::DryRunSubstitutions,
+    env_isolation::{EnvIsolation, DEFAULT_SAFE_ENV_VARS},
+    errs::ShellErr,
+    interpreter::{run_external, Interpreter},
+    memo::Memo,
+    shell::Shell,
+    BashErr, BashOut,
+};
 use crate::prelude::*;
 
 /// Execute an arbitrary bash script.
@@ -17,6 +26,7 @@ use crate::prelude::*;
 ///
 /// Purposeful deviations from bash:
 /// - set -e is enabled by default, each cmd line will stop if it fails
+/// - set -o pipefail is supported, but off by default like bash
 ///
 /// Assume everything is unimplemented unless stated below:
 /// - `&&` and
@@ -26,14 +36,19 @@ use crate::prelude::*;
 /// - `~` home dir
 /// - `foo=bar` param setting
 /// - `$foo` param substitution
+/// - `$?` last exit code, `$1`.. positional params (see [`Bash::args`]), `$#` and `$@`/`$*`
 /// - `$(echo foo)` command substitution
+/// - `*`, `?` and `[...]` filename expansion, left as is when nothing matches
 /// - `'` quotes
 /// - `"` double quotes
 /// - `\` escaping
 /// - `(...)` simple compound commands e.g. (echo foo && echo bar)
+/// - `for x in a b c; do ...; done` loops, unquoted `$foo`/`$(...)` in the word list are split on whitespace
 /// - Basic file/stderr/stdout redirection
 ///
 /// This should theoretically work with multi line full bash scripts but only tested with single line commands.
+///
+/// When the subset isn't enough, [`Bash::interpreter`] can run the commands with a system bash or PowerShell instead, still returning a [`BashOut`].
 pub struct Bash {
     // The commands that will be loaded in to run, treated as && separated (only running the next if the last succeeded):
     cmds: Vec<String>,
@@ -41,6 +56,22 @@ pub struct Bash {
     root_dir: Option<PathBuf>,
     // Extra environment variables to run the commands with:
     env_vars: HashMap<String, String>,
+    // What executes the commands, the internal shell by default:
+    interpreter: Interpreter,
+    // How much of the parent environment is visible:
+    env_isolation: EnvIsolation,
+    // The parent vars still visible when isolated:
+    safe_env_vars: Vec<String>,
+    // Only plan external commands rather than running them:
+    dry_run: bool,
+    // How command substitutions are expanded in a dry run:
+    dry_run_substitutions: DryRunSubstitutions,
+    // The positional params, $1 onwards:
+    args: Vec<String>,
+    // Kill external commands that go this long without output, as they're likely waiting for input:
+    stdin_read_grace: Option<chrono::TimeDelta>,
+    // Skip external commands that already succeeded with the same inputs:
+    memo: Memo,
 }
 
 impl Default for Bash {
@@ -56,6 +87,17 @@ impl Bash {
             cmds: Vec::new(),
             root_dir: None,
             env_vars: HashMap::new(),
+            interpreter: Interpreter::Internal,
+            env_isolation: EnvIsolation::Inherit,
+            safe_env_vars: DEFAULT_SAFE_ENV_VARS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            dry_run: false,
+            dry_run_substitutions: DryRunSubstitutions::Stub,
+            args: Vec::new(),
+            stdin_read_grace: None,
+            memo: Memo::default(),
         }
     }
 
@@ -69,6 +111,14 @@ impl Bash {
             cmds,
             root_dir: self.root_dir,
             env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
         }
     }
 
@@ -80,6 +130,14 @@ impl Bash {
             cmds: self.cmds,
             root_dir: Some(root_dir.to_path_buf()),
             env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
         }
     }
 
@@ -91,6 +149,257 @@ impl Bash {
             cmds: self.cmds,
             root_dir: self.root_dir,
             env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Set what executes the commands, by default the internal shell ([`Interpreter::Internal`]).
+    ///
+    /// The external interpreters run all the commands in a single process, so state like vars and cd carries between them.
+    /// Results are still split into one [`super::CmdResult`] per [`Bash::cmd`], with the remaining commands not run after a non zero code.
+    /// [`BashErr::InterpreterNotFound`] is returned from [`Bash::run`] when the interpreter isn't installed.
+    pub fn interpreter(self, interpreter: Interpreter) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Set how much of the parent process environment the script can see, by default everything ([`EnvIsolation::Inherit`]).
+    ///
+    /// Useful for reproducible builds, or to stop secrets in the parent environment leaking into untrusted scripts.
+    pub fn env_isolation(self, env_isolation: EnvIsolation) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Override the parent vars still visible under [`EnvIsolation::Clean`] and [`EnvIsolation::CleanExcept`],
+    /// [`DEFAULT_SAFE_ENV_VARS`] by default.
+    pub fn safe_env_vars(self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: names.into_iter().map(Into::into).collect(),
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Show what would run without running it, e.g. for a `--dry-run` flag.
+    ///
+    /// - External commands aren't executed, instead their fully expanded arguments are recorded
+    ///   in [`super::CmdResult::planned`] (listed by [`BashOut::dry_run_plan`]) as if they succeeded with code 0 and no output.
+    /// - Builtins still run, so output is realistic (`echo`, `pwd`) and shell state (`cd`, `set`) applies to later expansions.
+    /// - File redirects are recorded but never opened, so nothing is created or written.
+    /// - Command substitutions are stubbed by default, see [`Bash::dry_run_substitutions`].
+    ///
+    /// Only supported by [`Interpreter::Internal`], other interpreters return [`BashErr::BashFeatureUnsupported`].
+    pub fn dry_run(self, dry_run: bool) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Set how command substitutions are expanded under [`Bash::dry_run`], [`DryRunSubstitutions::Stub`] by default.
+    pub fn dry_run_substitutions(self, dry_run_substitutions: DryRunSubstitutions) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Set the positional params the script sees, `$1`, `$2` etc, like args passed to a script file.
+    ///
+    /// `$#` is their count, `$@` and `$*` expand to each as a separate argument, or joined with spaces when part of a larger word.
+    /// Not supported by [`Interpreter::PowerShell`], which returns [`BashErr::BashFeatureUnsupported`].
+    pub fn args(self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: args.into_iter().map(Into::into).collect(),
+            stdin_read_grace: self.stdin_read_grace,
+            memo: self.memo,
+        }
+    }
+
+    /// Fail external commands that look to be waiting for input, rather than hanging forever, e.g. git prompting for credentials in CI.
+    ///
+    /// - External commands not piped input from an earlier stage get an immediately closed stdin, so most prompts fail straight away.
+    /// - Commands that still neither exit nor write any output for `grace` are killed,
+    ///   their [`super::CmdResult::termination`] set to [`super::CmdTermination::TimedOutAwaitingInput`] with a note in their stderr.
+    ///
+    /// This can't tell a prompt from a command that's legitimately slow and silent, so `grace` should be comfortably longer than any silent work.
+    /// Earlier stages of a pipe aren't watched, as their output goes straight to the next stage.
+    ///
+    /// Only supported by [`Interpreter::Internal`], other interpreters return [`BashErr::BashFeatureUnsupported`].
+    pub fn fail_on_stdin_read(self, grace: chrono::TimeDelta) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: Some(grace),
+            memo: self.memo,
+        }
+    }
+
+    /// Skip external commands that already succeeded with the same inputs, replaying their recorded output instead,
+    /// e.g. so build scripts don't rerun expensive steps that haven't changed.
+    ///
+    /// - The key is the fully expanded argv, the working directory, the vars added with [`Bash::env`] or set by the script,
+    ///   the visible parent vars when isolated (see [`Bash::env_isolation`]), and the contents of any [`Bash::memo_inputs`].
+    ///   The inherited parent environment isn't part of the key.
+    /// - Successful runs are recorded in `dir` with their stdout and stderr, failures are never recorded so always rerun.
+    /// - Replayed commands are listed in [`super::CmdResult::cached`].
+    /// - Only commands not part of a pipe are memoized, as their input can't be known up front.
+    /// - `dir` is kept under [`Bash::memo_max_bytes`] by evicting the least recently used entries.
+    ///
+    /// Only supported by [`Interpreter::Internal`], other interpreters return [`BashErr::BashFeatureUnsupported`].
+    pub fn memoize(self, dir: &Path) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: Memo {
+                dir: Some(dir.to_path_buf()),
+                ..self.memo
+            },
+        }
+    }
+
+    /// Add a glob of files whose contents invalidate memoized commands when changed, see [`Bash::memoize`].
+    ///
+    /// Relative to each command's working directory, e.g. `src/**/*.rs`.
+    pub fn memo_inputs(self, glob: impl Into<String>) -> Self {
+        let mut memo = self.memo;
+        memo.inputs.push(glob.into());
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo,
+        }
+    }
+
+    /// Never memoize commands matching any of these globs, e.g. for a `--no-cache` flag, see [`Bash::memoize`].
+    ///
+    /// Matched against both the program (e.g. `cargo`) and the full command line (e.g. `cargo test *`).
+    pub fn memoize_disabled_for(
+        self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let mut memo = self.memo;
+        memo.disabled_for
+            .extend(patterns.into_iter().map(Into::into));
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo,
+        }
+    }
+
+    /// Cap the total size of the [`Bash::memoize`] directory, [`super::DEFAULT_MEMO_MAX_BYTES`] by default.
+    pub fn memo_max_bytes(self, max_bytes: u64) -> Self {
+        Self {
+            cmds: self.cmds,
+            root_dir: self.root_dir,
+            env_vars: self.env_vars,
+            interpreter: self.interpreter,
+            env_isolation: self.env_isolation,
+            safe_env_vars: self.safe_env_vars,
+            dry_run: self.dry_run,
+            dry_run_substitutions: self.dry_run_substitutions,
+            args: self.args,
+            stdin_read_grace: self.stdin_read_grace,
+            memo: Memo {
+                max_bytes,
+                ..self.memo
+            },
         }
     }
 
@@ -100,8 +409,59 @@ impl Bash {
             return Ok(BashOut::empty());
         }
 
-        let mut shell = Shell::new(self.env_vars, self.root_dir)
+        let base_env = self.env_isolation.base_env(&self.safe_env_vars);
+
+        if self.interpreter != Interpreter::Internal {
+            if self.dry_run {
+                return Err(err!(
+                    BashErr::BashFeatureUnsupported(BashOut::empty()),
+                    "Dry runs are only supported by the internal interpreter, not {:?}.",
+                    self.interpreter
+                ));
+            }
+            if self.stdin_read_grace.is_some() {
+                return Err(err!(
+                    BashErr::BashFeatureUnsupported(BashOut::empty()),
+                    "Failing on stdin reads is only supported by the internal interpreter, not {:?}.",
+                    self.interpreter
+                ));
+            }
+            if self.memo.dir.is_some() {
+                return Err(err!(
+                    BashErr::BashFeatureUnsupported(BashOut::empty()),
+                    "Memoization is only supported by the internal interpreter, not {:?}.",
+                    self.interpreter
+                ));
+            }
+            if self.interpreter == Interpreter::PowerShell && !self.args.is_empty() {
+                return Err(err!(
+                    BashErr::BashFeatureUnsupported(BashOut::empty()),
+                    "Positional args aren't supported by {:?}.",
+                    self.interpreter
+                ));
+            }
+            return run_external(
+                self.interpreter,
+                self.cmds,
+                self.root_dir,
+                base_env,
+                self.env_vars,
+                self.args,
+            );
+        }
+
+        let mut shell = Shell::new(self.env_vars, base_env, self.root_dir)
             .map_err(|e| shell_to_bash_err(BashOut::empty(), e))?;
+        if self.dry_run {
+            shell.dry_run = Some(self.dry_run_substitutions);
+        }
+        shell.positional = self.args;
+        shell.stdin_read_grace = self
+            .stdin_read_grace
+            .map(|grace| grace.to_std().unwrap_or_default());
+        if self.memo.dir.is_some() {
+            shell.memo = Some(Arc::new(self.memo));
+        }
 
         if let Err(e) = shell.execute_command_strings(self.cmds) {
             return Err(shell_to_bash_err(shell.into(), e));
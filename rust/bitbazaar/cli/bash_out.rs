@@ -1,5 +1,19 @@
+use std::time::Duration;
+
+use super::{PlannedCommand, ResourceUsage};
 use crate::prelude::*;
 
+/// How a command finished, see [`CmdResult::termination`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CmdTermination {
+    /// Ran to completion, successfully or not.
+    #[default]
+    Exited,
+    /// An external command was killed for going too long without output, as it was likely waiting for input,
+    /// see [`super::Bash::fail_on_stdin_read`].
+    TimedOutAwaitingInput,
+}
+
 /// The result of an individual command.
 #[derive(Debug, Clone)]
 pub struct CmdResult {
@@ -11,6 +25,20 @@ pub struct CmdResult {
     pub stdout: String,
     /// The stderr of the command
     pub stderr: String,
+    /// The exit codes of each stage of the last pipeline run by the command, e.g. `[1, 0]` for `false | true`.
+    pub pipeline_codes: Vec<i32>,
+    /// The combined resource usage of the external commands run, None if only builtins were run or the platform doesn't support it.
+    pub resource_usage: Option<ResourceUsage>,
+    /// The resource usage of each external command run (e.g. each stage of a pipe), alongside its command line.
+    pub stage_usage: Vec<(String, ResourceUsage)>,
+    /// The commands the command would have run, only populated under [`super::Bash::dry_run`].
+    pub planned: Vec<PlannedCommand>,
+    /// How long the command took, None when not timed, e.g. external interpreters run the whole script as one process.
+    pub duration: Option<Duration>,
+    /// How the command finished.
+    pub termination: CmdTermination,
+    /// The external commands replayed from a previous run rather than run, only populated under [`super::Bash::memoize`].
+    pub cached: Vec<String>,
 }
 
 impl CmdResult {
@@ -26,8 +54,25 @@ impl CmdResult {
             code,
             stdout: stdout.into(),
             stderr: stderr.into(),
+            pipeline_codes: Vec::new(),
+            resource_usage: None,
+            stage_usage: Vec::new(),
+            planned: Vec::new(),
+            duration: None,
+            termination: CmdTermination::Exited,
+            cached: Vec::new(),
         }
     }
+
+    /// Set the usage of each external command, updating the combined usage.
+    pub(crate) fn set_stage_usage(&mut self, stage_usage: Vec<(String, ResourceUsage)>) {
+        self.resource_usage = stage_usage
+            .iter()
+            .map(|(_, usage)| *usage)
+            .filter(|usage| !usage.is_empty())
+            .reduce(|a, b| a.merge(&b));
+        self.stage_usage = stage_usage;
+    }
 }
 
 /// The result of running a command
@@ -139,6 +184,48 @@ impl BashOut {
         }
     }
 
+    /// Summarise the run: the number of commands and exit code, the combined resource usage and the heaviest external command.
+    pub fn fmt_summary(&self) -> String {
+        let mut out = format!(
+            "Ran {} command{}, exited with code: {}",
+            self.command_results.len(),
+            if self.command_results.len() == 1 {
+                ""
+            } else {
+                "s"
+            },
+            self.code()
+        );
+        let usage = self
+            .command_results
+            .iter()
+            .filter_map(|r| r.resource_usage)
+            .reduce(|a, b| a.merge(&b));
+        match usage {
+            Some(usage) => out.push_str(&format!("\nResource usage: {}", usage)),
+            None => out.push_str("\nResource usage: not recorded"),
+        }
+        // Heaviest by memory, falling back to cpu time when memory isn't known:
+        let top = self
+            .command_results
+            .iter()
+            .flat_map(|r| r.stage_usage.iter())
+            .filter(|(_, usage)| !usage.is_empty())
+            .max_by_key(|(_, usage)| (usage.max_rss_bytes, usage.total_cpu()));
+        if let Some((command, usage)) = top {
+            out.push_str(&format!("\nTop consumer: '{}' ({})", command, usage));
+        }
+        out
+    }
+
+    /// Every command that would have run under [`super::Bash::dry_run`], in order, empty when not a dry run.
+    pub fn dry_run_plan(&self) -> Vec<&PlannedCommand> {
+        self.command_results
+            .iter()
+            .flat_map(|r| r.planned.iter())
+            .collect()
+    }
+
     /// Throw an error if the last command run was not successful.
     pub fn throw_on_bad_code<T: error_stack::Context>(&self, err_variant: T) -> RResult<(), T> {
         if self.success() {
@@ -15,12 +15,16 @@ pub fn set(shell: &mut Shell, args: &[String]) -> RResult<BashOut, BuiltinErr> {
                 shell.set_e = true;
                 return Ok(BashOut::empty());
             }
+            "-o" | "+o" if args.get(1).map(|s| s.as_str()) == Some("pipefail") => {
+                shell.pipefail = arg == "-o";
+                return Ok(BashOut::empty());
+            }
             _ => {}
         }
     }
 
     Err(err!(BuiltinErr::Unsupported).attach_printable(
-        "The 'set' builtin is not fully implemented. Only 'set -e' and 'set +e' are supported.",
+        "The 'set' builtin is not fully implemented. Only 'set -e', 'set +e', 'set -o pipefail' and 'set +o pipefail' are supported.",
     ))
 }
 
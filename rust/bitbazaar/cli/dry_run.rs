@@ -0,0 +1,37 @@
+use std::{fmt, path::PathBuf};
+
+/// How command substitutions (`$(...)`) are expanded under [`super::Bash::dry_run`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DryRunSubstitutions {
+    /// Leave them as the `$(...)` placeholder, nothing in them is run.
+    #[default]
+    Stub,
+    /// Really execute them for accurate expansion, including any external commands inside,
+    /// so only use when the substitutions are known to be side effect free.
+    Execute,
+}
+
+/// A command that would have run, recorded under [`super::Bash::dry_run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCommand {
+    /// The fully expanded arguments, starting with the program.
+    pub argv: Vec<String>,
+    /// The directory the command would run in.
+    pub dir: PathBuf,
+    /// The files the command's output would be written to or its input read from, e.g. `> out.txt`.
+    ///
+    /// Relative targets are resolved against [`PlannedCommand::dir`].
+    pub redirects: Vec<String>,
+    /// True for builtins (e.g. `echo`, `cd`), which still run in a dry run, only their file redirects are skipped.
+    pub builtin: bool,
+}
+
+impl fmt::Display for PlannedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.argv.join(" "))?;
+        for redirect in &self.redirects {
+            write!(f, " {}", redirect)?;
+        }
+        Ok(())
+    }
+}
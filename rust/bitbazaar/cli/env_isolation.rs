@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+/// The parent process env vars passed through by default under [`EnvIsolation::Clean`] and [`EnvIsolation::CleanExcept`],
+/// the minimum needed for commands to be found and run normally. Override with [`super::Bash::safe_env_vars`].
+pub const DEFAULT_SAFE_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "TMPDIR",
+    // Windows equivalents, processes can fail to start without SYSTEMROOT:
+    "PATHEXT",
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+];
+
+/// How much of the parent process environment a [`super::Bash`] script can see, see [`super::Bash::env_isolation`].
+///
+/// Applies both to the commands spawned and to `$VAR` expansion in the script itself, so the two always agree.
+/// Vars added with [`super::Bash::env`] are always available.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EnvIsolation {
+    /// The full parent environment is available.
+    #[default]
+    Inherit,
+    /// Only the safe vars (see [`DEFAULT_SAFE_ENV_VARS`]) are taken from the parent environment.
+    Clean,
+    /// Like [`EnvIsolation::Clean`], additionally taking the named vars from the parent environment.
+    CleanExcept(Vec<String>),
+}
+
+impl EnvIsolation {
+    /// The parent vars to start from, None when the full parent environment should be inherited.
+    pub(crate) fn base_env(&self, safe_vars: &[String]) -> Option<HashMap<String, String>> {
+        let extra: &[String] = match self {
+            EnvIsolation::Inherit => return None,
+            EnvIsolation::Clean => &[],
+            EnvIsolation::CleanExcept(names) => names,
+        };
+        Some(
+            safe_vars
+                .iter()
+                .chain(extra.iter())
+                .filter_map(|name| std::env::var(name).ok().map(|val| (name.clone(), val)))
+                .collect(),
+        )
+    }
+}
@@ -11,6 +11,9 @@ pub enum BashErr {
 
     /// InternalError
     InternalError(BashOut),
+
+    /// InterpreterNotFound, the requested [`super::Interpreter`] isn't installed.
+    InterpreterNotFound(BashOut),
 }
 
 impl BashErr {
@@ -20,6 +23,7 @@ impl BashErr {
             BashErr::BashSyntaxError(bash_out) => bash_out,
             BashErr::BashFeatureUnsupported(bash_out) => bash_out,
             BashErr::InternalError(bash_out) => bash_out,
+            BashErr::InterpreterNotFound(bash_out) => bash_out,
         }
     }
 }
@@ -35,6 +39,7 @@ impl std::fmt::Display for BashErr {
                 f,
                 "InternalError: this shouldn't occur, open an issue at https://github.com/zakstucke/bitbazaar/issues\n{}", bash_out.fmt_attempted_commands()
             ),
+            BashErr::InterpreterNotFound(_) => write!(f, "InterpreterNotFound: the requested interpreter isn't installed."),
         }
     }
 }
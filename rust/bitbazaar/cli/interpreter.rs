@@ -0,0 +1,323 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use conch_parser::{ast, lexer::Lexer, parse::DefaultParser};
+
+use super::{BashErr, BashOut, CmdResult};
+use crate::prelude::*;
+
+/// What executes the commands of a [`super::Bash`] script, see [`super::Bash::interpreter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpreter {
+    /// The built in pure rust shell, supporting a subset of bash on all platforms.
+    #[default]
+    Internal,
+    /// A bash binary discovered on the system, on windows Git Bash is preferred, falling back to any bash on the path (e.g. WSL).
+    SystemBash,
+    /// PowerShell (`pwsh` if available, otherwise `powershell`), run with `-NoProfile -Command`.
+    PowerShell,
+}
+
+impl Interpreter {
+    /// Find the binary for an external interpreter, `None` for [`Interpreter::Internal`] or when not installed.
+    fn discover(&self) -> Option<PathBuf> {
+        match self {
+            Interpreter::Internal => None,
+            Interpreter::SystemBash => {
+                #[cfg(windows)]
+                for git_bash in [
+                    r"C:\Program Files\Git\bin\bash.exe",
+                    r"C:\Program Files (x86)\Git\bin\bash.exe",
+                ] {
+                    if Path::new(git_bash).is_file() {
+                        return Some(PathBuf::from(git_bash));
+                    }
+                }
+                which("bash")
+            }
+            Interpreter::PowerShell => which("pwsh").or_else(|| which("powershell")),
+        }
+    }
+}
+
+/// Find an executable on the PATH, like the `which` command.
+pub(crate) fn which(name: &str) -> Option<PathBuf> {
+    which_in(name, &std::env::var_os("PATH")?)
+}
+
+fn which_in(name: &str, path: &std::ffi::OsStr) -> Option<PathBuf> {
+    #[cfg(windows)]
+    let exts = std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+        .split(';')
+        .map(|ext| ext.to_string())
+        .collect::<Vec<_>>();
+    for dir in std::env::split_paths(path) {
+        #[cfg(windows)]
+        for ext in &exts {
+            let candidate = dir.join(format!("{}{}", name, ext));
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        let candidate = dir.join(name);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Run the command strings sequentially in a single external interpreter process, so state (vars, cd etc) carries between them.
+///
+/// After each command a unique marker line with its exit code is written to both stdout and stderr,
+/// allowing the output to be split back into one [`CmdResult`] per command string.
+/// Like the internal shell, a non zero code stops the remaining commands from running (bash ignores negated pipelines),
+/// with bash this can be disabled with `set +e`, PowerShell always stops.
+pub(crate) fn run_external(
+    interpreter: Interpreter,
+    cmds: Vec<String>,
+    root_dir: Option<PathBuf>,
+    base_env: Option<HashMap<String, String>>,
+    env_vars: HashMap<String, String>,
+    args: Vec<String>,
+) -> RResult<BashOut, BashErr> {
+    let bin = interpreter.discover().ok_or_else(|| {
+        err!(
+            BashErr::InterpreterNotFound(BashOut::empty()),
+            "Could not find an installation of {:?} to run the commands with.",
+            interpreter
+        )
+    })?;
+
+    let marker = format!(
+        "__BB_CMD_END_{}_{}__",
+        std::process::id(),
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    );
+    let mut command = Command::new(&bin);
+    match interpreter {
+        Interpreter::PowerShell => {
+            command
+                .arg("-NoProfile")
+                .arg("-NonInteractive")
+                .arg("-Command")
+                .arg(powershell_script(&cmds, &marker));
+        }
+        _ => {
+            // The arg after the script is $0, then the positional params:
+            command
+                .arg("-c")
+                .arg(bash_script(&cmds, &marker))
+                .arg("bash")
+                .args(args);
+        }
+    }
+    if let Some(root_dir) = root_dir {
+        command.current_dir(root_dir);
+    }
+    if let Some(base_env) = base_env {
+        command.env_clear().envs(base_env);
+    }
+    command.envs(env_vars);
+
+    let output = command.output().map_err(|e| {
+        err!(
+            BashErr::InternalError(BashOut::empty()),
+            "Failed to run {:?} interpreter at '{}': {}",
+            interpreter,
+            bin.display(),
+            e
+        )
+    })?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n");
+    let stderr = String::from_utf8_lossy(&output.stderr).replace("\r\n", "\n");
+    // Killed by a signal has no code:
+    let process_code = output.status.code().unwrap_or(1);
+    Ok(BashOut::new(split_results(
+        cmds,
+        &stdout,
+        &stderr,
+        &marker,
+        process_code,
+    )))
+}
+
+fn bash_script(cmds: &[String], marker: &str) -> String {
+    let write_marker = format!(
+        "printf \"\\n{marker}%d\\n\" \"$__bb_code\"; printf \"\\n{marker}%d\\n\" \"$__bb_code\" >&2"
+    );
+    // Like the internal shell, set -e is on by default but can be disabled,
+    // the exit trap writes the marker when set -e (or an explicit exit) ends the script part way through a command:
+    let mut script = format!("set -e\ntrap '__bb_code=$?; {write_marker}' EXIT\n");
+    for cmd in cmds {
+        script.push_str(cmd);
+        script.push_str(&format!("\n__bb_code=$?\n{write_marker}\n"));
+        // set -e doesn't apply to failures in && and || lists etc, whereas the internal shell stops whenever a command line fails,
+        // both agree a negated pipeline never stops it:
+        if !ends_negated(cmd) {
+            script.push_str(
+                "if [ \"$__bb_code\" -ne 0 ]; then case $- in *e*) trap - EXIT; exit \"$__bb_code\";; esac; fi\n",
+            );
+        }
+    }
+    script.push_str("trap - EXIT\n");
+    script
+}
+
+/// Whether the command string ends with a pipeline negated with `!`, false if it can't be parsed.
+fn ends_negated(cmd: &str) -> bool {
+    let last = DefaultParser::new(Lexer::new(cmd.chars()))
+        .into_iter()
+        .last();
+    let Some(Ok(ast::TopLevelCommand(ast::Command::List(list)))) = last else {
+        return false;
+    };
+    let last_pipeline = match list.rest.last() {
+        Some(ast::AndOr::And(cmd) | ast::AndOr::Or(cmd)) => cmd,
+        None => &list.first,
+    };
+    matches!(last_pipeline, ast::ListableCommand::Pipe(true, _))
+}
+
+fn powershell_script(cmds: &[String], marker: &str) -> String {
+    let mut script = String::new();
+    for cmd in cmds {
+        // $? is false after a failed cmdlet or native command, $LASTEXITCODE holds the native command's code:
+        script.push_str("$global:LASTEXITCODE = 0\n");
+        script.push_str(cmd);
+        script.push_str(&format!(
+            "\n$__bb_ok = $?\n$__bb_code = if ($__bb_ok -and $LASTEXITCODE -eq 0) {{ 0 }} elseif ($LASTEXITCODE) {{ $LASTEXITCODE }} else {{ 1 }}\nWrite-Output \"`n{marker}$__bb_code\"\n[Console]::Error.WriteLine(\"`n{marker}$__bb_code\")\nif ($__bb_code -ne 0) {{ exit $__bb_code }}\n"
+        ));
+    }
+    script
+}
+
+/// Split marked output into (output, code) for each completed command, plus the trailing unmarked remainder.
+fn split_marked(output: &str, marker: &str) -> (Vec<(String, i32)>, String) {
+    let needle = format!("\n{}", marker);
+    let mut segments = vec![];
+    let mut rest = output;
+    while let Some(index) = rest.find(&needle) {
+        let after = &rest[index + needle.len()..];
+        let line_end = after.find('\n').unwrap_or(after.len());
+        let code = after[..line_end].trim().parse().unwrap_or(1);
+        segments.push((rest[..index].to_string(), code));
+        rest = &after[(line_end + 1).min(after.len())..];
+    }
+    (segments, rest.to_string())
+}
+
+fn split_results(
+    cmds: Vec<String>,
+    stdout: &str,
+    stderr: &str,
+    marker: &str,
+    process_code: i32,
+) -> Vec<CmdResult> {
+    let (stdouts, stdout_rest) = split_marked(stdout, marker);
+    let (stderrs, stderr_rest) = split_marked(stderr, marker);
+    let mut stderrs = stderrs.into_iter();
+
+    let mut results = vec![];
+    let mut cmds = cmds.into_iter();
+    for (stdout, code) in stdouts {
+        let Some(cmd) = cmds.next() else {
+            break;
+        };
+        let stderr = stderrs.next().map(|(stderr, _)| stderr).unwrap_or_default();
+        results.push(CmdResult::new(cmd, code, stdout, stderr));
+    }
+
+    let last_failed = results.last().is_some_and(|result| result.code != 0);
+    if !last_failed {
+        if let Some(cmd) = cmds.next() {
+            // The process ended before this command finished, e.g. it called exit or the script couldn't be parsed:
+            results.push(CmdResult::new(cmd, process_code, stdout_rest, stderr_rest));
+        } else if let Some(last) = results.last_mut() {
+            // Anything written after the final marker, e.g. by a trap:
+            last.stdout.push_str(&stdout_rest);
+            last.stderr.push_str(&stderr_rest);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_split_results() {
+        let marker = "__M__";
+        let cmds = || {
+            vec![
+                "echo a".to_string(),
+                "printf b".to_string(),
+                "exit 3".to_string(),
+                "echo never".to_string(),
+            ]
+        };
+
+        // Command 3 exited without writing a marker:
+        let results = split_results(
+            cmds(),
+            "a\n\n__M__0\nb\n__M__0\nleft",
+            "\n__M__0\n\n__M__0\nerr",
+            marker,
+            3,
+        );
+        assert_eq!(
+            results
+                .iter()
+                .map(|r| (
+                    r.command.as_str(),
+                    r.code,
+                    r.stdout.as_str(),
+                    r.stderr.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("echo a", 0, "a\n", ""),
+                ("printf b", 0, "b", ""),
+                ("exit 3", 3, "left", "err"),
+            ]
+        );
+
+        // A failed command stops the rest:
+        let results = split_results(cmds(), "a\n\n__M__2\n", "oops\n__M__2\n", marker, 2);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].code, 2);
+        assert_eq!(results[0].stderr, "oops");
+    }
+
+    #[rstest]
+    fn test_which() {
+        assert_eq!(which_in("bash", std::ffi::OsStr::new("")), None);
+        #[cfg(unix)]
+        {
+            assert!(which("sh").is_some());
+            assert_eq!(which_in("sh", std::ffi::OsStr::new("/no/such/dir")), None);
+        }
+    }
+}
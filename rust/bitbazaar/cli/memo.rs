@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The default cap on the size of a memo directory, see [`super::Bash::memo_max_bytes`].
+pub const DEFAULT_MEMO_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The memoization config of a [`super::Bash`], see [`super::Bash::memoize`].
+#[derive(Debug, Clone)]
+pub struct Memo {
+    /// None when memoization is off.
+    pub dir: Option<PathBuf>,
+    /// Globs of files whose contents are part of every key, relative to the command's working directory.
+    pub inputs: Vec<String>,
+    /// Globs of commands never memoized.
+    pub disabled_for: Vec<String>,
+    pub max_bytes: u64,
+}
+
+impl Default for Memo {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            inputs: Vec::new(),
+            disabled_for: Vec::new(),
+            max_bytes: DEFAULT_MEMO_MAX_BYTES,
+        }
+    }
+}
+
+/// The recorded output of a successful run, stored as `{dir}/{key}.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoEntry {
+    pub command: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Memo {
+    /// The cache key for running `argv` in `dir` with the given env, None when memoization is off or disabled for the command.
+    ///
+    /// Uses std's hasher, which may change between rust versions, that only means a one off cache miss.
+    pub fn key(
+        &self,
+        argv: &[String],
+        dir: &Path,
+        env: &BTreeMap<&String, &String>,
+    ) -> Option<String> {
+        self.dir.as_ref()?;
+        let command = argv.join(" ");
+        if self.disabled_for.iter().any(|pattern| {
+            let program = argv.first().map(String::as_str).unwrap_or_default();
+            match glob::Pattern::new(pattern) {
+                Ok(glob) => glob.matches(&command) || glob.matches(program),
+                Err(_) => pattern == &command || pattern == program,
+            }
+        }) {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        argv.hash(&mut hasher);
+        dir.hash(&mut hasher);
+        env.hash(&mut hasher);
+        for input in &self.inputs {
+            let Ok(paths) = glob::glob(&dir.join(input).to_string_lossy()) else {
+                // An invalid glob can't match anything, but still distinguishes the key:
+                input.hash(&mut hasher);
+                continue;
+            };
+            for path in paths.flatten() {
+                path.hash(&mut hasher);
+                // Unreadable files (e.g. dirs) only contribute their path:
+                if let Ok(contents) = std::fs::read(&path) {
+                    contents.hash(&mut hasher);
+                }
+            }
+        }
+        Some(format!("{:016x}", hasher.finish()))
+    }
+
+    /// The recorded output for the key if there is one, marking it as recently used.
+    pub fn lookup(&self, key: &str) -> Option<MemoEntry> {
+        let path = self.entry_path(key)?;
+        let entry = serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+        if let Err(e) = std::fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            tracing::warn!(
+                "Couldn't mark memo entry '{}' as used: {}",
+                path.display(),
+                e
+            );
+        }
+        Some(entry)
+    }
+
+    /// Record a successful run, evicting the least recently used entries if the directory is over its cap.
+    ///
+    /// Memoization is best effort, failures are logged rather than failing the command.
+    pub fn store(&self, key: &str, entry: &MemoEntry) {
+        let (Some(dir), Some(path)) = (&self.dir, self.entry_path(key)) else {
+            return;
+        };
+        let result = std::fs::create_dir_all(dir)
+            .and_then(|_| serde_json::to_vec(entry).map_err(std::io::Error::other))
+            .and_then(|contents| {
+                // Written to the side then moved, so a concurrent lookup never sees a partial entry:
+                let tmp_path = path.with_extension("json.tmp");
+                std::fs::write(&tmp_path, contents)?;
+                std::fs::rename(&tmp_path, &path)
+            })
+            .and_then(|_| self.evict(dir));
+        if let Err(e) = result {
+            tracing::warn!("Couldn't store memo entry for '{}': {}", entry.command, e);
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", key)))
+    }
+
+    fn evict(&self, dir: &Path) -> std::io::Result<()> {
+        let mut entries = vec![];
+        for dir_entry in std::fs::read_dir(dir)? {
+            let dir_entry = dir_entry?;
+            if dir_entry
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "json")
+            {
+                let meta = dir_entry.metadata()?;
+                entries.push((meta.modified()?, meta.len(), dir_entry.path()));
+            }
+        }
+        let mut total = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
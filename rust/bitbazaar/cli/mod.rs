@@ -1,14 +1,26 @@
 mod bash;
 mod bash_out;
 mod builtins;
+mod dry_run;
+mod env_isolation;
 mod errs;
+mod interpreter;
+mod memo;
 mod redirect;
+mod report;
+mod resource_usage;
 mod runner;
 mod shell;
 
 pub use bash::Bash;
-pub use bash_out::{BashOut, CmdResult};
+pub use bash_out::{BashOut, CmdResult, CmdTermination};
+pub use dry_run::{DryRunSubstitutions, PlannedCommand};
+pub use env_isolation::{EnvIsolation, DEFAULT_SAFE_ENV_VARS};
 pub use errs::BashErr;
+pub use interpreter::Interpreter;
+pub use memo::DEFAULT_MEMO_MAX_BYTES;
+pub use report::{ReportOpts, REPORT_VERSION};
+pub use resource_usage::ResourceUsage;
 
 #[cfg(test)]
 mod tests {
@@ -18,7 +30,10 @@ mod tests {
     use tempfile::NamedTempFile;
 
     use super::*;
-    use crate::{errors::prelude::*, log::GlobalLog};
+    use crate::{
+        errors::{prelude::*, test_helpers::*},
+        log::GlobalLog,
+    };
 
     #[fixture]
     fn logging() -> () {
@@ -227,13 +242,29 @@ mod tests {
     #[case::set_e_on_by_default(["echo hello", "false", "echo goodbye"], "hello", 1)]
     #[case::set_e_can_be_disabled(["set +e", "echo hello", "false", "echo goodbye"], "hello\ngoodbye", 0)]
     #[case::set_e_can_be_disabled_and_re_enabled(["set +e", "set -e", "echo hello", "false", "echo goodbye"], "hello", 1)]
+    // Pipefail off by default, only the final stage's code counts:
+    #[case::pipefail_off_by_default(["false | echo hello", "echo goodbye"], "hello\ngoodbye", 0)]
+    // Pipefail on, the failing first stage fails the pipeline and set -e aborts:
+    #[case::pipefail_on(["set -o pipefail", "false | echo hello", "echo goodbye"], "hello", 1)]
+    #[case::pipefail_can_be_disabled(["set -o pipefail", "set +o pipefail", "false | echo hello", "echo goodbye"], "hello\ngoodbye", 0)]
+    // Negation applies after the pipefail computation:
+    #[case::pipefail_negated(["set -o pipefail", "! false | echo hello", "echo goodbye"], "hello\ngoodbye", 0)]
+    // Like bash, set -e ignores negated pipelines even when they fail:
+    #[case::negated_without_pipefail(["! false | echo hello", "echo goodbye"], "hello\ngoodbye", 0)]
+    #[case::negated_success_not_fatal(["! true", "echo goodbye"], "goodbye", 0)]
     fn test_bash_multiline<S: Into<String>>(
         #[case] cmds: impl Into<Vec<S>>,
         #[case] exp_std_all: S,
         #[case] code: i32,
+        // A system bash should behave the same:
+        #[values(Interpreter::Internal, Interpreter::SystemBash)] interpreter: Interpreter,
         #[allow(unused_variables)] logging: (),
     ) -> RResult<(), AnyErr> {
-        let mut bash = Bash::new();
+        if cfg!(windows) && interpreter == Interpreter::SystemBash {
+            return Ok(());
+        }
+
+        let mut bash = Bash::new().interpreter(interpreter);
         for cmd in cmds.into() {
             bash = bash.cmd(cmd);
         }
@@ -244,6 +275,397 @@ mod tests {
         Ok(())
     }
 
+    /// For loops should bind each word in turn, and respect set -e and exit from inside the body.
+    #[rstest]
+    #[case::basic("for f in a b c; do echo $f.txt; done", "a.txt\nb.txt\nc.txt", 0)]
+    #[case::and_chain(
+        "for f in a b; do echo $f && false || echo no_$f; done",
+        "a\nno_a\nb\nno_b",
+        0
+    )]
+    #[case::subst_words(
+        "for f in a $(echo b c) \"$(echo d e)\"; do echo \"[$f]\"; done",
+        "[a]\n[b]\n[c]\n[d e]",
+        0
+    )]
+    #[case::var_persists("for f in a b; do x=$f; done; echo $f $x", "b b", 0)]
+    #[case::set_e("for f in a b; do echo $f; false; echo no; done; echo after", "a", 1)]
+    #[case::set_e_disabled(
+        "set +e; for f in a b; do false; echo $f; done; echo after",
+        "a\nb\nafter",
+        0
+    )]
+    #[case::exit("for f in a b; do echo $f; exit 3; done; echo after", "a", 3)]
+    #[case::piped(format!("for f in a b c; do echo $f; done | {WC_CMD}"), "3", 0)]
+    #[case::appends(format!("for f in a b; do echo $f >> {fp}; done && {CAT_CMD} {fp}", fp=tf()), "a\nb", 0)]
+    #[case::redirected(format!("for f in a b; do echo $f; done > {fp} && {CAT_CMD} {fp}", fp=tf()), "a\nb", 0)]
+    #[case::empty("for f in; do echo $f; done; echo empty", "empty", 0)]
+    // No positional params to loop over:
+    #[case::no_words("for f; do echo $f; done; echo none", "none", 0)]
+    fn test_bash_for<S: Into<String>>(
+        #[case] cmd_str: S,
+        #[case] exp_std_all: &str,
+        #[case] code: i32,
+        #[values(Interpreter::Internal, Interpreter::SystemBash)] interpreter: Interpreter,
+        #[allow(unused_variables)] logging: (),
+    ) -> RResult<(), AnyErr> {
+        if cfg!(windows) && interpreter == Interpreter::SystemBash {
+            return Ok(());
+        }
+
+        let res = Bash::new()
+            .interpreter(interpreter)
+            .cmd(cmd_str)
+            .run()
+            .change_context(AnyErr)?;
+
+        assert_eq!(res.code(), code, "{}: {}", res.code(), res.std_all());
+        assert_eq!(res.std_all().trim(), exp_std_all);
+        Ok(())
+    }
+
+    /// $? should expand to the last code at the time, positional params to the args given to the script.
+    /// Subshells see the outer $?, but only their final code comes back out.
+    #[rstest]
+    #[case::code_success("true; echo $?", "0", 0)]
+    #[case::code_failure("set +e; false; echo $?", "1", 0)]
+    #[case::code_or("false || echo $?", "1", 0)]
+    #[case::code_and("true && echo $?", "0", 0)]
+    #[case::code_chain("false || true && echo $?", "0", 0)]
+    #[case::code_pipe_sees_previous("set +e; false; true | echo $?", "1", 0)]
+    #[case::code_into_subshell("set +e; false; (echo $?)", "1", 0)]
+    #[case::code_into_subst("set +e; false; echo $(echo $?)", "1", 0)]
+    #[case::code_subshell_final_only("set +e; (false; true); echo $?", "0", 0)]
+    #[case::code_subshell_failed("set +e; (true; false); echo $?", "1", 0)]
+    #[case::code_subshell_exit("(exit 3) || echo $?", "3", 0)]
+    #[case::subshell_vars_dont_leak("(x=1); echo \"[$x]\"", "[]", 0)]
+    #[case::positional("echo $1 $3 $#", "a d 3", 0)]
+    #[case::positional_missing("echo \"[$4]\"", "[]", 0)]
+    #[case::positional_zero("echo $0", "bash", 0)]
+    #[case::at_quoted("for a in \"$@\"; do echo \"[$a]\"; done", "[a]\n[b c]\n[d]", 0)]
+    #[case::at_unquoted("for a in $@; do echo \"[$a]\"; done", "[a]\n[b]\n[c]\n[d]", 0)]
+    #[case::star_quoted("for a in \"$*\"; do echo \"[$a]\"; done", "[a b c d]", 0)]
+    // Simplified to joining with spaces when part of a larger word:
+    #[case::at_in_word("echo \"x$@y\"", "xa b c dy", 0)]
+    #[case::for_without_words("for a; do echo \"[$a]\"; done", "[a]\n[b c]\n[d]", 0)]
+    fn test_bash_params(
+        #[case] cmd_str: &str,
+        #[case] exp_std_all: &str,
+        #[case] code: i32,
+        #[values(Interpreter::Internal, Interpreter::SystemBash)] interpreter: Interpreter,
+        #[allow(unused_variables)] logging: (),
+    ) -> RResult<(), AnyErr> {
+        if cfg!(windows) && interpreter == Interpreter::SystemBash {
+            return Ok(());
+        }
+
+        let res = Bash::new()
+            .interpreter(interpreter)
+            .args(["a", "b c", "d"])
+            .cmd(cmd_str)
+            .run()
+            .change_context(AnyErr)?;
+
+        assert_eq!(res.code(), code, "{}: {}", res.code(), res.std_all());
+        assert_eq!(res.std_all().trim(), exp_std_all);
+        Ok(())
+    }
+
+    /// Unquoted globs should expand to the sorted matching files in the active dir, quoted ones stay literal.
+    #[rstest]
+    #[case::star("echo *.txt", "a.txt b.txt")]
+    #[case::bare_star_skips_hidden("echo *", "a.txt b.txt c.rs subdir")]
+    #[case::explicit_hidden("echo .*.txt", ".hidden.txt")]
+    #[case::question("echo ?.rs", "c.rs")]
+    #[case::class("echo [bc].*", "b.txt c.rs")]
+    #[case::no_match("echo *.none", "*.none")]
+    #[case::double_quoted("echo \"*.txt\"", "*.txt")]
+    #[case::single_quoted("echo '*.txt'", "*.txt")]
+    #[case::escaped("echo \\*.txt", "*.txt")]
+    #[case::for_words("for f in *.txt; do echo $f; done", "a.txt\nb.txt")]
+    fn test_bash_glob(
+        #[case] cmd_str: &str,
+        #[case] exp_std_all: &str,
+        #[values(Interpreter::Internal, Interpreter::SystemBash)] interpreter: Interpreter,
+        #[allow(unused_variables)] logging: (),
+    ) -> RResult<(), AnyErr> {
+        if cfg!(windows) && interpreter == Interpreter::SystemBash {
+            return Ok(());
+        }
+
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        for file in ["a.txt", "b.txt", ".hidden.txt", "c.rs"] {
+            std::fs::write(temp_dir.path().join(file), "").change_context(AnyErr)?;
+        }
+        std::fs::create_dir(temp_dir.path().join("subdir")).change_context(AnyErr)?;
+        std::fs::write(temp_dir.path().join("subdir").join("d.rs"), "").change_context(AnyErr)?;
+
+        let res = Bash::new()
+            .interpreter(interpreter)
+            .chdir(temp_dir.path())
+            .cmd(cmd_str)
+            .run()
+            .change_context(AnyErr)?;
+
+        assert_eq!(res.code(), 0, "{}: {}", res.code(), res.std_all());
+        assert_eq!(res.std_all().trim(), exp_std_all);
+        Ok(())
+    }
+
+    /// Confirm the codes of each stage of a pipeline are recorded, whether or not pipefail is enabled.
+    #[rstest]
+    #[case::no_pipefail(false, 0)]
+    #[case::pipefail(true, 1)]
+    fn test_pipeline_codes(
+        #[case] pipefail: bool,
+        #[case] exp_code: i32,
+        #[allow(unused_variables)] logging: (),
+    ) -> RResult<(), AnyErr> {
+        let mut bash = Bash::new().cmd("set +e");
+        if pipefail {
+            bash = bash.cmd("set -o pipefail");
+        }
+        let res = bash
+            .cmd("false | true | echo hello")
+            .run()
+            .change_context(AnyErr)?;
+        assert_eq!(res.code(), exp_code, "{}: {}", res.code(), res.std_all());
+        let last = res.command_results.last().unwrap();
+        assert_eq!(last.pipeline_codes, vec![1, 0, 0]);
+        Ok(())
+    }
+
+    /// Confirm external commands record their resource usage, per pipe stage, and builtins don't.
+    #[cfg(unix)]
+    #[rstest]
+    fn test_resource_usage(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let res = Bash::new()
+            .cmd("dd if=/dev/zero of=/dev/null bs=50M count=1")
+            .cmd("sh -c 'i=0; while [ $i -lt 200000 ]; do i=$((i+1)); done'")
+            .cmd("echo hello | cat")
+            .cmd("echo builtin")
+            .run()
+            .change_context(AnyErr)?;
+        assert!(res.success(), "{}", res.std_all());
+        let [heavy, busy, piped, builtin] = &res.command_results[..] else {
+            panic!("{:?}", res.command_results);
+        };
+
+        // The dd buffer is allocated and filled, so should be resident:
+        let max_rss = heavy.resource_usage.unwrap().max_rss_bytes.unwrap();
+        assert!(
+            (45 * 1024 * 1024..500 * 1024 * 1024).contains(&max_rss),
+            "{}",
+            max_rss
+        );
+        assert!(busy.resource_usage.unwrap().total_cpu().unwrap() > std::time::Duration::ZERO);
+
+        // Only the external stage of the pipe is recorded:
+        assert_eq!(piped.stage_usage.len(), 1);
+        assert_eq!(piped.stage_usage[0].0, "cat");
+
+        assert_eq!(builtin.resource_usage, None);
+        assert!(builtin.stage_usage.is_empty());
+
+        let summary = res.fmt_summary();
+        assert!(
+            summary.contains("Top consumer: 'dd if=/dev/zero of=/dev/null bs=50M count=1'"),
+            "{}",
+            summary
+        );
+        Ok(())
+    }
+
+    /// Confirm commands stuck awaiting input are killed and marked, without affecting slow commands or the default.
+    #[cfg(unix)]
+    #[rstest]
+    fn test_fail_on_stdin_read(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let grace = chrono::TimeDelta::milliseconds(300);
+
+        // Standing in for a prompt that ignores the closed stdin, blocking without output:
+        let started = std::time::Instant::now();
+        let res = Bash::new()
+            .cmd("tail -f /dev/null")
+            .cmd("echo unreachable")
+            .fail_on_stdin_read(grace)
+            .run()
+            .change_context(AnyErr)?;
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(3),
+            "{:?}",
+            started.elapsed()
+        );
+        assert!(!res.success());
+        let [killed] = &res.command_results[..] else {
+            panic!("{:?}", res.command_results);
+        };
+        assert_eq!(killed.termination, CmdTermination::TimedOutAwaitingInput);
+        assert!(
+            killed.stderr.contains("Killed 'tail -f /dev/null'"),
+            "{}",
+            killed.stderr
+        );
+
+        // Reading stdin gets an immediate EOF rather than blocking:
+        let res = Bash::new()
+            .cmd("cat")
+            .fail_on_stdin_read(grace)
+            .run()
+            .change_context(AnyErr)?;
+        assert!(res.success(), "{}", res.std_all());
+        assert_eq!(res.command_results[0].termination, CmdTermination::Exited);
+
+        // Slow commands survive whilst silent for less than the grace, even if running for longer overall:
+        let res = Bash::new()
+            .cmd("sleep 0.1 && echo done")
+            .cmd("sh -c 'for i in 1 2 3 4 5; do echo $i; sleep 0.1; done'")
+            .fail_on_stdin_read(grace)
+            .run()
+            .change_context(AnyErr)?;
+        assert!(res.success(), "{}", res.std_all());
+        assert_eq!(res.stdout(), "done\n1\n2\n3\n4\n5\n");
+        assert!(res
+            .command_results
+            .iter()
+            .all(|result| result.termination == CmdTermination::Exited));
+
+        // Without the flag, silent commands are left alone:
+        let res = Bash::new().cmd("sleep 0.5").run().change_context(AnyErr)?;
+        assert!(res.success(), "{}", res.std_all());
+        assert_eq!(res.command_results[0].termination, CmdTermination::Exited);
+
+        // Only the internal interpreter supports it:
+        let e = expect_err_report(
+            Bash::new()
+                .cmd("echo hi")
+                .interpreter(Interpreter::SystemBash)
+                .fail_on_stdin_read(grace)
+                .run(),
+        );
+        assert!(matches!(
+            e.current_context(),
+            BashErr::BashFeatureUnsupported(_)
+        ));
+        Ok(())
+    }
+
+    /// Confirm memoized commands are replayed while their inputs are unchanged, and the memo dir stays bounded.
+    #[cfg(unix)]
+    #[rstest]
+    fn test_memoize(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let work_dir = temp_dir.path().join("work");
+        let memo_dir = temp_dir.path().join("memo");
+        std::fs::create_dir(&work_dir).change_context(AnyErr)?;
+        std::fs::write(work_dir.join("input.txt"), "v1").change_context(AnyErr)?;
+        // Each real run leaves a mark, so replays can be told apart:
+        let runs = |name: &str| {
+            std::fs::read_to_string(work_dir.join(name))
+                .unwrap_or_default()
+                .lines()
+                .count()
+        };
+        let cmd = "sh -c 'echo ran >> marks.txt && cat input.txt'";
+        let run = |cmd: &str| {
+            Bash::new()
+                .chdir(&work_dir)
+                .memoize(&memo_dir)
+                .memo_inputs("input*")
+                .cmd(cmd)
+                .run()
+        };
+
+        let first = run(cmd).change_context(AnyErr)?;
+        assert_eq!(first.stdout(), "v1");
+        assert!(first.command_results[0].cached.is_empty());
+        assert_eq!(runs("marks.txt"), 1);
+
+        // Skipped the second time, with identical output:
+        let second = run(cmd).change_context(AnyErr)?;
+        assert_eq!(second.stdout(), "v1");
+        assert!(second.success());
+        assert_eq!(
+            second.command_results[0].cached,
+            vec!["sh -c echo ran >> marks.txt && cat input.txt"]
+        );
+        assert_eq!(runs("marks.txt"), 1);
+
+        // Changing an input invalidates it:
+        std::fs::write(work_dir.join("input.txt"), "v2").change_context(AnyErr)?;
+        assert_eq!(run(cmd).change_context(AnyErr)?.stdout(), "v2");
+        assert_eq!(runs("marks.txt"), 2);
+
+        // Failures are never recorded:
+        let failing = "sh -c 'echo ran >> fail_marks && exit 3'";
+        for _ in 0..2 {
+            assert_eq!(run(failing).change_context(AnyErr)?.code(), 3);
+        }
+        assert_eq!(runs("fail_marks"), 2);
+
+        // Piped commands and disabled patterns always run:
+        let piped = "sh -c 'echo ran >> pipe_marks' | cat";
+        for _ in 0..2 {
+            run(piped).change_context(AnyErr)?;
+        }
+        assert_eq!(runs("pipe_marks"), 2);
+        let disabled = "sh -c 'echo ran >> disabled_marks'";
+        for _ in 0..2 {
+            Bash::new()
+                .chdir(&work_dir)
+                .memoize(&memo_dir)
+                .memoize_disabled_for(["sh -c *disabled*"])
+                .cmd(disabled)
+                .run()
+                .change_context(AnyErr)?;
+        }
+        assert_eq!(runs("disabled_marks"), 2);
+
+        // The least recently used entries are evicted to stay under the cap:
+        let max_bytes = 500;
+        for i in 0..20 {
+            Bash::new()
+                .chdir(&work_dir)
+                .memoize(&memo_dir)
+                .memo_max_bytes(max_bytes)
+                .cmd(format!("sh -c 'echo {}'", i))
+                .run()
+                .change_context(AnyErr)?;
+        }
+        let sizes = std::fs::read_dir(&memo_dir)
+            .change_context(AnyErr)?
+            .map(|entry| {
+                Ok(entry
+                    .change_context(AnyErr)?
+                    .metadata()
+                    .change_context(AnyErr)?
+                    .len())
+            })
+            .collect::<RResult<Vec<_>, AnyErr>>()?;
+        assert!(sizes.len() < 20, "{:?}", sizes);
+        assert!(sizes.iter().sum::<u64>() <= max_bytes, "{:?}", sizes);
+        let latest = Bash::new()
+            .chdir(&work_dir)
+            .memoize(&memo_dir)
+            .cmd("sh -c 'echo 19'")
+            .run()
+            .change_context(AnyErr)?;
+        assert_eq!(latest.command_results[0].cached.len(), 1);
+
+        // Only the internal interpreter supports it:
+        let e = expect_err_report(
+            Bash::new()
+                .cmd("echo hi")
+                .interpreter(Interpreter::SystemBash)
+                .memoize(&memo_dir)
+                .run(),
+        );
+        assert!(matches!(
+            e.current_context(),
+            BashErr::BashFeatureUnsupported(_)
+        ));
+        Ok(())
+    }
+
     /// Confirm setting a custom working dir on the builder works plus when changing with cd in bash.
     #[rstest]
     fn test_run_dir(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
@@ -299,27 +721,315 @@ mod tests {
         Ok(())
     }
 
+    /// Confirm parent env vars are hidden when isolated, both from expansion and from child processes.
+    #[rstest]
+    #[case::inherit(EnvIsolation::Inherit, true, true)]
+    #[case::clean(EnvIsolation::Clean, false, false)]
+    #[case::clean_except(EnvIsolation::CleanExcept(vec!["BB_TEST_ALLOWED".into()]), false, true)]
+    fn test_env_isolation(
+        #[allow(unused_variables)] logging: (),
+        #[case] env_isolation: EnvIsolation,
+        #[case] exp_secret: bool,
+        #[case] exp_allowed: bool,
+    ) -> RResult<(), AnyErr> {
+        // Cases only ever set the same values, so fine to share between parallel tests:
+        std::env::set_var("BB_TEST_SECRET", "hunter2");
+        std::env::set_var("BB_TEST_ALLOWED", "allowed");
+
+        let run = |cmd: &str| -> RResult<String, AnyErr> {
+            let res = Bash::new()
+                .env_isolation(env_isolation.clone())
+                .env("BB_TEST_EXPLICIT", "explicit")
+                .cmd(cmd)
+                .run()
+                .change_context(AnyErr)?;
+            assert_eq!(res.code(), 0, "{}: {}", res.code(), res.std_all());
+            Ok(res.stdout().trim().to_string())
+        };
+        let expected = |visible: bool, val: &str| if visible { val.to_string() } else { "".into() };
+
+        // Expansion:
+        assert_eq!(
+            run("echo \"$BB_TEST_SECRET\"")?,
+            expected(exp_secret, "hunter2")
+        );
+        assert_eq!(
+            run("echo \"$BB_TEST_ALLOWED\"")?,
+            expected(exp_allowed, "allowed")
+        );
+        assert_eq!(run("echo $BB_TEST_EXPLICIT")?, "explicit");
+
+        // Child processes, PATH must still resolve for env to spawn at all:
+        if !cfg!(windows) {
+            let child_vars = run("env")?
+                .lines()
+                .filter_map(|line| line.split_once('=').map(|(name, _)| name.to_string()))
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(child_vars.contains("BB_TEST_SECRET"), exp_secret);
+            assert_eq!(child_vars.contains("BB_TEST_ALLOWED"), exp_allowed);
+            assert!(child_vars.contains("BB_TEST_EXPLICIT"));
+            assert!(child_vars.contains("PATH"));
+            if env_isolation != EnvIsolation::Inherit {
+                // Nothing beyond the safe vars, whitelist and explicit vars:
+                let mut allowed = DEFAULT_SAFE_ENV_VARS
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<std::collections::HashSet<_>>();
+                allowed.insert("BB_TEST_EXPLICIT".into());
+                if exp_allowed {
+                    allowed.insert("BB_TEST_ALLOWED".into());
+                }
+                assert!(
+                    child_vars.is_subset(&allowed),
+                    "{:?}",
+                    child_vars.difference(&allowed)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_dry_run(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let temp_dir_pb = temp_dir
+            .path()
+            .normalize()
+            .change_context(AnyErr)?
+            .into_path_buf();
+        let sub_dir = temp_dir_pb.join("sub");
+        std::fs::create_dir(&sub_dir).change_context(AnyErr)?;
+
+        let res = Bash::new()
+            .chdir(&temp_dir_pb)
+            .dry_run(true)
+            .cmd("NAME=world")
+            .cmd("echo hello $NAME | tr a-z A-Z > out.txt")
+            .cmd("cd sub && touch \"$NAME.txt\" 2>> errs.log && pwd")
+            .run()
+            .change_context(AnyErr)?;
+        assert_eq!(res.code(), 0, "{}", res.std_all());
+
+        // Nothing external ran, nothing was created:
+        assert!(!temp_dir_pb.join("out.txt").exists());
+        assert!(!sub_dir.join("world.txt").exists());
+        assert!(!sub_dir.join("errs.log").exists());
+
+        let plan = res
+            .dry_run_plan()
+            .into_iter()
+            .map(|planned| (planned.to_string(), planned.dir.clone(), planned.builtin))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            plan,
+            vec![
+                ("echo hello world".to_string(), temp_dir_pb.clone(), true),
+                (
+                    "tr a-z A-Z > out.txt".to_string(),
+                    temp_dir_pb.clone(),
+                    false
+                ),
+                ("cd sub".to_string(), temp_dir_pb.clone(), true),
+                // cd still applied to the later commands:
+                (
+                    "touch world.txt >> errs.log".to_string(),
+                    sub_dir.clone(),
+                    false
+                ),
+                ("pwd".to_string(), sub_dir.clone(), true),
+            ]
+        );
+        // Piped builtin output is consumed by the planned command, builtins not in a pipe still output:
+        assert_eq!(res.stdout().trim(), sub_dir.to_string_lossy());
+
+        // Substitutions:
+        for (substitutions, exp) in [
+            (DryRunSubstitutions::Stub, "$(...)"),
+            (DryRunSubstitutions::Execute, "inner"),
+        ] {
+            let res = Bash::new()
+                .dry_run(true)
+                .dry_run_substitutions(substitutions)
+                .cmd("echo \"v=$(echo inner)\"")
+                .cmd("ls $(echo inner)")
+                .run()
+                .change_context(AnyErr)?;
+            assert_eq!(res.stdout().trim(), format!("v={}", exp));
+            assert_eq!(
+                res.dry_run_plan()[1].argv,
+                vec!["ls".to_string(), exp.into()]
+            );
+        }
+
+        // Only the internal interpreter can dry run:
+        let _ = assert_err_contains(
+            Bash::new()
+                .interpreter(Interpreter::SystemBash)
+                .dry_run(true)
+                .cmd("echo foo")
+                .run(),
+            "Dry runs are only supported by the internal interpreter",
+        );
+        Ok(())
+    }
+
+    /// Confirm the external interpreters apply env and chdir, and split results per command.
+    #[rstest]
+    fn test_external_interpreters(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let temp_dir_pb = temp_dir
+            .path()
+            .normalize()
+            .change_context(AnyErr)?
+            .into_path_buf();
+
+        let (interpreter, cmds) = if cfg!(windows) {
+            (
+                Interpreter::PowerShell,
+                [
+                    "Write-Output \"$env:FOO\"",
+                    "$x = 'set'; Write-Output (Split-Path -Leaf (Get-Location))",
+                    "Write-Output $x; cmd /c exit 3",
+                    "Write-Output never",
+                ],
+            )
+        } else {
+            (
+                Interpreter::SystemBash,
+                [
+                    "echo $FOO",
+                    "x=set; basename $(pwd)",
+                    "echo $x; exit 3",
+                    "echo never",
+                ],
+            )
+        };
+        let res = Bash::new()
+            .interpreter(interpreter)
+            .env("FOO", "bar")
+            .chdir(&temp_dir_pb)
+            .cmd(cmds[0])
+            .cmd(cmds[1])
+            .cmd(cmds[2])
+            .cmd(cmds[3])
+            .run()
+            .change_context(AnyErr)?;
+
+        let dir_name = temp_dir_pb
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(res.code(), 3, "{}", res.std_all());
+        assert_eq!(
+            res.command_results
+                .iter()
+                .map(|r| (r.command.as_str(), r.code, r.stdout.trim()))
+                .collect::<Vec<_>>(),
+            vec![
+                (cmds[0], 0, "bar"),
+                (cmds[1], 0, dir_name.as_str()),
+                (cmds[2], 3, "set"),
+            ]
+        );
+
+        // Missing interpreters give a clear error:
+        if interpreter::which("pwsh").is_none() && interpreter::which("powershell").is_none() {
+            let e = expect_err_report(
+                Bash::new()
+                    .interpreter(Interpreter::PowerShell)
+                    .cmd("echo foo")
+                    .run(),
+            );
+            assert!(matches!(
+                e.current_context(),
+                BashErr::InterpreterNotFound(_)
+            ));
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_json_report(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        let opts = ReportOpts {
+            max_output_bytes_per_cmd: 10,
+        };
+        let res = Bash::new()
+            .cmd("echo hello")
+            .cmd(format!("echo {}", "x".repeat(100)))
+            .cmd("echo oops >&2 && false")
+            .cmd("echo never")
+            .run()
+            .change_context(AnyErr)?;
+        let report: serde_json::Value =
+            serde_json::from_str(&res.to_json_report(&opts)).change_context(AnyErr)?;
+        assert_eq!(report["version"], REPORT_VERSION);
+        let commands = report["commands"].as_array().unwrap();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0]["index"], 0);
+        assert_eq!(commands[0]["command"], "echo hello");
+        assert_eq!(commands[0]["code"], 0);
+        assert_eq!(
+            commands[0]["stdout"],
+            serde_json::json!({"bytes": 6, "truncated": false, "sample": "hello\n"})
+        );
+        assert!(commands[0]["duration_ms"].as_f64().unwrap() >= 0.0);
+        // Large outputs are truncated, keeping the full size:
+        assert_eq!(
+            commands[1]["stdout"],
+            serde_json::json!({"bytes": 101, "truncated": true, "sample": "x".repeat(10)})
+        );
+        assert_eq!(commands[2]["code"], 1);
+        assert_eq!(commands[2]["stderr"]["sample"], "oops\n");
+        assert_eq!(report["overall"]["code"], 1);
+        assert_eq!(report["overall"]["success"], false);
+        assert_eq!(report["overall"]["command_count"], 3);
+        assert!(report["overall"]["duration_ms"].as_f64().is_some());
+
+        // Written atomically to a file:
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let path = temp_dir.path().join("report.json");
+        res.write_report(&path, &opts)?;
+        let written = std::fs::read_to_string(&path).change_context(AnyErr)?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&written).change_context(AnyErr)?,
+            report
+        );
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        // Still available when bash itself errors, from the attached output:
+        let e = expect_err_report(Bash::new().cmd("echo foo").cmd("ab||)(cd").run());
+        let report: serde_json::Value = serde_json::from_str(
+            &e.current_context()
+                .bash_out()
+                .to_json_report(&ReportOpts::default()),
+        )
+        .change_context(AnyErr)?;
+        assert_eq!(report["commands"].as_array().unwrap().len(), 2);
+        assert_eq!(report["commands"][1]["command"], "ab||)(cd");
+        assert_eq!(report["overall"]["success"], false);
+        // The failed command never ran, so wasn't timed:
+        assert!(report["commands"][1]["duration_ms"].is_null());
+        assert!(report["overall"]["duration_ms"].is_null());
+        Ok(())
+    }
+
     // Confirm when both when doesn't error but not all commands run AND when Bash errors the final command that was attempted is accessible and printable.
     #[rstest]
     fn test_error_source_attached(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
-        let err_cmd = "ab||][/?cd";
+        let err_cmd = "ab||)(cd";
 
         // Confirm that when bash itself fails (i.e. invalid syntax), the source is attached to the error:
-        let res = Bash::new()
-            .cmd("echo foo")
-            .cmd(err_cmd)
-            .cmd("echo bar")
-            .run();
-        assert!(res.is_err());
-        let fmted = format!("{:?}", res.as_ref().unwrap_err());
-        assert!(
-            fmted.contains(format!("{} <-- exited with code:", err_cmd).as_str()),
-            "{}",
-            fmted
+        let e = assert_err_contains(
+            Bash::new()
+                .cmd("echo foo")
+                .cmd(err_cmd)
+                .cmd("echo bar")
+                .run(),
+            &format!("{} <-- exited with code:", err_cmd),
         );
 
         // Confirm cmd out is attached and the source could be inferred from there:
-        let e = res.unwrap_err();
         let bash_out = e.current_context().bash_out();
         assert_eq!(bash_out.command_results.len(), 2);
         assert_eq!(bash_out.command_results[1].command, err_cmd);
@@ -163,7 +163,7 @@ impl Data {
                         RunnerBashOut::Concrete(conc) => {
                             Self::String(conc.stdout.take().unwrap_or_default())
                         }
-                        RunnerBashOut::Pending(child) => {
+                        RunnerBashOut::Pending(child, _, _) => {
                             if let Some(h) = child.stdout.take() {
                                 Self::StdoutHandle(h)
                             } else {
@@ -181,7 +181,7 @@ impl Data {
                         RunnerBashOut::Concrete(conc) => {
                             Self::String(conc.stderr.take().unwrap_or_default())
                         }
-                        RunnerBashOut::Pending(child) => {
+                        RunnerBashOut::Pending(child, _, _) => {
                             if let Some(h) = child.stderr.take() {
                                 Self::StderrHandle(h)
                             } else {
@@ -204,7 +204,7 @@ impl Data {
         })
     }
 
-    fn submit(self, shell: &Shell, dest: Target) -> RResult<RunnerBashOut, ShellErr> {
+    fn submit(self, shell: &mut Shell, dest: Target) -> RResult<RunnerBashOut, ShellErr> {
         let mut conc = ConcreteOutput::default();
 
         match dest.variant {
@@ -224,6 +224,17 @@ impl Data {
                         Some(String::from_utf8(buf).change_context(ShellErr::InternalError)?);
                 }
             }
+            TargetVariant::File(name) if shell.dry_run.is_some() => {
+                // Never touch files in a dry run, just record against the command the redirect applies to:
+                let op = match (dest.read, dest.append) {
+                    (true, _) => "<",
+                    (false, true) => ">>",
+                    (false, false) => ">",
+                };
+                if let Some(planned) = shell.dry_run_plan.last_mut() {
+                    planned.redirects.push(format!("{} {}", op, name));
+                }
+            }
             TargetVariant::File(name) => {
                 let mut opts = std::fs::OpenOptions::new();
                 if dest.read {
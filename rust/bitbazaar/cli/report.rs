@@ -0,0 +1,192 @@
+use std::{path::Path, time::Duration};
+
+use serde::Serialize;
+
+use super::{BashOut, CmdResult, ResourceUsage};
+use crate::prelude::*;
+
+/// The version of the [`BashOut::to_json_report`] schema, bumped on any breaking change.
+pub const REPORT_VERSION: u32 = 1;
+
+/// Options for [`BashOut::to_json_report`].
+#[derive(Debug, Clone)]
+pub struct ReportOpts {
+    /// The max bytes of each command's stdout and stderr kept in the report, the full size is still recorded.
+    pub max_output_bytes_per_cmd: usize,
+}
+
+impl Default for ReportOpts {
+    fn default() -> Self {
+        Self {
+            max_output_bytes_per_cmd: 4096,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    version: u32,
+    commands: Vec<CmdReport<'a>>,
+    overall: OverallReport,
+}
+
+#[derive(Serialize)]
+struct CmdReport<'a> {
+    index: usize,
+    command: &'a str,
+    code: i32,
+    pipeline_codes: &'a [i32],
+    duration_ms: Option<f64>,
+    stdout: OutputReport<'a>,
+    stderr: OutputReport<'a>,
+    resource_usage: Option<UsageReport>,
+}
+
+#[derive(Serialize)]
+struct OutputReport<'a> {
+    bytes: usize,
+    truncated: bool,
+    sample: &'a str,
+}
+
+#[derive(Serialize)]
+struct UsageReport {
+    max_rss_bytes: Option<u64>,
+    user_cpu_ms: Option<f64>,
+    system_cpu_ms: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct OverallReport {
+    code: i32,
+    success: bool,
+    command_count: usize,
+    duration_ms: Option<f64>,
+    resource_usage: Option<UsageReport>,
+}
+
+impl BashOut {
+    /// A json report of everything the run did, e.g. to upload as a CI artifact.
+    ///
+    /// The schema is a contract for external tooling. Fields are only added within a version.
+    /// Any other change bumps `version` (currently [`REPORT_VERSION`]).
+    /// The output is pretty printed with keys in a fixed order, so reports from different runs diff cleanly:
+    ///
+    /// ```text
+    /// {
+    ///   "version": 1,
+    ///   "commands": [                       // In the order run, a failing command is the last, the rest weren't attempted.
+    ///     {
+    ///       "index": 0,
+    ///       "command": "echo hello",
+    ///       "code": 0,
+    ///       "pipeline_codes": [0],          // Each stage of the last pipeline run, may be empty.
+    ///       "duration_ms": 1.2,             // null when not timed, e.g. external interpreters.
+    ///       "stdout": {
+    ///         "bytes": 6,                   // The full size, even when truncated.
+    ///         "truncated": false,
+    ///         "sample": "hello\n"           // The first max_output_bytes_per_cmd bytes (at a char boundary).
+    ///       },
+    ///       "stderr": { "bytes": 0, "truncated": false, "sample": "" },
+    ///       "resource_usage": {             // null when only builtins ran, or the platform can't report it.
+    ///         "max_rss_bytes": 1048576,     // Each field null when unknown.
+    ///         "user_cpu_ms": 0.5,
+    ///         "system_cpu_ms": 0.1
+    ///       }
+    ///     }
+    ///   ],
+    ///   "overall": {
+    ///     "code": 0,
+    ///     "success": true,
+    ///     "command_count": 1,
+    ///     "duration_ms": 1.2,               // The sum of the commands, null if any weren't timed.
+    ///     "resource_usage": null            // Combined like the commands', null if none recorded.
+    ///   }
+    /// }
+    /// ```
+    pub fn to_json_report(&self, opts: &ReportOpts) -> String {
+        let report = Report {
+            version: REPORT_VERSION,
+            commands: self
+                .command_results
+                .iter()
+                .enumerate()
+                .map(|(index, result)| cmd_report(index, result, opts))
+                .collect(),
+            overall: OverallReport {
+                code: self.code(),
+                success: self.success(),
+                command_count: self.command_results.len(),
+                duration_ms: self
+                    .command_results
+                    .iter()
+                    .map(|result| result.duration)
+                    .sum::<Option<Duration>>()
+                    .map(millis),
+                resource_usage: self
+                    .command_results
+                    .iter()
+                    .filter_map(|result| result.resource_usage)
+                    .reduce(|a, b| a.merge(&b))
+                    .map(usage_report),
+            },
+        };
+        // Only fails on non-string map keys, which the report doesn't have:
+        serde_json::to_string_pretty(&report).expect("Report should always serialize.")
+    }
+
+    /// Write [`BashOut::to_json_report`] to a file, atomically so a reader never sees a partial report.
+    pub fn write_report(&self, path: impl AsRef<Path>, opts: &ReportOpts) -> RResult<(), AnyErr> {
+        let path = path.as_ref();
+        let Some(file_name) = path.file_name() else {
+            return Err(anyerr!(
+                "Report path has no file name: '{}'",
+                path.display()
+            ));
+        };
+        // Written alongside and renamed, a rename within a directory is atomic:
+        let mut tmp_name = file_name.to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        std::fs::write(&tmp_path, self.to_json_report(opts)).change_context(AnyErr)?;
+        std::fs::rename(&tmp_path, path).change_context(AnyErr)?;
+        Ok(())
+    }
+}
+
+fn cmd_report<'a>(index: usize, result: &'a CmdResult, opts: &ReportOpts) -> CmdReport<'a> {
+    CmdReport {
+        index,
+        command: &result.command,
+        code: result.code,
+        pipeline_codes: &result.pipeline_codes,
+        duration_ms: result.duration.map(millis),
+        stdout: output_report(&result.stdout, opts.max_output_bytes_per_cmd),
+        stderr: output_report(&result.stderr, opts.max_output_bytes_per_cmd),
+        resource_usage: result.resource_usage.map(usage_report),
+    }
+}
+
+fn output_report(output: &str, max_bytes: usize) -> OutputReport<'_> {
+    let mut end = max_bytes.min(output.len());
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    OutputReport {
+        bytes: output.len(),
+        truncated: end < output.len(),
+        sample: &output[..end],
+    }
+}
+
+fn usage_report(usage: ResourceUsage) -> UsageReport {
+    UsageReport {
+        max_rss_bytes: usage.max_rss_bytes,
+        user_cpu_ms: usage.user_cpu.map(millis),
+        system_cpu_ms: usage.system_cpu.map(millis),
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
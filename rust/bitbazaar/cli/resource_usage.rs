@@ -0,0 +1,254 @@
+use std::{
+    io::Read,
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// Resource usage of an external command, fields are None where the platform can't report them.
+///
+/// Only collected on unix currently, builtins never have usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The peak resident memory of the process.
+    pub max_rss_bytes: Option<u64>,
+    /// Time spent running the process' own code.
+    pub user_cpu: Option<Duration>,
+    /// Time the kernel spent working on behalf of the process.
+    pub system_cpu: Option<Duration>,
+}
+
+impl ResourceUsage {
+    /// User and system cpu time combined, None if neither are known.
+    pub fn total_cpu(&self) -> Option<Duration> {
+        match (self.user_cpu, self.system_cpu) {
+            (None, None) => None,
+            (user, system) => Some(user.unwrap_or_default() + system.unwrap_or_default()),
+        }
+    }
+
+    /// True when nothing was collected, e.g. a builtin or an unsupported platform.
+    pub fn is_empty(&self) -> bool {
+        self.max_rss_bytes.is_none() && self.user_cpu.is_none() && self.system_cpu.is_none()
+    }
+
+    /// Combine with the usage of another process, peak memory is the max of the two, cpu times are summed.
+    pub(crate) fn merge(&self, other: &ResourceUsage) -> ResourceUsage {
+        fn combine<T>(a: Option<T>, b: Option<T>, f: impl FnOnce(T, T) -> T) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(f(a, b)),
+                (a, b) => a.or(b),
+            }
+        }
+        ResourceUsage {
+            max_rss_bytes: combine(self.max_rss_bytes, other.max_rss_bytes, u64::max),
+            user_cpu: combine(self.user_cpu, other.user_cpu, |a, b| a + b),
+            system_cpu: combine(self.system_cpu, other.system_cpu, |a, b| a + b),
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no usage recorded");
+        }
+        let mut parts = vec![];
+        if let Some(bytes) = self.max_rss_bytes {
+            parts.push(format!("max rss {:.1}MB", bytes as f64 / (1024.0 * 1024.0)));
+        }
+        if let Some(user) = self.user_cpu {
+            parts.push(format!("user cpu {:.3}s", user.as_secs_f64()));
+        }
+        if let Some(system) = self.system_cpu {
+            parts.push(format!("system cpu {:.3}s", system.as_secs_f64()));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Like [`process::Child::wait_with_output`], but also collecting the resource usage of the child where possible.
+pub(crate) fn wait_with_usage(
+    child: process::Child,
+) -> std::io::Result<(process::Output, ResourceUsage)> {
+    wait_inner(child, None).map(|(output, usage, _)| (output, usage))
+}
+
+/// Like [`wait_with_usage`], but killing the child if it goes `grace` without exiting or writing any output,
+/// see [`super::Bash::fail_on_stdin_read`].
+///
+/// The returned bool is true when the child was killed.
+pub(crate) fn wait_with_usage_watched(
+    child: process::Child,
+    grace: Duration,
+) -> std::io::Result<(process::Output, ResourceUsage, bool)> {
+    wait_inner(child, Some(grace))
+}
+
+/// How often a watched child is checked for exiting.
+const WATCH_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Drain a pipe in the background, recording when output was last seen.
+fn spawn_reader(
+    mut pipe: impl Read + Send + 'static,
+    last_output: Arc<Mutex<Instant>>,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut out = vec![];
+        let mut chunk = [0; 8192];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) => return Ok(out),
+                Ok(read) => {
+                    out.extend_from_slice(&chunk[..read]);
+                    *last_output.lock() = Instant::now();
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    })
+}
+
+fn join_reader(
+    reader: Option<std::thread::JoinHandle<std::io::Result<Vec<u8>>>>,
+) -> std::io::Result<Vec<u8>> {
+    match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| std::io::Error::other("output reader panicked"))?,
+        None => Ok(vec![]),
+    }
+}
+
+/// Whether the child has gone the grace without output, so should be killed.
+fn silent_for(last_output: &Mutex<Instant>, grace: Duration) -> bool {
+    last_output.lock().elapsed() >= grace
+}
+
+#[cfg(unix)]
+fn wait_inner(
+    mut child: process::Child,
+    grace: Option<Duration>,
+) -> std::io::Result<(process::Output, ResourceUsage, bool)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    // Close stdin so the child isn't left waiting on it:
+    drop(child.stdin.take());
+
+    // Both pipes need draining at the same time, otherwise the child could block on a full one:
+    let last_output = Arc::new(Mutex::new(Instant::now()));
+    let stdout_reader = child
+        .stdout
+        .take()
+        .map(|stdout| spawn_reader(stdout, last_output.clone()));
+    let stderr_reader = child
+        .stderr
+        .take()
+        .map(|stderr| spawn_reader(stderr, last_output.clone()));
+
+    let pid = child.id() as libc::pid_t;
+    let mut status: libc::c_int = 0;
+    // SAFETY: rusage is plain old data, zeroed is a valid value that wait4 overwrites.
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    let mut killed = false;
+    loop {
+        // Only polling when watched, then the child is killed once silent for the grace:
+        let options = if grace.is_some() && !killed {
+            libc::WNOHANG
+        } else {
+            0
+        };
+        // SAFETY: the pointers are to live locals, the pid is our unreaped child.
+        let result = unsafe { libc::wait4(pid, &mut status, options, &mut rusage) };
+        if result == pid {
+            break;
+        }
+        if result == 0 {
+            if let Some(grace) = grace {
+                if silent_for(&last_output, grace) {
+                    child.kill()?;
+                    killed = true;
+                } else {
+                    std::thread::sleep(WATCH_INTERVAL);
+                }
+            }
+            continue;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    let stdout = join_reader(stdout_reader)?;
+    let stderr = join_reader(stderr_reader)?;
+
+    let timeval = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    // Linux reports in kilobytes, macos in bytes:
+    #[cfg(target_os = "macos")]
+    let max_rss_bytes = rusage.ru_maxrss as u64;
+    #[cfg(not(target_os = "macos"))]
+    let max_rss_bytes = rusage.ru_maxrss as u64 * 1024;
+
+    Ok((
+        process::Output {
+            status: process::ExitStatus::from_raw(status),
+            stdout,
+            stderr,
+        },
+        ResourceUsage {
+            max_rss_bytes: Some(max_rss_bytes),
+            user_cpu: Some(timeval(rusage.ru_utime)),
+            system_cpu: Some(timeval(rusage.ru_stime)),
+        },
+        killed,
+    ))
+}
+
+/// Usage isn't collected on this platform.
+#[cfg(not(unix))]
+fn wait_inner(
+    mut child: process::Child,
+    grace: Option<Duration>,
+) -> std::io::Result<(process::Output, ResourceUsage, bool)> {
+    let Some(grace) = grace else {
+        return Ok((child.wait_with_output()?, ResourceUsage::default(), false));
+    };
+
+    drop(child.stdin.take());
+    let last_output = Arc::new(Mutex::new(Instant::now()));
+    let stdout_reader = child
+        .stdout
+        .take()
+        .map(|stdout| spawn_reader(stdout, last_output.clone()));
+    let stderr_reader = child
+        .stderr
+        .take()
+        .map(|stderr| spawn_reader(stderr, last_output.clone()));
+
+    let mut killed = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if !killed && silent_for(&last_output, grace) {
+            child.kill()?;
+            killed = true;
+        } else {
+            std::thread::sleep(WATCH_INTERVAL);
+        }
+    };
+    Ok((
+        process::Output {
+            status,
+            stdout: join_reader(stdout_reader)?,
+            stderr: join_reader(stderr_reader)?,
+        },
+        ResourceUsage::default(),
+        killed,
+    ))
+}
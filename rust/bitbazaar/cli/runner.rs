@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     io::Write,
     process::{self, Stdio},
     str,
@@ -9,9 +10,11 @@ use conch_parser::ast;
 use super::{
     builtins::Builtin,
     errs::{BuiltinErr, ShellErr},
+    memo::MemoEntry,
     redirect::handle_redirect,
+    resource_usage::{wait_with_usage, wait_with_usage_watched},
     shell::Shell,
-    BashOut,
+    BashOut, PlannedCommand,
 };
 use crate::prelude::*;
 
@@ -19,9 +22,11 @@ use crate::prelude::*;
 pub enum VariCommand {
     /// A builtin command implemented directly in rust, alongside the arguments to pass.
     Builtin(String, Builtin, Vec<String>),
-    Normal(process::Command),
-    // Instead of running a command, use the given string as stdin for the next command, or use as stdout if final.
-    PipedStdout(String),
+    /// An external command, alongside its full argv to attribute resource usage to.
+    Normal(Vec<String>, process::Command),
+    /// Instead of running a command, use the output of a compound command the shell already ran itself, e.g. a subshell.
+    /// Its stdout is used as stdin for the next command, or as stdout if final.
+    Output(BashOut),
     Redirect(ast::DefaultRedirect),
 }
 
@@ -35,7 +40,8 @@ pub struct PipeRunner {
 
 pub enum RunnerBashOut {
     Concrete(ConcreteOutput),
-    Pending(process::Child),
+    /// A running external command, alongside its full command line, and its memo key when it should be recorded.
+    Pending(process::Child, String, Option<String>),
 }
 
 impl Default for RunnerBashOut {
@@ -52,8 +58,9 @@ pub struct ConcreteOutput {
 }
 
 impl RunnerBashOut {
-    fn into_shell(self, shell: &mut Shell) -> RResult<(), ShellErr> {
-        match self {
+    /// Load the output into the shell, returning the exit code of the stage if it had one.
+    fn into_shell(self, shell: &mut Shell) -> RResult<Option<i32>, ShellErr> {
+        let code = match self {
             RunnerBashOut::Concrete(conc) => {
                 if let Some(stdout) = conc.stdout {
                     shell.push_stdout(&stdout);
@@ -66,24 +73,55 @@ impl RunnerBashOut {
                 if let Some(code) = conc.code {
                     shell.set_code(code);
                 }
+                conc.code
             }
             // This is probably the last command:
-            RunnerBashOut::Pending(child) => {
-                let output = child
-                    .wait_with_output()
-                    .change_context(ShellErr::InternalError)?;
-
-                shell.push_stdout(
-                    str::from_utf8(&output.stdout).change_context(ShellErr::InternalError)?,
-                );
-                shell.push_stderr(
-                    str::from_utf8(&output.stderr).change_context(ShellErr::InternalError)?,
-                );
-                shell.set_code(output.status.code().unwrap_or(1));
+            RunnerBashOut::Pending(child, command, memo_key) => {
+                // Earlier stages of a pipe have had their stdout taken, so can't be watched for output:
+                let (output, usage, killed) = match shell.stdin_read_grace {
+                    Some(grace) if child.stdout.is_some() => wait_with_usage_watched(child, grace),
+                    _ => wait_with_usage(child).map(|(output, usage)| (output, usage, false)),
+                }
+                .change_context(ShellErr::InternalError)?;
+                let killed_note = killed.then(|| {
+                    format!(
+                        "Killed '{}' after {:?} without output, it's likely waiting for input.\n",
+                        command,
+                        shell.stdin_read_grace.unwrap_or_default()
+                    )
+                });
+                let stdout =
+                    str::from_utf8(&output.stdout).change_context(ShellErr::InternalError)?;
+                let stderr =
+                    str::from_utf8(&output.stderr).change_context(ShellErr::InternalError)?;
+                // Failures are never recorded, so always rerun:
+                if let (Some(memo), Some(key)) = (&shell.memo, memo_key) {
+                    if output.status.success() && !killed {
+                        memo.store(
+                            &key,
+                            &MemoEntry {
+                                command: command.clone(),
+                                stdout: stdout.to_string(),
+                                stderr: stderr.to_string(),
+                            },
+                        );
+                    }
+                }
+                shell.stage_usage.push((command, usage));
+
+                shell.push_stdout(stdout);
+                shell.push_stderr(stderr);
+                if let Some(note) = killed_note {
+                    shell.push_stderr(&note);
+                    shell.awaited_input = true;
+                }
+                let code = output.status.code().unwrap_or(1);
+                shell.set_code(code);
+                Some(code)
             }
-        }
+        };
 
-        Ok(())
+        Ok(code)
     }
 }
 
@@ -116,9 +154,9 @@ impl PipeRunner {
         } else {
             let mut cmd = process::Command::new(first_arg);
             if args.len() > 1 {
-                cmd.args(args.into_iter().skip(1));
+                cmd.args(args.iter().skip(1));
             }
-            VariCommand::Normal(cmd)
+            VariCommand::Normal(args, cmd)
         };
         self.commands.push(vari);
 
@@ -130,42 +168,107 @@ impl PipeRunner {
         Ok(())
     }
 
-    pub fn add_piped_stdout(&mut self, stdout: String) {
-        self.commands.push(VariCommand::PipedStdout(stdout));
+    pub fn add_output(&mut self, output: BashOut) {
+        self.commands.push(VariCommand::Output(output));
     }
 
     pub fn run(mut self, shell: &mut Shell) -> RResult<(), ShellErr> {
+        // Piped or redirected commands have inputs or outputs a memo can't capture:
+        let memoizable = shell.memo.is_some() && self.commands.len() == 1;
         for command in self.commands.into_iter() {
             let last_out = self.outputs.last_mut();
             let next_out: RunnerBashOut = match command {
                 VariCommand::Redirect(redirect) => handle_redirect(shell, last_out, redirect)?,
-                VariCommand::Builtin(name, builtin, args) => match builtin(shell, &args) {
-                    Ok(bash_out) => bash_out.into(),
-                    Err(mut e) => {
-                        e = e.attach_printable(format!("Command: '{}' args: '{:?}'", name, args));
-                        match e.current_context() {
-                            BuiltinErr::Exit => return Err(e.change_context(ShellErr::Exit)),
-                            BuiltinErr::Unsupported => {
-                                return Err(e.change_context(ShellErr::BashFeatureUnsupported))
+                VariCommand::Builtin(name, builtin, args) => {
+                    if shell.dry_run.is_some() {
+                        shell.dry_run_plan.push(PlannedCommand {
+                            argv: std::iter::once(name.clone())
+                                .chain(args.iter().cloned())
+                                .collect(),
+                            dir: shell.active_dir()?,
+                            redirects: vec![],
+                            builtin: true,
+                        });
+                    }
+                    match builtin(shell, &args) {
+                        Ok(bash_out) => bash_out.into(),
+                        Err(mut e) => {
+                            e = e.attach_printable(format!(
+                                "Command: '{}' args: '{:?}'",
+                                name, args
+                            ));
+                            match e.current_context() {
+                                BuiltinErr::Exit => return Err(e.change_context(ShellErr::Exit)),
+                                BuiltinErr::Unsupported => {
+                                    return Err(e.change_context(ShellErr::BashFeatureUnsupported))
+                                }
+                                BuiltinErr::InternalError => {
+                                    return Err(e.change_context(ShellErr::InternalError))
+                                }
                             }
-                            BuiltinErr::InternalError => {
-                                return Err(e.change_context(ShellErr::InternalError))
+                        }
+                    }
+                }
+                VariCommand::Output(output) => output.into(),
+                VariCommand::Normal(argv, _) if shell.dry_run.is_some() => {
+                    // Record instead of running, as if it succeeded without output, still consuming any piped stdin:
+                    if let Some(RunnerBashOut::Concrete(conc)) = last_out {
+                        conc.stdout.take();
+                    }
+                    shell.dry_run_plan.push(PlannedCommand {
+                        argv,
+                        dir: shell.active_dir()?,
+                        redirects: vec![],
+                        builtin: false,
+                    });
+                    RunnerBashOut::Concrete(ConcreteOutput {
+                        stdout: None,
+                        stderr: None,
+                        code: Some(0),
+                    })
+                }
+                VariCommand::Normal(argv, mut command) => {
+                    let memo_key = match &shell.memo {
+                        Some(memo) if memoizable => {
+                            let mut env = shell.vars.iter().collect::<BTreeMap<_, _>>();
+                            if let Some(base_env) = &shell.base_env {
+                                for (name, val) in base_env {
+                                    env.entry(name).or_insert(val);
+                                }
                             }
+                            memo.key(&argv, &shell.active_dir()?, &env)
                         }
+                        _ => None,
+                    };
+                    if let Some(entry) = memo_key
+                        .as_ref()
+                        .and_then(|key| shell.memo.as_ref()?.lookup(key))
+                    {
+                        shell.memo_hits.push(entry.command);
+                        self.outputs.push(RunnerBashOut::Concrete(ConcreteOutput {
+                            stdout: Some(entry.stdout),
+                            stderr: Some(entry.stderr),
+                            code: Some(0),
+                        }));
+                        continue;
                     }
-                },
-                VariCommand::PipedStdout(stdout) => RunnerBashOut::Concrete(ConcreteOutput {
-                    stdout: Some(stdout),
-                    stderr: None,
-                    code: None,
-                }),
-                VariCommand::Normal(mut command) => {
+
                     // Set the working dir:
                     command.current_dir(shell.active_dir()?);
 
+                    // When isolated, start from only the visible part of the env:
+                    if let Some(base_env) = &shell.base_env {
+                        command.env_clear().envs(base_env.clone());
+                    }
+
                     // Add all the shell args to the env of the command:
                     command.envs(shell.vars.clone());
 
+                    // Close stdin when failing on reads, anything piped in below replaces it:
+                    if shell.stdin_read_grace.is_some() {
+                        command.stdin(Stdio::null());
+                    }
+
                     // Pipe in stdin if needed:
                     let mut str_stdin = None;
                     if let Some(last_out) = last_out {
@@ -179,7 +282,7 @@ impl PipeRunner {
                             }
 
                             // Child process, pipe its handle through to the next command, keeping track of the stderr:
-                            RunnerBashOut::Pending(child) => {
+                            RunnerBashOut::Pending(child, _, _) => {
                                 if let Some(stdout) = child.stdout.take() {
                                     command.stdin(stdout);
                                 }
@@ -205,7 +308,7 @@ impl PipeRunner {
                                     .change_context(ShellErr::InternalError)?;
                             }
 
-                            RunnerBashOut::Pending(child)
+                            RunnerBashOut::Pending(child, argv.join(" "), memo_key)
                         }
                         Err(e) => {
                             // Command might error straight away, in which case convert the err to stderr.
@@ -234,12 +337,23 @@ impl PipeRunner {
             self.outputs.push(next_out);
         }
 
-        // Load all the outputs into the shell:
+        // Load all the outputs into the shell, keeping track of each stage's code:
+        let mut codes = Vec::with_capacity(self.outputs.len());
         for output in self.outputs {
-            output.into_shell(shell)?;
+            if let Some(code) = output.into_shell(shell)? {
+                codes.push(code);
+            }
+        }
+
+        // With pipefail, the pipeline fails with the last stage that failed, rather than just the final stage:
+        if shell.pipefail {
+            if let Some(code) = codes.iter().rev().find(|code| **code != 0) {
+                shell.set_code(*code);
+            }
         }
+        shell.pipeline_codes = codes;
 
-        // Negate the code if needed:
+        // Negate the code if needed, like bash this applies after pipefail:
         if self.negate {
             shell.set_code(if shell.code() == 0 { 1 } else { 0 });
         }
@@ -1,9 +1,12 @@
-use std::{collections::HashMap, mem, path::PathBuf, str};
+use std::{collections::HashMap, mem, path::PathBuf, str, sync::Arc, time::Duration};
 
 use conch_parser::{ast, lexer::Lexer, parse::DefaultParser};
 use normpath::PathExt;
 
-use super::{errs::ShellErr, runner::PipeRunner, BashOut, CmdResult};
+use super::{
+    errs::ShellErr, memo::Memo, runner::PipeRunner, BashOut, CmdResult, CmdTermination,
+    DryRunSubstitutions, PlannedCommand, ResourceUsage,
+};
 use crate::prelude::*;
 
 #[derive(Debug)]
@@ -19,7 +22,31 @@ pub struct Shell {
     root_dir: Option<PathBuf>,
     /// Extra params/env vars added to this shell
     pub vars: HashMap<String, String>,
+    /// The positional params, $1 onwards.
+    pub positional: Vec<String>,
+    /// The parent env vars visible to the shell when isolated, None to inherit the full parent environment.
+    pub base_env: Option<HashMap<String, String>>,
     pub set_e: bool,
+    /// set -o pipefail, a pipeline's code is the last nonzero code of its stages, rather than the final stage's code.
+    pub pipefail: bool,
+    /// The exit codes of each stage of the most recently run pipeline.
+    pub pipeline_codes: Vec<i32>,
+    /// The most recently run pipeline was negated with `!`, like bash set -e ignores its code.
+    negated: bool,
+    /// The resource usage of each external command run by the current command string, in the order they finished.
+    pub stage_usage: Vec<(String, ResourceUsage)>,
+    /// Set in a dry run, external commands are planned rather than run.
+    pub dry_run: Option<DryRunSubstitutions>,
+    /// The commands planned by the current command string in a dry run.
+    pub dry_run_plan: Vec<PlannedCommand>,
+    /// Set with [`super::Bash::fail_on_stdin_read`], external commands silent for this long are killed.
+    pub stdin_read_grace: Option<Duration>,
+    /// Whether an external command in the current command string was killed for awaiting input.
+    pub awaited_input: bool,
+    /// Set with [`super::Bash::memoize`].
+    pub memo: Option<Arc<Memo>>,
+    /// The external commands replayed from the memo by the current command string.
+    pub memo_hits: Vec<String>,
     // Each executed command string supplied will be added here. Will be here even if the command fails.
     // Only commands that weren't tried due to previous problems will be missing.
     pub attempted_command_strings: Vec<String>,
@@ -45,13 +72,29 @@ impl From<Shell> for BashOut {
 }
 
 impl Shell {
-    pub fn new(env: HashMap<String, String>, root_dir: Option<PathBuf>) -> RResult<Self, ShellErr> {
+    pub fn new(
+        env: HashMap<String, String>,
+        base_env: Option<HashMap<String, String>>,
+        root_dir: Option<PathBuf>,
+    ) -> RResult<Self, ShellErr> {
         let mut shell = Self {
             cmd_results: Vec::new(),
             root_dir: None,
             vars: env,
+            positional: Vec::new(),
+            base_env,
             // By default have set -e enabled to break if a line errors:
             set_e: true,
+            pipefail: false,
+            pipeline_codes: Vec::new(),
+            negated: false,
+            stage_usage: Vec::new(),
+            dry_run: None,
+            dry_run_plan: Vec::new(),
+            stdin_read_grace: None,
+            awaited_input: false,
+            memo: None,
+            memo_hits: Vec::new(),
             attempted_command_strings: Vec::new(),
             stdout: String::new(),
             stderr: String::new(),
@@ -82,6 +125,7 @@ impl Shell {
 
             // Add the command before hitting anything that could fail:
             self.attempted_command_strings.push(cmd_source.clone());
+            let started = std::time::Instant::now();
 
             let lex = Lexer::new(cmd_source.chars());
             let parser = DefaultParser::new(lex);
@@ -98,6 +142,14 @@ impl Shell {
             cmd_result.code = self.code;
             cmd_result.stdout = std::mem::take(&mut self.stdout);
             cmd_result.stderr = std::mem::take(&mut self.stderr);
+            cmd_result.pipeline_codes = std::mem::take(&mut self.pipeline_codes);
+            cmd_result.set_stage_usage(std::mem::take(&mut self.stage_usage));
+            cmd_result.planned = std::mem::take(&mut self.dry_run_plan);
+            cmd_result.duration = Some(started.elapsed());
+            cmd_result.cached = std::mem::take(&mut self.memo_hits);
+            if std::mem::take(&mut self.awaited_input) {
+                cmd_result.termination = CmdTermination::TimedOutAwaitingInput;
+            }
 
             // Handle actual shell errors (not code errors, problems parsing etc)
             if let Err(e) = result {
@@ -167,9 +219,9 @@ impl Shell {
 
     /// Returns false when the code isn't 0 and set -e is enabled.
     fn should_continue(&self) -> bool {
-        // Don't continue if set -e is enabled and the last command failed:
+        // Don't continue if set -e is enabled and the last command failed, unless its code was negated:
         #[allow(clippy::needless_bool)]
-        if self.code() != 0 && self.set_e {
+        if self.code() != 0 && self.set_e && !self.negated {
             false
         } else {
             true
@@ -237,7 +289,9 @@ impl Shell {
             }
         };
 
+        let negated = pipe_runner.negate;
         pipe_runner.run(self)?;
+        self.negated = negated;
 
         Ok(())
     }
@@ -253,15 +307,31 @@ impl Shell {
                 // E.g. (echo foo && echo bar)
                 match &compound.kind {
                     ast::CompoundCommandKind::Subshell(sub_cmds) => {
-                        let mut shell = Shell::new(self.vars.clone(), self.root_dir.clone())?;
-                        shell.run_top_cmds(sub_cmds.clone())?;
-                        let out: BashOut = shell.into();
-
-                        // Add the stderr to the current shell:
-                        self.push_stderr(&out.stderr());
+                        let mut shell = Shell::new(
+                            self.vars.clone(),
+                            self.base_env.clone(),
+                            self.root_dir.clone(),
+                        )?;
+                        shell.dry_run = self.dry_run;
+                        shell.stdin_read_grace = self.stdin_read_grace;
+                        shell.memo = self.memo.clone();
+                        shell.set_e = self.set_e;
+                        shell.pipefail = self.pipefail;
+                        shell.positional = self.positional.clone();
+                        shell.set_code(self.code());
+                        // An exit only ends the subshell:
+                        if let Err(e) = shell.run_top_cmds(sub_cmds.clone()) {
+                            if !matches!(e.current_context(), ShellErr::Exit) {
+                                return Err(e);
+                            }
+                        }
+                        self.dry_run_plan.extend(mem::take(&mut shell.dry_run_plan));
+                        self.awaited_input |= shell.awaited_input;
+                        self.memo_hits.extend(mem::take(&mut shell.memo_hits));
 
-                        // Add the pre-computed stdout to be used as stdin to the next command in the outer runner:
-                        pipe_runner.add_piped_stdout(out.stdout());
+                        // The pre-computed stdout is used as stdin to the next command in the outer runner,
+                        // the code becomes the outer $?, but nothing else set in the subshell carries over:
+                        pipe_runner.add_output(shell.into());
                     }
                     ast::CompoundCommandKind::Brace(_) => {
                         return Err(unsup(
@@ -283,10 +353,13 @@ impl Shell {
                             "Compound if. A conditional command that runs the respective command branch when a certain of the first condition that exits successfully.",
                         ));
                     }
-                    ast::CompoundCommandKind::For { .. } => {
-                        return Err(unsup(
-                            "Compound for. A command that binds a variable to a number of provided words and runs its body once for each binding.",
-                        ));
+                    ast::CompoundCommandKind::For { var, words, body } => {
+                        let out = self.run_for(var, words.as_deref(), body)?;
+                        pipe_runner.add_output(out);
+                        // E.g. for ...; done > file.txt
+                        for redirect in compound.io.iter() {
+                            pipe_runner.add_redirect(redirect)?;
+                        }
                     }
                     ast::CompoundCommandKind::Case { .. } => {
                         return Err(unsup(
@@ -305,6 +378,90 @@ impl Shell {
         Ok(())
     }
 
+    /// Run a for loop, its body in the current environment like bash, so the loop variable
+    /// and anything else the body sets persists after the loop.
+    ///
+    /// The output is collected and returned to be piped onwards like a subshell's.
+    /// Stops early if a command fails with set -e, an `exit` in the body propagates out of the loop.
+    fn run_for(
+        &mut self,
+        var: &str,
+        words: Option<&[ast::TopLevelWord<String>]>,
+        body: &[ast::TopLevelCommand<String>],
+    ) -> RResult<BashOut, ShellErr> {
+        // Without a word list bash loops over the positional params:
+        let Some(words) = words else {
+            let items = self.positional.clone();
+            return self.run_for_items(var, items, body);
+        };
+        let mut items = vec![];
+        for word in words {
+            // Unquoted substitutions are split into separate words, e.g. for f in $(ls):
+            if matches!(
+                &word.0,
+                ast::ComplexWord::Single(ast::Word::Simple(
+                    ast::SimpleWord::Param(_) | ast::SimpleWord::Subst(_)
+                ))
+            ) {
+                let value = self.process_complex_word(&word.0)?;
+                items.extend(value.split_whitespace().map(|item| item.to_string()));
+            } else {
+                items.extend(self.expand_complex_word(&word.0)?);
+            }
+        }
+
+        self.run_for_items(var, items, body)
+    }
+
+    fn run_for_items(
+        &mut self,
+        var: &str,
+        items: Vec<String>,
+        body: &[ast::TopLevelCommand<String>],
+    ) -> RResult<BashOut, ShellErr> {
+        let mut shell = Shell::new(
+            self.vars.clone(),
+            self.base_env.clone(),
+            self.root_dir.clone(),
+        )?;
+        shell.set_e = self.set_e;
+        shell.pipefail = self.pipefail;
+        shell.dry_run = self.dry_run;
+        shell.stdin_read_grace = self.stdin_read_grace;
+        shell.memo = self.memo.clone();
+        shell.positional = self.positional.clone();
+        // $? carries into the body, but a loop without any iterations succeeds:
+        shell.set_code(if items.is_empty() { 0 } else { self.code() });
+        let mut result = Ok(());
+        for item in items {
+            shell.vars.insert(var.to_string(), item);
+            result = shell.run_top_cmds(body.to_vec());
+            if result.is_err() || !shell.should_continue() {
+                break;
+            }
+        }
+
+        // Carry any changes to the environment back over:
+        self.vars = mem::take(&mut shell.vars);
+        self.root_dir = shell.root_dir.take();
+        self.set_e = shell.set_e;
+        self.pipefail = shell.pipefail;
+        self.stage_usage.extend(mem::take(&mut shell.stage_usage));
+        self.dry_run_plan.extend(mem::take(&mut shell.dry_run_plan));
+        self.awaited_input |= shell.awaited_input;
+        self.memo_hits.extend(mem::take(&mut shell.memo_hits));
+        let out: BashOut = shell.into();
+
+        if let Err(e) = result {
+            // The rest of the pipeline won't run, so straight into this shell:
+            self.push_stdout(&out.stdout());
+            self.push_stderr(&out.stderr());
+            self.set_code(out.code());
+            return Err(e);
+        }
+        Ok(out)
+    }
+
     fn add_simple_command(
         &mut self,
         pipe_runner: &mut PipeRunner,
@@ -336,7 +493,7 @@ impl Shell {
         for arg in cmd.redirects_or_cmd_words.iter() {
             match arg {
                 ast::RedirectOrCmdWord::CmdWord(word) => {
-                    args.push(self.process_complex_word(&word.0)?)
+                    args.extend(self.expand_complex_word(&word.0)?)
                 }
                 ast::RedirectOrCmdWord::Redirect(redirect) => {
                     // A redirect occurring, split off into 2 commands surrounding the redirect:
@@ -378,6 +535,113 @@ impl Shell {
         }
     }
 
+    /// Process a word into the args it produces, unquoted `*`, `?` and `[...]` are expanded to the sorted matching paths,
+    /// relative to the active dir unless the pattern is absolute.
+    ///
+    /// Like bash, `*` and `?` don't match a leading `.`, and a pattern without any matches is passed through as is.
+    fn expand_complex_word(
+        &mut self,
+        word: &ast::DefaultComplexWord,
+    ) -> RResult<Vec<String>, ShellErr> {
+        let words = match word {
+            ast::ComplexWord::Single(word) => std::slice::from_ref(word),
+            ast::ComplexWord::Concat(words) => words.as_slice(),
+        };
+        // Quoted pattern chars are nested inside the quoted variants, so only these top level ones count:
+        let is_pattern = |word: &ast::DefaultWord| {
+            matches!(
+                word,
+                ast::Word::Simple(
+                    ast::SimpleWord::Star
+                        | ast::SimpleWord::Question
+                        | ast::SimpleWord::SquareOpen
+                        | ast::SimpleWord::SquareClose
+                )
+            )
+        };
+        if !words.iter().any(is_pattern) {
+            // $@, $* and "$@" on their own are one arg per positional param, "$*" is joined into one:
+            let is_positional_list = match word {
+                ast::ComplexWord::Single(ast::Word::Simple(ast::SimpleWord::Param(
+                    ast::Parameter::At | ast::Parameter::Star,
+                ))) => true,
+                ast::ComplexWord::Single(ast::Word::DoubleQuoted(parts)) => matches!(
+                    parts.as_slice(),
+                    [ast::SimpleWord::Param(ast::Parameter::At)]
+                ),
+                _ => false,
+            };
+            if is_positional_list {
+                return Ok(self.positional.clone());
+            }
+            return Ok(vec![self.process_complex_word(word)?]);
+        }
+
+        // Each part processed once, everything but the pattern chars escaped so only they are special:
+        let mut literal = String::new();
+        let mut pattern = String::new();
+        for (index, part) in words.iter().enumerate() {
+            let concat_state = match word {
+                ast::ComplexWord::Single(_) => None,
+                ast::ComplexWord::Concat(words) => Some(WordConcatState {
+                    active: index,
+                    words,
+                }),
+            };
+            let processed = self.process_word(part, concat_state.as_ref(), false)?;
+            if is_pattern(part) {
+                pattern.push_str(&processed);
+            } else {
+                pattern.push_str(&glob::Pattern::escape(&processed));
+            }
+            literal.push_str(&processed);
+        }
+
+        let active_dir = self.active_dir()?;
+        let relative = !PathBuf::from(&literal).is_absolute();
+        let full_pattern = if relative {
+            format!(
+                "{}{}{}",
+                glob::Pattern::escape(&active_dir.to_string_lossy()),
+                std::path::MAIN_SEPARATOR,
+                pattern
+            )
+        } else {
+            pattern
+        };
+        let options = glob::MatchOptions {
+            require_literal_leading_dot: true,
+            ..Default::default()
+        };
+        // An invalid pattern, e.g. a lone '[' as in '[ -f foo ]', is just treated as having no matches:
+        let mut matches = glob::glob_with(&full_pattern, options)
+            .map(|paths| {
+                paths
+                    .filter_map(|path| path.ok())
+                    .map(|path| {
+                        if relative {
+                            path.strip_prefix(&active_dir)
+                                .map(|path| path.to_path_buf())
+                                .unwrap_or(path)
+                        } else {
+                            path
+                        }
+                        .to_string_lossy()
+                        .to_string()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        debug!("Expanded glob '{}' to: {:?}", literal, matches);
+
+        if matches.is_empty() {
+            Ok(vec![literal])
+        } else {
+            matches.sort();
+            Ok(matches)
+        }
+    }
+
     fn process_word(
         &mut self,
         word: &ast::DefaultWord,
@@ -427,18 +691,11 @@ impl Shell {
             ast::SimpleWord::Subst(sub) => self.process_substitution(sub)?,
             // Colon does have some special meaning, but not currently supporting and also has normal meaning (e.g. on windows), so leaving as is:
             ast::SimpleWord::Colon => ":".to_string(),
-            ast::SimpleWord::Question => {
-                return Err(unsup("'?', useful for handling pattern expansions."));
-            }
-            ast::SimpleWord::Star => {
-                return Err(unsup("'*', useful for handling pattern expansions."));
-            }
-            ast::SimpleWord::SquareOpen => {
-                return Err(unsup("'[', useful for handling pattern expansions."));
-            }
-            ast::SimpleWord::SquareClose => {
-                return Err(unsup("']', useful for handling pattern expansions."));
-            }
+            // Pattern chars are kept as is here, only expanded against the filesystem by expand_complex_word() when unquoted:
+            ast::SimpleWord::Question => "?".to_string(),
+            ast::SimpleWord::Star => "*".to_string(),
+            ast::SimpleWord::SquareOpen => "[".to_string(),
+            ast::SimpleWord::SquareClose => "]".to_string(),
         })
     }
 
@@ -448,6 +705,9 @@ impl Shell {
                 // First try variables in current shell, otherwise try env:
                 let value = if let Some(val) = self.vars.get(var) {
                     val.clone()
+                } else if let Some(base_env) = &self.base_env {
+                    // Isolated, only the visible part of the env:
+                    base_env.get(var).cloned().unwrap_or_default()
                 } else {
                     // Return the env var, or empty string if not set:
                     std::env::var(var).unwrap_or_else(|_| "".to_string())
@@ -455,21 +715,17 @@ impl Shell {
                 debug!("Substituting param: '{}'='{}'", var, value);
                 value
             }
-            ast::Parameter::Positional(_) => {
-                return Err(unsup("positional, e.g. '$0, $1, ..., $9, ${100}'."));
-            }
-            ast::Parameter::At => {
-                return Err(unsup("$@'."));
-            }
-            ast::Parameter::Star => {
-                return Err(unsup("'$*'."));
-            }
-            ast::Parameter::Pound => {
-                return Err(unsup("'$#'."));
-            }
-            ast::Parameter::Question => {
-                return Err(unsup("'$?'."));
-            }
+            // Like a script run with 'bash -c':
+            ast::Parameter::Positional(0) => "bash".to_string(),
+            ast::Parameter::Positional(index) => self
+                .positional
+                .get(*index as usize - 1)
+                .cloned()
+                .unwrap_or_default(),
+            // Simplified to always joining with spaces, expand_complex_word() handles them on their own:
+            ast::Parameter::At | ast::Parameter::Star => self.positional.join(" "),
+            ast::Parameter::Pound => self.positional.len().to_string(),
+            ast::Parameter::Question => self.code().to_string(),
             ast::Parameter::Dash => {
                 return Err(unsup("'$-'."));
             }
@@ -493,9 +749,23 @@ impl Shell {
                 // - stdout is injected but trailing newlines removed
                 // - stderr prints to console so in our case it should be added to the root stderr
                 // - It runs in its own shell, so shell vars aren't shared
+                // In a dry run, either left as a placeholder, or executed for real (the nested shell isn't a dry run):
+                if self.dry_run == Some(DryRunSubstitutions::Stub) {
+                    return Ok("$(...)".to_string());
+                }
                 debug!("Running nested command: {:?}", cmds);
-                let mut shell = Shell::new(self.vars.clone(), self.root_dir.clone())?;
+                let mut shell = Shell::new(
+                            self.vars.clone(),
+                            self.base_env.clone(),
+                            self.root_dir.clone(),
+                        )?;
+                shell.positional = self.positional.clone();
+                shell.stdin_read_grace = self.stdin_read_grace;
+                shell.memo = self.memo.clone();
+                shell.set_code(self.code());
                 shell.run_top_cmds(cmds.clone())?;
+                self.awaited_input |= shell.awaited_input;
+                self.memo_hits.extend(mem::take(&mut shell.memo_hits));
                 let out: BashOut = shell.into();
 
                 // Add the stderr to the outer stderr, the stdout return to the caller:
@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::log::record_exception;
 
@@ -39,6 +40,127 @@ pub fn set_cookie(name: &str, value: &impl serde::Serialize, options: CookieOpti
     };
 }
 
+/// Browsers silently drop cookies larger than this, including the name.
+pub const MAX_COOKIE_BYTES: usize = 4096;
+
+/// Get a cookie set with [`set_cookie_json`].
+/// If the cookie isn't found, or if it fails to decode, returns None.
+/// When it fails to decode, an error will be recorded.
+pub fn get_cookie_json<T: DeserializeOwned>(name: &str) -> Option<T> {
+    decode_json(&get_cookie_raw(name)?)
+}
+
+/// Set a cookie to a serializable value, as base64 encoded json so any value is safe to store.
+///
+/// A warning is logged when the encoded cookie is larger than [`MAX_COOKIE_BYTES`], as browsers will drop it.
+/// If serialization fails, an error will be recorded.
+pub fn set_cookie_json(name: &str, value: &impl Serialize, options: CookieOptions<'_>) {
+    if let Some(encoded) = encode_json(value) {
+        set_cookie_checked(name, &encoded, options);
+    }
+}
+
+/// Get a cookie set with [`set_cookie_json_signed`] with the same key.
+/// If the cookie isn't found, its signature doesn't match (e.g. it was tampered with), or it fails to decode, returns None.
+#[cfg(feature = "hash")]
+pub fn get_cookie_json_signed<T: DeserializeOwned>(
+    name: &str,
+    key: &CookieSigningKey,
+) -> Option<T> {
+    let signed = get_cookie_raw(name)?;
+    decode_json(key.verify(name, &signed)?)
+}
+
+/// Same as [`set_cookie_json`], with an HMAC-SHA256 signature so changes made by the client are detected by [`get_cookie_json_signed`].
+///
+/// The value is still readable by the client, only tamper evident.
+/// The signature covers the cookie name, so a signed value can't be moved to another cookie.
+#[cfg(feature = "hash")]
+pub fn set_cookie_json_signed(
+    name: &str,
+    value: &impl Serialize,
+    key: &CookieSigningKey,
+    options: CookieOptions<'_>,
+) {
+    if let Some(encoded) = encode_json(value) {
+        set_cookie_checked(name, &key.sign(name, encoded), options);
+    }
+}
+
+/// The secret used by [`set_cookie_json_signed`] and [`get_cookie_json_signed`], should be long and random, kept server side.
+#[cfg(feature = "hash")]
+#[derive(Clone)]
+pub struct CookieSigningKey(Vec<u8>);
+
+#[cfg(feature = "hash")]
+impl CookieSigningKey {
+    /// Create a new signing key from a secret.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self(secret.as_ref().to_vec())
+    }
+
+    /// Append the signature of the value: `{value}.{signature}`.
+    fn sign(&self, name: &str, value: String) -> String {
+        let signature = crate::hash::hmac_sha256(&self.0, format!("{}={}", name, value));
+        format!("{}.{}", value, BASE64.encode(signature))
+    }
+
+    /// The value without its signature, None if the signature is missing or doesn't match.
+    fn verify<'v>(&self, name: &str, signed: &'v str) -> Option<&'v str> {
+        let (value, signature) = signed.rsplit_once('.')?;
+        let signature = BASE64.decode(signature).ok()?;
+        let expected = crate::hash::hmac_sha256(&self.0, format!("{}={}", name, value));
+        crate::hash::constant_time_eq(&expected, &signature).then_some(value)
+    }
+}
+
+#[cfg(feature = "hash")]
+impl std::fmt::Debug for CookieSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CookieSigningKey(***)")
+    }
+}
+
+fn encode_json(value: &impl Serialize) -> Option<String> {
+    match serde_json::to_vec(value) {
+        Ok(json) => Some(BASE64.encode(json)),
+        Err(e) => {
+            record_exception("Failed to serialize cookie value.", format!("{:?}", e));
+            None
+        }
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(encoded: &str) -> Option<T> {
+    let json = match BASE64.decode(encoded) {
+        Ok(json) => json,
+        Err(e) => {
+            record_exception("Failed to decode cookie value.", format!("{:?}", e));
+            return None;
+        }
+    };
+    match serde_json::from_slice(&json) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            record_exception("Failed to deserialize cookie value.", format!("{:?}", e));
+            None
+        }
+    }
+}
+
+fn set_cookie_checked(name: &str, value: &str, options: CookieOptions<'_>) {
+    let size = name.len() + value.len() + 1;
+    if size > MAX_COOKIE_BYTES {
+        tracing::warn!(
+            "Cookie '{}' is {} bytes encoded, over the {} browsers accept, it will likely be dropped.",
+            name,
+            size,
+            MAX_COOKIE_BYTES
+        );
+    }
+    set_cookie_raw(name, value, options);
+}
+
 /// Get the raw value of a cookie.
 /// If the cookie isn't found, returns None.
 pub fn get_cookie_raw(name: &str) -> Option<String> {
@@ -191,3 +313,64 @@ impl<'a> From<CookieOptions<'a>> for wasm_cookies::CookieOptions<'a> {
         inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Prefs {
+        theme: String,
+        tags: Vec<String>,
+    }
+
+    fn prefs() -> Prefs {
+        Prefs {
+            theme: "dark; \"quoted\", =odd".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+        }
+    }
+
+    #[rstest]
+    fn test_cookie_json_encoding() {
+        let encoded = encode_json(&prefs()).unwrap();
+        // Only chars that are safe in a cookie value:
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode_json::<Prefs>(&encoded), Some(prefs()));
+        assert_eq!(decode_json::<Prefs>("not base64!"), None);
+        assert_eq!(decode_json::<Prefs>(&BASE64.encode("[1, 2]")), None);
+    }
+
+    #[cfg(feature = "hash")]
+    #[rstest]
+    fn test_cookie_json_signing() {
+        let key = CookieSigningKey::new("secret");
+        let signed = key.sign("prefs", encode_json(&prefs()).unwrap());
+        let value = key.verify("prefs", &signed).unwrap();
+        assert_eq!(decode_json::<Prefs>(value), Some(prefs()));
+
+        // Tampering with the value, signature, or moving it to another cookie is rejected:
+        let (value, signature) = signed.rsplit_once('.').unwrap();
+        let tampered = encode_json(&Prefs {
+            theme: "light".to_string(),
+            tags: vec![],
+        })
+        .unwrap();
+        assert_eq!(
+            key.verify("prefs", &format!("{}.{}", tampered, signature)),
+            None
+        );
+        assert_eq!(key.verify("prefs", &format!("{}.{}", value, "AAAA")), None);
+        assert_eq!(key.verify("prefs", value), None);
+        assert_eq!(key.verify("other", &signed), None);
+        assert_eq!(
+            CookieSigningKey::new("wrong").verify("prefs", &signed),
+            None
+        );
+        assert_eq!(format!("{:?}", key), "CookieSigningKey(***)");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    backtrace::Backtrace,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use error_stack::{Context, Report};
+
+/// When backtraces are captured for reports created with [`crate::anyerr`] and [`crate::err`], see [`set_backtrace_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BacktracePolicy {
+    /// Never capture, the cheapest, good for hot paths full of expected errors.
+    #[default]
+    Never,
+    /// Only capture when the current subscriber would record an `ERROR` level event, i.e. when there's somewhere for the backtrace to end up.
+    OnErrorLevel,
+    /// Always capture.
+    Always,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(BacktracePolicy::Never as u8);
+
+/// Set the process wide [`BacktracePolicy`], takes effect for all reports created afterwards.
+///
+/// Capturing a backtrace is by far the most expensive part of creating a report.
+/// Independently of the policy, `error_stack` captures one for every report whenever `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` enable it,
+/// leave those unset (or set `RUST_LIB_BACKTRACE=0` to keep panic backtraces) for the policy to be in full control.
+///
+/// Use [`crate::anyerr_nobt`] or [`BacktraceResultExt::without_backtrace`] for errors that should never capture no matter the policy.
+pub fn set_backtrace_policy(policy: BacktracePolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// The current process wide [`BacktracePolicy`].
+pub fn backtrace_policy() -> BacktracePolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        x if x == BacktracePolicy::OnErrorLevel as u8 => BacktracePolicy::OnErrorLevel,
+        x if x == BacktracePolicy::Always as u8 => BacktracePolicy::Always,
+        _ => BacktracePolicy::Never,
+    }
+}
+
+/// Attach a backtrace to the report if the current [`BacktracePolicy`] requires one, used internally by [`crate::anyerr`] and [`crate::err`].
+///
+/// Reports already carrying a backtrace (e.g. from the env vars) are left as is.
+/// Only the stack is walked here, symbols are resolved lazily when the report is actually formatted.
+pub fn attach_policy_backtrace<C>(report: Report<C>) -> Report<C> {
+    let capture = match backtrace_policy() {
+        BacktracePolicy::Never => false,
+        BacktracePolicy::OnErrorLevel => tracing::enabled!(tracing::Level::ERROR),
+        BacktracePolicy::Always => true,
+    };
+    if capture && !report.contains::<Backtrace>() {
+        report.attach(Backtrace::force_capture())
+    } else {
+        report
+    }
+}
+
+/// Converting plain errors to reports without a policy backtrace.
+pub trait BacktraceResultExt<T, C> {
+    /// Convert the error to a [`Report`] that never has a policy backtrace attached,
+    /// for cheap expected errors (e.g. validation failures), the [`crate::anyerr_nobt`] equivalent for existing errors.
+    fn without_backtrace(self) -> Result<T, Report<C>>;
+}
+
+impl<T, C: Context> BacktraceResultExt<T, C> for Result<T, C> {
+    fn without_backtrace(self) -> Result<T, Report<C>> {
+        self.map_err(Report::new)
+    }
+}
+
+/// Wraps a [`std::fmt::Debug`] value to be formatted with [`std::fmt::Display`], only when actually written.
+///
+/// Pass e.g. `LazyDebug(&report)` as the stacktrace of [`crate::log::record_exception`],
+/// the report (and its backtraces) will only be formatted if the event passes the subscriber's filters.
+pub struct LazyDebug<T>(pub T);
+
+impl<T: std::fmt::Debug> std::fmt::Display for LazyDebug<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl<T: std::fmt::Debug> From<LazyDebug<T>> for String {
+    fn from(lazy: LazyDebug<T>) -> Self {
+        format!("{:?}", lazy.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::prelude::*;
+
+    fn has_backtrace<C>(report: &Report<C>) -> bool {
+        report.contains::<Backtrace>()
+    }
+
+    #[rstest]
+    fn test_backtrace_policy() {
+        set_backtrace_policy(BacktracePolicy::Never);
+        // If the env vars enable it, error_stack captures regardless of the policy:
+        let env_capture =
+            Backtrace::capture().status() == std::backtrace::BacktraceStatus::Captured;
+
+        // Policy changes apply immediately:
+        set_backtrace_policy(BacktracePolicy::Always);
+        assert_eq!(backtrace_policy(), BacktracePolicy::Always);
+        assert!(has_backtrace(&anyerr!("foo")));
+        assert!(has_backtrace(&err!(AnyErr, "foo: {}", 1)));
+
+        // No subscriber in this test, so nowhere for errors to go:
+        set_backtrace_policy(BacktracePolicy::OnErrorLevel);
+        assert_eq!(has_backtrace(&anyerr!()), env_capture);
+
+        set_backtrace_policy(BacktracePolicy::Never);
+        assert_eq!(has_backtrace(&anyerr!("foo")), env_capture);
+        if env_capture {
+            return;
+        }
+
+        // Explicitly cheap errors never capture:
+        set_backtrace_policy(BacktracePolicy::Always);
+        assert!(!has_backtrace(&anyerr_nobt!("foo: {}", 1)));
+        assert!(!has_backtrace(
+            &Err::<(), _>(AnyErr).without_backtrace().unwrap_err()
+        ));
+
+        // Loose benchmark, cheap errors are roughly an order of magnitude faster, a lower bound to keep debug builds and busy ci from flaking:
+        let time = |f: &dyn Fn() -> Report<AnyErr>| {
+            let start = std::time::Instant::now();
+            for _ in 0..10_000 {
+                let _ = std::hint::black_box(f());
+            }
+            start.elapsed()
+        };
+        let always = time(&|| anyerr!("foo"));
+        let nobt = time(&|| anyerr_nobt!("foo"));
+        set_backtrace_policy(BacktracePolicy::Never);
+        let never = time(&|| anyerr!("foo"));
+        assert!(always > nobt * 5, "{:?} vs {:?}", always, nobt);
+        assert!(always > never * 5, "{:?} vs {:?}", always, never);
+    }
+}
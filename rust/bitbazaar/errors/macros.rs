@@ -5,8 +5,37 @@
 /// `anyerr!("foo")` is equivalent to `Report::new(AnyErr).attach_printable("foo")`
 ///
 /// `anyerr!("foo: {}", "bar")` is equivalent to `Report::new(AnyErr).attach_printable(format!("foo: {}", "bar"))`
+///
+/// A backtrace is attached depending on the [`crate::errors::BacktracePolicy`].
 #[macro_export]
 macro_rules! anyerr {
+    () => {{
+        use error_stack::Report;
+        use $crate::errors::AnyErr;
+
+        $crate::errors::attach_policy_backtrace(Report::new(AnyErr))
+    }};
+
+    ($str:expr) => {{
+        use error_stack::Report;
+        use $crate::errors::AnyErr;
+
+        $crate::errors::attach_policy_backtrace(Report::new(AnyErr)).attach_printable($str)
+    }};
+
+    ($str:expr, $($arg:expr),*) => {{
+        use error_stack::Report;
+        use $crate::errors::AnyErr;
+
+        $crate::errors::attach_policy_backtrace(Report::new(AnyErr)).attach_printable(format!($str, $($arg),*))
+    }};
+}
+
+/// The same as [`crate::anyerr`], but never attaches a backtrace regardless of the [`crate::errors::BacktracePolicy`].
+///
+/// For cheap expected errors in hot paths, e.g. validation failures.
+#[macro_export]
+macro_rules! anyerr_nobt {
     () => {{
         use error_stack::Report;
         use $crate::errors::AnyErr;
@@ -36,24 +65,26 @@ macro_rules! anyerr {
 /// `err!(Err, "foo")` is equivalent to `Report::new(Err).attach_printable("foo")`
 ///
 /// `err!(Err, "foo: {}", "bar")` is equivalent to `Report::new(Err).attach_printable(format!("foo: {}", "bar"))`///
+///
+/// A backtrace is attached depending on the [`crate::errors::BacktracePolicy`].
 #[macro_export]
 macro_rules! err {
     ($err_variant:expr) => {{
         use error_stack::Report;
 
-        Report::new($err_variant)
+        $crate::errors::attach_policy_backtrace(Report::new($err_variant))
     }};
 
     ($err_variant:expr, $str:expr) => {{
         use error_stack::Report;
 
-        Report::new($err_variant).attach_printable($str)
+        $crate::errors::attach_policy_backtrace(Report::new($err_variant)).attach_printable($str)
     }};
 
     ($err_variant:expr, $str:expr, $($arg:expr),*) => {{
         use error_stack::Report;
 
-        Report::new($err_variant).attach_printable(format!($str, $($arg),*))
+        $crate::errors::attach_policy_backtrace(Report::new($err_variant)).attach_printable(format!($str, $($arg),*))
     }};
 }
 
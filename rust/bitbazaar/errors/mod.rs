@@ -1,7 +1,15 @@
 mod any;
+mod backtrace;
 mod macros;
+#[cfg(any(test, feature = "test"))]
+/// Assertion helpers for tests against [`RResult`]s and [`error_stack::Report`]s.
+pub mod test_helpers;
 
 pub use any::AnyErr;
+pub use backtrace::{
+    attach_policy_backtrace, backtrace_policy, set_backtrace_policy, BacktracePolicy,
+    BacktraceResultExt, LazyDebug,
+};
 
 /// Shorthand for a [`Result`] with a [`Report`] as the error variant
 pub type RResult<T, C> = Result<T, error_stack::Report<C>>;
@@ -12,8 +20,8 @@ pub mod prelude {
     pub use error_stack::{Report, ResultExt};
 
     #[allow(unused_imports)]
-    pub use super::{AnyErr, RResult};
+    pub use super::{AnyErr, BacktraceResultExt, RResult};
 
     #[allow(unused_imports)]
-    pub use crate::{anyerr, err, panic_on_err, panic_on_err_async};
+    pub use crate::{anyerr, anyerr_nobt, err, panic_on_err, panic_on_err_async};
 }
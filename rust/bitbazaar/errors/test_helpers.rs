@@ -0,0 +1,147 @@
+use std::fmt::Debug;
+
+use error_stack::{Context, Report};
+
+use super::RResult;
+
+/// Unwrap the error of a result, panicking with the Ok value if it succeeded.
+#[track_caller]
+pub fn expect_err_report<T: Debug, C: Context>(result: RResult<T, C>) -> Report<C> {
+    match result {
+        Ok(value) => panic!("Expected an error, but the result was Ok:\n{:#?}", value),
+        Err(report) => report,
+    }
+}
+
+/// Assert a result errored with `Wanted` somewhere in its chain of contexts, returning the report for further checks.
+///
+/// The returned report's context type is left opaque so only `Wanted` needs giving,
+/// use [`Report::downcast_ref`] to get at it, or [`expect_err_report`] when the concrete type is needed.
+#[track_caller]
+pub fn assert_err_context<Wanted: Context>(
+    result: RResult<impl Debug, impl Context>,
+) -> Report<impl Context> {
+    let report = expect_err_report(result);
+    if !report.contains::<Wanted>() {
+        panic!(
+            "Expected the error to contain a '{}' context, but it didn't:\n{:?}",
+            std::any::type_name::<Wanted>(),
+            report
+        );
+    }
+    report
+}
+
+/// Assert a result errored, with `substr` somewhere in its debug output (contexts, attachments and locations),
+/// returning the report for further checks.
+#[track_caller]
+pub fn assert_err_contains<T: Debug, C: Context>(result: RResult<T, C>, substr: &str) -> Report<C> {
+    let report = expect_err_report(result);
+    let formatted = format!("{:?}", report);
+    if !formatted.contains(substr) {
+        panic!(
+            "Expected the error to contain '{}', but it didn't:\n{}",
+            substr, formatted
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, UnwindSafe};
+
+    use super::*;
+    use crate::testing::prelude::*;
+
+    #[derive(Debug)]
+    struct Inner;
+
+    impl std::fmt::Display for Inner {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Inner")
+        }
+    }
+
+    impl Context for Inner {}
+
+    fn failing() -> RResult<Vec<u8>, AnyErr> {
+        Err(Report::new(Inner)
+            .attach_printable("Disk full.")
+            .change_context(AnyErr))
+    }
+
+    fn panic_msg(f: impl FnOnce() + UnwindSafe) -> String {
+        let payload = catch_unwind(f).unwrap_err();
+        payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap()
+    }
+
+    #[rstest]
+    fn test_expect_err_report() {
+        assert!(expect_err_report(failing()).contains::<Inner>());
+
+        let msg = panic_msg(|| {
+            let _ = expect_err_report(Ok::<_, Report<AnyErr>>(vec![1, 2]));
+        });
+        assert!(
+            msg.contains("Expected an error, but the result was Ok"),
+            "{}",
+            msg
+        );
+        // The Ok value is pretty printed:
+        assert!(msg.contains("[\n    1,\n    2,\n]"), "{}", msg);
+    }
+
+    #[rstest]
+    fn test_assert_err_context() {
+        // Both the current context and ones further down the chain match:
+        let _ = assert_err_context::<AnyErr>(failing());
+        let report = assert_err_context::<Inner>(failing());
+        assert!(format!("{:?}", report).contains("Disk full."));
+
+        let msg = panic_msg(|| {
+            let _ = assert_err_context::<std::fmt::Error>(failing());
+        });
+        assert!(
+            msg.contains("Expected the error to contain a 'core::fmt::Error' context"),
+            "{}",
+            msg
+        );
+        // The report is included to see what it did contain:
+        assert!(msg.contains("Disk full."), "{}", msg);
+
+        let msg = panic_msg(|| {
+            let _ = assert_err_context::<Inner>(Ok::<_, Report<AnyErr>>(1));
+        });
+        assert!(msg.contains("the result was Ok"), "{}", msg);
+    }
+
+    #[rstest]
+    #[case::context("Inner")]
+    #[case::attachment("Disk full.")]
+    fn test_assert_err_contains_passes(#[case] substr: &str) {
+        assert!(assert_err_contains(failing(), substr).contains::<Inner>());
+    }
+
+    #[rstest]
+    fn test_assert_err_contains_fails() {
+        let msg = panic_msg(|| {
+            let _ = assert_err_contains(failing(), "Disk empty.");
+        });
+        assert!(
+            msg.contains("Expected the error to contain 'Disk empty.'"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("Disk full."), "{}", msg);
+
+        let msg = panic_msg(|| {
+            let _ = assert_err_contains(Ok::<_, Report<AnyErr>>("fine"), "Disk full.");
+        });
+        assert!(msg.contains("\"fine\""), "{}", msg);
+    }
+}
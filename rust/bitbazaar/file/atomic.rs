@@ -0,0 +1,265 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::prelude::*;
+
+/// Distinguishes temp files created by the same process at the same time.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Write a file atomically, readers (and crashes) only ever see the old or the new contents, never a partial write.
+///
+/// Written to a temp file next to the target, synced to disk, then renamed over the target.
+/// The target's permissions are kept if it already exists.
+pub fn atomic_write_sync(path: impl AsRef<Path>, bytes: impl AsRef<[u8]>) -> RResult<(), AnyErr> {
+    atomic_write_with(path, |writer| writer.write_all(bytes.as_ref()))
+}
+
+/// Async [`atomic_write_sync`], run on tokio's blocking pool.
+pub async fn atomic_write_async(
+    path: impl AsRef<Path>,
+    bytes: impl Into<Vec<u8>>,
+) -> RResult<(), AnyErr> {
+    let path = path.as_ref().to_path_buf();
+    let bytes = bytes.into();
+    tokio::task::spawn_blocking(move || atomic_write_sync(path, bytes))
+        .await
+        .change_context(AnyErr)?
+}
+
+/// [`atomic_write_sync`] for streaming the contents, e.g. serializing straight into the file.
+///
+/// If the writer errors or panics the target is left untouched and the temp file removed.
+pub fn atomic_write_with(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+) -> RResult<(), AnyErr> {
+    let path = path.as_ref();
+    atomic_write_inner(path, write)
+        .change_context(AnyErr)
+        .attach_printable_lazy(|| format!("Couldn't atomically write '{}'.", path.display()))
+}
+
+fn atomic_write_inner(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path has no file name.")
+    })?;
+    // Same dir as the target, so the rename can't be across devices:
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let (file, tmp) = create_tmp(&dir, &file_name.to_string_lossy())?;
+
+    let mut writer = std::io::BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    if let Ok(meta) = std::fs::metadata(path) {
+        file.set_permissions(meta.permissions())?;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    rename_over(&tmp.path, path)?;
+    tmp.disarm();
+
+    // Persist the rename itself, dirs can't be opened for syncing on windows:
+    #[cfg(unix)]
+    std::fs::File::open(&dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Removes the temp file on drop unless disarmed, including when the writer panics.
+struct TmpFile {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl TmpFile {
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn create_tmp(dir: &Path, file_name: &str) -> std::io::Result<(std::fs::File, TmpFile)> {
+    loop {
+        let path = dir.join(format!(
+            ".{}.{}-{}.tmp",
+            file_name,
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left over from a crashed process with a reused pid, just try the next:
+        match std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, TmpFile { path, armed: true })),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn rename_over(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::rename(from, to)
+}
+
+/// Renaming already replaces existing files on windows (MoveFileExW with MOVEFILE_REPLACE_EXISTING),
+/// but fails whilst the target is open elsewhere, e.g. by an antivirus scan or another reader, so retry briefly.
+#[cfg(windows)]
+fn rename_over(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        match std::fs::rename(from, to) {
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && attempt < 10 => {
+                attempt += 1;
+                std::thread::sleep(std::time::Duration::from_millis(10 * attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut entries = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_atomic_write() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let path = temp_dir.path().join("data.txt");
+
+        atomic_write_sync(&path, "one")?;
+        assert_eq!(
+            std::fs::read_to_string(&path).change_context(AnyErr)?,
+            "one"
+        );
+        atomic_write_async(&path, "two").await?;
+        assert_eq!(
+            std::fs::read_to_string(&path).change_context(AnyErr)?,
+            "two"
+        );
+        atomic_write_with(&path, |writer| {
+            for part in ["th", "r", "ee"] {
+                writer.write_all(part.as_bytes())?;
+            }
+            Ok(())
+        })?;
+        assert_eq!(
+            std::fs::read_to_string(&path).change_context(AnyErr)?,
+            "three"
+        );
+
+        // Failing partway keeps the old contents:
+        assert!(atomic_write_with(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(std::io::Error::other("failed"))
+        })
+        .is_err());
+        let result = std::panic::catch_unwind(|| {
+            atomic_write_with(&path, |writer| {
+                writer.write_all(b"partial")?;
+                panic!("writer panicked");
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).change_context(AnyErr)?,
+            "three"
+        );
+        // No temp files left behind:
+        assert_eq!(dir_entries(temp_dir.path()), vec!["data.txt"]);
+
+        // Missing dirs aren't created:
+        assert!(atomic_write_sync(temp_dir.path().join("missing/data.txt"), "x").is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_atomic_write_keeps_permissions() -> RResult<(), AnyErr> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let path = temp_dir.path().join("script.sh");
+        std::fs::write(&path, "old").change_context(AnyErr)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750))
+            .change_context(AnyErr)?;
+
+        atomic_write_sync(&path, "new")?;
+        assert_eq!(
+            std::fs::read_to_string(&path).change_context(AnyErr)?,
+            "new"
+        );
+        let mode = std::fs::metadata(&path)
+            .change_context(AnyErr)?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_atomic_write_concurrent() -> RResult<(), AnyErr> {
+        const SIZE: usize = 256 * 1024;
+
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let path = temp_dir.path().join("data.bin");
+        atomic_write_sync(&path, vec![0; SIZE])?;
+
+        std::thread::scope(|scope| {
+            for writer in 1..=4u8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        atomic_write_sync(path, vec![writer; SIZE]).unwrap();
+                    }
+                });
+            }
+            // Every read sees one complete write:
+            let path = &path;
+            scope.spawn(move || {
+                for _ in 0..50 {
+                    let contents = std::fs::read(path).unwrap();
+                    assert_eq!(contents.len(), SIZE);
+                    assert!(contents.iter().all(|byte| *byte == contents[0]));
+                }
+            });
+        });
+
+        let contents = std::fs::read(&path).change_context(AnyErr)?;
+        assert_eq!(contents.len(), SIZE);
+        assert!((1..=4).contains(&contents[0]));
+        assert!(contents.iter().all(|byte| *byte == contents[0]));
+        assert_eq!(dir_entries(temp_dir.path()), vec!["data.bin"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,744 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::prelude::*;
+
+/// What to do when a file being copied already exists at the destination, see [`CopyDirOpts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopyOverwrite {
+    /// Leave the existing file, counted as skipped.
+    Skip,
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Fail for the file.
+    Error,
+}
+
+/// How symlinks in the source are copied, see [`CopyDirOpts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopySymlinks {
+    /// Copy what the link points to, links to dirs are recursed into. Loops and broken links error.
+    Follow,
+    /// Create an identical link at the destination, the target is kept as is (relative targets stay relative).
+    #[default]
+    Recreate,
+}
+
+/// Passed to the [`CopyDirOpts::on_progress`] callback after each file is copied.
+#[derive(Debug, Clone, Copy)]
+pub struct CopyProgress<'a> {
+    /// The files (and recreated symlinks) copied so far, including this one.
+    pub files_done: u64,
+    /// The bytes copied so far, including this file.
+    pub bytes_done: u64,
+    /// The source path of the file just copied.
+    pub current_path: &'a Path,
+}
+
+/// The totals of a finished copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyDirStats {
+    /// The files (and recreated symlinks) copied.
+    pub files_copied: u64,
+    /// The files left alone because they existed at the destination, with [`CopyOverwrite::Skip`].
+    pub files_skipped: u64,
+    /// The bytes copied.
+    pub bytes_copied: u64,
+}
+
+/// Options for [`copy_dir_sync`] and [`move_dir_sync`].
+#[derive(Clone, Default)]
+pub struct CopyDirOpts {
+    /// What to do with files that already exist at the destination, defaults to [`CopyOverwrite::Overwrite`].
+    pub overwrite: CopyOverwrite,
+    /// How symlinks are copied, defaults to [`CopySymlinks::Recreate`].
+    pub symlinks: CopySymlinks,
+    /// When not empty, only files matching one of these globs are copied, dirs are only created when they end up with an included file.
+    ///
+    /// Globs containing a `/` match the path relative to the source dir, e.g. `src/**/*.rs`, otherwise just the file name, e.g. `*.rs`.
+    pub include: Vec<String>,
+    /// Files and dirs matching one of these globs are skipped, a skipped dir skips everything inside it. Same matching as `include`.
+    pub exclude: Vec<String>,
+    /// Called after each file is copied, e.g. to drive a progress bar. Runs on the copying thread.
+    pub on_progress: Option<Arc<dyn Fn(&CopyProgress<'_>) + Send + Sync>>,
+    /// Carry on past failures, the returned error holds a report for every failed path.
+    ///
+    /// Otherwise the copy stops at the first failure.
+    pub best_effort: bool,
+}
+
+/// Recursively copy the contents of `src` into `dst`, creating `dst` if missing.
+pub fn copy_dir_sync(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    opts: &CopyDirOpts,
+) -> RResult<CopyDirStats, AnyErr> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let mut copier = Copier::new(src, opts, false)?;
+    copier.run(src, dst)?;
+    Ok(copier.stats)
+}
+
+/// Async [`copy_dir_sync`], run on tokio's blocking pool.
+pub async fn copy_dir_async(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    opts: CopyDirOpts,
+) -> RResult<CopyDirStats, AnyErr> {
+    let (src, dst) = (src.as_ref().to_path_buf(), dst.as_ref().to_path_buf());
+    tokio::task::spawn_blocking(move || copy_dir_sync(src, dst, &opts))
+        .await
+        .change_context(AnyErr)?
+}
+
+/// Recursively move `src` to `dst`.
+///
+/// A plain rename when `dst` doesn't exist yet and no filters are set, otherwise (or when the rename fails, e.g. across filesystems)
+/// falls back to a copy, then deleting what was copied from `src`.
+/// Files not copied (filtered out or skipped) stay in `src`, which is only removed once empty.
+/// Nothing is deleted if any part of the copy failed. The returned stats are empty for a plain rename, and no progress is reported.
+pub fn move_dir_sync(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    opts: &CopyDirOpts,
+) -> RResult<CopyDirStats, AnyErr> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if opts.include.is_empty()
+        && opts.exclude.is_empty()
+        && !dst.exists()
+        && std::fs::rename(src, dst).is_ok()
+    {
+        return Ok(CopyDirStats::default());
+    }
+
+    let mut copier = Copier::new(src, opts, true)?;
+    copier.run(src, dst)?;
+    for path in copier.copied.iter() {
+        std::fs::remove_file(path)
+            .change_context(AnyErr)
+            .attach_printable_lazy(|| format!("Couldn't remove '{}'.", path.display()))?;
+    }
+    // Deepest first, so parents are empty by the time they're reached:
+    copier
+        .visited_dirs
+        .sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in copier.visited_dirs.iter() {
+        // Still holds something not moved:
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(copier.stats)
+}
+
+/// Async [`move_dir_sync`], run on tokio's blocking pool.
+pub async fn move_dir_async(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    opts: CopyDirOpts,
+) -> RResult<CopyDirStats, AnyErr> {
+    let (src, dst) = (src.as_ref().to_path_buf(), dst.as_ref().to_path_buf());
+    tokio::task::spawn_blocking(move || move_dir_sync(src, dst, &opts))
+        .await
+        .change_context(AnyErr)?
+}
+
+struct Copier<'a> {
+    opts: &'a CopyDirOpts,
+    src_root: PathBuf,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    stats: CopyDirStats,
+    /// Canonical paths of the dirs currently being walked, to catch symlink loops.
+    ancestors: HashSet<PathBuf>,
+    /// The source files and dirs copied, only tracked for moving.
+    track_for_move: bool,
+    copied: Vec<PathBuf>,
+    visited_dirs: Vec<PathBuf>,
+    /// How many followed dir links deep the walk is, nothing inside them is removed when moving.
+    link_depth: usize,
+    /// Only used in best effort mode.
+    errors: Option<Report<AnyErr>>,
+    error_count: usize,
+}
+
+impl<'a> Copier<'a> {
+    fn new(src: &Path, opts: &'a CopyDirOpts, track_for_move: bool) -> RResult<Self, AnyErr> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|raw| {
+                    glob::Pattern::new(raw)
+                        .change_context(AnyErr)
+                        .attach_printable_lazy(|| format!("Invalid glob: '{}'.", raw))
+                })
+                .collect::<RResult<Vec<_>, AnyErr>>()
+        };
+        Ok(Self {
+            opts,
+            src_root: src.to_path_buf(),
+            include: compile(&opts.include)?,
+            exclude: compile(&opts.exclude)?,
+            stats: CopyDirStats::default(),
+            ancestors: HashSet::new(),
+            track_for_move,
+            copied: vec![],
+            visited_dirs: vec![],
+            link_depth: 0,
+            errors: None,
+            error_count: 0,
+        })
+    }
+
+    fn run(&mut self, src: &Path, dst: &Path) -> RResult<(), AnyErr> {
+        let src_canonical = src
+            .canonicalize()
+            .change_context(AnyErr)
+            .attach_printable_lazy(|| format!("Source dir '{}' not found.", src.display()))?;
+        if !src_canonical.is_dir() {
+            return Err(anyerr!("Source '{}' isn't a dir.", src.display()));
+        }
+        std::fs::create_dir_all(dst)
+            .change_context(AnyErr)
+            .attach_printable_lazy(|| format!("Couldn't create '{}'.", dst.display()))?;
+        // Would otherwise keep copying its own output:
+        if dst
+            .canonicalize()
+            .is_ok_and(|dst| dst.starts_with(&src_canonical))
+        {
+            return Err(anyerr!(
+                "Can't copy '{}' into itself at '{}'.",
+                src.display(),
+                dst.display()
+            ));
+        }
+
+        self.ancestors.insert(src_canonical);
+        self.copy_dir_contents(src, dst).attach_printable_lazy(|| {
+            format!("Couldn't copy '{}' to '{}'.", src.display(), dst.display())
+        })?;
+        match self.errors.take() {
+            Some(report) => Err(report.attach_printable(format!(
+                "Couldn't copy '{}' to '{}', {} path(s) failed.",
+                src.display(),
+                dst.display(),
+                self.error_count
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the failure in best effort mode, otherwise returns it to abort the copy.
+    fn handle(&mut self, result: RResult<(), AnyErr>, path: &Path) -> RResult<(), AnyErr> {
+        let Err(report) = result else {
+            return Ok(());
+        };
+        let report = report.attach_printable(format!("Path: '{}'.", path.display()));
+        if !self.opts.best_effort {
+            return Err(report);
+        }
+        self.error_count += 1;
+        match self.errors.as_mut() {
+            Some(errors) => errors.extend_one(report),
+            None => self.errors = Some(report),
+        }
+        Ok(())
+    }
+
+    fn copy_dir_contents(&mut self, src: &Path, dst: &Path) -> RResult<(), AnyErr> {
+        if self.track_for_move && self.link_depth == 0 {
+            self.visited_dirs.push(src.to_path_buf());
+        }
+        let entries = std::fs::read_dir(src).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => return self.handle(Err(e).change_context(AnyErr), src),
+        };
+        // Deterministic progress order:
+        entries.sort();
+
+        for src_path in entries {
+            let Some(file_name) = src_path.file_name() else {
+                continue;
+            };
+            let dst_path = dst.join(file_name);
+            let result = self.copy_entry(&src_path, &dst_path);
+            self.handle(result, &src_path)?;
+        }
+        Ok(())
+    }
+
+    fn copy_entry(&mut self, src: &Path, dst: &Path) -> RResult<(), AnyErr> {
+        let rel = src.strip_prefix(&self.src_root).unwrap_or(src);
+        if matches_any(&self.exclude, rel) {
+            return Ok(());
+        }
+
+        let link_meta = std::fs::symlink_metadata(src).change_context(AnyErr)?;
+        let is_link = link_meta.file_type().is_symlink();
+        if is_link && self.opts.symlinks == CopySymlinks::Recreate {
+            if !self.included(rel) || !self.make_room(dst)? {
+                return Ok(());
+            }
+            let target = std::fs::read_link(src).change_context(AnyErr)?;
+            self.ensure_parent(dst)?;
+            create_symlink(src, &target, dst).change_context(AnyErr)?;
+            return self.record_copied(src, 0);
+        }
+
+        let meta = if is_link {
+            std::fs::metadata(src)
+                .change_context(AnyErr)
+                .attach_printable("Broken symlink.")?
+        } else {
+            link_meta
+        };
+        if meta.is_dir() {
+            let canonical = src.canonicalize().change_context(AnyErr)?;
+            if !self.ancestors.insert(canonical.clone()) {
+                return Err(anyerr!("Symlink loop, points to a parent dir."));
+            }
+            if is_link {
+                self.link_depth += 1;
+            }
+            let result = self
+                .create_dir(dst)
+                .and_then(|_| self.copy_dir_contents(src, dst));
+            self.ancestors.remove(&canonical);
+            if is_link {
+                self.link_depth -= 1;
+                // Moving removes the link itself, never what it points to:
+                if result.is_ok() && self.track_for_move && self.link_depth == 0 {
+                    self.copied.push(src.to_path_buf());
+                }
+            }
+            return result;
+        }
+
+        if !self.included(rel) || !self.make_room(dst)? {
+            return Ok(());
+        }
+        self.ensure_parent(dst)?;
+        let bytes = std::fs::copy(src, dst).change_context(AnyErr)?;
+        self.record_copied(src, bytes)
+    }
+
+    fn included(&self, rel: &Path) -> bool {
+        self.include.is_empty() || matches_any(&self.include, rel)
+    }
+
+    /// False when the file should be skipped.
+    fn make_room(&mut self, dst: &Path) -> RResult<bool, AnyErr> {
+        let Ok(existing) = std::fs::symlink_metadata(dst) else {
+            return Ok(true);
+        };
+        if existing.is_dir() {
+            return Err(anyerr!("A dir already exists at '{}'.", dst.display()));
+        }
+        match self.opts.overwrite {
+            CopyOverwrite::Skip => {
+                self.stats.files_skipped += 1;
+                Ok(false)
+            }
+            CopyOverwrite::Error => Err(anyerr!("'{}' already exists.", dst.display())),
+            CopyOverwrite::Overwrite => {
+                // Copying would otherwise write through an existing link:
+                if existing.file_type().is_symlink() {
+                    std::fs::remove_file(dst).change_context(AnyErr)?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn create_dir(&self, dst: &Path) -> RResult<(), AnyErr> {
+        // Created lazily when filtering by include, so unmatched dirs don't show up empty:
+        if !self.include.is_empty() {
+            return Ok(());
+        }
+        match std::fs::symlink_metadata(dst) {
+            Ok(meta) if meta.is_dir() => Ok(()),
+            Ok(_) => Err(anyerr!("A file already exists at '{}'.", dst.display())),
+            Err(_) => std::fs::create_dir(dst).change_context(AnyErr),
+        }
+    }
+
+    fn ensure_parent(&self, dst: &Path) -> RResult<(), AnyErr> {
+        if let Some(parent) = dst.parent() {
+            if !self.include.is_empty() {
+                std::fs::create_dir_all(parent).change_context(AnyErr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record_copied(&mut self, src: &Path, bytes: u64) -> RResult<(), AnyErr> {
+        self.stats.files_copied += 1;
+        self.stats.bytes_copied += bytes;
+        if self.track_for_move && self.link_depth == 0 {
+            self.copied.push(src.to_path_buf());
+        }
+        if let Some(on_progress) = &self.opts.on_progress {
+            on_progress(&CopyProgress {
+                files_done: self.stats.files_copied,
+                bytes_done: self.stats.bytes_copied,
+                current_path: src,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn matches_any(patterns: &[glob::Pattern], rel: &Path) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    patterns.iter().any(|pattern| {
+        if pattern.as_str().contains('/') {
+            pattern.matches_path_with(rel, options)
+        } else {
+            rel.file_name()
+                .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options))
+        }
+    })
+}
+
+#[cfg(unix)]
+fn create_symlink(_src: &Path, target: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, dst)
+}
+
+/// Windows has separate file and dir links, decided by what the source link currently points to.
+#[cfg(windows)]
+fn create_symlink(src: &Path, target: &Path, dst: &Path) -> std::io::Result<()> {
+    if std::fs::metadata(src).is_ok_and(|meta| meta.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use rstest::*;
+
+    use super::*;
+
+    /// Relative path -> file contents (or the link target for links, dirs as "<dir>"), for comparing trees.
+    fn snapshot(root: &Path) -> Vec<(String, String)> {
+        fn walk(root: &Path, dir: &Path, out: &mut Vec<(String, String)>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let rel = path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                let meta = std::fs::symlink_metadata(&path).unwrap();
+                if meta.file_type().is_symlink() {
+                    let target = std::fs::read_link(&path).unwrap();
+                    out.push((rel, format!("-> {}", target.display())));
+                } else if meta.is_dir() {
+                    out.push((rel, "<dir>".into()));
+                    walk(root, &path, out);
+                } else {
+                    out.push((rel, std::fs::read_to_string(&path).unwrap()));
+                }
+            }
+        }
+        let mut out = vec![];
+        walk(root, root, &mut out);
+        out.sort();
+        out
+    }
+
+    fn build_tree(root: &Path) {
+        for (path, contents) in [
+            ("a.txt", "aaa"),
+            ("b.rs", "bb"),
+            ("nested/c.txt", "c"),
+            ("nested/deeper/d.rs", "dddd"),
+            ("nested/deeper/e.log", "eeeee"),
+            ("skip/f.txt", "ffffff"),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        std::fs::create_dir(root.join("empty")).unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("a.txt", root.join("link.txt")).unwrap();
+            std::os::unix::fs::symlink("nested/deeper", root.join("link_dir")).unwrap();
+        }
+    }
+
+    fn progress_recorder() -> (
+        Arc<Mutex<Vec<(u64, u64, String)>>>,
+        Arc<dyn Fn(&CopyProgress<'_>) + Send + Sync>,
+    ) {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let on_progress = {
+            let seen = seen.clone();
+            Arc::new(move |progress: &CopyProgress<'_>| {
+                seen.lock().push((
+                    progress.files_done,
+                    progress.bytes_done,
+                    progress
+                        .current_path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                ));
+            })
+        };
+        (seen, on_progress)
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_copy_dir() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let src = temp_dir.path().join("src");
+        build_tree(&src);
+
+        // Default recreates links, so the copy is identical:
+        let (seen, on_progress) = progress_recorder();
+        let stats = copy_dir_async(
+            &src,
+            temp_dir.path().join("copy"),
+            CopyDirOpts {
+                on_progress: Some(on_progress),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(snapshot(&src), snapshot(&temp_dir.path().join("copy")));
+        let file_count = if cfg!(unix) { 8 } else { 6 };
+        assert_eq!(
+            stats,
+            CopyDirStats {
+                files_copied: file_count,
+                files_skipped: 0,
+                bytes_copied: 21,
+            }
+        );
+        // Progress totals add up, one call per file:
+        let seen = seen.lock().clone();
+        assert_eq!(seen.len() as u64, file_count);
+        assert_eq!(
+            seen.last().map(|(files, bytes, _)| (*files, *bytes)),
+            Some((file_count, 21))
+        );
+        assert!(seen
+            .windows(2)
+            .all(|w| w[1].0 == w[0].0 + 1 && w[1].1 >= w[0].1));
+        assert_eq!(seen[0], (1, 3, "a.txt".into()));
+
+        // Filters, include by name and by relative path, excluding a dir skips its contents:
+        let stats = copy_dir_sync(
+            &src,
+            temp_dir.path().join("filtered"),
+            &CopyDirOpts {
+                include: vec!["*.txt".into(), "nested/deeper/*.rs".into()],
+                exclude: vec!["skip".into(), "link*".into()],
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            snapshot(&temp_dir.path().join("filtered")),
+            vec![
+                ("a.txt".into(), "aaa".into()),
+                ("nested".into(), "<dir>".into()),
+                ("nested/c.txt".into(), "c".into()),
+                ("nested/deeper".into(), "<dir>".into()),
+                ("nested/deeper/d.rs".into(), "dddd".into()),
+            ]
+        );
+        assert_eq!(stats.files_copied, 3);
+
+        // Invalid globs are rejected upfront:
+        assert!(copy_dir_sync(
+            &src,
+            temp_dir.path().join("bad_glob"),
+            &CopyDirOpts {
+                include: vec!["[".into()],
+                ..Default::default()
+            },
+        )
+        .is_err());
+        // As is copying into itself:
+        assert!(copy_dir_sync(&src, src.join("nested/inner"), &CopyDirOpts::default()).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_copy_dir_follow_symlinks() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let src = temp_dir.path().join("src");
+        build_tree(&src);
+        let dst = temp_dir.path().join("copy");
+
+        let stats = copy_dir_sync(
+            &src,
+            &dst,
+            &CopyDirOpts {
+                symlinks: CopySymlinks::Follow,
+                ..Default::default()
+            },
+        )?;
+        assert!(!std::fs::symlink_metadata(dst.join("link.txt"))
+            .change_context(AnyErr)?
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(dst.join("link.txt")).change_context(AnyErr)?,
+            "aaa"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.join("link_dir/e.log")).change_context(AnyErr)?,
+            "eeeee"
+        );
+        // 6 files plus the linked file and the 2 in the linked dir:
+        assert_eq!(stats.files_copied, 9);
+        assert_eq!(stats.bytes_copied, 21 + 3 + 9);
+
+        // Loops error rather than recursing forever:
+        std::os::unix::fs::symlink("..", src.join("nested/loop")).change_context(AnyErr)?;
+        assert!(copy_dir_sync(
+            &src,
+            temp_dir.path().join("loop_copy"),
+            &CopyDirOpts {
+                symlinks: CopySymlinks::Follow,
+                ..Default::default()
+            },
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_copy_dir_overwrite_and_best_effort() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let src = temp_dir.path().join("src");
+        build_tree(&src);
+        let dst = temp_dir.path().join("copy");
+        let file_count = if cfg!(unix) { 8 } else { 6 };
+        let write = |rel: &str, contents: &str| {
+            let path = dst.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write("a.txt", "old");
+        write("nested/c.txt", "old");
+
+        let opts = |overwrite, best_effort| CopyDirOpts {
+            overwrite,
+            best_effort,
+            ..Default::default()
+        };
+
+        // Error aborts at the first existing file:
+        assert!(copy_dir_sync(&src, &dst, &opts(CopyOverwrite::Error, false)).is_err());
+        assert!(!dst.join("b.rs").exists());
+
+        // Best effort carries on, reporting every failed path:
+        let report = copy_dir_sync(&src, &dst, &opts(CopyOverwrite::Error, true)).unwrap_err();
+        let rendered = format!("{:?}", report);
+        assert!(rendered.contains("2 path(s) failed"), "{}", rendered);
+        assert!(
+            rendered.contains("a.txt") && rendered.contains("c.txt"),
+            "{}",
+            rendered
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.join("b.rs")).change_context(AnyErr)?,
+            "bb"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.join("a.txt")).change_context(AnyErr)?,
+            "old"
+        );
+
+        // Skip leaves existing files:
+        write("a.txt", "old");
+        std::fs::remove_dir_all(dst.join("nested")).change_context(AnyErr)?;
+        write("nested/c.txt", "old");
+        let stats = copy_dir_sync(&src, &dst, &opts(CopyOverwrite::Skip, false))?;
+        // Everything but the 2 files in the removed dir:
+        assert_eq!(stats.files_skipped, file_count - 2);
+        assert_eq!(
+            std::fs::read_to_string(dst.join("a.txt")).change_context(AnyErr)?,
+            "old"
+        );
+
+        // Overwrite replaces them:
+        copy_dir_sync(&src, &dst, &opts(CopyOverwrite::Overwrite, false))?;
+        assert_eq!(snapshot(&src), snapshot(&dst));
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_move_dir() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let src = temp_dir.path().join("src");
+        build_tree(&src);
+        let expected = snapshot(&src);
+
+        // Plain rename:
+        let dst = temp_dir.path().join("moved");
+        move_dir_async(&src, &dst, CopyDirOpts::default()).await?;
+        assert!(!src.exists());
+        assert_eq!(snapshot(&dst), expected);
+
+        // Filtered, falls back to copying, only the matched files leave the source:
+        let filtered = temp_dir.path().join("filtered");
+        let stats = move_dir_sync(
+            &dst,
+            &filtered,
+            &CopyDirOpts {
+                include: vec!["*.txt".into()],
+                exclude: vec!["link*".into()],
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(stats.files_copied, 3);
+        assert_eq!(
+            snapshot(&filtered),
+            vec![
+                ("a.txt".into(), "aaa".into()),
+                ("nested".into(), "<dir>".into()),
+                ("nested/c.txt".into(), "c".into()),
+                ("skip".into(), "<dir>".into()),
+                ("skip/f.txt".into(), "ffffff".into()),
+            ]
+        );
+        assert!(!dst.join("a.txt").exists());
+        assert!(!dst.join("skip").exists());
+        assert!(!dst.join("empty").exists());
+        assert_eq!(
+            std::fs::read_to_string(dst.join("nested/deeper/d.rs")).change_context(AnyErr)?,
+            "dddd"
+        );
+
+        // Moving into an existing dir merges:
+        move_dir_sync(&dst, &filtered, &CopyDirOpts::default())?;
+        assert!(!dst.exists());
+        assert_eq!(
+            std::fs::read_to_string(filtered.join("b.rs")).change_context(AnyErr)?,
+            "bb"
+        );
+        Ok(())
+    }
+}
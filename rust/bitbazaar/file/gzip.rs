@@ -0,0 +1,127 @@
+use crate::prelude::*;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const DEFLATE_METHOD: u8 = 8;
+
+// Header flags:
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Compress the data into the gzip format.
+pub fn gzip_compress(data: &[u8]) -> Vec<u8> {
+    let deflated = miniz_oxide::deflate::compress_to_vec(data, 6);
+    let mut out = Vec::with_capacity(deflated.len() + 18);
+    // Magic, method, no flags, no mtime, no extra flags, unknown OS:
+    out.extend_from_slice(&GZIP_MAGIC);
+    out.extend_from_slice(&[DEFLATE_METHOD, 0, 0, 0, 0, 0, 0, 255]);
+    out.extend_from_slice(&deflated);
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Decompress gzip data, validating the trailing checksum.
+pub fn gzip_decompress(data: &[u8]) -> RResult<Vec<u8>, AnyErr> {
+    if data.len() < 18 || data[0..2] != GZIP_MAGIC || data[2] != DEFLATE_METHOD {
+        return Err(anyerr!("Not valid gzip data."));
+    }
+    let flags = data[3];
+    let mut index = 10;
+    // The optional fields are bounds checked as they're walked, their lengths come from the (possibly corrupt) data:
+    if flags & FEXTRA != 0 {
+        let extra_len = data
+            .get(index..index + 2)
+            .ok_or_else(|| anyerr!("Truncated gzip data."))?;
+        index += 2 + u16::from_le_bytes([extra_len[0], extra_len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let terminator = data
+                .get(index..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| anyerr!("Unterminated gzip header field."))?;
+            index += terminator + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        index += 2;
+    }
+    if index > data.len() - 8 {
+        return Err(anyerr!("Truncated gzip data."));
+    }
+
+    let trailer = &data[data.len() - 8..];
+    let out = miniz_oxide::inflate::decompress_to_vec(&data[index..data.len() - 8])
+        .map_err(|e| anyerr!("Failed to inflate gzip data: {:?}", e))?;
+    let exp_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let exp_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc32(&out) != exp_crc || out.len() as u32 != exp_len {
+        return Err(anyerr!("Gzip data failed checksum validation."));
+    }
+    Ok(out)
+}
+
+/// The standard (IEEE) crc32 used by gzip.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_gzip_roundtrip() -> RResult<(), AnyErr> {
+        // Known crc32 value:
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        for data in [&b""[..], b"hello world", &b"abc".repeat(10_000)] {
+            assert_eq!(gzip_decompress(&gzip_compress(data))?, data);
+        }
+
+        // Corruption should be detected:
+        let mut compressed = gzip_compress(b"hello world");
+        let len = compressed.len();
+        compressed[len - 5] ^= 0xff;
+        assert!(gzip_decompress(&compressed).is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    // Extra field longer than the data:
+    #[case(FEXTRA, &[0xff, 0xff])]
+    // Extra field length itself cut off:
+    #[case(FEXTRA, &[])]
+    // Name and comment without their terminators:
+    #[case(FNAME, &[b'a'; 20])]
+    #[case(FCOMMENT, &[b'a'; 20])]
+    // Name terminated inside the trailer:
+    #[case(FNAME | FHCRC, &[b'a', b'a', b'a', b'a', b'a', b'a', 0])]
+    fn test_gzip_truncated_header(#[case] flags: u8, #[case] fields: &[u8]) {
+        let mut data = GZIP_MAGIC.to_vec();
+        data.extend_from_slice(&[DEFLATE_METHOD, flags, 0, 0, 0, 0, 0, 255]);
+        data.extend_from_slice(fields);
+        // Fake trailer, so it's long enough to pass the initial length check:
+        data.resize(data.len().max(18), b'a');
+        assert!(gzip_decompress(&data).is_err());
+        // Every truncation errors rather than panicking:
+        for len in 0..data.len() {
+            assert!(gzip_decompress(&data[..len]).is_err());
+        }
+    }
+}
@@ -0,0 +1,663 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::Stream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::gzip::{gzip_compress, gzip_decompress};
+use crate::prelude::*;
+
+/// How often buffered lines are flushed to the file in the background.
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// When a [`JsonlLog`] should move on to a new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotateBy {
+    /// Rotate once the active file would exceed this many megabytes.
+    SizeMB(u64),
+    /// Rotate on the first append of each new UTC day.
+    Daily,
+}
+
+#[derive(Serialize)]
+struct EnvelopeOut<'a, T> {
+    /// Millis since epoch, used for filtering during replay.
+    ts: i64,
+    event: &'a T,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeIn<T> {
+    ts: i64,
+    event: T,
+}
+
+struct Inner {
+    file: tokio::io::BufWriter<tokio::fs::File>,
+    size: u64,
+    day: NaiveDate,
+    dirty: bool,
+}
+
+/// An append-only local event log, written as JSON lines, with automatic rotation.
+///
+/// - The active file is `{dir}/{prefix}.jsonl`, rotated files are `{dir}/{prefix}.{rotated_at_micros}.jsonl`, or `.jsonl.gz` when gzipped.
+/// - Each line is `{"ts": <millis since epoch>, "event": <event>}`.
+/// - Appends are buffered, flushed every second in the background and on [`JsonlLog::flush`].
+/// - Files are fsynced before being rotated.
+/// - Buffered appends can't be written from drop (the file is async), call [`JsonlLog::close`] before dropping the log or they're lost.
+///
+/// Use [`JsonlLog::replay`] to read the events back.
+pub struct JsonlLog {
+    dir: PathBuf,
+    prefix: String,
+    rotate_by: RotateBy,
+    gzip_rotated: bool,
+    inner: tokio::sync::Mutex<Inner>,
+}
+
+impl JsonlLog {
+    /// Open the log, continuing the active file if it already exists.
+    ///
+    /// If the active file ends in a partial line (e.g. from a crash mid-write), the partial line is dropped.
+    ///
+    /// Arguments:
+    /// - `dir`: The directory to hold the log files, will create if missing.
+    /// - `prefix`: The prefix for the filenames, e.g. "events" which will come out as "events.jsonl".
+    /// - `rotate_by`: When to rotate to a new file.
+    /// - `gzip_rotated`: Whether to gzip files once rotated.
+    pub async fn open(
+        dir: impl Into<PathBuf>,
+        prefix: impl Into<String>,
+        rotate_by: RotateBy,
+        gzip_rotated: bool,
+    ) -> RResult<Arc<Self>, AnyErr> {
+        let dir = dir.into();
+        let prefix = prefix.into();
+        if dir.is_file() {
+            return Err(anyerr!(
+                "Log directory is an existing file: {}",
+                dir.to_string_lossy()
+            ));
+        }
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .change_context(AnyErr)?;
+
+        let active_path = active_path(&dir, &prefix);
+        let (size, day) = if active_path.exists() {
+            let truncate_path = active_path.clone();
+            let size = tokio::task::spawn_blocking(move || drop_partial_line(&truncate_path))
+                .await
+                .change_context(AnyErr)??;
+            let modified: DateTime<Utc> = tokio::fs::metadata(&active_path)
+                .await
+                .change_context(AnyErr)?
+                .modified()
+                .change_context(AnyErr)?
+                .into();
+            (size, modified.date_naive())
+        } else {
+            (0, Utc::now().date_naive())
+        };
+
+        let log = Arc::new(Self {
+            inner: tokio::sync::Mutex::new(Inner {
+                file: tokio::io::BufWriter::new(open_append(&active_path).await?),
+                size,
+                day,
+                dirty: false,
+            }),
+            dir,
+            prefix,
+            rotate_by,
+            gzip_rotated,
+        });
+
+        // Background flushing, stops once the log is dropped:
+        let weak: Weak<Self> = Arc::downgrade(&log);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(FLUSH_EVERY).await;
+                match weak.upgrade() {
+                    Some(log) => {
+                        if let Err(e) = log.flush().await {
+                            tracing::error!("Failed to flush JsonlLog: {:?}", e);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        });
+
+        Ok(log)
+    }
+
+    /// Append an event to the log, rotating first if needed.
+    ///
+    /// Concurrent appends are serialized, each event is always written as a whole line.
+    pub async fn append<T: Serialize>(&self, event: &T) -> RResult<(), AnyErr> {
+        // Serialize outside the lock:
+        let mut line = serde_json::to_vec(&EnvelopeOut {
+            ts: Utc::now().timestamp_millis(),
+            event,
+        })
+        .change_context(AnyErr)?;
+        line.push(b'\n');
+
+        let mut inner = self.inner.lock().await;
+        let needs_rotation = match self.rotate_by {
+            RotateBy::SizeMB(mb) => {
+                inner.size > 0 && inner.size + line.len() as u64 > mb * 1024 * 1024
+            }
+            RotateBy::Daily => inner.day != Utc::now().date_naive(),
+        };
+        if needs_rotation {
+            self.rotate_inner(&mut inner).await?;
+        }
+
+        inner.file.write_all(&line).await.change_context(AnyErr)?;
+        inner.size += line.len() as u64;
+        inner.dirty = true;
+        Ok(())
+    }
+
+    /// Flush any buffered lines to the active file.
+    pub async fn flush(&self) -> RResult<(), AnyErr> {
+        let mut inner = self.inner.lock().await;
+        if inner.dirty {
+            inner.file.flush().await.change_context(AnyErr)?;
+            inner.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Flush any buffered lines and fsync the active file, call before dropping the log so nothing is lost.
+    ///
+    /// Appending afterwards is still allowed, but needs closing again.
+    pub async fn close(&self) -> RResult<(), AnyErr> {
+        let mut inner = self.inner.lock().await;
+        inner.file.flush().await.change_context(AnyErr)?;
+        inner.dirty = false;
+        inner.file.get_ref().sync_all().await.change_context(AnyErr)
+    }
+
+    /// Force a rotation now, regardless of [`RotateBy`]. No-op if the active file is empty.
+    pub async fn rotate(&self) -> RResult<(), AnyErr> {
+        let mut inner = self.inner.lock().await;
+        if inner.size > 0 {
+            self.rotate_inner(&mut inner).await?;
+        }
+        Ok(())
+    }
+
+    /// Replay all events from the log in the order they were appended, walking rotated files (including gzipped) then the active file.
+    ///
+    /// A truncated final line in a file (common after crashes) is skipped with a warning, any other undecodable line is returned as an error.
+    ///
+    /// Arguments:
+    /// - `dir`: The directory holding the log files.
+    /// - `prefix`: The prefix the log was opened with.
+    /// - `since`: If set, only events appended at or after this time are returned.
+    pub fn replay<T: DeserializeOwned>(
+        dir: impl Into<PathBuf>,
+        prefix: impl Into<String>,
+        since: Option<DateTime<Utc>>,
+    ) -> impl Stream<Item = RResult<T, AnyErr>> {
+        struct State<T> {
+            dir: PathBuf,
+            prefix: String,
+            since_ms: Option<i64>,
+            files: Option<VecDeque<PathBuf>>,
+            pending: VecDeque<RResult<T, AnyErr>>,
+        }
+
+        let state = State {
+            dir: dir.into(),
+            prefix: prefix.into(),
+            since_ms: since.map(|since| since.timestamp_millis()),
+            files: None,
+            pending: VecDeque::new(),
+        };
+
+        futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(item) = state.pending.pop_front() {
+                    return Some((item, state));
+                }
+
+                // Lazily list the files on first poll:
+                if state.files.is_none() {
+                    match list_files(&state.dir, &state.prefix).await {
+                        Ok(files) => state.files = Some(files),
+                        Err(e) => {
+                            state.files = Some(VecDeque::new());
+                            return Some((Err(e), state));
+                        }
+                    }
+                }
+
+                let path = state.files.as_mut()?.pop_front()?;
+                match read_file(&path).await {
+                    Ok(contents) => {
+                        state.pending = parse_lines(&path, &contents, state.since_ms);
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    async fn rotate_inner(&self, inner: &mut Inner) -> RResult<(), AnyErr> {
+        // Make sure everything is durable before moving on:
+        inner.file.flush().await.change_context(AnyErr)?;
+        inner
+            .file
+            .get_ref()
+            .sync_all()
+            .await
+            .change_context(AnyErr)?;
+
+        let active_path = active_path(&self.dir, &self.prefix);
+        let mut micros = Utc::now().timestamp_micros();
+        let rotated_path = loop {
+            let path = self
+                .dir
+                .join(format!("{}.{:020}.jsonl", self.prefix, micros));
+            if !path.exists() && !gz_path(&path).exists() {
+                break path;
+            }
+            micros += 1;
+        };
+        tokio::fs::rename(&active_path, &rotated_path)
+            .await
+            .change_context(AnyErr)?;
+
+        inner.file = tokio::io::BufWriter::new(open_append(&active_path).await?);
+        inner.size = 0;
+        inner.day = Utc::now().date_naive();
+        inner.dirty = false;
+
+        if self.gzip_rotated {
+            tokio::task::spawn_blocking(move || gzip_file(&rotated_path))
+                .await
+                .change_context(AnyErr)??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for JsonlLog {
+    fn drop(&mut self) {
+        if self.inner.get_mut().dirty {
+            tracing::warn!(
+                "JsonlLog '{}' dropped with unflushed appends, which are lost. Call JsonlLog::close() before dropping.",
+                self.prefix
+            );
+        }
+    }
+}
+
+fn active_path(dir: &Path, prefix: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", prefix))
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".gz");
+    PathBuf::from(path)
+}
+
+async fn open_append(path: &Path) -> RResult<tokio::fs::File, AnyErr> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .change_context(AnyErr)
+}
+
+/// Replace a rotated file with a gzipped version.
+fn gzip_file(path: &Path) -> RResult<(), AnyErr> {
+    let contents = std::fs::read(path).change_context(AnyErr)?;
+    let gz_path = gz_path(path);
+    let mut file = std::fs::File::create(&gz_path).change_context(AnyErr)?;
+    std::io::Write::write_all(&mut file, &gzip_compress(&contents)).change_context(AnyErr)?;
+    file.sync_all().change_context(AnyErr)?;
+    std::fs::remove_file(path).change_context(AnyErr)?;
+    Ok(())
+}
+
+/// Truncate the file to after its last newline, returning the new size.
+fn drop_partial_line(path: &Path) -> RResult<u64, AnyErr> {
+    const CHUNK: u64 = 4096;
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .change_context(AnyErr)?;
+    let len = file.metadata().change_context(AnyErr)?.len();
+
+    // Scan backwards for the last newline:
+    let mut end = len;
+    let mut keep = 0;
+    let mut buf = vec![0; CHUNK as usize];
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start)).change_context(AnyErr)?;
+        file.read_exact(chunk).change_context(AnyErr)?;
+        if let Some(pos) = chunk.iter().rposition(|b| *b == b'\n') {
+            keep = start + pos as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+
+    if keep != len {
+        tracing::warn!(
+            "JsonlLog file '{}' ended with a partial line ({} bytes), dropping it.",
+            path.display(),
+            len - keep
+        );
+        file.set_len(keep).change_context(AnyErr)?;
+        file.sync_all().change_context(AnyErr)?;
+    }
+    Ok(keep)
+}
+
+/// All files for the log, rotated in rotation order, then the active file.
+async fn list_files(dir: &Path, prefix: &str) -> RResult<VecDeque<PathBuf>, AnyErr> {
+    let mut rotated = vec![];
+    let mut entries = tokio::fs::read_dir(dir).await.change_context(AnyErr)?;
+    while let Some(entry) = entries.next_entry().await.change_context(AnyErr)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(rest) = name.strip_prefix(prefix).and_then(|r| r.strip_prefix('.')) else {
+            continue;
+        };
+        let rest = rest.strip_suffix(".gz").unwrap_or(rest);
+        if let Some(micros) = rest.strip_suffix(".jsonl") {
+            if let Ok(micros) = micros.parse::<i64>() {
+                rotated.push((micros, entry.path()));
+            }
+        }
+    }
+    rotated.sort();
+
+    let mut files = rotated
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect::<VecDeque<_>>();
+    let active_path = active_path(dir, prefix);
+    if active_path.exists() {
+        files.push_back(active_path);
+    }
+    Ok(files)
+}
+
+async fn read_file(path: &Path) -> RResult<Vec<u8>, AnyErr> {
+    let contents = tokio::fs::read(path).await.change_context(AnyErr)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        gzip_decompress(&contents).attach_printable_lazy(|| path.display().to_string())
+    } else {
+        Ok(contents)
+    }
+}
+
+fn parse_lines<T: DeserializeOwned>(
+    path: &Path,
+    contents: &[u8],
+    since_ms: Option<i64>,
+) -> VecDeque<RResult<T, AnyErr>> {
+    let mut out = VecDeque::new();
+    let ends_with_newline = contents.last() == Some(&b'\n');
+    let lines = contents.split(|b| *b == b'\n').collect::<Vec<_>>();
+    let last_index = lines.len() - 1;
+    for (index, line) in lines.into_iter().enumerate() {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        match serde_json::from_slice::<EnvelopeIn<T>>(line) {
+            Ok(envelope) => {
+                if since_ms.is_none_or(|since_ms| envelope.ts >= since_ms) {
+                    out.push_back(Ok(envelope.event));
+                }
+            }
+            // A final line without a trailing newline was never fully written:
+            Err(_) if index == last_index && !ends_with_newline => {
+                tracing::warn!(
+                    "Skipping truncated final line in JsonlLog file '{}'.",
+                    path.display()
+                );
+            }
+            Err(e) => out.push_back(Err(anyerr!(
+                "Failed to decode line {} of JsonlLog file '{}': {}",
+                index + 1,
+                path.display(),
+                e
+            ))),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use rstest::*;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        id: usize,
+        data: String,
+    }
+
+    fn ev(id: usize) -> Event {
+        Event {
+            id,
+            data: format!("event_{}", id),
+        }
+    }
+
+    async fn replay_all(dir: &Path, since: Option<DateTime<Utc>>) -> RResult<Vec<Event>, AnyErr> {
+        JsonlLog::replay::<Event>(dir, "events", since)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    #[rstest]
+    #[case::plain(false)]
+    #[case::gzipped(true)]
+    #[tokio::test]
+    async fn test_jsonl_log_rotation_and_replay(#[case] gzip: bool) -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, gzip).await?;
+
+        for id in 0..10 {
+            log.append(&ev(id)).await?;
+        }
+        log.rotate().await?;
+        for id in 10..20 {
+            log.append(&ev(id)).await?;
+        }
+        log.rotate().await?;
+        for id in 20..25 {
+            log.append(&ev(id)).await?;
+        }
+        log.flush().await?;
+
+        // 2 rotated files plus the active:
+        let rotated_ext = if gzip { ".jsonl.gz" } else { ".jsonl" };
+        let names = std::fs::read_dir(temp_dir.path())
+            .change_context(AnyErr)?
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert_eq!(
+            names
+                .iter()
+                .filter(|name| *name != "events.jsonl" && name.ends_with(rotated_ext))
+                .count(),
+            2,
+            "{:?}",
+            names
+        );
+
+        // Every event exactly once, in order:
+        assert_eq!(
+            replay_all(temp_dir.path(), None).await?,
+            (0..25).map(ev).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_jsonl_log_size_rotation_concurrent() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::SizeMB(1), false).await?;
+
+        // ~3MB of events from concurrent tasks:
+        let big = "x".repeat(1000);
+        let mut handles = vec![];
+        for task in 0..10 {
+            let log = log.clone();
+            let big = big.clone();
+            handles.push(tokio::spawn(async move {
+                for id in 0..300 {
+                    log.append(&Event {
+                        id: task * 1000 + id,
+                        data: big.clone(),
+                    })
+                    .await
+                    .unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.change_context(AnyErr)?;
+        }
+        log.flush().await?;
+
+        let file_count = std::fs::read_dir(temp_dir.path())
+            .change_context(AnyErr)?
+            .count();
+        assert!(file_count >= 3, "{}", file_count);
+
+        // All whole lines, each event once, and in order per task:
+        let events = replay_all(temp_dir.path(), None).await?;
+        assert_eq!(events.len(), 3000);
+        for task in 0..10 {
+            let ids = events
+                .iter()
+                .map(|e| e.id)
+                .filter(|id| id / 1000 == task)
+                .collect::<Vec<_>>();
+            assert_eq!(ids, (0..300).map(|id| task * 1000 + id).collect::<Vec<_>>());
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_jsonl_log_truncated_last_line() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, false).await?;
+        for id in 0..3 {
+            log.append(&ev(id)).await?;
+        }
+        log.flush().await?;
+        drop(log);
+
+        // Simulate a crash mid-write:
+        let active = active_path(temp_dir.path(), "events");
+        let mut contents = std::fs::read(&active).change_context(AnyErr)?;
+        contents.extend_from_slice(br#"{"ts": 1, "event": {"id": 3, "da"#);
+        std::fs::write(&active, contents).change_context(AnyErr)?;
+
+        // Replay should skip it rather than error:
+        assert_eq!(
+            replay_all(temp_dir.path(), None).await?,
+            (0..3).map(ev).collect::<Vec<_>>()
+        );
+
+        // Reopening should drop the partial line, so new appends aren't corrupted:
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, false).await?;
+        log.append(&ev(3)).await?;
+        log.flush().await?;
+        assert_eq!(
+            replay_all(temp_dir.path(), None).await?,
+            (0..4).map(ev).collect::<Vec<_>>()
+        );
+
+        // A corrupt line that isn't the last should error:
+        let mut contents = std::fs::read(&active).change_context(AnyErr)?;
+        contents.splice(0..0, b"not json\n".iter().copied());
+        std::fs::write(&active, contents).change_context(AnyErr)?;
+        assert!(replay_all(temp_dir.path(), None).await.is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_jsonl_log_close() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+
+        // Dropped before the background flush, the buffered appends never make it to the file:
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, false).await?;
+        for id in 0..3 {
+            log.append(&ev(id)).await?;
+        }
+        drop(log);
+        assert_eq!(replay_all(temp_dir.path(), None).await?, vec![]);
+
+        // Closing first keeps them:
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, false).await?;
+        for id in 0..3 {
+            log.append(&ev(id)).await?;
+        }
+        log.close().await?;
+        drop(log);
+        assert_eq!(
+            replay_all(temp_dir.path(), None).await?,
+            (0..3).map(ev).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_jsonl_log_since() -> RResult<(), AnyErr> {
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let log = JsonlLog::open(temp_dir.path(), "events", RotateBy::Daily, true).await?;
+        for id in 0..3 {
+            log.append(&ev(id)).await?;
+        }
+        log.rotate().await?;
+        log.append(&ev(3)).await?;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let since = Utc::now();
+        for id in 4..6 {
+            log.append(&ev(id)).await?;
+        }
+        log.flush().await?;
+
+        assert_eq!(
+            replay_all(temp_dir.path(), Some(since)).await?,
+            (4..6).map(ev).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+mod atomic;
+mod copy_dir;
+mod gzip;
+mod jsonl_log;
+
+pub use atomic::{atomic_write_async, atomic_write_sync, atomic_write_with};
+pub use copy_dir::{
+    copy_dir_async, copy_dir_sync, move_dir_async, move_dir_sync, CopyDirOpts, CopyDirStats,
+    CopyOverwrite, CopyProgress, CopySymlinks,
+};
+pub use jsonl_log::{JsonlLog, RotateBy};
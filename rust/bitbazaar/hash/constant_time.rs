@@ -0,0 +1,35 @@
+/// Compare two byte slices in constant time, to avoid leaking where they differ through timing,
+/// e.g. when checking signatures or tokens.
+///
+/// Differing lengths return false straight away, only the length is leaked, never the contents.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    // Stop the compiler reasoning about diff and short circuiting the loop:
+    std::hint::black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case::empty(b"", b"", true)]
+    #[case::equal(b"abc", b"abc", true)]
+    #[case::first_differs(b"abc", b"xbc", false)]
+    #[case::last_differs(b"abc", b"abx", false)]
+    #[case::shorter(b"ab", b"abc", false)]
+    #[case::longer(b"abcd", b"abc", false)]
+    #[case::empty_vs_non_empty(b"", b"a", false)]
+    fn test_constant_time_eq(#[case] a: &[u8], #[case] b: &[u8], #[case] expected: bool) {
+        assert_eq!(constant_time_eq(a, b), expected);
+        assert_eq!(constant_time_eq(b, a), expected);
+    }
+}
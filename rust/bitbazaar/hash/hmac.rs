@@ -0,0 +1,183 @@
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use super::constant_time_eq;
+
+const SHA256_BLOCK_SIZE: usize = 64;
+
+/// HMAC-SHA256 ([RFC 2104](https://www.rfc-editor.org/rfc/rfc2104)) of a message, e.g. for signing webhooks.
+pub fn hmac_sha256(key: impl AsRef<[u8]>, message: impl AsRef<[u8]>) -> [u8; 32] {
+    let key = key.as_ref();
+    // Keys longer than the block size are hashed first, shorter ones zero padded:
+    let mut block = [0u8; SHA256_BLOCK_SIZE];
+    if key.len() > SHA256_BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message.as_ref());
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// [`hmac_sha256`] as lowercase hex.
+pub fn hmac_sha256_hex(key: impl AsRef<[u8]>, message: impl AsRef<[u8]>) -> String {
+    hmac_sha256(key, message)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// [`hmac_sha256`] as standard padded base64.
+pub fn hmac_sha256_base64(key: impl AsRef<[u8]>, message: impl AsRef<[u8]>) -> String {
+    base64::engine::general_purpose::STANDARD.encode(hmac_sha256(key, message))
+}
+
+/// Verify a hex HMAC-SHA256 signature, e.g. from a webhook header, comparing in constant time.
+///
+/// Lenient with the format: either case hex, surrounding whitespace and an optional `sha256=` prefix
+/// (as sent by common webhook providers) are all accepted. Anything undecodable is false.
+pub fn verify_hmac_sha256(
+    key: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+    provided_sig_hex: &str,
+) -> bool {
+    let sig = provided_sig_hex.trim();
+    let sig = match sig.get(..7) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("sha256=") => &sig[7..],
+        _ => sig,
+    };
+    match decode_hex(sig) {
+        Some(provided) => constant_time_eq(&hmac_sha256(key, message), &provided),
+        None => false,
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        decode_hex(s).unwrap()
+    }
+
+    // RFC 4231 test vectors (case 5's truncated output is excluded):
+    #[rstest]
+    #[case::case_1(
+        hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+        b"Hi There".to_vec(),
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+    )]
+    #[case::case_2(
+        b"Jefe".to_vec(),
+        b"what do ya want for nothing?".to_vec(),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    )]
+    #[case::case_3(
+        vec![0xaa; 20],
+        vec![0xdd; 50],
+        "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"
+    )]
+    #[case::case_4(
+        hex("0102030405060708090a0b0c0d0e0f10111213141516171819"),
+        vec![0xcd; 50],
+        "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"
+    )]
+    #[case::case_6_long_key(
+        vec![0xaa; 131],
+        b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    )]
+    #[case::case_7_long_key_and_data(
+        vec![0xaa; 131],
+        b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.".to_vec(),
+        "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2"
+    )]
+    fn test_hmac_sha256_rfc4231(
+        #[case] key: Vec<u8>,
+        #[case] message: Vec<u8>,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(hmac_sha256(&key, &message).to_vec(), hex(expected));
+        assert_eq!(hmac_sha256_hex(&key, &message), expected);
+        assert!(verify_hmac_sha256(&key, &message, expected));
+    }
+
+    #[rstest]
+    fn test_hmac_sha256_base64() {
+        assert_eq!(
+            hmac_sha256_base64("Jefe", "what do ya want for nothing?"),
+            "W9zBRr9gdU5qBCQmCJV1x1oAPwidJzmDnexYuWTsOEM="
+        );
+    }
+
+    #[rstest]
+    #[case::lowercase("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")]
+    #[case::uppercase("5BDCC146BF60754E6A042426089575C75A003F089D2739839DEC58B964EC3843")]
+    #[case::prefixed("sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")]
+    #[case::prefix_uppercase(
+        "SHA256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    )]
+    #[case::whitespace(
+        " sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843\n"
+    )]
+    fn test_verify_hmac_sha256_lenient(#[case] sig: &str) {
+        assert!(verify_hmac_sha256(
+            "Jefe",
+            "what do ya want for nothing?",
+            sig
+        ));
+    }
+
+    #[rstest]
+    #[case::flipped_bit("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3842")]
+    #[case::truncated("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec38")]
+    #[case::odd_length("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec384")]
+    #[case::not_hex("zbdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")]
+    #[case::other_prefix("sha1=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")]
+    #[case::empty("")]
+    fn test_verify_hmac_sha256_rejects(#[case] sig: &str) {
+        assert!(!verify_hmac_sha256(
+            "Jefe",
+            "what do ya want for nothing?",
+            sig
+        ));
+    }
+
+    #[rstest]
+    fn test_verify_hmac_sha256_every_flipped_bit() {
+        let sig = hmac_sha256("key", "message");
+        for byte in 0..sig.len() {
+            for bit in 0..8 {
+                let mut flipped = sig;
+                flipped[byte] ^= 1 << bit;
+                let flipped_hex = flipped
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                assert!(!verify_hmac_sha256("key", "message", &flipped_hex));
+            }
+        }
+    }
+}
@@ -1,8 +1,16 @@
+mod constant_time;
 mod fnv1a;
+mod hmac;
+mod sha_stream;
 
+pub use constant_time::constant_time_eq;
 pub use fnv1a::fnv1a;
+pub use hmac::{hmac_sha256, hmac_sha256_base64, hmac_sha256_hex, verify_hmac_sha256};
+pub use sha_stream::Sha256Hasher;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sha_stream::{sha256_file_async, sha256_file_sync};
 
-/// SHA256 hash function.
+/// SHA256 hash function, see [`Sha256Hasher`] for input too large to hold in memory.
 ///
 /// Arguments
 /// - `input`: The input data to hash.
@@ -0,0 +1,139 @@
+use sha2::{Digest, Sha256};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::errors::prelude::*;
+
+/// The size of the chunks files are read in by [`sha256_file_sync`] and [`sha256_file_async`].
+#[cfg(not(target_arch = "wasm32"))]
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Incremental SHA256, for input too large to hold in memory at once, e.g. large files or network streams.
+///
+/// Gives the same output as [`super::sha256`] without a salt for the same input, however it was split.
+/// Implements [`std::io::Write`], so can be used as the destination of [`std::io::copy`].
+#[derive(Debug, Clone, Default)]
+pub struct Sha256Hasher {
+    inner: Sha256,
+}
+
+impl Sha256Hasher {
+    /// Create a new hasher with no input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next piece of input.
+    pub fn update(&mut self, input: impl AsRef<[u8]>) {
+        self.inner.update(input.as_ref());
+    }
+
+    /// The hash of all the input, as lowercase hex.
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.inner.finalize())
+    }
+}
+
+impl std::io::Write for Sha256Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// SHA256 of a file's contents as lowercase hex, read in chunks so the file is never fully loaded into memory.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn sha256_file_sync(path: impl AsRef<std::path::Path>) -> RResult<String, AnyErr> {
+    use std::io::Read;
+
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)
+        .change_context(AnyErr)
+        .attach_printable_lazy(|| format!("Couldn't open '{}'.", path.display()))?;
+    let mut hasher = Sha256Hasher::new();
+    let mut buf = vec![0; FILE_CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf).change_context(AnyErr)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Async [`sha256_file_sync`].
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sha256_file_async(path: impl AsRef<std::path::Path>) -> RResult<String, AnyErr> {
+    use tokio::io::AsyncReadExt;
+
+    let path = path.as_ref();
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .change_context(AnyErr)
+        .attach_printable_lazy(|| format!("Couldn't open '{}'.", path.display()))?;
+    let mut hasher = Sha256Hasher::new();
+    let mut buf = vec![0; FILE_CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf).await.change_context(AnyErr)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::hash::sha256;
+
+    /// Varied bytes, so chunks hashed out of order would change the hash.
+    fn data(size: usize) -> Vec<u8> {
+        (0..size)
+            .map(|i| (i % 251) as u8 ^ (i / 251) as u8)
+            .collect()
+    }
+
+    #[rstest]
+    fn test_sha256_hasher_matches_one_shot() {
+        let data = data(100_000);
+
+        let mut hasher = Sha256Hasher::new();
+        for chunk in data.chunks(7_919) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), sha256(&data, None));
+        assert_eq!(Sha256Hasher::new().finalize(), sha256(b"", None));
+
+        // As the destination of io::copy:
+        let mut hasher = Sha256Hasher::new();
+        std::io::copy(&mut std::io::Cursor::new(&data), &mut hasher).unwrap();
+        assert_eq!(hasher.finalize(), sha256(&data, None));
+    }
+
+    #[rstest]
+    #[case::multi_chunk(FILE_CHUNK_SIZE * 3 + 123)]
+    #[case::exact_chunk(FILE_CHUNK_SIZE)]
+    #[case::empty(0)]
+    #[tokio::test]
+    async fn test_sha256_file(#[case] size: usize) -> RResult<(), AnyErr> {
+        let data = data(size);
+        let temp_dir = tempfile::tempdir().change_context(AnyErr)?;
+        let path = temp_dir.path().join("data.bin");
+        std::fs::write(&path, &data).change_context(AnyErr)?;
+
+        let expected = sha256(&data, None);
+        assert_eq!(sha256_file_sync(&path)?, expected);
+        assert_eq!(sha256_file_async(&path).await?, expected);
+
+        assert!(sha256_file_sync(temp_dir.path().join("missing.bin")).is_err());
+        Ok(())
+    }
+}
@@ -19,6 +19,9 @@ pub mod chrono;
 pub mod cookies;
 /// Error handling utilities.
 pub mod errors;
+#[cfg(feature = "file")]
+/// File utilities, e.g. rotating local event logs.
+pub mod file;
 #[cfg(feature = "hash")]
 /// Hashing utilities.
 pub mod hash;
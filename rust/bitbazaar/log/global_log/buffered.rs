@@ -0,0 +1,356 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    io::Write,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use parking_lot::Mutex;
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The default for [`super::GlobalLogBuilder::buffered_scope_limit`].
+pub const DEFAULT_BUFFERED_SCOPE_LIMIT: usize = 1000;
+
+thread_local! {
+    // The scope of the future currently being polled on this thread, swapped in and out by ScopedFut.
+    static CURRENT_SCOPE: RefCell<Option<Arc<ScopeState>>> = const { RefCell::new(None) };
+}
+
+/// A handle to a [`buffered_scope`], passed to its closure for manual decisions.
+#[derive(Clone)]
+pub struct BufferedScope {
+    state: Arc<ScopeState>,
+}
+
+impl BufferedScope {
+    /// Write everything buffered so far (including that of any parent scopes, to keep the order), buffering continues afterwards.
+    pub fn flush_now(&self) {
+        self.state.flush_chain(false);
+    }
+
+    /// Drop everything this scope has buffered so far, buffering continues afterwards.
+    pub fn discard(&self) {
+        self.state.buffers.lock().sinks.clear();
+    }
+}
+
+/// Buffer the DEBUG/INFO logs produced inside the future in memory,
+/// only writing them if the future returns an error (or a WARN/ERROR is logged), otherwise they're dropped.
+/// WARN/ERROR logs always pass straight through, flushing anything buffered before them first.
+///
+/// - Buffered logs are formatted at the time they're logged, so keep their original timestamps and metadata.
+/// - The buffer is per scope and only applies to code running inside the future's task, spawned tasks aren't included.
+/// - Nested scopes merge into their parent when they succeed, a failure (or WARN/ERROR) flushes the whole chain.
+/// - Each output buffers at most [`super::GlobalLogBuilder::buffered_scope_limit`] logs per scope, the oldest are dropped first, replaced with a marker line.
+/// - Only applies to stdout, file and custom outputs, otlp outputs receive everything as normal.
+///
+/// ```
+/// use bitbazaar::log::buffered_scope;
+///
+/// async fn handler() -> Result<(), String> {
+///     buffered_scope(|scope| async move {
+///         tracing::info!("Only written if this request fails.");
+///         Ok(())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn buffered_scope<F, Fut, T, E>(f: F) -> Result<T, E>
+where
+    F: FnOnce(BufferedScope) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let parent = CURRENT_SCOPE.with(|current| current.borrow().clone());
+    let scope = BufferedScope {
+        state: Arc::new(ScopeState {
+            buffers: Mutex::new(ScopeBuffers {
+                sinks: vec![],
+                // Nothing to buffer for if the parent has already failed:
+                passthrough: parent
+                    .as_ref()
+                    .is_some_and(|parent| parent.is_passthrough()),
+            }),
+            parent,
+        }),
+    };
+    let mut scoped = ScopedFut {
+        state: scope.state.clone(),
+        fut: Box::pin(f(scope)),
+        finished: false,
+    };
+    let result = (&mut scoped).await;
+    scoped.finished = true;
+    scoped.state.finish(result.is_err());
+    result
+}
+
+struct ScopedFut<Fut> {
+    state: Arc<ScopeState>,
+    fut: Pin<Box<Fut>>,
+    finished: bool,
+}
+
+/// Puts back the scope that was active before a [`ScopedFut`] poll, even if the poll panicked.
+struct RestoreScope(Option<Arc<ScopeState>>);
+
+impl Drop for RestoreScope {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        CURRENT_SCOPE.with(|current| *current.borrow_mut() = prev);
+    }
+}
+
+impl<Fut: Future> Future for ScopedFut<Fut> {
+    type Output = Fut::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _restore =
+            RestoreScope(CURRENT_SCOPE.with(|current| current.replace(Some(self.state.clone()))));
+        // Panics are treated as failures, caught here as the task dropping the future afterwards isn't itself panicking:
+        match std::panic::catch_unwind(AssertUnwindSafe(|| self.fut.as_mut().poll(cx))) {
+            Ok(result) => result,
+            Err(panic) => {
+                self.finished = true;
+                self.state.finish(true);
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+}
+
+impl<Fut> Drop for ScopedFut<Fut> {
+    fn drop(&mut self) {
+        // Cancelled futures (e.g. a dropped request) are quiet:
+        if !self.finished {
+            self.state.finish(false);
+        }
+    }
+}
+
+struct ScopeState {
+    parent: Option<Arc<ScopeState>>,
+    buffers: Mutex<ScopeBuffers>,
+}
+
+#[derive(Default)]
+struct ScopeBuffers {
+    sinks: Vec<SinkBuffer>,
+    /// Set once the scope has failed, everything is written directly from then on.
+    passthrough: bool,
+}
+
+struct SinkBuffer {
+    sink: Arc<dyn BufferSink>,
+    limit: usize,
+    logs: VecDeque<Vec<u8>>,
+    dropped: usize,
+}
+
+impl SinkBuffer {
+    fn push(&mut self, log: Vec<u8>) {
+        if self.logs.len() >= self.limit {
+            self.logs.pop_front();
+            self.dropped += 1;
+        }
+        if self.limit > 0 {
+            self.logs.push_back(log);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    fn write(self) {
+        if self.dropped > 0 {
+            self.sink.write_buffered(
+                format!(
+                    "...{} earlier buffered log{} dropped, over the scope limit of {}.\n",
+                    self.dropped,
+                    if self.dropped == 1 { "" } else { "s" },
+                    self.limit
+                )
+                .as_bytes(),
+            );
+        }
+        for log in self.logs {
+            self.sink.write_buffered(&log);
+        }
+    }
+}
+
+fn same_sink(a: &Arc<dyn BufferSink>, b: &Arc<dyn BufferSink>) -> bool {
+    std::ptr::eq(Arc::as_ptr(a) as *const (), Arc::as_ptr(b) as *const ())
+}
+
+impl ScopeState {
+    fn push(&self, sink: &Arc<dyn BufferSink>, limit: usize, log: Vec<u8>) {
+        let mut buffers = self.buffers.lock();
+        if let Some(buffer) = buffers.sinks.iter_mut().find(|b| same_sink(&b.sink, sink)) {
+            buffer.push(log);
+        } else {
+            let mut buffer = SinkBuffer {
+                sink: sink.clone(),
+                limit,
+                logs: VecDeque::new(),
+                dropped: 0,
+            };
+            buffer.push(log);
+            buffers.sinks.push(buffer);
+        }
+    }
+
+    fn is_passthrough(&self) -> bool {
+        self.buffers.lock().passthrough
+    }
+
+    /// Write out the buffers of this scope and all its parents, oldest (outermost) first.
+    fn flush_chain(&self, passthrough: bool) {
+        let mut chain = vec![self];
+        while let Some(parent) = chain.last().and_then(|scope| scope.parent.as_deref()) {
+            chain.push(parent);
+        }
+        for scope in chain.into_iter().rev() {
+            // Taken out first, the lock mustn't be held whilst writing:
+            let sinks = {
+                let mut buffers = scope.buffers.lock();
+                if passthrough {
+                    buffers.passthrough = true;
+                }
+                std::mem::take(&mut buffers.sinks)
+            };
+            for buffer in sinks {
+                buffer.write();
+            }
+        }
+    }
+
+    fn finish(&self, failed: bool) {
+        if failed || self.is_passthrough() {
+            self.flush_chain(true);
+        } else if let Some(parent) = &self.parent {
+            let sinks = std::mem::take(&mut self.buffers.lock().sinks);
+            if parent.is_passthrough() {
+                for buffer in sinks {
+                    buffer.write();
+                }
+                return;
+            }
+            for buffer in sinks {
+                let SinkBuffer {
+                    sink,
+                    limit,
+                    logs,
+                    dropped,
+                } = buffer;
+                for log in logs {
+                    parent.push(&sink, limit, log);
+                }
+                if dropped > 0 {
+                    let mut buffers = parent.buffers.lock();
+                    if let Some(parent_buffer) =
+                        buffers.sinks.iter_mut().find(|b| same_sink(&b.sink, &sink))
+                    {
+                        parent_buffer.dropped += dropped;
+                    }
+                }
+            }
+        } else {
+            self.buffers.lock().sinks.clear();
+        }
+    }
+}
+
+/// Somewhere buffered logs can be written to later.
+trait BufferSink: Send + Sync {
+    fn write_buffered(&self, log: &[u8]);
+}
+
+impl<M> BufferSink for M
+where
+    M: for<'writer> MakeWriter<'writer> + Send + Sync,
+{
+    fn write_buffered(&self, log: &[u8]) {
+        let _ = self.make_writer().write_all(log);
+    }
+}
+
+/// Wraps a [`MakeWriter`], diverting logs into the active [`BufferedScope`] (if any).
+pub struct BufferingMakeWriter<M> {
+    inner: Arc<M>,
+    limit: usize,
+}
+
+impl<M> BufferingMakeWriter<M> {
+    pub fn new(inner: M, limit: usize) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            limit,
+        }
+    }
+}
+
+pub struct BufferingWriter<W>(WriterKind<W>);
+
+enum WriterKind<W> {
+    Direct(W),
+    Buffered {
+        scope: Arc<ScopeState>,
+        sink: Arc<dyn BufferSink>,
+        limit: usize,
+    },
+}
+
+impl<W: Write> Write for BufferingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.0 {
+            WriterKind::Direct(writer) => writer.write(buf),
+            WriterKind::Buffered { scope, sink, limit } => {
+                // The fmt layer writes each formatted event in one go, so each write is a full log:
+                scope.push(sink, *limit, buf.to_vec());
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.0 {
+            WriterKind::Direct(writer) => writer.flush(),
+            WriterKind::Buffered { .. } => Ok(()),
+        }
+    }
+}
+
+impl<'writer, M> MakeWriter<'writer> for BufferingMakeWriter<M>
+where
+    M: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    type Writer = BufferingWriter<<M as MakeWriter<'writer>>::Writer>;
+
+    fn make_writer(&'writer self) -> Self::Writer {
+        BufferingWriter(WriterKind::Direct(self.inner.make_writer()))
+    }
+
+    fn make_writer_for(&'writer self, meta: &Metadata<'_>) -> Self::Writer {
+        let scope = CURRENT_SCOPE.with(|current| current.borrow().clone());
+        match scope {
+            Some(scope) if !scope.is_passthrough() => {
+                if *meta.level() <= Level::WARN {
+                    // Failure context is wanted, write out what's been buffered before the warning itself:
+                    scope.flush_chain(true);
+                    BufferingWriter(WriterKind::Direct(self.inner.make_writer_for(meta)))
+                } else {
+                    BufferingWriter(WriterKind::Buffered {
+                        scope,
+                        sink: self.inner.clone(),
+                        limit: self.limit,
+                    })
+                }
+            }
+            _ => BufferingWriter(WriterKind::Direct(self.inner.make_writer_for(meta))),
+        }
+    }
+}
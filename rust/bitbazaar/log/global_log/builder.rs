@@ -2,7 +2,9 @@ use std::path::PathBuf;
 
 use tracing::Level;
 
-use super::GlobalLog;
+#[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+use super::{event_metrics::EventMetricRule, trace_sampling::Sampling};
+use super::{sanitizer::SanitizeOpts, user_output::UserOutput, GlobalLog};
 use crate::prelude::*;
 
 #[derive(Clone)]
@@ -16,6 +18,9 @@ pub struct SharedOpts {
     #[cfg(not(feature = "log-filter"))]
     #[allow(dead_code)]
     pub loc_matcher: Option<bool>,
+
+    /// Skip user-facing messages, see [`GlobalLogBuilder::exclude_user_messages`].
+    pub exclude_user: bool,
 }
 
 impl Default for SharedOpts {
@@ -23,6 +28,7 @@ impl Default for SharedOpts {
         Self {
             level_from: Level::INFO,
             loc_matcher: None,
+            exclude_user: false,
         }
     }
 }
@@ -32,6 +38,10 @@ pub struct StdoutConf {
     pub pretty: bool,
     /// Include the log location (file and line) in each log, defaults to false
     pub include_loc: bool,
+    /// Write each log as a json object rather than text, defaults to false.
+    pub json: bool,
+    /// Post-formatting sanitization, defaults to not stripping ANSI and no max line length.
+    pub sanitize: SanitizeOpts,
     pub shared: SharedOpts,
 }
 
@@ -40,6 +50,22 @@ pub struct FileConf {
     pub file_prefix: String,
     /// The directory to hold the log files, e.g. "./logs/", will create if missing.
     pub dir: PathBuf,
+    /// Write each log as a json object rather than text, defaults to false.
+    pub json: bool,
+    /// Post-formatting sanitization, defaults to stripping ANSI and no max line length.
+    pub sanitize: SanitizeOpts,
+    pub shared: SharedOpts,
+}
+
+pub struct DevConf {
+    /// Only fields with these keys are included in each log (from the event and its spans), defaults to none.
+    pub fields: Vec<String>,
+    /// Enable even when stdout isn't a terminal, defaults to false.
+    pub force: bool,
+    /// Write here rather than stdout, for testing.
+    pub(crate) write: Option<fn(&[u8])>,
+    /// Post-formatting sanitization, defaults to not stripping ANSI and no max line length.
+    pub sanitize: SanitizeOpts,
     pub shared: SharedOpts,
 }
 
@@ -58,6 +84,10 @@ pub struct CustomConf {
     pub write: fn(&[u8]),
     /// Whether to include the color codes in the output, e.g. for writing to a file I'd turn off:
     pub include_color: bool,
+    /// Write each log as a json object rather than text, defaults to false.
+    pub json: bool,
+    /// Post-formatting sanitization, defaults to not stripping ANSI and no max line length.
+    pub sanitize: SanitizeOpts,
     pub shared: SharedOpts,
 }
 
@@ -80,11 +110,24 @@ pub struct OtlpConf {
 #[derive(Default)]
 pub struct GlobalLogBuilder {
     pub(crate) outputs: Vec<Output>,
+    pub(crate) buffered_scope_limit: Option<usize>,
+    pub(crate) quiet_init: bool,
+    pub(crate) user_output: Option<UserOutput>,
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    pub(crate) metric_rules: Vec<EventMetricRule>,
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    pub(crate) trace_sampling: Sampling,
 }
 
 impl GlobalLogBuilder {
     /// Build the global log from the configured builder.
+    ///
+    /// Unless [`GlobalLogBuilder::quiet_init`] is set, a "Logging configured." INFO event describing the outputs is emitted once built,
+    /// see [`GlobalLog::describe`] for its contents.
     pub fn build(self) -> RResult<GlobalLog, AnyErr> {
+        for output in self.outputs.iter() {
+            check_level_not_stripped(output.shared_opts().level_from)?;
+        }
         super::setup::builder_into_global_log(self)
     }
 
@@ -97,11 +140,65 @@ impl GlobalLogBuilder {
         self.outputs.push(Output::Stdout(StdoutConf {
             pretty,
             include_loc,
+            json: false,
+            sanitize: SanitizeOpts::default(),
             shared: SharedOpts::default(),
         }));
         self
     }
 
+    /// Write to stdout in a compact colored format for local development:
+    ///
+    /// `HH:MM:SS L target message key=value`
+    ///
+    /// - The level is a single colored char, the target shortened to its last two segments.
+    /// - Fields are excluded unless allow-listed with [`GlobalLogBuilder::dev_fields`].
+    /// - Multi-line messages are indented under the first line, exceptions are red with their stacktrace dimmed.
+    ///
+    /// Only enabled when stdout is a terminal, so it's safe to leave configured in deployed builds,
+    /// see [`GlobalLogBuilder::dev_force`] to override.
+    pub fn dev_pretty(mut self) -> Self {
+        self.outputs.push(Output::Dev(DevConf {
+            fields: vec![],
+            force: false,
+            write: None,
+            sanitize: SanitizeOpts::default(),
+            shared: SharedOpts::default(),
+        }));
+        self
+    }
+
+    /// [`GlobalLogBuilder::dev_pretty`] but writing to the given fn, for testing.
+    #[cfg(test)]
+    pub(crate) fn dev_pretty_to(mut self, writer: fn(&[u8])) -> Self {
+        self = self.dev_pretty();
+        if let Some(Output::Dev(conf)) = self.outputs.last_mut() {
+            conf.write = Some(writer);
+        }
+        self
+    }
+
+    /// Include fields with these keys in each log, from both the event and its spans.
+    ///
+    /// NOTE: Applies to the last set output only, which must be [`GlobalLogBuilder::dev_pretty`].
+    pub fn dev_fields(
+        mut self,
+        fields: impl IntoIterator<Item = impl Into<String>>,
+    ) -> RResult<Self, AnyErr> {
+        let conf = self.get_active_dev()?;
+        conf.fields = fields.into_iter().map(Into::into).collect();
+        Ok(self)
+    }
+
+    /// Enable even when stdout isn't a terminal, e.g. when piping through another tool that keeps colors.
+    ///
+    /// NOTE: Applies to the last set output only, which must be [`GlobalLogBuilder::dev_pretty`].
+    pub fn dev_force(mut self, force: bool) -> RResult<Self, AnyErr> {
+        let conf = self.get_active_dev()?;
+        conf.force = force;
+        Ok(self)
+    }
+
     /// Write to a file:
     ///
     /// Arguments:
@@ -111,6 +208,12 @@ impl GlobalLogBuilder {
         self.outputs.push(Output::File(FileConf {
             file_prefix: file_prefix.into(),
             dir: dir.into(),
+            json: false,
+            // Files shouldn't contain color codes by default, they bloat and break parsers:
+            sanitize: SanitizeOpts {
+                strip_ansi: true,
+                max_line_len: None,
+            },
             shared: SharedOpts::default(),
         }));
         self
@@ -142,6 +245,8 @@ impl GlobalLogBuilder {
             include_color,
             include_ts,
             write: writer,
+            json: false,
+            sanitize: SanitizeOpts::default(),
             shared: SharedOpts::default(),
         }));
         self
@@ -195,8 +300,105 @@ impl GlobalLogBuilder {
         self
     }
 
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    /// Derive otlp counters from log events, e.g. the rate of "payment declined" warnings, without touching the code that logs them.
+    ///
+    /// Rules are compiled during [`GlobalLogBuilder::build`], which will error if any are invalid or over the evaluation budget.
+    /// Counters are only recorded when an otlp output is configured, rules are independent of each output's level and filters.
+    pub fn metric_from_events(mut self, rules: Vec<EventMetricRule>) -> Self {
+        self.metric_rules.extend(rules);
+        self
+    }
+
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    /// Sample the otlp traces exported, by default all are ([`Sampling::Always`]).
+    ///
+    /// Spans recording an ERROR event (or an exception) are always exported regardless of the decision,
+    /// to achieve this every span is recorded, with the unsampled ones only dropped when they end error free.
+    /// Logs and metrics are never sampled.
+    pub fn trace_sampling(mut self, sampling: Sampling) -> Self {
+        self.trace_sampling = sampling;
+        self
+    }
+
+    /// The maximum number of logs each output buffers per [`crate::log::buffered_scope`], the oldest are dropped past this.
+    /// Defaults to 1000.
+    ///
+    /// NOTE: Applies to all outputs.
+    pub fn buffered_scope_limit(mut self, limit: usize) -> Self {
+        self.buffered_scope_limit = Some(limit);
+        self
+    }
+
+    /// Don't emit the "Logging configured." INFO event describing the outputs when built.
+    ///
+    /// The description is still available from [`GlobalLog::describe`].
+    pub fn quiet_init(mut self) -> Self {
+        self.quiet_init = true;
+        self
+    }
+
+    /// Render user-facing messages from [`crate::user_msg`], [`crate::user_warn`] and [`crate::user_err`] here,
+    /// cleanly without timestamps, targets or fields, and regardless of each output's level and filters:
+    ///
+    /// `Uploading 3 files...`, `warning: 1 file skipped` or `error: Upload failed`, the prefixes yellow and red respectively.
+    ///
+    /// User messages are still normal events, so also reach the other outputs unless they use [`GlobalLogBuilder::exclude_user_messages`].
+    ///
+    /// NOTE: Applies to all outputs, the last call wins.
+    pub fn user_output(mut self, output: UserOutput) -> Self {
+        self.user_output = Some(output);
+        self
+    }
+
+    /// Skip user-facing messages in this output, to avoid duplicating those already shown by [`GlobalLogBuilder::user_output`].
+    ///
+    /// NOTE: Applies to the last set output type only.
+    pub fn exclude_user_messages(mut self) -> RResult<Self, AnyErr> {
+        let shared = self.get_active_shared()?;
+        shared.exclude_user = true;
+        Ok(self)
+    }
+
+    /// Write each log as a single line json object, for log aggregators like Loki or Elastic:
+    ///
+    /// `{"timestamp":"..","level":"INFO","target":"..","file":"..","line":1,"message":"..","fields":{..},"spans":[{"name":"..",..}]}`
+    ///
+    /// - The timestamp is RFC 3339 in UTC, `file` and `line` are always included when known.
+    /// - `fields` are the event's, `spans` are the event's spans from the root, each with its name and fields.
+    /// - Exceptions use `type: message` as the message, with their stacktrace in a `stacktrace` string.
+    ///
+    /// The text formatting options of the output (pretty, color etc.) are ignored.
+    /// Avoid [`GlobalLogBuilder::max_line_len`] with json, truncated lines won't parse.
+    ///
+    /// NOTE: Applies to the last set output only, which must be a stdout, file or custom output.
+    pub fn json(mut self) -> RResult<Self, AnyErr> {
+        match self.outputs.last_mut() {
+            Some(Output::Stdout(conf)) => conf.json = true,
+            Some(Output::File(conf)) => conf.json = true,
+            Some(Output::Custom(conf)) => conf.json = true,
+            Some(_) => {
+                return Err(anyerr!(
+                    "Json only applies to stdout, file and custom outputs."
+                ))
+            }
+            None => {
+                return Err(anyerr!(
+                    "No output set yet to apply this value to. Set an output first."
+                ))
+            }
+        }
+        Ok(self)
+    }
+
     /// Set the minimum level to log for.
     ///
+    /// With the `strip-debug-logs` feature, DEBUG and TRACE events are compiled out of release builds,
+    /// so [`GlobalLogBuilder::build`] errors if any output asks for them rather than silently logging nothing.
+    /// The feature sets tracing's `release_max_level_info`, which applies to every crate in the binary.
+    /// Downstream crates enabling their own tracing `max_level_*` or `release_max_level_*` features combine with it, the most restrictive winning,
+    /// but only this feature is checked here.
+    ///
     /// NOTE: Applies to the last set output type only.
     pub fn level_from(mut self, level: Level) -> RResult<Self, AnyErr> {
         let shared = self.get_active_shared()?;
@@ -216,10 +418,59 @@ impl GlobalLogBuilder {
         Ok(self)
     }
 
+    /// Strip ANSI escape sequences (e.g. colors from pre-colored strings) from each log before writing.
+    /// Defaults to true for file outputs, false otherwise.
+    ///
+    /// NOTE: Applies to the last set output type only, not supported for otlp outputs.
+    pub fn strip_ansi(mut self, strip_ansi: bool) -> RResult<Self, AnyErr> {
+        let sanitize = self.get_active_sanitize()?;
+        sanitize.strip_ansi = strip_ansi;
+        Ok(self)
+    }
+
+    /// Truncate any log line longer than this many chars, a `…(+N chars)` suffix is added to truncated lines.
+    /// Prevents a single runaway log producing multi-MB lines.
+    ///
+    /// NOTE: Applies to the last set output type only, not supported for otlp outputs.
+    pub fn max_line_len(mut self, max_line_len: usize) -> RResult<Self, AnyErr> {
+        let sanitize = self.get_active_sanitize()?;
+        sanitize.max_line_len = Some(max_line_len);
+        Ok(self)
+    }
+
+    fn get_active_dev(&mut self) -> RResult<&mut DevConf, AnyErr> {
+        match self.outputs.last_mut() {
+            Some(Output::Dev(conf)) => Ok(conf),
+            Some(_) => Err(anyerr!(
+                "Dev options only apply to dev_pretty() outputs, set one first."
+            )),
+            None => Err(anyerr!(
+                "No output set yet to apply this value to. Set an output first."
+            )),
+        }
+    }
+
+    fn get_active_sanitize(&mut self) -> RResult<&mut SanitizeOpts, AnyErr> {
+        match self.outputs.last_mut() {
+            Some(Output::Stdout(conf)) => Ok(&mut conf.sanitize),
+            Some(Output::Dev(conf)) => Ok(&mut conf.sanitize),
+            Some(Output::File(conf)) => Ok(&mut conf.sanitize),
+            Some(Output::Custom(conf)) => Ok(&mut conf.sanitize),
+            #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+            Some(Output::Otlp(_)) => Err(anyerr!(
+                "Line sanitization options are not supported for otlp outputs."
+            )),
+            None => Err(anyerr!(
+                "No output set yet to apply this value to. Set an output first."
+            )),
+        }
+    }
+
     fn get_active_shared(&mut self) -> RResult<&mut SharedOpts, AnyErr> {
         if let Some(output) = self.outputs.last_mut() {
             Ok(match output {
                 Output::Stdout(conf) => &mut conf.shared,
+                Output::Dev(conf) => &mut conf.shared,
                 Output::File(conf) => &mut conf.shared,
                 Output::Custom(conf) => &mut conf.shared,
                 #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
@@ -235,6 +486,7 @@ impl GlobalLogBuilder {
 
 pub enum Output {
     Stdout(StdoutConf),
+    Dev(DevConf),
     File(FileConf),
     Custom(CustomConf),
     #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
@@ -246,6 +498,7 @@ impl Output {
     pub fn shared_opts(&self) -> &SharedOpts {
         match self {
             Output::Stdout(conf) => &conf.shared,
+            Output::Dev(conf) => &conf.shared,
             Output::File(conf) => &conf.shared,
             Output::Custom(conf) => &conf.shared,
             #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
@@ -253,3 +506,14 @@ impl Output {
         }
     }
 }
+
+/// Error if `level` needs events compiled out by the `strip-debug-logs` feature, so the misconfiguration is loud rather than silent.
+pub(crate) fn check_level_not_stripped(level: Level) -> RResult<(), AnyErr> {
+    if cfg!(all(feature = "strip-debug-logs", not(debug_assertions))) && level > Level::INFO {
+        return Err(anyerr!(
+            "Can't log from {}, DEBUG and TRACE events are compiled out of this build by bitbazaar's 'strip-debug-logs' feature. Use INFO or above.",
+            level
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use tracing_core::{Field, Level, Subscriber};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    registry::LookupSpan,
+};
+
+use super::event_formatter::{clean_string, ExceptionEventVisitor};
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const MAGENTA: &str = "\x1b[35m";
+
+/// Formats both event and span fields for the dev output, only keeping those in the allow-list.
+///
+/// Used as the layer's field formatter, so span fields are stored pre-filtered when the span is created.
+#[derive(Clone)]
+pub struct DevFields {
+    allowed: Arc<Vec<String>>,
+    include_color: bool,
+}
+
+impl DevFields {
+    pub fn new(allowed: Vec<String>, include_color: bool) -> Self {
+        Self {
+            allowed: Arc::new(allowed),
+            include_color,
+        }
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.include_color {
+            format!("{}{}{}", color, s, RESET)
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn write_fields(&self, writer: &mut Writer<'_>, fields: &[(&str, String)]) -> std::fmt::Result {
+        for (index, (key, value)) in fields.iter().enumerate() {
+            if index > 0 {
+                write!(writer, " ")?;
+            }
+            write!(writer, "{}{}", self.paint(DIM, &format!("{}=", key)), value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'w> FormatFields<'w> for DevFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'w>,
+        fields: R,
+    ) -> std::fmt::Result {
+        let mut visitor = DevVisitor::new(&self.allowed);
+        fields.record(&mut visitor);
+        self.write_fields(&mut writer, &visitor.fields)
+    }
+}
+
+/// The compact colored formatter for local development, see [`super::GlobalLogBuilder::dev_pretty`]:
+///
+/// `HH:MM:SS L target message key=value`
+///
+/// - The level is a single colored char.
+/// - The target is shortened to its last two segments.
+/// - Only allow-listed fields are included, the event's then its spans' from the root.
+/// - Multi-line messages are indented under the first line.
+/// - Exceptions are rendered in red, with their stacktrace dimmed underneath.
+pub struct DevEventFormatter {
+    fields: DevFields,
+    time_offset: time::UtcOffset,
+}
+
+impl DevEventFormatter {
+    pub fn new(fields: DevFields) -> Self {
+        Self {
+            fields,
+            // Local offset can only reliably be read before other threads are spawned, so reading once up front:
+            time_offset: time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC),
+        }
+    }
+}
+
+impl<S> FormatEvent<S, DevFields> for DevEventFormatter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, DevFields>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let now = time::OffsetDateTime::now_utc().to_offset(self.time_offset);
+        let time = format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second());
+        let (level, level_color) = match *meta.level() {
+            Level::ERROR => ("E", RED),
+            Level::WARN => ("W", YELLOW),
+            Level::INFO => ("I", GREEN),
+            Level::DEBUG => ("D", BLUE),
+            Level::TRACE => ("T", MAGENTA),
+        };
+        write!(
+            writer,
+            "{} {} ",
+            self.fields.paint(DIM, &time),
+            self.fields.paint(level_color, level)
+        )?;
+
+        // Exceptions always come from the same internal location, so no target, just the error and its stack:
+        if meta
+            .fields()
+            .iter()
+            .any(|field| field.name().starts_with("exception."))
+        {
+            let mut visitor = ExceptionEventVisitor::default();
+            event.record(&mut visitor);
+            let indent = time.len() + level.len() + 2;
+            let header = match (visitor.typ, visitor.message) {
+                (Some(typ), Some(message)) => {
+                    format!("{}: {}", clean_string(&typ), clean_string(&message))
+                }
+                (Some(only), None) | (None, Some(only)) => clean_string(&only).to_string(),
+                (None, None) => "Exception".to_string(),
+            };
+            write_indented(&mut writer, &header, indent, |line| {
+                self.fields.paint(RED, line)
+            })?;
+            if let Some(stacktrace) = visitor.stacktrace {
+                for line in clean_string(&stacktrace).lines() {
+                    writeln!(writer)?;
+                    write!(
+                        writer,
+                        "{:indent$}{}",
+                        "",
+                        self.fields.paint(DIM, line),
+                        indent = indent
+                    )?;
+                }
+            }
+            if let Some(span_trace) = visitor.span_trace {
+                writeln!(writer)?;
+                write!(
+                    writer,
+                    "{:indent$}{}",
+                    "",
+                    self.fields.paint(DIM, "span trace:"),
+                    indent = indent
+                )?;
+                for line in clean_string(&span_trace).lines() {
+                    writeln!(writer)?;
+                    write!(
+                        writer,
+                        "{:indent$}{}",
+                        "",
+                        self.fields.paint(DIM, line),
+                        indent = indent + 4
+                    )?;
+                }
+            }
+            return writeln!(writer);
+        }
+
+        let target = short_target(meta.target());
+        write!(writer, "{} ", self.fields.paint(DIM, target))?;
+        let indent = time.len() + level.len() + target.len() + 3;
+
+        let mut visitor = DevVisitor::new(&self.fields.allowed);
+        event.record(&mut visitor);
+        write_indented(
+            &mut writer,
+            visitor.message.as_deref().unwrap_or_default(),
+            indent,
+            |line| line.to_string(),
+        )?;
+        if !visitor.fields.is_empty() {
+            write!(writer, " ")?;
+            self.fields.write_fields(&mut writer, &visitor.fields)?;
+        }
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<DevFields>>() {
+                    let fields = fields.trim();
+                    if !fields.is_empty() {
+                        write!(writer, " {}", fields)?;
+                    }
+                }
+            }
+        }
+
+        writeln!(writer)
+    }
+}
+
+/// Write each line of a possibly multi-line string, indenting all but the first.
+fn write_indented(
+    writer: &mut Writer<'_>,
+    s: &str,
+    indent: usize,
+    paint: impl Fn(&str) -> String,
+) -> std::fmt::Result {
+    for (index, line) in s.lines().enumerate() {
+        if index > 0 {
+            writeln!(writer)?;
+            write!(writer, "{:indent$}", "", indent = indent)?;
+        }
+        write!(writer, "{}", paint(line))?;
+    }
+    Ok(())
+}
+
+/// The last two segments of a module path target, e.g. `bitbazaar::log::tests` -> `log::tests`.
+fn short_target(target: &str) -> &str {
+    match target.rmatch_indices("::").nth(1) {
+        Some((index, _)) => &target[index + 2..],
+        None => target,
+    }
+}
+
+struct DevVisitor<'a> {
+    allowed: &'a [String],
+    message: Option<String>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl<'a> DevVisitor<'a> {
+    fn new(allowed: &'a [String]) -> Self {
+        Self {
+            allowed,
+            message: None,
+            fields: vec![],
+        }
+    }
+
+    fn is_allowed(&self, field: &Field) -> bool {
+        self.allowed.iter().any(|allowed| allowed == field.name())
+    }
+}
+
+impl tracing::field::Visit for DevVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else if self.is_allowed(field) {
+            self.fields.push((field.name(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else if self.is_allowed(field) {
+            self.fields.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case("bitbazaar::log::tests", "log::tests")]
+    #[case("log::tests", "log::tests")]
+    #[case("main", "main")]
+    fn test_short_target(#[case] target: &str, #[case] expected: &str) {
+        assert_eq!(short_target(target), expected);
+    }
+}
@@ -44,6 +44,7 @@ where
             "exception.message",
             "exception.type",
             "exception.stacktrace",
+            "exception.span_trace",
         ];
         let meta = event.metadata();
 
@@ -76,10 +77,11 @@ where
 }
 
 #[derive(Default)]
-struct ExceptionEventVisitor {
-    message: Option<String>,
-    typ: Option<String>,
-    stacktrace: Option<String>,
+pub(super) struct ExceptionEventVisitor {
+    pub(super) message: Option<String>,
+    pub(super) typ: Option<String>,
+    pub(super) stacktrace: Option<String>,
+    pub(super) span_trace: Option<String>,
 }
 
 impl ExceptionEventVisitor {
@@ -104,6 +106,12 @@ impl ExceptionEventVisitor {
             msg.push_str(clean_string(&message));
             msg.push('\n');
         }
+        if let Some(span_trace) = self.span_trace {
+            msg.push_str("span trace:\n");
+            for line in clean_string(&span_trace).lines() {
+                msg.push_str(&format!("    {}\n", line));
+            }
+        }
         msg
     }
 }
@@ -111,7 +119,7 @@ impl ExceptionEventVisitor {
 #[inline]
 /// Weirdly they seem to come in with quotes around them, this simple removes them.
 /// In a sep func to allow extending if needed.
-fn clean_string(s: &str) -> &str {
+pub(super) fn clean_string(s: &str) -> &str {
     s.trim_matches('"')
 }
 
@@ -121,6 +129,7 @@ impl tracing::field::Visit for ExceptionEventVisitor {
             "exception.message" => self.message = Some(value.to_string()),
             "exception.type" => self.typ = Some(value.to_string()),
             "exception.stacktrace" => self.stacktrace = Some(value.to_string()),
+            "exception.span_trace" => self.span_trace = Some(value.to_string()),
             _ => {}
         }
     }
@@ -132,6 +141,7 @@ impl tracing::field::Visit for ExceptionEventVisitor {
             "exception.message" => self.message = Some(format!("{:?}", value)),
             "exception.type" => self.typ = Some(format!("{:?}", value)),
             "exception.stacktrace" => self.stacktrace = Some(format!("{:?}", value)),
+            "exception.span_trace" => self.span_trace = Some(format!("{:?}", value)),
             _ => {}
         }
     }
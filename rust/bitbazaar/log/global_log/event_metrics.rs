@@ -0,0 +1,286 @@
+use std::fmt::Debug;
+
+use opentelemetry::{
+    metrics::{Counter, MeterProvider},
+    KeyValue,
+};
+use tracing::{field::Visit, Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, Layer};
+
+use crate::prelude::*;
+
+/// The max number of rules that can be registered, each is checked against every event.
+const MAX_RULES: usize = 64;
+
+/// The max compiled size of each rule's message regex, prevents pathological regexes slowing down every matching event.
+#[cfg(feature = "log-filter")]
+const MAX_REGEX_SIZE: usize = 64 * 1024;
+
+/// Where to take the value of a counter attribute from.
+#[derive(Debug, Clone)]
+enum AttrSource {
+    /// A field on the event, e.g. `user_id` in `warn!(user_id = 1, "...")`.
+    Field(String),
+    /// A named capture group in the rule's message regex.
+    #[cfg(feature = "log-filter")]
+    Capture(String),
+}
+
+/// A rule deriving an otlp counter from log events, see [`super::GlobalLogBuilder::metric_from_events`].
+///
+/// Each event matching all of the rule's conditions increments the counter by 1.
+///
+/// ```
+/// use bitbazaar::log::EventMetricRule;
+/// use tracing::Level;
+///
+/// let rule = EventMetricRule::new("payments_declined")
+///     .level_from(Level::WARN)
+///     .target_prefix("my_app::payments")
+///     .message_regex(r"declined: (?P<reason>\w+)")
+///     .attr_from_capture("reason", "reason")
+///     .attr_from_field("provider", "provider");
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventMetricRule {
+    counter: String,
+    level_from: Option<Level>,
+    target_prefix: Option<String>,
+    #[cfg(feature = "log-filter")]
+    message_regex: Option<String>,
+    attrs: Vec<(String, AttrSource)>,
+}
+
+impl EventMetricRule {
+    /// Create a new rule incrementing the counter with the given name, by default matches every event.
+    pub fn new(counter: impl Into<String>) -> Self {
+        Self {
+            counter: counter.into(),
+            level_from: None,
+            target_prefix: None,
+            #[cfg(feature = "log-filter")]
+            message_regex: None,
+            attrs: vec![],
+        }
+    }
+
+    /// Only match events at this level or more severe, e.g. `Level::WARN` matches warnings and errors.
+    pub fn level_from(mut self, level: Level) -> Self {
+        self.level_from = Some(level);
+        self
+    }
+
+    /// Only match events whose target (by default the module path) starts with this prefix.
+    pub fn target_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.target_prefix = Some(prefix.into());
+        self
+    }
+
+    #[cfg(feature = "log-filter")]
+    /// Only match events whose message matches this regex, compiled when the log is built.
+    pub fn message_regex(mut self, regex: impl Into<String>) -> Self {
+        self.message_regex = Some(regex.into());
+        self
+    }
+
+    /// Add an attribute to the counter, taken from a field on the event. Skipped when the event doesn't have the field.
+    pub fn attr_from_field(mut self, attr: impl Into<String>, field: impl Into<String>) -> Self {
+        self.attrs
+            .push((attr.into(), AttrSource::Field(field.into())));
+        self
+    }
+
+    #[cfg(feature = "log-filter")]
+    /// Add an attribute to the counter, taken from a named capture group in [`EventMetricRule::message_regex`].
+    /// Skipped when the group didn't participate in the match.
+    pub fn attr_from_capture(mut self, attr: impl Into<String>, group: impl Into<String>) -> Self {
+        self.attrs
+            .push((attr.into(), AttrSource::Capture(group.into())));
+        self
+    }
+}
+
+/// A rule compiled against a meter, ready to evaluate events.
+pub struct CompiledRule {
+    counter: Counter<u64>,
+    level_from: Option<Level>,
+    target_prefix: Option<String>,
+    #[cfg(feature = "log-filter")]
+    message_regex: Option<regex::Regex>,
+    attrs: Vec<(String, AttrSource)>,
+}
+
+impl CompiledRule {
+    /// The checks possible without visiting the event's fields.
+    #[inline]
+    fn cheap_match(&self, event: &Event<'_>) -> bool {
+        let metadata = event.metadata();
+        if let Some(level_from) = &self.level_from {
+            if level_from < metadata.level() {
+                return false;
+            }
+        }
+        if let Some(target_prefix) = &self.target_prefix {
+            if !metadata.target().starts_with(target_prefix.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn evaluate(&self, fields: &EventFields) {
+        #[cfg(feature = "log-filter")]
+        let captures = if let Some(regex) = &self.message_regex {
+            match regex.captures(fields.message.as_deref().unwrap_or("")) {
+                Some(captures) => Some(captures),
+                None => return,
+            }
+        } else {
+            None
+        };
+
+        let attrs = self
+            .attrs
+            .iter()
+            .filter_map(|(attr, source)| {
+                let value = match source {
+                    AttrSource::Field(field) => fields
+                        .fields
+                        .iter()
+                        .find(|(name, _)| name == field)
+                        .map(|(_, value)| value.clone()),
+                    #[cfg(feature = "log-filter")]
+                    AttrSource::Capture(group) => captures
+                        .as_ref()
+                        .and_then(|captures| captures.name(group))
+                        .map(|m| m.as_str().to_string()),
+                };
+                value.map(|value| KeyValue::new(attr.clone(), value))
+            })
+            .collect::<Vec<_>>();
+        self.counter.add(1, &attrs);
+    }
+}
+
+/// Compile the rules, erroring if any are invalid or over budget.
+pub fn compile_rules(
+    rules: Vec<EventMetricRule>,
+    meter_provider: &impl MeterProvider,
+) -> RResult<Vec<CompiledRule>, AnyErr> {
+    if rules.len() > MAX_RULES {
+        return Err(anyerr!(
+            "Too many event metric rules: {}, max is {}.",
+            rules.len(),
+            MAX_RULES
+        ));
+    }
+
+    let meter = meter_provider.meter("event_metrics");
+    rules
+        .into_iter()
+        .map(|rule| {
+            if rule.counter.is_empty() {
+                return Err(anyerr!("Event metric rule counter name cannot be empty."));
+            }
+
+            #[cfg(feature = "log-filter")]
+            let message_regex = if let Some(regex) = &rule.message_regex {
+                Some(
+                    regex::RegexBuilder::new(regex)
+                        .size_limit(MAX_REGEX_SIZE)
+                        .build()
+                        .change_context(AnyErr)
+                        .attach_printable_lazy(|| {
+                            format!(
+                                "Event metric rule '{}' message regex invalid or over the evaluation budget.",
+                                rule.counter
+                            )
+                        })?,
+                )
+            } else {
+                None
+            };
+
+            #[cfg(feature = "log-filter")]
+            for (attr, source) in &rule.attrs {
+                if let AttrSource::Capture(group) = source {
+                    let has_group = message_regex.as_ref().is_some_and(|regex| {
+                        regex.capture_names().any(|name| name == Some(group.as_str()))
+                    });
+                    if !has_group {
+                        return Err(anyerr!(
+                            "Event metric rule '{}' attribute '{}' uses capture group '{}', which isn't in the rule's message regex.",
+                            rule.counter,
+                            attr,
+                            group
+                        ));
+                    }
+                }
+            }
+
+            Ok(CompiledRule {
+                counter: meter.u64_counter(rule.counter).init(),
+                level_from: rule.level_from,
+                target_prefix: rule.target_prefix,
+                #[cfg(feature = "log-filter")]
+                message_regex,
+                attrs: rule.attrs,
+            })
+        })
+        .collect()
+}
+
+/// The layer incrementing counters for events matching the rules.
+pub struct EventMetricsLayer {
+    rules: Vec<CompiledRule>,
+}
+
+impl EventMetricsLayer {
+    pub fn new(rules: Vec<CompiledRule>) -> Self {
+        Self { rules }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for EventMetricsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Only visit the fields (the expensive part) when at least one rule could match:
+        let mut fields = None;
+        for rule in &self.rules {
+            if rule.cheap_match(event) {
+                let fields = fields.get_or_insert_with(|| {
+                    let mut fields = EventFields::default();
+                    event.record(&mut fields);
+                    fields
+                });
+                rule.evaluate(fields);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct EventFields {
+    #[cfg_attr(not(feature = "log-filter"), allow(dead_code))]
+    message: Option<String>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl EventFields {
+    fn push(&mut self, name: &'static str, value: String) {
+        if name == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.push((name, value));
+        }
+    }
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.push(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn Debug) {
+        self.push(field.name(), format!("{:?}", value));
+    }
+}
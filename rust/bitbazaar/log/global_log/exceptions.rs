@@ -1,18 +1,47 @@
+use std::cell::{Cell, OnceCell};
+
 // Inner for record_exception to allow specifying type internally.
+// The stacktrace is recorded as a display value, so it's only formatted by layers that actually receive the event.
+// Same for the span trace, which is left off entirely when not inside a span.
 pub fn record_exception_inner(
     message: impl Into<String>,
-    stacktrace: impl Into<String>,
+    stacktrace: impl std::fmt::Display,
     typ: &str,
 ) {
     tracing::event!(
         tracing::Level::ERROR,
         name = "exception", // Must be named this for observers to recognise it as an exception
         exception.message = message.into(),
-        exception.stacktrace = stacktrace.into(),
+        exception.stacktrace = tracing::field::display(stacktrace),
+        exception.span_trace = super::span_trace::SpanTrace::capture().map(tracing::field::display),
         "exception.type" = typ
     );
 }
 
+/// Converts the source into a string when first formatted, i.e. only by layers that actually receive the event.
+pub struct LazyString<S> {
+    source: Cell<Option<S>>,
+    converted: OnceCell<String>,
+}
+
+impl<S> LazyString<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source: Cell::new(Some(source)),
+            converted: OnceCell::new(),
+        }
+    }
+}
+
+impl<S: Into<String>> std::fmt::Display for LazyString<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            self.converted
+                .get_or_init(|| self.source.take().map(Into::into).unwrap_or_default()),
+        )
+    }
+}
+
 /// Setup the program to automatically log panics as an error event on the current span.
 /// Internally makes sure it only runs once.
 pub fn auto_trace_panics() {
@@ -10,8 +10,13 @@ use crate::prelude::*;
 /// Arguments:
 /// - `message`: Information about the exception e.g. `Internal Error leading to 500 http response`.
 /// - `stacktrace`: All of the location information for the exception, (maybe also the exception itself if e.g. from `Report<T>`).
+///   Only converted to a string if the event passes the subscriber's filters, so prefer passing e.g. [`crate::errors::LazyDebug`]`(&report)` over a pre-formatted string.
 pub fn record_exception(message: impl Into<String>, stacktrace: impl Into<String>) {
-    super::exceptions::record_exception_inner(message, stacktrace, "Err");
+    super::exceptions::record_exception_inner(
+        message,
+        super::exceptions::LazyString::new(stacktrace),
+        "Err",
+    );
 }
 
 #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
@@ -54,6 +59,20 @@ pub fn set_response_headers_from_ctx<B>(response: &mut http::Response<B>) -> RRe
     get_global()?.set_response_headers_from_ctx(response)
 }
 
+/// Change the minimum level logged at runtime, e.g. to get debug logs from a misbehaving production process without a restart.
+///
+/// Replaces the `level_from` of every output (stdout, file, custom and otlp), the outputs are otherwise untouched.
+pub fn set_global_level_from(level: tracing::Level) -> RResult<(), AnyErr> {
+    get_global()?.set_level_from(level)
+}
+
+/// Describe the outputs the global logger was built with, e.g. for a health or debug endpoint.
+///
+/// Credentials in otlp endpoints are masked, so it's safe to expose.
+pub fn describe_global_log() -> RResult<super::LogConfigDescription, AnyErr> {
+    Ok(get_global()?.describe())
+}
+
 /// Force through logs, traces and metrics, useful in e.g. testing.
 ///
 /// Note there doesn't seem to be an underlying interface to force through metrics.
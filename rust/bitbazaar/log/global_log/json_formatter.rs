@@ -0,0 +1,245 @@
+use std::fmt::Write as _;
+
+use tracing_core::{Field, Subscriber};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    registry::LookupSpan,
+};
+
+use super::event_formatter::clean_string;
+
+/// Formats span fields as comma separated json object members, e.g. `"a":1,"b":"x"`.
+///
+/// Used as the layer's field formatter, so span fields are stored pre-encoded when the span is created,
+/// the event formatter then wraps them in an object per span.
+#[derive(Clone, Default)]
+pub struct JsonFields;
+
+impl<'w> FormatFields<'w> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'w>,
+        fields: R,
+    ) -> std::fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        if let Some(message) = visitor.message.take() {
+            visitor.add_member("message", &json_str(&message));
+        }
+        write!(writer, "{}", visitor.members)
+    }
+
+    fn add_fields(
+        &self,
+        current: &'w mut FormattedFields<Self>,
+        fields: &tracing_core::span::Record<'_>,
+    ) -> std::fmt::Result {
+        // The default separates with a space, which would break the json:
+        if !current.fields.is_empty() {
+            current.fields.push(',');
+        }
+        self.format_fields(current.as_writer(), fields)
+    }
+}
+
+/// Formats each event as a single line json object, see [`super::GlobalLogBuilder::json`]:
+///
+/// `{"timestamp":"..","level":"INFO","target":"..","file":"..","line":1,"message":"..","fields":{..},"spans":[{"name":"..",..}]}`
+///
+/// - The timestamp is RFC 3339 in UTC.
+/// - `file` and `line` are omitted when unknown.
+/// - Spans are ordered from the root, each with its name and fields.
+/// - Exceptions use `type: message` as the message, with their stacktrace in a `stacktrace` string,
+///   and their span trace in a `span_trace` string when recorded inside a span.
+pub struct JsonEventFormatter;
+
+impl<S> FormatEvent<S, JsonFields> for JsonEventFormatter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+
+        let timestamp = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .map_err(|_| std::fmt::Error)?;
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"timestamp\":{},\"level\":{},\"target\":{}",
+            json_str(&timestamp),
+            json_str(meta.level().as_str()),
+            json_str(meta.target())
+        )?;
+        if let Some(file) = meta.file() {
+            write!(out, ",\"file\":{}", json_str(file))?;
+        }
+        if let Some(line) = meta.line() {
+            write!(out, ",\"line\":{}", line)?;
+        }
+
+        let message = match (visitor.exc_type.take(), visitor.exc_message.take()) {
+            (Some(typ), Some(message)) => Some(format!(
+                "{}: {}",
+                clean_string(&typ),
+                clean_string(&message)
+            )),
+            (Some(only), None) | (None, Some(only)) => Some(clean_string(&only).to_string()),
+            (None, None) => visitor.message.take(),
+        };
+        if let Some(message) = message {
+            write!(out, ",\"message\":{}", json_str(&message))?;
+        }
+        if let Some(stacktrace) = visitor.exc_stacktrace.take() {
+            write!(
+                out,
+                ",\"stacktrace\":{}",
+                json_str(clean_string(&stacktrace))
+            )?;
+        }
+        if let Some(span_trace) = visitor.exc_span_trace.take() {
+            write!(
+                out,
+                ",\"span_trace\":{}",
+                json_str(clean_string(&span_trace))
+            )?;
+        }
+        write!(out, ",\"fields\":{{{}}},\"spans\":[", visitor.members)?;
+
+        if let Some(scope) = ctx.event_scope() {
+            for (index, span) in scope.from_root().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write!(out, "{{\"name\":{}", json_str(span.name()))?;
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() {
+                    if !fields.is_empty() {
+                        write!(out, ",{}", fields)?;
+                    }
+                }
+                out.push('}');
+            }
+        }
+        out.push_str("]}");
+
+        writeln!(writer, "{}", out)
+    }
+}
+
+/// Collects fields as json members, pulling out the message and exception fields.
+#[derive(Default)]
+struct JsonVisitor {
+    message: Option<String>,
+    exc_message: Option<String>,
+    exc_type: Option<String>,
+    exc_stacktrace: Option<String>,
+    exc_span_trace: Option<String>,
+    members: String,
+}
+
+impl JsonVisitor {
+    fn add_member(&mut self, key: &str, encoded_value: &str) {
+        if !self.members.is_empty() {
+            self.members.push(',');
+        }
+        self.members.push_str(&json_str(key));
+        self.members.push(':');
+        self.members.push_str(encoded_value);
+    }
+
+    /// Returns true if the field was taken as the message or part of an exception.
+    fn take_special(&mut self, field: &Field, value: impl FnOnce() -> String) -> bool {
+        let target = match field.name() {
+            "message" => &mut self.message,
+            "exception.message" => &mut self.exc_message,
+            "exception.type" => &mut self.exc_type,
+            "exception.stacktrace" => &mut self.exc_stacktrace,
+            "exception.span_trace" => &mut self.exc_span_trace,
+            _ => return false,
+        };
+        *target = Some(value());
+        true
+    }
+}
+
+impl tracing::field::Visit for JsonVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !self.take_special(field, || value.to_string()) {
+            self.add_member(field.name(), &json_str(value));
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.add_member(field.name(), &value.to_string());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.add_member(field.name(), &value.to_string());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.add_member(field.name(), &value.to_string());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // Json has no representation for nan or infinity:
+        if value.is_finite() {
+            self.add_member(field.name(), &value.to_string());
+        } else {
+            self.add_member(field.name(), &json_str(&value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.take_special(field, || format!("{:?}", value)) {
+            self.add_member(field.name(), &json_str(&format!("{:?}", value)));
+        }
+    }
+}
+
+/// Encode a string as a quoted and escaped json string.
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case("plain", r#""plain""#)]
+    #[case("a \"quoted\" \\ path", r#""a \"quoted\" \\ path""#)]
+    #[case("line1\nline2\ttab\r", r#""line1\nline2\ttab\r""#)]
+    #[case("\x1b[31mred", r#""\u001b[31mred""#)]
+    #[case("ünïcode", r#""ünïcode""#)]
+    fn test_json_str(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(json_str(input), expected);
+    }
+}
@@ -1,11 +1,30 @@
+mod buffered;
 mod builder;
+mod describe;
+mod dev_formatter;
 mod event_formatter;
+#[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+mod event_metrics;
 mod exceptions;
 pub mod global_fns;
 #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
 mod http_headers;
+mod json_formatter;
 mod out;
+mod sanitizer;
 mod setup;
+mod span_trace;
+#[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+mod trace_sampling;
+mod user_output;
 
+pub use buffered::{buffered_scope, BufferedScope};
 pub use builder::GlobalLogBuilder;
+pub use describe::{LogConfigDescription, SinkDescription};
+#[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+pub use event_metrics::EventMetricRule;
 pub use out::GlobalLog;
+pub use sanitizer::SanitizeOpts;
+#[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+pub use trace_sampling::Sampling;
+pub use user_output::{UserOutput, USER_FIELD};
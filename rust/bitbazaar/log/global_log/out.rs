@@ -7,6 +7,9 @@ use crate::errors::prelude::*;
 
 pub static GLOBAL_LOG: Lazy<Mutex<Option<GlobalLog>>> = Lazy::new(Mutex::default);
 
+/// Replaces the level filter of one output.
+pub(crate) type LevelSetter = Box<dyn Fn(Level) -> RResult<(), AnyErr> + Send + Sync>;
+
 /// The global logger/tracer for stdout, file and full open telemetry. Works with the tracing crates (info!, debug!, warn!, error!) and span funcs and decorators.
 ///
 /// [`GlobalLog::meter`] is also provided to create metrics, these aren't native to the tracing crate.
@@ -38,6 +41,12 @@ pub struct GlobalLog {
     /// Tracing dispatcher, needed to make the global logger.
     pub(crate) dispatch: Option<Dispatch>,
 
+    /// One per output, to change their levels at runtime.
+    pub(crate) level_setters: Vec<LevelSetter>,
+
+    /// What the outputs were built with.
+    pub(crate) description: super::LogConfigDescription,
+
     // tracing_appender not included in wasm:
     #[cfg(not(target_arch = "wasm32"))]
     /// Need to store these guards, when they go out of scope the logging may stop.
@@ -147,6 +156,20 @@ impl GlobalLog {
         }
     }
 
+    /// See [`super::global_fns::describe_global_log`]`
+    pub fn describe(&self) -> super::LogConfigDescription {
+        self.description.clone()
+    }
+
+    /// See [`super::global_fns::set_global_level_from`]`
+    pub fn set_level_from(&self, level: Level) -> RResult<(), AnyErr> {
+        super::builder::check_level_not_stripped(level)?;
+        for setter in self.level_setters.iter() {
+            setter(level)?;
+        }
+        Ok(())
+    }
+
     /// See [`super::global_fns::flush`]`
     pub fn flush(&self) -> RResult<(), AnyErr> {
         #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
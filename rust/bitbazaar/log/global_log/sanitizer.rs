@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+
+/// Post-formatting options applied to each formatted log before it's passed to the underlying writer.
+#[derive(Clone, Debug, Default)]
+pub struct SanitizeOpts {
+    /// Remove ANSI escape sequences (e.g. colors) from the formatted output.
+    pub strip_ansi: bool,
+    /// Truncate any line longer than this many chars, adding a `…(+N chars)` suffix.
+    pub max_line_len: Option<usize>,
+}
+
+impl SanitizeOpts {
+    /// Apply the options to a formatted log.
+    pub fn apply<'a>(&self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut out = Cow::Borrowed(buf);
+        if self.strip_ansi && buf.contains(&ESC) {
+            out = Cow::Owned(strip_ansi(buf));
+        }
+        if let Some(max_line_len) = self.max_line_len {
+            if let Some(truncated) = truncate_lines(&out, max_line_len) {
+                out = Cow::Owned(truncated);
+            }
+        }
+        out
+    }
+}
+
+const ESC: u8 = 0x1b;
+
+/// Remove ANSI escape sequences.
+/// Operating on bytes is utf8 safe, all bytes of an escape sequence are ascii and ascii bytes never appear inside multi-byte chars.
+fn strip_ansi(buf: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buf.len());
+    let mut index = 0;
+    while index < buf.len() {
+        if buf[index] != ESC {
+            out.push(buf[index]);
+            index += 1;
+            continue;
+        }
+        index += 1;
+        match buf.get(index) {
+            // CSI sequence, e.g. colors: ESC [ <params> <final byte in 0x40..=0x7E>
+            Some(b'[') => {
+                index += 1;
+                while index < buf.len() && !(0x40..=0x7E).contains(&buf[index]) {
+                    index += 1;
+                }
+                // Skip the final byte:
+                index += 1;
+            }
+            // OSC sequence, e.g. hyperlinks: ESC ] ... terminated by BEL or ESC \
+            Some(b']') => {
+                index += 1;
+                while index < buf.len() {
+                    if buf[index] == 0x07 {
+                        index += 1;
+                        break;
+                    }
+                    if buf[index] == ESC && buf.get(index + 1) == Some(&b'\\') {
+                        index += 2;
+                        break;
+                    }
+                    index += 1;
+                }
+            }
+            // Other 2 byte escape sequences:
+            Some(_) => index += 1,
+            None => {}
+        }
+    }
+    out
+}
+
+/// Truncate all lines longer than max_len chars, returning None when no line needed truncating.
+fn truncate_lines(buf: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    // Quick exit, can't contain a line with more chars than total bytes:
+    if buf.len() <= max_len {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(buf);
+    let mut truncated_any = false;
+    let mut out = String::with_capacity(buf.len().min(max_len * 2));
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        // Find the byte index of the char at max_len, if it exists the line is too long:
+        if let Some((cut_at, _)) = content.char_indices().nth(max_len) {
+            let remaining = content[cut_at..].chars().count();
+            out.push_str(&content[..cut_at]);
+            out.push_str(&format!("…(+{} chars)", remaining));
+            truncated_any = true;
+        } else {
+            out.push_str(content);
+        }
+        out.push_str(ending);
+    }
+
+    if truncated_any {
+        Some(out.into_bytes())
+    } else {
+        None
+    }
+}
+
+/// Wraps a [`tracing_subscriber::fmt::MakeWriter`], applying [`SanitizeOpts`] to each formatted log before writing.
+pub struct SanitizingMakeWriter<M> {
+    inner: M,
+    opts: SanitizeOpts,
+}
+
+impl<M> SanitizingMakeWriter<M> {
+    pub fn new(inner: M, opts: SanitizeOpts) -> Self {
+        Self { inner, opts }
+    }
+}
+
+pub struct SanitizingWriter<W> {
+    inner: W,
+    opts: SanitizeOpts,
+}
+
+impl<W: std::io::Write> std::io::Write for SanitizingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // The fmt layer writes each formatted event in one go, so the full event is available here:
+        self.inner.write_all(&self.opts.apply(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'writer, M> tracing_subscriber::fmt::MakeWriter<'writer> for SanitizingMakeWriter<M>
+where
+    M: tracing_subscriber::fmt::MakeWriter<'writer>,
+{
+    type Writer = SanitizingWriter<M::Writer>;
+
+    fn make_writer(&'writer self) -> Self::Writer {
+        SanitizingWriter {
+            inner: self.inner.make_writer(),
+            opts: self.opts.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case::untouched("hello world\n", false, None, "hello world\n")]
+    #[case::strip_color("\x1b[31mred\x1b[0m text\n", true, None, "red text\n")]
+    #[case::keep_color("\x1b[31mred\x1b[0m\n", false, None, "\x1b[31mred\x1b[0m\n")]
+    #[case::truncate("abcdef\n", false, Some(3), "abc…(+3 chars)\n")]
+    #[case::truncate_multibyte("ééééé", false, Some(2), "éé…(+3 chars)")]
+    #[case::truncate_per_line("ab\nabcd\n", false, Some(3), "ab\nabc…(+1 chars)\n")]
+    #[case::both("\x1b[1mabcdef\x1b[0m", true, Some(4), "abcd…(+2 chars)")]
+    fn test_sanitize(
+        #[case] input: &str,
+        #[case] strip_ansi: bool,
+        #[case] max_line_len: Option<usize>,
+        #[case] expected: &str,
+    ) {
+        let opts = SanitizeOpts {
+            strip_ansi,
+            max_line_len,
+        };
+        assert_eq!(
+            String::from_utf8(opts.apply(input.as_bytes()).to_vec()).unwrap(),
+            expected
+        );
+    }
+}
@@ -1,8 +1,21 @@
 use tracing::{Dispatch, Level, Metadata, Subscriber};
-use tracing_subscriber::{filter::FilterFn, layer::SubscriberExt, registry::LookupSpan, Layer};
-
-use super::{builder::GlobalLogBuilder, GlobalLog};
-use crate::{log::global_log::event_formatter::CustEventFormatter, prelude::*};
+use tracing_subscriber::{
+    filter::FilterFn, layer::SubscriberExt, registry::LookupSpan, reload, Layer,
+};
+
+use super::{builder::GlobalLogBuilder, out::LevelSetter, GlobalLog};
+use crate::{
+    log::global_log::{
+        buffered::{BufferingMakeWriter, DEFAULT_BUFFERED_SCOPE_LIMIT},
+        dev_formatter::{DevEventFormatter, DevFields},
+        event_formatter::CustEventFormatter,
+        json_formatter::{JsonEventFormatter, JsonFields},
+        sanitizer::{SanitizeOpts, SanitizingMakeWriter},
+        span_trace::{span_trace_filter, SpanTraceLayer},
+        user_output::{is_user_event, UserLayer, UserOutput},
+    },
+    prelude::*,
+};
 
 /// Need the write trait for our write function.
 impl std::io::Write for super::builder::CustomConf {
@@ -26,11 +39,37 @@ impl<'writer> tracing_subscriber::fmt::MakeWriter<'writer> for super::builder::C
     }
 }
 
+/// A plain write fn as a tracing writer, for dev and user outputs written somewhere other than stdout.
+#[derive(Clone, Copy)]
+struct FnWriter(fn(&[u8]));
+
+impl std::io::Write for FnWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (self.0)(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'writer> tracing_subscriber::fmt::MakeWriter<'writer> for FnWriter {
+    type Writer = FnWriter;
+
+    fn make_writer(&self) -> Self::Writer {
+        *self
+    }
+}
+
 pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog, AnyErr> {
     #[cfg(windows)]
     // When on windows, this might be needed to fix colored output:
     let _ = colored::control::set_virtual_terminal(true);
 
+    let description = super::describe::LogConfigDescription::from_builder(&builder);
+    let quiet_init = builder.quiet_init;
+
     // Configure the program to automatically log panics as an error event on the current span:
     super::exceptions::auto_trace_panics();
 
@@ -55,25 +94,78 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
         }
     };
     let mut out_layers = vec![];
+    let mut level_setters: Vec<LevelSetter> = vec![];
+
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    let trace_sampling = builder.trace_sampling;
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    let metric_rules = builder.metric_rules;
+    #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
+    if !metric_rules.is_empty()
+        && !builder
+            .outputs
+            .iter()
+            .any(|output| matches!(output, super::builder::Output::Otlp(_)))
+    {
+        // Still compile to error on invalid rules, even though they'll be inactive:
+        super::event_metrics::compile_rules(metric_rules.clone(), &otlp_providers.meter_provider)?;
+        tracing::debug!(
+            "Event metric rules configured without an otlp output, they will be inactive."
+        );
+    }
 
     #[cfg(not(target_arch = "wasm32"))]
     let mut guards = vec![];
 
+    let buffer_limit = builder
+        .buffered_scope_limit
+        .unwrap_or(DEFAULT_BUFFERED_SCOPE_LIMIT);
+
+    // Spans are recorded for exception span traces up to the most verbose output, following any level changes:
+    if let Some(level_from) = builder
+        .outputs
+        .iter()
+        .map(|output| output.shared_opts().level_from)
+        .max()
+    {
+        let (filter, handle) = reload::Layer::new(span_trace_filter(level_from));
+        level_setters.push(Box::new(move |level| {
+            handle
+                .reload(span_trace_filter(level))
+                .change_context(AnyErr)
+        }));
+        out_layers.push(SpanTraceLayer.with_filter(filter).boxed());
+    }
+
     for output in builder.outputs {
         macro_rules! add_layer {
             ($shared:expr, $layer:expr) => {
-                // Now add the filtering for the layer:
-                out_layers.push(
-                    $layer
-                        .with_filter(filter_layer(
-                            $shared.level_from.clone(),
+                // Now add the filtering for the layer, reloadable to allow changing the level at runtime:
+                let (filter, handle) = reload::Layer::new(filter_layer(
+                    $shared.level_from.clone(),
+                    $shared.exclude_user,
+                    #[cfg(feature = "log-filter")]
+                    $shared.loc_matcher.clone(),
+                    #[cfg(feature = "log-filter")]
+                    &all_loc_matchers,
+                )?);
+                #[cfg(feature = "log-filter")]
+                let (loc_matcher, all_loc_matchers) =
+                    ($shared.loc_matcher.clone(), all_loc_matchers.clone());
+                let exclude_user = $shared.exclude_user;
+                level_setters.push(Box::new(move |level| {
+                    handle
+                        .reload(filter_layer(
+                            level,
+                            exclude_user,
                             #[cfg(feature = "log-filter")]
-                            $shared.loc_matcher.clone(),
+                            loc_matcher.clone(),
                             #[cfg(feature = "log-filter")]
                             &all_loc_matchers,
                         )?)
-                        .boxed(),
-                );
+                        .change_context(AnyErr)
+                }));
+                out_layers.push($layer.with_filter(filter).boxed());
             };
         }
 
@@ -84,10 +176,25 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
                 {
                     let (writer, _guard) = tracing_appender::non_blocking(std::io::stdout());
                     guards.push(_guard);
-                    add_layer!(
-                        stdout.shared,
-                        create_fmt_layer(stdout.pretty, false, stdout.include_loc, true, writer)?
-                    );
+                    if stdout.json {
+                        add_layer!(
+                            stdout.shared,
+                            create_json_layer(stdout.sanitize, buffer_limit, writer)
+                        );
+                    } else {
+                        add_layer!(
+                            stdout.shared,
+                            create_fmt_layer(
+                                stdout.pretty,
+                                false,
+                                stdout.include_loc,
+                                true,
+                                stdout.sanitize,
+                                buffer_limit,
+                                writer
+                            )?
+                        );
+                    }
                 };
 
                 // When web:
@@ -98,18 +205,57 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
                 {
                     use tracing_subscriber_wasm::MakeConsoleWriter;
 
-                    add_layer!(
-                        stdout.shared,
-                        create_fmt_layer(
-                            stdout.pretty,
-                            false,
-                            stdout.include_loc,
-                            false,
-                            MakeConsoleWriter::default()
-                        )?
-                    );
+                    if stdout.json {
+                        add_layer!(
+                            stdout.shared,
+                            create_json_layer(
+                                stdout.sanitize,
+                                buffer_limit,
+                                MakeConsoleWriter::default()
+                            )
+                        );
+                    } else {
+                        add_layer!(
+                            stdout.shared,
+                            create_fmt_layer(
+                                stdout.pretty,
+                                false,
+                                stdout.include_loc,
+                                false,
+                                stdout.sanitize,
+                                buffer_limit,
+                                MakeConsoleWriter::default()
+                            )?
+                        );
+                    }
                 };
             }
+            #[cfg(target_arch = "wasm32")]
+            super::builder::Output::Dev(_) => {
+                return Err(anyerr!("Dev logging not supported in wasm."));
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            super::builder::Output::Dev(dev) => {
+                use std::io::IsTerminal;
+
+                // Only for humans watching a terminal unless forced, so it can be left configured everywhere:
+                if dev.force || std::io::stdout().is_terminal() {
+                    let fields = DevFields::new(dev.fields, true);
+                    if let Some(write) = dev.write {
+                        add_layer!(
+                            dev.shared,
+                            create_dev_layer(fields, dev.sanitize, buffer_limit, FnWriter(write))
+                        );
+                    } else {
+                        let (writer, _guard) = tracing_appender::non_blocking(std::io::stdout());
+                        guards.push(_guard);
+                        add_layer!(
+                            dev.shared,
+                            create_dev_layer(fields, dev.sanitize, buffer_limit, writer)
+                        );
+                    }
+                }
+            }
             // File obvs can't be written in wasm, excluding to keep tracing_appender out of build etc.
             #[cfg(target_arch = "wasm32")]
             super::builder::Output::File(_) => {
@@ -135,23 +281,45 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
                 let (writer, _guard) = tracing_appender::non_blocking(file_appender);
                 guards.push(_guard);
 
-                add_layer!(
-                    file.shared,
-                    create_fmt_layer(false, true, true, false, writer,)?
-                );
+                if file.json {
+                    add_layer!(
+                        file.shared,
+                        create_json_layer(file.sanitize, buffer_limit, writer)
+                    );
+                } else {
+                    add_layer!(
+                        file.shared,
+                        create_fmt_layer(
+                            false,
+                            true,
+                            true,
+                            false,
+                            file.sanitize,
+                            buffer_limit,
+                            writer
+                        )?
+                    );
+                }
             }
             super::builder::Output::Custom(custom) => {
                 let shared = custom.shared.clone();
-                add_layer!(
-                    shared,
-                    create_fmt_layer(
-                        custom.pretty,
-                        custom.include_ts,
-                        custom.include_loc,
-                        custom.include_color,
-                        custom,
-                    )?
-                );
+                let sanitize = custom.sanitize.clone();
+                if custom.json {
+                    add_layer!(shared, create_json_layer(sanitize, buffer_limit, custom));
+                } else {
+                    add_layer!(
+                        shared,
+                        create_fmt_layer(
+                            custom.pretty,
+                            custom.include_ts,
+                            custom.include_loc,
+                            custom.include_color,
+                            sanitize,
+                            buffer_limit,
+                            custom,
+                        )?
+                    );
+                }
             }
             #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
             super::builder::Output::Otlp(otlp) => {
@@ -279,17 +447,34 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
                     otlp_providers.logger_provider = Some(logging_provider.clone());
                     add_layer!(otlp.shared, log_layer);
 
-                    let tracer = new_pipeline()
-                        .tracing()
-                        .with_trace_config(
-                            sdktrace::Config::default().with_resource(resource.clone()),
+                    // Built manually rather than with the pipeline, to wrap the batch processor for sampling:
+                    let batch_processor = sdktrace::BatchSpanProcessor::builder(
+                        trace_exporter
+                            .build_span_exporter()
+                            .change_context(AnyErr)?,
+                        opentelemetry_sdk::runtime::Tokio,
+                    )
+                    .build();
+                    let tracing_provider = sdktrace::TracerProvider::builder()
+                        .with_span_processor(super::trace_sampling::ErrorRetainingProcessor::new(
+                            batch_processor,
+                        ))
+                        .with_config(
+                            sdktrace::Config::default()
+                                .with_resource(resource.clone())
+                                .with_sampler(super::trace_sampling::ErrorRetainingSampler::new(
+                                    trace_sampling.clone(),
+                                )),
                         )
-                        .with_exporter(trace_exporter)
-                        .install_batch(opentelemetry_sdk::runtime::Tokio)
-                        .change_context(AnyErr)?;
-                    let tracing_provider = tracer
-                        .provider()
-                        .ok_or_else(|| anyerr!("No tracing provider attached."))?;
+                        .build();
+                    let tracer = opentelemetry::trace::TracerProvider::versioned_tracer(
+                        &tracing_provider,
+                        "bitbazaar",
+                        Some(env!("CARGO_PKG_VERSION")),
+                        Some(opentelemetry_semantic_conventions::SCHEMA_URL),
+                        None,
+                    );
+                    let _ = opentelemetry::global::set_tracer_provider(tracing_provider.clone());
                     let trace_layer = tracing_opentelemetry::layer().with_tracer(tracer);
                     otlp_providers.tracer_provider = Some(tracing_provider);
                     add_layer!(otlp.shared, trace_layer);
@@ -303,18 +488,69 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
                     let metric_layer: tracing_opentelemetry::MetricsLayer<
                         tracing_subscriber::Registry,
                     > = tracing_opentelemetry::MetricsLayer::new(meter_provider.clone());
-                    otlp_providers.meter_provider = meter_provider;
                     add_layer!(otlp.shared, metric_layer);
+
+                    // Event metric rules do their own level filtering, so not using the output's filter:
+                    if !metric_rules.is_empty() {
+                        out_layers.push(
+                            super::event_metrics::EventMetricsLayer::new(
+                                super::event_metrics::compile_rules(
+                                    metric_rules.clone(),
+                                    &meter_provider,
+                                )?,
+                            )
+                            .boxed(),
+                        );
+                    }
+                    otlp_providers.meter_provider = meter_provider;
                 }
             }
         };
     }
 
+    // User messages ignore the level and filters of the outputs, as shown to the user rather than for diagnostics:
+    match builder.user_output {
+        #[cfg(not(target_arch = "wasm32"))]
+        Some(UserOutput::Stdout) => {
+            // Blocking, so messages are shown in order with anything else the program prints:
+            out_layers.push(
+                UserLayer::new(std::io::stdout, true)
+                    .with_filter(FilterFn::new(is_user_event))
+                    .boxed(),
+            );
+        }
+        // No color to match the stdout output on web:
+        #[cfg(target_arch = "wasm32")]
+        Some(UserOutput::Stdout) => {
+            out_layers.push(
+                UserLayer::new(tracing_subscriber_wasm::MakeConsoleWriter::default(), false)
+                    .with_filter(FilterFn::new(is_user_event))
+                    .boxed(),
+            );
+        }
+        Some(UserOutput::Custom(write)) => {
+            out_layers.push(
+                UserLayer::new(FnWriter(write), true)
+                    .with_filter(FilterFn::new(is_user_event))
+                    .boxed(),
+            );
+        }
+        Some(UserOutput::Suppress) | None => {}
+    }
+
     // Combine the layers into the final subscriber:
     let subscriber = tracing_subscriber::registry().with(out_layers);
     let dispatch: Dispatch = subscriber.into();
+
+    // Make it obvious from the logs alone what this process was logging to:
+    if !quiet_init {
+        tracing::dispatcher::with_default(&dispatch, || description.emit());
+    }
+
     Ok(GlobalLog {
         dispatch: Some(dispatch),
+        level_setters,
+        description,
         #[cfg(not(target_arch = "wasm32"))]
         _guards: guards,
         #[cfg(any(feature = "opentelemetry-grpc", feature = "opentelemetry-http"))]
@@ -324,6 +560,7 @@ pub fn builder_into_global_log(builder: GlobalLogBuilder) -> RResult<GlobalLog,
 
 fn filter_layer(
     level_from: Level,
+    exclude_user: bool,
     #[cfg(feature = "log-filter")] loc_matcher: Option<regex::Regex>,
     #[cfg(feature = "log-filter")] all_loc_matchers: &[regex::Regex],
 ) -> Result<FilterFn<impl Fn(&Metadata<'_>) -> bool>, AnyErr> {
@@ -337,6 +574,10 @@ fn filter_layer(
             return false;
         }
 
+        if exclude_user && is_user_event(metadata) {
+            return false;
+        }
+
         #[cfg(feature = "log-filter")]
         // Check loc matching:
         if let Some(file_info) = metadata.file() {
@@ -360,11 +601,52 @@ fn filter_layer(
     }))
 }
 
+fn create_dev_layer<S, W>(
+    fields: DevFields,
+    sanitize: SanitizeOpts,
+    buffer_limit: usize,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + Send + Sync + 'static,
+    for<'a> S: LookupSpan<'a>,
+    W: for<'writer> tracing_subscriber::fmt::MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let writer =
+        BufferingMakeWriter::new(SanitizingMakeWriter::new(writer, sanitize), buffer_limit);
+    tracing_subscriber::fmt::layer()
+        .fmt_fields(fields.clone())
+        .event_format(DevEventFormatter::new(fields))
+        .with_writer(writer)
+        .boxed()
+}
+
+fn create_json_layer<S, W>(
+    sanitize: SanitizeOpts,
+    buffer_limit: usize,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + Send + Sync + 'static,
+    for<'a> S: LookupSpan<'a>,
+    W: for<'writer> tracing_subscriber::fmt::MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let writer =
+        BufferingMakeWriter::new(SanitizingMakeWriter::new(writer, sanitize), buffer_limit);
+    tracing_subscriber::fmt::layer()
+        .fmt_fields(JsonFields)
+        .event_format(JsonEventFormatter)
+        .with_writer(writer)
+        .boxed()
+}
+
 fn create_fmt_layer<S, W>(
     pretty: bool,
     include_timestamp: bool,
     include_loc: bool,
     include_color: bool,
+    sanitize: SanitizeOpts,
+    buffer_limit: usize,
     writer: W,
 ) -> RResult<Box<dyn Layer<S> + Send + Sync + 'static>, AnyErr>
 where
@@ -389,6 +671,11 @@ where
         };
     }
 
+    // Sanitization happens after formatting, but before reaching the writer (which might be non-blocking),
+    // buffered scopes hold onto the formatted logs, so they keep their original timestamps if written later:
+    let writer =
+        BufferingMakeWriter::new(SanitizingMakeWriter::new(writer, sanitize), buffer_limit);
+
     macro_rules! base_layer {
         () => {
             base!(tracing_subscriber::fmt::layer()).with_writer(writer)
@@ -0,0 +1,122 @@
+use std::fmt::{Debug, Display};
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    filter::FilterFn,
+    layer::Context,
+    registry::{LookupSpan, Registry},
+    Layer,
+};
+
+/// The max number of spans included in a span trace, any further out are replaced with a marker.
+pub(crate) const MAX_SPAN_TRACE_DEPTH: usize = 16;
+
+/// The max number of fields included per span in a span trace, any more are replaced with a marker.
+pub(crate) const MAX_SPAN_TRACE_FIELDS: usize = 8;
+
+/// Records the fields of each span for [`SpanTrace`], the registry itself only knows their names.
+pub struct SpanTraceLayer;
+
+impl<S> Layer<S> for SpanTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = SpanTraceFields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanTraceFields>() {
+                values.record(fields);
+            }
+        }
+    }
+}
+
+/// Only spans are needed, up to the most verbose level of the outputs, so it doesn't enable spans nothing else would record.
+pub fn span_trace_filter(level_from: Level) -> FilterFn<impl Fn(&Metadata<'_>) -> bool> {
+    FilterFn::new(move |metadata| metadata.is_span() && *metadata.level() <= level_from)
+}
+
+#[derive(Default)]
+struct SpanTraceFields {
+    fields: Vec<String>,
+    truncated: bool,
+}
+
+impl Visit for SpanTraceFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if self.fields.len() < MAX_SPAN_TRACE_FIELDS {
+            self.fields.push(format!("{}={:?}", field.name(), value));
+        } else {
+            self.truncated = true;
+        }
+    }
+}
+
+/// The stack of spans an exception was recorded in, innermost first, each with its fields:
+///
+/// ```text
+/// 0: handler{user_id=1}
+/// 1: request{method="GET" path="/"}
+/// ```
+///
+/// Only formatted if the event is actually recorded, like the stacktrace.
+pub struct SpanTrace(tracing::Span);
+
+impl SpanTrace {
+    /// Capture the current span, None if not inside one.
+    pub fn capture() -> Option<Self> {
+        let span = tracing::Span::current();
+        if span.is_none() {
+            None
+        } else {
+            Some(Self(span))
+        }
+    }
+}
+
+impl Display for SpanTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Using the span's own dispatch, the default one isn't accessible whilst a layer is handling the event:
+        self.0
+            .with_subscriber(|(id, dispatch)| {
+                let Some(span) = dispatch
+                    .downcast_ref::<Registry>()
+                    .and_then(|registry| registry.span(id))
+                else {
+                    return Ok(());
+                };
+                let spans = span.scope().collect::<Vec<_>>();
+                for (index, span) in spans.iter().take(MAX_SPAN_TRACE_DEPTH).enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}: {}", index, span.name())?;
+                    if let Some(fields) = span.extensions().get::<SpanTraceFields>() {
+                        if !fields.fields.is_empty() {
+                            write!(f, "{{{}", fields.fields.join(" "))?;
+                            if fields.truncated {
+                                write!(f, " ...")?;
+                            }
+                            write!(f, "}}")?;
+                        }
+                    }
+                }
+                if spans.len() > MAX_SPAN_TRACE_DEPTH {
+                    write!(f, "\n... {} more", spans.len() - MAX_SPAN_TRACE_DEPTH)?;
+                }
+                Ok(())
+            })
+            .unwrap_or(Ok(()))
+    }
+}
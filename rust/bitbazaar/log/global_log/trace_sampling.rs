@@ -0,0 +1,165 @@
+use opentelemetry::{
+    trace::{
+        Link, SamplingDecision, SamplingResult, SpanContext, SpanKind, Status, TraceContextExt,
+        TraceId, TraceResult, TraceState,
+    },
+    Context, KeyValue,
+};
+use opentelemetry_sdk::{
+    export::trace::SpanData,
+    trace::{Sampler, ShouldSample, Span, SpanProcessor},
+};
+
+/// The attribute tracing-opentelemetry sets to the module path of each span, matched against by [`Sampling::PerTarget`].
+const TARGET_ATTR: &str = "code.namespace";
+
+/// Head-based sampling of otlp traces, see [`super::GlobalLogBuilder::trace_sampling`].
+///
+/// Whatever the decision, spans that record an ERROR event (including [`crate::log::record_exception`]) are always exported.
+/// Logs and metrics are never sampled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Sampling {
+    /// Export every trace, the default.
+    #[default]
+    Always,
+    /// Export this fraction of traces, from 0.0 to 1.0, decided per trace so a trace is never partially exported.
+    Ratio(f64),
+    /// Like [`Sampling::Ratio`], but by target prefix (e.g. `("my_app::db", 0.01)`), the longest matching prefix wins.
+    /// Spans are matched by their module path, which is the target unless overridden.
+    /// Spans matching no prefix are always exported.
+    PerTarget(Vec<(String, f64)>),
+    /// Follow the decision of the span's parent when it has one (e.g. extracted from an incoming request's headers),
+    /// otherwise decide with the inner sampling.
+    ParentBased(Box<Sampling>),
+}
+
+impl Sampling {
+    fn decide(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        attributes: &[KeyValue],
+    ) -> SamplingDecision {
+        let ratio = match self {
+            Sampling::Always => return SamplingDecision::RecordAndSample,
+            Sampling::Ratio(ratio) => *ratio,
+            Sampling::PerTarget(ratios) => {
+                let target = attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == TARGET_ATTR)
+                    .map(|kv| kv.value.as_str());
+                match target.and_then(|target| {
+                    ratios
+                        .iter()
+                        .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+                        .max_by_key(|(prefix, _)| prefix.len())
+                }) {
+                    Some((_, ratio)) => *ratio,
+                    None => return SamplingDecision::RecordAndSample,
+                }
+            }
+            Sampling::ParentBased(inner) => {
+                return match parent_context.filter(|cx| cx.has_active_span()) {
+                    Some(cx) if cx.span().span_context().is_sampled() => {
+                        SamplingDecision::RecordAndSample
+                    }
+                    Some(_) => SamplingDecision::Drop,
+                    None => inner.decide(parent_context, trace_id, attributes),
+                }
+            }
+        };
+        Sampler::TraceIdRatioBased(ratio)
+            .should_sample(parent_context, trace_id, "", &SpanKind::Internal, &[], &[])
+            .decision
+    }
+}
+
+/// The sampler given to the tracer provider.
+///
+/// Spans the [`Sampling`] would drop are still recorded rather than dropped (but not marked as sampled),
+/// so [`ErrorRetainingProcessor`] can export them if they turn out to contain an error.
+/// The cost is recording the data of every span, rather than only the sampled ones.
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorRetainingSampler {
+    sampling: Sampling,
+}
+
+impl ErrorRetainingSampler {
+    pub fn new(sampling: Sampling) -> Self {
+        Self { sampling }
+    }
+}
+
+impl ShouldSample for ErrorRetainingSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        _name: &str,
+        _span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let decision = match self.sampling.decide(parent_context, trace_id, attributes) {
+            SamplingDecision::Drop => SamplingDecision::RecordOnly,
+            decision => decision,
+        };
+        SamplingResult {
+            decision,
+            attributes: vec![],
+            trace_state: match parent_context {
+                Some(cx) => cx.span().span_context().trace_state().clone(),
+                None => TraceState::default(),
+            },
+        }
+    }
+}
+
+/// Wraps the exporting processor, marking recorded but unsampled spans as sampled when they contain an error,
+/// the inner processor only exports sampled spans so the rest are still dropped.
+///
+/// Only the erroring span itself is retained, its unsampled parents and children aren't.
+#[derive(Debug)]
+pub(crate) struct ErrorRetainingProcessor<P: SpanProcessor> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> ErrorRetainingProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ErrorRetainingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx)
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        if !span.span_context.is_sampled() && has_error(&span) {
+            let cx = &span.span_context;
+            span.span_context = SpanContext::new(
+                cx.trace_id(),
+                cx.span_id(),
+                cx.trace_flags().with_sampled(true),
+                cx.is_remote(),
+                cx.trace_state().clone(),
+            );
+        }
+        self.inner.on_end(span)
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// tracing-opentelemetry sets the status to error for ERROR events, exceptions are also ERROR events but checking by name too to be safe.
+fn has_error(span: &SpanData) -> bool {
+    matches!(span.status, Status::Error { .. })
+        || span.events.iter().any(|event| event.name == "exception")
+}
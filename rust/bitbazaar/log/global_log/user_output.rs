@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use tracing::{field::Visit, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::{fmt::MakeWriter, layer::Context, Layer};
+
+/// The reserved field marking an event as a user-facing message, set by [`crate::user_msg`], [`crate::user_warn`] and [`crate::user_err`].
+pub const USER_FIELD: &str = "bitbazaar.user";
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+
+/// Where user-facing messages are written, see [`super::GlobalLogBuilder::user_output`].
+#[derive(Debug, Clone, Copy)]
+pub enum UserOutput {
+    /// Colored on native, written to the web console on wasm (uncolored, warnings and errors use `console.warn`/`console.error`).
+    Stdout,
+    /// The fn to handle writing, passed each rendered message (colored) as the raw byte string.
+    Custom(fn(&[u8])),
+    /// Not written anywhere, e.g. for a `--quiet` flag decided at runtime.
+    Suppress,
+}
+
+/// Whether the event was created by one of the user message macros.
+pub fn is_user_event(metadata: &Metadata<'_>) -> bool {
+    metadata.is_event() && metadata.fields().field(USER_FIELD).is_some()
+}
+
+/// Renders user messages only, without timestamps, targets or fields:
+///
+/// - `message` from [`crate::user_msg`].
+/// - `warning: message` from [`crate::user_warn`], the prefix yellow.
+/// - `error: message` from [`crate::user_err`], the prefix red.
+pub struct UserLayer<W> {
+    writer: W,
+    include_color: bool,
+}
+
+impl<W> UserLayer<W> {
+    pub fn new(writer: W, include_color: bool) -> Self {
+        Self {
+            writer,
+            include_color,
+        }
+    }
+
+    fn render(&self, level: &Level, message: &str) -> String {
+        let prefix = match *level {
+            Level::ERROR => Some((RED, "error:")),
+            Level::WARN => Some((YELLOW, "warning:")),
+            _ => None,
+        };
+        match prefix {
+            Some((color, prefix)) if self.include_color => {
+                format!("{}{}{} {}\n", color, prefix, RESET, message)
+            }
+            Some((_, prefix)) => format!("{} {}\n", prefix, message),
+            None => format!("{}\n", message),
+        }
+    }
+}
+
+impl<S, W> Layer<S> for UserLayer<W>
+where
+    S: Subscriber,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let rendered = self.render(event.metadata().level(), &visitor.message);
+        let mut writer = self.writer.make_writer_for(event.metadata());
+        // Nowhere to report a failed write to the user:
+        let _ = writer.write_all(rendered.as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        }
+    }
+}
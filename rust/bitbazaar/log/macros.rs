@@ -33,3 +33,30 @@ macro_rules! warn_user_once {
         }
     };
 }
+
+/// Emit a user-facing message, e.g. progress/status like "Uploading 3 files...", distinct from diagnostic logs.
+///
+/// An INFO event marked with the reserved [`crate::log::USER_FIELD`], rendered cleanly by [`crate::log::GlobalLogBuilder::user_output`].
+/// Takes the same args as [`tracing::info!`], only the message is rendered.
+#[macro_export]
+macro_rules! user_msg {
+    ($($arg:tt)*) => {
+        $crate::log::_tracing::info!(bitbazaar.user = true, $($arg)*)
+    };
+}
+
+/// [`crate::user_msg`] as a WARN event, rendered with a `warning:` prefix.
+#[macro_export]
+macro_rules! user_warn {
+    ($($arg:tt)*) => {
+        $crate::log::_tracing::warn!(bitbazaar.user = true, $($arg)*)
+    };
+}
+
+/// [`crate::user_msg`] as an ERROR event, rendered with an `error:` prefix.
+#[macro_export]
+macro_rules! user_err {
+    ($($arg:tt)*) => {
+        $crate::log::_tracing::error!(bitbazaar.user = true, $($arg)*)
+    };
+}
@@ -314,22 +314,25 @@ pub fn init_system_and_process_metrics(meter: &Meter) -> RResult<(), AnyErr> {
                     );
                 }
 
-                // Refresh the process for the specific metrics:
-                sys.refresh_process(pid);
-                if let Some(process) = sys.process(pid) {
-                    // process.cpu.utilization
-                    context.observe_f64(
-                        &process_cpu_utilization,
-                        ((process.cpu_usage() / 100.0) / logical_core_count as f32).into(),
-                        &[],
-                    );
+                // Shared with crate::misc::process_info() so health endpoints report the same numbers:
+                let process_info = crate::misc::process_info();
+                // process.cpu.utilization (zero on the very first observation, nothing to compare against yet):
+                let cpu_percent = process_info.cpu_percent_since_last_call.unwrap_or(0.0);
+                context.observe_f64(
+                    &process_cpu_utilization,
+                    (cpu_percent / 100.0) / logical_core_count as f64,
+                    &[],
+                );
 
-                    // process.memory.usage
-                    context.observe_u64(&process_memory_usage, process.memory(), &[]);
+                // process.memory.usage
+                context.observe_u64(&process_memory_usage, process_info.rss_bytes, &[]);
 
-                    // process.memory.virtual
-                    context.observe_u64(&process_memory_virtual, process.virtual_memory(), &[]);
+                // process.memory.virtual
+                context.observe_u64(&process_memory_virtual, process_info.virtual_bytes, &[]);
 
+                // Disk io isn't in the process info, refresh the process for it:
+                sys.refresh_process(pid);
+                if let Some(process) = sys.process(pid) {
                     // - process.disk.io
                     let disk_io = process.disk_usage();
                     context.observe_u64(
@@ -0,0 +1,351 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::{
+    hash::fnv1a,
+    prelude::*,
+    redis::{Redis, RedisBatchFire, RedisChannelListener, RedisJsonBorrowed},
+};
+
+/// The key of the redis hash holding all flags, under the configured namespace.
+const FLAGS_KEY: &str = "feature_flags";
+
+/// Total number of buckets subjects are split into, allows rollouts down to 0.01%.
+const BUCKETS: u64 = 10_000;
+
+/// The config of a single feature flag, stored as json in a redis hash.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FeatureFlag {
+    /// Master switch, when false the flag is off for everyone.
+    pub enabled: bool,
+    /// The percentage (0-100) of subjects the flag is on for in [`FeatureFlags::enabled_for`], `None` meaning everyone.
+    #[serde(default)]
+    pub rollout_percent: Option<f64>,
+    /// Weighted json payloads for multivariate flags, picked between deterministically per subject in [`FeatureFlags::variant`].
+    #[serde(default)]
+    pub variants: Vec<(u32, serde_json::Value)>,
+}
+
+impl FeatureFlag {
+    /// A simple on/off flag.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            rollout_percent: None,
+            variants: vec![],
+        }
+    }
+
+    /// A flag on for the given percentage (0-100) of subjects.
+    pub fn rollout(percent: f64) -> Self {
+        Self {
+            enabled: true,
+            rollout_percent: Some(percent),
+            variants: vec![],
+        }
+    }
+
+    /// Add a weighted variant payload to the flag.
+    pub fn with_variant(mut self, weight: u32, value: serde_json::Value) -> Self {
+        self.variants.push((weight, value));
+        self
+    }
+}
+
+/// A change announced over pubsub by [`FeatureFlags::set_flag`] and [`FeatureFlags::remove_flag`], `config` is None when removed.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct FlagAnnouncement {
+    flag: String,
+    config: Option<FeatureFlag>,
+}
+
+struct Shared {
+    redis: Redis,
+    namespace: &'static str,
+    refresh_every: Duration,
+    flags: RwLock<HashMap<String, FeatureFlag>>,
+}
+
+impl Shared {
+    /// Replace the snapshot with all flags from redis, keeping the previous one if redis is unavailable.
+    async fn poll(&self) {
+        let mut conn = self.redis.conn();
+        let final_key = conn.final_key(self.namespace, FLAGS_KEY.into());
+        let Some(inner) = conn.get_inner_conn().await else {
+            return;
+        };
+        let raw = match redis::cmd("HGETALL")
+            .arg(final_key)
+            .query_async::<_, HashMap<String, String>>(inner)
+            .await
+        {
+            Ok(raw) => raw,
+            Err(e) => {
+                tracing::error!("Failed to refresh feature flags: {}", e);
+                return;
+            }
+        };
+        *self.flags.write() = raw
+            .into_iter()
+            .filter_map(
+                |(flag, encoded)| match serde_json::from_str::<FeatureFlag>(&encoded) {
+                    Ok(config) => Some((flag, config)),
+                    Err(e) => {
+                        tracing::error!("Feature flag '{}' couldn't be decoded: {}", flag, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+    }
+
+    fn announced(&self, announcement: FlagAnnouncement) {
+        let mut flags = self.flags.write();
+        match announcement.config {
+            Some(config) => flags.insert(announcement.flag, config),
+            None => flags.remove(&announcement.flag),
+        };
+    }
+}
+
+/// Typed feature flag evaluation, backed by a redis hash.
+///
+/// Checks are served from a local snapshot of all flags and never touch redis.
+/// The snapshot is updated immediately by the pubsub announcement of each [`FeatureFlags::set_flag`] and [`FeatureFlags::remove_flag`],
+/// and re-read from redis every `refresh_every` in case an announcement was missed.
+/// If redis is unavailable, the last snapshot continues to be used.
+///
+/// Unknown flags are always off.
+///
+/// The background refresh task is stopped when the flags are dropped.
+pub struct FeatureFlags {
+    shared: Arc<Shared>,
+    task: tokio::task::JoinHandle<()>,
+    logged_unknown: Mutex<HashSet<String>>,
+}
+
+impl FeatureFlags {
+    /// Create a new feature flag evaluator.
+    ///
+    /// Returns once the flags have been read from redis (or failed to be), so checks are accurate straight away.
+    /// Must be called from within a tokio runtime.
+    ///
+    /// Arguments:
+    /// - `redis`: The redis wrapper the flags are stored in.
+    /// - `namespace`: The redis namespace the flags are stored under.
+    /// - `refresh_every`: How often the flags are re-read from redis, so changes converge even if their announcement was missed.
+    pub async fn new(redis: Redis, namespace: &'static str, refresh_every: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            redis,
+            namespace,
+            refresh_every,
+            flags: RwLock::new(HashMap::new()),
+        });
+        // Subscribe before the first read so changes in between aren't missed:
+        let listener = shared
+            .redis
+            .subscribe::<FlagAnnouncement>(namespace, FLAGS_KEY)
+            .await;
+        shared.poll().await;
+        let task = tokio::spawn(refresh_loop(shared.clone(), listener));
+        Self {
+            shared,
+            task,
+            logged_unknown: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// True if the flag is enabled. Percentage rollouts are ignored, use [`FeatureFlags::enabled_for`] for those.
+    pub fn enabled(&self, flag: &str) -> bool {
+        self.get_flag(flag).is_some_and(|flag| flag.enabled)
+    }
+
+    /// True if the flag is enabled for the given subject (e.g. a user id).
+    /// Percentage rollouts are deterministic, the same subject will always get the same result for a flag.
+    pub fn enabled_for(&self, flag: &str, subject_id: &str) -> bool {
+        if let Some(config) = self.get_flag(flag) {
+            if !config.enabled {
+                return false;
+            }
+            match config.rollout_percent {
+                Some(percent) => {
+                    (subject_bucket(flag, subject_id) as f64) < (percent / 100.0) * BUCKETS as f64
+                }
+                None => true,
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Get the subject's variant payload for a multivariate flag.
+    /// Variants are picked deterministically by weight, the same subject will always get the same variant.
+    ///
+    /// Returns `None` if the flag isn't enabled for the subject, has no variants, or the payload doesn't decode to `T`.
+    pub fn variant<T: serde::de::DeserializeOwned>(
+        &self,
+        flag: &str,
+        subject_id: &str,
+    ) -> Option<T> {
+        if !self.enabled_for(flag, subject_id) {
+            return None;
+        }
+        let config = self.get_flag(flag)?;
+        let total_weight: u64 = config.variants.iter().map(|(w, _)| *w as u64).sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        // Different salt to the rollout bucketing, so variant isn't correlated with being in the rollout:
+        let mut pick = fnv1a(format!("{}:variant:{}", subject_id, flag).as_bytes()) % total_weight;
+        for (weight, value) in config.variants {
+            if pick < weight as u64 {
+                return match serde_json::from_value(value) {
+                    Ok(value) => Some(value),
+                    Err(e) => {
+                        tracing::error!(
+                            "Feature flag '{}' variant couldn't be decoded: {}",
+                            flag,
+                            e
+                        );
+                        None
+                    }
+                };
+            }
+            pick -= weight as u64;
+        }
+        None
+    }
+
+    /// Create or update a flag, announcing it over pubsub. This is immediately reflected locally.
+    pub async fn set_flag(&self, flag: &str, config: FeatureFlag) -> RResult<(), AnyErr> {
+        self.write(flag, Some(config))
+            .await
+            .ok_or_else(|| anyerr!("Redis unavailable, couldn't set feature flag '{}'.", flag))
+    }
+
+    /// Remove a flag, meaning it will be off for everyone, announcing it over pubsub. This is immediately reflected locally.
+    pub async fn remove_flag(&self, flag: &str) -> RResult<(), AnyErr> {
+        self.write(flag, None).await.ok_or_else(|| {
+            anyerr!(
+                "Redis unavailable, couldn't remove feature flag '{}'.",
+                flag
+            )
+        })
+    }
+
+    /// Force a re-read of the flags from redis, rather than waiting for the next poll.
+    /// If redis is unavailable, the previous snapshot is kept.
+    pub async fn refresh(&self) {
+        self.shared.poll().await;
+    }
+
+    /// Write the flag to the hash and announce it, None if redis is unavailable.
+    async fn write(&self, flag: &str, config: Option<FeatureFlag>) -> Option<()> {
+        let announcement = FlagAnnouncement {
+            flag: flag.to_string(),
+            config,
+        };
+        let encoded = match serde_json::to_vec(&announcement) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                tracing::error!("Feature flag '{}' couldn't be encoded: {}", flag, e);
+                return None;
+            }
+        };
+        let mut conn = self.shared.redis.conn();
+        let batch = match &announcement.config {
+            Some(config) => conn.batch().hset_multi(
+                self.shared.namespace,
+                FLAGS_KEY,
+                None,
+                [(flag, RedisJsonBorrowed(config))],
+            ),
+            None => conn.batch().hdel(self.shared.namespace, FLAGS_KEY, [flag]),
+        };
+        batch
+            .publish(self.shared.namespace, FLAGS_KEY, encoded)
+            .fire()
+            .await?;
+        self.shared.announced(announcement);
+        Some(())
+    }
+
+    fn get_flag(&self, flag: &str) -> Option<FeatureFlag> {
+        let config = self.shared.flags.read().get(flag).cloned();
+        if config.is_none() && self.logged_unknown.lock().insert(flag.to_string()) {
+            tracing::debug!("Unknown feature flag '{}', treating as off.", flag);
+        }
+        config
+    }
+}
+
+impl Drop for FeatureFlags {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Apply announcements as they arrive, polling every `refresh_every` regardless, resubscribing if the subscription was lost.
+async fn refresh_loop(
+    shared: Arc<Shared>,
+    mut listener: Option<RedisChannelListener<FlagAnnouncement>>,
+) {
+    let mut next_poll = tokio::time::Instant::now() + shared.refresh_every;
+    loop {
+        if let Some(active) = listener.as_mut() {
+            match tokio::time::timeout_at(next_poll, active.recv()).await {
+                Ok(Some(announcement)) => {
+                    shared.announced(announcement);
+                    continue;
+                }
+                Ok(None) => {
+                    listener = None;
+                    continue;
+                }
+                // Time to poll:
+                Err(_) => {}
+            }
+        } else {
+            tokio::time::sleep_until(next_poll).await;
+            listener = shared
+                .redis
+                .subscribe::<FlagAnnouncement>(shared.namespace, FLAGS_KEY)
+                .await;
+        }
+        shared.poll().await;
+        next_poll = tokio::time::Instant::now() + shared.refresh_every;
+    }
+}
+
+/// The deterministic bucket (0..BUCKETS) a subject falls into for a flag.
+fn subject_bucket(flag: &str, subject_id: &str) -> u64 {
+    fnv1a(format!("{}{}", subject_id, flag).as_bytes()) % BUCKETS
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_feature_flag_bucketing() {
+        // Deterministic:
+        assert_eq!(
+            subject_bucket("flag", "user_1"),
+            subject_bucket("flag", "user_1")
+        );
+
+        // Roughly uniform, 25% of buckets should be below a quarter of the total:
+        let below = (0..10_000)
+            .filter(|i| subject_bucket("flag", &format!("user_{}", i)) < BUCKETS / 4)
+            .count();
+        assert!((2_200..2_800).contains(&below), "{}", below);
+    }
+}
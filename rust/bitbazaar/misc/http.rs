@@ -0,0 +1,562 @@
+use std::time::Duration;
+
+use error_stack::Context;
+use tracing::Instrument;
+
+use super::{retry_backoff, with_timeout};
+use crate::prelude::*;
+
+/// The max length of a non-2xx response body kept in [`HttpErr::Status`], longer bodies are truncated.
+const MAX_ERR_BODY_LEN: usize = 1000;
+
+/// Errors from [`HttpClient`] requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpErr {
+    /// The request couldn't be sent or its response couldn't be read, e.g. the connection was refused.
+    Transport,
+    /// The request didn't finish within [`HttpOpts::timeout`].
+    Timeout,
+    /// The server responded with a non-2xx status.
+    Status {
+        /// The response's status code.
+        status: u16,
+        /// The response's body, truncated if long.
+        body: String,
+    },
+    /// The request body couldn't be encoded, or the response body couldn't be decoded as the expected json.
+    Decode,
+}
+
+impl std::fmt::Display for HttpErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpErr::Transport => write!(f, "Http transport error"),
+            HttpErr::Timeout => write!(f, "Http request timed out"),
+            HttpErr::Status { status, body } => write!(f, "Http status {}: {}", status, body),
+            HttpErr::Decode => write!(f, "Http json encode/decode error"),
+        }
+    }
+}
+
+impl Context for HttpErr {}
+
+impl HttpErr {
+    /// Transient failures that might succeed if tried again.
+    fn is_retryable(&self) -> bool {
+        match self {
+            HttpErr::Transport | HttpErr::Timeout => true,
+            HttpErr::Status { status, .. } => *status >= 500 || *status == 429,
+            HttpErr::Decode => false,
+        }
+    }
+}
+
+/// How [`HttpClient`] retries failed requests, using [`retry_backoff`].
+///
+/// Only transport errors, timeouts, 5xx and 429 statuses are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The delays between each retry, this also specifies how many retries to attempt.
+    pub delays: Vec<Duration>,
+    /// The number of times the last delay should be repeated, see [`retry_backoff`].
+    pub last_delay_repeat_times: Option<usize>,
+    /// Also retry non-idempotent requests (POST).
+    /// Off by default, the server might have acted on a request that still failed, e.g. timed out.
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self {
+            delays: vec![],
+            last_delay_repeat_times: None,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            delays: vec![
+                Duration::from_millis(100),
+                Duration::from_millis(500),
+                Duration::from_secs(2),
+            ],
+            last_delay_repeat_times: None,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+/// Options for [`HttpClient::new`].
+#[derive(Debug, Clone)]
+pub struct HttpOpts {
+    /// Prefixed to request paths, unless the path is already a full url.
+    pub base_url: String,
+    /// The max time for each attempt, including reading the response body.
+    pub timeout: Duration,
+    /// How failed requests are retried.
+    pub retry: RetryPolicy,
+    /// Headers added to every request.
+    pub default_headers: Vec<(String, String)>,
+}
+
+impl Default for HttpOpts {
+    fn default() -> Self {
+        Self {
+            base_url: String::new(),
+            timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            default_headers: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        }
+    }
+
+    fn is_idempotent(&self) -> bool {
+        match self {
+            Method::Get => true,
+            Method::Post => false,
+        }
+    }
+}
+
+/// A json http client with retries, timeouts and error context,
+/// backed by reqwest natively and the browser's fetch on wasm.
+///
+/// Each request runs inside an `http_request` debug span with its method and url.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    opts: HttpOpts,
+    #[cfg(not(target_arch = "wasm32"))]
+    client: reqwest::Client,
+}
+
+impl HttpClient {
+    /// Create a new client, cheap to clone, connections are shared between clones.
+    pub fn new(opts: HttpOpts) -> Self {
+        Self {
+            opts,
+            #[cfg(not(target_arch = "wasm32"))]
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// GET the path, decoding the json response.
+    pub async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> RResult<T, HttpErr> {
+        self.request_json(Method::Get, path, None).await
+    }
+
+    /// POST the body as json to the path, decoding the json response.
+    ///
+    /// Not retried unless [`RetryPolicy::retry_non_idempotent`] is set.
+    pub async fn post_json<B: serde::Serialize + ?Sized, T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> RResult<T, HttpErr> {
+        let body = serde_json::to_string(body).change_context(HttpErr::Decode)?;
+        self.request_json(Method::Post, path, Some(body)).await
+    }
+
+    async fn request_json<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> RResult<T, HttpErr> {
+        let url = self.url(path);
+        let span = tracing::debug_span!("http_request", method = method.as_str(), url = url);
+        async {
+            let (delays, last_delay_repeat_times) =
+                if method.is_idempotent() || self.opts.retry.retry_non_idempotent {
+                    (
+                        self.opts.retry.delays.as_slice(),
+                        // retry_backoff needs a last delay to repeat:
+                        self.opts
+                            .retry
+                            .last_delay_repeat_times
+                            .filter(|_| !self.opts.retry.delays.is_empty()),
+                    )
+                } else {
+                    (&[] as &[Duration], None)
+                };
+            let body = body.as_deref();
+            let text = retry_backoff(
+                delays,
+                last_delay_repeat_times,
+                || self.send(method, &url, body),
+                |info| {
+                    if info.last_error.current_context().is_retryable() {
+                        debug!(
+                            "Retrying http request, attempt {} failed: {}",
+                            info.last_attempt_no,
+                            info.last_error.current_context()
+                        );
+                        None
+                    } else {
+                        Some(info.last_error)
+                    }
+                },
+            )
+            .await?;
+            serde_json::from_str(&text)
+                .change_context(HttpErr::Decode)
+                .attach_printable_lazy(|| format!("Response body: {}", truncate_body(text.clone())))
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// A single attempt, returning the body of a 2xx response.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&str>,
+    ) -> RResult<String, HttpErr> {
+        match with_timeout(self.opts.timeout, self.send_raw(method, url, body)).await {
+            Ok((result, _elapsed)) => {
+                let (status, text) = result?;
+                if (200..300).contains(&status) {
+                    Ok(text)
+                } else {
+                    Err(err!(HttpErr::Status {
+                        status,
+                        body: truncate_body(text)
+                    }))
+                }
+            }
+            Err(elapsed) => Err(err!(HttpErr::Timeout, "Timed out after {:?}", elapsed)),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn send_raw(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&str>,
+    ) -> RResult<(u16, String), HttpErr> {
+        let mut request = match method {
+            Method::Get => self.client.get(url),
+            Method::Post => self.client.post(url),
+        };
+        for (key, value) in &self.opts.default_headers {
+            request = request.header(key, value);
+        }
+        if let Some(body) = body {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_string());
+        }
+        let response = request.send().await.change_context(HttpErr::Transport)?;
+        let status = response.status().as_u16();
+        let text = response.text().await.change_context(HttpErr::Transport)?;
+        Ok((status, text))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    async fn send_raw(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&str>,
+    ) -> RResult<(u16, String), HttpErr> {
+        use gloo_net::http::RequestBuilder;
+
+        let mut request = RequestBuilder::new(url).method(match method {
+            Method::Get => gloo_net::http::Method::GET,
+            Method::Post => gloo_net::http::Method::POST,
+        });
+        for (key, value) in &self.opts.default_headers {
+            request = request.header(key, value);
+        }
+        // gloo errors can hold js values, which aren't Send, so only keeping their message:
+        let request = if let Some(body) = body {
+            request
+                .header("content-type", "application/json")
+                .body(body.to_string())
+        } else {
+            request.build()
+        }
+        .map_err(|e| err!(HttpErr::Transport, "{}", e))?;
+        let response = request
+            .send()
+            .await
+            .map_err(|e| err!(HttpErr::Transport, "{}", e))?;
+        let text = response
+            .text()
+            .await
+            .map_err(|e| err!(HttpErr::Transport, "{}", e))?;
+        Ok((response.status(), text))
+    }
+
+    fn url(&self, path: &str) -> String {
+        if path.starts_with("http://")
+            || path.starts_with("https://")
+            || self.opts.base_url.is_empty()
+        {
+            path.to_string()
+        } else {
+            format!(
+                "{}/{}",
+                self.opts.base_url.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            )
+        }
+    }
+}
+
+fn truncate_body(mut body: String) -> String {
+    if body.len() > MAX_ERR_BODY_LEN {
+        let mut end = MAX_ERR_BODY_LEN;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str("...");
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use rstest::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// A tiny http server responding to each connection with the next (status, body, delay), returning its url and a request counter.
+    async fn stub_server(responses: Vec<(u16, String, Duration)>) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        tokio::spawn(async move {
+            for (status, body, delay) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                // Reading the full request before responding, so closing doesn't reset the connection:
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                loop {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                    let text = String::from_utf8_lossy(&request).to_lowercase();
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let content_length = text
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .map(|len| len.trim().parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        if request.len() >= head_end + 4 + content_length {
+                            break;
+                        }
+                    }
+                    if read == 0 {
+                        break;
+                    }
+                }
+                tokio::time::sleep(delay).await;
+                let _ = stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {} Stub\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                            status,
+                            body.len(),
+                            body
+                        )
+                        .as_bytes(),
+                    )
+                    .await;
+            }
+        });
+        (url, count)
+    }
+
+    fn client(base_url: String, retry: RetryPolicy) -> HttpClient {
+        HttpClient::new(HttpOpts {
+            base_url,
+            timeout: Duration::from_millis(100),
+            retry,
+            default_headers: vec![("x-test".to_string(), "1".to_string())],
+        })
+    }
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            delays: vec![Duration::from_millis(5), Duration::from_millis(5)],
+            ..Default::default()
+        }
+    }
+
+    fn respond(status: u16, body: &str) -> (u16, String, Duration) {
+        (status, body.to_string(), Duration::ZERO)
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_http_retries() {
+        // GET retries on 503:
+        let (url, count) = stub_server(vec![
+            respond(503, "down"),
+            respond(503, "down"),
+            respond(200, r#"{"a": 1}"#),
+        ])
+        .await;
+        let value: serde_json::Value = client(url, fast_retries())
+            .get_json("/thing")
+            .await
+            .unwrap();
+        assert_eq!(value, serde_json::json!({"a": 1}));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Gives up after the last retry, with the status and body:
+        let (url, count) = stub_server(vec![respond(503, "down"); 3]).await;
+        let e = client(url, fast_retries())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.current_context(),
+            &HttpErr::Status {
+                status: 503,
+                body: "down".to_string()
+            }
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Client errors aren't retried:
+        let (url, count) = stub_server(vec![respond(404, "missing"), respond(200, "{}")]).await;
+        let e = client(url, fast_retries())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e.current_context(),
+            HttpErr::Status { status: 404, .. }
+        ));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // POST isn't retried by default:
+        let (url, count) = stub_server(vec![respond(503, "down"), respond(200, "2")]).await;
+        let e = client(url, fast_retries())
+            .post_json::<_, u32>("thing", &[1, 2])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e.current_context(),
+            HttpErr::Status { status: 503, .. }
+        ));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Unless configured to:
+        let (url, count) = stub_server(vec![respond(503, "down"), respond(200, "2")]).await;
+        let value = client(
+            url,
+            RetryPolicy {
+                retry_non_idempotent: true,
+                ..fast_retries()
+            },
+        )
+        .post_json::<_, u32>("thing", &[1, 2])
+        .await
+        .unwrap();
+        assert_eq!(value, 2);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_http_errors() {
+        // Slow responses time out:
+        let (url, _count) =
+            stub_server(vec![(200, "{}".to_string(), Duration::from_millis(300))]).await;
+        let e = client(url, RetryPolicy::none())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        assert_eq!(e.current_context(), &HttpErr::Timeout);
+
+        // Invalid json is a decode error, and not retried:
+        let (url, count) = stub_server(vec![respond(200, "not json"), respond(200, "{}")]).await;
+        let e = client(url, fast_retries())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        assert_eq!(e.current_context(), &HttpErr::Decode);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // As is valid json of the wrong shape:
+        let (url, _count) = stub_server(vec![respond(200, r#"{"a": 1}"#)]).await;
+        let e = client(url, RetryPolicy::none())
+            .get_json::<Vec<u32>>("thing")
+            .await
+            .unwrap_err();
+        assert_eq!(e.current_context(), &HttpErr::Decode);
+
+        // Long error bodies are truncated:
+        let (url, _count) = stub_server(vec![respond(400, &"x".repeat(5000))]).await;
+        let e = client(url, RetryPolicy::none())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        match e.current_context() {
+            HttpErr::Status { status, body } => {
+                assert_eq!(*status, 400);
+                assert_eq!(body.len(), MAX_ERR_BODY_LEN + 3);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Nothing listening is a transport error:
+        let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let e = client(format!("http://{}", addr), RetryPolicy::none())
+            .get_json::<serde_json::Value>("thing")
+            .await
+            .unwrap_err();
+        assert_eq!(e.current_context(), &HttpErr::Transport);
+    }
+
+    #[rstest]
+    #[case("http://a.com", "/b", "http://a.com/b")]
+    #[case("http://a.com/", "b", "http://a.com/b")]
+    #[case("http://a.com/api/", "/b/c", "http://a.com/api/b/c")]
+    #[case("http://a.com", "https://other.com/b", "https://other.com/b")]
+    #[case("", "http://a.com/b", "http://a.com/b")]
+    fn test_http_url(#[case] base_url: &str, #[case] path: &str, #[case] expected: &str) {
+        let client = HttpClient::new(HttpOpts {
+            base_url: base_url.to_string(),
+            ..Default::default()
+        });
+        assert_eq!(client.url(path), expected);
+    }
+}
@@ -1,18 +1,50 @@
 /// Byte manipulation utilities, e.g. transfer speed.
 pub mod bytes;
+#[cfg(feature = "config")]
+/// Layered config loading from files, env vars and overrides.
+pub mod config;
+#[cfg(feature = "random")]
+/// Weighted random selection and reservoir sampling.
+pub mod random;
 
 mod binary_search;
+#[cfg(feature = "redis")]
+mod feature_flags;
 mod flexi_logger;
+#[cfg(feature = "http")]
+mod http;
 mod in_ci;
 mod is_tcp_port_listening;
 mod periodic_updater;
+#[cfg(feature = "system")]
+mod process_info;
 mod retry_backoff;
 mod sleep_compat;
+#[cfg(feature = "sortable-id")]
+mod sortable_id;
+#[cfg(all(not(target_arch = "wasm32"), feature = "rt"))]
+mod supervisor;
+mod timeout;
+#[cfg(feature = "tolerant-serde")]
+mod tolerant_serde;
 
 pub use binary_search::*;
+#[cfg(feature = "redis")]
+pub use feature_flags::*;
 pub use flexi_logger::*;
+#[cfg(feature = "http")]
+pub use http::*;
 pub use in_ci::in_ci;
 pub use is_tcp_port_listening::is_tcp_port_listening;
 pub use periodic_updater::*;
+#[cfg(feature = "system")]
+pub use process_info::*;
 pub use retry_backoff::*;
 pub use sleep_compat::*;
+#[cfg(feature = "sortable-id")]
+pub use sortable_id::*;
+#[cfg(all(not(target_arch = "wasm32"), feature = "rt"))]
+pub use supervisor::*;
+pub use timeout::*;
+#[cfg(feature = "tolerant-serde")]
+pub use tolerant_serde::*;
@@ -1,13 +1,17 @@
 use std::{
-    sync::atomic::AtomicU64,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 /// A periodic updater. Run a callback at a specified time interval. Synchronous. Requires polling.
 /// Useful in long running loops to do something at a specified time interval. [`PeriodicUpdater::maybe_update`] should be called during each loop.
+///
+/// At most one callback runs at a time, even when polled from multiple threads.
 pub struct PeriodicUpdater<A, F: Fn(std::time::Duration, A)> {
     last_timestamp_ms: AtomicU64,
-    update_every: std::time::Duration,
+    update_every_ms: AtomicU64,
+    paused: AtomicBool,
+    running: AtomicBool,
     on_progress: F,
     _a: std::marker::PhantomData<A>,
 }
@@ -23,25 +27,79 @@ impl<A, F: Fn(std::time::Duration, A)> PeriodicUpdater<A, F> {
         Self {
             on_progress,
             last_timestamp_ms: AtomicU64::new(0),
-            update_every,
+            update_every_ms: AtomicU64::new(update_every.as_millis() as u64),
+            paused: AtomicBool::new(false),
+            running: AtomicBool::new(false),
             _a: std::marker::PhantomData,
         }
     }
 
     /// Call this function frequently to check if the callback should be run.
     pub fn maybe_update(&self, ext_params: A) {
-        let epoch_ms = get_epoch_ms();
-        let elapsed = std::time::Duration::from_millis(
-            epoch_ms
-                - self
-                    .last_timestamp_ms
-                    .load(std::sync::atomic::Ordering::Relaxed),
-        );
-        if elapsed >= self.update_every {
-            (self.on_progress)(elapsed, ext_params);
-            self.last_timestamp_ms
-                .store(epoch_ms, std::sync::atomic::Ordering::Relaxed);
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        let update_every_ms = self.update_every_ms.load(Ordering::Relaxed);
+        // Checked again once running, another caller may have just updated:
+        if get_epoch_ms().saturating_sub(self.last_timestamp_ms.load(Ordering::Relaxed))
+            < update_every_ms
+        {
+            return;
+        }
+        self.run_exclusive(ext_params, Some(update_every_ms));
+    }
+
+    /// Change the interval, taking effect from the next [`PeriodicUpdater::maybe_update`].
+    pub fn set_interval(&self, update_every: std::time::Duration) {
+        self.update_every_ms
+            .store(update_every.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Stop [`PeriodicUpdater::maybe_update`] running the callback until [`PeriodicUpdater::resume`] is called.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resume after a [`PeriodicUpdater::pause`], the next run is a full interval from now.
+    pub fn resume(&self) {
+        self.last_timestamp_ms
+            .store(get_epoch_ms(), Ordering::Relaxed);
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether [`PeriodicUpdater::pause`] is in effect.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Run the callback now, regardless of the interval or being paused, the next interval then counts from now.
+    ///
+    /// Returns false without running if the callback is already running elsewhere.
+    pub fn trigger_now(&self, ext_params: A) -> bool {
+        self.run_exclusive(ext_params, None)
+    }
+
+    /// Run the callback unless already running, when `due_every_ms` is given only if still due.
+    fn run_exclusive(&self, ext_params: A, due_every_ms: Option<u64>) -> bool {
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
         }
+        let epoch_ms = get_epoch_ms();
+        let elapsed_ms = epoch_ms.saturating_sub(self.last_timestamp_ms.load(Ordering::Relaxed));
+        let ran = match due_every_ms {
+            Some(every_ms) if elapsed_ms < every_ms => false,
+            _ => {
+                (self.on_progress)(std::time::Duration::from_millis(elapsed_ms), ext_params);
+                self.last_timestamp_ms.store(epoch_ms, Ordering::Relaxed);
+                true
+            }
+        };
+        self.running.store(false, Ordering::Release);
+        ran
     }
 }
 
@@ -51,3 +109,114 @@ fn get_epoch_ms() -> u64 {
         .unwrap()
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::testing::prelude::*;
+
+    /// Poll the updater for `poll_for`, returning the ticks counted.
+    fn poll<A: Clone, F: Fn(Duration, A)>(
+        updater: &PeriodicUpdater<A, F>,
+        ticks: &AtomicUsize,
+        poll_for: Duration,
+        ext_params: A,
+    ) -> usize {
+        let before = ticks.load(Ordering::SeqCst);
+        let started = Instant::now();
+        while started.elapsed() < poll_for {
+            updater.maybe_update(ext_params.clone());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        ticks.load(Ordering::SeqCst) - before
+    }
+
+    #[rstest]
+    fn test_periodic_updater_interval_and_pause() {
+        let ticks = AtomicUsize::new(0);
+        let updater = PeriodicUpdater::new(Duration::from_millis(50), |_, ()| {
+            ticks.fetch_add(1, Ordering::SeqCst);
+        });
+        // The first poll always runs, then every 50ms:
+        let counted = poll(&updater, &ticks, Duration::from_millis(260), ());
+        assert!((5..=7).contains(&counted), "{}", counted);
+
+        // Faster from the next tick:
+        updater.set_interval(Duration::from_millis(20));
+        let counted = poll(&updater, &ticks, Duration::from_millis(260), ());
+        assert!((10..=14).contains(&counted), "{}", counted);
+
+        // No ticks whilst paused, however long:
+        updater.pause();
+        assert!(updater.is_paused());
+        assert_eq!(poll(&updater, &ticks, Duration::from_millis(100), ()), 0);
+
+        // Resuming waits a full interval before the next tick:
+        updater.resume();
+        assert!(!updater.is_paused());
+        assert_eq!(poll(&updater, &ticks, Duration::from_millis(10), ()), 0);
+        let counted = poll(&updater, &ticks, Duration::from_millis(250), ());
+        assert!((10..=13).contains(&counted), "{}", counted);
+    }
+
+    #[rstest]
+    fn test_periodic_updater_trigger_now() {
+        let ticks = AtomicUsize::new(0);
+        let updater = PeriodicUpdater::new(Duration::from_secs(60), |_, ()| {
+            ticks.fetch_add(1, Ordering::SeqCst);
+        });
+        updater.maybe_update(());
+        updater.maybe_update(());
+        assert_eq!(ticks.load(Ordering::SeqCst), 1);
+
+        // Runs out of band, even when paused:
+        assert!(updater.trigger_now(()));
+        updater.pause();
+        assert!(updater.trigger_now(()));
+        assert_eq!(ticks.load(Ordering::SeqCst), 3);
+    }
+
+    #[rstest]
+    fn test_periodic_updater_no_overlap() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let updater = Arc::new(PeriodicUpdater::new(Duration::from_millis(5), {
+            let (active, max_active, runs) = (active.clone(), max_active.clone(), runs.clone());
+            move |_, ()| {
+                let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max_active.fetch_max(now_active, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(10));
+                runs.fetch_add(1, Ordering::SeqCst);
+                active.fetch_sub(1, Ordering::SeqCst);
+            }
+        }));
+
+        // Polling and triggering from many threads at once:
+        let handles = (0..8)
+            .map(|i| {
+                let updater = updater.clone();
+                std::thread::spawn(move || {
+                    let started = Instant::now();
+                    while started.elapsed() < Duration::from_millis(200) {
+                        if i % 2 == 0 {
+                            updater.maybe_update(());
+                        } else {
+                            updater.trigger_now(());
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(max_active.load(Ordering::SeqCst), 1);
+        assert!(runs.load(Ordering::SeqCst) > 5);
+    }
+}
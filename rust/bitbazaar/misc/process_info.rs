@@ -0,0 +1,176 @@
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sysinfo::{
+    get_current_pid, CpuRefreshKind, MemoryRefreshKind, ProcessRefreshKind, RefreshKind, System,
+};
+
+/// A snapshot of the current process, see [`process_info`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    /// The resident memory of the process.
+    pub rss_bytes: u64,
+    /// The committed virtual memory of the process.
+    pub virtual_bytes: u64,
+    /// The number of open file descriptors, only known on linux and macos.
+    pub open_fds: Option<u32>,
+    /// The number of threads in the process, only counted on linux, 1 elsewhere.
+    pub threads: u32,
+    /// How long the process has been running.
+    pub uptime: chrono::TimeDelta,
+    /// The cpu usage of the process since the previous call to [`process_info`] (from anywhere in the process,
+    /// including the otel process metrics), as a percentage of a single core, so can exceed 100 on multi core machines.
+    ///
+    /// None on the first call, as there's nothing to compare against.
+    pub cpu_percent_since_last_call: Option<f64>,
+}
+
+/// Static information about the host machine, see [`host_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    /// The hostname, if it could be read.
+    pub hostname: Option<String>,
+    /// The os name and version, e.g. `Linux 22.04 Ubuntu`.
+    pub os: Option<String>,
+    /// The cpu architecture the binary was compiled for, e.g. `x86_64`.
+    pub arch: &'static str,
+    /// The number of logical cores.
+    pub logical_cores: usize,
+    /// The number of physical cores, if it could be read.
+    pub physical_cores: Option<usize>,
+    /// The total memory of the machine.
+    pub total_memory_bytes: u64,
+}
+
+/// Reused between calls, both for speed and to compute cpu usage against the previous call.
+/// The bool is whether a previous call has happened.
+static PROCESS_SYS: Lazy<Mutex<(System, bool)>> = Lazy::new(|| Mutex::new((System::new(), false)));
+
+/// A snapshot of the current process' resource usage, for health endpoints and log enrichment.
+///
+/// Cheap enough to call per health check, only the current process is refreshed.
+/// Also used by [`crate::log::init_system_and_process_metrics`] for the process metrics.
+///
+/// Where the process can't be read (an unsupported platform), memory and uptime are zero.
+pub fn process_info() -> ProcessInfo {
+    let mut guard = PROCESS_SYS.lock();
+    let (sys, called_before) = &mut *guard;
+    let process = get_current_pid().ok().and_then(|pid| {
+        sys.refresh_process_specifics(pid, ProcessRefreshKind::new().with_cpu().with_memory());
+        sys.process(pid)
+    });
+
+    let info = ProcessInfo {
+        rss_bytes: process.map(|p| p.memory()).unwrap_or(0),
+        virtual_bytes: process.map(|p| p.virtual_memory()).unwrap_or(0),
+        open_fds: open_fds(),
+        threads: process
+            .and_then(|p| p.tasks())
+            // The main thread isn't always included in the tasks:
+            .map(|tasks| (tasks.len() as u32).max(1))
+            .unwrap_or(1),
+        uptime: process
+            .and_then(|p| chrono::DateTime::from_timestamp(p.start_time() as i64, 0))
+            // Start time is only to the second, so measured against now for a steadily growing uptime:
+            .map(|started| (chrono::Utc::now() - started).max(chrono::TimeDelta::zero()))
+            .unwrap_or_else(chrono::TimeDelta::zero),
+        cpu_percent_since_last_call: process
+            .filter(|_| *called_before)
+            .map(|p| p.cpu_usage() as f64),
+    };
+    *called_before = true;
+    info
+}
+
+/// Information about the host machine, for health endpoints and log enrichment.
+///
+/// Reads the memory and cpus each call, so prefer calling once and keeping the result.
+pub fn host_info() -> HostInfo {
+    let sys = System::new_with_specifics(
+        RefreshKind::new()
+            .with_cpu(CpuRefreshKind::new())
+            .with_memory(MemoryRefreshKind::new().with_ram()),
+    );
+    HostInfo {
+        hostname: System::host_name(),
+        os: System::long_os_version(),
+        arch: std::env::consts::ARCH,
+        logical_cores: sys.cpus().len(),
+        physical_cores: sys.physical_core_count(),
+        total_memory_bytes: sys.total_memory(),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn open_fds() -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    let dir = "/proc/self/fd";
+    #[cfg(target_os = "macos")]
+    let dir = "/dev/fd";
+    // Minus the fd used to read the dir itself:
+    let count = std::fs::read_dir(dir).ok()?.count();
+    Some(count.saturating_sub(1) as u32)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_fds() -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_process_info() {
+        let first = process_info();
+        assert!(first.rss_bytes > 0);
+        assert!(first.virtual_bytes >= first.rss_bytes);
+        assert!(first.uptime > chrono::TimeDelta::zero());
+        assert!(first.threads >= 1);
+        if cfg!(target_os = "linux") {
+            // At least stdin, stdout and stderr:
+            assert!(first.open_fds.unwrap() >= 3);
+        }
+
+        // Burn a little cpu so there's something to measure:
+        let mut x = 0u64;
+        for i in 0..10_000_000u64 {
+            x = x.wrapping_add(std::hint::black_box(i));
+        }
+        std::hint::black_box(x);
+
+        let second = process_info();
+        assert!(second.uptime > first.uptime);
+        let cpu = second.cpu_percent_since_last_call.unwrap();
+        assert!(cpu >= 0.0, "{}", cpu);
+
+        // Repeated calls reuse the same state, so shouldn't grow memory or leak fds:
+        for _ in 0..1000 {
+            process_info();
+        }
+        let after = process_info();
+        assert!(
+            after.rss_bytes < second.rss_bytes + 10 * 1024 * 1024,
+            "{} -> {}",
+            second.rss_bytes,
+            after.rss_bytes
+        );
+        if let (Some(before), Some(after)) = (second.open_fds, after.open_fds) {
+            // Allowing a little for other tests running in parallel:
+            assert!(after <= before + 10, "{} -> {}", before, after);
+        }
+    }
+
+    #[rstest]
+    fn test_host_info() {
+        let host = host_info();
+        assert!(host.logical_cores >= 1);
+        assert!(host.total_memory_bytes > 0);
+        assert!(!host.arch.is_empty());
+        if let Some(physical) = host.physical_cores {
+            assert!(physical >= 1 && physical <= host.logical_cores);
+        }
+    }
+}
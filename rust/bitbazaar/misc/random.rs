@@ -0,0 +1,271 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::errors::prelude::*;
+
+/// Pick a random item, each with a chance proportional to its weight. O(n).
+///
+/// Items with a weight of 0 are never picked.
+///
+/// Arguments:
+/// - `items`: (item, weight) pairs, weights must be finite and non-negative, not all 0.
+/// - `rng`: The source of randomness, e.g. `rand::thread_rng()`, or a seeded rng for determinism.
+///
+/// Returns:
+/// - `Ok(None)` if `items` is empty.
+/// - `Err` if the weights are invalid.
+pub fn choose_weighted<'a, T>(
+    items: &'a [(T, f64)],
+    rng: &mut impl Rng,
+) -> RResult<Option<&'a T>, AnyErr> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+    let total = validate_weights(items)?;
+    let mut target = rng.gen::<f64>() * total;
+    for (item, weight) in items {
+        if *weight > 0.0 {
+            if target < *weight {
+                return Ok(Some(item));
+            }
+            target -= weight;
+        }
+    }
+    // Float rounding can leave the target just past the last weight:
+    Ok(items
+        .iter()
+        .rev()
+        .find(|(_, weight)| *weight > 0.0)
+        .map(|(item, _)| item))
+}
+
+/// Pick up to `k` distinct random items without replacement, each pick weighted by the item's weight
+/// (Efraimidis-Spirakis, each item gets a key `u^(1/weight)`, the `k` largest keys win). O(n log n).
+///
+/// Items with a weight of 0 are never picked, so fewer than `k` are returned if there aren't enough non-zero weights.
+/// The items are in pick order, e.g. the first is distributed the same as [`choose_weighted`].
+///
+/// Arguments:
+/// - `items`: (item, weight) pairs, weights must be finite and non-negative, not all 0.
+/// - `k`: The max number of items to pick.
+/// - `rng`: The source of randomness, e.g. `rand::thread_rng()`, or a seeded rng for determinism.
+///
+/// Returns `Err` if the weights are invalid, an empty `items` is valid and returns no items.
+pub fn choose_multiple_weighted<'a, T>(
+    items: &'a [(T, f64)],
+    k: usize,
+    rng: &mut impl Rng,
+) -> RResult<Vec<&'a T>, AnyErr> {
+    if items.is_empty() {
+        return Ok(vec![]);
+    }
+    validate_weights(items)?;
+    // ln(u) / weight orders the same as u^(1/weight), without underflowing for small weights.
+    // u is in (0, 1], so ln(u) is never -inf:
+    let mut keyed = items
+        .iter()
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(item, weight)| ((1.0 - rng.gen::<f64>()).ln() / weight, item))
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Ok(keyed.into_iter().take(k).map(|(_, item)| item).collect())
+}
+
+/// Check weights are finite, non-negative and not all 0, returning their total.
+fn validate_weights<T>(items: &[(T, f64)]) -> RResult<f64, AnyErr> {
+    let mut total = 0.0;
+    for (index, (_, weight)) in items.iter().enumerate() {
+        if !weight.is_finite() || *weight < 0.0 {
+            return Err(anyerr!(
+                "Weights must be finite and non-negative, got '{}' at index {}.",
+                weight,
+                index
+            ));
+        }
+        total += weight;
+    }
+    if total == 0.0 {
+        return Err(anyerr!("Weights can't all be 0."));
+    }
+    if !total.is_finite() {
+        return Err(anyerr!("Weights sum to more than an f64 can hold."));
+    }
+    Ok(total)
+}
+
+/// Keeps a uniform random sample of up to `k` items from a stream of unknown length (Algorithm R).
+///
+/// Every item offered has the same chance of being in the final sample, using O(k) memory however many are offered.
+#[derive(Debug)]
+pub struct ReservoirSampler<T, R: Rng = StdRng> {
+    k: usize,
+    seen: u64,
+    sample: Vec<T>,
+    rng: R,
+}
+
+impl<T> ReservoirSampler<T> {
+    /// Create a new sampler keeping up to `k` items, seeded from the os.
+    pub fn new(k: usize) -> Self {
+        Self::with_rng(k, StdRng::from_entropy())
+    }
+}
+
+impl<T, R: Rng> ReservoirSampler<T, R> {
+    /// Create a new sampler keeping up to `k` items, using the given rng, e.g. a seeded one for determinism.
+    pub fn with_rng(k: usize, rng: R) -> Self {
+        Self {
+            k,
+            seen: 0,
+            sample: Vec::with_capacity(k),
+            rng,
+        }
+    }
+
+    /// Offer the next item from the stream, it either replaces a random item in the sample or is dropped.
+    pub fn offer(&mut self, item: T) {
+        self.seen += 1;
+        if self.sample.len() < self.k {
+            self.sample.push(item);
+        } else {
+            let index = self.rng.gen_range(0..self.seen);
+            if index < self.k as u64 {
+                self.sample[index as usize] = item;
+            }
+        }
+    }
+
+    /// The number of items offered so far.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// The current sample, in no particular order.
+    pub fn sample(&self) -> &[T] {
+        &self.sample
+    }
+
+    /// Consume the sampler, returning the sample, in no particular order.
+    pub fn into_sample(self) -> Vec<T> {
+        self.sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_choose_weighted() -> RResult<(), AnyErr> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let items = [("a", 1.0), ("b", 2.0), ("zero", 0.0), ("c", 7.0)];
+        let draws = 100_000;
+        let mut counts = HashMap::new();
+        for _ in 0..draws {
+            *counts
+                .entry(*choose_weighted(&items, &mut rng)?.unwrap())
+                .or_insert(0) += 1;
+        }
+        for (item, expected) in [("a", 0.1), ("b", 0.2), ("c", 0.7)] {
+            let freq = counts[item] as f64 / draws as f64;
+            assert!((freq - expected).abs() < 0.01, "{}: {}", item, freq);
+        }
+        assert!(!counts.contains_key("zero"));
+
+        let empty: [(&str, f64); 0] = [];
+        assert_eq!(choose_weighted(&empty, &mut rng)?, None);
+        assert_eq!(choose_weighted(&[("only", 0.5)], &mut rng)?, Some(&"only"));
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_choose_multiple_weighted() -> RResult<(), AnyErr> {
+        let mut rng = StdRng::seed_from_u64(2);
+        let items = [
+            ("a", 1.0),
+            ("b", 2.0),
+            ("zero", 0.0),
+            ("c", 3.0),
+            ("d", 4.0),
+        ];
+        let draws = 50_000;
+        let mut first_counts = HashMap::new();
+        for _ in 0..draws {
+            let picked = choose_multiple_weighted(&items, 3, &mut rng)?;
+            assert_eq!(picked.len(), 3);
+            assert_eq!(picked.iter().collect::<HashSet<_>>().len(), 3);
+            assert!(!picked.contains(&&"zero"));
+            *first_counts.entry(*picked[0]).or_insert(0) += 1;
+        }
+        // The first pick is a plain weighted choice:
+        for (item, expected) in [("a", 0.1), ("b", 0.2), ("c", 0.3), ("d", 0.4)] {
+            let freq = first_counts[item] as f64 / draws as f64;
+            assert!((freq - expected).abs() < 0.01, "{}: {}", item, freq);
+        }
+
+        // Zero weights are never picked, even when asking for everything:
+        let mut all = choose_multiple_weighted(&items, 10, &mut rng)?;
+        all.sort();
+        assert_eq!(all, vec![&"a", &"b", &"c", &"d"]);
+        assert!(choose_multiple_weighted(&items, 0, &mut rng)?.is_empty());
+        let empty: [(&str, f64); 0] = [];
+        assert!(choose_multiple_weighted(&empty, 3, &mut rng)?.is_empty());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(vec![("a", 1.0), ("b", -1.0)])]
+    #[case(vec![("a", 1.0), ("b", f64::NAN)])]
+    #[case(vec![("a", 1.0), ("b", f64::INFINITY)])]
+    #[case(vec![("a", f64::MAX), ("b", f64::MAX)])]
+    #[case(vec![("a", 0.0), ("b", 0.0)])]
+    fn test_weights_invalid(#[case] items: Vec<(&str, f64)>) {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(choose_weighted(&items, &mut rng).is_err());
+        assert!(choose_multiple_weighted(&items, 1, &mut rng).is_err());
+    }
+
+    #[rstest]
+    fn test_reservoir_sampler() {
+        // Fewer items than k keeps everything:
+        let mut sampler = ReservoirSampler::with_rng(10, StdRng::seed_from_u64(4));
+        for i in 0..5 {
+            sampler.offer(i);
+        }
+        assert_eq!(sampler.seen(), 5);
+        assert_eq!(sampler.into_sample(), vec![0, 1, 2, 3, 4]);
+
+        // Every item of the stream should be equally likely to end up in the sample:
+        let (stream_len, k, trials) = (100, 10, 20_000);
+        let mut counts = vec![0_u64; stream_len];
+        for trial in 0..trials {
+            let mut sampler = ReservoirSampler::with_rng(k, StdRng::seed_from_u64(trial));
+            for i in 0..stream_len {
+                sampler.offer(i);
+            }
+            let sample = sampler.into_sample();
+            assert_eq!(sample.len(), k);
+            assert_eq!(sample.iter().collect::<HashSet<_>>().len(), k);
+            for i in sample {
+                counts[i] += 1;
+            }
+        }
+        let expected = (trials as f64 * k as f64) / stream_len as f64;
+        let chi_square = counts
+            .iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum::<f64>();
+        // 99 degrees of freedom, so ~99 expected, a loose bound that would catch any real bias:
+        assert!(chi_square < 200.0, "{}", chi_square);
+
+        // Unseeded works too:
+        let mut sampler = ReservoirSampler::new(3);
+        for i in 0..1000 {
+            sampler.offer(i);
+        }
+        assert_eq!(sampler.sample().len(), 3);
+    }
+}
@@ -9,3 +9,21 @@ pub async fn sleep_compat(duration: std::time::Duration) {
     #[cfg(not(target_arch = "wasm32"))]
     tokio::time::sleep(duration).await;
 }
+
+/// Yield back to the executor once, allowing other tasks to run, compatible with any executor.
+///
+/// Useful in loops that may otherwise never return `Pending`, e.g. when processing many already completed futures.
+pub async fn yield_compat() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            // Immediately reschedule, just at the back of the queue:
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
@@ -0,0 +1,235 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+
+use crate::errors::prelude::*;
+
+/// Crockford's base32, excludes I, L, O & U to avoid confusion.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ENCODED_LEN: usize = 26;
+const RANDOM_BITS: u32 = 80;
+const RANDOM_MASK: u128 = (1 << RANDOM_BITS) - 1;
+const MAX_MILLIS: u64 = (1 << 48) - 1;
+
+/// The last id generated by [`sortable_id`] in this process, to increment from when in the same millisecond.
+static LAST: Mutex<Option<(u64, u128)>> = Mutex::new(None);
+
+/// A lexicographically sortable, url safe unique id, compatible with [ULID](https://github.com/ulid/spec).
+///
+/// 128 bits: a 48 bit unix millisecond timestamp followed by 80 random bits,
+/// displayed as 26 chars of Crockford base32, e.g. `01HQ3Z8V5N4XKQ2M7R9T1C6B0D`.
+///
+/// Serializes as its string form.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct SortableId(u128);
+
+impl SortableId {
+    fn from_parts(millis: u64, random: u128) -> Self {
+        Self(((millis as u128) << RANDOM_BITS) | (random & RANDOM_MASK))
+    }
+
+    /// The unix millisecond timestamp embedded in the id.
+    pub fn timestamp_millis(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// The time embedded in the id, accurate to the millisecond.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.timestamp_millis() as i64).unwrap_or_default()
+    }
+
+    /// The raw 128 bit value.
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl fmt::Display for SortableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = [0u8; ENCODED_LEN];
+        let mut value = self.0;
+        for c in out.iter_mut().rev() {
+            *c = ALPHABET[(value & 0x1f) as usize];
+            value >>= 5;
+        }
+        // Always ascii:
+        f.write_str(std::str::from_utf8(&out).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for SortableId {
+    type Err = error_stack::Report<AnyErr>;
+
+    /// Parse from the 26 char string form, case insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ENCODED_LEN {
+            return Err(anyerr!(
+                "Sortable id '{}' should be {} chars, got {}.",
+                s,
+                ENCODED_LEN,
+                s.len()
+            ));
+        }
+        let mut value: u128 = 0;
+        for (index, c) in s.bytes().enumerate() {
+            let digit = ALPHABET
+                .iter()
+                .position(|a| *a == c.to_ascii_uppercase())
+                .ok_or_else(|| {
+                    anyerr!(
+                        "Sortable id '{}' contains invalid char '{}'.",
+                        s,
+                        (c as char).escape_debug()
+                    )
+                })?;
+            // 26 chars is 130 bits, so the first char can only use the lower 3:
+            if index == 0 && digit > 7 {
+                return Err(anyerr!("Sortable id '{}' is out of range.", s));
+            }
+            value = (value << 5) | digit as u128;
+        }
+        Ok(Self(value))
+    }
+}
+
+impl From<SortableId> for String {
+    fn from(id: SortableId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for SortableId {
+    type Error = error_stack::Report<AnyErr>;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Generate a new [`SortableId`] for the current time.
+///
+/// Ids generated in this process are strictly increasing:
+/// within the same millisecond (or if the clock goes backwards) the random part of the last id is incremented instead,
+/// like the monotonic mode of the ULID spec.
+pub fn sortable_id() -> SortableId {
+    let now = Utc::now().timestamp_millis().clamp(0, MAX_MILLIS as i64) as u64;
+    let mut last = LAST.lock();
+    let (millis, random) = match *last {
+        Some((last_millis, last_random)) if now <= last_millis => {
+            if last_random == RANDOM_MASK {
+                // Random part exhausted, (practically impossible) borrow the next millisecond:
+                (last_millis + 1, rand::random::<u128>() & RANDOM_MASK)
+            } else {
+                (last_millis, last_random + 1)
+            }
+        }
+        _ => (now, rand::random::<u128>() & RANDOM_MASK),
+    };
+    *last = Some((millis, random));
+    SortableId::from_parts(millis, random)
+}
+
+/// Generate a new [`SortableId`] for the given time, unlike [`sortable_id`] this isn't monotonic.
+pub fn sortable_id_with_time(time: DateTime<Utc>) -> SortableId {
+    SortableId::from_parts(
+        time.timestamp_millis().clamp(0, MAX_MILLIS as i64) as u64,
+        rand::random(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_sortable_id_order() {
+        // Across millisecond boundaries:
+        let mut ids = vec![];
+        for _ in 0..5 {
+            ids.push(sortable_id());
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        // Within one (monotonic):
+        ids.extend((0..1000).map(|_| sortable_id()));
+
+        let strs = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let mut sorted = strs.clone();
+        sorted.sort();
+        assert_eq!(strs, sorted);
+        assert!(strs.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Explicit times order by time:
+        let earlier = sortable_id_with_time(Utc::now() - chrono::TimeDelta::days(1));
+        assert!(earlier.to_string() < sortable_id().to_string());
+    }
+
+    #[rstest]
+    fn test_sortable_id_round_trip() -> RResult<(), AnyErr> {
+        let time = DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+        let id = sortable_id_with_time(time);
+        let s = id.to_string();
+        assert_eq!(s.len(), 26);
+        assert!(s.starts_with("01HF"), "{}", s);
+
+        let parsed: SortableId = s.parse()?;
+        assert_eq!(parsed, id);
+        assert_eq!(parsed.timestamp(), time);
+        assert_eq!(s.to_lowercase().parse::<SortableId>()?, id);
+
+        // Serde uses the string form:
+        let json = serde_json::to_string(&id).change_context(AnyErr)?;
+        assert_eq!(json, format!("\"{}\"", s));
+        assert_eq!(
+            serde_json::from_str::<SortableId>(&json).change_context(AnyErr)?,
+            id
+        );
+
+        // Bounds:
+        assert_eq!(
+            "00000000000000000000000000"
+                .parse::<SortableId>()?
+                .as_u128(),
+            0
+        );
+        assert_eq!(
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+                .parse::<SortableId>()?
+                .as_u128(),
+            u128::MAX
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::short("01HF")]
+    #[case::long("01HFZZZZZZZZZZZZZZZZZZZZZZZ")]
+    #[case::invalid_char("01HFZZZZZZZZZZZZZZZZZZZZZU")]
+    #[case::non_ascii("01HFZZZZZZZZZZZZZZZZZZZZé")]
+    #[case::overflow("80000000000000000000000000")]
+    fn test_sortable_id_invalid(#[case] s: &str) {
+        assert!(s.parse::<SortableId>().is_err(), "{}", s);
+        assert!(serde_json::from_str::<SortableId>(&format!("\"{}\"", s)).is_err());
+    }
+
+    #[rstest]
+    fn test_sortable_id_unique() {
+        let ids = (0..100_000).map(|_| sortable_id()).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 100_000);
+        // Explicit times rely on the randomness alone:
+        let time = Utc::now();
+        let ids = (0..100_000)
+            .map(|_| sortable_id_with_time(time))
+            .collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 100_000);
+    }
+}
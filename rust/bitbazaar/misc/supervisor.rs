@@ -0,0 +1,560 @@
+use std::{future::Future, pin::pin, sync::Arc, time::Duration};
+
+use futures::future::{select, BoxFuture};
+use parking_lot::Mutex;
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{errors::LazyDebug, log::record_exception, prelude::*};
+
+/// When a [`Supervisor`] task should be restarted after it stops.
+#[derive(Debug, Clone)]
+pub enum RestartPolicy {
+    /// Never restart, the task is left stopped or dead.
+    Never,
+    /// Restart whenever the task stops, whether it returned, errored or panicked.
+    Always {
+        /// The delays between restarts.
+        backoff: RestartBackoff,
+    },
+    /// Only restart after a panic, returning (even with an error) is final.
+    OnPanic {
+        /// The delays between restarts.
+        backoff: RestartBackoff,
+    },
+}
+
+/// The delays between restarts of a [`Supervisor`] task, growing with each consecutive restart.
+#[derive(Debug, Clone)]
+pub struct RestartBackoff {
+    /// The delay before the first restart.
+    pub initial: Duration,
+    /// The delay is multiplied by this for each consecutive restart, defaults to 2.
+    pub multiplier: f64,
+    /// The delay never grows past this.
+    /// A run lasting at least this long is considered healthy, resetting the delay and consecutive restarts.
+    pub max: Duration,
+    /// Give up after this many consecutive restarts, None to restart forever (the default).
+    pub max_restarts: Option<usize>,
+}
+
+impl RestartBackoff {
+    /// Delays starting at `initial`, doubling up to `max`.
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            multiplier: 2.0,
+            max,
+            max_restarts: None,
+        }
+    }
+
+    /// Give up after this many consecutive restarts.
+    pub fn max_restarts(mut self, max_restarts: usize) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    fn delay(&self, consecutive: usize) -> Duration {
+        self.initial
+            .mul_f64(self.multiplier.powi(consecutive as i32))
+            .min(self.max)
+    }
+}
+
+/// The state of a [`Supervisor`] task, see [`SupervisedTaskStatus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupervisedTaskState {
+    /// Currently running.
+    Running,
+    /// Stopped, waiting to be restarted.
+    BackingOff,
+    /// Returned without error and not restarted, or stopped by [`Supervisor::shutdown`].
+    Stopped,
+    /// Errored or panicked and the policy doesn't restart it.
+    Dead,
+    /// Hit [`RestartBackoff::max_restarts`].
+    GivenUp,
+}
+
+/// A snapshot of a [`Supervisor`] task, from [`Supervisor::status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupervisedTaskStatus {
+    /// The name given to [`Supervisor::supervise`].
+    pub name: String,
+    /// What the task is doing now.
+    pub state: SupervisedTaskState,
+    /// The total number of restarts.
+    pub restarts: usize,
+    /// The last error or panic message, if any.
+    pub last_error: Option<String>,
+}
+
+/// Passed to each run of a [`Supervisor`] task, to stop cooperatively on [`Supervisor::shutdown`].
+#[derive(Debug, Clone)]
+pub struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    /// True once shutdown has started, or the supervisor was dropped.
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow() || self.0.has_changed().is_err()
+    }
+
+    /// Resolves once shutdown has started, or the supervisor was dropped, e.g. to `select!` against the task's work.
+    pub async fn cancelled(&self) {
+        let mut rx = self.0.clone();
+        // Only errors if the supervisor was dropped:
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+type Factory = Arc<dyn Fn(CancelToken) -> BoxFuture<'static, RResult<(), AnyErr>> + Send + Sync>;
+
+struct Supervised {
+    status: Arc<Mutex<SupervisedTaskStatus>>,
+    /// Taken by [`Supervisor::shutdown`].
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Runs long-lived background tasks (loopers, pubsub pumps, flushers etc), restarting them according to a [`RestartPolicy`].
+///
+/// Errors and panics are recorded with [`record_exception`] along with the task's name, so a dying task never goes unnoticed.
+///
+/// Must be used within a tokio runtime. Dropping the supervisor cancels its tasks like [`Supervisor::shutdown`], without waiting for them.
+///
+/// ```
+/// use std::time::Duration;
+/// use bitbazaar::misc::{RestartBackoff, RestartPolicy, Supervisor};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let supervisor = Supervisor::new();
+/// supervisor.supervise(
+///     "flusher",
+///     RestartPolicy::Always {
+///         backoff: RestartBackoff::new(Duration::from_millis(100), Duration::from_secs(30)),
+///     },
+///     |cancel| async move {
+///         while !cancel.is_cancelled() {
+///             // Flush something...
+///             tokio::time::sleep(Duration::from_millis(10)).await;
+///         }
+///         Ok(())
+///     },
+/// );
+/// supervisor.shutdown(Duration::from_secs(1)).await.unwrap();
+/// # }
+/// ```
+pub struct Supervisor {
+    cancel: watch::Sender<bool>,
+    tasks: Mutex<Vec<Supervised>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    /// Create a supervisor with no tasks.
+    pub fn new() -> Self {
+        Self {
+            cancel: watch::Sender::new(false),
+            tasks: Mutex::new(vec![]),
+        }
+    }
+
+    /// Start running a task, `factory` is called to create the future for each (re)start.
+    ///
+    /// Arguments:
+    /// - `name`: Identifies the task in [`Supervisor::status`] and recorded exceptions.
+    /// - `policy`: When to restart the task.
+    /// - `factory`: Creates the task's future, which should finish soon after its [`CancelToken`] is cancelled.
+    pub fn supervise<Fut>(
+        &self,
+        name: impl Into<String>,
+        policy: RestartPolicy,
+        factory: impl Fn(CancelToken) -> Fut + Send + Sync + 'static,
+    ) where
+        Fut: Future<Output = RResult<(), AnyErr>> + Send + 'static,
+    {
+        let status = Arc::new(Mutex::new(SupervisedTaskStatus {
+            name: name.into(),
+            state: SupervisedTaskState::Running,
+            restarts: 0,
+            last_error: None,
+        }));
+        let factory: Factory = Arc::new(move |cancel| Box::pin(factory(cancel)));
+        let handle = tokio::spawn(drive(
+            status.clone(),
+            policy,
+            factory,
+            CancelToken(self.cancel.subscribe()),
+        ));
+        self.tasks.lock().push(Supervised {
+            status,
+            handle: Some(handle),
+        });
+    }
+
+    /// A snapshot of every task, in the order they were added.
+    pub fn status(&self) -> Vec<SupervisedTaskStatus> {
+        self.tasks
+            .lock()
+            .iter()
+            .map(|task| task.status.lock().clone())
+            .collect()
+    }
+
+    /// Cancel every task's [`CancelToken`] and wait for them to stop, no more restarts happen.
+    ///
+    /// Tasks still running after `grace` are aborted, erroring with their names.
+    pub async fn shutdown(&self, grace: Duration) -> RResult<(), AnyErr> {
+        self.cancel.send_replace(true);
+        // The statuses stay in place, so they can still be read during and after shutdown:
+        let drivers = self
+            .tasks
+            .lock()
+            .iter_mut()
+            .filter_map(|task| Some((task.status.clone(), task.handle.take()?)))
+            .collect::<Vec<_>>();
+        let deadline = tokio::time::Instant::now() + grace;
+        let mut aborted = vec![];
+        for (status, mut handle) in drivers {
+            if tokio::time::timeout_at(deadline, &mut handle)
+                .await
+                .is_err()
+            {
+                // Aborting the driver drops the run, aborting it too:
+                handle.abort();
+                let mut status = status.lock();
+                status.state = SupervisedTaskState::Stopped;
+                aborted.push(status.name.clone());
+            }
+        }
+        if aborted.is_empty() {
+            Ok(())
+        } else {
+            Err(anyerr!(
+                "Supervised tasks didn't stop within {:?} and were aborted: {}",
+                grace,
+                aborted.join(", ")
+            ))
+        }
+    }
+}
+
+/// Aborts the run when the driver stops or is aborted itself.
+struct AbortOnDrop(JoinHandle<RResult<(), AnyErr>>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+enum Exit {
+    Returned,
+    Errored,
+    Panicked,
+}
+
+async fn drive(
+    status: Arc<Mutex<SupervisedTaskStatus>>,
+    policy: RestartPolicy,
+    factory: Factory,
+    cancel: CancelToken,
+) {
+    let name = status.lock().name.clone();
+    let mut consecutive = 0;
+    // No cancellation check before the first run, a shutdown before the driver is first polled still
+    // starts the task with its token cancelled, so it gets the grace period and is aborted after it like any other run:
+    loop {
+        status.lock().state = SupervisedTaskState::Running;
+        let started = std::time::Instant::now();
+
+        // Run on its own task, so panics (including in the factory) are caught:
+        let mut run = AbortOnDrop(tokio::spawn({
+            let factory = factory.clone();
+            let cancel = cancel.clone();
+            async move { factory(cancel).await }
+        }));
+        let exit = match (&mut run.0).await {
+            Ok(Ok(())) => Exit::Returned,
+            Ok(Err(e)) => {
+                record_exception(
+                    format!("Supervised task '{}' errored.", name),
+                    LazyDebug(&e),
+                );
+                status.lock().last_error = Some(format!("{:?}", e));
+                Exit::Errored
+            }
+            Err(e) => {
+                let message = if e.is_panic() {
+                    let panic = e.into_panic();
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "Unknown panic.".to_string())
+                } else {
+                    "Cancelled.".to_string()
+                };
+                record_exception(format!("Supervised task '{}' panicked.", name), &message);
+                status.lock().last_error = Some(message);
+                Exit::Panicked
+            }
+        };
+
+        if cancel.is_cancelled() {
+            status.lock().state = SupervisedTaskState::Stopped;
+            return;
+        }
+
+        let backoff = match (&policy, &exit) {
+            (RestartPolicy::Always { backoff }, _)
+            | (RestartPolicy::OnPanic { backoff }, Exit::Panicked) => backoff,
+            _ => {
+                status.lock().state = match exit {
+                    Exit::Returned => SupervisedTaskState::Stopped,
+                    Exit::Errored | Exit::Panicked => SupervisedTaskState::Dead,
+                };
+                return;
+            }
+        };
+
+        // A long healthy run starts the backoff afresh:
+        if started.elapsed() >= backoff.max {
+            consecutive = 0;
+        }
+        if let Some(max_restarts) = backoff.max_restarts {
+            if consecutive >= max_restarts {
+                record_exception(
+                    format!(
+                        "Supervised task '{}' gave up after {} consecutive restarts.",
+                        name, consecutive
+                    ),
+                    "",
+                );
+                status.lock().state = SupervisedTaskState::GivenUp;
+                return;
+            }
+        }
+
+        let delay = backoff.delay(consecutive);
+        consecutive += 1;
+        {
+            let mut status = status.lock();
+            status.state = SupervisedTaskState::BackingOff;
+            status.restarts += 1;
+        }
+        // Cancelling can race the end of the sleep, so check again either way:
+        select(pin!(tokio::time::sleep(delay)), pin!(cancel.cancelled())).await;
+        if cancel.is_cancelled() {
+            status.lock().state = SupervisedTaskState::Stopped;
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rstest::*;
+
+    use super::*;
+
+    fn backoff(initial_ms: u64) -> RestartBackoff {
+        RestartBackoff::new(Duration::from_millis(initial_ms), Duration::from_secs(10))
+    }
+
+    async fn wait_for_state(supervisor: &Supervisor, state: SupervisedTaskState) {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while supervisor.status()[0].state != state {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{:?}", supervisor.status()));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_supervisor_restarts_with_backoff() -> RResult<(), AnyErr> {
+        let starts = Arc::new(Mutex::new(vec![]));
+        let supervisor = Supervisor::new();
+        supervisor.supervise(
+            "flaky",
+            RestartPolicy::Always {
+                backoff: backoff(30),
+            },
+            {
+                let starts = starts.clone();
+                move |cancel| {
+                    let starts = starts.clone();
+                    async move {
+                        let attempt = {
+                            let mut starts = starts.lock();
+                            starts.push(std::time::Instant::now());
+                            starts.len()
+                        };
+                        if attempt <= 2 {
+                            panic!("Attempt {} failed.", attempt);
+                        }
+                        cancel.cancelled().await;
+                        Ok(())
+                    }
+                }
+            },
+        );
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while starts.lock().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .change_context(AnyErr)?;
+
+        // Each restart waited at least its backoff, doubling each time
+        // (only lower bounds, the first gap can be stretched by e.g. capturing the panic's backtrace):
+        let starts = starts.lock().clone();
+        let (first_gap, second_gap) = (starts[1] - starts[0], starts[2] - starts[1]);
+        assert!(first_gap >= Duration::from_millis(30), "{:?}", first_gap);
+        assert!(second_gap >= Duration::from_millis(60), "{:?}", second_gap);
+
+        wait_for_state(&supervisor, SupervisedTaskState::Running).await;
+        let status = supervisor.status();
+        assert_eq!(status[0].name, "flaky");
+        assert_eq!(status[0].restarts, 2);
+        assert_eq!(status[0].last_error.as_deref(), Some("Attempt 2 failed."));
+
+        supervisor.shutdown(Duration::from_secs(1)).await?;
+        assert_eq!(supervisor.status()[0].state, SupervisedTaskState::Stopped);
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_supervisor_policies() -> RResult<(), AnyErr> {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counting_err = |runs: &Arc<AtomicUsize>| {
+            let runs = runs.clone();
+            move |_: CancelToken| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async { Err(anyerr!("Broken.")) }
+            }
+        };
+
+        // Never leaves it dead:
+        let supervisor = Supervisor::new();
+        supervisor.supervise("never", RestartPolicy::Never, counting_err(&runs));
+        wait_for_state(&supervisor, SupervisedTaskState::Dead).await;
+        let status = supervisor.status();
+        assert_eq!(status[0].restarts, 0);
+        assert!(status[0].last_error.as_ref().unwrap().contains("Broken."));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // OnPanic doesn't restart errors or clean returns:
+        let supervisor = Supervisor::new();
+        supervisor.supervise(
+            "on_panic",
+            RestartPolicy::OnPanic {
+                backoff: backoff(1),
+            },
+            |_| async { Ok(()) },
+        );
+        wait_for_state(&supervisor, SupervisedTaskState::Stopped).await;
+        assert_eq!(supervisor.status()[0].restarts, 0);
+
+        // Gives up after max restarts:
+        runs.store(0, Ordering::SeqCst);
+        let supervisor = Supervisor::new();
+        supervisor.supervise(
+            "give_up",
+            RestartPolicy::Always {
+                backoff: backoff(1).max_restarts(3),
+            },
+            counting_err(&runs),
+        );
+        wait_for_state(&supervisor, SupervisedTaskState::GivenUp).await;
+        assert_eq!(supervisor.status()[0].restarts, 3);
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_supervisor_shutdown() -> RResult<(), AnyErr> {
+        let supervisor = Supervisor::new();
+        supervisor.supervise("cooperative", RestartPolicy::Never, |cancel| async move {
+            cancel.cancelled().await;
+            Ok(())
+        });
+        supervisor.supervise(
+            "backing_off",
+            RestartPolicy::Always {
+                backoff: backoff(60_000),
+            },
+            |_| async { Ok(()) },
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            supervisor
+                .status()
+                .iter()
+                .map(|status| status.state)
+                .collect::<Vec<_>>(),
+            vec![
+                SupervisedTaskState::Running,
+                SupervisedTaskState::BackingOff
+            ]
+        );
+
+        let started = std::time::Instant::now();
+        supervisor.shutdown(Duration::from_secs(1)).await?;
+        assert!(started.elapsed() < Duration::from_millis(100));
+        for status in supervisor.status() {
+            assert_eq!(status.state, SupervisedTaskState::Stopped);
+        }
+
+        // Uncooperative tasks are aborted after the grace period:
+        let finished = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(tokio::sync::Notify::new());
+        let stubborn = |supervisor: &Supervisor| {
+            let finished = finished.clone();
+            let running = running.clone();
+            supervisor.supervise("stubborn", RestartPolicy::Never, move |_| {
+                let finished = finished.clone();
+                let running = running.clone();
+                async move {
+                    running.notify_one();
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        };
+        let supervisor = Supervisor::new();
+        stubborn(&supervisor);
+        tokio::time::timeout(Duration::from_secs(2), running.notified())
+            .await
+            .change_context(AnyErr)?;
+        let started = std::time::Instant::now();
+        let err = supervisor.shutdown(Duration::from_millis(30)).await;
+        assert!(format!("{:?}", err.unwrap_err()).contains("stubborn"));
+        assert!(started.elapsed() < Duration::from_millis(150));
+        assert_eq!(supervisor.status()[0].state, SupervisedTaskState::Stopped);
+
+        // Shutting down before the driver has even been polled still runs and aborts it:
+        let supervisor = Supervisor::new();
+        stubborn(&supervisor);
+        let err = supervisor.shutdown(Duration::from_millis(30)).await;
+        assert!(format!("{:?}", err.unwrap_err()).contains("stubborn"));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(finished.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::{future::Future, pin::pin, time::Duration};
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::future::{select, Either};
+
+use super::sleep_compat;
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+/// The result of [`with_timeout_detach`].
+#[derive(Debug)]
+pub enum TimeoutOutcome<T> {
+    /// The future finished within the timeout, with its result and how long it took.
+    Completed(T, Duration),
+    /// The timeout was hit first, with a handle to the still running future and how long was waited.
+    Detached(DetachedHandle<T>, Duration),
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+/// A handle to a future that outlived the timeout in [`with_timeout_detach`].
+///
+/// Await it for the future's result, which is None if the future panicked, or couldn't be detached.
+/// Dropping the handle leaves the future running to completion, discarding its result.
+#[derive(Debug)]
+pub struct DetachedHandle<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    task: Option<tokio::task::JoinHandle<T>>,
+    #[cfg(target_arch = "wasm32")]
+    _marker: std::marker::PhantomData<T>,
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+impl<T: Send + 'static> DetachedHandle<T> {
+    /// Move the future onto its own task, dropped if there's no tokio runtime to spawn it on, or on wasm.
+    fn spawn(fut: impl Future<Output = T> + Send + 'static) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Self {
+                task: tokio::runtime::Handle::try_current()
+                    .ok()
+                    .map(|handle| handle.spawn(fut)),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            drop(fut);
+            Self {
+                _marker: std::marker::PhantomData,
+            }
+        }
+    }
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+impl<T> DetachedHandle<T> {
+    /// True if the future has finished, i.e. awaiting the handle will return immediately.
+    ///
+    /// Always true when the future couldn't be detached.
+    pub fn is_finished(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.task
+                .as_ref()
+                .map(|task| task.is_finished())
+                .unwrap_or(true)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            true
+        }
+    }
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+impl<T> Future for DetachedHandle<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match self.get_mut().task.as_mut() {
+                Some(task) => Pin::new(task).poll(cx).map(Result::ok),
+                None => Poll::Ready(None),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = cx;
+            Poll::Ready(None)
+        }
+    }
+}
+
+/// Wait for a future for up to `duration`, returning its result and how long it took,
+/// or how long was waited if it timed out, the future is dropped in that case.
+///
+/// Unlike [`with_timeout_detach`], the future can borrow and needn't be `Send`.
+pub async fn with_timeout<T>(
+    duration: Duration,
+    fut: impl Future<Output = T>,
+) -> Result<(T, Duration), Duration> {
+    let elapsed = stopwatch();
+    match select(pin!(fut), pin!(sleep_compat(duration))).await {
+        Either::Left((value, _)) => Ok((value, elapsed())),
+        Either::Right(_) => Err(elapsed()),
+    }
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+/// Wait for a future for up to `duration`, if it hasn't finished by then it's moved onto its own task to complete
+/// in the background, e.g. for prewarming where a slow result is still useful later, but shouldn't block now.
+///
+/// Both outcomes include how long was waited, see [`TimeoutOutcome`].
+///
+/// Natively needs the `rt` feature, and the future can only be detached inside a tokio runtime. On wasm, or outside a runtime,
+/// a timed out future is dropped instead, with its handle resolving to None straight away.
+pub async fn with_timeout_detach<T: Send + 'static>(
+    duration: Duration,
+    fut: impl Future<Output = T> + Send + 'static,
+) -> TimeoutOutcome<T> {
+    let elapsed = stopwatch();
+    let mut fut = Box::pin(fut);
+    let value = match select(fut.as_mut(), pin!(sleep_compat(duration))).await {
+        Either::Left((value, _)) => Some(value),
+        Either::Right(_) => None,
+    };
+    match value {
+        Some(value) => TimeoutOutcome::Completed(value, elapsed()),
+        None => TimeoutOutcome::Detached(DetachedHandle::spawn(fut), elapsed()),
+    }
+}
+
+/// Returns a fn giving the time since it was created, [`std::time::Instant`] isn't supported on wasm.
+fn stopwatch() -> impl Fn() -> Duration {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let start = std::time::Instant::now();
+        move || start.elapsed()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let start = js_sys::Date::now();
+        move || Duration::from_secs_f64(((js_sys::Date::now() - start) / 1000.0).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    async fn sleep_then(ms: u64, value: u32) -> u32 {
+        sleep_compat(Duration::from_millis(ms)).await;
+        value
+    }
+
+    fn assert_plausible(elapsed: Duration, min_ms: u64) {
+        assert!(
+            elapsed >= Duration::from_millis(min_ms)
+                && elapsed < Duration::from_millis(min_ms + 80),
+            "{:?}",
+            elapsed
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let (value, elapsed) = with_timeout(Duration::from_millis(100), sleep_then(50, 1))
+            .await
+            .unwrap();
+        assert_eq!(value, 1);
+        assert_plausible(elapsed, 50);
+
+        let elapsed = with_timeout(Duration::from_millis(100), sleep_then(200, 1))
+            .await
+            .unwrap_err();
+        assert_plausible(elapsed, 100);
+
+        // Borrowing futures are fine:
+        let mut count = 0;
+        with_timeout(Duration::from_millis(100), async { count += 1 })
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[cfg(feature = "rt")]
+    #[rstest]
+    #[tokio::test]
+    async fn test_with_timeout_detach() {
+        match with_timeout_detach(Duration::from_millis(100), sleep_then(50, 1)).await {
+            TimeoutOutcome::Completed(value, elapsed) => {
+                assert_eq!(value, 1);
+                assert_plausible(elapsed, 50);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match with_timeout_detach(Duration::from_millis(100), sleep_then(200, 2)).await {
+            TimeoutOutcome::Detached(handle, elapsed) => {
+                assert_plausible(elapsed, 100);
+                assert!(!handle.is_finished());
+                // Continued in the background, so the remaining ~100ms rather than another 200ms:
+                let (value, elapsed) = with_timeout(Duration::from_millis(170), handle)
+                    .await
+                    .unwrap();
+                assert_eq!(value, Some(2));
+                assert_plausible(elapsed, 90);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Dropping the handle leaves the future running:
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        match with_timeout_detach(Duration::from_millis(10), async move {
+            sleep_compat(Duration::from_millis(30)).await;
+            tx.send(3).unwrap();
+        })
+        .await
+        {
+            TimeoutOutcome::Detached(handle, _) => drop(handle),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(rx.await.unwrap(), 3);
+
+        // Panics in the detached future come through as None:
+        match with_timeout_detach(Duration::from_millis(10), async {
+            sleep_compat(Duration::from_millis(20)).await;
+            panic!("detached panic");
+        })
+        .await
+        {
+            TimeoutOutcome::<()>::Detached(handle, _) => assert_eq!(handle.await, None),
+            other => panic!("{:?}", other),
+        }
+    }
+}
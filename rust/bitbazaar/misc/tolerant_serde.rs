@@ -0,0 +1,324 @@
+use std::{collections::HashMap, hash::Hash, ops::Deref};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// A [`Vec`] that deserializes element-wise, so one malformed element doesn't fail the whole collection.
+///
+/// Elements that fail to deserialize are dropped, recorded with their index in [`TolerantVec::errors`].
+/// Serializes transparently as the surviving items.
+///
+/// The document itself must still be well-formed, e.g. invalid json syntax or a non-array still fails.
+/// Elements are buffered as [`serde_json::Value`], so only self-describing formats are supported.
+///
+/// Use [`tolerant_vec`] on a plain `Vec<T>` field instead when the errors don't need keeping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TolerantVec<T> {
+    items: Vec<T>,
+    errors: Vec<(usize, String)>,
+}
+
+impl<T> TolerantVec<T> {
+    /// The indices of the elements that failed to deserialize in the original collection, alongside why.
+    pub fn errors(&self) -> &[(usize, String)] {
+        &self.errors
+    }
+
+    /// The successfully deserialized items.
+    pub fn into_inner(self) -> Vec<T> {
+        self.items
+    }
+}
+
+impl<T> Default for TolerantVec<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            errors: vec![],
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for TolerantVec<T> {
+    fn from(items: Vec<T>) -> Self {
+        Self {
+            items,
+            errors: vec![],
+        }
+    }
+}
+
+impl<T> Deref for TolerantVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl<T: Serialize> Serialize for TolerantVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.items.serialize(serializer)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for TolerantVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<Value>::deserialize(deserializer)?;
+        let mut items = Vec::with_capacity(values.len());
+        let mut errors = vec![];
+        for (index, value) in values.into_iter().enumerate() {
+            match T::deserialize(value) {
+                Ok(item) => items.push(item),
+                Err(e) => errors.push((index, e.to_string())),
+            }
+        }
+        Ok(Self { items, errors })
+    }
+}
+
+/// A [`HashMap`] that deserializes entry-wise, so one malformed entry doesn't fail the whole map.
+///
+/// Entries with a key or value that fails to deserialize are dropped, recorded with their raw key in [`TolerantMap::errors`].
+/// Serializes transparently as the surviving entries.
+///
+/// Same constraints as [`TolerantVec`], use [`tolerant_map`] on a plain `HashMap<K, V>` field when the errors don't need keeping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TolerantMap<K: Eq + Hash, V> {
+    items: HashMap<K, V>,
+    errors: Vec<(String, String)>,
+}
+
+impl<K: Eq + Hash, V> TolerantMap<K, V> {
+    /// The raw keys of the entries that failed to deserialize, alongside why.
+    pub fn errors(&self) -> &[(String, String)] {
+        &self.errors
+    }
+
+    /// The successfully deserialized entries.
+    pub fn into_inner(self) -> HashMap<K, V> {
+        self.items
+    }
+}
+
+impl<K: Eq + Hash, V> Default for TolerantMap<K, V> {
+    fn default() -> Self {
+        Self {
+            items: HashMap::new(),
+            errors: vec![],
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> From<HashMap<K, V>> for TolerantMap<K, V> {
+    fn from(items: HashMap<K, V>) -> Self {
+        Self {
+            items,
+            errors: vec![],
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> Deref for TolerantMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+impl<K: Eq + Hash + Serialize, V: Serialize> Serialize for TolerantMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.items.serialize(serializer)
+    }
+}
+
+impl<'de, K: Eq + Hash + DeserializeOwned, V: DeserializeOwned> Deserialize<'de>
+    for TolerantMap<K, V>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = serde_json::Map::<String, Value>::deserialize(deserializer)?;
+        let mut items = HashMap::with_capacity(entries.len());
+        let mut errors = vec![];
+        for (raw_key, value) in entries {
+            let key = match deserialize_key::<K>(&raw_key) {
+                Ok(key) => key,
+                Err(e) => {
+                    errors.push((raw_key, e.to_string()));
+                    continue;
+                }
+            };
+            match V::deserialize(value) {
+                Ok(value) => {
+                    items.insert(key, value);
+                }
+                Err(e) => errors.push((raw_key, e.to_string())),
+            }
+        }
+        Ok(Self { items, errors })
+    }
+}
+
+/// Map keys are always strings once buffered, so also try parsing the key itself, for numeric keys like `{"1": ...}`.
+fn deserialize_key<K: DeserializeOwned>(raw_key: &str) -> Result<K, serde_json::Error> {
+    K::deserialize(Value::String(raw_key.to_string()))
+        .or_else(|e| serde_json::from_str::<K>(raw_key).map_err(|_| e))
+}
+
+/// For `#[serde(with = "bitbazaar::misc::tolerant_vec")]` on a `Vec<T>` field, deserializing it like a [`TolerantVec`].
+///
+/// There's nowhere to keep the failures, so each is logged as a warning instead.
+pub mod tolerant_vec {
+    use super::*;
+
+    /// Serializes the vec as normal.
+    pub fn serialize<S: Serializer, T: Serialize>(
+        items: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        items.serialize(serializer)
+    }
+
+    /// Deserializes element-wise, dropping and logging malformed elements.
+    pub fn deserialize<'de, D: Deserializer<'de>, T: DeserializeOwned>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error> {
+        let tolerant = TolerantVec::<T>::deserialize(deserializer)?;
+        for (index, e) in tolerant.errors() {
+            tracing::warn!("Dropped malformed element at index {}: {}", index, e);
+        }
+        Ok(tolerant.into_inner())
+    }
+}
+
+/// For `#[serde(with = "bitbazaar::misc::tolerant_map")]` on a `HashMap<K, V>` field, deserializing it like a [`TolerantMap`].
+///
+/// There's nowhere to keep the failures, so each is logged as a warning instead.
+pub mod tolerant_map {
+    use super::*;
+
+    /// Serializes the map as normal.
+    pub fn serialize<S: Serializer, K: Eq + Hash + Serialize, V: Serialize>(
+        items: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        items.serialize(serializer)
+    }
+
+    /// Deserializes entry-wise, dropping and logging malformed entries.
+    pub fn deserialize<
+        'de,
+        D: Deserializer<'de>,
+        K: Eq + Hash + DeserializeOwned,
+        V: DeserializeOwned,
+    >(
+        deserializer: D,
+    ) -> Result<HashMap<K, V>, D::Error> {
+        let tolerant = TolerantMap::<K, V>::deserialize(deserializer)?;
+        for (key, e) in tolerant.errors() {
+            tracing::warn!("Dropped malformed entry with key '{}': {}", key, e);
+        }
+        Ok(tolerant.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::prelude::*;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        id: u32,
+        name: String,
+    }
+
+    fn entry(id: u32, name: &str) -> Entry {
+        Entry {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    #[rstest]
+    fn test_tolerant_vec() {
+        let raw = r#"[{"id": 1, "name": "a"}, {"id": "oops"}, {"id": 3, "name": "c"}]"#;
+        let tolerant: TolerantVec<Entry> = serde_json::from_str(raw).unwrap();
+        assert_eq!(*tolerant, vec![entry(1, "a"), entry(3, "c")]);
+        assert_eq!(tolerant.errors().len(), 1);
+        assert_eq!(tolerant.errors()[0].0, 1);
+        assert!(!tolerant.errors()[0].1.is_empty());
+
+        // Only the surviving items are written back:
+        let written = serde_json::to_string(&tolerant).unwrap();
+        let reread: TolerantVec<Entry> = serde_json::from_str(&written).unwrap();
+        assert_eq!(*reread, *tolerant);
+        assert!(reread.errors().is_empty());
+
+        // The document itself still has to be an array:
+        assert!(serde_json::from_str::<TolerantVec<Entry>>(r#"{"id": 1}"#).is_err());
+    }
+
+    #[rstest]
+    fn test_tolerant_map() {
+        let raw = r#"{"a": {"id": 1, "name": "a"}, "b": [], "c": {"id": 3, "name": "c"}}"#;
+        let tolerant: TolerantMap<String, Entry> = serde_json::from_str(raw).unwrap();
+        assert_eq!(tolerant.len(), 2);
+        assert_eq!(tolerant.get("a"), Some(&entry(1, "a")));
+        assert_eq!(tolerant.get("c"), Some(&entry(3, "c")));
+        assert_eq!(tolerant.errors().len(), 1);
+        assert_eq!(tolerant.errors()[0].0, "b");
+
+        let written = serde_json::to_string(&tolerant).unwrap();
+        let reread: TolerantMap<String, Entry> = serde_json::from_str(&written).unwrap();
+        assert_eq!(*reread, *tolerant);
+
+        // Numeric keys are parsed, unparseable ones are recorded:
+        let tolerant: TolerantMap<u32, String> =
+            serde_json::from_str(r#"{"1": "a", "3": 4, "x": "b"}"#).unwrap();
+        assert_eq!(tolerant.len(), 1);
+        assert_eq!(tolerant.get(&1).map(String::as_str), Some("a"));
+        assert_eq!(
+            tolerant
+                .errors()
+                .iter()
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["3", "x"]
+        );
+    }
+
+    #[rstest]
+    fn test_tolerant_field_attrs() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            version: u32,
+            #[serde(with = "tolerant_vec")]
+            entries: Vec<Entry>,
+            #[serde(with = "tolerant_map", default)]
+            lookup: HashMap<String, u32>,
+        }
+
+        let raw = r#"{
+            "version": 2,
+            "entries": [{"id": 1, "name": "a"}, 5, {"id": 2, "name": "b"}],
+            "lookup": {"x": 1, "y": "two"}
+        }"#;
+        let config: Config = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                version: 2,
+                entries: vec![entry(1, "a"), entry(2, "b")],
+                lookup: HashMap::from([("x".to_string(), 1)]),
+            }
+        );
+        let reread: Config =
+            serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reread, config);
+
+        // Other fields are still strict:
+        assert!(serde_json::from_str::<Config>(r#"{"version": "2", "entries": []}"#).is_err());
+    }
+}
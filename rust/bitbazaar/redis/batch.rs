@@ -1,9 +1,15 @@
-use std::{collections::HashSet, marker::PhantomData};
+use std::{collections::HashSet, marker::PhantomData, panic::Location};
 
 use deadpool_redis::redis::{FromRedisValue, Pipeline, ToRedisArgs};
 use once_cell::sync::Lazy;
 
-use super::{RedisConn, RedisScript, RedisScriptInvoker};
+use super::{
+    batch_plan::BatchPlan,
+    slow_log::FireStats,
+    topic::{register_topic, EnvelopeOut},
+    RedisConn, RedisFuzzy, RedisScript, RedisScriptInvoker, RedisTopic, TtlJitter,
+};
+use crate::errors::prelude::*;
 
 static CLEAR_NAMESPACE_SCRIPT: Lazy<RedisScript> =
     Lazy::new(|| RedisScript::new(include_str!("lua_scripts/clear_namespace.lua")));
@@ -14,6 +20,71 @@ static MEXISTS_SCRIPT: Lazy<RedisScript> =
 static MSET_WITH_EXPIRY_SCRIPT: Lazy<RedisScript> =
     Lazy::new(|| RedisScript::new(include_str!("lua_scripts/mset_with_expiry.lua")));
 
+static INCR_BY_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/incr_by.lua")));
+static SET_IF_EQUALS_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/set_if_equals.lua")));
+
+static DEL_IF_EQUALS_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/del_if_equals.lua")));
+
+static RENAME_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/rename.lua")));
+
+static COPY_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/copy.lua")));
+
+/// Runs a single command, returning nil rather than failing the whole batch if it errors.
+static SOFT_FAIL_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/soft_fail.lua")));
+
+// Stands in for set algebra commands with no keys, which redis would reject:
+static EMPTY_ARRAY_SCRIPT: Lazy<RedisScript> = Lazy::new(|| RedisScript::new("return {}"));
+
+/// Options for [`RedisBatchReturningOps::copy`].
+#[derive(Debug, Clone, Default)]
+pub struct CopyOpts {
+    /// Replace the destination if it already exists, otherwise the copy doesn't happen.
+    pub overwrite: bool,
+    /// The ttl of the copy, defaults to [`CopyTtl::KeepSource`].
+    pub ttl: CopyTtl,
+}
+
+/// The ttl given to a copy, see [`CopyOpts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CopyTtl {
+    /// The source's ttl, the native COPY behaviour.
+    #[default]
+    KeepSource,
+    /// The ttl the destination had before being overwritten, no expiry if it didn't exist.
+    KeepTtl,
+    /// A new ttl, [`RedisBatch::ttl_jitter`] applies. (accurate to the millisecond)
+    Fresh(std::time::Duration),
+}
+
+/// Build a set algebra command (SINTER, SUNIONSTORE etc) over keys in a namespace, `None` when there are no keys.
+fn set_algebra_cmd<'key>(
+    redis_conn: &RedisConn<'_>,
+    cmd: &str,
+    dest: Option<&str>,
+    namespace: &str,
+    keys: impl IntoIterator<Item = &'key str>,
+) -> Option<redis::Cmd> {
+    let final_keys = keys
+        .into_iter()
+        .map(|key| redis_conn.final_key(namespace, key.into()))
+        .collect::<Vec<_>>();
+    if final_keys.is_empty() {
+        return None;
+    }
+    let mut cmd = redis::cmd(cmd);
+    if let Some(dest) = dest {
+        cmd.arg(redis_conn.final_key(namespace, dest.into()));
+    }
+    cmd.arg(final_keys);
+    Some(cmd)
+}
+
 /// A command builder struct. Committed with [`RedisBatch::fire`].
 ///
 /// Batched commands are run in order, but other commands from different sources may be interleaved.
@@ -24,6 +95,10 @@ pub struct RedisBatch<'a, 'b, 'c, ReturnType> {
     pipe: Pipeline,
     /// Need to keep a reference to used scripts, these will all be reloaded to redis errors because one wasn't cached on the server.
     used_scripts: HashSet<&'c RedisScript>,
+    /// Set with [`RedisBatch::ttl_jitter`].
+    ttl_jitter: Option<TtlJitter>,
+    /// The indices of the commands in the pipe that don't return, needed to rebuild it in [`RedisBatch::allow_fail`].
+    ignored_cmds: HashSet<usize>,
 }
 
 impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
@@ -33,10 +108,26 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
             redis_conn,
             pipe: deadpool_redis::redis::pipe(),
             used_scripts: HashSet::new(),
+            ttl_jitter: None,
+            ignored_cmds: HashSet::new(),
+        }
+    }
+
+    async fn inner_fire<R: FromRedisValue>(
+        &mut self,
+        caller: &'static Location<'static>,
+    ) -> Option<R> {
+        // Only timed when the slow log is enabled:
+        let started = self.redis_conn.slow_log.map(|_| std::time::Instant::now());
+        let mut stats = FireStats::default();
+        let result = self.inner_fire_attempts(&mut stats).await;
+        if let (Some(slow_log), Some(started)) = (self.redis_conn.slow_log, started) {
+            slow_log.check(started, self.redis_conn.prefix, &self.pipe, stats, caller);
         }
+        result
     }
 
-    async fn inner_fire<R: FromRedisValue>(&mut self) -> Option<R> {
+    async fn inner_fire_attempts<R: FromRedisValue>(&mut self, stats: &mut FireStats) -> Option<R> {
         if let Some(conn) = self.redis_conn.get_inner_conn().await {
             match self.pipe.query_async(conn).await {
                 Ok(result) => Some(result),
@@ -55,6 +146,7 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
                             err
                         );
 
+                        stats.scripts_reloaded = true;
                         let mut load_pipe = deadpool_redis::redis::pipe();
                         for script in &self.used_scripts {
                             load_pipe.add_command(script.load_cmd());
@@ -64,13 +156,16 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
                             .await
                         {
                             // Now loaded the scripts, rerun the batch:
-                            Ok(_) => match self.pipe.query_async(conn).await {
-                                Ok(result) => Some(result),
-                                Err(err) => {
-                                    tracing::error!("Redis batch failed. Second attempt as first required reloading of scripts (not necessarily related). Err: '{}'", err);
-                                    None
+                            Ok(_) => {
+                                stats.retries += 1;
+                                match self.pipe.query_async(conn).await {
+                                    Ok(result) => Some(result),
+                                    Err(err) => {
+                                        tracing::error!("Redis batch failed. Second attempt as first required reloading of scripts (not necessarily related). Err: '{}'", err);
+                                        None
+                                    }
                                 }
-                            },
+                            }
                             Err(err) => {
                                 tracing::error!(
                                     "Redis script reload during batch failed. Err: '{}'",
@@ -92,14 +187,179 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
 
     /// Run an arbitrary redis (lua script). But discards any return value.
     pub fn script_no_return(mut self, script_invokation: RedisScriptInvoker<'c>) -> Self {
-        // Adding ignore() to ignore response.
-        self.pipe.add_command(script_invokation.eval_cmd()).ignore();
+        self.pipe.add_command(script_invokation.eval_cmd());
+        // Ignoring the response.
+        self.ignore_last();
         self.used_scripts.insert(script_invokation.script);
         RedisBatch {
             _returns: PhantomData,
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
+        }
+    }
+
+    /// Publish a message to a pubsub channel, prefer [`RedisBatch::publish_topic`] to bind the channel to its payload type.
+    ///
+    /// https://redis.io/commands/publish/
+    pub fn publish<T: ToRedisArgs>(mut self, namespace: &str, channel: &str, message: T) -> Self {
+        self.pipe.publish(
+            self.redis_conn.final_key(namespace, channel.into()),
+            message,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
+
+        RedisBatch {
+            _returns: PhantomData,
+            redis_conn: self.redis_conn,
+            pipe: self.pipe,
+            used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
+        }
+    }
+
+    /// Publish a payload as json wrapped in an envelope recording when and by which [`super::Redis::origin`] it was published:
+    /// `{"ts": <utc millis>, "origin": <origin>, "payload": <payload>}`.
+    ///
+    /// Receive the metadata with [`super::Redis::subscribe_enveloped`], plain subscribers unwrap it transparently.
+    /// Plain publishers can't produce envelopes, so enveloped subscribers receive their messages with the metadata `None`.
+    pub fn publish_enveloped<T: serde::Serialize>(
+        self,
+        namespace: &str,
+        channel: &str,
+        payload: &T,
+    ) -> Self {
+        let envelope = EnvelopeOut {
+            ts: chrono::Utc::now().timestamp_millis(),
+            origin: self.redis_conn.origin,
+            payload,
+        };
+        match serde_json::to_vec(&envelope) {
+            Ok(message) => self.publish(namespace, channel, message),
+            Err(e) => {
+                tracing::error!(
+                    "Could not encode enveloped payload for redis channel '{}:{}', not publishing. Err: '{}'",
+                    namespace,
+                    channel,
+                    e
+                );
+                self
+            }
+        }
+    }
+
+    /// Publish a payload to a typed topic as json, received with [`super::Redis::subscribe_topic`].
+    pub fn publish_topic<T: RedisTopic>(self, payload: &T::Payload) -> Self {
+        register_topic::<T>();
+        match serde_json::to_vec(payload) {
+            Ok(message) => self.publish(T::NAMESPACE, T::CHANNEL, message),
+            Err(e) => {
+                tracing::error!(
+                    "Could not encode payload for redis topic '{}:{}', not publishing. Err: '{}'",
+                    T::NAMESPACE,
+                    T::CHANNEL,
+                    e
+                );
+                self
+            }
+        }
+    }
+
+    /// Randomly spread the ttls of the writes added after this call, so they don't all expire at once.
+    ///
+    /// Applied separately to each key, by [`RedisBatch::set`], [`RedisBatch::mset`], [`RedisBatch::expire`]
+    /// (so also the set ttls of e.g. [`RedisBatch::sadd`] and [`RedisBatch::zadd`]),
+    /// [`RedisBatchReturningOps::incr`] and [`RedisBatchReturningOps::hincrby`].
+    pub fn ttl_jitter(mut self, jitter: TtlJitter) -> Self {
+        self.ttl_jitter = Some(jitter);
+        self
+    }
+
+    /// Don't fail the whole batch if the previous command errors, e.g. WRONGTYPE from a [`RedisBatchReturningOps::smembers`]
+    /// on a key holding a string. Its slot decodes as if the command returned nil instead:
+    /// None for `Option` slots, empty for collections and false for bools, the error itself isn't logged.
+    ///
+    /// Must directly follow a returning op, by default any command erroring fails the batch, returning `None`.
+    ///
+    /// The command is run through a lua script that catches the error, so not supported for script based ops
+    /// (e.g. [`RedisBatchReturningOps::script`] or [`RedisBatchReturningOps::mexists`]), which are left unchanged.
+    /// Slots that can't decode nil (e.g. the count of [`RedisBatchReturningOps::scard`]) will still fail the batch when soft-failed.
+    pub fn allow_fail(mut self) -> Self {
+        let cmds = self.pipe.cmd_iter().cloned().collect::<Vec<_>>();
+        let Some((last, preceding)) = cmds.split_last() else {
+            tracing::warn!("allow_fail() called on an empty redis batch, ignoring.");
+            return self;
+        };
+        if self.ignored_cmds.contains(&preceding.len()) {
+            tracing::warn!(
+                "allow_fail() must directly follow a returning redis batch op, ignoring."
+            );
+            return self;
+        }
+        let args = last
+            .args_iter()
+            .filter_map(|arg| match arg {
+                redis::Arg::Simple(arg) => Some(arg),
+                redis::Arg::Cursor => None,
+            })
+            .collect::<Vec<_>>();
+        if args
+            .first()
+            .map(|name| name.eq_ignore_ascii_case(b"EVALSHA"))
+            .unwrap_or(false)
+        {
+            tracing::warn!(
+                "allow_fail() not supported for script based redis batch ops, ignoring."
+            );
+            return self;
+        }
+
+        // Commands can't be removed from a pipe, so rebuilding with the last replaced:
+        let mut pipe = deadpool_redis::redis::pipe();
+        for (index, cmd) in preceding.iter().enumerate() {
+            pipe.add_command(cmd.clone());
+            if self.ignored_cmds.contains(&index) {
+                pipe.ignore();
+            }
+        }
+        let invoker = args
+            .into_iter()
+            .fold(SOFT_FAIL_SCRIPT.invoker(), |invoker, arg| invoker.arg(arg));
+        pipe.add_command(invoker.eval_cmd());
+        self.used_scripts.insert(&SOFT_FAIL_SCRIPT);
+        self.pipe = pipe;
+        self
+    }
+
+    /// What the batch would send to redis if fired now, e.g. to debug a misbehaving batch or assert namespacing in tests.
+    ///
+    /// Doesn't touch redis or affect a later fire.
+    pub fn explain(&self) -> BatchPlan {
+        BatchPlan::from_pipe(self.redis_conn.prefix, &self.pipe, |index| {
+            self.ignored_cmds.contains(&index)
+        })
+    }
+
+    /// The connection the batch will be fired on, e.g. to build final keys for script invocations.
+    pub(crate) fn conn(&self) -> &RedisConn<'b> {
+        self.redis_conn
+    }
+
+    /// Ignore the response of the last command added to the pipe, so it doesn't take up a slot.
+    fn ignore_last(&mut self) {
+        self.pipe.ignore();
+        self.ignored_cmds.insert(self.pipe.cmd_iter().count() - 1);
+    }
+
+    /// The ttl to actually use for a key, after any [`RedisBatch::ttl_jitter`].
+    fn jittered(&self, ttl: std::time::Duration) -> std::time::Duration {
+        match &self.ttl_jitter {
+            Some(jitter) => jitter.apply(ttl),
+            None => ttl,
         }
     }
 
@@ -107,19 +367,21 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
     ///
     /// https://redis.io/commands/pexpire/
     pub fn expire(mut self, namespace: &str, key: &str, ttl: std::time::Duration) -> Self {
-        self.pipe
-            .pexpire(
-                self.redis_conn.final_key(namespace, key.into()),
-                ttl.as_millis() as i64,
-            )
-            // Ignoring so it doesn't take up a space in the tuple response.
-            .ignore();
+        let ttl = self.jittered(ttl);
+        self.pipe.pexpire(
+            self.redis_conn.final_key(namespace, key.into()),
+            ttl.as_millis() as i64,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
 
         RedisBatch {
             _returns: PhantomData,
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
         }
     }
 
@@ -140,14 +402,13 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         score: i64,
         value: impl ToRedisArgs,
     ) -> Self {
-        self.pipe
-            .zadd(
-                self.redis_conn.final_key(set_namespace, set_key.into()),
-                value,
-                score,
-            )
-            // Ignoring so it doesn't take up a space in the tuple response.
-            .ignore();
+        self.pipe.zadd(
+            self.redis_conn.final_key(set_namespace, set_key.into()),
+            value,
+            score,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
         if let Some(set_ttl) = set_ttl {
             self.expire(set_namespace, set_key, set_ttl)
         } else {
@@ -156,6 +417,8 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
                 redis_conn: self.redis_conn,
                 pipe: self.pipe,
                 used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
             }
         }
     }
@@ -178,18 +441,19 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         if members.is_empty() {
             return self;
         }
-        self.pipe
-            .zrem(
-                self.redis_conn.final_key(set_namespace, set_key.into()),
-                members,
-            )
-            // Ignoring so it doesn't take up a space in the tuple response.
-            .ignore();
+        self.pipe.zrem(
+            self.redis_conn.final_key(set_namespace, set_key.into()),
+            members,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
         RedisBatch {
             _returns: PhantomData,
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
         }
     }
 
@@ -208,13 +472,12 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         set_ttl: Option<std::time::Duration>,
         items: &[(i64, impl ToRedisArgs)],
     ) -> Self {
-        self.pipe
-            .zadd_multiple(
-                self.redis_conn.final_key(set_namespace, set_key.into()),
-                items,
-            )
-            // Ignoring so it doesn't take up a space in the tuple response.
-            .ignore();
+        self.pipe.zadd_multiple(
+            self.redis_conn.final_key(set_namespace, set_key.into()),
+            items,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
         if let Some(set_ttl) = set_ttl {
             self.expire(set_namespace, set_key, set_ttl)
         } else {
@@ -223,6 +486,8 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
                 redis_conn: self.redis_conn,
                 pipe: self.pipe,
                 used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
             }
         }
     }
@@ -237,19 +502,275 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         min: i64,
         max: i64,
     ) -> Self {
-        self.pipe
-            .zrembyscore(
-                self.redis_conn.final_key(set_namespace, set_key.into()),
-                min,
-                max,
-            )
-            // Ignoring so it doesn't take up a space in the tuple response.
-            .ignore();
+        self.pipe.zrembyscore(
+            self.redis_conn.final_key(set_namespace, set_key.into()),
+            min,
+            max,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
+        RedisBatch {
+            _returns: PhantomData,
+            redis_conn: self.redis_conn,
+            pipe: self.pipe,
+            used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
+        }
+    }
+
+    /// Set multiple fields of a hash (auto creating the hash if it doesn't exist).
+    /// https://redis.io/commands/hset/
+    ///
+    /// Arguments:
+    /// - `hashmap_namespace`: The namespace of the hash.
+    /// - `hashmap_key`: The key of the hash.
+    /// - `hashmap_ttl`: The time to live of the whole hash, reset on each call when provided. (fields can't expire individually)
+    /// - `pairs`: The fields and their values.
+    pub fn hset_multi<Value: ToRedisArgs>(
+        mut self,
+        hashmap_namespace: &str,
+        hashmap_key: &str,
+        hashmap_ttl: Option<std::time::Duration>,
+        pairs: impl IntoIterator<Item = (impl AsRef<str>, Value)>,
+    ) -> Self {
+        let pairs = pairs
+            .into_iter()
+            .map(|(field, value)| (field.as_ref().to_string(), value))
+            .collect::<Vec<_>>();
+        // No-op if no pairs so skip (redis would actually error if empty anyway)
+        if pairs.is_empty() {
+            return self;
+        }
+        self.pipe.hset_multiple(
+            self.redis_conn
+                .final_key(hashmap_namespace, hashmap_key.into()),
+            &pairs,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
+        if let Some(hashmap_ttl) = hashmap_ttl {
+            self.expire(hashmap_namespace, hashmap_key, hashmap_ttl)
+        } else {
+            RedisBatch {
+                _returns: PhantomData,
+                redis_conn: self.redis_conn,
+                pipe: self.pipe,
+                used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
+            }
+        }
+    }
+
+    /// Remove fields from a hash.
+    /// https://redis.io/commands/hdel/
+    pub fn hdel(
+        mut self,
+        hashmap_namespace: &str,
+        hashmap_key: &str,
+        fields: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|field| field.as_ref().to_string())
+            .collect::<Vec<_>>();
+        // No-op if no fields so skip (redis would actually error if empty anyway)
+        if fields.is_empty() {
+            return self;
+        }
+        self.pipe.hdel(
+            self.redis_conn
+                .final_key(hashmap_namespace, hashmap_key.into()),
+            fields,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
         RedisBatch {
             _returns: PhantomData,
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
+        }
+    }
+
+    /// Add members to a set (auto creating the set if it doesn't exist).
+    /// https://redis.io/commands/sadd/
+    ///
+    /// Arguments:
+    /// - `set_namespace`: The namespace of the set.
+    /// - `set_key`: The key of the set.
+    /// - `set_ttl`: The time to live of the set. This will reset on each addition, meaning after the last update the set will expire after this time.
+    /// - `members`: The members to add as an iterator.
+    pub fn sadd<T: ToRedisArgs>(
+        mut self,
+        set_namespace: &str,
+        set_key: &str,
+        set_ttl: Option<std::time::Duration>,
+        members: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let members = members.into_iter().collect::<Vec<_>>();
+        // No-op if no members so skip (redis would actually error if empty anyway)
+        if members.is_empty() {
+            return self;
+        }
+        self.pipe.sadd(
+            self.redis_conn.final_key(set_namespace, set_key.into()),
+            members,
+        );
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
+        if let Some(set_ttl) = set_ttl {
+            self.expire(set_namespace, set_key, set_ttl)
+        } else {
+            RedisBatch {
+                _returns: PhantomData,
+                redis_conn: self.redis_conn,
+                pipe: self.pipe,
+                used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
+            }
+        }
+    }
+
+    /// Append values to the end of a list (auto creating the list if it doesn't exist).
+    /// https://redis.io/commands/rpush/
+    ///
+    /// Arguments:
+    /// - `list_namespace`: The namespace of the list.
+    /// - `list_key`: The key of the list.
+    /// - `list_ttl`: The time to live of the list. This will reset on each push, meaning after the last update the list will expire after this time.
+    /// - `values`: The values to push as an iterator, in order.
+    pub fn rpush<T: ToRedisArgs>(
+        self,
+        list_namespace: &str,
+        list_key: &str,
+        list_ttl: Option<std::time::Duration>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        self.push_inner("RPUSH", list_namespace, list_key, list_ttl, values)
+    }
+
+    /// Prepend values to the start of a list (auto creating the list if it doesn't exist).
+    /// Each value is pushed to the front in turn, so they end up in reverse order.
+    /// https://redis.io/commands/lpush/
+    ///
+    /// Arguments as [`RedisBatch::rpush`].
+    pub fn lpush<T: ToRedisArgs>(
+        self,
+        list_namespace: &str,
+        list_key: &str,
+        list_ttl: Option<std::time::Duration>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        self.push_inner("LPUSH", list_namespace, list_key, list_ttl, values)
+    }
+
+    fn push_inner<T: ToRedisArgs>(
+        mut self,
+        push_cmd: &str,
+        list_namespace: &str,
+        list_key: &str,
+        list_ttl: Option<std::time::Duration>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let values = values.into_iter().collect::<Vec<_>>();
+        // No-op if no values so skip (redis would actually error if empty anyway)
+        if values.is_empty() {
+            return self;
+        }
+        self.pipe
+            .cmd(push_cmd)
+            .arg(self.redis_conn.final_key(list_namespace, list_key.into()))
+            .arg(values);
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.ignore_last();
+        if let Some(list_ttl) = list_ttl {
+            self.expire(list_namespace, list_key, list_ttl)
+        } else {
+            RedisBatch {
+                _returns: PhantomData,
+                redis_conn: self.redis_conn,
+                pipe: self.pipe,
+                used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
+            }
+        }
+    }
+
+    /// Store the intersection of the sets in `dest_key`, replacing anything already there.
+    /// A no-op when no keys are given, missing sets are treated as empty.
+    /// https://redis.io/commands/sinterstore/
+    ///
+    /// Arguments:
+    /// - `namespace`: The namespace of all the sets.
+    /// - `dest_key`: The key to store the result in.
+    /// - `keys`: The keys of the sets to intersect.
+    /// - `dest_ttl`: The time to live of the result, none for no expiry.
+    pub fn sinterstore<'key>(
+        self,
+        namespace: &str,
+        dest_key: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+        dest_ttl: Option<std::time::Duration>,
+    ) -> Self {
+        self.set_algebra_store("SINTERSTORE", namespace, dest_key, keys, dest_ttl)
+    }
+
+    /// Store the union of the sets in `dest_key`, see [`RedisBatch::sinterstore`].
+    /// https://redis.io/commands/sunionstore/
+    pub fn sunionstore<'key>(
+        self,
+        namespace: &str,
+        dest_key: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+        dest_ttl: Option<std::time::Duration>,
+    ) -> Self {
+        self.set_algebra_store("SUNIONSTORE", namespace, dest_key, keys, dest_ttl)
+    }
+
+    /// Store the members of the first set that aren't in any of the others in `dest_key`, see [`RedisBatch::sinterstore`].
+    /// https://redis.io/commands/sdiffstore/
+    pub fn sdiffstore<'key>(
+        self,
+        namespace: &str,
+        dest_key: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+        dest_ttl: Option<std::time::Duration>,
+    ) -> Self {
+        self.set_algebra_store("SDIFFSTORE", namespace, dest_key, keys, dest_ttl)
+    }
+
+    fn set_algebra_store<'key>(
+        mut self,
+        cmd: &str,
+        namespace: &str,
+        dest_key: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+        dest_ttl: Option<std::time::Duration>,
+    ) -> Self {
+        let Some(cmd) = set_algebra_cmd(self.redis_conn, cmd, Some(dest_key), namespace, keys)
+        else {
+            return self;
+        };
+        // Ignoring so it doesn't take up a space in the tuple response.
+        self.pipe.add_command(cmd);
+        self.ignore_last();
+        if let Some(dest_ttl) = dest_ttl {
+            self.expire(namespace, dest_key, dest_ttl)
+        } else {
+            RedisBatch {
+                _returns: PhantomData,
+                redis_conn: self.redis_conn,
+                pipe: self.pipe,
+                used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
+            }
         }
     }
 
@@ -268,14 +789,16 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         if let Some(expiry) = expiry {
             // If expiry is weirdly 0 don't send to prevent redis error:
             if (expiry) > std::time::Duration::from_millis(0) {
+                let expiry = self.jittered(expiry);
                 // Ignoring so it doesn't take up a space in the tuple response.
                 self.pipe
-                    .pset_ex(final_key, value, expiry.as_millis() as u64)
-                    .ignore();
+                    .pset_ex(final_key, value, expiry.as_millis() as u64);
+                self.ignore_last();
             }
         } else {
             // Ignoring so it doesn't take up a space in the tuple response.
-            self.pipe.set(final_key, value).ignore();
+            self.pipe.set(final_key, value);
+            self.ignore_last();
         }
 
         RedisBatch {
@@ -283,6 +806,8 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
         }
     }
 
@@ -308,11 +833,13 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         if let Some(expiry) = expiry {
             // If expiry is weirdly 0 don't send to prevent redis error:
             if (expiry) > std::time::Duration::from_millis(0) {
-                let mut invoker = MSET_WITH_EXPIRY_SCRIPT
-                    .invoker()
-                    .arg(expiry.as_millis() as u64);
+                let mut invoker = MSET_WITH_EXPIRY_SCRIPT.invoker();
                 for (key, value) in final_pairs {
-                    invoker = invoker.key(key).arg(value);
+                    // Each key jittered separately:
+                    invoker = invoker
+                        .key(key)
+                        .arg(self.jittered(expiry).as_millis() as u64)
+                        .arg(value);
                 }
                 self.script_no_return(invoker)
             } else {
@@ -321,16 +848,21 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
                     used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
         } else {
             // Ignoring so it doesn't take up a space in the tuple response.
-            self.pipe.mset(&final_pairs).ignore();
+            self.pipe.mset(&final_pairs);
+            self.ignore_last();
             RedisBatch {
                 _returns: PhantomData,
                 redis_conn: self.redis_conn,
                 pipe: self.pipe,
                 used_scripts: self.used_scripts,
+                ttl_jitter: self.ttl_jitter,
+                ignored_cmds: self.ignored_cmds,
             }
         }
     }
@@ -347,12 +879,15 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
             .map(|key| self.redis_conn.final_key(namespace, key))
             .collect::<Vec<_>>();
         // Ignoring so it doesn't take up a space in the tuple response.
-        self.pipe.del(final_keys).ignore();
+        self.pipe.del(final_keys);
+        self.ignore_last();
         RedisBatch {
             _returns: PhantomData,
             redis_conn: self.redis_conn,
             pipe: self.pipe,
             used_scripts: self.used_scripts,
+            ttl_jitter: self.ttl_jitter,
+            ignored_cmds: self.ignored_cmds,
         }
     }
 
@@ -361,6 +896,76 @@ impl<'a, 'b, 'c, ReturnType> RedisBatch<'a, 'b, 'c, ReturnType> {
         let final_namespace = self.redis_conn.final_namespace(namespace);
         self.script_no_return(CLEAR_NAMESPACE_SCRIPT.invoker().arg(final_namespace))
     }
+
+    /// Commit the batch, passing the raw results to the closure rather than decoding them into the batch's types.
+    ///
+    /// Useful for large payloads, values can be decoded borrowing straight from the response buffer with [`BorrowedBatchResult::json`],
+    /// rather than first allocating an owned copy. The buffer only lives for the duration of the closure.
+    ///
+    /// `None` (without calling the closure) if redis is unavailable, see [`RedisBatchFire::fire`].
+    #[track_caller]
+    pub fn fire_with<R, F: for<'v> FnOnce(BorrowedBatchResult<'v>) -> R>(
+        mut self,
+        f: F,
+    ) -> impl std::future::Future<Output = Option<R>> + use<'a, 'b, 'c, ReturnType, R, F> {
+        let caller = Location::caller();
+        async move {
+            self.inner_fire::<Vec<redis::Value>>(caller)
+                .await
+                .map(|values| f(BorrowedBatchResult { values: &values }))
+        }
+    }
+}
+
+/// The raw results of a batch, see [`RedisBatch::fire_with`].
+///
+/// Slots are in the same order as the batch's usual return tuple, commands that don't return (e.g. [`RedisBatch::set`]) don't take a slot.
+#[derive(Debug, Clone, Copy)]
+pub struct BorrowedBatchResult<'v> {
+    values: &'v [redis::Value],
+}
+
+impl<'v> BorrowedBatchResult<'v> {
+    /// The number of slots.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// True when the batch had no returning commands.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The raw value of a slot, None if out of range.
+    pub fn raw(&self, slot: usize) -> Option<&'v redis::Value> {
+        self.values.get(slot)
+    }
+
+    /// The bytes of a slot, None if out of range or not bytes, e.g. the key was missing.
+    pub fn bytes(&self, slot: usize) -> Option<&'v [u8]> {
+        match self.values.get(slot)? {
+            redis::Value::Data(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Decode the json in a slot, borrowing from the response where the type allows (e.g. `&str` or `#[serde(borrow)] Cow<str>` fields).
+    ///
+    /// None if out of range or not bytes (e.g. the key was missing), Some(Err) if the value couldn't be decoded as `T`.
+    pub fn json<T: serde::Deserialize<'v>>(&self, slot: usize) -> Option<RResult<T, AnyErr>> {
+        let data = self.bytes(slot)?;
+        Some(
+            serde_json::from_slice(data)
+                .change_context(AnyErr)
+                .attach_printable_lazy(|| {
+                    format!(
+                        "Failed to decode slot {} as '{}'.",
+                        slot,
+                        std::any::type_name::<T>()
+                    )
+                }),
+        )
+    }
 }
 
 /// Trait implementing the fire() method on a batch, variable over the items in the batch.
@@ -370,6 +975,9 @@ pub trait RedisBatchFire {
 
     /// Commit the batch and return the result.
     /// If redis unavailable, or the types didn't match causing decoding to fail, `None` will be returned and the error logged.
+    ///
+    /// The calling location is recorded in the slow log, see [`super::Redis::slow_batch_log`].
+    #[track_caller]
     fn fire(self) -> impl std::future::Future<Output = Option<Self::ReturnType>>;
 }
 
@@ -377,8 +985,10 @@ pub trait RedisBatchFire {
 impl<'a, 'b, 'c, R: FromRedisValue> RedisBatchFire for RedisBatch<'a, 'b, 'c, (R,)> {
     type ReturnType = R;
 
-    async fn fire(mut self) -> Option<R> {
-        self.inner_fire().await.map(|(r,)| r)
+    #[track_caller]
+    fn fire(mut self) -> impl std::future::Future<Output = Option<R>> {
+        let caller = Location::caller();
+        async move { self.inner_fire(caller).await.map(|(r,)| r) }
     }
 }
 
@@ -387,8 +997,10 @@ macro_rules! impl_batch_fire {
         impl<'a, 'b, 'c, $($tup_item: FromRedisValue),*> RedisBatchFire for RedisBatch<'a, 'b, 'c, ($($tup_item,)*)> {
             type ReturnType = ($($tup_item,)*);
 
-            async fn fire(mut self) -> Option<($($tup_item,)*)> {
-                self.inner_fire().await
+            #[track_caller]
+            fn fire(mut self) -> impl std::future::Future<Output = Option<($($tup_item,)*)>> {
+                let caller = Location::caller();
+                async move { self.inner_fire(caller).await }
             }
         }
     );
@@ -429,6 +1041,211 @@ pub trait RedisBatchReturningOps<'c> {
         keys: impl IntoIterator<Item = impl AsRef<str>>,
     ) -> Self::NextType<Vec<Option<Value>>>;
 
+    /// Compare-and-set: set a key to `new_value` only if its current value equals `expected`, returning true if set.
+    /// Useful for small state machines, e.g. transition from "pending" to "running" only if still "pending".
+    ///
+    /// The comparison is byte-exact on the encoded values, e.g. [`super::RedisJson`] values only match
+    /// when serialized identically (same field order and formatting), so compare with values encoded the same way.
+    ///
+    /// If the key doesn't exist, nothing is set, see [`RedisBatchReturningOps::set_if_absent_or_equals`] to allow this.
+    ///
+    /// `expiry` is only applied when the set happens, when `None` the key won't expire, like [`RedisBatch::set`].
+    /// (expiry accurate to the millisecond)
+    fn set_if_equals(
+        self,
+        namespace: &str,
+        key: &str,
+        expected: impl ToRedisArgs,
+        new_value: impl ToRedisArgs,
+        expiry: Option<std::time::Duration>,
+    ) -> Self::NextType<bool>;
+
+    /// Same as [`RedisBatchReturningOps::set_if_equals`], but the set also happens when the key doesn't exist yet.
+    fn set_if_absent_or_equals(
+        self,
+        namespace: &str,
+        key: &str,
+        expected: impl ToRedisArgs,
+        new_value: impl ToRedisArgs,
+        expiry: Option<std::time::Duration>,
+    ) -> Self::NextType<bool>;
+
+    /// Delete a key only if its current value equals `expected`, returning true if deleted.
+    ///
+    /// The comparison is byte-exact on the encoded values, see [`RedisBatchReturningOps::set_if_equals`].
+    fn del_if_equals(
+        self,
+        namespace: &str,
+        key: &str,
+        expected: impl ToRedisArgs,
+    ) -> Self::NextType<bool>;
+
+    /// Get all the members of a set, empty if it doesn't exist.
+    /// https://redis.io/commands/smembers/
+    fn smembers(self, set_namespace: &str, set_key: &str) -> Self::NextType<Vec<String>>;
+
+    /// The number of members in a set, 0 if it doesn't exist.
+    /// https://redis.io/commands/scard/
+    fn scard(self, set_namespace: &str, set_key: &str) -> Self::NextType<i64>;
+
+    /// The number of members in an ordered set, 0 if it doesn't exist.
+    /// https://redis.io/commands/zcard/
+    fn zcard(self, set_namespace: &str, set_key: &str) -> Self::NextType<i64>;
+
+    /// The number of values in a list, 0 if it doesn't exist.
+    /// https://redis.io/commands/llen/
+    fn llen(self, list_namespace: &str, list_key: &str) -> Self::NextType<i64>;
+
+    /// Retrieve values from a list by index range, inclusive, negative indexes count back from the end (e.g. 0 to -1 for all).
+    /// Values that cannot be decoded into the specified type are returned as `None`, rather than failing the batch.
+    /// https://redis.io/commands/lrange/
+    fn lrange<Value: FromRedisValue>(
+        self,
+        list_namespace: &str,
+        list_key: &str,
+        start: isize,
+        stop: isize,
+    ) -> Self::NextType<Vec<RedisFuzzy<Value>>>;
+
+    /// Remove and return up to `count` values from the start of a list, empty if it doesn't exist.
+    /// Values that cannot be decoded into the specified type are returned as `None` (but are still removed), rather than failing the batch.
+    /// https://redis.io/commands/lpop/
+    fn lpop<Value: FromRedisValue>(
+        self,
+        list_namespace: &str,
+        list_key: &str,
+        count: usize,
+    ) -> Self::NextType<Vec<RedisFuzzy<Value>>>;
+
+    /// The remaining time to live of a key in milliseconds, -1 if it has no expiry, -2 if it doesn't exist.
+    /// https://redis.io/commands/pttl/
+    fn pttl(self, namespace: &str, key: &str) -> Self::NextType<i64>;
+
+    /// Rename a key, keeping its value and ttl, returning true if renamed.
+    ///
+    /// When `overwrite` is false, an existing `to_key` isn't replaced (RENAMENX) and false is returned.
+    /// A missing `from_key` returns false, without failing the rest of the batch.
+    ///
+    /// https://redis.io/commands/rename/
+    fn rename(
+        self,
+        namespace: &str,
+        from_key: &str,
+        to_key: &str,
+        overwrite: bool,
+    ) -> Self::NextType<bool>;
+
+    /// [`RedisBatchReturningOps::rename`], but moving the key between namespaces.
+    fn rename_across(
+        self,
+        from_namespace: &str,
+        from_key: &str,
+        to_namespace: &str,
+        to_key: &str,
+        overwrite: bool,
+    ) -> Self::NextType<bool>;
+
+    /// Copy a key's value to another key, returning true if copied.
+    ///
+    /// An existing `to_key` is only replaced with [`CopyOpts::overwrite`], otherwise false is returned.
+    /// A missing `from_key` returns false, without failing the rest of the batch.
+    ///
+    /// Requires redis 6.2+. https://redis.io/commands/copy/
+    fn copy(
+        self,
+        namespace: &str,
+        from_key: &str,
+        to_key: &str,
+        opts: CopyOpts,
+    ) -> Self::NextType<bool>;
+
+    /// [`RedisBatchReturningOps::copy`], but copying the key between namespaces.
+    fn copy_across(
+        self,
+        from_namespace: &str,
+        from_key: &str,
+        to_namespace: &str,
+        to_key: &str,
+        opts: CopyOpts,
+    ) -> Self::NextType<bool>;
+
+    /// Atomically increment an integer key by `by` (negative to decrement), returning the new value.
+    /// A missing key starts from 0.
+    ///
+    /// `expiry` is reset on each increment when provided, when `None` the ttl is left as is.
+    /// (expiry accurate to the millisecond)
+    ///
+    /// Returns `None` if the existing value isn't an integer, without failing the rest of the batch.
+    fn incr(
+        self,
+        namespace: &str,
+        key: &str,
+        by: i64,
+        expiry: Option<std::time::Duration>,
+    ) -> Self::NextType<Option<i64>>;
+
+    /// Atomically increment an integer field of a hash by `by` (negative to decrement), returning the new value.
+    /// A missing hash or field starts from 0.
+    ///
+    /// `hashmap_ttl` is the time to live of the whole hash, reset on each increment when provided, see [`RedisBatchReturningOps::incr`].
+    ///
+    /// Returns `None` if the existing field isn't an integer, without failing the rest of the batch.
+    fn hincrby(
+        self,
+        hashmap_namespace: &str,
+        hashmap_key: &str,
+        field: &str,
+        by: i64,
+        hashmap_ttl: Option<std::time::Duration>,
+    ) -> Self::NextType<Option<i64>>;
+
+    /// Get multiple fields (HMGET) of a hash, of the same type at once. Returning `None` for each field (or the whole hash) that didn't exist.
+    /// https://redis.io/commands/hmget/
+    ///
+    /// At least one field must be given, redis errors otherwise.
+    fn hmget<Value>(
+        self,
+        hashmap_namespace: &str,
+        hashmap_key: &str,
+        fields: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self::NextType<Vec<Option<Value>>>;
+
+    /// Get all the fields and values of a hash, empty if it doesn't exist.
+    /// https://redis.io/commands/hgetall/
+    fn hgetall(
+        self,
+        namespace: &str,
+        key: &str,
+    ) -> Self::NextType<std::collections::HashMap<String, String>>;
+
+    /// The members in all of the sets, empty when no keys are given, missing sets are treated as empty.
+    /// https://redis.io/commands/sinter/
+    ///
+    /// Arguments:
+    /// - `namespace`: The namespace of all the sets.
+    /// - `keys`: The keys of the sets.
+    fn sinter<'key>(
+        self,
+        namespace: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+    ) -> Self::NextType<Vec<String>>;
+
+    /// The members in any of the sets, see [`RedisBatchReturningOps::sinter`].
+    /// https://redis.io/commands/sunion/
+    fn sunion<'key>(
+        self,
+        namespace: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+    ) -> Self::NextType<Vec<String>>;
+
+    /// The members of the first set that aren't in any of the others, see [`RedisBatchReturningOps::sinter`].
+    /// https://redis.io/commands/sdiff/
+    fn sdiff<'key>(
+        self,
+        namespace: &str,
+        keys: impl IntoIterator<Item = &'key str>,
+    ) -> Self::NextType<Vec<String>>;
+
     /// HIGHEST TO LOWEST SCORES.
     /// Retrieve entries from an ordered set by score range. (range is inclusive)
     /// Items that cannot be decoded into the specified type are returned as `None`.
@@ -490,7 +1307,9 @@ macro_rules! impl_batch_ops {
                     _returns: PhantomData,
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
-                    used_scripts: self.used_scripts
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
 
@@ -502,7 +1321,9 @@ macro_rules! impl_batch_ops {
                     _returns: PhantomData,
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
-                    used_scripts: self.used_scripts
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
 
@@ -529,7 +1350,9 @@ macro_rules! impl_batch_ops {
                     _returns: PhantomData,
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
-                    used_scripts: self.used_scripts
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
 
@@ -545,10 +1368,309 @@ macro_rules! impl_batch_ops {
                     _returns: PhantomData,
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
-                    used_scripts: self.used_scripts
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn set_if_equals(
+                self,
+                namespace: &str,
+                key: &str,
+                expected: impl ToRedisArgs,
+                new_value: impl ToRedisArgs,
+                expiry: Option<std::time::Duration>,
+            ) -> Self::NextType<bool> {
+                let invoker = SET_IF_EQUALS_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(namespace, key.into()))
+                    .arg(expected)
+                    .arg(new_value)
+                    .arg(expiry.map(|expiry| expiry.as_millis() as u64).unwrap_or(0))
+                    .arg("0");
+                self.script::<bool>(invoker)
+            }
+
+            fn set_if_absent_or_equals(
+                self,
+                namespace: &str,
+                key: &str,
+                expected: impl ToRedisArgs,
+                new_value: impl ToRedisArgs,
+                expiry: Option<std::time::Duration>,
+            ) -> Self::NextType<bool> {
+                let invoker = SET_IF_EQUALS_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(namespace, key.into()))
+                    .arg(expected)
+                    .arg(new_value)
+                    .arg(expiry.map(|expiry| expiry.as_millis() as u64).unwrap_or(0))
+                    .arg("1");
+                self.script::<bool>(invoker)
+            }
+
+            fn del_if_equals(
+                self,
+                namespace: &str,
+                key: &str,
+                expected: impl ToRedisArgs,
+            ) -> Self::NextType<bool> {
+                let invoker = DEL_IF_EQUALS_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(namespace, key.into()))
+                    .arg(expected);
+                self.script::<bool>(invoker)
+            }
+
+            fn smembers(mut self, set_namespace: &str, set_key: &str) -> Self::NextType<Vec<String>> {
+                self.pipe.smembers(self.redis_conn.final_key(set_namespace, set_key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
 
+            fn scard(mut self, set_namespace: &str, set_key: &str) -> Self::NextType<i64> {
+                self.pipe.scard(self.redis_conn.final_key(set_namespace, set_key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn zcard(mut self, set_namespace: &str, set_key: &str) -> Self::NextType<i64> {
+                self.pipe.zcard(self.redis_conn.final_key(set_namespace, set_key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn llen(mut self, list_namespace: &str, list_key: &str) -> Self::NextType<i64> {
+                self.pipe.llen(self.redis_conn.final_key(list_namespace, list_key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn lrange<Value: FromRedisValue>(
+                mut self,
+                list_namespace: &str,
+                list_key: &str,
+                start: isize,
+                stop: isize,
+            ) -> Self::NextType<Vec<RedisFuzzy<Value>>> {
+                self.pipe.lrange(self.redis_conn.final_key(list_namespace, list_key.into()), start, stop);
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn lpop<Value: FromRedisValue>(
+                mut self,
+                list_namespace: &str,
+                list_key: &str,
+                count: usize,
+            ) -> Self::NextType<Vec<RedisFuzzy<Value>>> {
+                // Always with a count, so a single value still comes back as an array:
+                self.pipe
+                    .cmd("LPOP")
+                    .arg(self.redis_conn.final_key(list_namespace, list_key.into()))
+                    .arg(count);
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn pttl(mut self, namespace: &str, key: &str) -> Self::NextType<i64> {
+                self.pipe.pttl(self.redis_conn.final_key(namespace, key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn rename(
+                self,
+                namespace: &str,
+                from_key: &str,
+                to_key: &str,
+                overwrite: bool,
+            ) -> Self::NextType<bool> {
+                self.rename_across(namespace, from_key, namespace, to_key, overwrite)
+            }
+
+            fn rename_across(
+                self,
+                from_namespace: &str,
+                from_key: &str,
+                to_namespace: &str,
+                to_key: &str,
+                overwrite: bool,
+            ) -> Self::NextType<bool> {
+                let invoker = RENAME_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(from_namespace, from_key.into()))
+                    .key(self.redis_conn.final_key(to_namespace, to_key.into()))
+                    .arg(if overwrite { "1" } else { "0" });
+                self.script::<bool>(invoker)
+            }
+
+            fn copy(
+                self,
+                namespace: &str,
+                from_key: &str,
+                to_key: &str,
+                opts: CopyOpts,
+            ) -> Self::NextType<bool> {
+                self.copy_across(namespace, from_key, namespace, to_key, opts)
+            }
+
+            fn copy_across(
+                self,
+                from_namespace: &str,
+                from_key: &str,
+                to_namespace: &str,
+                to_key: &str,
+                opts: CopyOpts,
+            ) -> Self::NextType<bool> {
+                let (ttl_mode, ttl_ms) = match opts.ttl {
+                    CopyTtl::KeepSource => ("source", 0),
+                    CopyTtl::KeepTtl => ("keep", 0),
+                    // A 0ms expiry would delete the copy straight away:
+                    CopyTtl::Fresh(ttl) => ("fresh", (self.jittered(ttl).as_millis() as u64).max(1)),
+                };
+                let invoker = COPY_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(from_namespace, from_key.into()))
+                    .key(self.redis_conn.final_key(to_namespace, to_key.into()))
+                    .arg(if opts.overwrite { "1" } else { "0" })
+                    .arg(ttl_mode)
+                    .arg(ttl_ms);
+                self.script::<bool>(invoker)
+            }
+
+            fn incr(
+                self,
+                namespace: &str,
+                key: &str,
+                by: i64,
+                expiry: Option<std::time::Duration>,
+            ) -> Self::NextType<Option<i64>> {
+                let invoker = INCR_BY_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(namespace, key.into()))
+                    .arg(by)
+                    .arg(expiry.map(|expiry| self.jittered(expiry).as_millis() as u64).unwrap_or(0));
+                self.script::<Option<i64>>(invoker)
+            }
+
+            fn hincrby(
+                self,
+                hashmap_namespace: &str,
+                hashmap_key: &str,
+                field: &str,
+                by: i64,
+                hashmap_ttl: Option<std::time::Duration>,
+            ) -> Self::NextType<Option<i64>> {
+                let invoker = INCR_BY_SCRIPT
+                    .invoker()
+                    .key(self.redis_conn.final_key(hashmap_namespace, hashmap_key.into()))
+                    .arg(by)
+                    .arg(hashmap_ttl.map(|ttl| self.jittered(ttl).as_millis() as u64).unwrap_or(0))
+                    .arg(field);
+                self.script::<Option<i64>>(invoker)
+            }
+
+            fn hmget<Value>(
+                mut self,
+                hashmap_namespace: &str,
+                hashmap_key: &str,
+                fields: impl IntoIterator<Item = impl AsRef<str>>,
+            ) -> Self::NextType<Vec<Option<Value>>> {
+                // Explicitly HMGET, so a single field still comes back as an array:
+                self.pipe
+                    .cmd("HMGET")
+                    .arg(self.redis_conn.final_key(hashmap_namespace, hashmap_key.into()))
+                    .arg(fields.into_iter().map(|field| field.as_ref().to_string()).collect::<Vec<_>>());
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn hgetall(mut self, namespace: &str, key: &str) -> Self::NextType<std::collections::HashMap<String, String>> {
+                self.pipe.hgetall(self.redis_conn.final_key(namespace, key.into()));
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+
+            fn sinter<'key>(
+                self,
+                namespace: &str,
+                keys: impl IntoIterator<Item = &'key str>,
+            ) -> Self::NextType<Vec<String>> {
+                self.set_algebra("SINTER", namespace, keys)
+            }
+
+            fn sunion<'key>(
+                self,
+                namespace: &str,
+                keys: impl IntoIterator<Item = &'key str>,
+            ) -> Self::NextType<Vec<String>> {
+                self.set_algebra("SUNION", namespace, keys)
+            }
+
+            fn sdiff<'key>(
+                self,
+                namespace: &str,
+                keys: impl IntoIterator<Item = &'key str>,
+            ) -> Self::NextType<Vec<String>> {
+                self.set_algebra("SDIFF", namespace, keys)
+            }
+
             fn zrangebyscore_high_to_low<Value: FromRedisValue>(
                 mut self,
                 set_namespace: &str,
@@ -569,6 +1691,8 @@ macro_rules! impl_batch_ops {
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
                     used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
 
@@ -592,6 +1716,31 @@ macro_rules! impl_batch_ops {
                     redis_conn: self.redis_conn,
                     pipe: self.pipe,
                     used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
+                }
+            }
+        }
+
+        impl<'a, 'b, 'c, $($tup_item: FromRedisValue),*> RedisBatch<'a, 'b, 'c, ($($tup_item,)*)> {
+            fn set_algebra<'key>(
+                mut self,
+                cmd: &str,
+                namespace: &str,
+                keys: impl IntoIterator<Item = &'key str>,
+            ) -> RedisBatch<'a, 'b, 'c, ($($tup_item,)* Vec<String>,)> {
+                let Some(cmd) = set_algebra_cmd(self.redis_conn, cmd, None, namespace, keys) else {
+                    // Still needs a slot in the response, so return an empty array:
+                    return self.script(EMPTY_ARRAY_SCRIPT.invoker());
+                };
+                self.pipe.add_command(cmd);
+                RedisBatch {
+                    _returns: PhantomData,
+                    redis_conn: self.redis_conn,
+                    pipe: self.pipe,
+                    used_scripts: self.used_scripts,
+                    ttl_jitter: self.ttl_jitter,
+                    ignored_cmds: self.ignored_cmds,
                 }
             }
         }
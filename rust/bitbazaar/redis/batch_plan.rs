@@ -0,0 +1,108 @@
+use std::fmt;
+
+use redis::{Arg, Pipeline};
+use serde::{Deserialize, Serialize};
+
+/// What a batch would send to redis, see [`super::RedisBatch::explain`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchPlan {
+    /// The queued commands in the order they'll run.
+    pub commands: Vec<BatchPlanCommand>,
+}
+
+/// A single command of a [`BatchPlan`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchPlanCommand {
+    /// The redis command, uppercased, e.g. `SET` or `EVALSHA`.
+    pub name: String,
+    /// The final (prefixed) keys the command touches, including pubsub channels.
+    ///
+    /// Exact for scripts, otherwise the args starting with the wrapper prefix,
+    /// so a value that happens to look like a key would be included.
+    pub keys: Vec<String>,
+    /// The number of args after the command name, keys included.
+    pub arg_count: usize,
+    /// The sha1 of the script, for `EVALSHA` only.
+    pub script_hash: Option<String>,
+    /// False when the command's response is discarded, so doesn't take a slot in the batch's return value.
+    pub returns: bool,
+}
+
+impl fmt::Display for BatchPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, cmd) in self.commands.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", cmd)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BatchPlanCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(script_hash) = &self.script_hash {
+            write!(f, " {}", script_hash)?;
+        }
+        if !self.keys.is_empty() {
+            write!(f, " [{}]", self.keys.join(", "))?;
+        }
+        write!(f, " ({} args)", self.arg_count)?;
+        if !self.returns {
+            write!(f, " (no return)")?;
+        }
+        Ok(())
+    }
+}
+
+impl BatchPlan {
+    /// Build the plan from the pipe of a batch, commands at the `ignored` indices don't return.
+    pub(crate) fn from_pipe(
+        prefix: &str,
+        pipe: &Pipeline,
+        ignored: impl Fn(usize) -> bool,
+    ) -> Self {
+        let key_prefix = format!("{}:", prefix);
+        let commands = pipe
+            .cmd_iter()
+            .enumerate()
+            .filter_map(|(index, cmd)| {
+                let mut args = cmd.args_iter().filter_map(|arg| match arg {
+                    Arg::Simple(arg) => Some(String::from_utf8_lossy(arg).to_string()),
+                    Arg::Cursor => None,
+                });
+                let name = args.next()?.to_uppercase();
+                let args = args.collect::<Vec<_>>();
+                let (keys, script_hash) = if name == "EVALSHA" {
+                    // EVALSHA <hash> <numkeys> <keys...> <args...>
+                    let num_keys = args
+                        .get(1)
+                        .and_then(|num_keys| num_keys.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    (
+                        args.iter().skip(2).take(num_keys).cloned().collect(),
+                        args.first().cloned(),
+                    )
+                } else {
+                    (
+                        args.iter()
+                            .filter(|arg| arg.starts_with(&key_prefix))
+                            .cloned()
+                            .collect(),
+                        None,
+                    )
+                };
+                Some(BatchPlanCommand {
+                    name,
+                    keys,
+                    arg_count: args.len(),
+                    script_hash,
+                    returns: !ignored(index),
+                })
+            })
+            .collect();
+        Self { commands }
+    }
+}
@@ -1,15 +1,93 @@
 use std::{borrow::Cow, future::Future};
 
 use deadpool_redis::redis::{FromRedisValue, ToRedisArgs};
+use futures::{Stream, StreamExt};
+use rand::Rng;
 
-use super::batch::{RedisBatch, RedisBatchFire, RedisBatchReturningOps};
+use super::{
+    batch::{RedisBatch, RedisBatchFire, RedisBatchReturningOps},
+    slow_log::SlowBatchLog,
+    RedisJsonTagged, RedisSchema, RedisTopic, ServerChannel, ServerPubSub, TtlJitter,
+};
 use crate::errors::prelude::*;
 
+/// Suffix appended to a [`RedisConn::cached_fn_with_opts`] key to form its sibling compute lock key.
+const CACHED_FN_LOCK_SUFFIX: &str = "__compute_lock";
+
+/// The keys each SCAN page asks for in [`RedisConn::namespace_usage`] and [`RedisConn::count_namespace`].
+const USAGE_SCAN_COUNT: usize = 500;
+
+/// The pause between SCAN pages in [`RedisConn::namespace_usage`], so scanning a large keyspace doesn't hog redis.
+const USAGE_SCAN_PAUSE: std::time::Duration = std::time::Duration::from_millis(2);
+
+/// The delays between retries of a failed SCAN page in [`RedisConn::scan_namespace`], each retry on a fresh connection.
+const SCAN_RETRY_DELAYS: [std::time::Duration; 4] = [
+    std::time::Duration::from_millis(50),
+    std::time::Duration::from_millis(200),
+    std::time::Duration::from_millis(500),
+    std::time::Duration::from_secs(1),
+];
+
+/// Extra configuration for [`RedisConn::cached_fn_with_opts`].
+#[derive(Debug, Clone, Default)]
+pub struct CacheOpts {
+    /// Cache stampede protection across processes.
+    /// When set, on a cache miss only the process winning a short lock (living for this long) computes the value,
+    /// others wait for it to appear in the cache, falling back to computing themselves once the lock would have expired.
+    ///
+    /// Should be set to comfortably more than the expected compute time.
+    pub compute_lock: Option<chrono::TimeDelta>,
+    /// Randomly spread the expiry of each cached value, so values cached together don't all expire together.
+    pub ttl_jitter: Option<TtlJitter>,
+}
+
+/// The outcome of trying to take out a [`CacheOpts::compute_lock`].
+enum ComputeLock {
+    /// Acquired, with the token to release it with.
+    Won(String),
+    /// Already held elsewhere.
+    HeldElsewhere,
+    /// Redis couldn't be used to lock.
+    Unavailable,
+}
+
+/// The result of [`RedisConn::consistent_two_phase`].
+#[derive(Debug)]
+pub struct TwoPhaseRead<P1, P2> {
+    /// The result of the first phase.
+    pub first: P1,
+    /// The result of the second phase.
+    pub second: P2,
+    /// The number of times the read was retried after failing verification.
+    pub retries: usize,
+    /// False if the retries ran out, the phases might then be inconsistent.
+    pub verified: bool,
+}
+
+/// The approximate memory usage of a namespace, see [`RedisConn::namespace_usage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamespaceUsage {
+    /// The namespace, without the prefix.
+    pub namespace: String,
+    /// The number of keys in the namespace.
+    pub approx_keys: u64,
+    /// The average MEMORY USAGE of the sampled keys, 0 if none were sampled.
+    pub sampled_avg_bytes: f64,
+    /// The average applied to every key.
+    pub approx_total_bytes: u64,
+}
+
 /// Wrapper around a lazy redis connection.
 pub struct RedisConn<'a> {
     pub(crate) prefix: &'a str,
     pool: &'a deadpool_redis::Pool,
     conn: Option<deadpool_redis::Connection>,
+    /// From a disabled [`super::Redis`], never connects.
+    disabled: bool,
+    /// Set when [`super::Redis::slow_batch_log`] is enabled.
+    pub(crate) slow_log: Option<&'a SlowBatchLog>,
+    /// See [`super::Redis::origin`].
+    pub(crate) origin: &'a str,
 }
 
 impl std::fmt::Debug for RedisConn<'_> {
@@ -18,6 +96,7 @@ impl std::fmt::Debug for RedisConn<'_> {
             .field("prefix", &self.prefix)
             .field("pool", &self.pool)
             .field("conn", &self.conn.is_some())
+            .field("disabled", &self.disabled)
             .finish()
     }
 }
@@ -27,7 +106,12 @@ impl<'a> RedisConn<'a> {
     /// Get an internal connection from the pool, connections are kept in the pool for reuse.
     /// If redis is acting up and unavailable, this will return None.
     /// NOTE: this mainly is used internally, but provides a fallback to the underlying connection, if the exposed interface does not provide options that fit an external user need (which could definitely happen).
+    ///
+    /// Always None (without logging) when the connection comes from a disabled [`super::Redis`].
     pub async fn get_inner_conn(&mut self) -> Option<&mut deadpool_redis::Connection> {
+        if self.disabled {
+            return None;
+        }
         if self.conn.is_none() {
             match self.pool.get().await {
                 Ok(conn) => self.conn = Some(conn),
@@ -52,11 +136,89 @@ impl<'a> RedisConn<'a> {
         }
     }
 
+    /// True when the connection comes from a disabled [`super::Redis`], see [`super::Redis::new_disabled`].
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
     /// Get a new [`RedisBatch`] for this connection that commands can be piped together with.
     pub fn batch<'ref_lt>(&'ref_lt mut self) -> RedisBatch<'ref_lt, 'a, '_, ()> {
         RedisBatch::new(self)
     }
 
+    /// Publish a payload to a typed topic, shorthand for a batch only containing [`RedisBatch::publish_topic`].
+    pub async fn publish_topic<T: RedisTopic>(&mut self, payload: &T::Payload) {
+        self.batch().publish_topic::<T>(payload).fire().await;
+    }
+
+    /// Get a value stored with [`RedisJsonTagged`], returning `None` if it doesn't exist or can't be decoded.
+    ///
+    /// A schema fingerprint mismatch (or otherwise undecodable value) records an exception naming the key and fingerprints,
+    /// so version skew between services shows up immediately rather than looking like missing data.
+    pub async fn get_tagged<T>(&mut self, namespace: &str, key: &str) -> Option<T>
+    where
+        T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        self.mget_tagged(namespace, [key]).await.pop().flatten()
+    }
+
+    /// Get multiple values stored with [`RedisJsonTagged`], see [`RedisConn::get_tagged`].
+    ///
+    /// Each value is decoded separately, so one undecodable value doesn't prevent the others being returned.
+    pub async fn mget_tagged<T>(
+        &mut self,
+        namespace: &str,
+        keys: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Vec<Option<T>>
+    where
+        T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        // A single key would be sent as a GET rather than MGET, changing the response shape:
+        let values = match keys.as_slice() {
+            [] => return vec![],
+            [key] => self
+                .batch()
+                .get::<Vec<u8>>(namespace, key.as_ref())
+                .fire()
+                .await
+                .map(|value| vec![value]),
+            keys => {
+                self.batch()
+                    .mget::<Vec<u8>>(namespace, keys.iter().map(|key| key.as_ref()))
+                    .fire()
+                    .await
+            }
+        };
+        let Some(values) = values else {
+            return keys.iter().map(|_| None).collect();
+        };
+
+        values
+            .into_iter()
+            .zip(keys.iter())
+            .map(|(value, key)| {
+                let decoded = serde_json::from_slice(&value?)
+                    .map_err(|e| e.to_string())
+                    .and_then(RedisJsonTagged::<T>::decode);
+                match decoded {
+                    Ok(decoded) => Some(decoded.0),
+                    Err(e) => {
+                        crate::log::record_exception(
+                            format!(
+                                "Redis key '{}' could not be decoded. {}",
+                                self.final_key(namespace, key.as_ref().into()),
+                                e
+                            ),
+                            "",
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
     /// Redis keys are all prefixed, use this to finalise a namespace outside of built in commands, e.g. for use in a custom script.
     #[inline]
     pub fn final_namespace(&self, namespace: &str) -> String {
@@ -69,6 +231,211 @@ impl<'a> RedisConn<'a> {
         format!("{}:{}", self.final_namespace(namespace), key)
     }
 
+    /// Run a read needing two round trips, e.g. a zset's members then the keys they point to,
+    /// retrying the whole read if the data was modified in between.
+    ///
+    /// Each phase builds a raw pipeline (keys finalised with [`RedisConn::final_key`]), `phase2` from the result of `phase1`.
+    /// `phase2` should also re-read something cheap that `phase1` read, e.g. a version key or the zset's members,
+    /// for `verify` to compare, returning false when there was a concurrent modification.
+    ///
+    /// After `max_retries` the last attempt is returned, with [`TwoPhaseRead::verified`] false.
+    /// Returns None if redis is unavailable, or either phase's result couldn't be decoded.
+    pub async fn consistent_two_phase<P1: FromRedisValue, P2: FromRedisValue>(
+        &mut self,
+        phase1: impl Fn(&Self) -> redis::Pipeline,
+        phase2: impl Fn(&Self, &P1) -> redis::Pipeline,
+        verify: impl Fn(&P1, &P2) -> bool,
+        max_retries: usize,
+    ) -> Option<TwoPhaseRead<P1, P2>> {
+        let mut retries = 0;
+        loop {
+            let first = self.query_pipe::<P1>(&phase1(self)).await?;
+            let second = self.query_pipe::<P2>(&phase2(self, &first)).await?;
+            let verified = verify(&first, &second);
+            if verified || retries >= max_retries {
+                return Some(TwoPhaseRead {
+                    first,
+                    second,
+                    retries,
+                    verified,
+                });
+            }
+            retries += 1;
+        }
+    }
+
+    /// Estimate the memory used by each namespace, e.g. to find what's filling a redis shared between features.
+    ///
+    /// Keys are counted with SCAN, pausing between pages so it's safe to run against production,
+    /// then up to `sample_size` random keys per namespace are measured with MEMORY USAGE to estimate the average size.
+    /// Counts drift if keys are added or removed during the scan.
+    /// Nested namespaces (e.g. "a" and "a:b") overlap, the nested keys are counted in both.
+    ///
+    /// Returns None if redis is unavailable.
+    pub async fn namespace_usage(
+        &mut self,
+        namespaces: &[&str],
+        sample_size: usize,
+    ) -> Option<Vec<NamespaceUsage>> {
+        let mut usages = Vec::with_capacity(namespaces.len());
+        for namespace in namespaces {
+            let pattern = format!("{}:*", escape_glob(&self.final_namespace(namespace)));
+            let mut cursor = 0_u64;
+            let mut approx_keys = 0_u64;
+            let mut sampled = Vec::with_capacity(sample_size);
+            loop {
+                let conn = self.get_inner_conn().await?;
+                let (next_cursor, keys): (u64, Vec<String>) = match redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(USAGE_SCAN_COUNT)
+                    .query_async(conn)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        tracing::error!("Redis SCAN failed. Err: '{}'", e);
+                        return None;
+                    }
+                };
+                for key in keys {
+                    approx_keys += 1;
+                    // Reservoir sampling, so every key has the same chance of being measured:
+                    if sampled.len() < sample_size {
+                        sampled.push(key);
+                    } else {
+                        let index = rand::thread_rng().gen_range(0..approx_keys) as usize;
+                        if index < sample_size {
+                            sampled[index] = key;
+                        }
+                    }
+                }
+                cursor = next_cursor;
+                if cursor == 0 {
+                    break;
+                }
+                tokio::time::sleep(USAGE_SCAN_PAUSE).await;
+            }
+
+            let sizes: Vec<Option<u64>> = if sampled.is_empty() {
+                vec![]
+            } else {
+                let mut pipe = redis::pipe();
+                for key in &sampled {
+                    pipe.cmd("MEMORY").arg("USAGE").arg(key);
+                }
+                self.query_pipe(&pipe).await?
+            };
+            // Keys that expired since being scanned come back nil:
+            let sizes = sizes.into_iter().flatten().collect::<Vec<_>>();
+            let sampled_avg_bytes = if sizes.is_empty() {
+                0.0
+            } else {
+                sizes.iter().sum::<u64>() as f64 / sizes.len() as f64
+            };
+            usages.push(NamespaceUsage {
+                namespace: namespace.to_string(),
+                approx_keys,
+                sampled_avg_bytes,
+                approx_total_bytes: (sampled_avg_bytes * approx_keys as f64).round() as u64,
+            });
+        }
+        Some(usages)
+    }
+
+    /// Iterate the keys in a namespace with cursor-based SCAN, so it's safe to run against production unlike KEYS.
+    ///
+    /// Keys are yielded without the prefix and namespace, i.e. as they'd be passed to other commands with the same namespace.
+    /// `match_pattern` is a SCAN glob applied to that remainder, defaulting to every key.
+    /// The prefix and namespace are always matched literally, so wildcards can never reach keys in other prefixes,
+    /// but nested namespaces (e.g. "a:b" in "a") are included, their keys yielded as "b:key".
+    ///
+    /// SCAN's guarantees apply: keys present throughout are yielded at least once,
+    /// keys added or removed during the iteration may or may not be, and a key can rarely be yielded twice if redis resizes mid-iteration.
+    ///
+    /// A failed page is retried from the same cursor on a fresh connection a few times,
+    /// after which the error is logged and the stream ends early. Ends immediately if redis is disabled.
+    ///
+    /// Arguments:
+    /// - `namespace`: The namespace to iterate.
+    /// - `match_pattern`: Only yield keys matching this glob, e.g. `Some("user_*")`.
+    /// - `count_hint`: SCAN's COUNT, roughly how many keys redis checks per page.
+    pub fn scan_namespace<'s>(
+        &'s mut self,
+        namespace: &str,
+        match_pattern: Option<&str>,
+        count_hint: usize,
+    ) -> impl Stream<Item = String> + use<'s, 'a> {
+        let strip = format!("{}:", self.final_namespace(namespace));
+        let pattern = format!("{}{}", escape_glob(&strip), match_pattern.unwrap_or("*"));
+        let count_hint = count_hint.max(1);
+        // The cursor, None once finished:
+        futures::stream::unfold((self, Some(0_u64)), move |(conn, cursor)| {
+            let (strip, pattern) = (strip.clone(), pattern.clone());
+            async move {
+                let cursor = cursor?;
+                let (next_cursor, keys) = conn.scan_page(cursor, &pattern, count_hint).await?;
+                let keys = keys
+                    .into_iter()
+                    .filter_map(|key| key.strip_prefix(&strip).map(str::to_string))
+                    .collect::<Vec<_>>();
+                Some((keys, (conn, (next_cursor != 0).then_some(next_cursor))))
+            }
+        })
+        .flat_map(futures::stream::iter)
+    }
+
+    /// The number of keys in a namespace, consuming [`RedisConn::scan_namespace`], so the same caveats apply.
+    pub async fn count_namespace(&mut self, namespace: &str) -> usize {
+        self.scan_namespace(namespace, None, USAGE_SCAN_COUNT)
+            .count()
+            .await
+    }
+
+    /// The server's view of pubsub channels under the prefix, see [`super::Redis::pubsub_diagnostics`].
+    ///
+    /// Returns None if redis is unavailable.
+    pub(crate) async fn pubsub_server_state(&mut self) -> Option<ServerPubSub> {
+        let pattern = format!("{}:*", escape_glob(self.prefix));
+        let mut pipe = redis::pipe();
+        pipe.cmd("PUBSUB").arg("CHANNELS").arg(&pattern);
+        pipe.cmd("PUBSUB").arg("NUMPAT");
+        let (mut channels, patterns): (Vec<String>, usize) = self.query_pipe(&pipe).await?;
+        channels.sort();
+
+        let counts: Vec<(String, usize)> = if channels.is_empty() {
+            vec![]
+        } else {
+            let mut pipe = redis::pipe();
+            pipe.cmd("PUBSUB").arg("NUMSUB").arg(&channels);
+            let (counts,): (Vec<(String, usize)>,) = self.query_pipe(&pipe).await?;
+            counts
+        };
+        let prefix = format!("{}:", self.prefix);
+        Some(ServerPubSub {
+            channels: counts
+                .into_iter()
+                // Channels can be unsubscribed from between the two queries:
+                .filter(|(_, subscribers)| *subscribers > 0)
+                .map(|(final_channel, subscribers)| {
+                    let rest = final_channel
+                        .strip_prefix(&prefix)
+                        .unwrap_or(&final_channel);
+                    let (namespace, channel) = rest.split_once(':').unwrap_or(("", rest));
+                    ServerChannel {
+                        namespace: namespace.to_string(),
+                        channel: channel.to_string(),
+                        final_channel: final_channel.clone(),
+                        subscribers,
+                    }
+                })
+                .collect(),
+            patterns,
+        })
+    }
+
     /// Cache an async function in redis with an optional expiry.
     /// If already stored, the cached value will be returned, otherwise the function will be stored in redis for next time.
     ///
@@ -84,35 +451,231 @@ impl<'a> RedisConn<'a> {
         expiry: Option<std::time::Duration>,
         cb: impl FnOnce() -> Fut,
     ) -> RResult<T, AnyErr>
+    where
+        T: FromRedisValue + ToRedisArgs,
+        Fut: Future<Output = Result<T, AnyErr>>,
+    {
+        self.cached_fn_with_opts(namespace, key, expiry, CacheOpts::default(), cb)
+            .await
+    }
+
+    /// Same as [`RedisConn::cached_fn`], but with extra [`CacheOpts`] configuration.
+    ///
+    /// When [`CacheOpts::compute_lock`] is set, a cache miss will try and take out a short lock on a sibling key before computing:
+    /// - The winner computes the value, writes it to the cache and releases the lock.
+    /// - Everyone else polls the cache with jittered sleeps until the value lands.
+    /// - If the value hasn't landed by the time the lock would have expired (e.g. the winner crashed), the waiter computes the value itself.
+    ///
+    /// If redis can't be locked (e.g. unavailable), this degrades to computing directly, just like [`RedisConn::cached_fn`].
+    pub async fn cached_fn_with_opts<'b, T, Fut, K: Into<Cow<'b, str>>>(
+        &mut self,
+        namespace: &str,
+        key: K,
+        expiry: Option<std::time::Duration>,
+        opts: CacheOpts,
+        cb: impl FnOnce() -> Fut,
+    ) -> RResult<T, AnyErr>
     where
         T: FromRedisValue + ToRedisArgs,
         Fut: Future<Output = Result<T, AnyErr>>,
     {
         let key: Cow<'b, str> = key.into();
+        let expiry = expiry.map(|expiry| {
+            opts.ttl_jitter
+                .map_or(expiry, |jitter| jitter.apply(expiry))
+        });
+
+        if let Some(cached) = self.cached_fn_get(namespace, &key).await {
+            return Ok(cached);
+        }
+
+        let compute_lock = match opts.compute_lock.and_then(|td| td.to_std().ok()) {
+            Some(compute_lock) if !compute_lock.is_zero() => compute_lock,
+            _ => return self.cached_fn_compute(namespace, &key, expiry, cb).await,
+        };
 
-        let cached = self
-            .batch()
-            .get::<T>(namespace, &key)
-            .fire()
+        let lock_key = format!("{}{}", key, CACHED_FN_LOCK_SUFFIX);
+        match self
+            .cached_fn_try_lock(namespace, &lock_key, compute_lock)
             .await
-            .flatten();
-        if let Some(cached) = cached {
-            Ok(cached)
-        } else {
-            let val = cb().await?;
-            self.batch().set(namespace, &key, &val, expiry).fire().await;
-            Ok(val)
+        {
+            ComputeLock::Won(token) => {
+                let result = self.cached_fn_compute(namespace, &key, expiry, cb).await;
+                // Release straight away so any waiters that arrive after a failure don't have to wait out the ttl.
+                // Only if still ours, it might have expired mid-compute and been taken by someone else:
+                self.batch()
+                    .del_if_equals(namespace, &lock_key, token)
+                    .fire()
+                    .await;
+                result
+            }
+            // Nothing to release:
+            ComputeLock::Unavailable => self.cached_fn_compute(namespace, &key, expiry, cb).await,
+            // Someone else is computing, wait for their result to land in the cache:
+            ComputeLock::HeldElsewhere => {
+                let started = std::time::Instant::now();
+                while started.elapsed() < compute_lock {
+                    let max_sleep = (compute_lock / 10).clamp(
+                        std::time::Duration::from_millis(2),
+                        std::time::Duration::from_millis(100),
+                    );
+                    let sleep_for = rand::thread_rng().gen_range(max_sleep / 2..=max_sleep);
+                    tokio::time::sleep(
+                        sleep_for.min(compute_lock.saturating_sub(started.elapsed())),
+                    )
+                    .await;
+                    if let Some(cached) = self.cached_fn_get(namespace, &key).await {
+                        return Ok(cached);
+                    }
+                }
+                // Winner didn't populate in time (might have crashed), compute ourselves:
+                self.cached_fn_compute(namespace, &key, expiry, cb).await
+            }
         }
     }
 }
 
 /// Private (public inside crate)
 impl<'a> RedisConn<'a> {
-    pub(crate) fn new(pool: &'a deadpool_redis::Pool, prefix: &'a str) -> Self {
+    /// A single SCAN page, retried with [`SCAN_RETRY_DELAYS`]. None if disabled or all attempts failed.
+    async fn scan_page(
+        &mut self,
+        cursor: u64,
+        pattern: &str,
+        count_hint: usize,
+    ) -> Option<(u64, Vec<String>)> {
+        if self.disabled {
+            return None;
+        }
+        let mut delays = SCAN_RETRY_DELAYS.iter();
+        loop {
+            if let Some(conn) = self.get_inner_conn().await {
+                match redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(count_hint)
+                    .query_async(conn)
+                    .await
+                {
+                    Ok(page) => return Some(page),
+                    Err(e) => {
+                        tracing::warn!("Redis SCAN failed, cursor: '{}'. Err: '{}'", cursor, e)
+                    }
+                }
+                // Might be a broken connection, so don't reuse it:
+                self.conn = None;
+            }
+            match delays.next() {
+                Some(delay) => tokio::time::sleep(*delay).await,
+                None => {
+                    tracing::error!(
+                        "Redis SCAN of '{}' failed after {} retries, ending early.",
+                        pattern,
+                        SCAN_RETRY_DELAYS.len()
+                    );
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Run a pipe built outside a [`RedisBatch`], which needs a fixed number of commands.
+    pub(crate) async fn query_pipe<R: FromRedisValue>(
+        &mut self,
+        pipe: &redis::Pipeline,
+    ) -> Option<R> {
+        let inner_conn = self.get_inner_conn().await?;
+        match pipe.query_async(inner_conn).await {
+            Ok(result) => Some(result),
+            Err(e) => {
+                tracing::error!("Redis pipe failed. Err: '{}'", e);
+                None
+            }
+        }
+    }
+
+    async fn cached_fn_get<T: FromRedisValue>(&mut self, namespace: &str, key: &str) -> Option<T> {
+        self.batch().get::<T>(namespace, key).fire().await.flatten()
+    }
+
+    async fn cached_fn_compute<T, Fut>(
+        &mut self,
+        namespace: &str,
+        key: &str,
+        expiry: Option<std::time::Duration>,
+        cb: impl FnOnce() -> Fut,
+    ) -> RResult<T, AnyErr>
+    where
+        T: FromRedisValue + ToRedisArgs,
+        Fut: Future<Output = Result<T, AnyErr>>,
+    {
+        let val = cb().await?;
+        self.batch().set(namespace, key, &val, expiry).fire().await;
+        Ok(val)
+    }
+
+    /// Take out the compute lock with a random token, so only the holder can release it.
+    async fn cached_fn_try_lock(
+        &mut self,
+        namespace: &str,
+        lock_key: &str,
+        ttl: std::time::Duration,
+    ) -> ComputeLock {
+        let final_key = self.final_key(namespace, lock_key.into());
+        let Some(conn) = self.get_inner_conn().await else {
+            return ComputeLock::Unavailable;
+        };
+        let token = uuid::Uuid::new_v4().to_string();
+        match redis::cmd("SET")
+            .arg(final_key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async::<_, Option<String>>(conn)
+            .await
+        {
+            Ok(Some(_)) => ComputeLock::Won(token),
+            Ok(None) => ComputeLock::HeldElsewhere,
+            Err(e) => {
+                tracing::error!("Could not take out cached_fn compute lock: {}", e);
+                ComputeLock::Unavailable
+            }
+        }
+    }
+
+    pub(crate) fn pool(&self) -> &'a deadpool_redis::Pool {
+        self.pool
+    }
+
+    pub(crate) fn new(
+        pool: &'a deadpool_redis::Pool,
+        prefix: &'a str,
+        disabled: bool,
+        slow_log: Option<&'a SlowBatchLog>,
+        origin: &'a str,
+    ) -> Self {
         Self {
             pool,
             prefix,
             conn: None,
+            disabled,
+            slow_log,
+            origin,
+        }
+    }
+}
+
+/// Escape the chars SCAN's MATCH (or PUBSUB CHANNELS) would treat as a pattern.
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
         }
+        escaped.push(c);
     }
+    escaped
 }
@@ -0,0 +1,324 @@
+use deadpool_redis::redis::{FromRedisValue, Value};
+use once_cell::sync::Lazy;
+
+use super::{RedisBatchFire, RedisBatchReturningOps, RedisConn, RedisScript};
+use crate::errors::prelude::*;
+
+static CONTRACT_FETCH_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/contract_fetch.lua")));
+
+/// Collection keys are checked against their declared type using at most this many of their elements,
+/// rather than fetching them whole, see [`RedisContractBuilder::require_key`].
+pub const CONTRACT_DECODE_SAMPLE: usize = 100;
+
+/// The keys, hash fields and scripts a service relies on existing in redis, checked at startup
+/// so a bad deployment is caught straight away, rather than when a request path happens to use the missing data.
+///
+/// Create with [`RedisContract::builder`], then [`RedisContract::check`] or [`RedisContract::enforce`].
+#[derive(Debug, Clone, Default)]
+pub struct RedisContract {
+    keys: Vec<KeyRequirement>,
+    scripts: Vec<RedisScript>,
+}
+
+#[derive(Debug, Clone)]
+struct KeyRequirement {
+    namespace: String,
+    key: String,
+    check: KeyCheck,
+}
+
+#[derive(Debug, Clone)]
+enum KeyCheck {
+    Decodes {
+        type_name: &'static str,
+        decode: fn(&Value) -> Result<(), String>,
+        optional: bool,
+    },
+    HashFields(Vec<String>),
+}
+
+/// Builder for a [`RedisContract`].
+#[derive(Debug, Clone, Default)]
+pub struct RedisContractBuilder {
+    contract: RedisContract,
+}
+
+impl RedisContractBuilder {
+    /// The key must exist and decode into `T`, e.g. a [`super::RedisJson`] or any other [`FromRedisValue`] type.
+    ///
+    /// Keys of all types are supported, e.g. a hash key could be required as a `HashMap<String, String>`.
+    /// Collections are decoded from a sample of up to [`CONTRACT_DECODE_SAMPLE`] of their elements, so large keys stay cheap to check.
+    pub fn require_key<T: FromRedisValue>(self, namespace: &str, key: &str) -> Self {
+        self.key::<T>(namespace, key, false)
+    }
+
+    /// When the key exists it must decode into `T`, see [`RedisContractBuilder::require_key`].
+    pub fn optional_key<T: FromRedisValue>(self, namespace: &str, key: &str) -> Self {
+        self.key::<T>(namespace, key, true)
+    }
+
+    /// The key must be a hash containing all the fields.
+    pub fn require_hash_fields(
+        mut self,
+        namespace: &str,
+        key: &str,
+        fields: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.contract.keys.push(KeyRequirement {
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+            check: KeyCheck::HashFields(fields.into_iter().map(Into::into).collect()),
+        });
+        self
+    }
+
+    /// The script must load successfully, it's loaded during the check so the first real use doesn't need to.
+    pub fn require_script(mut self, script: &RedisScript) -> Self {
+        self.contract.scripts.push(script.clone());
+        self
+    }
+
+    /// Build the contract.
+    pub fn build(self) -> RedisContract {
+        self.contract
+    }
+
+    fn key<T: FromRedisValue>(mut self, namespace: &str, key: &str, optional: bool) -> Self {
+        self.contract.keys.push(KeyRequirement {
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+            check: KeyCheck::Decodes {
+                type_name: std::any::type_name::<T>(),
+                decode: decodes::<T>,
+                optional,
+            },
+        });
+        self
+    }
+}
+
+fn decodes<T: FromRedisValue>(value: &Value) -> Result<(), String> {
+    T::from_redis_value(value)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// The outcome of [`RedisContract::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractReport {
+    /// True when there are no failures.
+    pub satisfied: bool,
+    /// Every requirement that wasn't met, in the order they were added to the contract (keys before scripts).
+    pub failures: Vec<ContractFailure>,
+}
+
+/// A requirement of a [`RedisContract`] that wasn't met. Keys are the final keys in redis (including the prefix and namespace).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContractFailure {
+    /// Redis couldn't be reached, so nothing could be checked.
+    Unavailable,
+    /// A required key doesn't exist.
+    MissingKey {
+        /// The missing key.
+        key: String,
+    },
+    /// A key exists but couldn't be decoded into the type it was declared with.
+    Undecodable {
+        /// The key.
+        key: String,
+        /// The declared type's name, only for debugging.
+        type_name: &'static str,
+        /// Why decoding failed.
+        error: String,
+    },
+    /// A key exists but is the wrong redis type, e.g. a string where a hash was required.
+    WrongType {
+        /// The key.
+        key: String,
+        /// The redis type required.
+        expected: &'static str,
+        /// The redis type found.
+        found: String,
+    },
+    /// A hash exists but is missing some of the required fields.
+    MissingHashFields {
+        /// The hash's key.
+        key: String,
+        /// The missing fields.
+        fields: Vec<String>,
+    },
+    /// A script failed to load, e.g. a lua syntax error.
+    ScriptLoadFailed {
+        /// The sha1 of the script.
+        sha: String,
+        /// The error from redis.
+        error: String,
+    },
+}
+
+impl std::fmt::Display for ContractFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContractFailure::Unavailable => write!(f, "Redis unavailable, could not check."),
+            ContractFailure::MissingKey { key } => write!(f, "Required key '{}' is missing.", key),
+            ContractFailure::Undecodable {
+                key,
+                type_name,
+                error,
+            } => write!(
+                f,
+                "Key '{}' could not be decoded as '{}'. Err: '{}'",
+                key, type_name, error
+            ),
+            ContractFailure::WrongType {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Key '{}' is a '{}', expected a '{}'.",
+                key, found, expected
+            ),
+            ContractFailure::MissingHashFields { key, fields } => write!(
+                f,
+                "Hash '{}' is missing fields: '{}'.",
+                key,
+                fields.join("', '")
+            ),
+            ContractFailure::ScriptLoadFailed { sha, error } => {
+                write!(f, "Script '{}' failed to load. Err: '{}'", sha, error)
+            }
+        }
+    }
+}
+
+impl RedisContract {
+    /// Create a new contract.
+    pub fn builder() -> RedisContractBuilder {
+        RedisContractBuilder::default()
+    }
+
+    /// Check every requirement of the contract, collecting all failures rather than stopping at the first.
+    pub async fn check(&self, conn: &mut RedisConn<'_>) -> ContractReport {
+        let mut failures = vec![];
+        if let Err(failure) = self.check_keys(conn, &mut failures).await {
+            failures.push(failure);
+        } else {
+            self.check_scripts(conn, &mut failures).await;
+        }
+        ContractReport {
+            satisfied: failures.is_empty(),
+            failures,
+        }
+    }
+
+    /// Check the contract, returning an error listing every failure when it isn't satisfied, e.g. to abort startup.
+    pub async fn enforce(&self, conn: &mut RedisConn<'_>) -> RResult<(), AnyErr> {
+        let report = self.check(conn).await;
+        if report.satisfied {
+            return Ok(());
+        }
+        let mut err = anyerr!(
+            "Redis contract not satisfied, {} failure{}.",
+            report.failures.len(),
+            if report.failures.len() == 1 { "" } else { "s" }
+        );
+        for failure in &report.failures {
+            err = err.attach_printable(failure.to_string());
+        }
+        Err(err)
+    }
+
+    async fn check_keys(
+        &self,
+        conn: &mut RedisConn<'_>,
+        failures: &mut Vec<ContractFailure>,
+    ) -> Result<(), ContractFailure> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let final_keys = self
+            .keys
+            .iter()
+            .map(|req| conn.final_key(&req.namespace, req.key.as_str().into()))
+            .collect::<Vec<_>>();
+        let mut invoker = CONTRACT_FETCH_SCRIPT.invoker();
+        for (req, key) in self.keys.iter().zip(&final_keys) {
+            invoker = invoker.key(key);
+            invoker = match &req.check {
+                KeyCheck::Decodes { .. } => invoker.arg("decode").arg(CONTRACT_DECODE_SAMPLE),
+                KeyCheck::HashFields(fields) => invoker.arg("fields").arg(fields.len()).arg(fields),
+            };
+        }
+        let fetched = conn
+            .batch()
+            .script::<Vec<(String, Value)>>(invoker)
+            .fire()
+            .await
+            .ok_or(ContractFailure::Unavailable)?;
+
+        for ((req, key), (typ, value)) in self.keys.iter().zip(final_keys).zip(fetched) {
+            let exists = typ != "none";
+            match &req.check {
+                KeyCheck::Decodes {
+                    type_name,
+                    decode,
+                    optional,
+                } => {
+                    if !exists {
+                        if !optional {
+                            failures.push(ContractFailure::MissingKey { key });
+                        }
+                    } else if let Err(error) = decode(&value) {
+                        failures.push(ContractFailure::Undecodable {
+                            key,
+                            type_name,
+                            error,
+                        });
+                    }
+                }
+                KeyCheck::HashFields(_) => {
+                    if !exists {
+                        failures.push(ContractFailure::MissingKey { key });
+                    } else if typ != "hash" {
+                        failures.push(ContractFailure::WrongType {
+                            key,
+                            expected: "hash",
+                            found: typ,
+                        });
+                    } else {
+                        // The script only returns the fields it couldn't find:
+                        let missing = Vec::<String>::from_redis_value(&value).unwrap_or_default();
+                        if !missing.is_empty() {
+                            failures.push(ContractFailure::MissingHashFields {
+                                key,
+                                fields: missing,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_scripts(&self, conn: &mut RedisConn<'_>, failures: &mut Vec<ContractFailure>) {
+        if self.scripts.is_empty() {
+            return;
+        }
+        let Some(inner) = conn.get_inner_conn().await else {
+            failures.push(ContractFailure::Unavailable);
+            return;
+        };
+        // Loaded one at a time so each failure is attributed to the right script:
+        for script in &self.scripts {
+            if let Err(e) = script.load_cmd().query_async::<_, String>(inner).await {
+                failures.push(ContractFailure::ScriptLoadFailed {
+                    sha: script.sha().to_string(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use futures::future::{select, Either};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::{Redis, RedisBatchFire, RedisBatchReturningOps, RedisScript};
+
+static INCR_MULTI_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/incr_multi.lua")));
+
+/// Counts of what a [`RedisCounterBuffer`] has done, see [`RedisCounterBuffer::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RedisCounterBufferStats {
+    /// Flushes attempted with increments to send, each a single round trip.
+    pub flushes: usize,
+    /// Of the flushes, those triggered by hitting the distinct key cap rather than the interval.
+    pub early_flushes: usize,
+    /// Flushes that failed, their increments are kept for the next flush whilst under the distinct key cap.
+    pub failed_flushes: usize,
+    /// The total distinct keys sent across all flushes.
+    pub flushed_keys: usize,
+    /// Increments dropped because a flush failed whilst the buffer was full.
+    pub dropped_keys: usize,
+}
+
+/// Accumulates counter increments locally, flushing them to redis in a single round trip every interval,
+/// rather than firing a batch per increment, e.g. for page views or feature usage ticks.
+///
+/// - [`RedisCounterBuffer::incr`] is sync, and only allocates the first time a key is seen each interval.
+/// - Hitting `max_distinct_keys` triggers an early flush, to bound memory.
+/// - Dropping the buffer flushes what's pending in the background, use [`RedisCounterBuffer::shutdown`] to wait for it.
+///
+/// Loss tolerance: increments only exist locally until flushed, so a crash loses at most one interval of them.
+/// A failed flush keeps its increments for the next, unless that would take the buffer past `max_distinct_keys`,
+/// and could very rarely double count if redis applied it but the reply was lost.
+pub struct RedisCounterBuffer {
+    inner: Arc<Inner>,
+    /// Taken by [`RedisCounterBuffer::shutdown`], returns whether the final flush succeeded.
+    task: Option<tokio::task::JoinHandle<bool>>,
+}
+
+struct Inner {
+    redis: Redis,
+    namespace: String,
+    max_distinct_keys: usize,
+    pending: Mutex<HashMap<String, i64>>,
+    stats: Mutex<RedisCounterBufferStats>,
+    /// Woken when the distinct key cap is hit.
+    full: Notify,
+    /// Woken to stop the flusher, after a final flush.
+    stop: Notify,
+    /// Held whilst flushing, so [`RedisCounterBuffer::force_flush`] waits for one already in progress.
+    flushing: tokio::sync::Mutex<()>,
+}
+
+impl std::fmt::Debug for RedisCounterBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisCounterBuffer")
+            .field("namespace", &self.inner.namespace)
+            .field("max_distinct_keys", &self.inner.max_distinct_keys)
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl RedisCounterBuffer {
+    /// Create a new buffer, spawning its background flusher, so must be called from within a tokio runtime.
+    ///
+    /// Arguments:
+    /// - `redis`: The redis wrapper to flush to.
+    /// - `namespace`: The namespace of the counters.
+    /// - `flush_interval`: How often pending increments are flushed.
+    /// - `max_distinct_keys`: Flush early once this many keys are pending.
+    pub fn new(
+        redis: Redis,
+        namespace: impl Into<String>,
+        flush_interval: Duration,
+        max_distinct_keys: usize,
+    ) -> Self {
+        let inner = Arc::new(Inner {
+            redis,
+            namespace: namespace.into(),
+            max_distinct_keys: max_distinct_keys.max(1),
+            pending: Mutex::new(HashMap::new()),
+            stats: Mutex::new(RedisCounterBufferStats::default()),
+            full: Notify::new(),
+            stop: Notify::new(),
+            flushing: tokio::sync::Mutex::new(()),
+        });
+        let task = tokio::spawn(run_flusher(inner.clone(), flush_interval));
+        Self {
+            inner,
+            task: Some(task),
+        }
+    }
+
+    /// Add `by` (negative to decrement) to a counter, sent to redis with the next flush.
+    pub fn incr(&self, key: &str, by: i64) {
+        let mut pending = self.inner.pending.lock();
+        if let Some(delta) = pending.get_mut(key) {
+            *delta += by;
+            return;
+        }
+        pending.insert(key.to_string(), by);
+        if pending.len() >= self.inner.max_distinct_keys {
+            self.inner.full.notify_one();
+        }
+    }
+
+    /// The number of distinct keys with increments not yet flushed.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.lock().len()
+    }
+
+    /// What the buffer has done so far.
+    pub fn stats(&self) -> RedisCounterBufferStats {
+        *self.inner.stats.lock()
+    }
+
+    /// Flush pending increments now, waiting for any flush already in progress.
+    ///
+    /// Returns false if redis couldn't be used, the increments are then kept for the next flush.
+    pub async fn force_flush(&self) -> bool {
+        self.inner.flush(false).await
+    }
+
+    /// Stop the background flusher, flushing anything pending.
+    ///
+    /// Returns false if the final flush failed, those increments are lost.
+    pub async fn shutdown(mut self) -> bool {
+        self.inner.stop.notify_one();
+        match self.task.take() {
+            Some(task) => task.await.unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+impl Drop for RedisCounterBuffer {
+    fn drop(&mut self) {
+        // The flusher does a final flush in the background before exiting:
+        if self.task.is_some() {
+            self.inner.stop.notify_one();
+        }
+    }
+}
+
+async fn run_flusher(inner: Arc<Inner>, flush_interval: Duration) -> bool {
+    loop {
+        let (early, stopping) = {
+            let sleep = tokio::time::sleep(flush_interval);
+            let full = inner.full.notified();
+            let stop = inner.stop.notified();
+            futures::pin_mut!(sleep, full, stop);
+            match select(sleep, select(full, stop)).await {
+                Either::Left(_) => (false, false),
+                Either::Right((Either::Left(_), _)) => (true, false),
+                Either::Right((Either::Right(_), _)) => (false, true),
+            }
+        };
+        if stopping {
+            return inner.flush(false).await;
+        }
+        inner.flush(early).await;
+    }
+}
+
+impl Inner {
+    async fn flush(&self, early: bool) -> bool {
+        let _flushing = self.flushing.lock().await;
+        let deltas = std::mem::take(&mut *self.pending.lock())
+            .into_iter()
+            .filter(|(_, delta)| *delta != 0)
+            .collect::<Vec<_>>();
+        if deltas.is_empty() {
+            return true;
+        }
+
+        let mut conn = self.redis.conn();
+        let mut invoker = INCR_MULTI_SCRIPT.invoker();
+        for (key, delta) in &deltas {
+            invoker = invoker
+                .key(conn.final_key(&self.namespace, key.as_str().into()))
+                .arg(*delta);
+        }
+        let result = conn.batch().script::<i64>(invoker).fire().await;
+
+        let mut stats = self.stats.lock();
+        stats.flushes += 1;
+        if early {
+            stats.early_flushes += 1;
+        }
+        match result {
+            Some(failed) => {
+                if failed > 0 {
+                    tracing::error!(
+                        "{} counters in namespace '{}' couldn't be incremented as they aren't integers.",
+                        failed,
+                        self.namespace
+                    );
+                }
+                stats.flushed_keys += deltas.len();
+                true
+            }
+            None => {
+                stats.failed_flushes += 1;
+                // Keep for the next flush, whilst that doesn't break the memory bound:
+                let mut pending = self.pending.lock();
+                for (key, delta) in deltas {
+                    if let Some(existing) = pending.get_mut(&key) {
+                        *existing += delta;
+                    } else if pending.len() < self.max_distinct_keys {
+                        pending.insert(key, delta);
+                    } else {
+                        stats.dropped_keys += 1;
+                    }
+                }
+                false
+            }
+        }
+    }
+}
@@ -17,9 +17,10 @@ use futures::{Future, FutureExt};
 use once_cell::sync::Lazy;
 use rand::{thread_rng, Rng, RngCore};
 use redis::{RedisResult, Value};
+use serde::{Deserialize, Serialize};
 
-use super::{RedisBatchFire, RedisBatchReturningOps, RedisConn, RedisScript};
-use crate::{chrono::chrono_format_td, prelude::*};
+use super::{RedisBatchFire, RedisBatchReturningOps, RedisConn, RedisDisabledLocks, RedisScript};
+use crate::{chrono::chrono_format_td, prelude::*, threads::AsyncGuard};
 
 const RETRY_DELAY: u32 = 200;
 const CLOCK_DRIFT_FACTOR: f32 = 0.01;
@@ -32,43 +33,153 @@ else
 end
 "#;
 const EXTEND_LUA: &str = r#"
-if redis.call("get", KEYS[1]) ~= ARGV[1] then
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+  return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+else
   return 0
+end
+"#;
+
+// Signed as sha1(lock_id|expires_at_ms|ttl_ms|owner), with the owner last so the signature can't be length extended:
+const HANDOFF_LUA: &str = r#"
+local current = redis.call("GET", KEYS[1])
+if current and redis.sha1hex(KEYS[1] .. "|" .. ARGV[1] .. "|" .. current) == ARGV[2] then
+  redis.call("SET", KEYS[1], ARGV[3], "PX", ARGV[4])
+  return 1
 else
-  if redis.call("set", KEYS[1], ARGV[1], "PX", ARGV[2]) ~= nil then
-    return 1
-  else
-    return 0
-  end
+  return 0
 end
 "#;
 
 static UNLOCK_SCRIPT: Lazy<RedisScript> = Lazy::new(|| RedisScript::new(UNLOCK_LUA));
 static EXTEND_SCRIPT: Lazy<RedisScript> = Lazy::new(|| RedisScript::new(EXTEND_LUA));
+static HANDOFF_SCRIPT: Lazy<RedisScript> = Lazy::new(|| RedisScript::new(HANDOFF_LUA));
 
 /// Errors that can occur when trying to lock a resource.
+///
+/// Branch on the variant or [`RedisLockErr::is_retryable`] rather than the message.
 #[derive(Debug)]
 pub enum RedisLockErr {
     /// When the lock is held by someone else.
-    Unavailable,
-    /// When the user has done something wrong.
-    UserErr,
-    /// Internal error.
-    InternalErr,
+    Contended {
+        /// Roughly how long until the holder's lock expires, if known.
+        /// It might be released sooner, or extended.
+        retry_after_hint: Option<chrono::TimeDelta>,
+    },
+    /// When redis couldn't be used to lock, e.g. it's down, too slow to lock within the ttl, or disabled and configured to fail locks.
+    Unavailable {
+        /// What went wrong.
+        source: String,
+    },
+    /// When the user has passed invalid arguments, e.g. a ttl too small to be useful.
+    Misuse {
+        /// What was wrong.
+        reason: String,
+    },
+    /// When the lock expired or was taken by someone else whilst we thought we held it.
+    LostLock {
+        /// How long since the lock was acquired.
+        held_for: chrono::TimeDelta,
+    },
+    /// When the future run by [`RedisLock::hold_for_fut`] errored, the lock itself was fine.
+    FutFailed,
+    /// When a [`HandoffToken`] was accepted after its expiry, the lock it was for may since have been taken by someone else.
+    HandoffExpired {
+        /// How long ago the token expired.
+        expired_for: chrono::TimeDelta,
+    },
+    /// When a [`HandoffToken`] doesn't match the current owner of the lock,
+    /// e.g. it was already accepted, the lock was released or re-acquired since it was created, or it was forged.
+    HandoffStale,
+}
+
+impl RedisLockErr {
+    /// Whether trying the same thing again later might succeed.
+    ///
+    /// True for [`RedisLockErr::Contended`] and [`RedisLockErr::Unavailable`].
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RedisLockErr::Contended { .. } | RedisLockErr::Unavailable { .. } => true,
+            RedisLockErr::Misuse { .. }
+            | RedisLockErr::LostLock { .. }
+            | RedisLockErr::FutFailed
+            | RedisLockErr::HandoffExpired { .. }
+            | RedisLockErr::HandoffStale => false,
+        }
+    }
 }
 
 impl std::fmt::Display for RedisLockErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RedisLockErr::UserErr => write!(f, "User error"),
-            RedisLockErr::Unavailable => write!(f, "Lock unavailable"),
-            RedisLockErr::InternalErr => write!(f, "Internal error"),
+            RedisLockErr::Contended { retry_after_hint } => {
+                write!(f, "Lock held elsewhere")?;
+                if let Some(hint) = retry_after_hint {
+                    write!(f, ", expires in {}", chrono_format_td(*hint, true))?;
+                }
+                Ok(())
+            }
+            RedisLockErr::Unavailable { source } => {
+                write!(f, "Redis unavailable for locking: {}", source)
+            }
+            RedisLockErr::Misuse { reason } => write!(f, "Lock misuse: {}", reason),
+            RedisLockErr::LostLock { held_for } => write!(
+                f,
+                "Lock lost, {} after it was acquired",
+                chrono_format_td(*held_for, true)
+            ),
+            RedisLockErr::FutFailed => write!(f, "Future errored whilst holding the lock"),
+            RedisLockErr::HandoffExpired { expired_for } => write!(
+                f,
+                "Lock handoff expired {} ago",
+                chrono_format_td(*expired_for, true)
+            ),
+            RedisLockErr::HandoffStale => {
+                write!(f, "Lock handoff doesn't match the lock's current owner")
+            }
         }
     }
 }
 
 impl Context for RedisLockErr {}
 
+/// Transfers a held lock to another process, see [`RedisLock::create_handoff`].
+///
+/// Serializable, to send out-of-band, e.g. in the queue message handing over the job.
+/// The owner's secret isn't included, only a signature of the token's contents made with it,
+/// so the token can't be altered, and is only accepted whilst the lock is still held by its creator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandoffToken {
+    lock_id: String,
+    /// The lock's validity time when created, the token is rejected after this.
+    expires_at_ms: i64,
+    /// The ttl the acceptor holds the lock with.
+    ttl_ms: u64,
+    signature: String,
+}
+
+impl HandoffToken {
+    /// When the token stops being accepted, the validity time of the lock it was created from.
+    pub fn expires_at(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp_millis(self.expires_at_ms).unwrap_or_default()
+    }
+
+    /// The signed part after the lock id, matching the handoff script.
+    fn signed_args(&self) -> String {
+        format!("{}|{}", self.expires_at_ms, self.ttl_ms)
+    }
+
+    fn sign(lock_id: &str, signed_args: &str, owner: &[u8]) -> String {
+        let mut hasher = sha1_smol::Sha1::new();
+        hasher.update(lock_id.as_bytes());
+        hasher.update(b"|");
+        hasher.update(signed_args.as_bytes());
+        hasher.update(b"|");
+        hasher.update(owner);
+        hasher.digest().to_string()
+    }
+}
+
 /// A distributed lock for Redis.
 pub struct RedisLock<'a> {
     redis: &'a super::Redis,
@@ -80,6 +191,19 @@ pub struct RedisLock<'a> {
     pub wait_up_to: Option<Duration>,
     /// The time at which the lock will expire. Must be renewed before this point to maintain.
     pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// The ttl last locked or extended with, reused by [`RedisLock::with_heartbeat`].
+    ttl: Duration,
+    /// When the lock was acquired, for [`RedisLockErr::LostLock`].
+    acquired_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The outcome of a locking operation (lock/extend) on a single server.
+enum Attempt {
+    Won,
+    /// Held by someone else, with its remaining ttl if known.
+    Held(Option<chrono::TimeDelta>),
+    /// The server couldn't be used.
+    Failed(String),
 }
 
 impl<'a> RedisLock<'a> {
@@ -92,10 +216,12 @@ impl<'a> RedisLock<'a> {
         wait_up_to: Option<Duration>,
     ) -> RResult<RedisLock<'a>, RedisLockErr> {
         if ttl < Duration::from_millis(100) {
-            return Err(err!(
-                RedisLockErr::UserErr,
-                "Do not set time to live to less than 100 milliseconds."
-            ));
+            return Err(err!(RedisLockErr::Misuse {
+                reason: format!(
+                    "Do not set time to live to less than 100 milliseconds, got {:?}.",
+                    ttl
+                )
+            }));
         }
 
         let mut lock = RedisLock {
@@ -104,8 +230,24 @@ impl<'a> RedisLock<'a> {
             val: get_unique_lock_id(),
             wait_up_to,
             expires_at: chrono::DateTime::<chrono::Utc>::MIN_UTC,
+            ttl,
+            acquired_at: chrono::Utc::now(),
         };
 
+        // Nothing to coordinate with when disabled, decided straight away:
+        match redis.disabled_locks() {
+            Some(RedisDisabledLocks::Acquire) => {
+                lock.expires_at = chrono::Utc::now() + ttl;
+                return Ok(lock);
+            }
+            Some(RedisDisabledLocks::Fail) => {
+                return Err(err!(RedisLockErr::Unavailable {
+                    source: "Redis is disabled, configured to fail locks.".to_string()
+                }));
+            }
+            None => {}
+        }
+
         // Need to actually lock for the first time:
         let lock_id = lock.lock_id.clone();
         let val = lock.val.clone();
@@ -113,26 +255,136 @@ impl<'a> RedisLock<'a> {
             let lock_id = lock_id.clone();
             let val = val.clone();
             async move {
-                if let Some(conn) = conn.get_inner_conn().await {
-                    let result: RedisResult<Value> = redis::cmd("SET")
-                        .arg(lock_id)
-                        .arg(val)
-                        .arg("NX")
-                        .arg("PX")
-                        .arg(ttl.as_millis() as usize)
-                        .query_async(conn)
-                        .await;
-
-                    match result {
-                        Ok(Value::Okay) => true,
-                        Ok(_) | Err(_) => false,
+                let Some(conn) = conn.get_inner_conn().await else {
+                    return Attempt::Failed("Couldn't get a redis connection.".to_string());
+                };
+                // The remaining ttl is of whoever holds it after the set, us if it succeeded:
+                let result: RedisResult<(Value, i64)> = redis::pipe()
+                    .cmd("SET")
+                    .arg(&lock_id)
+                    .arg(val)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl.as_millis() as usize)
+                    .cmd("PTTL")
+                    .arg(&lock_id)
+                    .query_async(conn)
+                    .await;
+
+                match result {
+                    Ok((Value::Okay, _)) => Attempt::Won,
+                    Ok((_, pttl)) => {
+                        Attempt::Held((pttl > 0).then(|| chrono::TimeDelta::milliseconds(pttl)))
                     }
-                } else {
-                    false
+                    Err(e) => Attempt::Failed(e.to_string()),
                 }
             }
         })
         .await?;
+        lock.acquired_at = chrono::Utc::now();
+
+        Ok(lock)
+    }
+
+    /// Hand the lock over to another process, without a gap another acquirer could take it in, see [`RedisLock::accept_handoff`].
+    ///
+    /// The token is valid until the lock's current validity time, so keep the lock held (but don't use it) until accepted,
+    /// e.g. extend it beforehand to cover the transfer.
+    /// Once accepted, this lock is inert: extending errors with [`RedisLockErr::LostLock`] and unlocking is a no-op.
+    pub fn create_handoff(&self) -> HandoffToken {
+        let mut token = HandoffToken {
+            lock_id: String::from_utf8_lossy(&self.lock_id).to_string(),
+            expires_at_ms: self.expires_at.timestamp_millis(),
+            ttl_ms: self.ttl.as_millis() as u64,
+            signature: String::new(),
+        };
+        token.signature = HandoffToken::sign(&token.lock_id, &token.signed_args(), &self.val);
+        token
+    }
+
+    /// Take over a lock from the process that created the token with [`RedisLock::create_handoff`].
+    ///
+    /// Atomically checks the token matches the lock's current owner and replaces the owner with the returned lock,
+    /// which is held for the ttl of the original.
+    ///
+    /// Errors with [`RedisLockErr::HandoffExpired`] once past the token's expiry,
+    /// or [`RedisLockErr::HandoffStale`] if it doesn't match the current owner, e.g. it's already been accepted.
+    pub async fn accept_handoff(
+        redis: &'a super::Redis,
+        token: &HandoffToken,
+    ) -> RResult<RedisLock<'a>, RedisLockErr> {
+        let now = chrono::Utc::now();
+        if token.expires_at() <= now {
+            return Err(err!(RedisLockErr::HandoffExpired {
+                expired_for: now - token.expires_at()
+            }));
+        }
+
+        let ttl = Duration::from_millis(token.ttl_ms);
+        let mut lock = RedisLock {
+            redis,
+            lock_id: token.lock_id.as_bytes().to_vec(),
+            val: get_unique_lock_id(),
+            wait_up_to: None,
+            expires_at: chrono::DateTime::<chrono::Utc>::MIN_UTC,
+            ttl,
+            acquired_at: now,
+        };
+
+        match redis.disabled_locks() {
+            Some(RedisDisabledLocks::Acquire) => {
+                lock.expires_at = chrono::Utc::now() + ttl;
+                return Ok(lock);
+            }
+            Some(RedisDisabledLocks::Fail) => {
+                return Err(err!(RedisLockErr::Unavailable {
+                    source: "Redis is disabled, configured to fail locks.".to_string()
+                }));
+            }
+            None => {}
+        }
+
+        let lock_id = lock.lock_id.clone();
+        let val = lock.val.clone();
+        let signed_args = token.signed_args();
+        let signature = token.signature.clone();
+        lock.exec_or_retry(ttl, move |mut conn| {
+            let lock_id = lock_id.clone();
+            let val = val.clone();
+            let signed_args = signed_args.clone();
+            let signature = signature.clone();
+            async move {
+                let result: Option<i32> = conn
+                    .batch()
+                    .script(
+                        HANDOFF_SCRIPT
+                            .invoker()
+                            .key(lock_id)
+                            .arg(signed_args)
+                            .arg(signature)
+                            .arg(val)
+                            .arg(ttl.as_millis() as usize),
+                    )
+                    .fire()
+                    .await;
+
+                match result {
+                    Some(1) => Attempt::Won,
+                    Some(_) => Attempt::Held(None),
+                    None => Attempt::Failed("Handoff script errored, see logs.".to_string()),
+                }
+            }
+        })
+        .await
+        .map_err(|e| {
+            // Not matching the owner is the token's fault, rather than the lock being contended:
+            if matches!(e.current_context(), RedisLockErr::Contended { .. }) {
+                e.change_context(RedisLockErr::HandoffStale)
+            } else {
+                e
+            }
+        })?;
+        lock.acquired_at = chrono::Utc::now();
 
         Ok(lock)
     }
@@ -143,15 +395,19 @@ impl<'a> RedisLock<'a> {
     ///
     /// Lock will start extending at the configured ttl,
     /// then slowly increase extension intervals (and ttls) automatically if the closure is long running, to reduce unnecessary redis calls.
+    /// Extensions failing with a retryable error are retried until the lock expires, then this errors with [`RedisLockErr::LostLock`].
+    /// If the future itself errors, this errors with [`RedisLockErr::FutFailed`].
     pub async fn hold_for_fut<R, Fut: Future<Output = RResult<R, AnyErr>>>(
         &mut self,
         fut: Fut,
     ) -> RResult<R, RedisLockErr> {
         if self.expires_at - chrono::Utc::now() < chrono::TimeDelta::zero() {
             return Err(err!(
-                RedisLockErr::UserErr,
+                RedisLockErr::LostLock {
+                    held_for: chrono::Utc::now() - self.acquired_at
+                },
                 "Lock already expired {} ago.",
-                chrono_format_td(self.expires_at - chrono::Utc::now(), true)
+                chrono_format_td(chrono::Utc::now() - self.expires_at, true)
             ));
         }
 
@@ -166,7 +422,7 @@ impl<'a> RedisLock<'a> {
                     tokio::time::sleep(
                         (expires_in_td - chrono::TimeDelta::seconds(1))
                             .to_std()
-                            .change_context(AnyErr)?,
+                            .unwrap_or_default(),
                     )
                     .await;
                 }
@@ -180,53 +436,72 @@ impl<'a> RedisLock<'a> {
                 } else {
                     been_running_for
                 };
-                if !self
-                    .extend(extend_by.to_std().change_context(AnyErr)?)
-                    .await
-                    .change_context(AnyErr)?
-                {
-                    return Err(anyerr!("Failed to extend lock."));
+                match self.extend(extend_by).await {
+                    Ok(()) => {}
+                    // Redis might come back before the lock expires, once it has extending errors with LostLock:
+                    Err(e) if e.current_context().is_retryable() => {
+                        tracing::warn!("Failed to extend lock, retrying: {:?}", e);
+                        tokio::time::sleep(Duration::from_millis(RETRY_DELAY as u64)).await;
+                    }
+                    Err(e) => return Err(e.attach_printable("Failed to extend lock.")),
                 }
             }
             #[allow(unreachable_code)]
-            Ok::<_, error_stack::Report<AnyErr>>(())
+            Ok::<_, error_stack::Report<RedisLockErr>>(())
         };
 
-        let result = futures::select! {
+        futures::select! {
             res = {fut.fuse()} => {
-                res
+                res.change_context(RedisLockErr::FutFailed)
             }
             e_result = {extender_fut.fuse()} => {
                 match e_result {
-                    Ok(_) => Err(anyerr!("Auto lock extender exited unexpectedly with no error.")),
+                    Ok(_) => Err(err!(RedisLockErr::LostLock { held_for: chrono::Utc::now() - self.acquired_at }, "Auto lock extender exited unexpectedly with no error.")),
                     Err(e) => Err(e),
                 }
             }
-        };
-
-        result.change_context(RedisLockErr::UserErr)
+        }
     }
 
-    /// Extend the lifetime of the lock from the previous ttl.
+    /// Extend the lifetime of the lock, the standard redlock extend.
     /// Note this will be the new ttl from this point, meaning if this is called with 10 seconds, the lock will be killed after 10 seconds, not the prior remaining plus 10 seconds.
     ///
-    /// Returns:
-    /// true: the lock was successfully extended.
-    /// false: the lock could not be extended for some reason.
-    pub async fn extend(&mut self, new_ttl: Duration) -> RResult<bool, RedisLockErr> {
+    /// Only extends whilst the lock is still held by us, an expired or stolen lock errors with [`RedisLockErr::LostLock`] rather than being re-acquired.
+    pub async fn extend(&mut self, new_ttl: chrono::TimeDelta) -> RResult<(), RedisLockErr> {
+        let new_ttl = new_ttl.to_std().unwrap_or_default();
         if new_ttl < Duration::from_millis(100) {
+            return Err(err!(RedisLockErr::Misuse {
+                reason: format!(
+                    "Do not set time to live to less than 100 milliseconds, got {:?}.",
+                    new_ttl
+                )
+            }));
+        }
+
+        // Even if the key happens to still exist, past our validity time someone else may already hold it:
+        if self.expires_at <= chrono::Utc::now() {
             return Err(err!(
-                RedisLockErr::UserErr,
-                "Do not set time to live to less than 100 milliseconds."
+                RedisLockErr::LostLock {
+                    held_for: chrono::Utc::now() - self.acquired_at
+                },
+                "Lock already expired {} ago, cannot extend.",
+                chrono_format_td(chrono::Utc::now() - self.expires_at, true)
             ));
         }
 
+        // Only disabled wrappers configured to acquire can have handed out the lock:
+        if self.redis.is_disabled() {
+            self.expires_at = chrono::Utc::now() + new_ttl;
+            return Ok(());
+        }
+
         let lock_id = self.lock_id.clone();
         let val = self.val.clone();
         self.exec_or_retry(new_ttl, move |mut conn| {
             let lock_id = lock_id.clone();
             let val = val.clone();
             async move {
+                // Compare and pexpire, so only extends if the stored token is still ours:
                 let result: Option<i32> = conn
                     .batch()
                     .script(
@@ -240,12 +515,132 @@ impl<'a> RedisLock<'a> {
                     .await;
 
                 match result {
-                    Some(val) => val == 1,
-                    None => false,
+                    Some(1) => Attempt::Won,
+                    Some(_) => Attempt::Held(None),
+                    None => Attempt::Failed("Extend script errored, see logs.".to_string()),
                 }
             }
         })
         .await
+        .map_err(|e| {
+            // Someone else holding it means ours was lost:
+            if matches!(e.current_context(), RedisLockErr::Contended { .. }) {
+                let held_for = chrono::Utc::now() - self.acquired_at;
+                e.change_context(RedisLockErr::LostLock { held_for })
+            } else {
+                e
+            }
+        })?;
+        self.ttl = new_ttl;
+        Ok(())
+    }
+
+    /// Keep the lock held in the background until the returned guard is dropped,
+    /// for when [`RedisLock::hold_for_fut`] doesn't fit, e.g. the work isn't a single future.
+    ///
+    /// A spawned task extends the lock by its ttl every third of its ttl.
+    /// Retryable failures (e.g. redis unavailable) are retried on the next beat whilst the lock is still valid.
+    /// Once the lock is lost, renewal stops and [`RedisLockGuard::lost`] becomes true,
+    /// work should check this, or await [`RedisLockGuard::lost_watch`], and abort as it's no longer protected.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn with_heartbeat(self) -> RedisLockGuard {
+        let (lost_tx, lost_rx) = tokio::sync::watch::channel(false);
+        let redis = self.redis.clone();
+        let lock_id = self.lock_id.clone();
+        let val = self.val.clone();
+        let ttl = self.ttl;
+        let acquired_at = self.acquired_at;
+        let mut expires_at = self.expires_at;
+        let heartbeat = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(ttl / 3).await;
+                let mut lock = RedisLock {
+                    redis: &redis,
+                    lock_id: lock_id.clone(),
+                    val: val.clone(),
+                    wait_up_to: None,
+                    expires_at,
+                    ttl,
+                    acquired_at,
+                };
+                match lock
+                    .extend(chrono::TimeDelta::from_std(ttl).unwrap_or_default())
+                    .await
+                {
+                    Ok(()) => expires_at = lock.expires_at,
+                    Err(e) if e.current_context().is_retryable() => {
+                        tracing::warn!("Failed to renew redis lock heartbeat, will retry: {:?}", e);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Lost redis lock whilst holding with a heartbeat: {:?}", e);
+                        let _ = lost_tx.send(true);
+                        return;
+                    }
+                }
+            }
+        });
+        let redis = self.redis.clone();
+        let lost = lost_rx.clone();
+        let release = AsyncGuard::new(move || async move {
+            // Unlocking can't help if already lost, the token is either gone or someone else's:
+            if *lost.borrow() {
+                return Ok(());
+            }
+            let unlocked = RedisLock {
+                redis: &redis,
+                lock_id: self.lock_id,
+                val: self.val,
+                wait_up_to: None,
+                expires_at: chrono::Utc::now(),
+                ttl: Duration::ZERO,
+                acquired_at: chrono::Utc::now(),
+            }
+            .unlock()
+            .await;
+            if unlocked {
+                Ok(())
+            } else {
+                Err(anyerr!("Couldn't release redis lock."))
+            }
+        });
+        RedisLockGuard {
+            lost: lost_rx,
+            heartbeat,
+            release: Some(release),
+        }
+    }
+
+    /// Cheaply check the lock is still held by us, e.g. before committing the results of the work it protects.
+    ///
+    /// False if the lock has passed its validity time, or a quorum of servers no longer store our token (it expired or was stolen).
+    pub async fn is_still_held(&self) -> bool {
+        if self.expires_at <= chrono::Utc::now() {
+            return false;
+        }
+        if self.redis.is_disabled() {
+            return true;
+        }
+
+        let conns = self.redis.get_conn_to_each_server();
+        let quorum = conns.len() / 2 + 1;
+        let held = futures::future::join_all(conns.into_iter().map(|mut conn| {
+            let lock_id = self.lock_id.clone();
+            async move {
+                if let Some(conn) = conn.get_inner_conn().await {
+                    let result: RedisResult<Option<Vec<u8>>> =
+                        redis::cmd("GET").arg(lock_id).query_async(conn).await;
+                    matches!(result, Ok(Some(val)) if val == self.val)
+                } else {
+                    false
+                }
+            }
+        }))
+        .await
+        .into_iter()
+        .filter(|held| *held)
+        .count();
+        held >= quorum
     }
 
     /// Unlock the lock manually.
@@ -255,6 +650,9 @@ impl<'a> RedisLock<'a> {
     /// true: the lock was successfully unlocked.
     /// false: the lock could not be unlocked for some reason.
     pub async fn unlock(&mut self) -> bool {
+        if self.redis.is_disabled() {
+            return true;
+        }
         let result =
             futures::future::join_all(self.redis.get_conn_to_each_server().into_iter().map(
                 |mut conn| {
@@ -279,65 +677,148 @@ impl<'a> RedisLock<'a> {
     }
 
     // Error handling and retrying for a locking operation (lock/extend).
-    async fn exec_or_retry<F, Fut>(&mut self, ttl: Duration, cb: F) -> RResult<bool, RedisLockErr>
+    async fn exec_or_retry<F, Fut>(&mut self, ttl: Duration, cb: F) -> RResult<(), RedisLockErr>
     where
         F: Fn(RedisConn<'a>) -> Fut,
-        Fut: Future<Output = bool>,
+        Fut: Future<Output = Attempt>,
     {
         let ttl = ttl.as_millis() as usize;
 
         let attempt_beginning = Instant::now();
         let wait_up_to = self.wait_up_to.unwrap_or(Duration::from_secs(0));
         let mut first_run = true;
+        // Context from the latest attempt, used to build the error if never succeeds:
+        let mut unreachable_quorum = None;
+        let mut retry_after_hint = None;
+        let mut slow_attempt = None;
         while first_run || wait_up_to > attempt_beginning.elapsed() {
             first_run = false;
 
             let start_time = Instant::now();
             let conns = self.redis.get_conn_to_each_server();
+            let n_servers = conns.len() as u32;
             // Quorum is defined to be N/2+1, with N being the number of given Redis instances.
-            let quorum = (conns.len() as u32) / 2 + 1;
+            let quorum = n_servers / 2 + 1;
 
-            let n = futures::future::join_all(conns.into_iter().map(&cb))
-                .await
-                .into_iter()
-                .fold(0, |count, locked| if locked { count + 1 } else { count });
+            let attempts = futures::future::join_all(conns.into_iter().map(&cb)).await;
+            let n = attempts
+                .iter()
+                .filter(|attempt| matches!(attempt, Attempt::Won))
+                .count() as u32;
+            let failures = attempts
+                .iter()
+                .filter_map(|attempt| match attempt {
+                    Attempt::Failed(source) => Some(source.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            // Too many failed servers to ever reach quorum, so redis is the problem rather than a holder:
+            unreachable_quorum =
+                (failures.len() as u32 > n_servers - quorum).then(|| failures.join(" | "));
+            // The lock might be free once all holders' locks have expired:
+            retry_after_hint = attempts
+                .iter()
+                .filter_map(|attempt| match attempt {
+                    Attempt::Held(remaining) => *remaining,
+                    _ => None,
+                })
+                .max();
 
             let drift = (ttl as f32 * CLOCK_DRIFT_FACTOR) as usize + 2;
             let elapsed = start_time.elapsed();
             let elapsed_ms =
                 elapsed.as_secs() as usize * 1000 + elapsed.subsec_nanos() as usize / 1_000_000;
             if ttl <= drift + elapsed_ms {
-                return Err(err!(RedisLockErr::Unavailable).attach_printable(format!(
-                    "Ttl expired during locking, ttl millis: {}, potential_drift: {}, elapsed_ms: {}. Try increasing the lock's ttl.",
+                // Slow round trips or clock drift, transient so retried rather than treated as misuse.
+                // Anything acquired is released, it can't be relied on:
+                slow_attempt = Some(format!(
+                    "Ttl expired during locking, ttl millis: {}, potential_drift: {}, elapsed_ms: {}. Consider increasing the lock's ttl.",
                     ttl, drift, elapsed_ms
-                )));
-            }
-            let validity_time_millis = ttl
-                - drift
-                - elapsed.as_secs() as usize * 1000
-                - elapsed.subsec_nanos() as usize / 1_000_000;
-
-            // If met the quorum and ttl still holds, succeed, otherwise just unlock.
-            if n >= quorum && validity_time_millis > 0 {
-                self.expires_at =
-                    chrono::Utc::now() + Duration::from_millis(validity_time_millis as u64);
-                return Ok(true);
-            } else {
+                ));
                 self.unlock().await;
+            } else {
+                slow_attempt = None;
+                let validity_time_millis = ttl
+                    - drift
+                    - elapsed.as_secs() as usize * 1000
+                    - elapsed.subsec_nanos() as usize / 1_000_000;
+
+                // If met the quorum and ttl still holds, succeed, otherwise just unlock.
+                if n >= quorum && validity_time_millis > 0 {
+                    self.expires_at =
+                        chrono::Utc::now() + Duration::from_millis(validity_time_millis as u64);
+                    return Ok(());
+                } else {
+                    self.unlock().await;
+                }
             }
 
             let n = thread_rng().gen_range(0..RETRY_DELAY);
             tokio::time::sleep(Duration::from_millis(n as u64)).await;
         }
 
-        Err(err!(RedisLockErr::Unavailable)).attach_printable(format!(
-            "Lock, unavailable, {}",
-            if let Some(wait_up_to) = self.wait_up_to {
-                format!("waited for: {:?}.", wait_up_to)
-            } else {
-                "user configured to not wait all.".to_string()
-            }
-        ))
+        let waited = if let Some(wait_up_to) = self.wait_up_to {
+            format!("waited for: {:?}.", wait_up_to)
+        } else {
+            "user configured to not wait all.".to_string()
+        };
+        if let Some(source) = unreachable_quorum.or(slow_attempt) {
+            Err(err!(
+                RedisLockErr::Unavailable { source },
+                "Lock, unavailable, {}",
+                waited
+            ))
+        } else {
+            Err(err!(
+                RedisLockErr::Contended { retry_after_hint },
+                "Lock, held elsewhere, {}",
+                waited
+            ))
+        }
+    }
+}
+
+/// A lock kept held by a background heartbeat, see [`RedisLock::with_heartbeat`].
+///
+/// Released on drop (best-effort, via a spawned task), use [`RedisLockGuard::release`] to release it immediately.
+pub struct RedisLockGuard {
+    lost: tokio::sync::watch::Receiver<bool>,
+    heartbeat: tokio::task::JoinHandle<()>,
+    // Only taken by release(), left for the drop otherwise:
+    release: Option<AsyncGuard>,
+}
+
+impl RedisLockGuard {
+    /// True once a renewal has failed, meaning the lock may now be held by someone else.
+    pub fn lost(&self) -> bool {
+        *self.lost.borrow()
+    }
+
+    /// A watch of [`RedisLockGuard::lost`], e.g. to select against the protected work:
+    ///
+    /// `lost.wait_for(|lost| *lost).await`
+    pub fn lost_watch(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.lost.clone()
+    }
+
+    /// Stop renewing and release the lock, making it immediately available to others.
+    ///
+    /// Returns false if the lock had already been lost, or redis couldn't be used.
+    pub async fn release(mut self) -> bool {
+        self.heartbeat.abort();
+        let lost = self.lost();
+        let released = match self.release.take() {
+            Some(release) => release.disarm_and_run().await.is_ok(),
+            None => false,
+        };
+        released && !lost
+    }
+}
+
+impl Drop for RedisLockGuard {
+    fn drop(&mut self) {
+        // The release guard then unlocks in the background:
+        self.heartbeat.abort();
     }
 }
 
@@ -350,10 +831,10 @@ fn get_unique_lock_id() -> Vec<u8> {
 
 /// Run by the main tester that spawns up a redis process.
 #[cfg(test)]
-pub async fn redis_dlock_tests(r: &super::Redis) -> RResult<(), AnyErr> {
-    use chrono::TimeDelta;
+pub async fn redis_dlock_tests(r: &super::Redis, fail_r: &super::Redis) -> RResult<(), AnyErr> {
+    use chrono::{SubsecRound, TimeDelta};
 
-    use crate::chrono::chrono_format_td;
+    use crate::{chrono::chrono_format_td, errors::test_helpers::*};
 
     // Just checking the object is normal: (from upstream)
     fn is_normal<T: Sized + Send + Sync + Unpin>() {}
@@ -444,7 +925,7 @@ pub async fn redis_dlock_tests(r: &super::Redis) -> RResult<(), AnyErr> {
     );
     tokio::time::sleep(Duration::from_millis(50)).await;
     // This means should be valid for another 100ms:
-    lock.extend(Duration::from_millis(100))
+    lock.extend(TimeDelta::milliseconds(100))
         .await
         .change_context(AnyErr)?;
     // Sleep for 60, would have expired original, but new will still be valid for another 40:
@@ -454,6 +935,57 @@ pub async fn redis_dlock_tests(r: &super::Redis) -> RResult<(), AnyErr> {
     tokio::time::sleep(Duration::from_millis(50)).await;
     check_lockable!("test_lock_extend");
 
+    // Extending mid-hold keeps it held, extending after expiry errors rather than re-acquiring:
+    let mut lock = r
+        .dlock(
+            NS,
+            "test_lock_extend_expired",
+            Duration::from_millis(100),
+            None,
+        )
+        .await
+        .change_context(AnyErr)?;
+    assert!(lock.is_still_held().await);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    lock.extend(TimeDelta::milliseconds(150))
+        .await
+        .change_context(AnyErr)?;
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(lock.is_still_held().await);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!lock.is_still_held().await);
+    assert!(lock.extend(TimeDelta::milliseconds(500)).await.is_err());
+    // The failed extend didn't re-acquire, so another client can take it, and ours still can't be extended:
+    let mut other = r
+        .dlock(
+            NS,
+            "test_lock_extend_expired",
+            Duration::from_millis(500),
+            None,
+        )
+        .await
+        .change_context(AnyErr)?;
+    assert!(other.is_still_held().await);
+    assert!(!lock.is_still_held().await);
+    assert!(lock.extend(TimeDelta::milliseconds(500)).await.is_err());
+    assert!(other.is_still_held().await);
+
+    // A stolen lock (our token replaced whilst we think it's valid) can't be extended either:
+    let mut lock = r
+        .dlock(NS, "test_lock_extend_stolen", Duration::from_secs(1), None)
+        .await
+        .change_context(AnyErr)?;
+    let mut conn = r.conn();
+    redis::cmd("SET")
+        .arg("test_lock:test_lock_extend_stolen")
+        .arg("thief")
+        .query_async::<_, ()>(conn.get_inner_conn().await.unwrap())
+        .await
+        .change_context(AnyErr)?;
+    assert!(!lock.is_still_held().await);
+    assert!(lock.extend(TimeDelta::seconds(1)).await.is_err());
+    other.unlock().await;
+
     // Confirm retries would work to wait for a lock:
     let lock = r
         .dlock(NS, "test_lock_retry", Duration::from_millis(300), None)
@@ -535,5 +1067,264 @@ pub async fn redis_dlock_tests(r: &super::Redis) -> RResult<(), AnyErr> {
     // Should now be able to lock as the lock should be released the second the closure finishes:
     check_lockable!("test_lock_hold_for_fut");
 
+    // Heartbeat guards keep the lock held well past its ttl until dropped:
+    let guard = r
+        .dlock_with_heartbeat(NS, "test_lock_heartbeat", Duration::from_millis(300), None)
+        .await
+        .change_context(AnyErr)?;
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    check_not_lockable!("test_lock_heartbeat");
+    assert!(!guard.lost());
+    drop(guard);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    check_lockable!("test_lock_heartbeat");
+
+    // A competing acquirer waiting on the lock only gets it after the guard is released:
+    let guard = r
+        .dlock_with_heartbeat(
+            NS,
+            "test_lock_heartbeat_wait",
+            Duration::from_millis(300),
+            None,
+        )
+        .await
+        .change_context(AnyErr)?;
+    let waiter = async {
+        r.dlock(
+            NS,
+            "test_lock_heartbeat_wait",
+            Duration::from_secs(1),
+            Some(Duration::from_secs(3)),
+        )
+        .await
+        .map(|_| Instant::now())
+    };
+    let releaser = async {
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let released_at = Instant::now();
+        assert!(guard.release().await);
+        released_at
+    };
+    let (acquired_at, released_at) = tokio::join!(waiter, releaser);
+    assert!(acquired_at.change_context(AnyErr)? >= released_at);
+
+    // Lost once a renewal fails, here because the lock was stolen:
+    let guard = r
+        .dlock_with_heartbeat(
+            NS,
+            "test_lock_heartbeat_lost",
+            Duration::from_millis(300),
+            None,
+        )
+        .await
+        .change_context(AnyErr)?;
+    let mut lost = guard.lost_watch();
+    redis::cmd("SET")
+        .arg("test_lock:test_lock_heartbeat_lost")
+        .arg("thief")
+        .query_async::<_, ()>(conn.get_inner_conn().await.unwrap())
+        .await
+        .change_context(AnyErr)?;
+    tokio::time::timeout(Duration::from_secs(1), lost.wait_for(|lost| *lost))
+        .await
+        .change_context(AnyErr)?
+        .change_context(AnyErr)?;
+    assert!(guard.lost());
+    // The thief's lock is untouched:
+    assert!(!guard.release().await);
+    check_not_lockable!("test_lock_heartbeat_lost");
+
+    // Handing a lock over to another process, B takes over without a gap, A's lock becomes inert:
+    macro_rules! handoff_err {
+        ($token:expr) => {{
+            expect_err_report(RedisLock::accept_handoff(r, $token).await.map(|_| ()))
+        }};
+    }
+    let mut lock_a = r
+        .dlock(NS, "test_lock_handoff", Duration::from_secs(1), None)
+        .await
+        .change_context(AnyErr)?;
+    let token = lock_a.create_handoff();
+    // Sent out-of-band:
+    let token: HandoffToken =
+        serde_json::from_str(&serde_json::to_string(&token).change_context(AnyErr)?)
+            .change_context(AnyErr)?;
+    assert_eq!(token.expires_at(), lock_a.expires_at.trunc_subsecs(3));
+    // Still held by A in the handoff window:
+    check_not_lockable!("test_lock_handoff");
+    let mut lock_b = RedisLock::accept_handoff(r, &token)
+        .await
+        .change_context(AnyErr)?;
+    assert!(lock_b.is_still_held().await);
+    assert!(!lock_a.is_still_held().await);
+    check_not_lockable!("test_lock_handoff");
+    lock_b
+        .extend(TimeDelta::seconds(2))
+        .await
+        .change_context(AnyErr)?;
+    // A's late release and extend are no-ops:
+    assert!(!lock_a.unlock().await);
+    assert!(matches!(
+        lock_a
+            .extend(TimeDelta::seconds(1))
+            .await
+            .unwrap_err()
+            .current_context(),
+        RedisLockErr::LostLock { .. }
+    ));
+    assert!(lock_b.is_still_held().await);
+    check_not_lockable!("test_lock_handoff");
+    // The token can't be reused, e.g. by an interloper that intercepted it:
+    let e = handoff_err!(&token);
+    assert!(matches!(e.current_context(), RedisLockErr::HandoffStale));
+    assert!(!e.current_context().is_retryable());
+    assert!(lock_b.unlock().await);
+    check_lockable!("test_lock_handoff");
+
+    // Forged tokens don't match the owner, e.g. with a later expiry:
+    let mut lock_a = r
+        .dlock(NS, "test_lock_handoff", Duration::from_secs(1), None)
+        .await
+        .change_context(AnyErr)?;
+    let mut forged = lock_a.create_handoff();
+    forged.expires_at_ms += 60_000;
+    let e = handoff_err!(&forged);
+    assert!(matches!(e.current_context(), RedisLockErr::HandoffStale));
+    let mut forged = lock_a.create_handoff();
+    forged.signature = HandoffToken::sign(&forged.lock_id, &forged.signed_args(), b"guess");
+    let e = handoff_err!(&forged);
+    assert!(matches!(e.current_context(), RedisLockErr::HandoffStale));
+    assert!(lock_a.is_still_held().await);
+    // Stale once the lock was released and re-acquired, even by the same process:
+    let token = lock_a.create_handoff();
+    lock_a.unlock().await;
+    let mut lock_c = r
+        .dlock(NS, "test_lock_handoff", Duration::from_secs(1), None)
+        .await
+        .change_context(AnyErr)?;
+    let e = handoff_err!(&token);
+    assert!(matches!(e.current_context(), RedisLockErr::HandoffStale));
+    assert!(lock_c.is_still_held().await);
+    lock_c.unlock().await;
+
+    // Expired tokens are rejected before reaching redis:
+    let lock_a = r
+        .dlock(NS, "test_lock_handoff", Duration::from_millis(150), None)
+        .await
+        .change_context(AnyErr)?;
+    let token = lock_a.create_handoff();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let e = handoff_err!(&token);
+    assert!(matches!(
+        e.current_context(),
+        RedisLockErr::HandoffExpired { expired_for } if *expired_for > TimeDelta::zero()
+    ));
+    assert!(e
+        .current_context()
+        .to_string()
+        .starts_with("Lock handoff expired"));
+    check_lockable!("test_lock_handoff");
+
+    // Errors should say what went wrong and whether retrying could help:
+    macro_rules! lock_err {
+        ($r:expr, $name:expr, $ttl:expr) => {{
+            match $r.dlock(NS, $name, $ttl, None).await {
+                Ok(_) => return Err(anyerr!("Lock acquired, even though it should have errored")),
+                Err(e) => e,
+            }
+        }};
+    }
+
+    // Held by another:
+    let mut holder = r
+        .dlock(NS, "test_lock_errs", Duration::from_secs(2), None)
+        .await
+        .change_context(AnyErr)?;
+    let e = lock_err!(r, "test_lock_errs", Duration::from_secs(1));
+    assert!(e.current_context().is_retryable());
+    match e.current_context() {
+        RedisLockErr::Contended {
+            retry_after_hint: Some(hint),
+        } => {
+            assert_td_in_range!(
+                *hint,
+                TimeDelta::milliseconds(1500)..TimeDelta::milliseconds(2001)
+            );
+        }
+        other => return Err(anyerr!("Expected Contended, got: {:?}", other)),
+    }
+    assert!(e
+        .current_context()
+        .to_string()
+        .starts_with("Lock held elsewhere, expires in"));
+    holder.unlock().await;
+
+    // Redis down:
+    let e = lock_err!(fail_r, "test_lock_errs", Duration::from_secs(1));
+    assert!(e.current_context().is_retryable());
+    assert!(
+        matches!(e.current_context(), RedisLockErr::Unavailable { source } if !source.is_empty()),
+        "{:?}",
+        e
+    );
+
+    // Bad ttls:
+    let e = lock_err!(r, "test_lock_errs", Duration::ZERO);
+    assert!(!e.current_context().is_retryable());
+    assert!(
+        matches!(e.current_context(), RedisLockErr::Misuse { reason } if reason.contains("100 milliseconds")),
+        "{:?}",
+        e
+    );
+    let mut lock = r
+        .dlock(NS, "test_lock_errs", Duration::from_millis(200), None)
+        .await
+        .change_context(AnyErr)?;
+    let e = expect_err_report(lock.extend(TimeDelta::zero()).await);
+    assert!(matches!(e.current_context(), RedisLockErr::Misuse { .. }));
+
+    // Expiring mid-hold, both from our own validity time and when redis no longer has our token:
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let e = lock.extend(TimeDelta::seconds(1)).await.unwrap_err();
+    assert!(!e.current_context().is_retryable());
+    match e.current_context() {
+        RedisLockErr::LostLock { held_for } => {
+            assert_td_in_range!(
+                *held_for,
+                TimeDelta::milliseconds(250)..TimeDelta::seconds(1)
+            );
+        }
+        other => return Err(anyerr!("Expected LostLock, got: {:?}", other)),
+    }
+    let mut lock = r
+        .dlock(NS, "test_lock_errs", Duration::from_secs(1), None)
+        .await
+        .change_context(AnyErr)?;
+    redis::cmd("DEL")
+        .arg("test_lock:test_lock_errs")
+        .query_async::<_, ()>(conn.get_inner_conn().await.unwrap())
+        .await
+        .change_context(AnyErr)?;
+    let e = lock.extend(TimeDelta::seconds(1)).await.unwrap_err();
+    assert!(matches!(
+        e.current_context(),
+        RedisLockErr::LostLock { held_for } if *held_for < TimeDelta::seconds(1)
+    ));
+
+    // The future held for erroring isn't a lock problem:
+    // (the future's own error is kept in the chain)
+    let e = assert_err_context::<AnyErr>(
+        r.dlock_for_fut(NS, "test_lock_errs", None, async {
+            Err::<(), _>(anyerr!("Work failed."))
+        })
+        .await,
+    );
+    assert!(matches!(
+        e.downcast_ref::<RedisLockErr>(),
+        Some(RedisLockErr::FutFailed)
+    ));
+    assert!(!e.downcast_ref::<RedisLockErr>().unwrap().is_retryable());
+    check_lockable!("test_lock_errs");
+
     Ok(())
 }
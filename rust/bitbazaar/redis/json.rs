@@ -51,3 +51,206 @@ where
         out.write_arg(&data)
     }
 }
+
+/// Decodes to `None` rather than erroring when a value is missing or can't be decoded as `T`,
+/// so one corrupt entry in a collection doesn't fail the whole batch.
+/// Access the inner with .0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisFuzzy<T>(pub Option<T>);
+
+impl<T: FromRedisValue> FromRedisValue for RedisFuzzy<T> {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        match v {
+            redis::Value::Nil => Ok(Self(None)),
+            v => Ok(Self(T::from_redis_value(v).ok())),
+        }
+    }
+}
+
+/// A version for the schema of a type stored with [`RedisJsonTagged`].
+///
+/// Bump it whenever a change would stop older readers decoding the data (or newer readers decoding older data).
+pub trait RedisSchema {
+    /// Any string identifying the current version of the type's schema, e.g. `"user_v2"`.
+    const SCHEMA_VERSION: &'static str;
+}
+
+/// An opt-in alternative to [`RedisJson`] that stores a fingerprint of the type's [`RedisSchema::SCHEMA_VERSION`] alongside the data,
+/// as `{"s": "<fingerprint>", "d": <data>}`.
+///
+/// When services are deployed out of sync, a reader of a different version fails with a detailed error naming the fingerprints,
+/// rather than looking like missing data. Read with [`super::RedisConn::get_tagged`] or [`super::RedisConn::mget_tagged`] to also record an exception naming the key.
+///
+/// For migrating from [`RedisJson`], untagged data that decodes as `T` is also accepted.
+///
+/// Access the inner with .0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisJsonTagged<T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned>(pub T);
+
+impl<T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned> RedisJsonTagged<T> {
+    /// The fingerprint stored with the data, derived from [`RedisSchema::SCHEMA_VERSION`].
+    pub fn fingerprint() -> String {
+        format!("{:016x}", crate::hash::fnv1a(T::SCHEMA_VERSION.as_bytes()))
+    }
+
+    /// Decode, returning a detailed message if not possible.
+    pub(crate) fn decode(value: serde_json::Value) -> Result<Self, String> {
+        match value {
+            serde_json::Value::Object(mut map)
+                if map.len() == 2 && map.get("s").is_some_and(|s| s.is_string()) =>
+            {
+                if let Some(data) = map.remove("d") {
+                    let found = map.remove("s").unwrap_or_default();
+                    let found = found.as_str().unwrap_or_default();
+                    let expected = Self::fingerprint();
+                    if found != expected {
+                        return Err(format!(
+                            "Schema mismatch decoding '{}': found fingerprint '{}', expected '{}' (schema version '{}').",
+                            std::any::type_name::<T>(),
+                            found,
+                            expected,
+                            T::SCHEMA_VERSION
+                        ));
+                    }
+                    return serde_json::from_value(data).map(Self).map_err(|e| {
+                        format!(
+                            "Failed to decode '{}' with a matching schema fingerprint: {}",
+                            std::any::type_name::<T>(),
+                            e
+                        )
+                    });
+                }
+                Self::decode_untagged(serde_json::Value::Object(map))
+            }
+            value => Self::decode_untagged(value),
+        }
+    }
+
+    /// Legacy untagged data, e.g. written by [`RedisJson`] before migrating.
+    fn decode_untagged(value: serde_json::Value) -> Result<Self, String> {
+        serde_json::from_value(value).map(Self).map_err(|e| {
+            format!(
+                "Failed to decode '{}', the data isn't tagged with a schema fingerprint and doesn't decode untagged either: {}",
+                std::any::type_name::<T>(),
+                e
+            )
+        })
+    }
+}
+
+impl<T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned> serde::Serialize
+    for RedisJsonTagged<T>
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("RedisJsonTagged", 2)?;
+        state.serialize_field("s", &Self::fingerprint())?;
+        state.serialize_field("d", &self.0)?;
+        state.end()
+    }
+}
+
+impl<'de, T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned> serde::Deserialize<'de>
+    for RedisJsonTagged<T>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Self::decode(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned> FromRedisValue
+    for RedisJsonTagged<T>
+{
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        match v {
+            redis::Value::Data(data) => Ok(serde_json::from_slice(data)?),
+            _ => Err(redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Cannot convert to Serialize",
+            ))),
+        }
+    }
+}
+
+impl<T: RedisSchema + serde::Serialize + serde::de::DeserializeOwned> ToRedisArgs
+    for RedisJsonTagged<T>
+{
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + redis::RedisWrite,
+    {
+        let data = serde_json::to_vec(&self).unwrap();
+        out.write_arg(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserV1 {
+        name: String,
+    }
+
+    impl RedisSchema for UserV1 {
+        const SCHEMA_VERSION: &'static str = "user_v1";
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserV2 {
+        name: String,
+    }
+
+    impl RedisSchema for UserV2 {
+        const SCHEMA_VERSION: &'static str = "user_v2";
+    }
+
+    #[rstest]
+    fn test_redis_json_tagged() {
+        let v1 = serde_json::to_value(RedisJsonTagged(UserV1 {
+            name: "bob".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(
+            v1,
+            serde_json::json!({"s": RedisJsonTagged::<UserV1>::fingerprint(), "d": {"name": "bob"}})
+        );
+
+        // Matching round-trip:
+        assert_eq!(
+            RedisJsonTagged::<UserV1>::decode(v1.clone()).unwrap().0,
+            UserV1 {
+                name: "bob".to_string()
+            }
+        );
+
+        // Mismatch names both fingerprints, even though the data would decode:
+        let e = RedisJsonTagged::<UserV2>::decode(v1.clone()).unwrap_err();
+        assert!(
+            e.contains(&RedisJsonTagged::<UserV1>::fingerprint()),
+            "{}",
+            e
+        );
+        assert!(
+            e.contains(&RedisJsonTagged::<UserV2>::fingerprint()),
+            "{}",
+            e
+        );
+        assert!(serde_json::from_value::<RedisJsonTagged<UserV2>>(v1).is_err());
+
+        // Legacy untagged data still readable:
+        assert_eq!(
+            RedisJsonTagged::<UserV2>::decode(serde_json::json!({"name": "bob"}))
+                .unwrap()
+                .0,
+            UserV2 {
+                name: "bob".to_string()
+            }
+        );
+        assert!(RedisJsonTagged::<UserV2>::decode(serde_json::json!({"other": 1})).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use super::RedisConn;
+use crate::errors::prelude::*;
+
+/// Suffix appended to a [`RedisConn::set_json_streamed`] key (with a random id) to form the temporary key it's uploaded under.
+const STREAM_TMP_SUFFIX: &str = "__streaming";
+
+/// Each append refreshes the temporary key's expiry to this, so uploads from crashed processes clean themselves up.
+const STREAM_TMP_TTL: Duration = Duration::from_secs(60);
+
+/// Public methods for RedisConn.
+impl<'a> RedisConn<'a> {
+    /// Set a (very large) value as json, without ever holding the full json in memory.
+    ///
+    /// [`super::RedisJson`] serializes the whole value before handing it to redis, doubling peak memory for e.g. multi-hundred-MB export blobs.
+    /// Here the value is serialized incrementally on the blocking pool, uploaded `chunk_size` bytes at a time
+    /// with APPEND to a temporary key, which is then atomically renamed into place, so readers only ever see the old or the complete new value.
+    /// Peak memory is a few chunks.
+    ///
+    /// Can't be part of a [`super::RedisBatch`], pipelines need all their data upfront.
+    /// Read back with a normal get of [`super::RedisJson`] (which needs the full value in memory at once), or [`RedisConn::get_json_streamed`].
+    ///
+    /// The temporary key is removed if the upload fails or is dropped partway, otherwise expires shortly after the last chunk.
+    pub async fn set_json_streamed<T>(
+        &mut self,
+        namespace: &str,
+        key: &str,
+        value: T,
+        expiry: Option<Duration>,
+        chunk_size: usize,
+    ) -> RResult<(), AnyErr>
+    where
+        T: serde::Serialize + Send + 'static,
+    {
+        if chunk_size == 0 {
+            return Err(anyerr!("chunk_size must be greater than 0."));
+        }
+        let final_key = self.final_key(namespace, key.into());
+        let tmp_key = self.final_key(
+            namespace,
+            format!("{}{}_{}", key, STREAM_TMP_SUFFIX, uuid::Uuid::new_v4()).into(),
+        );
+        let pool = self.pool().clone();
+        let conn = self
+            .get_inner_conn()
+            .await
+            .ok_or_else(|| anyerr!("Redis unavailable."))?;
+
+        // Channel of 1 so the serializer is at most a chunk ahead of the upload:
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+        let serializer = tokio::task::spawn_blocking(move || {
+            let mut writer = ChunkWriter {
+                buf: Vec::with_capacity(chunk_size),
+                chunk_size,
+                tx,
+            };
+            serde_json::to_writer(&mut writer, &value)?;
+            std::io::Write::flush(&mut writer).map_err(serde_json::Error::io)
+        });
+
+        let guard = TmpKeyGuard {
+            pool,
+            key: Some(tmp_key.clone()),
+        };
+        while let Some(chunk) = rx.recv().await {
+            redis::pipe()
+                .append(&tmp_key, chunk)
+                .ignore()
+                .pexpire(&tmp_key, STREAM_TMP_TTL.as_millis() as i64)
+                .ignore()
+                .query_async::<_, ()>(&mut *conn)
+                .await
+                .change_context(AnyErr)
+                .attach_printable_lazy(|| {
+                    format!("Failed to upload a chunk of '{}'.", final_key)
+                })?;
+        }
+        serializer
+            .await
+            .change_context(AnyErr)?
+            .change_context(AnyErr)
+            .attach_printable_lazy(|| format!("Failed to serialize '{}'.", final_key))?;
+
+        // The temporary key's expiry moves with the rename, so always replace it:
+        let mut pipe = redis::pipe();
+        pipe.atomic().rename(&tmp_key, &final_key).ignore();
+        match expiry {
+            Some(expiry) => pipe.pexpire(&final_key, expiry.as_millis().max(1) as i64),
+            None => pipe.persist(&final_key),
+        }
+        .ignore();
+        pipe.query_async::<_, ()>(&mut *conn)
+            .await
+            .change_context(AnyErr)
+            .attach_printable_lazy(|| {
+                format!("Failed to rename the upload into '{}'.", final_key)
+            })?;
+        guard.disarm();
+        Ok(())
+    }
+
+    /// Get a json value in `chunk_size` byte ranges with GETRANGE, e.g. one written with [`RedisConn::set_json_streamed`].
+    ///
+    /// Avoids a single huge reply, but the full json is still held in memory to decode it.
+    /// The chunks aren't read atomically, a value replaced mid-read will almost certainly fail to decode.
+    ///
+    /// Returns None if the value doesn't exist, can't be decoded, or redis is unavailable.
+    pub async fn get_json_streamed<T>(
+        &mut self,
+        namespace: &str,
+        key: &str,
+        chunk_size: usize,
+    ) -> Option<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let final_key = self.final_key(namespace, key.into());
+        let chunk_size = chunk_size.max(1);
+        let conn = self.get_inner_conn().await?;
+        let len = match redis::cmd("STRLEN")
+            .arg(&final_key)
+            .query_async::<_, usize>(&mut *conn)
+            .await
+        {
+            Ok(0) => return None,
+            Ok(len) => len,
+            Err(e) => {
+                tracing::error!("Could not get the length of '{}': {}", final_key, e);
+                return None;
+            }
+        };
+
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let start = data.len();
+            match redis::cmd("GETRANGE")
+                .arg(&final_key)
+                .arg(start)
+                .arg(start + chunk_size - 1)
+                .query_async::<_, Vec<u8>>(&mut *conn)
+                .await
+            {
+                // Shrunk since the STRLEN:
+                Ok(chunk) if chunk.is_empty() => break,
+                Ok(chunk) => data.extend(chunk),
+                Err(e) => {
+                    tracing::error!("Could not read a chunk of '{}': {}", final_key, e);
+                    return None;
+                }
+            }
+        }
+        match serde_json::from_slice(&data) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::error!("Could not decode '{}': {}", final_key, e);
+                None
+            }
+        }
+    }
+}
+
+/// Hands json to the uploader in chunks as it's serialized.
+struct ChunkWriter {
+    buf: Vec<u8>,
+    chunk_size: usize,
+    tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+}
+
+impl ChunkWriter {
+    fn send(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(self.chunk_size));
+        // The uploader stopped (failed or dropped), so stop serializing:
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| std::io::Error::other("Upload stopped."))
+    }
+}
+
+impl std::io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() >= self.chunk_size {
+            self.send()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send()
+    }
+}
+
+/// Deletes the temporary upload key on drop unless disarmed, including when the upload future is dropped partway.
+struct TmpKeyGuard {
+    pool: deadpool_redis::Pool,
+    key: Option<String>,
+}
+
+impl TmpKeyGuard {
+    fn disarm(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for TmpKeyGuard {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        // Can't await in drop, the temporary key expires by itself if there's no runtime left:
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        handle.spawn(async move {
+            match pool.get().await {
+                Ok(mut conn) => {
+                    if let Err(e) = redis::cmd("DEL")
+                        .arg(&key)
+                        .query_async::<_, ()>(&mut conn)
+                        .await
+                    {
+                        tracing::error!("Could not remove the streamed upload '{}': {}", key, e);
+                    }
+                }
+                Err(e) => tracing::error!("Could not get redis connection: {}", e),
+            }
+        });
+    }
+}
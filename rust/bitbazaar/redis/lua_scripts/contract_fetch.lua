@@ -0,0 +1,42 @@
+-- Fetch only what's needed to check each of KEYS against a contract, returning a flat type, value, type, value... array.
+-- ARGV holds each key's check in order, either:
+-- - "decode", SAMPLE: the value is the string, or up to SAMPLE elements of a collection (hashes and zsets flattened with their values/scores).
+-- - "fields", N, FIELD1...FIELDN: the value is the fields missing from the hash.
+-- The value is false (nil) for missing keys, hash field checks on other types,
+-- or types with no simple representation (e.g. streams).
+local results = {}
+local arg = 1
+for i, key in ipairs(KEYS) do
+    local typ = redis.call("TYPE", key)["ok"]
+    local value = false
+    if ARGV[arg] == "fields" then
+        local count = tonumber(ARGV[arg + 1])
+        if typ == "hash" then
+            value = {}
+            for f = arg + 2, arg + 1 + count do
+                if redis.call("HEXISTS", key, ARGV[f]) == 0 then
+                    table.insert(value, ARGV[f])
+                end
+            end
+        end
+        arg = arg + 2 + count
+    else
+        local sample = tonumber(ARGV[arg + 1])
+        if typ == "string" then
+            value = redis.call("GET", key)
+        elseif typ == "hash" then
+            -- Small hashes are returned whole by a single scan, large ones roughly COUNT at a time:
+            value = redis.call("HSCAN", key, 0, "COUNT", sample)[2]
+        elseif typ == "list" then
+            value = redis.call("LRANGE", key, 0, sample - 1)
+        elseif typ == "set" then
+            value = redis.call("SRANDMEMBER", key, sample)
+        elseif typ == "zset" then
+            value = redis.call("ZRANGE", key, 0, sample - 1, "WITHSCORES")
+        end
+        arg = arg + 2
+    end
+    results[i * 2 - 1] = typ
+    results[i * 2] = value
+end
+return results
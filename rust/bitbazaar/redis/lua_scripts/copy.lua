@@ -0,0 +1,33 @@
+-- Copy KEYS[1] to KEYS[2], returning 1 if copied, 0 if KEYS[1] is missing or KEYS[2] exists without replacing.
+-- ARGV[1] is "1" to replace an existing KEYS[2].
+-- ARGV[2] is the ttl of the copy:
+-- - "source": the ttl of KEYS[1], the native COPY behaviour.
+-- - "keep": the ttl KEYS[2] had before, none if it didn't exist.
+-- - "fresh": ARGV[3] milliseconds.
+-- Requires redis 6.2+ for COPY.
+if KEYS[1] == KEYS[2] then
+    return 0
+end
+
+local dest_pttl = redis.call("PTTL", KEYS[2])
+
+local copied
+if ARGV[1] == "1" then
+    copied = redis.call("COPY", KEYS[1], KEYS[2], "REPLACE")
+else
+    copied = redis.call("COPY", KEYS[1], KEYS[2])
+end
+if copied == 0 then
+    return 0
+end
+
+if ARGV[2] == "keep" then
+    if dest_pttl > 0 then
+        redis.call("PEXPIRE", KEYS[2], dest_pttl)
+    else
+        redis.call("PERSIST", KEYS[2])
+    end
+elseif ARGV[2] == "fresh" then
+    redis.call("PEXPIRE", KEYS[2], ARGV[3])
+end
+return 1
@@ -0,0 +1,6 @@
+-- Delete KEYS[1] only if its current value is byte-equal to ARGV[1], returning 1 if deleted, 0 otherwise.
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    redis.call("DEL", KEYS[1])
+    return 1
+end
+return 0
@@ -0,0 +1,18 @@
+-- Increment KEYS[1] by ARGV[1], or the field ARGV[3] of the hash at KEYS[1] when given.
+-- ARGV[2] is the expiry in milliseconds to reset to, 0 for no expiry.
+-- Returns the new value, or nil if the existing value isn't an integer (rather than erroring the whole pipeline).
+local result
+if ARGV[3] then
+    result = redis.pcall("HINCRBY", KEYS[1], ARGV[3], ARGV[1])
+else
+    result = redis.pcall("INCRBY", KEYS[1], ARGV[1])
+end
+if type(result) == "table" and result.err then
+    return nil
+end
+
+local expiry = tonumber(ARGV[2])
+if expiry > 0 then
+    redis.call("PEXPIRE", KEYS[1], expiry)
+end
+return result
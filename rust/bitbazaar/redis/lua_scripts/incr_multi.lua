@@ -0,0 +1,13 @@
+-- Increments many counters at once, used to flush a RedisCounterBuffer in a single round trip.
+-- KEYS: the counters to increment.
+-- ARGV: the amount to increment each counter by, in the same order as KEYS.
+-- Returns the number of counters that couldn't be incremented, i.e. those holding a non-integer.
+local failed = 0
+for i, key in ipairs(KEYS) do
+    -- pcall so one bad key doesn't stop the rest being applied:
+    local result = redis.pcall("INCRBY", key, ARGV[i])
+    if type(result) == "table" and result.err then
+        failed = failed + 1
+    end
+end
+return failed
@@ -1,8 +1,7 @@
--- Expiry in milliseconds is passed as the first argument, therefore KEYS[i] = ARGV[i + 1]
-local expiry = tonumber(ARGV[1])
-
+-- Each key has its own expiry in milliseconds, passed before its value, therefore KEYS[i] = ARGV[2i], with expiry ARGV[2i - 1]
 for i = 1, #KEYS do
     local key = KEYS[i]
-    local value = ARGV[i + 1]
+    local expiry = tonumber(ARGV[i * 2 - 1])
+    local value = ARGV[i * 2]
     redis.call("SET", key, value, "PX", expiry)
 end
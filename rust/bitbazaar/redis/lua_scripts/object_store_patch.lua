@@ -0,0 +1,14 @@
+-- Set the field/value pairs in ARGV[2..] on the hash at KEYS[1], only if it already exists.
+-- ARGV[1] is the expiry in milliseconds to refresh to, 0 for no expiry.
+-- Returns 1 if patched, 0 if the hash doesn't exist.
+if redis.call("EXISTS", KEYS[1]) == 0 then
+    return 0
+end
+if #ARGV > 1 then
+    redis.call("HSET", KEYS[1], unpack(ARGV, 2))
+end
+local expiry = tonumber(ARGV[1])
+if expiry > 0 then
+    redis.call("PEXPIRE", KEYS[1], expiry)
+end
+return 1
@@ -0,0 +1,10 @@
+-- Replace the hash at KEYS[1] with the field/value pairs in ARGV[2..].
+-- ARGV[1] is the expiry in milliseconds, 0 for no expiry.
+redis.call("DEL", KEYS[1])
+if #ARGV > 1 then
+    redis.call("HSET", KEYS[1], unpack(ARGV, 2))
+    local expiry = tonumber(ARGV[1])
+    if expiry > 0 then
+        redis.call("PEXPIRE", KEYS[1], expiry)
+    end
+end
@@ -0,0 +1,12 @@
+-- Rename KEYS[1] to KEYS[2], keeping its value and ttl, returning 1 if renamed, 0 otherwise.
+-- ARGV[1] is "1" to replace an existing KEYS[2] (RENAME), otherwise it's left alone (RENAMENX).
+-- A missing KEYS[1] returns 0 rather than erroring like RENAME would.
+if redis.call("EXISTS", KEYS[1]) == 0 then
+    return 0
+end
+
+if ARGV[1] == "1" then
+    redis.call("RENAME", KEYS[1], KEYS[2])
+    return 1
+end
+return redis.call("RENAMENX", KEYS[1], KEYS[2])
@@ -0,0 +1,30 @@
+-- Writes a session's changed fields in one go, refreshing its ttl.
+-- KEYS[1]: the session hash.
+-- ARGV[1]: the ttl (ms).
+-- ARGV[2]: the number of fields being set.
+-- ARGV[3...]: the (field, value) pairs to set, followed by the fields to delete.
+local key = KEYS[1]
+local set_count = tonumber(ARGV[2])
+
+local set_args = {}
+for i = 3, 2 + set_count * 2 do
+    table.insert(set_args, ARGV[i])
+end
+if #set_args > 0 then
+    redis.call("HSET", key, unpack(set_args))
+end
+
+local del_args = {}
+for i = 3 + set_count * 2, #ARGV do
+    table.insert(del_args, ARGV[i])
+end
+if #del_args > 0 then
+    redis.call("HDEL", key, unpack(del_args))
+end
+
+-- Deleting every field removes the hash:
+if redis.call("EXISTS", key) == 1 then
+    redis.call("PEXPIRE", key, ARGV[1])
+end
+
+return 1
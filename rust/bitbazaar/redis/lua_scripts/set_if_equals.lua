@@ -0,0 +1,20 @@
+-- Set KEYS[1] to ARGV[2] only if its current value is byte-equal to ARGV[1], returning 1 if set, 0 otherwise.
+-- ARGV[3] is the expiry in milliseconds, 0 for no expiry.
+-- ARGV[4] is "1" if the set should also happen when the key doesn't exist.
+local current = redis.call("GET", KEYS[1])
+
+if current == false then
+    if ARGV[4] ~= "1" then
+        return 0
+    end
+elseif current ~= ARGV[1] then
+    return 0
+end
+
+local expiry = tonumber(ARGV[3])
+if expiry > 0 then
+    redis.call("SET", KEYS[1], ARGV[2], "PX", expiry)
+else
+    redis.call("SET", KEYS[1], ARGV[2])
+end
+return 1
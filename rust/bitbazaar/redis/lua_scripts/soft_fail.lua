@@ -0,0 +1,7 @@
+-- Runs a single command, returning nil rather than erroring, so its failure doesn't fail the rest of the batch.
+-- ARGV: the command name followed by its args.
+local result = redis.pcall(unpack(ARGV))
+if type(result) == "table" and result.err then
+    return nil
+end
+return result
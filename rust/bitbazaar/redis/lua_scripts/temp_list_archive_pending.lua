@@ -0,0 +1,41 @@
+-- Reads the items in a temp list expiring within the archive window that haven't been archived yet.
+-- NOTE: the value keys are derived from the uids here rather than passed in as KEYS,
+-- so this isn't cluster safe, but the uids can't be known ahead of the call.
+-- KEYS[1]: the list (sorted set).
+-- KEYS[2]: the archived set (sorted set), each uid scored with the expiry it was archived for.
+-- ARGV[1]: the current timestamp (ms), members scored before this have already expired.
+-- ARGV[2]: the end of the window (ms), members scored after this aren't expiring yet.
+-- ARGV[3]: the final namespace the item values are stored under.
+-- ARGV[4]: the final key of the hash holding the item values, empty when each value is its own key.
+-- Returns a flat array of (uid, score, value) triples, soonest to expire first.
+local list = KEYS[1]
+local archived = KEYS[2]
+local namespace = ARGV[3]
+local hash = ARGV[4]
+local out = {}
+
+-- Archived entries for items that have since expired are no longer needed:
+redis.call("ZREMRANGEBYSCORE", archived, "-inf", "(" .. ARGV[1])
+
+local members = redis.call("ZRANGEBYSCORE", list, ARGV[1], ARGV[2], "WITHSCORES")
+for i = 1, #members, 2 do
+    local uid = members[i]
+    local score = tonumber(members[i + 1])
+    -- An item updated since it was archived has a later expiry, so gets archived again:
+    local archived_score = redis.call("ZSCORE", archived, uid)
+    if not archived_score or tonumber(archived_score) < score then
+        local value
+        if hash ~= "" then
+            value = redis.call("HGET", hash, uid)
+        else
+            value = redis.call("GET", namespace .. ":" .. uid)
+        end
+        if value then
+            table.insert(out, uid)
+            table.insert(out, score)
+            table.insert(out, value)
+        end
+    end
+end
+
+return out
@@ -0,0 +1,17 @@
+-- Removes the expired members of a temp list stored with its values in a hash.
+-- Hash fields can't expire, so the values of the expired members are removed here too, otherwise they'd be orphaned.
+-- KEYS[1]: the list (sorted set).
+-- KEYS[2]: the hash holding the item values.
+-- ARGV[1]: the current timestamp (ms), members scored at or before this have expired.
+-- Returns the number of members removed.
+local list = KEYS[1]
+local hash = KEYS[2]
+
+local expired = redis.call("ZRANGEBYSCORE", list, "-inf", ARGV[1])
+-- In chunks, unpack() is limited by the lua stack size:
+for i = 1, #expired, 1000 do
+    redis.call("HDEL", hash, unpack(expired, i, math.min(i + 999, #expired)))
+end
+redis.call("ZREMRANGEBYSCORE", list, "-inf", ARGV[1])
+
+return #expired
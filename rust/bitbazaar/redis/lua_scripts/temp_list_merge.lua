@@ -0,0 +1,65 @@
+-- Copies items into a temp list, preserving their scores and remaining value ttls.
+-- Either list can store its values in a hash, where values have no ttl of their own, it's emulated by the score.
+-- KEYS[1]: the destination list (sorted set).
+-- KEYS[2..]: pairs of (source value key, destination value key), unused for lists storing values in a hash.
+-- ARGV[1]: the ttl (ms) to refresh the destination list with.
+-- ARGV[2]: the current timestamp (ms).
+-- ARGV[3]: the final key of the source's value hash, empty when each value is its own key.
+-- ARGV[4]: the final key of the destination's value hash, empty when each value is its own key.
+-- ARGV[5..]: triples of (source uid, destination uid, score), one triple per key pair.
+-- Returns the destination uids merged, the number missing and the zero based indices of any collisions.
+local dest_list = KEYS[1]
+local now = tonumber(ARGV[2])
+local source_hash = ARGV[3]
+local dest_hash = ARGV[4]
+local merged = {}
+local missing = 0
+local collided = {}
+
+for i = 2, #KEYS, 2 do
+    local pair_index = (i - 2) / 2
+    local source_uid = ARGV[5 + pair_index * 3]
+    local uid = ARGV[6 + pair_index * 3]
+    local score = ARGV[7 + pair_index * 3]
+
+    if redis.call("ZSCORE", dest_list, uid) then
+        -- Return the zero based index so the caller can retry with a new uid:
+        table.insert(collided, pair_index)
+    else
+        local value
+        local ttl
+        if source_hash ~= "" then
+            value = redis.call("HGET", source_hash, source_uid)
+            ttl = tonumber(score) - now
+            if ttl <= 0 then
+                value = false
+            end
+        else
+            value = redis.call("GET", KEYS[i])
+            ttl = redis.call("PTTL", KEYS[i])
+        end
+        if value then
+            if dest_hash ~= "" then
+                redis.call("HSET", dest_hash, uid, value)
+            elseif ttl > 0 then
+                redis.call("SET", KEYS[i + 1], value, "PX", ttl)
+            else
+                redis.call("SET", KEYS[i + 1], value)
+            end
+            redis.call("ZADD", dest_list, score, uid)
+            table.insert(merged, uid)
+        else
+            -- Value already expired, but the list hadn't been cleaned yet:
+            missing = missing + 1
+        end
+    end
+end
+
+if #merged > 0 then
+    redis.call("PEXPIRE", dest_list, ARGV[1])
+    if dest_hash ~= "" then
+        redis.call("PEXPIRE", dest_hash, ARGV[1])
+    end
+end
+
+return {merged, missing, collided}
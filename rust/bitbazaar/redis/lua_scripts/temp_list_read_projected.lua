@@ -0,0 +1,76 @@
+-- Reads the newest items in a temp list, returning only the requested top-level fields of each value.
+-- NOTE: the value keys are derived from the uids here rather than passed in as KEYS,
+-- so this isn't cluster safe, but means fat values never leave redis.
+-- KEYS[1]: the list (sorted set).
+-- ARGV[1]: the current timestamp (ms), members scored before this have expired.
+-- ARGV[2]: the max number of items to return.
+-- ARGV[3]: the ttl (ms) to refresh the list with.
+-- ARGV[4]: the final namespace the item values are stored under.
+-- ARGV[5]: the final key of the hash holding the item values, empty when each value is its own key.
+-- ARGV[6...]: the fields to keep.
+-- Returns a flat array of (uid, projected json) pairs, newest to oldest.
+-- The projected json is false when the value couldn't be decoded or re-encoded by cjson.
+local list = KEYS[1]
+local namespace = ARGV[4]
+local hash = ARGV[5]
+local fields = {}
+for i = 6, #ARGV do
+    table.insert(fields, ARGV[i])
+end
+local out = {}
+
+-- Cleanup old members that have now expired, along with their values when stored in a hash (hash fields can't expire):
+if hash ~= "" then
+    local expired = redis.call("ZRANGEBYSCORE", list, "-inf", ARGV[1])
+    for i = 1, #expired, 1000 do
+        redis.call("HDEL", hash, unpack(expired, i, math.min(i + 999, #expired)))
+    end
+end
+redis.call("ZREMRANGEBYSCORE", list, "-inf", ARGV[1])
+
+local uids = redis.call("ZREVRANGEBYSCORE", list, "+inf", "-inf", "LIMIT", 0, ARGV[2])
+if #uids > 0 then
+    local values
+    if hash ~= "" then
+        values = redis.call("HMGET", hash, unpack(uids))
+    else
+        local value_keys = {}
+        for i, uid in ipairs(uids) do
+            value_keys[i] = namespace .. ":" .. uid
+        end
+        values = redis.call("MGET", unpack(value_keys))
+    end
+
+    for i, uid in ipairs(uids) do
+        local value = values[i]
+        -- Matching read_multi(), items whose values have expired are skipped:
+        if value then
+            local projected = false
+            local ok, decoded = pcall(cjson.decode, value)
+            if ok then
+                local slim = {}
+                -- Non-object values have none of the fields, so project to an empty object:
+                if type(decoded) == "table" then
+                    for _, field in ipairs(fields) do
+                        if decoded[field] ~= nil then
+                            slim[field] = decoded[field]
+                        end
+                    end
+                end
+                local encoded_ok, encoded = pcall(cjson.encode, slim)
+                if encoded_ok then
+                    projected = encoded
+                end
+            end
+            table.insert(out, uid)
+            table.insert(out, projected)
+        end
+    end
+end
+
+redis.call("PEXPIRE", list, ARGV[3])
+if hash ~= "" then
+    redis.call("PEXPIRE", hash, ARGV[3])
+end
+
+return out
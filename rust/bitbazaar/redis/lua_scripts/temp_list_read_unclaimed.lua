@@ -0,0 +1,67 @@
+-- Reads the newest items in a temp list that aren't currently claimed.
+-- NOTE: the value and claim keys are derived from the uids here rather than passed in as KEYS,
+-- so this isn't cluster safe, but saves workers fetching items they can't claim.
+-- KEYS[1]: the list (sorted set).
+-- ARGV[1]: the current timestamp (ms), members scored before this have expired.
+-- ARGV[2]: the max number of items to return.
+-- ARGV[3]: the ttl (ms) to refresh the list with.
+-- ARGV[4]: the final namespace the item values and claims are stored under.
+-- ARGV[5]: the suffix appended to a uid to form its claim key.
+-- ARGV[6]: the final key of the hash holding the item values, empty when each value is its own key.
+-- Returns a flat array of (uid, value) pairs, newest to oldest.
+local list = KEYS[1]
+local limit = tonumber(ARGV[2])
+local namespace = ARGV[4]
+local claim_suffix = ARGV[5]
+local hash = ARGV[6]
+local out = {}
+
+-- Cleanup old members that have now expired, along with their values when stored in a hash (hash fields can't expire):
+if hash ~= "" then
+    local expired = redis.call("ZRANGEBYSCORE", list, "-inf", ARGV[1])
+    for i = 1, #expired, 1000 do
+        redis.call("HDEL", hash, unpack(expired, i, math.min(i + 999, #expired)))
+    end
+end
+redis.call("ZREMRANGEBYSCORE", list, "-inf", ARGV[1])
+
+-- Paging through newest to oldest, so heavily claimed lists don't get fetched in full each time:
+local page_size = math.max(limit, 10)
+local offset = 0
+local found = 0
+while found < limit do
+    local uids = redis.call("ZREVRANGEBYSCORE", list, "+inf", "-inf", "LIMIT", offset, page_size)
+    if #uids == 0 then
+        break
+    end
+
+    local claim_keys = {}
+    for i, uid in ipairs(uids) do
+        claim_keys[i] = namespace .. ":" .. uid .. claim_suffix
+    end
+    local claims = redis.call("MGET", unpack(claim_keys))
+
+    for i, uid in ipairs(uids) do
+        if found < limit and not claims[i] then
+            local value
+            if hash ~= "" then
+                value = redis.call("HGET", hash, uid)
+            else
+                value = redis.call("GET", namespace .. ":" .. uid)
+            end
+            if value then
+                table.insert(out, uid)
+                table.insert(out, value)
+                found = found + 1
+            end
+        end
+    end
+    offset = offset + #uids
+end
+
+redis.call("PEXPIRE", list, ARGV[3])
+if hash ~= "" then
+    redis.call("PEXPIRE", hash, ARGV[3])
+end
+
+return out
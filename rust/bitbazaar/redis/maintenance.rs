@@ -0,0 +1,311 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+
+use super::{
+    Redis, RedisBatchFire, RedisBatchReturningOps, RedisChannelListener, RedisConn, RedisJson,
+};
+use crate::errors::prelude::*;
+
+/// The key the flag is stored at, and the channel changes are announced on, under the configured namespace.
+const MAINTENANCE_KEY: &str = "maintenance";
+
+/// The maintenance mode services should be operating in, see [`MaintenanceFlag`].
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum MaintenanceMode {
+    /// Operating normally.
+    #[default]
+    Off,
+    /// Reject writes, keep serving reads.
+    ReadOnly,
+    /// Reject everything.
+    Full,
+}
+
+/// Extra configuration for [`MaintenanceFlag::new_with_opts`].
+#[derive(Debug, Clone)]
+pub struct MaintenanceFlagOpts {
+    /// How often the flag is re-read from redis, so changes converge even if their pubsub announcement was missed.
+    ///
+    /// Defaults to 10 seconds.
+    pub poll_every: Duration,
+    /// The mode reported while redis is unreachable (or disabled).
+    ///
+    /// Defaults to [`MaintenanceMode::Off`], i.e. fail-open: a redis outage doesn't take every service down with it.
+    /// Set to [`MaintenanceMode::ReadOnly`] or [`MaintenanceMode::Full`] to fail closed instead.
+    pub unreachable_mode: MaintenanceMode,
+}
+
+impl Default for MaintenanceFlagOpts {
+    fn default() -> Self {
+        Self {
+            poll_every: Duration::from_secs(10),
+            unreachable_mode: MaintenanceMode::Off,
+        }
+    }
+}
+
+/// The flag as stored in redis and announced over pubsub.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct MaintenanceState {
+    mode: MaintenanceMode,
+    reason: String,
+    since_ms: i64,
+    /// Also set as the key's ttl, kept here so cached copies revert without waiting for a poll.
+    expires_at_ms: Option<i64>,
+}
+
+impl MaintenanceState {
+    fn is_expired(&self) -> bool {
+        self.expires_at_ms
+            .is_some_and(|at| at <= chrono::Utc::now().timestamp_millis())
+    }
+}
+
+struct Cached {
+    /// The last state seen, None if unset.
+    state: Option<MaintenanceState>,
+    /// False if the last poll failed, reset by a successful poll or an incoming announcement.
+    reachable: bool,
+}
+
+struct Shared {
+    redis: Redis,
+    namespace: &'static str,
+    opts: MaintenanceFlagOpts,
+    cached: RwLock<Cached>,
+    polls: AtomicUsize,
+}
+
+impl Shared {
+    async fn poll(&self) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        let result = self
+            .redis
+            .conn()
+            .batch()
+            .get::<RedisJson<MaintenanceState>>(self.namespace, MAINTENANCE_KEY)
+            .fire()
+            .await;
+        let mut cached = self.cached.write();
+        match result {
+            Some(state) => {
+                cached.state = state.map(|state| state.0);
+                cached.reachable = true;
+            }
+            None => {
+                if cached.reachable {
+                    tracing::warn!(
+                        "Maintenance flag '{}' couldn't be read from redis, reporting {:?} until it can.",
+                        self.namespace,
+                        self.opts.unreachable_mode
+                    );
+                }
+                cached.reachable = false;
+            }
+        }
+    }
+
+    fn announced(&self, state: MaintenanceState) {
+        let mut cached = self.cached.write();
+        cached.state = Some(state);
+        cached.reachable = true;
+    }
+
+    /// The active state, None when unset, expired or redis is unreachable.
+    fn active<R>(&self, f: impl FnOnce(&MaintenanceState) -> R) -> Option<R> {
+        let cached = self.cached.read();
+        if !cached.reachable {
+            return None;
+        }
+        cached
+            .state
+            .as_ref()
+            .filter(|state| !state.is_expired())
+            .map(f)
+    }
+}
+
+/// A single cluster-wide maintenance switch, e.g. "reject writes, serve reads" during a migration.
+///
+/// [`MaintenanceFlag::current`] is served from a local copy and never touches redis, so it's cheap enough to check on every request.
+/// The copy is updated immediately by the pubsub announcement of each [`MaintenanceFlag::set`],
+/// and re-read from redis every [`MaintenanceFlagOpts::poll_every`] in case an announcement was missed.
+///
+/// While redis is unreachable [`MaintenanceFlagOpts::unreachable_mode`] is reported, fail-open ([`MaintenanceMode::Off`]) by default.
+///
+/// Expiry uses the clock of the process that set the flag, so skewed clocks shift the local revert by the skew, redis' own ttl still applies on the next poll.
+///
+/// The background refresh task is stopped when the flag is dropped.
+pub struct MaintenanceFlag {
+    shared: Arc<Shared>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MaintenanceFlag {
+    /// Create a new maintenance flag with the default [`MaintenanceFlagOpts`], fail-open and polling every 10 seconds.
+    ///
+    /// Returns once the flag has been read from redis (or failed to be), so [`MaintenanceFlag::current`] is accurate straight away.
+    /// Must be called from within a tokio runtime.
+    ///
+    /// Arguments:
+    /// - `redis`: The redis wrapper the flag is stored in.
+    /// - `namespace`: The redis namespace the flag is stored under, every service sharing the switch should use the same one.
+    pub async fn new(redis: Redis, namespace: &'static str) -> Self {
+        Self::new_with_opts(redis, namespace, MaintenanceFlagOpts::default()).await
+    }
+
+    /// Same as [`MaintenanceFlag::new`], with extra configuration.
+    pub async fn new_with_opts(
+        redis: Redis,
+        namespace: &'static str,
+        opts: MaintenanceFlagOpts,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            redis,
+            namespace,
+            opts,
+            cached: RwLock::new(Cached {
+                state: None,
+                reachable: false,
+            }),
+            polls: AtomicUsize::new(0),
+        });
+        // Subscribe before the first read so changes in between aren't missed:
+        let listener = shared
+            .redis
+            .subscribe::<MaintenanceState>(namespace, MAINTENANCE_KEY)
+            .await;
+        shared.poll().await;
+        let task = tokio::spawn(refresh_loop(shared.clone(), listener));
+        Self { shared, task }
+    }
+
+    /// The current maintenance mode, from the local copy.
+    ///
+    /// [`MaintenanceMode::Off`] if unset or expired, [`MaintenanceFlagOpts::unreachable_mode`] if redis is unreachable.
+    pub fn current(&self) -> MaintenanceMode {
+        if !self.shared.cached.read().reachable {
+            return self.shared.opts.unreachable_mode;
+        }
+        self.shared
+            .active(|state| state.mode)
+            .unwrap_or(MaintenanceMode::Off)
+    }
+
+    /// When the current mode was set, None if unset, expired or redis is unreachable.
+    pub fn since(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.shared
+            .active(|state| state.since_ms)
+            .and_then(chrono::DateTime::from_timestamp_millis)
+    }
+
+    /// The reason given when the current mode was set, None if unset, expired or redis is unreachable.
+    pub fn reason(&self) -> Option<String> {
+        self.shared.active(|state| state.reason.clone())
+    }
+
+    /// Set the mode for every service, announcing it over pubsub. This is immediately reflected locally.
+    ///
+    /// Arguments:
+    /// - `conn`: The connection to write with.
+    /// - `mode`: The new mode, [`MaintenanceMode::Off`] to end maintenance.
+    /// - `reason`: A human readable reason, available from [`MaintenanceFlag::reason`].
+    /// - `ttl`: Automatically revert to [`MaintenanceMode::Off`] after this long, None to keep the mode until changed.
+    pub async fn set(
+        &self,
+        conn: &mut RedisConn<'_>,
+        mode: MaintenanceMode,
+        reason: &str,
+        ttl: Option<chrono::TimeDelta>,
+    ) -> RResult<(), AnyErr> {
+        let ttl = ttl
+            .map(|ttl| match ttl.to_std() {
+                Ok(ttl) if !ttl.is_zero() => Ok(ttl),
+                _ => Err(anyerr!("Maintenance ttl must be positive, got '{}'.", ttl)),
+            })
+            .transpose()?;
+        let now = chrono::Utc::now();
+        let state = MaintenanceState {
+            mode,
+            reason: reason.to_string(),
+            since_ms: now.timestamp_millis(),
+            expires_at_ms: ttl.map(|ttl| now.timestamp_millis() + ttl.as_millis() as i64),
+        };
+        let announcement = serde_json::to_vec(&state).change_context(AnyErr)?;
+        conn.batch()
+            .set(
+                self.shared.namespace,
+                MAINTENANCE_KEY,
+                RedisJson(state.clone()),
+                ttl,
+            )
+            .publish(self.shared.namespace, MAINTENANCE_KEY, announcement)
+            .fire()
+            .await
+            .ok_or_else(|| {
+                anyerr!(
+                    "Redis unavailable, couldn't set maintenance mode to {:?}.",
+                    mode
+                )
+            })?;
+        self.shared.announced(state);
+        Ok(())
+    }
+
+    /// Force a re-read of the flag from redis, rather than waiting for the next poll.
+    pub async fn refresh(&self) {
+        self.shared.poll().await;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn poll_count(&self) -> usize {
+        self.shared.polls.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MaintenanceFlag {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Apply announcements as they arrive, polling every `poll_every` regardless, resubscribing if the subscription was lost.
+async fn refresh_loop(
+    shared: Arc<Shared>,
+    mut listener: Option<RedisChannelListener<MaintenanceState>>,
+) {
+    let mut next_poll = tokio::time::Instant::now() + shared.opts.poll_every;
+    loop {
+        if let Some(active) = listener.as_mut() {
+            match tokio::time::timeout_at(next_poll, active.recv()).await {
+                Ok(Some(state)) => {
+                    shared.announced(state);
+                    continue;
+                }
+                Ok(None) => {
+                    listener = None;
+                    continue;
+                }
+                // Time to poll:
+                Err(_) => {}
+            }
+        } else {
+            tokio::time::sleep_until(next_poll).await;
+            listener = shared
+                .redis
+                .subscribe::<MaintenanceState>(shared.namespace, MAINTENANCE_KEY)
+                .await;
+        }
+        shared.poll().await;
+        next_poll = tokio::time::Instant::now() + shared.opts.poll_every;
+    }
+}
@@ -1,27 +1,64 @@
 mod batch;
+mod batch_plan;
 mod conn;
+mod contract;
+mod counter_buffer;
 mod dlock;
 mod json;
+mod json_stream;
+mod maintenance;
+mod object_store;
+mod pubsub_bridge;
 mod script;
+#[cfg(feature = "cookies_ssr")]
+mod session;
+mod shard;
+mod slow_log;
 mod temp_list;
+mod topic;
+mod ttl_jitter;
 mod wrapper;
 
 mod standalone;
 
 pub use standalone::*;
 
-pub use batch::{RedisBatch, RedisBatchFire, RedisBatchReturningOps};
-pub use conn::RedisConn;
-pub use dlock::{RedisLock, RedisLockErr};
-pub use json::{RedisJson, RedisJsonBorrowed};
+pub use batch::{
+    BorrowedBatchResult, CopyOpts, CopyTtl, RedisBatch, RedisBatchFire, RedisBatchReturningOps,
+};
+pub use batch_plan::{BatchPlan, BatchPlanCommand};
+pub use conn::{CacheOpts, NamespaceUsage, RedisConn, TwoPhaseRead};
+pub use contract::{
+    ContractFailure, ContractReport, RedisContract, RedisContractBuilder, CONTRACT_DECODE_SAMPLE,
+};
+pub use counter_buffer::{RedisCounterBuffer, RedisCounterBufferStats};
+pub use dlock::{HandoffToken, RedisLock, RedisLockErr, RedisLockGuard};
+pub use json::{RedisFuzzy, RedisJson, RedisJsonBorrowed, RedisJsonTagged, RedisSchema};
+pub use maintenance::{MaintenanceFlag, MaintenanceFlagOpts, MaintenanceMode};
+pub use object_store::RedisObjectStore;
+pub use pubsub_bridge::{
+    PollResult, RedisPubSubBridge, DEFAULT_BRIDGE_POLL_LINGER, DEFAULT_BRIDGE_RING_SIZE,
+};
 // Re-exporting redis to be used outside: (this must also be in scope for the derive macros to work)
 pub use redis;
 // Re-exporting the json derive utilities to allow redis to take arbitrary json types without the need for the wrapper.
 // Both this and the custom wrapper are exported as latter works better for e.g. the temp list.
 pub use redis_macros::{FromRedisValue, ToRedisArgs};
 pub use script::{RedisScript, RedisScriptInvoker};
-pub use temp_list::{RedisTempList, RedisTempListItem, RedisTempListItemWithConn};
-pub use wrapper::Redis;
+#[cfg(feature = "cookies_ssr")]
+pub use session::{RedisSession, RedisSessionLayer, RequestCookies, SessionCookies, SessionOpts};
+pub use shard::{RedisShardInfo, RedisShardSet};
+pub use slow_log::SlowBatchEntry;
+pub use temp_list::{
+    ItemClaim, MergeReport, RedisTempList, RedisTempListItem, RedisTempListItemWithConn,
+    RedisTempListTyped, TempListChange, TempListStorage,
+};
+pub use topic::{
+    list_topics, EnvelopedMsg, LocalSubscription, PubSubDiagnostics, RedisChannelListener,
+    RedisEnvelopedListener, RedisTopic, RedisTopicInfo, ServerChannel, ServerPubSub,
+};
+pub use ttl_jitter::TtlJitter;
+pub use wrapper::{Redis, RedisDisabledLocks, RedisInstanceInfo};
 
 #[cfg(test)]
 mod tests {
@@ -34,7 +71,7 @@ mod tests {
 
     use super::*;
     use crate::{
-        errors::prelude::*,
+        errors::{prelude::*, test_helpers::*},
         log::GlobalLog,
         redis::{dlock::redis_dlock_tests, temp_list::redis_temp_list_tests},
     };
@@ -46,6 +83,16 @@ mod tests {
         ree: String,
     }
 
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserUpdated {
+        id: u64,
+    }
+
+    crate::redis_topics! {
+        UserUpdatedTopic => ("events", "user_updated", UserUpdated),
+        OrderPlacedTopic => ("events", "order_placed", String),
+    }
+
     struct AddScript {
         script: RedisScript,
     }
@@ -268,6 +315,94 @@ mod tests {
             );
         }
 
+        // <--- Tagged json:
+        {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct UserV1 {
+                name: String,
+            }
+            impl RedisSchema for UserV1 {
+                const SCHEMA_VERSION: &'static str = "user_v1";
+            }
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct UserV2 {
+                name: String,
+            }
+            impl RedisSchema for UserV2 {
+                const SCHEMA_VERSION: &'static str = "user_v2";
+            }
+
+            let user = UserV1 {
+                name: "bob".to_string(),
+            };
+            work_conn
+                .batch()
+                .set("tagged", "v1", RedisJsonTagged(user.clone()), None)
+                .set("tagged", "legacy", RedisJson(user.clone()), None)
+                .fire()
+                .await;
+
+            // Matching round-trip, through both the conn helpers and the batch:
+            assert_eq!(
+                work_conn.get_tagged::<UserV1>("tagged", "v1").await,
+                Some(user.clone())
+            );
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<RedisJsonTagged<UserV1>>("tagged", "v1")
+                    .fire()
+                    .await
+                    .flatten()
+                    .map(|tagged| tagged.0),
+                Some(user.clone())
+            );
+            // A reader on a different version gets None (recording an exception) without affecting other keys:
+            assert_eq!(
+                work_conn
+                    .mget_tagged::<UserV2>("tagged", ["v1", "legacy", "missing"])
+                    .await,
+                vec![
+                    None,
+                    Some(UserV2 {
+                        name: "bob".to_string()
+                    }),
+                    None
+                ]
+            );
+            // Untagged legacy values are still readable during migration:
+            assert_eq!(
+                work_conn.get_tagged::<UserV1>("tagged", "legacy").await,
+                Some(user.clone())
+            );
+            assert_eq!(fail_conn.get_tagged::<UserV1>("tagged", "v1").await, None);
+
+            // Usable as a temp list item:
+            let li = work_r.templist(
+                "tagged",
+                "list",
+                Duration::from_millis(500),
+                Duration::from_millis(500),
+            );
+            li.push(&mut work_conn, RedisJsonTagged(user.clone())).await;
+            let items = li
+                .read_multi::<RedisJsonTagged<UserV1>>(&mut work_conn, None)
+                .await;
+            assert_eq!(
+                RedisTempListItem::vec_items(items)
+                    .into_iter()
+                    .map(|tagged| tagged.0)
+                    .collect::<Vec<_>>(),
+                vec![user.clone()]
+            );
+            assert_eq!(
+                li.read_multi::<RedisJsonTagged<UserV2>>(&mut work_conn, None)
+                    .await
+                    .len(),
+                0
+            );
+        }
+
         // <--- Cached function:
         for (conn, expected_call_times) in [
             (&mut work_conn, 1),
@@ -324,6 +459,125 @@ mod tests {
         // The 3rd/4th call will have needed a reload due to the expiry, so should've been called twice:
         assert_eq!(called.load(std::sync::atomic::Ordering::SeqCst), 2);
 
+        // <--- Cached function with compute lock (stampede protection):
+        // Separate wrappers (with their own pools) to mimic separate processes, two contenders each, only one should end up computing:
+        let contenders = (0..4)
+            .map(|_| Redis::new(format!("redis://localhost:{}", rs.port), work_r.prefix()))
+            .collect::<RResult<Vec<_>, _>>()?;
+        let called = Arc::new(AtomicU8::new(0));
+        let opts = CacheOpts {
+            compute_lock: Some(chrono::TimeDelta::seconds(2)),
+            ..Default::default()
+        };
+        let compute = || async {
+            called.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(ExampleJson {
+                ree: "locked".to_string(),
+            })
+        };
+        let results =
+            futures::future::join_all(contenders.iter().chain(contenders.iter()).map(|r| {
+                let opts = opts.clone();
+                async move {
+                    r.conn()
+                        .cached_fn_with_opts("my_fn_lock_group", "foo", None, opts, compute)
+                        .await
+                }
+            }))
+            .await
+            .into_iter()
+            .collect::<RResult<Vec<_>, _>>()?;
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|result| result == &results[0]));
+        assert_eq!(called.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // A winner whose lock expired mid-compute mustn't release the lock someone else has since taken:
+        let (mut slow_conn, mut other_conn) = (work_r.conn(), contenders[0].conn());
+        let (value, _) = futures::join!(
+            slow_conn.cached_fn_with_opts(
+                "my_fn_lock_group",
+                "slow",
+                None,
+                CacheOpts {
+                    compute_lock: Some(chrono::TimeDelta::milliseconds(30)),
+                    ..Default::default()
+                },
+                || async {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Ok("slow".to_string())
+                }
+            ),
+            async {
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                other_conn
+                    .batch()
+                    .set(
+                        "my_fn_lock_group",
+                        "slow__compute_lock",
+                        "other",
+                        Some(Duration::from_secs(5)),
+                    )
+                    .fire()
+                    .await
+            }
+        );
+        assert_eq!(value?, "slow");
+        assert_eq!(
+            work_conn
+                .batch()
+                .get::<String>("my_fn_lock_group", "slow__compute_lock")
+                .fire()
+                .await,
+            Some(Some("other".to_string()))
+        );
+
+        // A crashed winner (lock held but value never written) should fall back to computing once the lock ttl passes:
+        work_conn
+            .batch()
+            .set(
+                "my_fn_lock_group",
+                "crashed__compute_lock",
+                1,
+                Some(Duration::from_millis(100)),
+            )
+            .fire()
+            .await;
+        let called = Arc::new(AtomicU8::new(0));
+        let started = std::time::Instant::now();
+        assert_eq!(
+            work_conn
+                .cached_fn_with_opts(
+                    "my_fn_lock_group",
+                    "crashed",
+                    None,
+                    CacheOpts {
+                        compute_lock: Some(chrono::TimeDelta::milliseconds(100)),
+                        ..Default::default()
+                    },
+                    || async {
+                        called.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        Ok("fallback".to_string())
+                    }
+                )
+                .await?,
+            "fallback"
+        );
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(called.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Redis down should just degrade to computing every time:
+        let called = Arc::new(AtomicU8::new(0));
+        for _ in 0..3 {
+            fail_conn
+                .cached_fn_with_opts("my_fn_lock_group", "foo", None, opts.clone(), || async {
+                    called.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    Ok("down".to_string())
+                })
+                .await?;
+        }
+        assert_eq!(called.load(std::sync::atomic::Ordering::SeqCst), 3);
+
         // <--- set/mset with expiry:
         work_conn
             .batch()
@@ -377,6 +631,303 @@ mod tests {
             Some((None, None, vec![None, None, None, None]))
         );
 
+        // <--- Batch plan:
+        {
+            static GET_SCRIPT: once_cell::sync::Lazy<RedisScript> =
+                once_cell::sync::Lazy::new(|| {
+                    RedisScript::new("return redis.call('GET', KEYS[1])")
+                });
+            let key = |namespace: &str, key: &str| work_conn.final_key(namespace, key.into());
+            let (p_a, p_b, p_set, p_chan) = (
+                key("plan", "a"),
+                key("plan", "b"),
+                key("plan_set", "s"),
+                key("plan", "chan"),
+            );
+            let batch = work_conn
+                .batch()
+                .mset(
+                    "plan",
+                    [("a", "1"), ("b", "2")],
+                    Some(Duration::from_secs(60)),
+                )
+                .get::<String>("plan", "a")
+                .zadd("plan_set", "s", None, 1, "member")
+                .script::<String>(GET_SCRIPT.invoker().key(&p_b).arg("unused"))
+                .publish("plan", "chan", "msg");
+            let plan = batch.explain();
+            assert_eq!(
+                plan.commands
+                    .iter()
+                    .map(|cmd| (cmd.name.as_str(), cmd.keys.clone(), cmd.returns))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("EVALSHA", vec![p_a.clone(), p_b.clone()], false),
+                    ("GET", vec![p_a.clone()], true),
+                    ("ZADD", vec![p_set.clone()], false),
+                    ("EVALSHA", vec![p_b.clone()], true),
+                    ("PUBLISH", vec![p_chan.clone()], false),
+                ]
+            );
+            assert!(plan
+                .commands
+                .iter()
+                .all(|cmd| cmd.keys.iter().all(|key| key.starts_with(work_r.prefix()))));
+            assert_eq!(
+                plan.commands[3].script_hash.as_deref(),
+                Some(GET_SCRIPT.sha())
+            );
+            assert_eq!(plan.commands[1].script_hash, None);
+            // GET_SCRIPT <hash> <numkeys> <key> <arg>:
+            assert_eq!(plan.commands[3].arg_count, 4);
+            assert_eq!(
+                plan.to_string().lines().nth(1),
+                Some(format!("GET [{}] (1 args)", p_a).as_str())
+            );
+            assert_eq!(
+                serde_json::from_str::<BatchPlan>(&serde_json::to_string(&plan).unwrap()).unwrap(),
+                plan
+            );
+
+            // Explaining doesn't affect firing:
+            assert_eq!(batch.explain(), plan);
+            assert_eq!(
+                batch.fire().await,
+                Some((Some("1".to_string()), "2".to_string()))
+            );
+
+            // No server needed, e.g. for asserting namespacing:
+            let fail_plan = fail_conn.batch().get::<String>("plan", "a").explain();
+            assert_eq!(
+                fail_plan.commands[0].keys,
+                vec![fail_conn.final_key("plan", "a".into())]
+            );
+        }
+
+        // <--- Rename/copy:
+        {
+            let minute = Duration::from_secs(60);
+
+            // Rename keeps the ttl:
+            work_conn
+                .batch()
+                .set("rn", "staging", "v1", Some(minute))
+                .fire()
+                .await;
+            let (renamed, old, new, pttl) = work_conn
+                .batch()
+                .rename("rn", "staging", "live", true)
+                .get::<String>("rn", "staging")
+                .get::<String>("rn", "live")
+                .pttl("rn", "live")
+                .fire()
+                .await
+                .unwrap();
+            assert!(renamed);
+            assert_eq!((old, new), (None, Some("v1".to_string())));
+            assert!(pttl > 50_000 && pttl <= 60_000, "{}", pttl);
+
+            // Without overwrite, an existing destination is left alone:
+            work_conn
+                .batch()
+                .set("rn", "staging", "v2", None)
+                .fire()
+                .await;
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .rename("rn", "staging", "live", false)
+                    .get::<String>("rn", "staging")
+                    .get::<String>("rn", "live")
+                    .fire()
+                    .await,
+                Some((false, Some("v2".to_string()), Some("v1".to_string())))
+            );
+            // With overwrite, it's replaced:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .rename("rn", "staging", "live", true)
+                    .get::<String>("rn", "live")
+                    .pttl("rn", "live")
+                    .fire()
+                    .await,
+                Some((true, Some("v2".to_string()), -1))
+            );
+
+            // Copy with a fresh ttl, the source is untouched:
+            let (copied, pttl, source_pttl) = work_conn
+                .batch()
+                .copy(
+                    "rn",
+                    "live",
+                    "snapshot",
+                    CopyOpts {
+                        ttl: CopyTtl::Fresh(Duration::from_millis(500)),
+                        ..Default::default()
+                    },
+                )
+                .pttl("rn", "snapshot")
+                .pttl("rn", "live")
+                .fire()
+                .await
+                .unwrap();
+            assert!(copied);
+            assert!(pttl > 0 && pttl <= 500, "{}", pttl);
+            assert_eq!(source_pttl, -1);
+
+            // Existing destinations need overwrite, KeepTtl keeps the destination's ttl:
+            work_conn
+                .batch()
+                .set("rn", "src", "new", None)
+                .set("rn", "dest", "old", Some(minute))
+                .fire()
+                .await;
+            let (refused, replaced, value, pttl) = work_conn
+                .batch()
+                .copy("rn", "src", "dest", CopyOpts::default())
+                .copy(
+                    "rn",
+                    "src",
+                    "dest",
+                    CopyOpts {
+                        overwrite: true,
+                        ttl: CopyTtl::KeepTtl,
+                    },
+                )
+                .get::<String>("rn", "dest")
+                .pttl("rn", "dest")
+                .fire()
+                .await
+                .unwrap();
+            assert!(!refused);
+            assert!(replaced);
+            assert_eq!(value, Some("new".to_string()));
+            assert!(pttl > 50_000 && pttl <= 60_000, "{}", pttl);
+
+            // Across namespaces, keeping the source's ttl:
+            let (copied, value, pttl, moved, moved_value) = work_conn
+                .batch()
+                .copy_across("rn", "dest", "rn_other", "dest", CopyOpts::default())
+                .get::<String>("rn_other", "dest")
+                .pttl("rn_other", "dest")
+                .rename_across("rn", "src", "rn_other", "src", false)
+                .get::<String>("rn_other", "src")
+                .fire()
+                .await
+                .unwrap();
+            assert!(copied && moved);
+            assert_eq!(value, Some("new".to_string()));
+            assert!(pttl > 50_000 && pttl <= 60_000, "{}", pttl);
+            assert_eq!(moved_value, Some("new".to_string()));
+
+            // Missing sources are just false, not failing the rest of the batch:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .set("rn", "after", "ok", None)
+                    .rename("rn", "missing", "live", true)
+                    .copy("rn", "missing", "live", CopyOpts::default())
+                    .get::<String>("rn", "after")
+                    .get::<String>("rn", "live")
+                    .fire()
+                    .await,
+                Some((false, false, Some("ok".to_string()), Some("v2".to_string())))
+            );
+
+            // Redis down:
+            assert_eq!(
+                fail_conn
+                    .batch()
+                    .rename("rn", "live", "other", true)
+                    .fire()
+                    .await,
+                None
+            );
+        }
+
+        // <--- Ttl jitter:
+        {
+            async fn pttls(conn: &mut RedisConn<'_>, namespace: &str, keys: &[String]) -> Vec<i64> {
+                let mut pttls = vec![];
+                for key in keys {
+                    let key = conn.final_key(namespace, key.into());
+                    pttls.push(
+                        redis::cmd("PTTL")
+                            .arg(key)
+                            .query_async::<_, i64>(conn.get_inner_conn().await.unwrap())
+                            .await
+                            .unwrap(),
+                    );
+                }
+                pttls
+            }
+
+            let keys = (0..100).map(|i| format!("k{}", i)).collect::<Vec<_>>();
+
+            // Each key of an mset gets its own jitter:
+            work_conn
+                .batch()
+                .ttl_jitter(TtlJitter::new(0.2)?)
+                .mset(
+                    "jitter",
+                    keys.iter().map(|key| (key, "v")),
+                    Some(Duration::from_secs(1)),
+                )
+                .fire()
+                .await;
+            let jittered = pttls(&mut work_conn, "jitter", &keys).await;
+            // Allowing a little for the time taken to read them back:
+            assert!(
+                jittered.iter().all(|pttl| *pttl > 700 && *pttl <= 1200),
+                "{:?}",
+                jittered
+            );
+            assert!(jittered.iter().any(|pttl| *pttl < 900), "{:?}", jittered);
+            assert!(jittered.iter().any(|pttl| *pttl > 1000), "{:?}", jittered);
+            // No value should dominate, uniform over ~400 values, rounded to 50ms buckets:
+            let mut buckets = std::collections::HashMap::new();
+            for pttl in &jittered {
+                *buckets.entry(pttl / 50).or_insert(0) += 1;
+            }
+            assert!(buckets.len() >= 5, "{:?}", buckets);
+            assert!(buckets.values().all(|count| *count < 66), "{:?}", buckets);
+
+            // Zero jitter is exactly the base:
+            work_conn
+                .batch()
+                .ttl_jitter(TtlJitter::new(0.0)?)
+                .mset(
+                    "jitter_zero",
+                    keys.iter().map(|key| (key, "v")),
+                    Some(Duration::from_secs(10)),
+                )
+                .set("jitter_zero", "single", "v", Some(Duration::from_secs(10)))
+                .fire()
+                .await;
+            let mut exact = pttls(&mut work_conn, "jitter_zero", &keys).await;
+            exact.extend(pttls(&mut work_conn, "jitter_zero", &["single".to_string()]).await);
+            assert!(
+                exact.iter().all(|pttl| *pttl > 9_500 && *pttl <= 10_000),
+                "{:?}",
+                exact
+            );
+
+            // Set ttls via expire are jittered too:
+            work_conn
+                .batch()
+                .ttl_jitter(TtlJitter::new(0.5)?)
+                .sadd("jitter_set", "set", Some(Duration::from_secs(10)), ["a"])
+                .fire()
+                .await;
+            let set_pttl = pttls(&mut work_conn, "jitter_set", &["set".to_string()]).await[0];
+            assert!(set_pttl > 4_500 && set_pttl <= 15_000, "{}", set_pttl);
+
+            // Validation:
+            assert!(TtlJitter::new(1.5).is_err());
+            assert!(TtlJitter::new(-0.5).is_err());
+        }
+
         // zadd/zaddmulti/zrem/zrangebyscore/zremrangebyscore
         assert_eq!(
             work_conn
@@ -456,11 +1007,2211 @@ mod tests {
         //     Some((None, Some("str".to_string()), None))
         // );
 
-        // Run the dlock tests:
-        redis_dlock_tests(&work_r).await?;
+        // <--- Set algebra:
+        {
+            use std::collections::HashSet;
+
+            let sorted = |mut members: Vec<String>| {
+                members.sort();
+                members
+            };
+            let reference = |members: HashSet<&str>| {
+                sorted(members.into_iter().map(|m| m.to_string()).collect())
+            };
+            let s1 = HashSet::from(["a", "b", "c", "d"]);
+            let s2 = HashSet::from(["b", "c", "e"]);
+            let s3 = HashSet::from(["c", "d", "e", "f"]);
+            work_conn
+                .batch()
+                .sadd("sa", "s1", None, s1.iter())
+                .sadd("sa", "s2", None, s2.iter())
+                .sadd("sa", "s3", None, s3.iter())
+                .fire()
+                .await
+                .unwrap();
+
+            let (inter, union, diff, card) = work_conn
+                .batch()
+                .sinter("sa", ["s1", "s2", "s3"])
+                .sunion("sa", ["s1", "s2", "s3"])
+                .sdiff("sa", ["s1", "s2", "s3"])
+                .scard("sa", "s1")
+                .fire()
+                .await
+                .unwrap();
+            let ref_inter = s1.iter().filter(|m| s2.contains(*m) && s3.contains(*m));
+            let ref_union = s1.iter().chain(s2.iter()).chain(s3.iter());
+            let ref_diff = s1.iter().filter(|m| !s2.contains(*m) && !s3.contains(*m));
+            assert_eq!(sorted(inter), reference(ref_inter.copied().collect()));
+            assert_eq!(sorted(union), reference(ref_union.copied().collect()));
+            assert_eq!(sorted(diff), reference(ref_diff.copied().collect()));
+            assert_eq!(card, 4);
+
+            // Missing sets are treated as empty:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .sinter("sa", ["s1", "missing"])
+                    .sunion("sa", ["missing", "s2"])
+                    .sdiff("sa", ["s2", "missing"])
+                    .scard("sa", "missing")
+                    .smembers("sa", "missing")
+                    .fire()
+                    .await
+                    .map(|(inter, union, diff, card, members)| (
+                        inter,
+                        sorted(union),
+                        sorted(diff),
+                        card,
+                        members
+                    )),
+                Some((
+                    vec![],
+                    reference(s2.clone()),
+                    reference(s2.clone()),
+                    0,
+                    vec![]
+                ))
+            );
+
+            // Empty key lists give empty results, store variants are no-ops:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .sinter("sa", [])
+                    .sunion("sa", [])
+                    .sdiff("sa", [])
+                    .sunionstore("sa", "empty_dest", [], None)
+                    .exists("sa", "empty_dest")
+                    .fire()
+                    .await,
+                Some((vec![], vec![], vec![], false))
+            );
+
+            // Store variants create the destination with the right members and ttl,
+            // composing with a read of the destination in the same batch:
+            let (inter, union, diff) = work_conn
+                .batch()
+                .sinterstore("sa", "inter", ["s1", "s3"], None)
+                .sunionstore("sa", "union", ["s2", "s3"], Some(Duration::from_secs(30)))
+                .sdiffstore("sa", "diff", ["s3", "s1"], None)
+                .smembers("sa", "inter")
+                .smembers("sa", "union")
+                .smembers("sa", "diff")
+                .fire()
+                .await
+                .unwrap();
+            assert_eq!(sorted(inter), reference(&s1 & &s3));
+            assert_eq!(sorted(union), reference(&s2 | &s3));
+            assert_eq!(sorted(diff), reference(&s3 - &s1));
+            for (key, has_ttl) in [("union", true), ("inter", false)] {
+                let pttl = redis::cmd("PTTL")
+                    .arg(work_conn.final_key("sa", key.into()))
+                    .query_async::<_, i64>(work_conn.get_inner_conn().await.unwrap())
+                    .await
+                    .change_context(AnyErr)?;
+                if has_ttl {
+                    assert!(pttl > 0 && pttl <= 30_000, "{}", pttl);
+                } else {
+                    assert_eq!(pttl, -1);
+                }
+            }
+
+            // Storing replaces whatever was in the destination:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .sdiffstore("sa", "union", ["s1", "s2"], None)
+                    .smembers("sa", "union")
+                    .fire()
+                    .await
+                    .map(sorted),
+                Some(reference(&s1 - &s2))
+            );
+        }
+
+        // <--- Lists:
+        {
+            let values = |fuzzy: Vec<RedisFuzzy<i64>>| {
+                fuzzy.into_iter().map(|value| value.0).collect::<Vec<_>>()
+            };
+            let (all, popped, len) = work_conn
+                .batch()
+                .rpush("li", "queue", Some(Duration::from_secs(30)), [3, 4, 5])
+                .lpush("li", "queue", None, [2, 1])
+                // Empty pushes are no-ops:
+                .rpush("li", "queue", None, Vec::<i64>::new())
+                .lrange::<i64>("li", "queue", 0, -1)
+                .lpop::<i64>("li", "queue", 2)
+                .llen("li", "queue")
+                .fire()
+                .await
+                .unwrap();
+            assert_eq!(
+                values(all),
+                vec![Some(1), Some(2), Some(3), Some(4), Some(5)]
+            );
+            assert_eq!(values(popped), vec![Some(1), Some(2)]);
+            assert_eq!(len, 3);
+            let pttl = redis::cmd("PTTL")
+                .arg(work_conn.final_key("li", "queue".into()))
+                .query_async::<_, i64>(work_conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?;
+            assert!(pttl > 0 && pttl <= 30_000, "{}", pttl);
+
+            // Corrupt entries are None without failing the rest of the batch:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .rpush("li", "queue", None, ["not a number"])
+                    .lrange::<i64>("li", "queue", 0, -1)
+                    .lpop::<i64>("li", "queue", 10)
+                    .get::<String>("li", "missing")
+                    .fire()
+                    .await
+                    .map(|(all, popped, missing)| (values(all), values(popped), missing)),
+                Some((
+                    vec![Some(3), Some(4), Some(5), None],
+                    vec![Some(3), Some(4), Some(5), None],
+                    None
+                ))
+            );
+            // Missing lists are empty:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .lrange::<i64>("li", "queue", 0, -1)
+                    .lpop::<i64>("li", "queue", 1)
+                    .llen("li", "queue")
+                    .fire()
+                    .await
+                    .map(|(all, popped, len)| (all.len(), popped.len(), len)),
+                Some((0, 0, 0))
+            );
+
+            // Redis down:
+            assert_eq!(
+                fail_conn
+                    .batch()
+                    .rpush("li", "queue", None, [1])
+                    .lrange::<i64>("li", "queue", 0, -1)
+                    .llen("li", "queue")
+                    .fire()
+                    .await,
+                None
+            );
+        }
+
+        // <--- Compare and set:
+        work_conn
+            .batch()
+            .set("cas", "state", "pending", None)
+            .fire()
+            .await;
+        // Successful transition:
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals("cas", "state", "pending", "running", None)
+                .get::<String>("cas", "state")
+                .fire()
+                .await,
+            Some((true, Some("running".to_string())))
+        );
+        // Stale expected leaves the value untouched:
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals("cas", "state", "pending", "done", None)
+                .get::<String>("cas", "state")
+                .fire()
+                .await,
+            Some((false, Some("running".to_string())))
+        );
+        // Missing key only set by the absent variant:
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals("cas", "missing", "pending", "running", None)
+                .exists("cas", "missing")
+                .set_if_absent_or_equals("cas", "missing2", "pending", "running", None)
+                .get::<String>("cas", "missing2")
+                .fire()
+                .await,
+            Some((false, false, true, Some("running".to_string())))
+        );
+        // Expiry only applied on a successful set:
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals(
+                    "cas",
+                    "state",
+                    "wrong",
+                    "done",
+                    Some(Duration::from_millis(15))
+                )
+                .set_if_equals(
+                    "cas",
+                    "missing2",
+                    "running",
+                    "done",
+                    Some(Duration::from_millis(15))
+                )
+                .fire()
+                .await,
+            Some((false, true))
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            work_conn
+                .batch()
+                .get::<String>("cas", "state")
+                .get::<String>("cas", "missing2")
+                .fire()
+                .await,
+            Some((Some("running".to_string()), None))
+        );
+        // Json values compare byte-exact on their serialized form:
+        let pending = RedisJson(ExampleJson {
+            ree: "pending".to_string(),
+        });
+        let running = RedisJson(ExampleJson {
+            ree: "running".to_string(),
+        });
+        work_conn
+            .batch()
+            .set("cas", "json", &pending, None)
+            .fire()
+            .await;
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals("cas", "json", &running, &running, None)
+                .set_if_equals("cas", "json", &pending, &running, None)
+                .get::<RedisJson<ExampleJson>>("cas", "json")
+                .fire()
+                .await,
+            Some((
+                false,
+                true,
+                Some(RedisJson(ExampleJson {
+                    ree: "running".to_string()
+                }))
+            ))
+        );
+        // Same json but formatted differently doesn't match:
+        assert_eq!(
+            work_conn
+                .batch()
+                .set_if_equals("cas", "json", "{ \"ree\": \"running\" }", "other", None)
+                .fire()
+                .await,
+            Some(false)
+        );
+        // Delete if equals:
+        assert_eq!(
+            work_conn
+                .batch()
+                .del_if_equals("cas", "state", "pending")
+                .del_if_equals("cas", "state", "running")
+                .exists("cas", "state")
+                .fire()
+                .await,
+            Some((false, true, false))
+        );
+        // Redis down:
+        assert_eq!(
+            fail_conn
+                .batch()
+                .set_if_equals("cas", "state", "pending", "running", None)
+                .fire()
+                .await,
+            None
+        );
+
+        // <--- Counters:
+        {
+            // Interleaved with gets, each sees the increments before it:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .incr("counters", "c", 5, None)
+                    .get::<i64>("counters", "c")
+                    .incr("counters", "c", -7, None)
+                    .get::<i64>("counters", "c")
+                    .hincrby("counters", "h", "hits", 2, None)
+                    .hincrby("counters", "h", "hits", 3, None)
+                    .hincrby("counters", "h", "misses", -1, None)
+                    .fire()
+                    .await,
+                Some((
+                    Some(5),
+                    Some(5),
+                    Some(-2),
+                    Some(-2),
+                    Some(2),
+                    Some(5),
+                    Some(-1)
+                ))
+            );
+
+            // Non-integers come back as None without failing the rest of the batch:
+            work_conn
+                .batch()
+                .set("counters", "text", "abc", None)
+                .fire()
+                .await;
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .incr("counters", "text", 1, None)
+                    .get::<String>("counters", "text")
+                    .incr("counters", "c", 1, None)
+                    .fire()
+                    .await,
+                Some((None, Some("abc".to_string()), Some(-1)))
+            );
+
+            // Expiry reset on each increment, left alone without:
+            async fn pttl(conn: &mut RedisConn<'_>, key: &str) -> i64 {
+                let key = conn.final_key("counters", key.into());
+                redis::cmd("PTTL")
+                    .arg(key)
+                    .query_async::<_, i64>(conn.get_inner_conn().await.unwrap())
+                    .await
+                    .unwrap()
+            }
+            work_conn
+                .batch()
+                .incr("counters", "ttl", 1, Some(Duration::from_secs(5)))
+                .hincrby("counters", "ttl_h", "f", 1, Some(Duration::from_secs(5)))
+                .fire()
+                .await;
+            assert!(pttl(&mut work_conn, "ttl").await <= 5_000);
+            assert!(pttl(&mut work_conn, "ttl_h").await <= 5_000);
+            work_conn
+                .batch()
+                .incr("counters", "ttl", 1, Some(Duration::from_secs(30)))
+                .hincrby("counters", "ttl_h", "f", 1, Some(Duration::from_secs(30)))
+                .fire()
+                .await;
+            assert!(pttl(&mut work_conn, "ttl").await > 5_000);
+            assert!(pttl(&mut work_conn, "ttl_h").await > 5_000);
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .incr("counters", "ttl", 1, None)
+                    .incr("counters", "c", 1, Some(Duration::ZERO))
+                    .fire()
+                    .await,
+                Some((Some(3), Some(0)))
+            );
+            assert!(pttl(&mut work_conn, "ttl").await > 5_000);
+            // Zero expiry is skipped rather than expiring straight away:
+            assert_eq!(pttl(&mut work_conn, "c").await, -1);
+
+            // Multiple hash fields set, read in order and deleted at once:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .hset_multi(
+                        "counters",
+                        "fields",
+                        Some(Duration::from_secs(5)),
+                        [("a", 1), ("b", 2)]
+                    )
+                    .hdel("counters", "fields", ["a"])
+                    .hdel("counters", "fields", Vec::<String>::new())
+                    .hmget::<i64>("counters", "fields", ["b", "a", "missing"])
+                    .fire()
+                    .await,
+                Some(vec![Some(2), None, None])
+            );
+            assert!(pttl(&mut work_conn, "fields").await <= 5_000);
+
+            // Redis down:
+            assert_eq!(
+                fail_conn
+                    .batch()
+                    .incr("counters", "c", 1, None)
+                    .fire()
+                    .await,
+                None
+            );
+        }
+
+        // <--- Counter buffer:
+        {
+            let keys = ["k0", "k1", "k2", "k3", "k4"];
+            // Thousands of increments across tasks collapse into a handful of flushes:
+            let buffer = Arc::new(RedisCounterBuffer::new(
+                work_r.clone(),
+                "counter_buf",
+                Duration::from_millis(100),
+                1000,
+            ));
+            let handles = (0..10)
+                .map(|_| {
+                    let buffer = buffer.clone();
+                    tokio::spawn(async move {
+                        for i in 0..500 {
+                            buffer.incr(keys[i % keys.len()], 1);
+                            if i % 100 == 0 {
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.await.change_context(AnyErr)?;
+            }
+            buffer.incr("neg", -3);
+            // Everything is in redis as soon as a forced flush returns:
+            assert!(buffer.force_flush().await);
+            assert_eq!(buffer.pending(), 0);
+            let stats = buffer.stats();
+            assert!((1..=5).contains(&stats.flushes), "{:?}", stats);
+            assert_eq!(stats.early_flushes, 0);
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .mget::<i64>("counter_buf", &keys)
+                    .get::<i64>("counter_buf", "neg")
+                    .fire()
+                    .await,
+                Some((vec![Some(1000); 5], Some(-3)))
+            );
+            // Shutting down flushes what's left:
+            buffer.incr("k0", 5);
+            assert!(Arc::into_inner(buffer).unwrap().shutdown().await);
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<i64>("counter_buf", "k0")
+                    .fire()
+                    .await,
+                Some(Some(1005))
+            );
+
+            // Hitting the distinct key cap flushes early, without waiting for the interval:
+            let capped =
+                RedisCounterBuffer::new(work_r.clone(), "counter_buf", Duration::from_secs(60), 10);
+            for i in 0..10 {
+                capped.incr(&format!("cap{}", i), 1);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(capped.pending(), 0);
+            assert_eq!(capped.stats().early_flushes, 1);
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<i64>("counter_buf", "cap9")
+                    .fire()
+                    .await,
+                Some(Some(1))
+            );
+            // Dropping flushes in the background:
+            capped.incr("dropped", 2);
+            drop(capped);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<i64>("counter_buf", "dropped")
+                    .fire()
+                    .await,
+                Some(Some(2))
+            );
+
+            // Redis down, increments are kept for the next flush:
+            let failing =
+                RedisCounterBuffer::new(fail_r.clone(), "counter_buf", Duration::from_secs(60), 10);
+            failing.incr("k0", 1);
+            assert!(!failing.force_flush().await);
+            assert_eq!(failing.pending(), 1);
+            assert_eq!(failing.stats().failed_flushes, 1);
+        }
+
+        // <--- Soft-fail commands:
+        {
+            work_conn
+                .batch()
+                .set("soft", "str", "abc", None)
+                .set("soft", "other", "xyz", None)
+                .fire()
+                .await;
+            // WRONGTYPE fails the whole batch by default:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<String>("soft", "str")
+                    .smembers("soft", "str")
+                    .get::<String>("soft", "other")
+                    .fire()
+                    .await,
+                None
+            );
+            // Annotated, just that slot falls back to empty:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<String>("soft", "str")
+                    .smembers("soft", "str")
+                    .allow_fail()
+                    .get::<String>("soft", "other")
+                    .hgetall("soft", "str")
+                    .allow_fail()
+                    .fire()
+                    .await,
+                Some((
+                    Some("abc".to_string()),
+                    vec![],
+                    Some("xyz".to_string()),
+                    std::collections::HashMap::new()
+                ))
+            );
+            // Writes in a failed batch aren't retried, so applied only once:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .incr("soft", "count", 1, None)
+                    .smembers("soft", "str")
+                    .fire()
+                    .await,
+                None
+            );
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .incr("soft", "count", 1, None)
+                    .smembers("soft", "str")
+                    .allow_fail()
+                    .fire()
+                    .await,
+                Some((Some(2), vec![]))
+            );
+            // Non-returning and script based commands are left as-is:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .set("soft", "ignored", "a", None)
+                    .allow_fail()
+                    .incr("soft", "count", 1, None)
+                    .allow_fail()
+                    .get::<String>("soft", "ignored")
+                    .fire()
+                    .await,
+                Some((Some(3), Some("a".to_string())))
+            );
+        }
+
+        // <--- Topics:
+        {
+            let mut users = work_r.subscribe_topic::<UserUpdatedTopic>().await.unwrap();
+            let mut orders = work_r.subscribe_topic::<OrderPlacedTopic>().await.unwrap();
+            assert_eq!(
+                users.channel(),
+                work_conn.final_key("events", "user_updated".into())
+            );
+
+            // Direct and batched publishing:
+            work_conn
+                .publish_topic::<UserUpdatedTopic>(&UserUpdated { id: 1 })
+                .await;
+            work_conn
+                .batch()
+                .publish_topic::<OrderPlacedTopic>(&"o1".to_string())
+                .publish_topic::<UserUpdatedTopic>(&UserUpdated { id: 2 })
+                .fire()
+                .await;
+            assert_eq!(users.recv().await, Some(UserUpdated { id: 1 }));
+            assert_eq!(users.recv().await, Some(UserUpdated { id: 2 }));
+            assert_eq!(orders.recv().await, Some("o1".to_string()));
+
+            // Topics sharing a namespace don't cross-deliver, nothing else should arrive:
+            let no_more = Duration::from_millis(50);
+            assert!(tokio::time::timeout(no_more, orders.recv()).await.is_err());
+            // Nor the same topic under a different prefix:
+            rs.instance()?
+                .conn()
+                .publish_topic::<UserUpdatedTopic>(&UserUpdated { id: 3 })
+                .await;
+            assert!(tokio::time::timeout(no_more, users.recv()).await.is_err());
+
+            // Used topics are registered for debugging:
+            let topics = list_topics();
+            for (channel, payload_type) in [
+                ("user_updated", std::any::type_name::<UserUpdated>()),
+                ("order_placed", std::any::type_name::<String>()),
+            ] {
+                assert!(topics.contains(&RedisTopicInfo {
+                    namespace: "events",
+                    channel,
+                    payload_type,
+                }));
+            }
+
+            // Redis down:
+            assert!(fail_r.subscribe_topic::<UserUpdatedTopic>().await.is_none());
+            fail_conn
+                .publish_topic::<UserUpdatedTopic>(&UserUpdated { id: 4 })
+                .await;
+        }
+
+        // <--- Unsubscribing:
+        {
+            let channel = work_conn.final_key("subs", "chan".into());
+            let active = |r: &Redis| {
+                r.active_subscriptions()
+                    .into_iter()
+                    .find(|(active, _)| *active == channel)
+                    .map(|(_, count)| count)
+            };
+            let mut first = work_r.subscribe::<u32>("subs", "chan").await.unwrap();
+            let second = work_r.subscribe::<u32>("subs", "chan").await.unwrap();
+            // Clones share the registrations:
+            let mut third = work_r
+                .clone()
+                .subscribe::<u32>("subs", "chan")
+                .await
+                .unwrap();
+            assert_eq!(
+                (second.channel(), second.namespace()),
+                (channel.as_str(), "subs")
+            );
+            assert_eq!(active(&work_r), Some(3));
+
+            // Removed as soon as unsubscribed, no background cleanup to wait for:
+            second.unsubscribe().await;
+            assert_eq!(active(&work_r), Some(2));
+
+            // The others keep receiving:
+            work_conn.batch().publish("subs", "chan", "1").fire().await;
+            assert_eq!(first.recv().await, Some(1));
+            assert_eq!(third.recv().await, Some(1));
+
+            first.unsubscribe().await;
+            drop(third);
+            assert_eq!(active(&work_r), None);
+
+            // Disabled listeners are tracked too:
+            let disabled_r = Redis::new_disabled("disabled_subs")?;
+            let listener = disabled_r.subscribe::<u32>("subs", "chan").await.unwrap();
+            assert_eq!(
+                disabled_r.active_subscriptions(),
+                vec![(listener.channel().to_string(), 1)]
+            );
+            listener.unsubscribe().await;
+            assert!(disabled_r.active_subscriptions().is_empty());
+        }
+
+        // <--- PubSub diagnostics:
+        {
+            let first = work_r.subscribe::<u32>("diag", "first").await.unwrap();
+            let second = work_r.subscribe::<u32>("diag", "second").await.unwrap();
+            let extra = work_r.subscribe::<u32>("diag", "second").await.unwrap();
+
+            let diag = work_r.pubsub_diagnostics().await;
+            let local = |diag: &PubSubDiagnostics, channel: &str| {
+                diag.local
+                    .iter()
+                    .find(|sub| sub.namespace == "diag" && sub.channel == channel)
+                    .map(|sub| sub.listeners)
+            };
+            assert_eq!(local(&diag, "first"), Some(1));
+            assert_eq!(local(&diag, "second"), Some(2));
+            let sub = diag
+                .local
+                .iter()
+                .find(|sub| sub.channel == "first")
+                .unwrap();
+            assert_eq!(sub.final_channel, first.channel());
+            assert!(sub.subscribed_at_ms > 0);
+
+            let server = diag.server.clone().unwrap();
+            let server_first = server
+                .channels
+                .iter()
+                .find(|chan| chan.final_channel == first.channel())
+                .unwrap();
+            assert_eq!(
+                (
+                    server_first.namespace.as_str(),
+                    server_first.channel.as_str()
+                ),
+                ("diag", "first")
+            );
+            assert!(server_first.subscribers >= 1);
+            // Serializable for debug endpoints:
+            let json = serde_json::to_string(&diag).unwrap();
+            assert_eq!(
+                serde_json::from_str::<PubSubDiagnostics>(&json).unwrap(),
+                diag
+            );
+
+            // Dropping a listener is reflected straight away:
+            drop(extra);
+            let diag = work_r.pubsub_diagnostics().await;
+            assert_eq!(local(&diag, "second"), Some(1));
+            drop(second);
+            let diag = work_r.pubsub_diagnostics().await;
+            assert_eq!(local(&diag, "second"), None);
+            assert_eq!(local(&diag, "first"), Some(1));
+            drop(first);
+
+            // The server section degrades to None when redis is down:
+            let diag = fail_r.pubsub_diagnostics().await;
+            assert!(diag.local.is_empty());
+            assert_eq!(diag.server, None);
+        }
+
+        // <--- Enveloped pubsub:
+        {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct Event {
+                name: String,
+            }
+            let event = |name: &str| Event { name: name.into() };
+
+            // A second wrapper sharing the prefix, e.g. another instance of the same service:
+            let other_r = Redis::new(format!("redis://{}", work_r.server()), work_r.prefix())?;
+            assert_ne!(other_r.origin(), work_r.origin());
+            assert!(work_r.origin().starts_with(work_r.prefix()));
+
+            let mut enveloped = work_r
+                .subscribe_enveloped::<Event>("env", "events")
+                .await
+                .unwrap();
+            let mut plain = work_r.subscribe::<Event>("env", "events").await.unwrap();
+
+            work_conn
+                .batch()
+                .publish_enveloped("env", "events", &event("a"))
+                .fire()
+                .await;
+            other_r
+                .conn()
+                .batch()
+                .publish_enveloped("env", "events", &event("b"))
+                .fire()
+                .await;
+            // Not enveloped:
+            work_conn
+                .batch()
+                .publish(
+                    "env",
+                    "events",
+                    serde_json::to_vec(&event("c")).change_context(AnyErr)?,
+                )
+                .fire()
+                .await;
+
+            let before = chrono::Utc::now();
+            let first = enveloped.recv().await.unwrap();
+            assert_eq!(first.payload, event("a"));
+            assert_eq!(first.origin.as_deref(), Some(work_r.origin()));
+            assert!(first.published_at.unwrap() <= before);
+            let latency = first.latency.unwrap();
+            assert!(
+                latency >= chrono::TimeDelta::zero() && latency < chrono::TimeDelta::seconds(1),
+                "{:?}",
+                latency
+            );
+
+            // Identifies which wrapper published:
+            let second = enveloped.recv().await.unwrap();
+            assert_eq!(second.payload, event("b"));
+            assert_eq!(second.origin.as_deref(), Some(other_r.origin()));
+
+            // Plain messages still arrive, without metadata:
+            assert_eq!(
+                enveloped.recv().await.unwrap(),
+                EnvelopedMsg {
+                    published_at: None,
+                    origin: None,
+                    latency: None,
+                    payload: event("c"),
+                }
+            );
+
+            // Plain subscribers unwrap transparently:
+            assert_eq!(plain.recv().await, Some(event("a")));
+            assert_eq!(plain.recv().await, Some(event("b")));
+            assert_eq!(plain.recv().await, Some(event("c")));
+
+            // Overridden origin:
+            work_r
+                .clone()
+                .with_origin("worker-1")
+                .conn()
+                .batch()
+                .publish_enveloped("env", "events", &event("d"))
+                .fire()
+                .await;
+            assert_eq!(
+                enveloped.recv().await.unwrap().origin.as_deref(),
+                Some("worker-1")
+            );
+        }
+
+        // <--- PubSub bridge:
+        {
+            use futures::StreamExt;
+
+            let bridge = RedisPubSubBridge::new(work_r.clone()).with_ring_size(2);
+            let wait = chrono::TimeDelta::seconds(2);
+            let publish = |id: u64| {
+                let r = work_r.clone();
+                async move {
+                    r.conn()
+                        .batch()
+                        .publish(
+                            "bridge",
+                            "live",
+                            serde_json::json!({ "id": id }).to_string(),
+                        )
+                        .fire()
+                        .await;
+                }
+            };
+
+            // First poll subscribes, nothing there yet:
+            let first = bridge
+                .poll("bridge", "live", None, chrono::TimeDelta::zero())
+                .await;
+            assert_eq!(first.events, Vec::<serde_json::Value>::new());
+            assert!(!first.resync);
+            let mut sse = Box::pin(bridge.subscribe_sse("bridge", "live").await);
+
+            // Two long-polling clients both receive the event, sharing the one subscription:
+            let (a, b, _) = futures::join!(
+                bridge.poll("bridge", "live", Some(first.next_cursor.clone()), wait),
+                bridge.poll("bridge", "live", Some(first.next_cursor.clone()), wait),
+                async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    publish(1).await;
+                }
+            );
+            assert_eq!(a.events, vec![serde_json::json!({ "id": 1 })]);
+            assert_eq!(a, b);
+
+            // SSE frames include the cursor:
+            assert_eq!(
+                sse.next().await,
+                Some(format!("id: {}\ndata: {{\"id\":1}}\n\n", a.next_cursor))
+            );
+
+            // Reconnecting with the previous cursor replays the missed event from the ring:
+            publish(2).await;
+            assert_eq!(
+                sse.next()
+                    .await
+                    .map(|frame| frame.contains("data: {\"id\":2}\n\n")),
+                Some(true)
+            );
+            let replayed = bridge
+                .poll("bridge", "live", Some(a.next_cursor.clone()), wait)
+                .await;
+            assert_eq!(replayed.events, vec![serde_json::json!({ "id": 2 })]);
+            assert!(!replayed.resync);
+
+            // Overflowing the ring signals a resync:
+            publish(3).await;
+            publish(4).await;
+            // Wait for them to land:
+            let mut cursor = replayed.next_cursor.clone();
+            let mut landed = vec![];
+            while landed.len() < 2 {
+                let polled = bridge.poll("bridge", "live", Some(cursor), wait).await;
+                assert!(!polled.resync);
+                landed.extend(polled.events);
+                cursor = polled.next_cursor;
+            }
+            let overflowed = bridge
+                .poll("bridge", "live", Some(a.next_cursor.clone()), wait)
+                .await;
+            assert!(overflowed.resync);
+            assert!(overflowed.events.is_empty());
+            // Unknown cursors do too:
+            assert!(
+                bridge
+                    .poll("bridge", "live", Some("foo-1".into()), wait)
+                    .await
+                    .resync
+            );
+
+            // Redis down, waits then returns empty:
+            let down = RedisPubSubBridge::new(fail_r.clone())
+                .poll("bridge", "live", None, chrono::TimeDelta::milliseconds(10))
+                .await;
+            assert!(down.events.is_empty());
+
+            // Subscriptions are dropped once their last client has gone:
+            let has_channel = |bridge: &RedisPubSubBridge, channel: &str| {
+                format!("{:?}", bridge).contains(channel)
+            };
+            let no_linger =
+                RedisPubSubBridge::new(work_r.clone()).with_poll_linger(chrono::TimeDelta::zero());
+            no_linger
+                .poll("bridge", "evicted", None, chrono::TimeDelta::zero())
+                .await;
+            assert!(!has_channel(&no_linger, "evicted"));
+            let sse = no_linger.subscribe_sse("bridge", "evicted").await;
+            assert!(has_channel(&no_linger, "evicted"));
+            drop(sse);
+            assert!(!has_channel(&no_linger, "evicted"));
+            // Polls keep it around for the client's next poll by default:
+            bridge
+                .poll("bridge", "lingering", None, chrono::TimeDelta::zero())
+                .await;
+            assert!(has_channel(&bridge, "lingering"));
+        }
+
+        // <--- Borrowed reads:
+        {
+            #[derive(Debug, serde::Serialize, serde::Deserialize)]
+            struct Blob<'a> {
+                #[serde(borrow)]
+                name: std::borrow::Cow<'a, str>,
+                data: &'a str,
+            }
+
+            let big = "x".repeat(2 * 1024 * 1024);
+            work_conn
+                .batch()
+                .set(
+                    "borrowed",
+                    "blob",
+                    RedisJsonBorrowed(&Blob {
+                        name: "big".into(),
+                        data: &big,
+                    }),
+                    None,
+                )
+                .set("borrowed", "corrupt", "{not json", None)
+                .fire()
+                .await;
+
+            // Decoded straight from the response, no owned copy of the strings:
+            let matched = work_conn
+                .batch()
+                .get::<String>("borrowed", "blob")
+                .fire_with(|result| {
+                    assert_eq!(result.len(), 1);
+                    let blob = result.json::<Blob>(0).unwrap().unwrap();
+                    assert!(matches!(blob.name, std::borrow::Cow::Borrowed("big")));
+                    blob.data.len() == big.len() && blob.data.bytes().all(|b| b == b'x')
+                })
+                .await;
+            assert_eq!(matched, Some(true));
+
+            // Slots follow the usual return order, missing keys and corrupt values are distinguishable:
+            let outcome = work_conn
+                .batch()
+                .set("borrowed", "ignored", "val", None)
+                .get::<String>("borrowed", "missing")
+                .get::<String>("borrowed", "corrupt")
+                .fire_with(|result| {
+                    (
+                        result.len(),
+                        result.json::<Blob>(0).is_none(),
+                        result.json::<Blob>(1).map(|res| res.is_err()),
+                        result.bytes(1).map(|bytes| bytes.to_vec()),
+                        result.json::<Blob>(2).is_none(),
+                    )
+                })
+                .await;
+            assert_eq!(
+                outcome,
+                Some((2, true, Some(true), Some(b"{not json".to_vec()), true))
+            );
+
+            // Redis down, closure never called:
+            assert_eq!(
+                fail_conn
+                    .batch()
+                    .get::<String>("borrowed", "blob")
+                    .fire_with(|_| unreachable!())
+                    .await,
+                None::<()>
+            );
+        }
+
+        // <--- Consistent two phase reads:
+        {
+            work_conn
+                .batch()
+                .set("two_phase", "version", 1, None)
+                .set("two_phase", "value", "a", None)
+                .fire()
+                .await;
+            let phase1 = |conn: &RedisConn<'_>| {
+                let mut pipe = redis::pipe();
+                pipe.get(conn.final_key("two_phase", "version".into()));
+                pipe
+            };
+            // Bumps the version whilst reading on the given calls, like a concurrent writer:
+            let phase2_calls = std::sync::atomic::AtomicUsize::new(0);
+            let calls = &phase2_calls;
+            let phase2 = move |bump_on: &'static [usize]| {
+                move |conn: &RedisConn<'_>, _: &(i64,)| {
+                    let mut pipe = redis::pipe();
+                    if bump_on.contains(&calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst)) {
+                        pipe.incr(conn.final_key("two_phase", "version".into()), 1)
+                            .ignore();
+                    }
+                    pipe.get(conn.final_key("two_phase", "value".into()))
+                        .get(conn.final_key("two_phase", "version".into()));
+                    pipe
+                }
+            };
+            let same_version = |(v1,): &(i64,), (_, v2): &(String, i64)| v1 == v2;
+
+            // Consistent first time:
+            let read = work_conn
+                .consistent_two_phase(phase1, phase2(&[]), same_version, 3)
+                .await
+                .unwrap();
+            assert_eq!(
+                (read.second.0.as_str(), read.retries, read.verified),
+                ("a", 0, true)
+            );
+
+            // Modified during the first attempt, so retried:
+            phase2_calls.store(0, std::sync::atomic::Ordering::SeqCst);
+            let read = work_conn
+                .consistent_two_phase(phase1, phase2(&[0]), same_version, 3)
+                .await
+                .unwrap();
+            assert_eq!((read.first.0, read.retries, read.verified), (2, 1, true));
+
+            // Never consistent, gives up returning the last attempt:
+            phase2_calls.store(0, std::sync::atomic::Ordering::SeqCst);
+            let read = work_conn
+                .consistent_two_phase(phase1, phase2(&[0, 1, 2, 3]), same_version, 3)
+                .await
+                .unwrap();
+            assert_eq!(
+                (read.first.0, read.second, read.retries, read.verified),
+                (5, ("a".to_string(), 6), 3, false)
+            );
+
+            // Redis down:
+            assert!(fail_conn
+                .consistent_two_phase(phase1, phase2(&[]), same_version, 3)
+                .await
+                .is_none());
+        }
+
+        // <--- Object store:
+        {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct Address {
+                line: String,
+                postcode: Option<String>,
+            }
+
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct User {
+                name: String,
+                age: u32,
+                nickname: Option<String>,
+                address: Address,
+                tags: Vec<String>,
+            }
+
+            // The same object after a new field was added:
+            #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+            struct UserV2 {
+                name: String,
+                #[serde(default)]
+                verified: bool,
+            }
+
+            let user = User {
+                name: "Bob".into(),
+                age: 30,
+                nickname: None,
+                address: Address {
+                    line: "1 Road".into(),
+                    postcode: Some("AB1 2CD".into()),
+                },
+                tags: vec!["a".into(), "b".into()],
+            };
+
+            let store = RedisObjectStore::<User>::new("objects", Some(Duration::from_secs(30)));
+            assert_eq!(store.load(&mut work_conn, "bob").await, None);
+            store.save(&mut work_conn, "bob", &user).await?;
+            assert_eq!(store.load(&mut work_conn, "bob").await, Some(user.clone()));
+            assert_eq!(store.load(&mut fail_conn, "bob").await, None);
+
+            // Stored field per field, nested json encoded:
+            let raw = work_conn
+                .batch()
+                .hgetall("objects", "bob")
+                .fire()
+                .await
+                .unwrap();
+            assert_eq!(raw.len(), 5);
+            assert_eq!(raw["name"], "\"Bob\"");
+            assert_eq!(raw["nickname"], "null");
+            assert_eq!(
+                serde_json::from_str::<Address>(&raw["address"]).change_context(AnyErr)?,
+                user.address
+            );
+
+            // Partial patch:
+            assert!(
+                store
+                    .patch(&mut work_conn, "bob", &[("nickname", Some("Bobby"))])
+                    .await?
+            );
+            assert!(store.patch(&mut work_conn, "bob", &[("age", 31)]).await?);
+            let patched = store.load(&mut work_conn, "bob").await.unwrap();
+            assert_eq!(patched.nickname.as_deref(), Some("Bobby"));
+            assert_eq!(patched.age, 31);
+            assert_eq!(patched.address, user.address);
+            // Doesn't create missing objects:
+            assert!(
+                !store
+                    .patch(&mut work_conn, "missing", &[("age", 1)])
+                    .await?
+            );
+            assert_eq!(store.load(&mut work_conn, "missing").await, None);
+
+            // Forward compatible with new fields:
+            let v2_store = RedisObjectStore::<UserV2>::new("objects", None);
+            assert_eq!(
+                v2_store.load(&mut work_conn, "bob").await,
+                Some(UserV2 {
+                    name: "Bob".into(),
+                    verified: false
+                })
+            );
+
+            // Non-objects are rejected:
+            assert!(RedisObjectStore::<Vec<u32>>::new("objects", None)
+                .save(&mut work_conn, "list", &vec![1, 2])
+                .await
+                .is_err());
+
+            // Save refreshes the ttl:
+            async fn pttl(conn: &mut RedisConn<'_>) -> i64 {
+                let key = conn.final_key("objects", "bob".into());
+                redis::cmd("PTTL")
+                    .arg(key)
+                    .query_async::<_, i64>(conn.get_inner_conn().await.unwrap())
+                    .await
+                    .unwrap()
+            }
+            work_conn
+                .batch()
+                .expire("objects", "bob", Duration::from_secs(5))
+                .fire()
+                .await;
+            assert!(pttl(&mut work_conn).await <= 5_000);
+            store.save(&mut work_conn, "bob", &user).await?;
+            let refreshed = pttl(&mut work_conn).await;
+            assert!(refreshed > 5_000 && refreshed <= 30_000, "{}", refreshed);
+
+            // Write-behind coalesces saves into few batches:
+            let behind = RedisObjectStore::<User>::new("objects_behind", None).with_write_behind(
+                &work_r,
+                Duration::from_millis(50),
+                1000,
+            );
+            for age in 0..100 {
+                behind
+                    .save(
+                        &mut work_conn,
+                        &format!("user_{}", age % 5),
+                        &User {
+                            age,
+                            ..user.clone()
+                        },
+                    )
+                    .await?;
+            }
+            // Reads its own queued writes before they're flushed:
+            assert_eq!(behind.load(&mut work_conn, "user_4").await.unwrap().age, 99);
+            let direct = RedisObjectStore::<User>::new("objects_behind", None);
+            assert_eq!(direct.load(&mut work_conn, "user_4").await, None);
+            behind.flush().await;
+            let flushes = behind.flush_count();
+            assert!((1..=2).contains(&flushes), "{}", flushes);
+            for index in 0..5 {
+                assert_eq!(
+                    direct
+                        .load(&mut work_conn, &format!("user_{}", index))
+                        .await
+                        .unwrap()
+                        .age,
+                    95 + index
+                );
+            }
+            // Nothing queued, the interval doesn't send empty batches:
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            assert_eq!(behind.flush_count(), flushes);
+        }
+
+        // <--- Contract:
+        {
+            static GOOD_SCRIPT: once_cell::sync::Lazy<RedisScript> =
+                once_cell::sync::Lazy::new(|| RedisScript::new("return 1"));
+            static BAD_SCRIPT: once_cell::sync::Lazy<RedisScript> =
+                once_cell::sync::Lazy::new(|| RedisScript::new("return ((("));
+
+            work_conn
+                .batch()
+                .set(
+                    "contract",
+                    "config",
+                    RedisJson(ExampleJson { ree: "v".into() }),
+                    None,
+                )
+                .set("contract", "count", 5, None)
+                .fire()
+                .await;
+            let settings_key = work_conn.final_key("contract", "settings".into());
+            redis::cmd("HSET")
+                .arg(&settings_key)
+                .arg("a")
+                .arg(1)
+                .arg("b")
+                .arg(2)
+                .query_async::<_, i64>(work_conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?;
+            // Collections bigger than the sample are still checked:
+            redis::cmd("RPUSH")
+                .arg(work_conn.final_key("contract", "numbers".into()))
+                .arg((0..CONTRACT_DECODE_SAMPLE * 2).collect::<Vec<_>>())
+                .query_async::<_, i64>(work_conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?;
+
+            let contract = RedisContract::builder()
+                .require_key::<RedisJson<ExampleJson>>("contract", "config")
+                .require_key::<i64>("contract", "count")
+                .require_hash_fields("contract", "settings", ["a", "b"])
+                .require_key::<Vec<i64>>("contract", "numbers")
+                .optional_key::<i64>("contract", "not_there")
+                .require_script(&GOOD_SCRIPT)
+                .build();
+            assert_eq!(
+                contract.check(&mut work_conn).await,
+                ContractReport {
+                    satisfied: true,
+                    failures: vec![],
+                }
+            );
+            contract.enforce(&mut work_conn).await?;
+
+            // Break everything:
+            work_conn
+                .batch()
+                .clear("contract", ["count"])
+                .set("contract", "config", "not json", None)
+                .set("contract", "not_there", "not a number", None)
+                .fire()
+                .await;
+            redis::cmd("HDEL")
+                .arg(&settings_key)
+                .arg("b")
+                .query_async::<_, i64>(work_conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?;
+            let broken = RedisContract::builder()
+                .require_key::<RedisJson<ExampleJson>>("contract", "config")
+                .require_key::<i64>("contract", "count")
+                .require_hash_fields("contract", "settings", ["a", "b"])
+                .require_hash_fields("contract", "config", ["a"])
+                .optional_key::<i64>("contract", "not_there")
+                .require_script(&GOOD_SCRIPT)
+                .require_script(&BAD_SCRIPT)
+                .build();
+            let report = broken.check(&mut work_conn).await;
+            assert!(!report.satisfied);
+            let config_key = work_conn.final_key("contract", "config".into());
+            let not_there_key = work_conn.final_key("contract", "not_there".into());
+            assert_eq!(report.failures.len(), 6, "{:?}", report.failures);
+            assert!(matches!(
+                &report.failures[0],
+                ContractFailure::Undecodable { key, type_name, .. } if key == &config_key && type_name.contains("ExampleJson")
+            ));
+            assert_eq!(
+                report.failures[1],
+                ContractFailure::MissingKey {
+                    key: work_conn.final_key("contract", "count".into())
+                }
+            );
+            assert_eq!(
+                report.failures[2],
+                ContractFailure::MissingHashFields {
+                    key: settings_key.clone(),
+                    fields: vec!["b".to_string()]
+                }
+            );
+            assert_eq!(
+                report.failures[3],
+                ContractFailure::WrongType {
+                    key: config_key.clone(),
+                    expected: "hash",
+                    found: "string".to_string()
+                }
+            );
+            assert!(matches!(
+                &report.failures[4],
+                ContractFailure::Undecodable { key, .. } if key == &not_there_key
+            ));
+            assert!(matches!(
+                &report.failures[5],
+                ContractFailure::ScriptLoadFailed { .. }
+            ));
+
+            // Enforcing lists every failure in the one report:
+            let err = assert_err_contains(broken.enforce(&mut work_conn).await, "6 failures");
+            let formatted = format!("{:?}", err);
+            for failure in &report.failures {
+                assert!(formatted.contains(&failure.to_string()), "{}", formatted);
+            }
+
+            // Redis down:
+            assert_eq!(
+                contract.check(&mut fail_conn).await,
+                ContractReport {
+                    satisfied: false,
+                    failures: vec![ContractFailure::Unavailable],
+                }
+            );
+        }
+
+        // <--- Feature flags:
+        {
+            use crate::misc::{FeatureFlag, FeatureFlags};
+
+            // On a separate wrapper, only announcements will reach the listener before the test ends:
+            let other_r = Redis::new(format!("redis://localhost:{}", rs.port), work_r.prefix())?;
+            let writer =
+                FeatureFlags::new(work_r.clone(), "flags", Duration::from_secs(3600)).await;
+            let listener =
+                FeatureFlags::new(other_r.clone(), "flags", Duration::from_secs(3600)).await;
+            let poller =
+                FeatureFlags::new(other_r.clone(), "flags", Duration::from_millis(50)).await;
+            assert!(!listener.enabled("new_ui"));
+
+            writer.set_flag("new_ui", FeatureFlag::new(true)).await?;
+            writer
+                .set_flag(
+                    "half",
+                    FeatureFlag::rollout(50.0)
+                        .with_variant(1, serde_json::json!("a"))
+                        .with_variant(1, serde_json::json!("b")),
+                )
+                .await?;
+            // Writer sees straight away, the other wrapper via the announcement:
+            assert!(writer.enabled("new_ui"));
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert!(listener.enabled("new_ui"));
+
+            // Rollouts are deterministic and roughly match the percentage:
+            let mut on = 0;
+            for i in 0..1000 {
+                let subject = format!("user_{}", i);
+                let enabled = listener.enabled_for("half", &subject);
+                assert_eq!(enabled, writer.enabled_for("half", &subject));
+                if enabled {
+                    on += 1;
+                    assert!(listener.variant::<String>("half", &subject).is_some());
+                } else {
+                    assert_eq!(listener.variant::<String>("half", &subject), None);
+                }
+            }
+            assert!((400..600).contains(&on), "{}", on);
+
+            // Removals are announced too:
+            writer.remove_flag("half").await?;
+            assert!(!writer.enabled_for("half", "user_1"));
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert!(!listener.enabled_for("half", "user_1"));
+
+            // A change without an announcement (i.e. missed) is still picked up by polling:
+            work_conn
+                .batch()
+                .hset_multi(
+                    "flags",
+                    "feature_flags",
+                    None,
+                    [("silent", r#"{"enabled":true}"#)],
+                )
+                .fire()
+                .await;
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            assert!(poller.enabled("silent"));
+            assert!(!listener.enabled("silent"));
+            listener.refresh().await;
+            assert!(listener.enabled("silent"));
+
+            // A failed write isn't applied locally:
+            let down = FeatureFlags::new(fail_r.clone(), "flags", Duration::from_millis(1)).await;
+            assert!(!down.enabled("new_ui"));
+            assert!(down
+                .set_flag("new_ui", FeatureFlag::new(true))
+                .await
+                .is_err());
+            assert!(!down.enabled("new_ui"));
+
+            // Redis going down should fall back to the last snapshot, through polls and forced refreshes:
+            let doomed_rs = RedisStandalone::new().await?;
+            let doomed =
+                FeatureFlags::new(doomed_rs.instance()?, "flags", Duration::from_millis(10)).await;
+            doomed.set_flag("new_ui", FeatureFlag::new(true)).await?;
+            drop(doomed_rs);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(doomed.enabled("new_ui"));
+            doomed.refresh().await;
+            assert!(doomed.enabled("new_ui"));
+            assert!(doomed
+                .set_flag("new_ui", FeatureFlag::new(false))
+                .await
+                .is_err());
+            assert!(doomed.enabled("new_ui"));
+        }
+
+        // <--- Maintenance flag:
+        {
+            let other_r = Redis::new(format!("redis://localhost:{}", rs.port), work_r.prefix())?;
+            let slow_poll = MaintenanceFlagOpts {
+                poll_every: Duration::from_secs(3600),
+                ..Default::default()
+            };
+            let setter = MaintenanceFlag::new(work_r.clone(), "maint").await;
+            // On a separate wrapper, only announcements will reach it before the test ends:
+            let listener =
+                MaintenanceFlag::new_with_opts(other_r.clone(), "maint", slow_poll.clone()).await;
+            let poller = MaintenanceFlag::new_with_opts(
+                other_r.clone(),
+                "maint",
+                MaintenanceFlagOpts {
+                    poll_every: Duration::from_millis(50),
+                    ..Default::default()
+                },
+            )
+            .await;
+            assert_eq!(listener.current(), MaintenanceMode::Off);
+            assert_eq!(listener.since(), None);
+            assert_eq!(listener.reason(), None);
+
+            // Setter sees straight away, others via the announcement:
+            setter
+                .set(&mut work_conn, MaintenanceMode::ReadOnly, "migrating", None)
+                .await?;
+            assert_eq!(setter.current(), MaintenanceMode::ReadOnly);
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert_eq!(listener.current(), MaintenanceMode::ReadOnly);
+            assert_eq!(listener.reason().as_deref(), Some("migrating"));
+            assert!(listener
+                .since()
+                .is_some_and(|since| since <= chrono::Utc::now()));
+
+            // A change without an announcement (i.e. missed) is still picked up by polling:
+            work_conn
+                .batch()
+                .set(
+                    "maint",
+                    "maintenance",
+                    r#"{"mode":"Full","reason":"silent","since_ms":0,"expires_at_ms":null}"#,
+                    None,
+                )
+                .fire()
+                .await;
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            assert_eq!(poller.current(), MaintenanceMode::Full);
+            assert_eq!(poller.reason().as_deref(), Some("silent"));
+            assert_eq!(listener.current(), MaintenanceMode::ReadOnly);
+
+            // Ttl reverts to off everywhere, the slow poller included:
+            setter
+                .set(
+                    &mut work_conn,
+                    MaintenanceMode::Full,
+                    "quick fix",
+                    Some(chrono::TimeDelta::milliseconds(100)),
+                )
+                .await?;
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            for flag in [&setter, &listener, &poller] {
+                assert_eq!(flag.current(), MaintenanceMode::Full);
+            }
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            for flag in [&setter, &listener, &poller] {
+                assert_eq!(flag.current(), MaintenanceMode::Off);
+                assert_eq!(flag.reason(), None);
+            }
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<String>("maint", "maintenance")
+                    .fire()
+                    .await,
+                Some(None)
+            );
+            assert!(setter
+                .set(
+                    &mut work_conn,
+                    MaintenanceMode::Full,
+                    "",
+                    Some(chrono::TimeDelta::zero())
+                )
+                .await
+                .is_err());
+
+            // Reads never go to redis, only the initial read happened:
+            for _ in 0..10_000 {
+                assert_eq!(listener.current(), MaintenanceMode::Off);
+            }
+            assert_eq!(listener.poll_count(), 1);
+
+            // Redis down, fail-open by default, closed if configured:
+            let open = MaintenanceFlag::new(fail_r.clone(), "maint").await;
+            assert_eq!(open.current(), MaintenanceMode::Off);
+            assert!(open
+                .set(&mut fail_conn, MaintenanceMode::Full, "", None)
+                .await
+                .is_err());
+            let closed = MaintenanceFlag::new_with_opts(
+                fail_r.clone(),
+                "maint",
+                MaintenanceFlagOpts {
+                    unreachable_mode: MaintenanceMode::ReadOnly,
+                    ..slow_poll
+                },
+            )
+            .await;
+            assert_eq!(closed.current(), MaintenanceMode::ReadOnly);
+            assert_eq!(closed.reason(), None);
+        }
+
+        // <--- Namespace usage:
+        {
+            // More keys than a single SCAN page in the small one, fewer but bigger values in the other:
+            work_conn
+                .batch()
+                .mset(
+                    "usage_small",
+                    (0..1200).map(|i| (format!("k{}", i), "x".repeat(10))),
+                    None,
+                )
+                .mset(
+                    "usage_big",
+                    (0..50).map(|i| (format!("k{}", i), "x".repeat(2000))),
+                    None,
+                )
+                .fire()
+                .await
+                .unwrap();
+            // Glob chars in the namespace shouldn't match other namespaces:
+            work_conn
+                .batch()
+                .set("usage_[g]lob*", "k", "v", None)
+                .set("usage_glob", "k", "v", None)
+                .fire()
+                .await
+                .unwrap();
+
+            let usages = work_conn
+                .namespace_usage(
+                    &["usage_small", "usage_big", "usage_empty", "usage_[g]lob*"],
+                    20,
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                usages
+                    .iter()
+                    .map(|u| (u.namespace.as_str(), u.approx_keys))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("usage_small", 1200),
+                    ("usage_big", 50),
+                    ("usage_empty", 0),
+                    ("usage_[g]lob*", 1)
+                ]
+            );
+            let (small, big) = (&usages[0], &usages[1]);
+            assert!(big.sampled_avg_bytes > small.sampled_avg_bytes);
+            // Allocator overhead makes MEMORY USAGE bigger than the raw value, but within reason:
+            assert!(
+                (2000.0..6000.0).contains(&big.sampled_avg_bytes),
+                "{}",
+                big.sampled_avg_bytes
+            );
+            assert_eq!(
+                big.approx_total_bytes,
+                (big.sampled_avg_bytes * 50.0).round() as u64
+            );
+            assert_eq!(
+                usages[2],
+                NamespaceUsage {
+                    namespace: "usage_empty".to_string(),
+                    approx_keys: 0,
+                    sampled_avg_bytes: 0.0,
+                    approx_total_bytes: 0,
+                }
+            );
+
+            // Redis down:
+            assert_eq!(fail_conn.namespace_usage(&["usage_small"], 20).await, None);
+        }
+
+        // <--- Scan namespace:
+        {
+            use futures::StreamExt;
+
+            work_conn
+                .batch()
+                .mset(
+                    "scan_a",
+                    (0..300).map(|i| (format!("user_{}", i), "v")),
+                    None,
+                )
+                .mset(
+                    "scan_b",
+                    (0..200).map(|i| (format!("user_{}", i), "v")),
+                    None,
+                )
+                // Glob chars in the namespace shouldn't match other namespaces:
+                .set("scan_[a]*", "user_0", "v", None)
+                .set("scan_a", "order_0", "v", None)
+                .fire()
+                .await
+                .unwrap();
+
+            let scanned = work_conn
+                .scan_namespace("scan_a", None, 50)
+                .collect::<std::collections::HashSet<_>>()
+                .await;
+            assert_eq!(
+                scanned,
+                (0..300)
+                    .map(|i| format!("user_{}", i))
+                    .chain(["order_0".to_string()])
+                    .collect()
+            );
+            let scanned = work_conn
+                .scan_namespace("scan_b", Some("user_1*"), 1000)
+                .collect::<std::collections::HashSet<_>>()
+                .await;
+            assert_eq!(
+                scanned,
+                (0..200)
+                    .map(|i| format!("user_{}", i))
+                    .filter(|key| key.starts_with("user_1"))
+                    .collect()
+            );
+            assert_eq!(work_conn.count_namespace("scan_a").await, 301);
+            assert_eq!(work_conn.count_namespace("scan_[a]*").await, 1);
+            assert_eq!(work_conn.count_namespace("scan_empty").await, 0);
+
+            // Wildcards in the pattern can't reach keys under other prefixes:
+            let other_r = rs.instance()?;
+            let mut other_conn = other_r.conn();
+            assert_eq!(
+                other_conn
+                    .scan_namespace("scan_a", Some("*"), 1000)
+                    .count()
+                    .await,
+                0
+            );
+            assert_eq!(
+                other_conn
+                    .scan_namespace("*", Some("*"), 1000)
+                    .count()
+                    .await,
+                0
+            );
+
+            // Redis down, ends after retrying:
+            assert_eq!(fail_conn.count_namespace("scan_a").await, 0);
+        }
+
+        // <--- Streamed json:
+        {
+            // A few MB, uploaded in many small chunks:
+            let value = (0..60_000)
+                .map(|i| format!("item_{}_{}", i, "x".repeat(30)))
+                .collect::<Vec<_>>();
+            work_conn
+                .set_json_streamed(
+                    "streamed",
+                    "big",
+                    value.clone(),
+                    Some(Duration::from_secs(30)),
+                    64 * 1024,
+                )
+                .await?;
+            assert_eq!(
+                work_conn
+                    .get_json_streamed::<Vec<String>>("streamed", "big", 64 * 1024)
+                    .await
+                    .as_ref(),
+                Some(&value)
+            );
+            // Identical to the non-streamed path:
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<Vec<u8>>("streamed", "big")
+                    .fire()
+                    .await
+                    .flatten(),
+                Some(serde_json::to_vec(&value).change_context(AnyErr)?)
+            );
+            assert_eq!(
+                work_conn
+                    .batch()
+                    .get::<RedisJson<Vec<String>>>("streamed", "big")
+                    .fire()
+                    .await
+                    .flatten()
+                    .map(|json| json.0)
+                    .as_ref(),
+                Some(&value)
+            );
+            async fn pttl(conn: &mut RedisConn<'_>, key: &str) -> i64 {
+                let key = conn.final_key("streamed", key.into());
+                redis::cmd("PTTL")
+                    .arg(key)
+                    .query_async::<_, i64>(conn.get_inner_conn().await.unwrap())
+                    .await
+                    .unwrap()
+            }
+            let ttl = pttl(&mut work_conn, "big").await;
+            assert!(ttl > 0 && ttl <= 30_000, "{}", ttl);
+            // Replacing without an expiry persists it:
+            work_conn
+                .set_json_streamed("streamed", "big", vec!["small"], None, 4)
+                .await?;
+            assert_eq!(
+                work_conn
+                    .get_json_streamed::<Vec<String>>("streamed", "big", 4)
+                    .await,
+                Some(vec!["small".to_string()])
+            );
+            assert_eq!(pttl(&mut work_conn, "big").await, -1);
+            // No temporary keys left behind:
+            assert_eq!(
+                work_conn.namespace_usage(&["streamed"], 0).await.unwrap()[0].approx_keys,
+                1
+            );
+
+            // Dropped mid-upload, the temporary key is removed and nothing is written:
+            assert!(tokio::time::timeout(
+                Duration::from_millis(20),
+                work_conn.set_json_streamed("streamed", "aborted", value.clone(), None, 1024),
+            )
+            .await
+            .is_err());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(
+                work_conn.namespace_usage(&["streamed"], 0).await.unwrap()[0].approx_keys,
+                1
+            );
+            assert_eq!(
+                work_conn
+                    .get_json_streamed::<Vec<String>>("streamed", "aborted", 1024)
+                    .await,
+                None
+            );
+
+            assert!(work_conn
+                .set_json_streamed("streamed", "big", vec!["small"], None, 0)
+                .await
+                .is_err());
+            // Redis down:
+            assert!(fail_conn
+                .set_json_streamed("streamed", "big", vec!["small"], None, 4)
+                .await
+                .is_err());
+            assert_eq!(
+                fail_conn
+                    .get_json_streamed::<Vec<String>>("streamed", "big", 4)
+                    .await,
+                None
+            );
+        }
+
+        // Run the dlock tests:
+        redis_dlock_tests(&work_r, &fail_r).await?;
+
+        // Run the session tests:
+        #[cfg(feature = "cookies_ssr")]
+        session::redis_session_tests(&work_r).await?;
+
+        Ok(())
+    }
+
+    /// The temp_list tests, with a fresh redis per storage layout.
+    #[rstest]
+    #[case(TempListStorage::IndividualKeys)]
+    #[case(TempListStorage::Hash)]
+    #[tokio::test]
+    async fn test_redis_temp_list(
+        #[allow(unused_variables)] logging: (),
+        #[case] storage: TempListStorage,
+    ) -> RResult<(), AnyErr> {
+        // Redis can't be run on windows, skip if so:
+        if cfg!(windows) {
+            return Ok(());
+        }
+
+        let rs = RedisStandalone::new().await?;
+        redis_temp_list_tests(&rs.instance()?, storage).await?;
+        Ok(())
+    }
+
+    /// Test sharding keys across multiple standalone redis servers.
+    #[rstest]
+    #[tokio::test]
+    async fn test_redis_shard_set(#[allow(unused_variables)] logging: ()) -> RResult<(), AnyErr> {
+        // Redis can't be run on windows, skip if so:
+        if cfg!(windows) {
+            return Ok(());
+        }
+
+        let servers = [
+            RedisStandalone::new().await?,
+            RedisStandalone::new().await?,
+            RedisStandalone::new().await?,
+        ];
+        let shards = RedisShardSet::new(
+            servers
+                .iter()
+                .map(|rs| rs.instance())
+                .collect::<RResult<Vec<_>, _>>()?,
+            100,
+        )?;
+
+        let info = shards.ring_info();
+        assert_eq!(info.len(), 3);
+        assert!(info.iter().all(|i| i.points == 100));
+        assert!((info.iter().map(|i| i.ownership).sum::<f64>() - 1.0).abs() < 0.001);
+
+        // Set each key on its owner:
+        let keys = (0..60).map(|i| format!("key_{}", i)).collect::<Vec<_>>();
+        for key in &keys {
+            shards
+                .conn_for("ns", key)
+                .batch()
+                .set("ns", key, format!("val_{}", key), None)
+                .fire()
+                .await;
+        }
+
+        // Keys should have been spread across all servers:
+        for instance in shards.instances() {
+            assert!(keys
+                .iter()
+                .any(|key| shards.instance_for("ns", key).server() == instance.server()));
+        }
+
+        // Sharded mget should match individual gets, in input order:
+        let mut individual = vec![];
+        for key in &keys {
+            individual.push(
+                shards
+                    .conn_for("ns", key)
+                    .batch()
+                    .get::<String>("ns", key)
+                    .fire()
+                    .await,
+            );
+        }
+        let sharded = shards
+            .batch_sharded("ns", keys.iter(), |mut conn, keys| async move {
+                conn.batch().mget::<String>("ns", keys).fire().await
+            })
+            .await;
+        assert_eq!(sharded, individual);
+        for (key, val) in keys.iter().zip(&sharded) {
+            assert_eq!(val, &Some(Some(format!("val_{}", key))));
+        }
+
+        // One server down should only null its share:
+        let down = shards.instances()[1].server().to_string();
+        let [rs_0, rs_1, rs_2] = servers;
+        drop(rs_1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let sharded = shards
+            .batch_sharded("ns", keys.iter(), |mut conn, keys| async move {
+                conn.batch().mget::<String>("ns", keys).fire().await
+            })
+            .await;
+        for (key, val) in keys.iter().zip(sharded) {
+            if shards.instance_for("ns", key).server() == down {
+                assert_eq!(val, None);
+            } else {
+                assert_eq!(val, Some(Some(format!("val_{}", key))));
+            }
+        }
+        drop((rs_0, rs_2));
+
+        Ok(())
+    }
+
+    /// Every major api should short-circuit instantly on a disabled wrapper, logging nothing but the construction INFO.
+    #[rstest]
+    fn test_redis_disabled() -> RResult<(), AnyErr> {
+        static LOGS: once_cell::sync::Lazy<parking_lot::Mutex<Vec<String>>> =
+            once_cell::sync::Lazy::new(Default::default);
+        LOGS.lock().clear();
+
+        let log = GlobalLog::builder()
+            .quiet_init()
+            .custom(false, false, false, false, |log| {
+                LOGS.lock()
+                    .push(String::from_utf8_lossy(log).trim().to_string());
+            })
+            .level_from(tracing::Level::DEBUG)?
+            .build()?;
+
+        log.with_tmp_global(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .change_context(AnyErr)?
+                .block_on(async {
+                    let started = std::time::Instant::now();
+                    let r = Redis::new_disabled("disabled_test")?;
+                    assert!(r.is_disabled());
+                    let mut conn = r.conn();
+                    assert!(conn.is_disabled());
+                    assert!(!conn.ping().await);
+
+                    // Batch:
+                    assert_eq!(
+                        conn.batch()
+                            .set("n1", "foo", "bar", None)
+                            .get::<String>("n1", "foo")
+                            .fire()
+                            .await,
+                        None
+                    );
+
+                    // Cached fn, computing every time:
+                    let calls = AtomicU8::new(0);
+                    for _ in 0..2 {
+                        let val = conn
+                            .cached_fn_with_opts(
+                                "n1",
+                                "cached",
+                                None,
+                                CacheOpts {
+                                    compute_lock: Some(chrono::TimeDelta::seconds(5)),
+                                    ..Default::default()
+                                },
+                                || async {
+                                    calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                    Ok("computed".to_string())
+                                },
+                            )
+                            .await?;
+                        assert_eq!(val, "computed");
+                    }
+                    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+                    // Temp list:
+                    let li = r.templist("n1", "li", Duration::from_secs(5), Duration::from_secs(5));
+                    let item = li.push(&mut conn, "foo".to_string()).await;
+                    assert_eq!(item.uid(), None);
+                    assert!(li.read_multi::<String>(&mut conn, None).await.is_empty());
+
+                    // Pubsub, subscribing works but never receives:
+                    conn.publish_topic::<UserUpdatedTopic>(&UserUpdated { id: 1 })
+                        .await;
+                    let mut listener = r.subscribe_topic::<UserUpdatedTopic>().await.unwrap();
+                    assert!(
+                        tokio::time::timeout(Duration::from_millis(20), listener.recv())
+                            .await
+                            .is_err()
+                    );
+
+                    // Locks always acquire by default:
+                    let mut lock = r
+                        .dlock("n1", "lock", Duration::from_secs(1), None)
+                        .await
+                        .change_context(AnyErr)?;
+                    assert!(lock.expires_at > chrono::Utc::now());
+                    lock.extend(chrono::TimeDelta::seconds(2))
+                        .await
+                        .change_context(AnyErr)?;
+                    assert!(lock.is_still_held().await);
+                    assert!(lock.unlock().await);
+                    assert_eq!(
+                        r.dlock_for_fut("n1", "lock", None, async { Ok(3) })
+                            .await
+                            .change_context(AnyErr)?,
+                        3
+                    );
+
+                    // Or always fail when configured:
+                    let r_fail = Redis::new_disabled_with_locks(
+                        "disabled_test_fail",
+                        RedisDisabledLocks::Fail,
+                    )?;
+                    let locked = r_fail
+                        .dlock(
+                            "n1",
+                            "lock",
+                            Duration::from_secs(1),
+                            Some(Duration::from_secs(5)),
+                        )
+                        .await;
+                    assert!(matches!(
+                        locked.as_ref().map_err(|e| e.current_context()),
+                        Err(RedisLockErr::Unavailable { .. })
+                    ));
+
+                    // No connection attempts or retries, so everything is near instant:
+                    assert!(
+                        started.elapsed() < Duration::from_millis(500),
+                        "{:?}",
+                        started.elapsed()
+                    );
+                    Ok::<_, error_stack::Report<AnyErr>>(())
+                })
+        })??;
+
+        // Only the construction logs, no errors or exceptions:
+        let logs = LOGS.lock().clone();
+        assert_eq!(logs.len(), 2, "{:?}", logs);
+        assert!(
+            logs.iter()
+                .all(|log| log.contains("Redis disabled for prefix")),
+            "{:?}",
+            logs
+        );
+
+        Ok(())
+    }
+
+    /// Batches over the threshold should be recorded with their details and logged, fast ones ignored.
+    #[rstest]
+    fn test_redis_slow_batch_log() -> RResult<(), AnyErr> {
+        // Redis can't be run on windows, skip if so:
+        if cfg!(windows) {
+            return Ok(());
+        }
+
+        static LOGS: once_cell::sync::Lazy<parking_lot::Mutex<Vec<String>>> =
+            once_cell::sync::Lazy::new(Default::default);
+        LOGS.lock().clear();
+
+        // Busy waits for ARGV[1] ms, redis lua has no sleep:
+        static SLOW_SCRIPT: once_cell::sync::Lazy<RedisScript> = once_cell::sync::Lazy::new(|| {
+            RedisScript::new(
+                r#"
+                local function now_ms()
+                    local time = redis.call('TIME')
+                    return tonumber(time[1]) * 1000 + tonumber(time[2]) / 1000
+                end
+                local start = now_ms()
+                while now_ms() - start < tonumber(ARGV[1]) do end
+                return 1
+                "#,
+            )
+        });
+
+        let log = GlobalLog::builder()
+            .custom(false, false, false, false, |log| {
+                LOGS.lock()
+                    .push(String::from_utf8_lossy(log).trim().to_string());
+            })
+            .level_from(tracing::Level::WARN)?
+            .build()?;
+
+        log.with_tmp_global(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .change_context(AnyErr)?
+                .block_on(async {
+                    let rs = RedisStandalone::new().await?;
+                    let r = rs
+                        .instance()?
+                        .slow_batch_log(chrono::TimeDelta::milliseconds(50), 2);
+                    let mut conn = r.conn();
+
+                    // Fast batches aren't recorded:
+                    conn.batch().set("fast", "foo", "bar", None).fire().await;
+                    assert_eq!(
+                        conn.batch().get::<String>("fast", "foo").fire().await,
+                        Some(Some("bar".into()))
+                    );
+                    assert!(r.slow_batches().is_empty());
+
+                    // First use of the script needs loading to redis, so also retried:
+                    let slow_key = conn.final_key("slow", "foo".into());
+                    let started = std::time::Instant::now();
+                    let fire_line = line!() + 5;
+                    conn.batch()
+                        .set("slow_ns", "foo", "bar", None)
+                        .set("slow_ns", "baz", "qux", None)
+                        .script::<i64>(SLOW_SCRIPT.invoker().key(&slow_key).arg(100))
+                        .fire()
+                        .await;
+                    let elapsed = started.elapsed();
+                    let entries = r.slow_batches();
+                    assert_eq!(entries.len(), 1, "{:?}", entries);
+                    let entry = &entries[0];
+                    assert!(
+                        entry.duration >= chrono::TimeDelta::milliseconds(100),
+                        "{:?}",
+                        entry
+                    );
+                    assert!(entry.duration.to_std().unwrap() <= elapsed, "{:?}", entry);
+                    assert_eq!(entry.summary, "SET x2, EVALSHA");
+                    assert_eq!(entry.namespaces, vec!["slow_ns", "slow"]);
+                    assert_eq!(entry.retries, 1);
+                    assert!(entry.scripts_reloaded);
+                    assert!(
+                        entry.caller.contains(&format!("mod.rs:{}:", fire_line)),
+                        "{:?}",
+                        entry
+                    );
+
+                    // Capacity evicts the oldest first:
+                    for ms in [60, 70] {
+                        conn.batch()
+                            .script::<i64>(SLOW_SCRIPT.invoker().arg(ms))
+                            .fire()
+                            .await;
+                    }
+                    let entries = r.slow_batches();
+                    assert_eq!(entries.len(), 2);
+                    assert!(entries
+                        .iter()
+                        .all(|entry| entry.summary == "EVALSHA" && !entry.scripts_reloaded));
+                    assert!(entries[0].duration < entries[1].duration);
+
+                    // Clones share the log, other wrappers don't have one:
+                    assert_eq!(r.clone().slow_batches().len(), 2);
+                    assert!(rs.instance()?.slow_batches().is_empty());
+                    Ok::<_, error_stack::Report<AnyErr>>(())
+                })
+        })??;
 
-        // Run the temp_list tests:
-        redis_temp_list_tests(&work_r).await?;
+        // Each slow batch logged as a warning:
+        let logs = LOGS.lock().clone();
+        let slow_logs = logs
+            .iter()
+            .filter(|log| log.contains("WARN") && log.contains("Slow redis batch"))
+            .collect::<Vec<_>>();
+        assert_eq!(slow_logs.len(), 3, "{:?}", logs);
+        assert!(slow_logs[0].contains("SET x2, EVALSHA"), "{:?}", slow_logs);
 
         Ok(())
     }
@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Redis, RedisBatchFire, RedisBatchReturningOps, RedisConn, RedisScript};
+use crate::errors::prelude::*;
+
+static SAVE_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/object_store_save.lua")));
+static PATCH_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/object_store_patch.lua")));
+
+/// (field, json encoded value) pairs, as stored in the hash.
+type Fields = Vec<(String, String)>;
+
+/// Stores structs as redis hashes, one hash field per top-level struct field, so subsets can be updated with [`RedisObjectStore::patch`].
+///
+/// - Field values are json encoded, so nested structs, lists and maps are stored as json in their field.
+/// - Loading goes through serde, so fields added to the struct since it was saved are handled by `#[serde(default)]`.
+/// - Every save or patch refreshes the ttl, if one is configured.
+///
+/// Cheap to clone, clones share the write-behind queue.
+pub struct RedisObjectStore<T> {
+    namespace: String,
+    ttl: Option<Duration>,
+    write_behind: Option<Arc<WriteBehind>>,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for RedisObjectStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            namespace: self.namespace.clone(),
+            ttl: self.ttl,
+            write_behind: self.write_behind.clone(),
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for RedisObjectStore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisObjectStore")
+            .field("namespace", &self.namespace)
+            .field("ttl", &self.ttl)
+            .field("write_behind", &self.write_behind.is_some())
+            .finish()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> RedisObjectStore<T> {
+    /// Create a new store.
+    ///
+    /// Arguments:
+    /// - `namespace`: The namespace objects are stored under.
+    /// - `ttl`: How long objects live after their last save or patch, None to keep them forever.
+    pub fn new(namespace: impl Into<String>, ttl: Option<Duration>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            ttl,
+            write_behind: None,
+            _t: PhantomData,
+        }
+    }
+
+    /// Queue saves in memory and write them in a single batch periodically, for objects with a high write rate.
+    ///
+    /// Saves to the same key between flushes are coalesced, only the latest is written.
+    /// Loads and patches see queued saves, so this process always reads its own writes.
+    ///
+    /// Durability tradeoff: queued saves are lost if the process dies before they're flushed,
+    /// and other processes won't see them until then. Call [`RedisObjectStore::flush`] before shutting down.
+    ///
+    /// Must be called from within a tokio runtime, the flushing task is stopped when the last clone of the store is dropped.
+    ///
+    /// Arguments:
+    /// - `redis`: The redis wrapper to flush with.
+    /// - `flush_every`: The max time a save is queued for.
+    /// - `max_pending`: Flush early once this many keys are queued.
+    pub fn with_write_behind(
+        mut self,
+        redis: &Redis,
+        flush_every: Duration,
+        max_pending: usize,
+    ) -> Self {
+        let queue = Arc::new(WriteBehindQueue {
+            redis: redis.clone(),
+            namespace: self.namespace.clone(),
+            ttl: self.ttl,
+            pending: Mutex::new(HashMap::new()),
+            notify: tokio::sync::Notify::new(),
+            flushes: AtomicUsize::new(0),
+        });
+        let task_queue = queue.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                // Either the interval passed or max_pending was hit:
+                let _ = tokio::time::timeout(flush_every, task_queue.notify.notified()).await;
+                task_queue.flush().await;
+            }
+        });
+        self.write_behind = Some(Arc::new(WriteBehind {
+            queue,
+            max_pending: max_pending.max(1),
+            task,
+        }));
+        self
+    }
+
+    /// Save an object, replacing any existing one at the key.
+    ///
+    /// Errors if the object doesn't serialize to a json object (e.g. it's a tuple or an enum).
+    /// Redis failures are logged by the batch, like any other write.
+    pub async fn save(
+        &self,
+        conn: &mut RedisConn<'_>,
+        key: &str,
+        value: &T,
+    ) -> RResult<(), AnyErr> {
+        let fields = encode_object(value)?;
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.queue(key, fields);
+            return Ok(());
+        }
+        let invoker = save_invoker(conn, &self.namespace, key, self.ttl, &fields);
+        conn.batch().script_no_return(invoker).fire().await;
+        Ok(())
+    }
+
+    /// Load an object, None if it doesn't exist, redis is unavailable, or it no longer decodes as `T` (logged).
+    pub async fn load(&self, conn: &mut RedisConn<'_>, key: &str) -> Option<T> {
+        let fields = match self
+            .write_behind
+            .as_ref()
+            .and_then(|write_behind| write_behind.queue.pending.lock().get(key).cloned())
+        {
+            Some(fields) => fields.into_iter().collect::<HashMap<_, _>>(),
+            None => conn.batch().hgetall(&self.namespace, key).fire().await?,
+        };
+        if fields.is_empty() {
+            return None;
+        }
+        match decode_object(fields) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::error!(
+                    "Failed to decode object at namespace '{}' key '{}': {:?}",
+                    self.namespace,
+                    key,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Update a subset of an existing object's fields, refreshing its ttl.
+    ///
+    /// Returns false if the object doesn't exist (nothing is written) or redis is unavailable.
+    /// Field names aren't checked against `T`, unknown fields are ignored on load unless `T` denies them.
+    pub async fn patch<V: Serialize>(
+        &self,
+        conn: &mut RedisConn<'_>,
+        key: &str,
+        fields: &[(&str, V)],
+    ) -> RResult<bool, AnyErr> {
+        let fields = fields
+            .iter()
+            .map(|(field, value)| Ok((field.to_string(), encode_field(value)?)))
+            .collect::<RResult<Fields, AnyErr>>()?;
+
+        // A queued save would overwrite the patch when flushed, so apply it to the queued save instead:
+        if let Some(write_behind) = &self.write_behind {
+            let mut pending = write_behind.queue.pending.lock();
+            if let Some(existing) = pending.get_mut(key) {
+                for (field, value) in fields {
+                    match existing.iter_mut().find(|(f, _)| *f == field) {
+                        Some(slot) => slot.1 = value,
+                        None => existing.push((field, value)),
+                    }
+                }
+                return Ok(true);
+            }
+        }
+
+        let invoker = PATCH_SCRIPT
+            .invoker()
+            .key(conn.final_key(&self.namespace, key.into()))
+            .arg(ttl_millis(self.ttl));
+        let invoker = fields.into_iter().fold(invoker, |invoker, (field, value)| {
+            invoker.arg(field).arg(value)
+        });
+        Ok(conn
+            .batch()
+            .script::<bool>(invoker)
+            .fire()
+            .await
+            .unwrap_or(false))
+    }
+
+    /// Write all queued saves now, a no-op when write-behind isn't enabled.
+    pub async fn flush(&self) {
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.queue.flush().await;
+        }
+    }
+
+    /// The number of write-behind batches sent to redis so far.
+    #[cfg(test)]
+    pub(crate) fn flush_count(&self) -> usize {
+        self.write_behind
+            .as_ref()
+            .map(|write_behind| write_behind.queue.flushes.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+}
+
+struct WriteBehind {
+    queue: Arc<WriteBehindQueue>,
+    max_pending: usize,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl WriteBehind {
+    fn queue(&self, key: &str, fields: Fields) {
+        let len = {
+            let mut pending = self.queue.pending.lock();
+            pending.insert(key.to_string(), fields);
+            pending.len()
+        };
+        if len >= self.max_pending {
+            self.queue.notify.notify_one();
+        }
+    }
+}
+
+impl Drop for WriteBehind {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct WriteBehindQueue {
+    redis: Redis,
+    namespace: String,
+    ttl: Option<Duration>,
+    /// The latest queued save for each key.
+    pending: Mutex<HashMap<String, Fields>>,
+    notify: tokio::sync::Notify,
+    flushes: AtomicUsize,
+}
+
+impl WriteBehindQueue {
+    async fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock());
+        if pending.is_empty() {
+            return;
+        }
+
+        let mut conn = self.redis.conn();
+        let invokers = pending
+            .iter()
+            .map(|(key, fields)| save_invoker(&conn, &self.namespace, key, self.ttl, fields))
+            .collect::<Vec<_>>();
+        let batch = invokers.into_iter().fold(conn.batch(), |batch, invoker| {
+            batch.script_no_return(invoker)
+        });
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        if batch.fire().await.is_none() {
+            // Redis unavailable, requeue for the next flush, unless saved again since:
+            let mut current = self.pending.lock();
+            for (key, fields) in pending {
+                current.entry(key).or_insert(fields);
+            }
+        }
+    }
+}
+
+fn save_invoker<'c>(
+    conn: &RedisConn<'_>,
+    namespace: &str,
+    key: &str,
+    ttl: Option<Duration>,
+    fields: &Fields,
+) -> super::RedisScriptInvoker<'c> {
+    let invoker = SAVE_SCRIPT
+        .invoker()
+        .key(conn.final_key(namespace, key.into()))
+        .arg(ttl_millis(ttl));
+    fields.iter().fold(invoker, |invoker, (field, value)| {
+        invoker.arg(field).arg(value)
+    })
+}
+
+fn ttl_millis(ttl: Option<Duration>) -> u64 {
+    ttl.map(|ttl| ttl.as_millis() as u64).unwrap_or(0)
+}
+
+fn encode_field<V: Serialize + ?Sized>(value: &V) -> RResult<String, AnyErr> {
+    serde_json::to_string(value).change_context(AnyErr)
+}
+
+fn encode_object<T: Serialize>(value: &T) -> RResult<Fields, AnyErr> {
+    match serde_json::to_value(value).change_context(AnyErr)? {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(field, value)| Ok((field, encode_field(&value)?)))
+            .collect(),
+        other => Err(anyerr!(
+            "Objects must serialize to a json object to be stored as a hash, got: {}",
+            other
+        )),
+    }
+}
+
+fn decode_object<T: DeserializeOwned>(fields: HashMap<String, String>) -> RResult<T, AnyErr> {
+    let map = fields
+        .into_iter()
+        .map(|(field, value)| {
+            // Fields written by something else might not be json encoded, treat as a plain string:
+            let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+            (field, value)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::from_value(serde_json::Value::Object(map)).change_context(AnyErr)
+}
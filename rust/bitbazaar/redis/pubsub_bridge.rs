@@ -0,0 +1,343 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Weak},
+};
+
+use futures::Stream;
+use parking_lot::Mutex;
+use tokio::sync::broadcast;
+
+use super::Redis;
+
+/// The default for [`RedisPubSubBridge::with_ring_size`].
+pub const DEFAULT_BRIDGE_RING_SIZE: usize = 100;
+
+/// The default for [`RedisPubSubBridge::with_poll_linger`].
+pub const DEFAULT_BRIDGE_POLL_LINGER: std::time::Duration = std::time::Duration::from_secs(30);
+
+const RESYNC_FRAME: &str = "event: resync\ndata: {}\n\n";
+
+/// The result of a [`RedisPubSubBridge::poll`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PollResult {
+    /// Events since the given cursor, oldest first.
+    pub events: Vec<serde_json::Value>,
+    /// The cursor to pass to the next poll.
+    pub next_cursor: String,
+    /// Events were missed (the cursor was too old for the replay ring, or from another process),
+    /// the client should reload its full state rather than relying on events.
+    pub resync: bool,
+}
+
+/// Delivers redis pubsub messages to clients that can't hold a redis connection (e.g. wasm frontends),
+/// via long-polling ([`RedisPubSubBridge::poll`]) or server sent events ([`RedisPubSubBridge::subscribe_sse`]).
+///
+/// Framework agnostic, hook the methods up to whichever http endpoints are needed.
+///
+/// - Each channel has a single redis subscription shared by all clients of the bridge, created on first use,
+///   and dropped once the last client has gone (long-polling clients are kept for [`RedisPubSubBridge::with_poll_linger`] after each poll).
+/// - The most recent events of each channel are kept in a replay ring, so clients reconnecting with a recent cursor don't miss anything.
+/// - Payloads are json, as published by [`super::RedisConn::publish_topic`].
+///
+/// Cheap to clone, clones share the same subscriptions.
+#[derive(Clone)]
+pub struct RedisPubSubBridge {
+    redis: Redis,
+    ring_size: usize,
+    poll_linger: std::time::Duration,
+    hubs: Arc<HubMap>,
+}
+
+/// Hubs are only held weakly, they're kept alive by their clients.
+type HubMap = Mutex<HashMap<String, Weak<ChannelHub>>>;
+
+impl std::fmt::Debug for RedisPubSubBridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisPubSubBridge")
+            .field("ring_size", &self.ring_size)
+            .field("poll_linger", &self.poll_linger)
+            .field("channels", &self.hubs.lock().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl RedisPubSubBridge {
+    /// Create a new bridge for the given redis wrapper.
+    pub fn new(redis: Redis) -> Self {
+        Self {
+            redis,
+            ring_size: DEFAULT_BRIDGE_RING_SIZE,
+            poll_linger: DEFAULT_BRIDGE_POLL_LINGER,
+            hubs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The number of recent events kept per channel for replaying to reconnecting clients, defaults to [`DEFAULT_BRIDGE_RING_SIZE`].
+    pub fn with_ring_size(mut self, ring_size: usize) -> Self {
+        self.ring_size = ring_size.max(1);
+        self
+    }
+
+    /// How long a channel's subscription (and replay ring) is kept after a poll when no other clients are using it,
+    /// so the client's next poll doesn't miss events, defaults to [`DEFAULT_BRIDGE_POLL_LINGER`].
+    pub fn with_poll_linger(mut self, poll_linger: chrono::TimeDelta) -> Self {
+        self.poll_linger = poll_linger.to_std().unwrap_or_default();
+        self
+    }
+
+    /// Long-poll a channel for events.
+    ///
+    /// Returns straight away if there are events since the cursor, otherwise waits up to `wait` for the next one.
+    ///
+    /// Arguments:
+    /// - `namespace`: The namespace of the channel.
+    /// - `channel`: The channel to receive from.
+    /// - `cursor`: The `next_cursor` from the client's last poll, `None` to only receive events from now on.
+    /// - `wait`: How long to wait for an event before returning empty.
+    pub async fn poll(
+        &self,
+        namespace: &str,
+        channel: &str,
+        cursor: Option<String>,
+        wait: chrono::TimeDelta,
+    ) -> PollResult {
+        let wait = wait.to_std().unwrap_or_default();
+        let Some(hub) = self.hub(namespace, channel).await else {
+            // Redis unavailable, still wait so clients don't poll in a hot loop:
+            tokio::time::sleep(wait).await;
+            return PollResult {
+                events: vec![],
+                next_cursor: cursor.unwrap_or_default(),
+                resync: false,
+            };
+        };
+
+        let result = Self::poll_hub(&hub, cursor, wait).await;
+        if !self.poll_linger.is_zero() {
+            let linger = self.poll_linger;
+            tokio::spawn(async move {
+                tokio::time::sleep(linger).await;
+                drop(hub);
+            });
+        }
+        result
+    }
+
+    async fn poll_hub(
+        hub: &ChannelHub,
+        cursor: Option<String>,
+        wait: std::time::Duration,
+    ) -> PollResult {
+        let (mut receiver, from_seq) = {
+            let state = hub.state.lock();
+            let from_seq = match cursor.as_deref().map(|cursor| state.parse_cursor(cursor)) {
+                None => state.next_seq,
+                Some(Some(seq)) => seq,
+                Some(None) => return state.resync(),
+            };
+            match state.events_since(from_seq) {
+                Some(events) if !events.is_empty() => {
+                    return PollResult {
+                        events,
+                        next_cursor: state.cursor(state.next_seq),
+                        resync: false,
+                    }
+                }
+                Some(_) => {}
+                None => return state.resync(),
+            }
+            // Subscribed whilst locked, so nothing can land between checking and waiting:
+            (hub.sender.subscribe(), from_seq)
+        };
+
+        let _ = tokio::time::timeout(wait, receiver.recv()).await;
+        let state = hub.state.lock();
+        match state.events_since(from_seq) {
+            Some(events) => PollResult {
+                events,
+                next_cursor: state.cursor(state.next_seq),
+                resync: false,
+            },
+            None => state.resync(),
+        }
+    }
+
+    /// Subscribe to a channel for a server sent events endpoint,
+    /// each item is a full pre-formatted event (`id: <cursor>\ndata: <json>\n\n`) to write to the response as is.
+    ///
+    /// If the client falls too far behind, an `event: resync` event is sent, the client should reload its full state.
+    /// The channel's subscription is kept for as long as the stream is alive.
+    /// The stream ends if redis can't be subscribed to, or the subscription is lost.
+    pub async fn subscribe_sse(
+        &self,
+        namespace: &str,
+        channel: &str,
+    ) -> impl Stream<Item = String> + Send + 'static {
+        let subscription = self.hub(namespace, channel).await.map(|hub| {
+            let receiver = hub.sender.subscribe();
+            (hub, receiver)
+        });
+        futures::stream::unfold(subscription, |subscription| async move {
+            let (hub, mut receiver) = subscription?;
+            let frame = match receiver.recv().await {
+                Ok(seq) => {
+                    let state = hub.state.lock();
+                    match state.ring.iter().find(|(s, _)| *s == seq) {
+                        Some((_, event)) => {
+                            format!("id: {}\ndata: {}\n\n", state.cursor(seq + 1), event)
+                        }
+                        // Already pushed out of the ring:
+                        None => RESYNC_FRAME.into(),
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => RESYNC_FRAME.into(),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            Some((frame, Some((hub, receiver))))
+        })
+    }
+
+    /// Get or create the shared hub for a channel, None if redis couldn't be subscribed to.
+    async fn hub(&self, namespace: &str, channel: &str) -> Option<Arc<ChannelHub>> {
+        let key = self.redis.conn().final_key(namespace, channel.into());
+        if let Some(hub) = self.hubs.lock().get(&key).and_then(Weak::upgrade) {
+            return Some(hub);
+        }
+
+        let mut listener = self
+            .redis
+            .subscribe::<serde_json::Value>(namespace, channel)
+            .await?;
+
+        // Another caller might have subscribed whilst this one was, only keep one:
+        let mut hubs = self.hubs.lock();
+        if let Some(hub) = hubs.get(&key).and_then(Weak::upgrade) {
+            return Some(hub);
+        }
+        let (sender, _) = broadcast::channel(self.ring_size);
+        let hub = Arc::new(ChannelHub {
+            state: Mutex::new(HubState {
+                epoch: crate::misc::sortable_id().to_string(),
+                ring: VecDeque::new(),
+                ring_size: self.ring_size,
+                next_seq: 0,
+            }),
+            sender,
+            pump: Mutex::new(None),
+            key: key.clone(),
+            hubs: Arc::downgrade(&self.hubs),
+        });
+        let weak_hub = Arc::downgrade(&hub);
+        let weak_hubs = Arc::downgrade(&self.hubs);
+        let pump_key = key.clone();
+        *hub.pump.lock() = Some(tokio::spawn(async move {
+            while let Some(event) = listener.recv().await {
+                let Some(hub) = weak_hub.upgrade() else {
+                    return;
+                };
+                hub.push(event);
+            }
+            // Subscription lost, forget the hub so the next use resubscribes:
+            remove_hub(&weak_hubs, &pump_key, &weak_hub);
+        }));
+        hubs.insert(key, Arc::downgrade(&hub));
+        Some(hub)
+    }
+}
+
+/// Forget the hub, unless the key has since been taken by a newer, still alive one.
+fn remove_hub(hubs: &Weak<HubMap>, key: &str, hub: &Weak<ChannelHub>) {
+    if let Some(hubs) = hubs.upgrade() {
+        let mut hubs = hubs.lock();
+        if hubs
+            .get(key)
+            .is_some_and(|existing| Weak::ptr_eq(existing, hub) || existing.strong_count() == 0)
+        {
+            hubs.remove(key);
+        }
+    }
+}
+
+/// The shared state of a single channel.
+struct ChannelHub {
+    state: Mutex<HubState>,
+    /// Notifies waiting clients of the seq of each new event.
+    sender: broadcast::Sender<u64>,
+    pump: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    key: String,
+    hubs: Weak<HubMap>,
+}
+
+impl ChannelHub {
+    fn push(&self, event: serde_json::Value) {
+        let seq = {
+            let mut state = self.state.lock();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.ring.push_back((seq, event));
+            while state.ring.len() > state.ring_size {
+                state.ring.pop_front();
+            }
+            seq
+        };
+        // Errors just mean nobody is currently waiting:
+        let _ = self.sender.send(seq);
+    }
+}
+
+impl Drop for ChannelHub {
+    fn drop(&mut self) {
+        // Stopping the pump drops the listener, unsubscribing from redis:
+        if let Some(pump) = self.pump.lock().take() {
+            pump.abort();
+        }
+        remove_hub(&self.hubs, &self.key, &Weak::new());
+    }
+}
+
+struct HubState {
+    /// Unique to this hub, so cursors from a previous subscription (or process) are detected.
+    epoch: String,
+    /// (seq, event), oldest first.
+    ring: VecDeque<(u64, serde_json::Value)>,
+    ring_size: usize,
+    next_seq: u64,
+}
+
+impl HubState {
+    fn cursor(&self, seq: u64) -> String {
+        format!("{}-{}", self.epoch, seq)
+    }
+
+    /// The seq the cursor points to, None if it's invalid or from a different hub.
+    fn parse_cursor(&self, cursor: &str) -> Option<u64> {
+        let (epoch, seq) = cursor.rsplit_once('-')?;
+        if epoch != self.epoch {
+            return None;
+        }
+        seq.parse().ok().filter(|seq| *seq <= self.next_seq)
+    }
+
+    /// The events from the given seq onwards, None if some have already left the ring.
+    fn events_since(&self, seq: u64) -> Option<Vec<serde_json::Value>> {
+        let oldest = self.ring.front().map_or(self.next_seq, |(seq, _)| *seq);
+        if seq < oldest {
+            return None;
+        }
+        Some(
+            self.ring
+                .iter()
+                .filter(|(s, _)| *s >= seq)
+                .map(|(_, event)| event.clone())
+                .collect(),
+        )
+    }
+
+    fn resync(&self) -> PollResult {
+        PollResult {
+            events: vec![],
+            next_cursor: self.cursor(self.next_seq),
+            resync: true,
+        }
+    }
+}
@@ -36,6 +36,11 @@ impl RedisScript {
         }
     }
 
+    /// The sha1 redis identifies the script by.
+    pub(crate) fn sha(&self) -> &str {
+        &self.hash
+    }
+
     /// The command to load the script to redis.
     pub(crate) fn load_cmd(&self) -> Cmd {
         let mut cmd = cmd("SCRIPT");
@@ -81,4 +86,16 @@ impl<'a> RedisScriptInvoker<'a> {
             .arg(&*self.args);
         cmd
     }
+
+    /// The command to run the script invocation, sending the full script rather than its hash.
+    ///
+    /// For raw pipes, which unlike batches can't load the script and retry when redis doesn't have it cached.
+    pub(crate) fn eval_source_cmd(&self) -> Cmd {
+        let mut cmd = cmd("EVAL");
+        cmd.arg(self.script.code.as_bytes())
+            .arg(self.keys.len())
+            .arg(&*self.keys)
+            .arg(&*self.args);
+        cmd
+    }
 }
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Redis, RedisBatchFire, RedisBatchReturningOps, RedisScript};
+use crate::{
+    cookies::{get_cookie_raw, set_cookie_raw, CookieOptions, SameSite},
+    errors::prelude::*,
+    hash::{hmac_sha256_hex, verify_hmac_sha256},
+};
+
+static SAVE_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/session_save.lua")));
+
+/// The namespace session hashes are stored under.
+const NAMESPACE: &str = "session";
+
+/// Where sessions read and write their cookie.
+///
+/// Keeps sessions framework agnostic, [`RequestCookies`] uses the crate's cookie fns for the current request.
+pub trait SessionCookies {
+    /// The raw value of the cookie, None if not set.
+    fn get(&self, name: &str) -> Option<String>;
+
+    /// Set the cookie to a raw value.
+    fn set(&self, name: &str, value: &str, options: CookieOptions<'_>);
+}
+
+/// Reads and writes cookies for the current request with [`get_cookie_raw`] and [`set_cookie_raw`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestCookies;
+
+impl SessionCookies for RequestCookies {
+    fn get(&self, name: &str) -> Option<String> {
+        get_cookie_raw(name)
+    }
+
+    fn set(&self, name: &str, value: &str, options: CookieOptions<'_>) {
+        set_cookie_raw(name, value, options)
+    }
+}
+
+/// Configuration for [`RedisSessionLayer`].
+#[derive(Clone, Debug)]
+pub struct SessionOpts {
+    /// The key session cookies are signed with, keep secret and stable across deployments, otherwise all sessions are lost.
+    pub signing_key: Vec<u8>,
+    /// The name of the session cookie, defaults to "session".
+    pub cookie_name: String,
+    /// How long a session lives after it was last loaded or saved, defaults to 7 days.
+    pub ttl: Duration,
+    /// Defaults to [`SameSite::Lax`].
+    pub same_site: SameSite,
+    /// Only send the cookie over https, defaults to true.
+    pub secure: bool,
+}
+
+impl SessionOpts {
+    /// Default options with the given signing key.
+    pub fn new(signing_key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            signing_key: signing_key.into(),
+            cookie_name: "session".to_string(),
+            ttl: Duration::from_secs(60 * 60 * 24 * 7),
+            same_site: SameSite::Lax,
+            secure: true,
+        }
+    }
+}
+
+/// Cookie keyed sessions stored as redis hashes.
+///
+/// The cookie holds a random session id signed with HMAC-SHA256, so ids can't be forged or tampered with,
+/// a cookie that fails verification just starts a new session.
+/// Sessions slide, each load or save refreshes both the hash and cookie ttl.
+///
+/// Cheap to clone, e.g. into app state.
+#[derive(Clone, Debug)]
+pub struct RedisSessionLayer {
+    redis: Redis,
+    opts: Arc<SessionOpts>,
+}
+
+impl RedisSessionLayer {
+    /// Create a new session layer.
+    pub fn new(redis: Redis, opts: SessionOpts) -> Self {
+        Self {
+            redis,
+            opts: Arc::new(opts),
+        }
+    }
+
+    /// Load the session identified by the request's cookie, or start a new one if there isn't one,
+    /// its signature is invalid, or it's expired.
+    ///
+    /// New sessions aren't written to redis, nor their cookie set, until [`RedisSession::save`].
+    pub async fn load_or_create(&self, cookies: &impl SessionCookies) -> RedisSession {
+        if let Some(id) = cookies
+            .get(&self.opts.cookie_name)
+            .and_then(|value| self.verify(&value))
+        {
+            let fields = self
+                .redis
+                .conn()
+                .batch()
+                .hgetall(NAMESPACE, &id)
+                .expire(NAMESPACE, &id, self.opts.ttl)
+                .fire()
+                .await
+                .unwrap_or_default();
+            // Empty when expired or destroyed, treated as missing:
+            if !fields.is_empty() {
+                cookies.set(&self.opts.cookie_name, &self.sign(&id), self.cookie_opts());
+                return RedisSession {
+                    layer: self.clone(),
+                    id,
+                    is_new: false,
+                    fields,
+                    changes: HashMap::new(),
+                };
+            }
+        }
+
+        let mut id_bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut id_bytes);
+        RedisSession {
+            layer: self.clone(),
+            id: id_bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            is_new: true,
+            fields: HashMap::new(),
+            changes: HashMap::new(),
+        }
+    }
+
+    fn sign(&self, id: &str) -> String {
+        format!("{}.{}", id, hmac_sha256_hex(&self.opts.signing_key, id))
+    }
+
+    /// The session id from a cookie value, None if the signature doesn't match.
+    fn verify(&self, value: &str) -> Option<String> {
+        let (id, sig) = value.rsplit_once('.')?;
+        verify_hmac_sha256(&self.opts.signing_key, id, sig).then(|| id.to_string())
+    }
+
+    fn cookie_opts(&self) -> CookieOptions<'static> {
+        CookieOptions {
+            path: Some("/"),
+            domain: None,
+            expires: chrono::TimeDelta::from_std(self.opts.ttl).ok(),
+            secure: self.opts.secure,
+            same_site: self.opts.same_site.clone(),
+            http_only: true,
+        }
+    }
+}
+
+/// A session loaded with [`RedisSessionLayer::load_or_create`].
+///
+/// Values are json encoded into the session's hash fields.
+/// Changes are only written on [`RedisSession::save`], all at once.
+#[derive(Debug)]
+pub struct RedisSession {
+    layer: RedisSessionLayer,
+    id: String,
+    is_new: bool,
+    /// The current fields, including unsaved changes.
+    fields: HashMap<String, String>,
+    /// Unsaved changes, None for removed fields.
+    changes: HashMap<String, Option<String>>,
+}
+
+impl RedisSession {
+    /// The session id, don't expose this to clients other than through the signed cookie.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// True if the session was created by this load rather than loaded from redis.
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+
+    /// Get a field, None if it isn't set, or doesn't decode as `T` (logged).
+    pub fn get<T: DeserializeOwned>(&self, field: &str) -> Option<T> {
+        let value = self.fields.get(field)?;
+        match serde_json::from_str(value) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::error!("Failed to decode session field '{}': {:?}", field, e);
+                None
+            }
+        }
+    }
+
+    /// Set a field, written on [`RedisSession::save`].
+    pub fn set<T: Serialize + ?Sized>(&mut self, field: &str, value: &T) -> RResult<(), AnyErr> {
+        let value = serde_json::to_string(value).change_context(AnyErr)?;
+        self.fields.insert(field.to_string(), value.clone());
+        self.changes.insert(field.to_string(), Some(value));
+        Ok(())
+    }
+
+    /// Remove a field, written on [`RedisSession::save`].
+    pub fn remove(&mut self, field: &str) {
+        self.fields.remove(field);
+        self.changes.insert(field.to_string(), None);
+    }
+
+    /// Write all changes in a single batch, refreshing the session's ttl and setting its cookie.
+    ///
+    /// Returns false if redis couldn't be used, the changes are kept to retry.
+    pub async fn save(&mut self, cookies: &impl SessionCookies) -> bool {
+        let opts = &self.layer.opts;
+        let mut conn = self.layer.redis.conn();
+        let (sets, dels): (Vec<_>, Vec<_>) =
+            self.changes.iter().partition(|(_, value)| value.is_some());
+        let invoker = SAVE_SCRIPT
+            .invoker()
+            .key(conn.final_key(NAMESPACE, self.id.as_str().into()))
+            .arg(opts.ttl.as_millis() as u64)
+            .arg(sets.len());
+        let invoker = sets.into_iter().fold(invoker, |invoker, (field, value)| {
+            invoker.arg(field).arg(value.as_deref().unwrap_or_default())
+        });
+        let invoker = dels
+            .into_iter()
+            .fold(invoker, |invoker, (field, _)| invoker.arg(field));
+        if conn
+            .batch()
+            .script_no_return(invoker)
+            .fire()
+            .await
+            .is_none()
+        {
+            return false;
+        }
+
+        self.changes.clear();
+        cookies.set(
+            &opts.cookie_name,
+            &self.layer.sign(&self.id),
+            self.layer.cookie_opts(),
+        );
+        true
+    }
+
+    /// Delete the session from redis and expire its cookie.
+    ///
+    /// Returns false if redis couldn't be used.
+    pub async fn destroy(self, cookies: &impl SessionCookies) -> bool {
+        let deleted = self
+            .layer
+            .redis
+            .conn()
+            .batch()
+            .clear(NAMESPACE, [self.id.as_str()])
+            .fire()
+            .await
+            .is_some();
+        let mut cookie_opts = self.layer.cookie_opts();
+        cookie_opts.expires = Some(chrono::TimeDelta::seconds(-1));
+        cookies.set(&self.layer.opts.cookie_name, "", cookie_opts);
+        deleted
+    }
+}
+
+/// Run by the main tester that spawns up a redis process.
+#[cfg(test)]
+pub async fn redis_session_tests(r: &Redis) -> RResult<(), AnyErr> {
+    use parking_lot::Mutex;
+
+    /// An in-memory jar standing in for the request/response cookies, storing the value, expiry and http_only.
+    #[derive(Default)]
+    struct Jar(Mutex<HashMap<String, (String, Option<chrono::TimeDelta>, bool)>>);
+
+    impl Jar {
+        fn raw(&self, name: &str) -> Option<String> {
+            self.0.lock().get(name).map(|(value, ..)| value.clone())
+        }
+    }
+
+    impl SessionCookies for Jar {
+        fn get(&self, name: &str) -> Option<String> {
+            self.raw(name)
+        }
+
+        fn set(&self, name: &str, value: &str, options: CookieOptions<'_>) {
+            self.0.lock().insert(
+                name.to_string(),
+                (value.to_string(), options.expires, options.http_only),
+            );
+        }
+    }
+
+    let layer = RedisSessionLayer::new(
+        r.clone(),
+        SessionOpts {
+            ttl: Duration::from_millis(300),
+            ..SessionOpts::new("sekret")
+        },
+    );
+    let hash_exists = |id: String| async move {
+        !r.conn()
+            .batch()
+            .hgetall(NAMESPACE, &id)
+            .fire()
+            .await
+            .unwrap()
+            .is_empty()
+    };
+
+    // Create, set and save:
+    let jar = Jar::default();
+    let mut session = layer.load_or_create(&jar).await;
+    assert!(session.is_new());
+    assert_eq!(session.get::<String>("user"), None);
+    // Nothing written until saved:
+    assert_eq!(jar.raw("session"), None);
+    session.set("user", "alice")?;
+    session.set("visits", &3)?;
+    session.set("temp", &true)?;
+    session.remove("temp");
+    assert_eq!(session.get::<String>("user"), Some("alice".to_string()));
+    assert!(session.save(&jar).await);
+    let cookie = jar.raw("session").unwrap();
+    assert!(cookie.starts_with(&format!("{}.", session.id())));
+    assert!(jar.0.lock()["session"].2);
+
+    // Reload from the cookie:
+    let reloaded = layer.load_or_create(&jar).await;
+    assert!(!reloaded.is_new());
+    assert_eq!(reloaded.id(), session.id());
+    assert_eq!(reloaded.get::<String>("user"), Some("alice".to_string()));
+    assert_eq!(reloaded.get::<u32>("visits"), Some(3));
+    assert_eq!(reloaded.get::<bool>("temp"), None);
+    // Wrong type decodes to None:
+    assert_eq!(reloaded.get::<u32>("user"), None);
+
+    // Removing and updating in a later save:
+    let mut reloaded = reloaded;
+    reloaded.remove("visits");
+    reloaded.set("user", "bob")?;
+    assert!(reloaded.save(&jar).await);
+    let reloaded = layer.load_or_create(&jar).await;
+    assert_eq!(reloaded.get::<String>("user"), Some("bob".to_string()));
+    assert_eq!(reloaded.get::<u32>("visits"), None);
+
+    // Ttl slides on each load, lasting well past the 300ms ttl:
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let session = layer.load_or_create(&jar).await;
+        assert!(!session.is_new());
+        assert_eq!(session.id(), reloaded.id());
+    }
+    // But expires when not accessed:
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!hash_exists(reloaded.id().to_string()).await);
+    let expired = layer.load_or_create(&jar).await;
+    assert!(expired.is_new());
+    assert_ne!(expired.id(), reloaded.id());
+
+    // Tampered cookies are rejected with a new session:
+    let mut session = layer.load_or_create(&jar).await;
+    session.set("user", "alice")?;
+    assert!(session.save(&jar).await);
+    let cookie = jar.raw("session").unwrap();
+    let (id, sig) = cookie.rsplit_once('.').unwrap();
+    for tampered in [
+        // Last char of the signature flipped:
+        format!(
+            "{}.{}{}",
+            id,
+            &sig[..sig.len() - 1],
+            if sig.ends_with('0') { '1' } else { '0' }
+        ),
+        format!("{}x.{}", id, sig),
+        id.to_string(),
+        format!("{}.", id),
+        // Signed with a different key:
+        format!("{}.{}", id, hmac_sha256_hex("other", id)),
+    ] {
+        let tampered_jar = Jar::default();
+        tampered_jar.set("session", &tampered, CookieOptions::default());
+        let loaded = layer.load_or_create(&tampered_jar).await;
+        assert!(loaded.is_new(), "{}", tampered);
+        assert_ne!(loaded.id(), id);
+        assert_eq!(loaded.get::<String>("user"), None);
+    }
+
+    // Destroy removes the hash and expires the cookie:
+    let session = layer.load_or_create(&jar).await;
+    assert!(!session.is_new());
+    let id = session.id().to_string();
+    assert!(hash_exists(id.clone()).await);
+    assert!(session.destroy(&jar).await);
+    assert!(!hash_exists(id.clone()).await);
+    let (value, expires, _) = jar.0.lock()["session"].clone();
+    assert_eq!(value, "");
+    assert!(expires.unwrap() < chrono::TimeDelta::zero());
+    assert!(layer.load_or_create(&jar).await.is_new());
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::{collections::HashSet, future::Future};
+
+use super::{Redis, RedisConn};
+use crate::{errors::prelude::*, hash::fnv1a};
+
+/// Diagnostic information about a single instance in a [`RedisShardSet`], see [`RedisShardSet::ring_info`].
+#[derive(Debug, Clone)]
+pub struct RedisShardInfo {
+    /// The server of the instance, this is what identifies the instance on the ring.
+    pub server: String,
+    /// The prefix of the instance.
+    pub prefix: String,
+    /// The number of points the instance has on the ring.
+    pub points: usize,
+    /// The fraction (0-1) of the hash space owned by the instance, roughly the fraction of keys it will receive.
+    pub ownership: f64,
+}
+
+/// Client side sharding of keys across multiple standalone [`Redis`] wrappers, using a consistent hash ring.
+///
+/// Each instance gets `replicas_per_node` points on the ring, derived from its server (e.g. `localhost:6379`),
+/// a key is owned by the first point at or after the hash of its namespaced key (`namespace:key`, excluding the prefix).
+/// Routing is therefore deterministic across processes and restarts for the same set of servers,
+/// and adding or removing an instance only moves roughly `1/N` of keys.
+#[derive(Debug, Clone)]
+pub struct RedisShardSet {
+    instances: Vec<Redis>,
+    /// Sorted by the point's hash, (hash, index into instances).
+    ring: Vec<(u64, usize)>,
+}
+
+impl RedisShardSet {
+    /// Create a new shard set from the given instances.
+    ///
+    /// Arguments:
+    /// - `instances`: The redis wrappers to shard across, each must connect to a different server.
+    /// - `replicas_per_node`: The number of points each instance gets on the ring, more points mean a more even distribution, 100-200 is usually plenty.
+    pub fn new(instances: Vec<Redis>, replicas_per_node: usize) -> RResult<Self, AnyErr> {
+        if instances.is_empty() {
+            return Err(anyerr!("A RedisShardSet needs at least one instance."));
+        }
+        if replicas_per_node == 0 {
+            return Err(anyerr!("replicas_per_node must be greater than 0."));
+        }
+        let mut seen = HashSet::new();
+        for instance in &instances {
+            if !seen.insert(instance.server()) {
+                return Err(anyerr!(
+                    "Server '{}' is included more than once in the RedisShardSet.",
+                    instance.server()
+                ));
+            }
+        }
+
+        let ring = build_ring(
+            instances.iter().map(|instance| instance.server()),
+            replicas_per_node,
+        );
+        Ok(Self { instances, ring })
+    }
+
+    /// The instances in the set, in the order they were provided.
+    pub fn instances(&self) -> &[Redis] {
+        &self.instances
+    }
+
+    /// Get the instance owning the given key.
+    pub fn instance_for(&self, namespace: &str, key: &str) -> &Redis {
+        &self.instances[self.owner_index(namespace, key)]
+    }
+
+    /// Get a [`RedisConn`] to the instance owning the given key.
+    pub fn conn_for(&self, namespace: &str, key: &str) -> RedisConn<'_> {
+        self.instance_for(namespace, key).conn()
+    }
+
+    /// Run keyed operations across the owning instances, merging the results back into input order.
+    ///
+    /// The keys are grouped by owner, `cb` is called once per instance owning at least one of the keys,
+    /// with a conn to the instance and its keys (in input order). It should return one result per key it was given,
+    /// e.g. using a single [`RedisBatch`](super::RedisBatch) per instance. All instances are processed concurrently.
+    ///
+    /// If `cb` returns `None` for an instance (e.g. it's down), or the wrong number of results, only that instance's share of keys will be `None`.
+    pub async fn batch_sharded<'a, K, R, Fut>(
+        &'a self,
+        namespace: &str,
+        keys: impl IntoIterator<Item = K>,
+        cb: impl Fn(RedisConn<'a>, Vec<K>) -> Fut,
+    ) -> Vec<Option<R>>
+    where
+        K: AsRef<str>,
+        Fut: Future<Output = Option<Vec<R>>>,
+    {
+        // (input indices, keys) per instance:
+        let mut groups: Vec<(Vec<usize>, Vec<K>)> = (0..self.instances.len())
+            .map(|_| (vec![], vec![]))
+            .collect();
+        let mut total = 0;
+        for (index, key) in keys.into_iter().enumerate() {
+            let owner = self.owner_index(namespace, key.as_ref());
+            groups[owner].0.push(index);
+            groups[owner].1.push(key);
+            total += 1;
+        }
+
+        let futs = groups
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (indices, _))| !indices.is_empty())
+            .map(|(owner, (indices, keys))| {
+                let fut = cb(self.instances[owner].conn(), keys);
+                async move { (owner, indices, fut.await) }
+            });
+
+        let mut results: Vec<Option<R>> = (0..total).map(|_| None).collect();
+        for (owner, indices, group_results) in futures::future::join_all(futs).await {
+            match group_results {
+                Some(group_results) if group_results.len() == indices.len() => {
+                    for (index, result) in indices.into_iter().zip(group_results) {
+                        results[index] = Some(result);
+                    }
+                }
+                Some(group_results) => {
+                    tracing::error!(
+                        "RedisShardSet batch for server '{}' returned {} results for {} keys, ignoring.",
+                        self.instances[owner].server(),
+                        group_results.len(),
+                        indices.len()
+                    );
+                }
+                None => {}
+            }
+        }
+        results
+    }
+
+    /// Diagnostic information about each instance on the ring, in the order they were provided.
+    pub fn ring_info(&self) -> Vec<RedisShardInfo> {
+        let mut info = self
+            .instances
+            .iter()
+            .map(|instance| RedisShardInfo {
+                server: instance.server().to_string(),
+                prefix: instance.prefix().to_string(),
+                points: 0,
+                ownership: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        for (index, (point, owner)) in self.ring.iter().enumerate() {
+            // Each point owns the hash space between the previous point (exclusive) and itself (inclusive), wrapping at the start:
+            let previous = if index == 0 {
+                self.ring[self.ring.len() - 1].0
+            } else {
+                self.ring[index - 1].0
+            };
+            let span = point.wrapping_sub(previous);
+            info[*owner].points += 1;
+            info[*owner].ownership += span as f64 / u64::MAX as f64;
+        }
+
+        // A single point owns everything, rather than the zero span calculated above:
+        if self.ring.len() == 1 {
+            info[0].ownership = 1.0;
+        }
+        info
+    }
+
+    fn owner_index(&self, namespace: &str, key: &str) -> usize {
+        ring_owner(
+            &self.ring,
+            ring_hash(format!("{}:{}", namespace, key).as_bytes()),
+        )
+    }
+}
+
+/// fnv1a followed by a splitmix64 finalizer, fnv1a alone distributes similar inputs (e.g. `server#1`, `server#2`) poorly.
+fn ring_hash(input: &[u8]) -> u64 {
+    let mut hash = fnv1a(input);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+fn build_ring<'a>(
+    node_ids: impl IntoIterator<Item = &'a str>,
+    replicas_per_node: usize,
+) -> Vec<(u64, usize)> {
+    let mut ring = node_ids
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, node_id)| {
+            (0..replicas_per_node).map(move |replica| {
+                (
+                    ring_hash(format!("{}#{}", node_id, replica).as_bytes()),
+                    index,
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    ring.sort_unstable();
+    ring
+}
+
+/// The node owning the hash, the first point at or after it, wrapping around to the first point.
+fn ring_owner(ring: &[(u64, usize)], hash: u64) -> usize {
+    let index = ring.partition_point(|(point, _)| *point < hash);
+    ring[if index == ring.len() { 0 } else { index }].1
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn owners(ring: &[(u64, usize)], count: usize) -> Vec<usize> {
+        (0..count)
+            .map(|i| ring_owner(ring, ring_hash(format!("ns:key_{}", i).as_bytes())))
+            .collect()
+    }
+
+    #[rstest]
+    fn test_shard_ring_stable() {
+        let servers = ["redis-a:6379", "redis-b:6379", "redis-c:6379"];
+        // Rebuilding the ring (i.e. a restart) gives the same routing, and the provided order doesn't matter:
+        let ring = build_ring(servers, 100);
+        assert_eq!(owners(&ring, 1000), owners(&build_ring(servers, 100), 1000));
+        let reversed = build_ring(servers.iter().rev().copied(), 100);
+        assert_eq!(
+            owners(&ring, 1000)
+                .into_iter()
+                .map(|owner| servers[owner])
+                .collect::<Vec<_>>(),
+            owners(&reversed, 1000)
+                .into_iter()
+                .map(|owner| servers[servers.len() - 1 - owner])
+                .collect::<Vec<_>>(),
+        );
+
+        // Keys are spread roughly evenly:
+        let owners = owners(&ring, 9000);
+        for node in 0..servers.len() {
+            let count = owners.iter().filter(|owner| **owner == node).count();
+            assert!((2000..4000).contains(&count), "{}: {}", node, count);
+        }
+    }
+
+    #[rstest]
+    fn test_shard_ring_minimal_movement() {
+        let before = build_ring(["redis-a:6379", "redis-b:6379", "redis-c:6379"], 150);
+        let after = build_ring(
+            [
+                "redis-a:6379",
+                "redis-b:6379",
+                "redis-c:6379",
+                "redis-d:6379",
+            ],
+            150,
+        );
+
+        let count = 10_000;
+        let before = owners(&before, count);
+        let after = owners(&after, count);
+        let mut moved = 0;
+        for (before, after) in before.into_iter().zip(after) {
+            if before != after {
+                moved += 1;
+                // Keys should only ever move to the new node:
+                assert_eq!(after, 3);
+            }
+        }
+        // Ideal is 1/4 of keys:
+        assert!((1_500..3_500).contains(&moved), "{}", moved);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::VecDeque, panic::Location, time::Instant};
+
+use parking_lot::Mutex;
+use redis::{Arg, Pipeline};
+
+/// A batch that took longer than the threshold of [`super::Redis::slow_batch_log`], see [`super::Redis::slow_batches`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowBatchEntry {
+    /// When the batch finished.
+    pub at: chrono::DateTime<chrono::Utc>,
+    /// End-to-end, including waiting for a pool connection, script reloads and retries.
+    pub duration: chrono::TimeDelta,
+    /// The commands in the batch in order of first use, repeats counted, e.g. `SET x2, EVALSHA`.
+    pub summary: String,
+    /// The namespaces of the keys touched, in order of first use.
+    pub namespaces: Vec<String>,
+    /// How many times the batch was re-sent, e.g. after reloading scripts.
+    pub retries: usize,
+    /// Whether scripts had to be reloaded to redis.
+    pub scripts_reloaded: bool,
+    /// Where [`super::RedisBatchFire::fire`] was called from, `file:line:col`.
+    pub caller: String,
+}
+
+/// The outcome of firing a batch, used to build a [`SlowBatchEntry`] if it was slow.
+#[derive(Debug, Default)]
+pub(crate) struct FireStats {
+    pub retries: usize,
+    pub scripts_reloaded: bool,
+}
+
+/// Bounded record of slow batches, shared by all clones of a [`super::Redis`].
+#[derive(Debug)]
+pub(crate) struct SlowBatchLog {
+    threshold: std::time::Duration,
+    capacity: usize,
+    entries: Mutex<VecDeque<SlowBatchEntry>>,
+}
+
+impl SlowBatchLog {
+    pub fn new(threshold: chrono::TimeDelta, capacity: usize) -> Self {
+        Self {
+            threshold: threshold.to_std().unwrap_or_default(),
+            capacity: capacity.max(1),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record the batch if it took longer than the threshold, the only cost for fast batches is reading the elapsed time.
+    pub fn check(
+        &self,
+        started: Instant,
+        prefix: &str,
+        pipe: &Pipeline,
+        stats: FireStats,
+        caller: &'static Location<'static>,
+    ) {
+        let elapsed = started.elapsed();
+        if elapsed <= self.threshold {
+            return;
+        }
+
+        let (summary, namespaces) = summarise_pipe(prefix, pipe);
+        let entry = SlowBatchEntry {
+            at: chrono::Utc::now(),
+            duration: chrono::TimeDelta::from_std(elapsed)
+                .unwrap_or(chrono::TimeDelta::max_value()),
+            summary,
+            namespaces,
+            retries: stats.retries,
+            scripts_reloaded: stats.scripts_reloaded,
+            caller: caller.to_string(),
+        };
+        tracing::warn!(
+            "Slow redis batch took {}ms (threshold {}ms) at {}: '{}', namespaces: {:?}, retries: {}, scripts reloaded: {}.",
+            elapsed.as_millis(),
+            self.threshold.as_millis(),
+            entry.caller,
+            entry.summary,
+            entry.namespaces,
+            entry.retries,
+            entry.scripts_reloaded
+        );
+
+        let mut entries = self.entries.lock();
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// The recorded entries, oldest first.
+    pub fn entries(&self) -> Vec<SlowBatchEntry> {
+        self.entries.lock().iter().cloned().collect()
+    }
+}
+
+/// The command summary and namespaces of a pipe.
+///
+/// Namespaces are found from args starting with the wrapper prefix, so are best effort,
+/// e.g. a value that happens to look like a key would be included.
+fn summarise_pipe(prefix: &str, pipe: &Pipeline) -> (String, Vec<String>) {
+    let key_prefix = format!("{}:", prefix);
+    let mut cmds: Vec<(String, usize)> = vec![];
+    let mut namespaces: Vec<String> = vec![];
+    for cmd in pipe.cmd_iter() {
+        let mut args = cmd.args_iter().filter_map(|arg| match arg {
+            Arg::Simple(arg) => Some(String::from_utf8_lossy(arg)),
+            Arg::Cursor => None,
+        });
+        let Some(name) = args.next() else {
+            continue;
+        };
+        let name = name.to_uppercase();
+        match cmds.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, count)) => *count += 1,
+            None => cmds.push((name, 1)),
+        }
+        for arg in args {
+            if let Some(namespace) = arg
+                .strip_prefix(&key_prefix)
+                .and_then(|rest| rest.split_once(':'))
+                .map(|(namespace, _)| namespace)
+            {
+                if !namespaces.iter().any(|existing| existing == namespace) {
+                    namespaces.push(namespace.to_string());
+                }
+            }
+        }
+    }
+    let summary = cmds
+        .into_iter()
+        .map(|(name, count)| {
+            if count == 1 {
+                name
+            } else {
+                format!("{} x{}", name, count)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    (summary, namespaces)
+}
@@ -27,16 +27,17 @@ impl RedisStandalone {
             .change_context(AnyErr)?;
 
         // Wait for redis to come up, raising if waited for 10 seconds.
+        let redis = Redis::new(
+            format!("redis://localhost:{}", port),
+            uuid::Uuid::new_v4().to_string(),
+        )?;
         let mut up = false;
         let elapsed = Instant::now();
         while !up && elapsed.elapsed() < std::time::Duration::from_secs(10) {
-            up = Redis::new(
-                format!("redis://localhost:{}", port),
-                uuid::Uuid::new_v4().to_string(),
-            )?
-            .conn()
-            .ping()
-            .await
+            up = redis.conn().ping().await;
+            if !up {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
         }
         if up {
             Ok(Self { child, port })
@@ -58,7 +59,10 @@ impl Drop for RedisStandalone {
     fn drop(&mut self) {
         match self.child.kill() {
             Ok(_) => {}
-            Err(e) => record_exception("Could not kill child process.", format!("{:?}", e)),
+            Err(e) => record_exception(
+                "Could not kill child process.",
+                crate::errors::LazyDebug(&e),
+            ),
         }
     }
 }
@@ -1,16 +1,105 @@
 use std::{
     borrow::Cow,
-    sync::{atomic::AtomicI64, Arc},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
 use futures::{future::BoxFuture, FutureExt};
+use once_cell::sync::Lazy;
 use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
 
-use super::{batch::*, RedisConn, RedisJson};
+use super::{batch::*, RedisChannelListener, RedisConn, RedisJson, RedisLockErr, RedisScript};
+use crate::errors::prelude::*;
+use crate::{
+    misc::{sortable_id, sortable_id_with_time, FlexiLog, SortableId},
+    redis::RedisJsonBorrowed,
+};
+
+static MERGE_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/temp_list_merge.lua")));
+static READ_UNCLAIMED_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/temp_list_read_unclaimed.lua")));
+static READ_PROJECTED_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/temp_list_read_projected.lua")));
+static ARCHIVE_PENDING_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/temp_list_archive_pending.lua")));
+static CLEANUP_HASH_SCRIPT: Lazy<RedisScript> =
+    Lazy::new(|| RedisScript::new(include_str!("lua_scripts/temp_list_cleanup.lua")));
+
+/// The number of items [`RedisTempList::read_recent_projected`] has had to fetch in full and project client side.
 #[cfg(test)]
-use crate::prelude::*;
-use crate::{misc::FlexiLog, redis::RedisJsonBorrowed};
+static PROJECTION_FALLBACKS: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+/// Suffix appended to an item's uid to form its sibling claim key, see [`RedisTempListItem::try_claim`].
+const CLAIM_SUFFIX: &str = "__claim";
+
+/// Suffix appended to a list's key to form its set of archived uids, see [`RedisTempList::archive_expiring`].
+const ARCHIVED_SUFFIX: &str = "__archived";
+
+/// Suffix appended to a list's key to form the hash holding its values, see [`TempListStorage::Hash`].
+const ITEMS_SUFFIX: &str = "__items";
+
+/// Suffix appended to a list's key to form the pubsub channel its changes are announced on, see [`RedisTempList::subscribe_changes`].
+const CHANGES_SUFFIX: &str = "__changes";
+
+/// The dlock namespace sweeps are locked under, so a list is only archived by one process at a time.
+const ARCHIVE_LOCK_NAMESPACE: &str = "templist_archive";
+
+type ArchiveFn = dyn Fn(Vec<(String, serde_json::Value)>) -> BoxFuture<'static, RResult<(), AnyErr>>
+    + Send
+    + Sync;
+
+/// Set with [`RedisTempList::with_archiver`], wrapped to give the list a Debug impl.
+#[derive(Clone)]
+struct Archiver(Arc<ArchiveFn>);
+
+impl std::fmt::Debug for Archiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Archiver")
+    }
+}
+
+/// How a [`RedisTempList`] stores its item values, set with [`RedisTempList::with_storage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TempListStorage {
+    /// Each value is its own redis key, expiring with a native ttl.
+    #[default]
+    IndividualKeys,
+    /// All values live in a single hash next to the list, keyed by uid, avoiding the per-key overhead for lists of many small items.
+    ///
+    /// Hash fields can't expire, so item expiry is emulated by the list's scores,
+    /// with expired values removed from the hash whenever the list is cleaned up. The hash expires with the list as a whole.
+    Hash,
+}
+
+/// The outcome of [`RedisTempList::merge_from`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The number of items copied into the destination list.
+    pub merged: usize,
+    /// Items still registered in the source list, but whose values had already expired, these are skipped.
+    pub missing: usize,
+    /// Items whose uid already existed in the destination, these were merged under a newly generated uid.
+    pub uid_collisions: usize,
+}
+
+/// A change to a [`RedisTempList`], announced to listeners from [`RedisTempList::subscribe_changes`]
+/// when enabled with [`RedisTempList::with_change_events`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TempListChange {
+    /// Items were added, by [`RedisTempList::push`], [`RedisTempList::extend`] or [`RedisTempList::merge_from`].
+    Extended {
+        /// The uids of the added items in this list.
+        uids: Vec<String>,
+    },
+    /// The list was cleared, by [`RedisTempList::clear`] or [`RedisTempList::merge_from`] deleting its source.
+    Clear,
+}
 
 /// A wrapped item, with a connection too, preventing need to pass 2 things around if useful for certain interfaces.
 #[derive(Debug)]
@@ -243,6 +332,143 @@ impl<T: serde::Serialize + for<'a> serde::Deserialize<'a>> RedisTempListItem<T>
     pub fn into_item(self) -> Option<T> {
         self.maybe_item
     }
+
+    /// Try and claim the item, to coordinate processing it across multiple workers/processes.
+    ///
+    /// The claim is a sibling key to the item, set only if not already claimed, and expires after `claim_ttl`,
+    /// so items claimed by a crashed worker become available again. Use [`ItemClaim::extend`] to hold it for longer.
+    ///
+    /// Returns:
+    /// - Some(ItemClaim): the claim was acquired, it's released when dropped or with [`ItemClaim::release`].
+    /// - None: the item is already claimed, the holder is empty, or redis couldn't be used.
+    pub async fn try_claim(
+        &self,
+        conn: &mut RedisConn<'_>,
+        claim_ttl: chrono::TimeDelta,
+    ) -> Option<ItemClaim> {
+        let tmp_list = self.maybe_tmp_list.as_ref()?;
+        let uid = self.maybe_uid.as_ref()?;
+
+        let claim_key = format!("{}{}", uid, CLAIM_SUFFIX);
+        let token = uuid::Uuid::new_v4().to_string();
+        let final_key = conn.final_key(&tmp_list.namespace, claim_key.as_str().into());
+        let inner_conn = conn.get_inner_conn().await?;
+        match redis::cmd("SET")
+            .arg(final_key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(claim_ttl_millis(claim_ttl))
+            .query_async::<_, Option<String>>(inner_conn)
+            .await
+        {
+            Ok(Some(_)) => Some(ItemClaim {
+                pool: conn.pool().clone(),
+                prefix: conn.prefix.to_string(),
+                namespace: tmp_list.namespace.clone(),
+                uid: uid.clone(),
+                claim_key,
+                token,
+                released: false,
+            }),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::error!("Could not claim temp list item: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// A held claim on a [`RedisTempListItem`], see [`RedisTempListItem::try_claim`].
+///
+/// Released on drop (best-effort, via a spawned task), use [`ItemClaim::release`] to release it immediately.
+#[derive(Debug)]
+pub struct ItemClaim {
+    pool: deadpool_redis::Pool,
+    prefix: String,
+    namespace: Cow<'static, str>,
+    uid: String,
+    claim_key: String,
+    /// Unique to this claim, so a claim that expired and was taken by another worker can't be extended or released from here.
+    token: String,
+    released: bool,
+}
+
+impl ItemClaim {
+    /// The uid of the claimed item.
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// Extend the claim to expire `claim_ttl` from now.
+    ///
+    /// Returns false if the claim has been lost (it expired, possibly being claimed by someone else), or redis couldn't be used.
+    pub async fn extend(&self, conn: &mut RedisConn<'_>, claim_ttl: chrono::TimeDelta) -> bool {
+        conn.batch()
+            .set_if_equals(
+                &self.namespace,
+                &self.claim_key,
+                &self.token,
+                &self.token,
+                Some(Duration::from_millis(claim_ttl_millis(claim_ttl))),
+            )
+            .fire()
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Release the claim, making the item immediately available to other claimers.
+    ///
+    /// Returns false if the claim had already been lost, or redis couldn't be used.
+    pub async fn release(mut self, conn: &mut RedisConn<'_>) -> bool {
+        self.released = true;
+        conn.batch()
+            .del_if_equals(&self.namespace, &self.claim_key, &self.token)
+            .fire()
+            .await
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for ItemClaim {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        // Can't release without a runtime, the claim will just expire instead:
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let pool = self.pool.clone();
+            let prefix = std::mem::take(&mut self.prefix);
+            let namespace = std::mem::take(&mut self.namespace);
+            let claim_key = std::mem::take(&mut self.claim_key);
+            let token = std::mem::take(&mut self.token);
+            handle.spawn(async move {
+                // Claims are never handed out by disabled wrappers:
+                RedisConn::new(&pool, &prefix, false, None, "")
+                    .batch()
+                    .del_if_equals(&namespace, &claim_key, token)
+                    .fire()
+                    .await;
+            });
+        }
+    }
+}
+
+/// Client side equivalent of the projection in [`RedisTempList::read_recent_projected`], empty if the value isn't a json object.
+fn project_fields(value: &str, fields: &[&str]) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::Object(mut map)) => fields
+            .iter()
+            .filter_map(|field| map.remove(*field).map(|value| (field.to_string(), value)))
+            .collect(),
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Claims need a positive ttl, redis errors on a px of 0.
+fn claim_ttl_millis(claim_ttl: chrono::TimeDelta) -> u64 {
+    claim_ttl.num_milliseconds().max(1) as u64
 }
 
 /// Connect up to a magic redis list that:
@@ -267,9 +493,25 @@ pub struct RedisTempList {
     /// If an item hasn't been read or written to in this time, it will be expired.
     pub item_inactive_ttl: Duration,
 
-    /// Used to prevent overlap between push() and extend() calls using the same ts by accident.
+    /// Set with [`RedisTempList::with_consistent_reads`].
+    #[serde(default)]
+    pub consistent_read_retries: Option<usize>,
+
+    /// Set with [`RedisTempList::with_storage`].
+    #[serde(default)]
+    pub storage: TempListStorage,
+
+    /// Set with [`RedisTempList::with_change_events`].
+    #[serde(default)]
+    pub change_events: bool,
+
+    /// Total retries made by consistent reads, see [`RedisTempList::consistent_read_retries_made`].
+    #[serde(skip)]
+    retries_made: Arc<AtomicUsize>,
+
+    /// Set with [`RedisTempList::with_archiver`], not serialized so a deserialized list needs it set again.
     #[serde(skip)]
-    last_extension_ts_millis: Arc<AtomicI64>,
+    archiver: Option<Archiver>,
 }
 
 /// A managed list entry in redis that will:
@@ -289,10 +531,201 @@ impl RedisTempList {
             key,
             list_inactive_ttl,
             item_inactive_ttl,
-            last_extension_ts_millis: Arc::new(AtomicI64::new(0)),
+            consistent_read_retries: None,
+            storage: TempListStorage::default(),
+            change_events: false,
+            retries_made: Arc::new(AtomicUsize::new(0)),
+            archiver: None,
+        })
+    }
+
+    /// Make [`RedisTempList::read_multi`] and [`RedisTempList::read_multi_raw`] consistent,
+    /// retrying up to `max_retries` times when the list is modified during the read, see [`RedisConn::consistent_two_phase`].
+    ///
+    /// The uids and values are read in separate round trips, so a concurrent write can otherwise cause
+    /// a uid to be read before its value exists, silently dropping the item from the result.
+    pub fn with_consistent_reads(self: &Arc<Self>, max_retries: usize) -> Arc<Self> {
+        Arc::new(Self {
+            consistent_read_retries: Some(max_retries),
+            ..(**self).clone()
+        })
+    }
+
+    /// Choose how the item values are stored, see [`TempListStorage`]. Every handle to the same list must use the same storage.
+    ///
+    /// Recorded when the list is serialized, so deserialized handles keep working.
+    pub fn with_storage(self: &Arc<Self>, storage: TempListStorage) -> Arc<Self> {
+        Arc::new(Self {
+            storage,
+            ..(**self).clone()
+        })
+    }
+
+    /// Announce pushes, extends, merges and clears to [`RedisTempList::subscribe_changes`] listeners.
+    ///
+    /// Off by default, as each announcement is an extra publish whether or not anyone is listening.
+    /// Recorded when the list is serialized, so deserialized handles keep announcing.
+    pub fn with_change_events(self: &Arc<Self>) -> Arc<Self> {
+        Arc::new(Self {
+            change_events: true,
+            ..(**self).clone()
+        })
+    }
+
+    /// The total retries made by consistent reads through this list, or lists cloned from it.
+    pub fn consistent_read_retries_made(&self) -> usize {
+        self.retries_made.load(Ordering::Relaxed)
+    }
+
+    /// Archive items to a durable sink before they expire, see [`RedisTempList::archive_expiring`] and [`RedisTempList::spawn_archiver`].
+    ///
+    /// The archiver is given batches of (uid, item json), erroring leaves the batch to be retried on the next sweep.
+    pub fn with_archiver(
+        self: &Arc<Self>,
+        archiver: impl Fn(Vec<(String, serde_json::Value)>) -> BoxFuture<'static, RResult<(), AnyErr>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            archiver: Some(Archiver(Arc::new(archiver))),
+            ..(**self).clone()
         })
     }
 
+    /// The key of the hash holding the values with [`TempListStorage::Hash`].
+    fn items_key(&self) -> String {
+        format!("{}{}", self.key, ITEMS_SUFFIX)
+    }
+
+    /// The channel changes to this list are announced on.
+    fn changes_channel(&self) -> String {
+        format!("{}{}", self.key, CHANGES_SUFFIX)
+    }
+
+    /// Announce a change to anyone listening with [`RedisTempList::subscribe_changes`], if enabled with [`RedisTempList::with_change_events`].
+    fn publish_change<'a, 'b, 'c, R>(
+        &self,
+        batch: RedisBatch<'a, 'b, 'c, R>,
+        change: &TempListChange,
+    ) -> RedisBatch<'a, 'b, 'c, R> {
+        if !self.change_events {
+            return batch;
+        }
+        match serde_json::to_vec(change) {
+            Ok(message) => batch.publish(&self.namespace, &self.changes_channel(), message),
+            Err(e) => {
+                tracing::error!(
+                    "Could not encode temp list change for '{}:{}', not publishing. Err: '{}'",
+                    self.namespace,
+                    self.key,
+                    e
+                );
+                batch
+            }
+        }
+    }
+
+    /// Listen for changes to the list made through any handle, see [`TempListChange`].
+    ///
+    /// Changes are only announced by handles created with [`RedisTempList::with_change_events`].
+    /// Only pushes, extends, merges and clears are announced, in-place updates and deletes of single items aren't.
+    ///
+    /// Returns `None` if redis couldn't be connected to or subscribed to.
+    pub async fn subscribe_changes(
+        &self,
+        redis: &super::Redis,
+    ) -> Option<RedisChannelListener<TempListChange>> {
+        redis
+            .subscribe(&self.namespace, &self.changes_channel())
+            .await
+    }
+
+    /// The final key of the value hash for scripts, empty when each value is its own key.
+    fn final_items_key(&self, conn: &RedisConn<'_>) -> String {
+        match self.storage {
+            TempListStorage::IndividualKeys => String::new(),
+            TempListStorage::Hash => conn.final_key(&self.namespace, self.items_key().into()),
+        }
+    }
+
+    /// Cleanup old members that have now expired:
+    /// (member expiry is a logical process, not currently part of redis but could be soon)
+    /// https://github.com/redis/redis/issues/135#issuecomment-2361996
+    /// https://github.com/redis/redis/pull/13172
+    ///
+    /// Their values are removed too when stored in a hash, as hash fields can't expire themselves.
+    fn cleanup<'a, 'b, 'c, R>(
+        &self,
+        batch: RedisBatch<'a, 'b, 'c, R>,
+    ) -> RedisBatch<'a, 'b, 'c, R> {
+        let now = chrono::Utc::now().timestamp_millis();
+        match self.storage {
+            TempListStorage::IndividualKeys => {
+                batch.zremrangebyscore(&self.namespace, &self.key, i64::MIN, now)
+            }
+            TempListStorage::Hash => {
+                let conn = batch.conn();
+                let invoker = CLEANUP_HASH_SCRIPT
+                    .invoker()
+                    .key(conn.final_key(&self.namespace, self.key.as_str().into()))
+                    .key(conn.final_key(&self.namespace, self.items_key().into()))
+                    .arg(now);
+                batch.script_no_return(invoker)
+            }
+        }
+    }
+
+    /// [`RedisTempList::cleanup`] for raw pipes.
+    fn cleanup_pipe(&self, pipe: &mut redis::Pipeline, conn: &RedisConn<'_>) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let list_key = conn.final_key(&self.namespace, self.key.as_str().into());
+        match self.storage {
+            TempListStorage::IndividualKeys => {
+                pipe.zrembyscore(list_key, i64::MIN, now).ignore();
+            }
+            TempListStorage::Hash => {
+                let invoker = CLEANUP_HASH_SCRIPT
+                    .invoker()
+                    .key(list_key)
+                    .key(conn.final_key(&self.namespace, self.items_key().into()))
+                    .arg(now);
+                pipe.add_command(invoker.eval_source_cmd()).ignore();
+            }
+        }
+    }
+
+    /// Reset the expire time of the list to self.list_inactive_ttl from now, along with the value hash if used.
+    fn refresh_ttl<'a, 'b, 'c, R>(
+        &self,
+        batch: RedisBatch<'a, 'b, 'c, R>,
+    ) -> RedisBatch<'a, 'b, 'c, R> {
+        let batch = batch.expire(&self.namespace, &self.key, self.list_inactive_ttl);
+        match self.storage {
+            TempListStorage::IndividualKeys => batch,
+            TempListStorage::Hash => {
+                batch.expire(&self.namespace, &self.items_key(), self.list_inactive_ttl)
+            }
+        }
+    }
+
+    /// [`RedisTempList::refresh_ttl`] for raw pipes.
+    fn refresh_ttl_pipe(&self, pipe: &mut redis::Pipeline, conn: &RedisConn<'_>) {
+        let ttl = self.list_inactive_ttl.as_millis() as i64;
+        pipe.pexpire(
+            conn.final_key(&self.namespace, self.key.as_str().into()),
+            ttl,
+        )
+        .ignore();
+        if self.storage == TempListStorage::Hash {
+            pipe.pexpire(
+                conn.final_key(&self.namespace, self.items_key().into()),
+                ttl,
+            )
+            .ignore();
+        }
+    }
+
     /// The score should be the utc timestamp to expire:
     async fn extend_inner<'a, T>(
         &self,
@@ -305,29 +738,13 @@ impl RedisTempList {
     {
         let score = (chrono::Utc::now() + self.item_inactive_ttl).timestamp_millis();
 
-        // Get the current millis, but actually increment the value if they're the same, protects against accidentally using the same ts on separate quickfire calls.
-        let last_ts_millis = self
-            .last_extension_ts_millis
-            .load(std::sync::atomic::Ordering::Relaxed);
-        let mut current_ts_millis = chrono::Utc::now().timestamp_millis();
-        if current_ts_millis <= last_ts_millis {
-            current_ts_millis = last_ts_millis + 1;
-        }
-        self.last_extension_ts_millis
-            .store(current_ts_millis, std::sync::atomic::Ordering::Relaxed);
-
-        fn generate_id(index: usize, current_ts_millis: &i64) -> String {
-            // Why am I adding the ts millis and index?
-            // When tts is the same, keys are returned reverse lexographically.
-            // We want the latest added to be first, hence the millis and index. So the last added will have the highest millis and index.
-            // This also means stable ordering whatever the generated key, useful for testing.
-            format!("{}-{}-{}", current_ts_millis, index, uuid::Uuid::new_v4())
-        }
-
+        // Why sortable ids?
+        // When tts is the same, keys are returned reverse lexographically.
+        // We want the latest added to be first, sortable ids are strictly increasing within the process so the last added will be highest.
+        // This also means stable ordering whatever the generated key, useful for testing.
         let items_with_uids = items
             .into_iter()
-            .enumerate()
-            .map(|(index, item)| (generate_id(index, &current_ts_millis), item))
+            .map(|item| (sortable_id().to_string(), item))
             .collect::<Vec<_>>();
 
         let uids = items_with_uids
@@ -335,39 +752,35 @@ impl RedisTempList {
             .map(|(uid, _)| uid.to_string())
             .collect::<Vec<_>>();
 
-        let result = conn
-            .batch()
-            // Add the uids to the main set, the command will auto update the set's ttl (self.list_inactive_ttl) given it's been updated.
-            .zadd_multi(
-                &self.namespace,
-                &self.key,
-                Some(self.list_inactive_ttl), // This will auto reset the expire time of the list as a whole
-                items_with_uids
-                    .iter()
-                    .map(|(uid, _)| (score, uid))
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            )
+        // Add the uids to the main set, the command will auto update the set's ttl (self.list_inactive_ttl) given it's been updated.
+        let batch = conn.batch().zadd_multi(
+            &self.namespace,
+            &self.key,
+            Some(self.list_inactive_ttl), // This will auto reset the expire time of the list as a whole
+            items_with_uids
+                .iter()
+                .map(|(uid, _)| (score, uid))
+                .collect::<Vec<_>>()
+                .as_slice(),
+        );
+        let values = items_with_uids
+            .into_iter()
+            .map(|(uid, item)| (uid, RedisJsonBorrowed(item)));
+        let batch = match self.storage {
             // Now store the values themselves as normal redis keys with the same ttl: (these are normal ttls that auto clean up)
-            .mset(
-                &self.namespace,
-                items_with_uids
-                    .into_iter()
-                    .map(|(uid, item)| (uid, RedisJsonBorrowed(item))),
-                Some(self.item_inactive_ttl),
-            )
-            // Cleanup old members that have now expired:
-            // (set member expiry is a logical process, not currently part of redis but could be soon)
-            // https://github.com/redis/redis/issues/135#issuecomment-2361996
-            // https://github.com/redis/redis/pull/13172
-            .zremrangebyscore(
+            TempListStorage::IndividualKeys => {
+                batch.mset(&self.namespace, values, Some(self.item_inactive_ttl))
+            }
+            // Or in the hash, which lives as long as the list:
+            TempListStorage::Hash => batch.hset_multi(
                 &self.namespace,
-                &self.key,
-                i64::MIN,
-                chrono::Utc::now().timestamp_millis(),
-            )
-            .fire()
-            .await;
+                &self.items_key(),
+                Some(self.list_inactive_ttl),
+                values,
+            ),
+        };
+        let batch = self.publish_change(batch, &TempListChange::Extended { uids: uids.clone() });
+        let result = self.cleanup(batch).fire().await;
 
         // Even though the result is empty, if result is None then something went wrong, so keep sending None outwards.
         if result.is_some() {
@@ -453,19 +866,15 @@ impl RedisTempList {
         // 2. Get the values from the uids
         // This cannot be done without a round-trip through a script because redis requires all keys used in scripts to be known ahead of time (using KEYS), so can't use that.
 
-        let item_info = conn
-            .batch()
-            // NOTE: cleaning up first as don't want these to be included in the read.
-            // Cleanup old members that have now expired:
-            // (member expiry is a logical process, not currently part of redis but could be soon)
-            // https://github.com/redis/redis/issues/135#issuecomment-2361996
-            // https://github.com/redis/redis/pull/13172
-            .zremrangebyscore(
-                &self.namespace,
-                &self.key,
-                i64::MIN,
-                chrono::Utc::now().timestamp_millis(),
-            )
+        if let Some(max_retries) = self.consistent_read_retries {
+            return self
+                .read_multi_raw_consistent(conn, limit, max_retries)
+                .await;
+        }
+
+        // NOTE: cleaning up first as don't want these to be included in the read.
+        let item_info = self
+            .cleanup(conn.batch())
             .zrangebyscore_high_to_low::<String>(
                 &self.namespace,
                 &self.key,
@@ -487,16 +896,19 @@ impl RedisTempList {
             // Don't continue if no items successfully decoded:
             if !item_info.is_empty() {
                 // Pull the items using the retrieved uids:
-                let items = conn
-                    .batch()
-                    .mget::<RedisJson<T>>(
+                let uids = item_info.iter().map(|(uid, _)| uid).collect::<Vec<_>>();
+                let batch = match self.storage {
+                    TempListStorage::IndividualKeys => {
+                        conn.batch().mget::<RedisJson<T>>(&self.namespace, &uids)
+                    }
+                    TempListStorage::Hash => conn.batch().hmget::<RedisJson<T>>(
                         &self.namespace,
-                        &item_info.iter().map(|(uid, _)| uid).collect::<Vec<_>>(),
-                    )
-                    // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
-                    .expire(&self.namespace, &self.key, self.list_inactive_ttl)
-                    .fire()
-                    .await;
+                        &self.items_key(),
+                        &uids,
+                    ),
+                };
+                // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
+                let items = self.refresh_ttl(batch).fire().await;
 
                 // Only continuing if succeeded to get:
                 if let Some(items) = items {
@@ -522,6 +934,97 @@ impl RedisTempList {
         vec![]
     }
 
+    /// [`RedisTempList::read_multi_raw`] when [`RedisTempList::with_consistent_reads`] is enabled,
+    /// the uids are re-read with the values, retrying if they changed or any value was missing.
+    async fn read_multi_raw_consistent<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
+        &self,
+        conn: &mut RedisConn<'_>,
+        limit: Option<isize>,
+        max_retries: usize,
+    ) -> Vec<(i64, String, T)> {
+        let read_uids = |pipe: &mut redis::Pipeline, conn: &RedisConn<'_>| {
+            pipe.zrevrangebyscore_limit_withscores(
+                conn.final_key(&self.namespace, self.key.as_str().into()),
+                i64::MAX,
+                i64::MIN,
+                0,
+                limit.unwrap_or(isize::MAX),
+            );
+        };
+        let Some(read) = conn
+            .consistent_two_phase::<(Vec<(String, i64)>,), (Vec<Option<Vec<u8>>>, Vec<(String, i64)>)>(
+                |conn| {
+                    let mut pipe = redis::pipe();
+                    // Cleanup old members that have now expired, as in the normal read:
+                    self.cleanup_pipe(&mut pipe, conn);
+                    read_uids(&mut pipe, conn);
+                    pipe
+                },
+                |conn, (uids,)| {
+                    let mut pipe = redis::pipe();
+                    match self.storage {
+                        // The list key is included so there's always at least 1 key, MGET returns nil for non-string keys:
+                        TempListStorage::IndividualKeys => {
+                            pipe.cmd("MGET").arg(
+                                std::iter::once(
+                                    conn.final_key(&self.namespace, self.key.as_str().into()),
+                                )
+                                .chain(
+                                    uids.iter().map(|(uid, _)| {
+                                        conn.final_key(&self.namespace, uid.into())
+                                    }),
+                                )
+                                .collect::<Vec<_>>(),
+                            );
+                        }
+                        // Likewise an empty field is included, which never exists:
+                        TempListStorage::Hash => {
+                            pipe.cmd("HMGET")
+                                .arg(conn.final_key(&self.namespace, self.items_key().into()))
+                                .arg("")
+                                .arg(uids.iter().map(|(uid, _)| uid).collect::<Vec<_>>());
+                        }
+                    }
+                    read_uids(&mut pipe, conn);
+                    // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
+                    self.refresh_ttl_pipe(&mut pipe, conn);
+                    pipe
+                },
+                |(uids,), (values, reread_uids)| {
+                    uids.iter()
+                        .map(|(uid, _)| uid)
+                        .eq(reread_uids.iter().map(|(uid, _)| uid))
+                        && values.iter().skip(1).all(Option::is_some)
+                },
+                max_retries,
+            )
+            .await
+        else {
+            return vec![];
+        };
+
+        if read.retries > 0 {
+            self.retries_made.fetch_add(read.retries, Ordering::Relaxed);
+        }
+        if !read.verified {
+            tracing::warn!(
+                "Temp list '{}' read still inconsistent after {} retries, returning the last attempt.",
+                self.key,
+                read.retries
+            );
+        }
+        let ((uids,), (values, _)) = (read.first, read.second);
+        uids.into_iter()
+            .zip(values.into_iter().skip(1))
+            // Exclude items that have expired or couldn't be deserialized to T:
+            .filter_map(|((uid, score), value)| {
+                value
+                    .and_then(|value| serde_json::from_slice::<T>(&value).ok())
+                    .map(|item| (score, uid, item))
+            })
+            .collect()
+    }
+
     /// Read multiple items from the list, ordered from last updated to least (newest to oldest).
     ///
     /// This will also:
@@ -542,88 +1045,333 @@ impl RedisTempList {
             .collect()
     }
 
-    /// Read a specific item given it's uid.
+    /// Read the newest items from many lists at once, the same as calling [`RedisTempList::read_multi`] on each with the same limit,
+    /// but in 2 round trips total rather than 2 per list. The lists can be in different namespaces.
     ///
-    /// This will also:
-    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// This will also, for each list:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now (if it has items)
     /// - Clean up expired list items
     ///
     /// Returns:
-    /// - RedisTempListItem<'a, 'c, T>: The item holder that encapsulates any error logic.
-    pub async fn read<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
-        self: &Arc<Self>, // Using arc to be cloning references into the list items rather than the full list object each time.
+    /// - Vec<Vec<RedisTempListItem<T>>>: One vec per list in the same order as `lists`, each newest to oldest up to `per_list_limit`.
+    pub async fn read_recent_multi<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
         conn: &mut RedisConn<'_>,
-        uid: &str,
-    ) -> RedisTempListItem<T> {
-        let item = conn
-            .batch()
-            // NOTE: cleaning up first as don't want these to be included in the read.
-            // Cleanup old members that have now expired:
-            // (member expiry is a logical process, not currently part of redis but could be soon)
-            // https://github.com/redis/redis/issues/135#issuecomment-2361996
-            // https://github.com/redis/redis/pull/13172
-            .zremrangebyscore(
-                &self.namespace,
-                &self.key,
+        lists: &[Arc<RedisTempList>],
+        per_list_limit: usize,
+    ) -> Vec<Vec<RedisTempListItem<T>>> {
+        let mut results = lists.iter().map(|_| vec![]).collect::<Vec<_>>();
+        if lists.is_empty() || per_list_limit == 0 {
+            return results;
+        }
+
+        // 1. Cleanup and get the uids for every list:
+        let mut pipe = redis::pipe();
+        for list in lists {
+            list.cleanup_pipe(&mut pipe, conn);
+            pipe.zrevrangebyscore_limit(
+                conn.final_key(&list.namespace, list.key.as_str().into()),
+                i64::MAX,
                 i64::MIN,
-                chrono::Utc::now().timestamp_millis(),
-            )
-            .get::<RedisJson<T>>(&self.namespace, uid)
-            // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
-            .expire(&self.namespace, &self.key, self.list_inactive_ttl)
-            .fire()
-            .await;
+                0,
+                per_list_limit as isize,
+            );
+        }
+        let Some(list_uids) = conn.query_pipe::<Vec<Vec<String>>>(&pipe).await else {
+            return results;
+        };
 
-        if let Some(item) = item.flatten() {
-            RedisTempListItem::new(Some(uid.to_string()), Some(item.0), Some(self))
-        } else {
-            RedisTempListItem::new_dummy()
+        // (list index, uid) of each item, in the order the values will be returned:
+        let owners = list_uids
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, uids)| uids.into_iter().map(move |uid| (index, uid)))
+            .collect::<Vec<_>>();
+        if owners.is_empty() {
+            return results;
+        }
+
+        // 2. Get all the values in one go, refreshing the expiry of lists that had items like read_multi().
+        // One MGET for all the lists storing values as individual keys, one HMGET per list storing them in a hash:
+        let mut pipe = redis::pipe();
+        let (keyed, hashed): (Vec<_>, Vec<_>) = owners
+            .iter()
+            .partition(|(index, _)| lists[*index].storage == TempListStorage::IndividualKeys);
+        let mut value_order = Vec::with_capacity(owners.len());
+        if !keyed.is_empty() {
+            pipe.cmd("MGET").arg(
+                keyed
+                    .iter()
+                    .map(|(index, uid)| {
+                        conn.final_key(&lists[*index].namespace, uid.as_str().into())
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            value_order.extend(keyed);
+        }
+        for (index, list) in lists.iter().enumerate() {
+            let list_uids = hashed
+                .iter()
+                .filter(|(owner, _)| *owner == index)
+                .copied()
+                .collect::<Vec<_>>();
+            if !list_uids.is_empty() {
+                pipe.cmd("HMGET")
+                    .arg(conn.final_key(&list.namespace, list.items_key().into()))
+                    .arg(list_uids.iter().map(|(_, uid)| uid).collect::<Vec<_>>());
+                value_order.extend(list_uids);
+            }
+        }
+        for (index, list) in lists.iter().enumerate() {
+            if owners.iter().any(|(owner, _)| *owner == index) {
+                list.refresh_ttl_pipe(&mut pipe, conn);
+            }
+        }
+        let Some(values) = conn.query_pipe::<Vec<Vec<Option<Vec<u8>>>>>(&pipe).await else {
+            return results;
+        };
+
+        for ((index, uid), value) in value_order
+            .into_iter()
+            .cloned()
+            .zip(values.into_iter().flatten())
+        {
+            // Exclude items that have expired or couldn't be deserialized to T:
+            if let Some(item) = value.and_then(|value| serde_json::from_slice::<T>(&value).ok()) {
+                results[index].push(RedisTempListItem::new(
+                    Some(uid),
+                    Some(item),
+                    Some(&lists[index]),
+                ));
+            }
         }
+        results
     }
 
-    /// Delete a specific item given it's uid.
+    /// Read the newest items in the list that aren't currently claimed (see [`RedisTempListItem::try_claim`]), newest to oldest.
+    ///
+    /// Claimed items are filtered out in redis, so workers polling for items to process don't repeatedly fetch ones they can't claim.
+    /// Note an item being returned doesn't mean it's claimable, another worker may claim it first, so still use [`RedisTempListItem::try_claim`].
     ///
     /// This will also:
     /// - Autoreset list's expire time to self.list_inactive_ttl from now
     /// - Clean up expired list items
-    pub async fn delete(&self, conn: &mut RedisConn<'_>, uid: &str) {
-        self.delete_multi(conn, std::iter::once(uid)).await;
+    pub async fn read_recent_unclaimed<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
+        self: &Arc<Self>, // Using arc to be cloning references into the list items rather than the full list object each time.
+        conn: &mut RedisConn<'_>,
+        limit: usize,
+    ) -> Vec<RedisTempListItem<T>> {
+        let invoker = READ_UNCLAIMED_SCRIPT
+            .invoker()
+            .key(conn.final_key(&self.namespace, self.key.as_str().into()))
+            .arg(chrono::Utc::now().timestamp_millis())
+            .arg(limit)
+            .arg(self.list_inactive_ttl.as_millis() as u64)
+            .arg(conn.final_namespace(&self.namespace))
+            .arg(CLAIM_SUFFIX)
+            .arg(self.final_items_key(conn));
+
+        conn.batch()
+            .script::<Vec<(String, String)>>(invoker)
+            .fire()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            // Exclude items which couldn't be deserialized to T:
+            .filter_map(|(uid, value)| {
+                serde_json::from_str::<T>(&value)
+                    .ok()
+                    .map(|item| RedisTempListItem::new(Some(uid), Some(item), Some(self)))
+            })
+            .collect()
     }
 
-    /// Delete multiple items via their ids.
+    /// Read the newest items in the list like [`RedisTempList::read_multi`], but only the requested top-level fields of each, newest to oldest.
+    ///
+    /// The projection happens in redis, so only the requested fields of fat items are transferred.
+    /// Items are returned as (uid, json map) pairs, as the projection generally won't deserialize to the full item type.
+    ///
+    /// - Requested fields an item doesn't have are missing from its map, items that aren't json objects project to an empty map.
+    /// - Items redis' cjson can't decode are fetched in full and projected here instead, as is the whole read if the script fails.
+    ///   Items that still can't be decoded return an empty map rather than failing the read.
+    /// - cjson re-encodes the projected fields, so numbers beyond 14 significant digits lose precision
+    ///   and empty arrays come back as empty objects.
     ///
     /// This will also:
     /// - Autoreset list's expire time to self.list_inactive_ttl from now
     /// - Clean up expired list items
-    pub async fn delete_multi<S: Into<String>>(
+    pub async fn read_recent_projected(
         &self,
         conn: &mut RedisConn<'_>,
-        uids: impl IntoIterator<Item = S>,
-    ) {
-        conn.batch()
-            // NOTE: cleaning up first as don't want these to be included in the read.
-            // Cleanup old members that have now expired:
-            // (member expiry is a logical process, not currently part of redis but could be soon)
-            // https://github.com/redis/redis/issues/135#issuecomment-2361996
-            // https://github.com/redis/redis/pull/13172
-            .zremrangebyscore(
-                &self.namespace,
-                &self.key,
-                i64::MIN,
-                chrono::Utc::now().timestamp_millis(),
-            )
-            .zrem(
-                &self.namespace,
-                &self.key,
-                uids.into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            )
-            // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
-            .expire(&self.namespace, &self.key, self.list_inactive_ttl)
+        limit: usize,
+        fields: &[&str],
+    ) -> Vec<(String, serde_json::Map<String, serde_json::Value>)> {
+        let invoker = READ_PROJECTED_SCRIPT
+            .invoker()
+            .key(conn.final_key(&self.namespace, self.key.as_str().into()))
+            .arg(chrono::Utc::now().timestamp_millis())
+            .arg(limit)
+            .arg(self.list_inactive_ttl.as_millis() as u64)
+            .arg(conn.final_namespace(&self.namespace))
+            .arg(self.final_items_key(conn));
+        let invoker = fields
+            .iter()
+            .fold(invoker, |invoker, field| invoker.arg(*field));
+
+        // None projections are items that need projecting client side:
+        let projected = match conn
+            .batch()
+            .script::<Vec<(String, Option<String>)>>(invoker)
             .fire()
-            .await;
+            .await
+        {
+            Some(projected) => projected,
+            None => {
+                // The script failed (already logged), read the uids the normal way and project everything here:
+                let batch = self
+                    .cleanup(conn.batch())
+                    .zrangebyscore_high_to_low::<String>(
+                        &self.namespace,
+                        &self.key,
+                        i64::MIN,
+                        i64::MAX,
+                        Some(limit as isize),
+                    );
+                self.refresh_ttl(batch)
+                    .fire()
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|(uid, _score)| uid.map(|uid| (uid, None)))
+                    .collect()
+            }
+        };
+
+        let unprojected = projected
+            .iter()
+            .filter(|(_, projection)| projection.is_none())
+            .map(|(uid, _)| uid.as_str())
+            .collect::<Vec<_>>();
+        let mut full_values = std::collections::HashMap::new();
+        if !unprojected.is_empty() {
+            tracing::debug!(
+                "Projecting {} temp list item(s) client side for list '{}'.",
+                unprojected.len(),
+                self.key
+            );
+            #[cfg(test)]
+            PROJECTION_FALLBACKS.fetch_add(unprojected.len(), std::sync::atomic::Ordering::Relaxed);
+            let values = match self.storage {
+                TempListStorage::IndividualKeys => {
+                    conn.batch()
+                        .mget::<String>(&self.namespace, &unprojected)
+                        .fire()
+                        .await
+                }
+                TempListStorage::Hash => {
+                    conn.batch()
+                        .hmget::<String>(&self.namespace, &self.items_key(), &unprojected)
+                        .fire()
+                        .await
+                }
+            };
+            if let Some(values) = values {
+                full_values = unprojected
+                    .into_iter()
+                    .map(String::from)
+                    .zip(values)
+                    .collect();
+            }
+        }
+
+        projected
+            .into_iter()
+            .filter_map(|(uid, projection)| {
+                let map = match projection {
+                    Some(projection) => serde_json::from_str(&projection).unwrap_or_default(),
+                    None => match full_values.remove(&uid) {
+                        Some(Some(value)) => project_fields(&value, fields),
+                        // Expired since the uids were read, skipped like read_multi():
+                        Some(None) => return None,
+                        // The full read failed (already logged):
+                        None => serde_json::Map::new(),
+                    },
+                };
+                Some((uid, map))
+            })
+            .collect()
+    }
+
+    /// Read a specific item given it's uid.
+    ///
+    /// This will also:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// - Clean up expired list items
+    ///
+    /// Returns:
+    /// - RedisTempListItem<'a, 'c, T>: The item holder that encapsulates any error logic.
+    pub async fn read<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
+        self: &Arc<Self>, // Using arc to be cloning references into the list items rather than the full list object each time.
+        conn: &mut RedisConn<'_>,
+        uid: &str,
+    ) -> RedisTempListItem<T> {
+        // NOTE: cleaning up first as don't want these to be included in the read.
+        let batch = self.cleanup(conn.batch());
+        // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
+        let item = match self.storage {
+            TempListStorage::IndividualKeys => self
+                .refresh_ttl(batch.get::<RedisJson<T>>(&self.namespace, uid))
+                .fire()
+                .await
+                .flatten(),
+            TempListStorage::Hash => self
+                .refresh_ttl(batch.hmget::<RedisJson<T>>(
+                    &self.namespace,
+                    &self.items_key(),
+                    std::iter::once(uid),
+                ))
+                .fire()
+                .await
+                .and_then(|values| values.into_iter().next().flatten()),
+        };
+
+        if let Some(item) = item {
+            RedisTempListItem::new(Some(uid.to_string()), Some(item.0), Some(self))
+        } else {
+            RedisTempListItem::new_dummy()
+        }
+    }
+
+    /// Delete a specific item given it's uid.
+    ///
+    /// This will also:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// - Clean up expired list items
+    pub async fn delete(&self, conn: &mut RedisConn<'_>, uid: &str) {
+        self.delete_multi(conn, std::iter::once(uid)).await;
+    }
+
+    /// Delete multiple items via their ids.
+    ///
+    /// This will also:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// - Clean up expired list items
+    pub async fn delete_multi<S: Into<String>>(
+        &self,
+        conn: &mut RedisConn<'_>,
+        uids: impl IntoIterator<Item = S>,
+    ) {
+        let uids = uids.into_iter().map(Into::into).collect::<Vec<String>>();
+        // NOTE: cleaning up first as don't want these to be included in the read.
+        let batch = self
+            .cleanup(conn.batch())
+            .zrem(&self.namespace, &self.key, uids.as_slice());
+        // Individual values are left to expire, but hash fields never would:
+        let batch = match self.storage {
+            TempListStorage::IndividualKeys => batch,
+            TempListStorage::Hash => batch.hdel(&self.namespace, &self.items_key(), &uids),
+        };
+        // Unlike our zadd during setting, need to manually refresh the expire time of the list here:
+        self.refresh_ttl(batch).fire().await;
     }
 
     /// Update a specific item given it's uid.
@@ -640,45 +1388,475 @@ impl RedisTempList {
     {
         let new_score = (chrono::Utc::now() + self.item_inactive_ttl).timestamp_millis();
 
-        conn.batch()
-            // This will update the uid's score/ttl, redis will automatically see it already existed (if it hadn't already expired) and update it.
-            // It will also implicitly renew the list's ttl.
-            .zadd(
-                &self.namespace,
-                &self.key,
-                Some(self.list_inactive_ttl),
-                new_score,
-                uid,
-            )
+        // This will update the uid's score/ttl, redis will automatically see it already existed (if it hadn't already expired) and update it.
+        // It will also implicitly renew the list's ttl.
+        let batch = conn.batch().zadd(
+            &self.namespace,
+            &self.key,
+            Some(self.list_inactive_ttl),
+            new_score,
+            uid,
+        );
+        let batch = match self.storage {
             // Update the value itself, which is stored under the uid as a normal redis value:
-            .set(
+            TempListStorage::IndividualKeys => batch.set(
                 &self.namespace,
                 uid,
                 RedisJsonBorrowed(item),
                 Some(self.item_inactive_ttl),
-            )
-            // Cleanup old members that have now expired:
-            // (member expiry is a logical process, not currently part of redis but could be soon)
-            // https://github.com/redis/redis/issues/135#issuecomment-2361996
-            // https://github.com/redis/redis/pull/13172
-            .zremrangebyscore(
+            ),
+            TempListStorage::Hash => batch.hset_multi(
                 &self.namespace,
-                &self.key,
+                &self.items_key(),
+                Some(self.list_inactive_ttl),
+                std::iter::once((uid, RedisJsonBorrowed(item))),
+            ),
+        };
+        self.cleanup(batch).fire().await;
+    }
+
+    /// Merge all the items from another list into this one, e.g. when merging an anonymous session's list into a user's.
+    ///
+    /// - Items keep their original scores, so recency interleaves correctly with existing items.
+    /// - Item values are copied (so the source can be safely deleted), keeping their remaining ttls.
+    /// - If a uid somehow already exists in this list, the moved item is given a newly generated uid.
+    ///
+    /// This will also:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// - Clean up expired list items
+    /// - Announce a [`TempListChange::Extended`] with the merged uids, and [`TempListChange::Clear`] on the source if deleted,
+    ///   when enabled with [`RedisTempList::with_change_events`]
+    ///
+    /// Arguments:
+    /// - `source`: The list to merge items from.
+    /// - `delete_source`: Clear the source list after merging.
+    pub async fn merge_from(
+        &self,
+        conn: &mut RedisConn<'_>,
+        source: &RedisTempList,
+        delete_source: bool,
+    ) -> MergeReport {
+        let mut report = MergeReport::default();
+        let mut merged_uids = vec![];
+
+        // Cleanup old members that have now expired, these shouldn't be merged:
+        let source_items = source
+            .cleanup(conn.batch())
+            .zrangebyscore_low_to_high::<String>(
+                &source.namespace,
+                &source.key,
                 i64::MIN,
-                chrono::Utc::now().timestamp_millis(),
+                i64::MAX,
+                None,
+            )
+            .fire()
+            .await;
+        let source_items = source_items
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(uid, score)| uid.map(|uid| (uid, score)))
+            .collect::<Vec<_>>();
+
+        let mut to_merge = source_items
+            .iter()
+            .map(|(uid, score)| (uid.as_str(), uid.clone(), *score))
+            .collect::<Vec<_>>();
+        // First attempt is with the original uids, the second only happens if there were uid collisions:
+        for _ in 0..2 {
+            if to_merge.is_empty() {
+                break;
+            }
+
+            let mut invoker = MERGE_SCRIPT
+                .invoker()
+                .key(conn.final_key(&self.namespace, self.key.as_str().into()))
+                .arg(self.list_inactive_ttl.as_millis() as u64)
+                .arg(chrono::Utc::now().timestamp_millis())
+                .arg(source.final_items_key(conn))
+                .arg(self.final_items_key(conn));
+            for (source_uid, dest_uid, score) in &to_merge {
+                invoker = invoker
+                    .key(conn.final_key(&source.namespace, (*source_uid).into()))
+                    .key(conn.final_key(&self.namespace, dest_uid.as_str().into()))
+                    .arg(*source_uid)
+                    .arg(dest_uid)
+                    .arg(*score);
+            }
+
+            let result = self
+                .cleanup(
+                    conn.batch()
+                        .script::<(Vec<String>, usize, Vec<usize>)>(invoker),
+                )
+                .fire()
+                .await;
+
+            if let Some((merged, missing, collided)) = result {
+                report.merged += merged.len();
+                merged_uids.extend(merged);
+                report.missing += missing;
+                report.uid_collisions += collided.len();
+                // Regenerate the uids for any collisions, keeping the timestamp to maintain ordering:
+                to_merge = collided
+                    .into_iter()
+                    .filter_map(|index| to_merge.get(index))
+                    .map(|(source_uid, dest_uid, score)| {
+                        let regenerated = match dest_uid.parse::<SortableId>() {
+                            Ok(id) => sortable_id_with_time(id.timestamp()).to_string(),
+                            // Uids from before sortable ids were timestamp-index-uuid:
+                            Err(_) => {
+                                let prefix = dest_uid
+                                    .splitn(3, '-')
+                                    .take(2)
+                                    .collect::<Vec<_>>()
+                                    .join("-");
+                                format!("{}-{}", prefix, uuid::Uuid::new_v4())
+                            }
+                        };
+                        (*source_uid, regenerated, *score)
+                    })
+                    .collect();
+            } else {
+                break;
+            }
+        }
+
+        if self.change_events && !merged_uids.is_empty() {
+            self.publish_change(
+                conn.batch(),
+                &TempListChange::Extended { uids: merged_uids },
             )
             .fire()
             .await;
+        }
+
+        if delete_source {
+            source.clear(conn).await;
+        }
+
+        report
+    }
+
+    /// The number of items in the list, without reading them.
+    ///
+    /// This will also:
+    /// - Autoreset list's expire time to self.list_inactive_ttl from now
+    /// - Clean up expired list items, so they aren't counted
+    ///
+    /// Returns None if redis is unavailable.
+    pub async fn len(&self, conn: &mut RedisConn<'_>) -> Option<usize> {
+        let batch = self.cleanup(conn.batch()).zcard(&self.namespace, &self.key);
+        let count = self.refresh_ttl(batch).fire().await?;
+        Some(count.max(0) as usize)
+    }
+
+    /// How long until the list expires from inactivity, zero if it doesn't exist (never written to or already expired).
+    ///
+    /// Unlike the other reads, this doesn't reset the list's expire time, otherwise it would always be self.list_inactive_ttl.
+    ///
+    /// Returns None if redis is unavailable.
+    pub async fn ttl_remaining(&self, conn: &mut RedisConn<'_>) -> Option<chrono::TimeDelta> {
+        let pttl = conn.batch().pttl(&self.namespace, &self.key).fire().await?;
+        // Negative when missing (-2) or without an expiry (-1, which the list never is):
+        Some(chrono::TimeDelta::milliseconds(pttl.max(0)))
     }
 
     /// Clear all the items in the list.
-    /// (by just deleting the list itself, stored values will still live until their ttl but used a random uid so no conflicts)
+    /// (by just deleting the list itself, stored values will still live until their ttl but used a random uid so no conflicts,
+    /// the value hash is deleted with the list when used)
     pub async fn clear(&self, conn: &mut RedisConn<'_>) {
-        conn.batch()
-            .clear(&self.namespace, std::iter::once(self.key.as_str()))
+        let items_key = self.items_key();
+        let keys = match self.storage {
+            TempListStorage::IndividualKeys => vec![self.key.as_str()],
+            TempListStorage::Hash => vec![self.key.as_str(), items_key.as_str()],
+        };
+        let batch = conn.batch().clear(&self.namespace, keys);
+        self.publish_change(batch, &TempListChange::Clear)
             .fire()
             .await;
     }
+
+    /// Pass the items expiring within `window` that haven't been archived yet to the archiver set with [`RedisTempList::with_archiver`].
+    ///
+    /// - Sweeps of the same list are serialized with a dlock across processes, so each item is archived once.
+    ///   If another sweep holds the lock, this returns Ok(0) without archiving.
+    /// - Items already expired are never archived, so `window` should comfortably cover the time between sweeps.
+    /// - An item updated after being archived has a later expiry, so is archived again when that nears.
+    /// - If the archiver errors the items aren't marked, so are retried on the next sweep.
+    ///
+    /// Returns the number of items archived.
+    pub async fn archive_expiring(
+        &self,
+        redis: &super::Redis,
+        window: Duration,
+    ) -> RResult<usize, AnyErr> {
+        let Some(archiver) = self.archiver.clone() else {
+            return Err(anyerr!(
+                "No archiver set on the temp list, see with_archiver()."
+            ));
+        };
+        let lock_key = format!("{}:{}", self.namespace, self.key);
+        match redis
+            .dlock_for_fut(
+                ARCHIVE_LOCK_NAMESPACE,
+                &lock_key,
+                None,
+                self.archive_expiring_locked(redis, window, archiver),
+            )
+            .await
+        {
+            Ok(archived) => Ok(archived),
+            Err(e) if matches!(e.current_context(), RedisLockErr::Contended { .. }) => Ok(0),
+            Err(e) => Err(e.change_context(AnyErr)),
+        }
+    }
+
+    async fn archive_expiring_locked(
+        &self,
+        redis: &super::Redis,
+        window: Duration,
+        archiver: Archiver,
+    ) -> RResult<usize, AnyErr> {
+        let mut conn = redis.conn();
+        let archived_key = format!("{}{}", self.key, ARCHIVED_SUFFIX);
+        let now = chrono::Utc::now().timestamp_millis();
+        let invoker = ARCHIVE_PENDING_SCRIPT
+            .invoker()
+            .key(conn.final_key(&self.namespace, self.key.as_str().into()))
+            .key(conn.final_key(&self.namespace, archived_key.as_str().into()))
+            .arg(now)
+            .arg(now + window.as_millis() as i64)
+            .arg(conn.final_namespace(&self.namespace))
+            .arg(self.final_items_key(&conn));
+        let pending = conn
+            .batch()
+            .script::<Vec<(String, i64, String)>>(invoker)
+            .fire()
+            .await
+            .ok_or_else(|| anyerr!("Failed to read the temp list's expiring items."))?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let mut scores = Vec::with_capacity(pending.len());
+        let mut batch = Vec::with_capacity(pending.len());
+        for (uid, score, value) in pending {
+            match serde_json::from_str::<serde_json::Value>(&value) {
+                Ok(value) => {
+                    scores.push((score, uid.clone()));
+                    batch.push((uid, value));
+                }
+                // Can't ever be archived, so don't hold up the rest:
+                Err(e) => crate::log::record_exception(
+                    format!(
+                        "Temp list item '{}' could not be decoded for archival.",
+                        uid
+                    ),
+                    crate::errors::LazyDebug(&e),
+                ),
+            }
+        }
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        (archiver.0)(batch).await?;
+
+        // Marks only need to outlive the items, which expire within item_inactive_ttl of their last write:
+        conn.batch()
+            .zadd_multi(
+                &self.namespace,
+                &archived_key,
+                Some(self.item_inactive_ttl),
+                scores
+                    .iter()
+                    .map(|(score, uid)| (*score, uid))
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+            )
+            .fire()
+            .await
+            .ok_or_else(|| anyerr!("Items archived, but failed to mark them as archived."))?;
+        Ok(scores.len())
+    }
+
+    /// Spawn a background task calling [`RedisTempList::archive_expiring`] every `check_interval`,
+    /// with a window of twice the interval so items expiring before the next sweep are always caught.
+    ///
+    /// Failed sweeps are recorded as exceptions and retried on the next.
+    ///
+    /// Must be called from within a tokio runtime, abort the returned handle to stop archiving.
+    pub fn spawn_archiver(
+        self: &Arc<Self>,
+        redis: &super::Redis,
+        check_interval: Duration,
+    ) -> RResult<tokio::task::JoinHandle<()>, AnyErr> {
+        if self.archiver.is_none() {
+            return Err(anyerr!(
+                "No archiver set on the temp list, see with_archiver()."
+            ));
+        }
+        let list = self.clone();
+        let redis = redis.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                if let Err(e) = list.archive_expiring(&redis, check_interval * 2).await {
+                    crate::log::record_exception(
+                        format!("Temp list '{}' archive sweep failed.", list.key),
+                        crate::errors::LazyDebug(&e),
+                    );
+                }
+                tokio::time::sleep(check_interval).await;
+            }
+        }))
+    }
+
+    /// Bind the list to a single item type, so all reads and writes use the same `T` without repeating it,
+    /// see [`RedisTempListTyped`].
+    pub fn typed<T: serde::Serialize + for<'a> serde::Deserialize<'a>>(
+        self: &Arc<Self>,
+    ) -> RedisTempListTyped<T> {
+        RedisTempListTyped::new(self.clone())
+    }
+}
+
+/// A [`RedisTempList`] with its item type bound at construction, created with [`RedisTempList::typed`] or [`super::Redis::templist_typed`].
+///
+/// The methods are the same as the untyped list, just without needing to specify `T`,
+/// so one call site can't accidentally read or write the list as a different type:
+/// ```compile_fail
+/// use bitbazaar::redis::{Redis, RedisTempListTyped};
+///
+/// async fn mixed(r: &Redis, list: &RedisTempListTyped<String>) {
+///     let mut conn = r.conn();
+///     list.push(&mut conn, 1_u32).await;
+/// }
+/// ```
+///
+/// Items are normal [`RedisTempListItem`]s, so [`RedisTempListItem::with_conn`] and its [`FlexiLog`] impl work the same.
+/// Use [`RedisTempListTyped::untyped`] for anything only on the raw list, e.g. [`RedisTempList::merge_from`].
+#[derive(Debug)]
+pub struct RedisTempListTyped<T> {
+    list: Arc<RedisTempList>,
+    // fn() -> T so the handle is Send + Sync whatever T is, it never holds one.
+    _item: PhantomData<fn() -> T>,
+}
+
+// Manual impl, a derive would needlessly require T: Clone.
+impl<T> Clone for RedisTempListTyped<T> {
+    fn clone(&self) -> Self {
+        Self {
+            list: self.list.clone(),
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T: serde::Serialize + for<'a> serde::Deserialize<'a>> RedisTempListTyped<T> {
+    pub(crate) fn new(list: Arc<RedisTempList>) -> Self {
+        Self {
+            list,
+            _item: PhantomData,
+        }
+    }
+
+    /// The underlying untyped list.
+    pub fn untyped(&self) -> &Arc<RedisTempList> {
+        &self.list
+    }
+
+    /// See [`RedisTempList::push`]
+    pub async fn push(&self, conn: &mut RedisConn<'_>, item: T) -> RedisTempListItem<T> {
+        self.list.push(conn, item).await
+    }
+
+    /// See [`RedisTempList::extend`]
+    pub async fn extend(
+        &self,
+        conn: &mut RedisConn<'_>,
+        items: impl IntoIterator<Item = T>,
+    ) -> Vec<RedisTempListItem<T>> {
+        self.list.extend(conn, items).await
+    }
+
+    /// See [`RedisTempList::read_multi_raw`]
+    pub async fn read_multi_raw(
+        &self,
+        conn: &mut RedisConn<'_>,
+        limit: Option<isize>,
+    ) -> Vec<(i64, String, T)> {
+        self.list.read_multi_raw(conn, limit).await
+    }
+
+    /// See [`RedisTempList::read_multi`]
+    pub async fn read_multi(
+        &self,
+        conn: &mut RedisConn<'_>,
+        limit: Option<isize>,
+    ) -> Vec<RedisTempListItem<T>> {
+        self.list.read_multi(conn, limit).await
+    }
+
+    /// See [`RedisTempList::read_recent_unclaimed`]
+    pub async fn read_recent_unclaimed(
+        &self,
+        conn: &mut RedisConn<'_>,
+        limit: usize,
+    ) -> Vec<RedisTempListItem<T>> {
+        self.list.read_recent_unclaimed(conn, limit).await
+    }
+
+    /// See [`RedisTempList::read_recent_multi`], all the lists share the item type.
+    pub async fn read_recent_multi(
+        conn: &mut RedisConn<'_>,
+        lists: &[RedisTempListTyped<T>],
+        per_list_limit: usize,
+    ) -> Vec<Vec<RedisTempListItem<T>>> {
+        let lists = lists
+            .iter()
+            .map(|list| list.list.clone())
+            .collect::<Vec<_>>();
+        RedisTempList::read_recent_multi(conn, &lists, per_list_limit).await
+    }
+
+    /// See [`RedisTempList::read`]
+    pub async fn read(&self, conn: &mut RedisConn<'_>, uid: &str) -> RedisTempListItem<T> {
+        self.list.read(conn, uid).await
+    }
+
+    /// See [`RedisTempList::update`]
+    pub async fn update(&self, conn: &mut RedisConn<'_>, uid: &str, item: &T) {
+        self.list.update(conn, uid, item).await
+    }
+
+    /// See [`RedisTempList::delete`]
+    pub async fn delete(&self, conn: &mut RedisConn<'_>, uid: &str) {
+        self.list.delete(conn, uid).await
+    }
+
+    /// See [`RedisTempList::delete_multi`]
+    pub async fn delete_multi<S: Into<String>>(
+        &self,
+        conn: &mut RedisConn<'_>,
+        uids: impl IntoIterator<Item = S>,
+    ) {
+        self.list.delete_multi(conn, uids).await
+    }
+
+    /// See [`RedisTempList::len`]
+    pub async fn len(&self, conn: &mut RedisConn<'_>) -> Option<usize> {
+        self.list.len(conn).await
+    }
+
+    /// See [`RedisTempList::ttl_remaining`]
+    pub async fn ttl_remaining(&self, conn: &mut RedisConn<'_>) -> Option<chrono::TimeDelta> {
+        self.list.ttl_remaining(conn).await
+    }
+
+    /// See [`RedisTempList::clear`]
+    pub async fn clear(&self, conn: &mut RedisConn<'_>) {
+        self.list.clear(conn).await
+    }
 }
 
 #[cfg(test)]
@@ -688,18 +1866,25 @@ pub struct ExampleObject {
     pub b: String,
 }
 
-/// Run by the main tester that spawns up a redis process.
+/// Run by the tester that spawns up a redis process, once per storage layout.
 #[cfg(test)]
-pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
+pub async fn redis_temp_list_tests(
+    r: &super::Redis,
+    storage: TempListStorage,
+) -> RResult<(), AnyErr> {
     // Just checking the object is normal: (from upstream)
     fn is_normal<T: Sized + Send + Sync + Unpin>() {}
     is_normal::<RedisTempList>();
 
     let mut conn = r.conn();
+    let templist = |namespace: &'static str, key: &str, list_ttl: Duration, item_ttl: Duration| {
+        r.templist(namespace, key, list_ttl, item_ttl)
+            .with_storage(storage)
+    };
 
     static NS: &str = "templist_tests";
 
-    let li1 = r.templist(
+    let li1 = templist(
         NS,
         "t1",
         Duration::from_millis(100),
@@ -745,7 +1930,7 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
     );
 
     // Let's create a strange list, one with a short list lifetime but effectively infinite item lifetime:
-    let li2 = r.templist(
+    let li2 = templist(
         NS,
         "t2",
         Duration::from_millis(50),
@@ -815,7 +2000,7 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
     );
 
     // Make sure manual clear() works on a new list too:
-    let li3 = r.templist(
+    let li3 = templist(
         NS,
         "t3",
         Duration::from_millis(100),
@@ -837,7 +2022,7 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
     );
 
     // Try with arb value, e.g. vec of (i32, String):
-    let li4 = r.templist(
+    let li4 = templist(
         NS,
         "t4",
         Duration::from_millis(100),
@@ -861,7 +2046,7 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
     );
 
     // Try with json value:
-    let li5 = r.templist(
+    let li5 = templist(
         NS,
         "t5",
         Duration::from_millis(100),
@@ -896,7 +2081,7 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
     );
 
     // Make sure duplicate values don't break the list and are still kept:
-    let li6 = r.templist(
+    let li6 = templist(
         NS,
         "t6",
         Duration::from_millis(100),
@@ -917,5 +2102,842 @@ pub async fn redis_temp_list_tests(r: &super::Redis) -> Result<(), AnyErr> {
         vec!["i3", "i1", "i2", "i1"]
     );
 
+    // The typed handle should work without any turbofish, with items the same as the untyped list's:
+    let example = |a: i32| ExampleObject {
+        a,
+        b: a.to_string(),
+    };
+    let typed = templist(
+        NS,
+        "typed",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    )
+    .typed::<ExampleObject>();
+    let mut item = typed.push(&mut conn, example(1)).await;
+    typed.extend(&mut conn, vec![example(2), example(3)]).await;
+    assert_eq!(
+        RedisTempListItem::vec_items(typed.read_multi(&mut conn, None).await),
+        vec![example(3), example(2), example(1)]
+    );
+    assert_eq!(
+        typed
+            .read_multi_raw(&mut conn, Some(1))
+            .await
+            .into_iter()
+            .map(|(_score, _uid, item)| item)
+            .collect::<Vec<_>>(),
+        vec![example(3)]
+    );
+    assert_eq!(typed.read_recent_unclaimed(&mut conn, 10).await.len(), 3);
+    let typed_other = templist(
+        NS,
+        "typed_other",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    )
+    .typed::<ExampleObject>();
+    typed_other.push(&mut conn, example(10)).await;
+    assert_eq!(
+        RedisTempListTyped::read_recent_multi(&mut conn, &[typed.clone(), typed_other], 2)
+            .await
+            .into_iter()
+            .map(RedisTempListItem::vec_items)
+            .collect::<Vec<_>>(),
+        vec![vec![example(3), example(2)], vec![example(10)]]
+    );
+    let uid = item.uid().unwrap().to_string();
+    typed.update(&mut conn, &uid, &example(4)).await;
+    assert_eq!(
+        typed.read(&mut conn, &uid).await.into_item(),
+        Some(example(4))
+    );
+    // Items still interop with the conn wrapper:
+    item.with_conn(&mut conn).update(|item| item.a = 5).await;
+    assert_eq!(
+        typed.read(&mut conn, &uid).await.into_item(),
+        Some(ExampleObject {
+            a: 5,
+            b: "1".to_string()
+        })
+    );
+    // The untyped escape hatch is the same list:
+    assert_eq!(
+        RedisTempListItem::vec_items(
+            typed
+                .untyped()
+                .read_multi::<ExampleObject>(&mut conn, None)
+                .await
+        )
+        .len(),
+        3
+    );
+    // The update bumped item 1 to the front:
+    let first_two = typed
+        .read_multi(&mut conn, Some(2))
+        .await
+        .into_iter()
+        .map(|item| item.uid().unwrap().to_string())
+        .collect::<Vec<_>>();
+    typed.delete_multi(&mut conn, first_two).await;
+    assert_eq!(
+        RedisTempListItem::vec_items(typed.clone().read_multi(&mut conn, None).await),
+        vec![example(2)]
+    );
+    typed.clear(&mut conn).await;
+    assert_eq!(
+        RedisTempListItem::vec_items(typed.read_multi(&mut conn, None).await),
+        vec![]
+    );
+
+    // FlexiLog items should log through the typed handle's items:
+    #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+    struct TaskLog {
+        logs: parking_lot::Mutex<Vec<String>>,
+    }
+    impl FlexiLog for TaskLog {
+        async fn set_progress(&self, progress: f64) {
+            self.logs.lock().push(format!("progress: {}", progress));
+        }
+
+        async fn log_with_opts(
+            &self,
+            _lvl: tracing::Level,
+            msg: String,
+            _force_replace_prior: bool,
+        ) {
+            self.logs.lock().push(msg);
+        }
+    }
+    let typed_logs = templist(
+        NS,
+        "typed_logs",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    )
+    .typed::<TaskLog>();
+    let mut log_item = typed_logs.push(&mut conn, TaskLog::default()).await;
+    let log_uid = log_item.uid().unwrap().to_string();
+    {
+        let logger = log_item.with_conn(&mut conn);
+        logger.log_info("started").await;
+        logger.set_progress(0.5).await;
+    }
+    assert_eq!(
+        typed_logs
+            .read(&mut conn, &log_uid)
+            .await
+            .into_item()
+            .map(|item| item.logs.into_inner()),
+        Some(vec!["started".to_string(), "progress: 0.5".to_string()])
+    );
+    typed_logs.delete(&mut conn, &log_uid).await;
+    assert!(typed_logs
+        .read(&mut conn, &log_uid)
+        .await
+        .into_item()
+        .is_none());
+
+    // Consistent reads shouldn't drop items when the list is written to during the read:
+    let consistent = r
+        .templist(
+            NS,
+            "consistent",
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+        .with_consistent_reads(50);
+    consistent
+        .extend(&mut conn, (0..5).map(|index| index.to_string()))
+        .await;
+    let writing = std::sync::atomic::AtomicBool::new(true);
+    let writer = async {
+        let mut conn = r.conn();
+        for index in 5..300 {
+            consistent.push(&mut conn, index.to_string()).await;
+        }
+        writing.store(false, Ordering::SeqCst);
+    };
+    let reader = async {
+        let mut conn = r.conn();
+        let mut reads = 0;
+        while writing.load(Ordering::SeqCst) {
+            // The list only grows, so fewer than the limit would mean a uid was read without its value:
+            assert_eq!(
+                consistent
+                    .read_multi_raw::<String>(&mut conn, Some(5))
+                    .await
+                    .len(),
+                5
+            );
+            reads += 1;
+        }
+        reads
+    };
+    let ((), reads) = tokio::join!(writer, reader);
+    assert!(reads > 0);
+    // Other handles to the same list don't read consistently unless enabled, and count their own retries:
+    let inconsistent = templist(
+        NS,
+        "consistent",
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+    );
+    assert_eq!(inconsistent.consistent_read_retries, None);
+    assert_eq!(inconsistent.consistent_read_retries_made(), 0);
+    let retries_made = consistent.consistent_read_retries_made();
+    assert_eq!(
+        RedisTempListItem::vec_items(consistent.read_multi::<String>(&mut conn, Some(2)).await),
+        vec!["299", "298"]
+    );
+    assert_eq!(consistent.consistent_read_retries_made(), retries_made);
+    consistent.clear(&mut conn).await;
+
+    // Merging lists should interleave by recency, keep ttls and optionally clear the source:
+    let dest = templist(
+        NS,
+        "merge_dest",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    )
+    .with_change_events();
+    let source = templist(
+        NS,
+        "merge_source",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    )
+    .with_change_events();
+    for (li, item) in [
+        (&dest, "d1"),
+        (&source, "s1"),
+        (&dest, "d2"),
+        (&source, "s2"),
+    ] {
+        li.push(&mut conn, item.to_string()).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut dest_changes = dest.subscribe_changes(r).await.unwrap();
+    let mut source_changes = source.subscribe_changes(r).await.unwrap();
+    let report = dest.merge_from(&mut conn, &source, true).await;
+    assert_eq!(
+        report,
+        MergeReport {
+            merged: 2,
+            missing: 0,
+            uid_collisions: 0
+        }
+    );
+    let merged = dest.read_multi::<String>(&mut conn, None).await;
+    assert_eq!(
+        merged
+            .iter()
+            .filter_map(|item| item.item().cloned())
+            .collect::<Vec<_>>(),
+        vec!["s2", "d2", "s1", "d1"]
+    );
+    // Copied values should have kept their remaining ttl, rather than being reset to the full item ttl:
+    let s2_uid = merged[0].uid().unwrap().to_string();
+    let pttl = match storage {
+        TempListStorage::IndividualKeys => {
+            let s2_key = conn.final_key(NS, s2_uid.into());
+            redis::cmd("PTTL")
+                .arg(s2_key)
+                .query_async(conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?
+        }
+        // Hash values expire by their score alone:
+        TempListStorage::Hash => {
+            let score: i64 = redis::cmd("ZSCORE")
+                .arg(conn.final_key(NS, dest.key.as_str().into()))
+                .arg(s2_uid)
+                .query_async(conn.get_inner_conn().await.unwrap())
+                .await
+                .change_context(AnyErr)?;
+            score - chrono::Utc::now().timestamp_millis()
+        }
+    };
+    assert!(pttl > 0 && pttl < 260, "{}", pttl);
+    // Source should have been cleared:
+    assert_eq!(source.read_multi::<String>(&mut conn, None).await.len(), 0);
+    // Subscribers on both lists should have been told, the destination with the merged uids oldest first:
+    async fn recv(
+        listener: &mut RedisChannelListener<TempListChange>,
+    ) -> RResult<Option<TempListChange>, AnyErr> {
+        tokio::time::timeout(Duration::from_secs(1), listener.recv())
+            .await
+            .change_context(AnyErr)
+    }
+    assert_eq!(
+        recv(&mut dest_changes).await?,
+        Some(TempListChange::Extended {
+            uids: vec![
+                merged[2].uid().unwrap().to_string(),
+                merged[0].uid().unwrap().to_string()
+            ]
+        })
+    );
+    assert_eq!(
+        recv(&mut source_changes).await?,
+        Some(TempListChange::Clear)
+    );
+    // Plain pushes are announced too:
+    let pushed = dest.push(&mut conn, "d3".to_string()).await;
+    assert_eq!(
+        recv(&mut dest_changes).await?,
+        Some(TempListChange::Extended {
+            uids: vec![pushed.uid().unwrap().to_string()]
+        })
+    );
+    pushed.delete(&mut conn).await;
+    // Handles without change events don't publish:
+    let quiet = templist(
+        NS,
+        "merge_dest",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    );
+    let pushed = quiet.push(&mut conn, "d4".to_string()).await;
+    assert!(recv(&mut dest_changes).await.is_err());
+    pushed.delete(&mut conn).await;
+
+    // Merging again with the same uids in both lists (not cleared) should regenerate the collided uids:
+    let other = templist(
+        NS,
+        "merge_other",
+        Duration::from_millis(500),
+        Duration::from_millis(300),
+    );
+    other.merge_from(&mut conn, &dest, false).await;
+    let report = other.merge_from(&mut conn, &dest, false).await;
+    assert_eq!(report.uid_collisions, 4);
+    assert_eq!(report.merged, 4);
+    assert_eq!(other.read_multi::<String>(&mut conn, None).await.len(), 8);
+
+    // <--- Item claims:
+    let claims = templist(
+        NS,
+        "claims",
+        Duration::from_millis(1000),
+        Duration::from_millis(1000),
+    );
+    let items = claims
+        .extend(&mut conn, vec!["c1".to_string(), "c2".to_string()])
+        .await;
+    let c2 = items[1].clone();
+    async fn unclaimed(list: &Arc<RedisTempList>, conn: &mut RedisConn<'_>) -> Vec<String> {
+        RedisTempListItem::vec_items(list.read_recent_unclaimed::<String>(conn, 10).await)
+    }
+
+    // Two concurrent claimers on one item, only one should win:
+    let mut conn2 = r.conn();
+    let (claim_a, claim_b) = tokio::join!(
+        c2.try_claim(&mut conn, chrono::TimeDelta::milliseconds(150)),
+        c2.try_claim(&mut conn2, chrono::TimeDelta::milliseconds(150)),
+    );
+    assert!(claim_a.is_some() != claim_b.is_some());
+    let claim = claim_a.or(claim_b).unwrap();
+    assert_eq!(Some(claim.uid()), c2.uid());
+
+    // Claimed item excluded until the claim ttl lapses:
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c1"]);
+    assert_eq!(
+        RedisTempListItem::vec_items(claims.read_recent_unclaimed::<String>(&mut conn, 1).await),
+        vec!["c1"]
+    );
+    // Extension keeps it held past the original ttl:
+    assert!(
+        claim
+            .extend(&mut conn, chrono::TimeDelta::milliseconds(400))
+            .await
+    );
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c1"]);
+    assert!(c2
+        .try_claim(&mut conn, chrono::TimeDelta::milliseconds(150))
+        .await
+        .is_none());
+    // Release makes it visible again immediately:
+    assert!(claim.release(&mut conn).await);
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c2", "c1"]);
+
+    // Claims expire so crashed workers free their items:
+    let claim = c2
+        .try_claim(&mut conn, chrono::TimeDelta::milliseconds(100))
+        .await
+        .unwrap();
+    std::mem::forget(claim);
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c1"]);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c2", "c1"]);
+
+    // Dropping releases in the background:
+    let claim = c2
+        .try_claim(&mut conn, chrono::TimeDelta::milliseconds(1000))
+        .await
+        .unwrap();
+    drop(claim);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(unclaimed(&claims, &mut conn).await, vec!["c2", "c1"]);
+
+    // Empty holders can't be claimed:
+    assert!(RedisTempListItem::<String>::new_dummy()
+        .try_claim(&mut conn, chrono::TimeDelta::milliseconds(100))
+        .await
+        .is_none());
+
+    // Reading many lists at once should match reading each individually, lists can be in different namespaces.
+    // Always 2 round trips by construction: one pipe for all the uids, one for all the values.
+    let multi_lists = vec![
+        templist(NS, "multi1", Duration::from_secs(5), Duration::from_secs(5)),
+        templist(
+            "templist_tests_other",
+            "multi2",
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        ),
+        templist(
+            NS,
+            "multi_empty",
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        ),
+        templist(NS, "multi3", Duration::from_secs(5), Duration::from_secs(5)),
+    ];
+    multi_lists[0]
+        .extend(&mut conn, ["a1", "a2", "a3", "a4"].map(String::from))
+        .await;
+    multi_lists[1]
+        .extend(&mut conn, ["b1"].map(String::from))
+        .await;
+    multi_lists[3]
+        .extend(&mut conn, ["c1", "c2", "c3"].map(String::from))
+        .await;
+    let multi = RedisTempList::read_recent_multi::<String>(&mut conn, &multi_lists, 3)
+        .await
+        .into_iter()
+        .map(RedisTempListItem::vec_items)
+        .collect::<Vec<_>>();
+    let mut individual = vec![];
+    for list in &multi_lists {
+        individual.push(RedisTempListItem::vec_items(
+            list.read_multi::<String>(&mut conn, Some(3)).await,
+        ));
+    }
+    assert_eq!(multi, individual);
+    assert_eq!(
+        multi,
+        vec![
+            vec!["a4", "a3", "a2"],
+            vec!["b1"],
+            vec![],
+            vec!["c3", "c2", "c1"],
+        ]
+    );
+    // Items are usable like any other:
+    let mut multi = RedisTempList::read_recent_multi::<String>(&mut conn, &multi_lists, 1).await;
+    multi[1].remove(0).delete(&mut conn).await;
+    assert_eq!(
+        RedisTempListItem::vec_items(multi_lists[1].read_multi::<String>(&mut conn, None).await),
+        Vec::<String>::new()
+    );
+    assert!(
+        RedisTempList::read_recent_multi::<String>(&mut conn, &[], 3)
+            .await
+            .is_empty()
+    );
+
+    // <--- Projected reads:
+    let projected = templist(
+        NS,
+        "projected",
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+    );
+    let padding = "x".repeat(100_000);
+    let items = projected
+        .extend(
+            &mut conn,
+            (1..=3)
+                .map(|i| {
+                    serde_json::json!({
+                        "title": format!("t{}", i),
+                        "status": if i == 2 { serde_json::Value::Null } else { "open".into() },
+                        "padding": padding,
+                        "nested": {"padding": padding},
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )
+        .await;
+    let fallbacks_before = PROJECTION_FALLBACKS.load(std::sync::atomic::Ordering::Relaxed);
+    let read = projected
+        .read_recent_projected(&mut conn, 10, &["title", "status", "missing"])
+        .await;
+    // Same order as read_multi():
+    assert_eq!(
+        read.iter().map(|(uid, _)| uid.as_str()).collect::<Vec<_>>(),
+        projected
+            .read_multi::<serde_json::Value>(&mut conn, None)
+            .await
+            .iter()
+            .map(|item| item.uid().unwrap())
+            .collect::<Vec<_>>()
+    );
+    // Exactly the requested fields that exist, nulls included:
+    assert_eq!(
+        read.into_iter().map(|(_, map)| map).collect::<Vec<_>>(),
+        vec![
+            serde_json::json!({"title": "t3", "status": "open"}),
+            serde_json::json!({"title": "t2", "status": null}),
+            serde_json::json!({"title": "t1", "status": "open"}),
+        ]
+        .into_iter()
+        .map(|value| value.as_object().unwrap().clone())
+        .collect::<Vec<_>>()
+    );
+    // Limit keeps the newest, all projected in redis, nothing fetched in full:
+    assert_eq!(
+        projected
+            .read_recent_projected(&mut conn, 1, &["title"])
+            .await
+            .into_iter()
+            .map(|(_, map)| map)
+            .collect::<Vec<_>>(),
+        vec![serde_json::json!({"title": "t3"})
+            .as_object()
+            .unwrap()
+            .clone()]
+    );
+    assert_eq!(
+        PROJECTION_FALLBACKS.load(std::sync::atomic::Ordering::Relaxed),
+        fallbacks_before
+    );
+
+    // A payload cjson can't decode falls back to a client side projection for that item only,
+    // still empty as it isn't json at all, without failing the read:
+    match storage {
+        TempListStorage::IndividualKeys => {
+            conn.batch()
+                .set(
+                    NS,
+                    items[1].uid().unwrap(),
+                    "not json",
+                    Some(Duration::from_secs(5)),
+                )
+                .fire()
+                .await
+        }
+        TempListStorage::Hash => {
+            conn.batch()
+                .hset_multi(
+                    NS,
+                    &projected.items_key(),
+                    None,
+                    std::iter::once((items[1].uid().unwrap(), "not json")),
+                )
+                .fire()
+                .await
+        }
+    };
+    let read = projected
+        .read_recent_projected(&mut conn, 10, &["title"])
+        .await;
+    assert_eq!(
+        PROJECTION_FALLBACKS.load(std::sync::atomic::Ordering::Relaxed),
+        fallbacks_before + 1
+    );
+    assert_eq!(
+        read,
+        vec![
+            (
+                items[2].uid().unwrap().to_string(),
+                serde_json::json!({"title": "t3"})
+                    .as_object()
+                    .unwrap()
+                    .clone()
+            ),
+            (items[1].uid().unwrap().to_string(), serde_json::Map::new()),
+            (
+                items[0].uid().unwrap().to_string(),
+                serde_json::json!({"title": "t1"})
+                    .as_object()
+                    .unwrap()
+                    .clone()
+            ),
+        ]
+    );
+    // The client side projection matches the server side one:
+    assert_eq!(
+        project_fields(
+            &serde_json::json!({"title": "t1", "status": null, "padding": "x"}).to_string(),
+            &["title", "status", "missing"]
+        ),
+        serde_json::json!({"title": "t1", "status": null})
+            .as_object()
+            .unwrap()
+            .clone()
+    );
+    assert_eq!(project_fields("[1, 2]", &["title"]), serde_json::Map::new());
+
+    // Counts and ttls shouldn't need to pull the items:
+    let counted = templist(
+        NS,
+        "counted",
+        Duration::from_secs(1),
+        Duration::from_millis(400),
+    );
+    assert_eq!(counted.len(&mut conn).await, Some(0));
+    assert_eq!(
+        counted.ttl_remaining(&mut conn).await,
+        Some(chrono::TimeDelta::zero())
+    );
+    counted
+        .extend(&mut conn, vec!["a".to_string(), "b".to_string()])
+        .await;
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    counted.push(&mut conn, "c".to_string()).await;
+    assert_eq!(counted.len(&mut conn).await, Some(3));
+    // Past the first two's expiry, they shouldn't be counted even though nothing else has cleaned them up yet:
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(counted.len(&mut conn).await, Some(1));
+    // len() counts as a read, so the ttl is near full, then shrinks until the next read:
+    let ttl = counted.ttl_remaining(&mut conn).await.unwrap();
+    assert!(ttl > chrono::TimeDelta::milliseconds(900), "{:?}", ttl);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let shrunk = counted.ttl_remaining(&mut conn).await.unwrap();
+    assert!(
+        shrunk < ttl - chrono::TimeDelta::milliseconds(80),
+        "{:?}",
+        shrunk
+    );
+    assert_eq!(counted.read_multi::<String>(&mut conn, None).await.len(), 1);
+    let reset = counted.ttl_remaining(&mut conn).await.unwrap();
+    assert!(
+        reset > shrunk + chrono::TimeDelta::milliseconds(80),
+        "{:?}",
+        reset
+    );
+    // The typed handle sees the same:
+    assert_eq!(counted.typed::<String>().len(&mut conn).await, Some(1));
+    // Redis being down shouldn't error:
+    let down = super::Redis::new(
+        "redis://FAKKEEEE:6372",
+        format!("test_{}", uuid::Uuid::new_v4()),
+    )?;
+    let down_list = down.templist(
+        NS,
+        "counted",
+        Duration::from_secs(1),
+        Duration::from_millis(400),
+    );
+    assert_eq!(down_list.len(&mut down.conn()).await, None);
+    assert_eq!(down_list.ttl_remaining(&mut down.conn()).await, None);
+
+    // <--- Archival:
+    let archived = Arc::new(parking_lot::Mutex::new(
+        Vec::<(String, serde_json::Value)>::new(),
+    ));
+    let failing = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let archiver = {
+        let archived = archived.clone();
+        let failing = failing.clone();
+        move |batch: Vec<(String, serde_json::Value)>| {
+            let archived = archived.clone();
+            let failing = failing.clone();
+            async move {
+                if failing.load(Ordering::SeqCst) {
+                    return Err(anyerr!("Sink down."));
+                }
+                archived.lock().extend(batch);
+                Ok(())
+            }
+            .boxed()
+        }
+    };
+    let base = templist(
+        NS,
+        "archived",
+        Duration::from_secs(5),
+        Duration::from_millis(500),
+    );
+    // Without an archiver sweeping errors:
+    assert!(base
+        .archive_expiring(r, Duration::from_secs(1))
+        .await
+        .is_err());
+    let sweeper_a = base.with_archiver(archiver.clone());
+    let sweeper_b = base.with_archiver(archiver);
+    let uids = base
+        .extend(
+            &mut conn,
+            vec!["a1".to_string(), "a2".to_string(), "a3".to_string()],
+        )
+        .await
+        .into_iter()
+        .map(|item| item.uid().unwrap().to_string())
+        .collect::<Vec<_>>();
+    // Not expiring within the window yet:
+    assert_eq!(
+        sweeper_a
+            .archive_expiring(r, Duration::from_millis(100))
+            .await?,
+        0
+    );
+    // Failures aren't marked, so retry next sweep:
+    failing.store(true, Ordering::SeqCst);
+    assert!(sweeper_a
+        .archive_expiring(r, Duration::from_secs(1))
+        .await
+        .is_err());
+    assert!(archived.lock().is_empty());
+    failing.store(false, Ordering::SeqCst);
+    // Concurrent sweepers archive each item exactly once:
+    let (a, b) = futures::join!(
+        sweeper_a.archive_expiring(r, Duration::from_secs(1)),
+        sweeper_b.archive_expiring(r, Duration::from_secs(1))
+    );
+    assert_eq!(a? + b?, 3);
+    assert_eq!(
+        sweeper_b
+            .archive_expiring(r, Duration::from_secs(1))
+            .await?,
+        0
+    );
+    let mut archived_uids = archived
+        .lock()
+        .iter()
+        .map(|(uid, _)| uid.clone())
+        .collect::<Vec<_>>();
+    archived_uids.sort();
+    assert_eq!(archived_uids, uids);
+    assert!(archived
+        .lock()
+        .iter()
+        .all(|(_, value)| value.as_str().unwrap().starts_with('a')));
+    // Normal reads and writes are unaffected:
+    assert_eq!(
+        RedisTempListItem::vec_items(sweeper_a.read_multi::<String>(&mut conn, None).await),
+        vec!["a3".to_string(), "a2".to_string(), "a1".to_string()]
+    );
+    let updated = uids[0].clone();
+    sweeper_a
+        .update(&mut conn, &updated, &"a1_v2".to_string())
+        .await;
+    assert_eq!(sweeper_a.len(&mut conn).await, Some(3));
+    // The update pushed back the expiry, so it's archived again with its new value:
+    assert_eq!(
+        sweeper_a
+            .archive_expiring(r, Duration::from_secs(1))
+            .await?,
+        1
+    );
+    assert_eq!(
+        archived.lock().last().cloned(),
+        Some((updated, serde_json::json!("a1_v2")))
+    );
+    // Items that have already expired are never archived:
+    archived.lock().clear();
+    sweeper_a.push(&mut conn, "late".to_string()).await;
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(
+        sweeper_a
+            .archive_expiring(r, Duration::from_secs(1))
+            .await?,
+        0
+    );
+    assert!(archived.lock().is_empty());
+    // The background archiver picks up new items:
+    let handle = sweeper_a.spawn_archiver(r, Duration::from_millis(100))?;
+    sweeper_a.push(&mut conn, "bg".to_string()).await;
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    handle.abort();
+    assert_eq!(
+        archived
+            .lock()
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<_>>(),
+        vec![serde_json::json!("bg")]
+    );
+    assert!(base.spawn_archiver(r, Duration::from_millis(100)).is_err());
+
+    // <--- Storage layouts:
+    static LAYOUT_NS: &str = "templist_layout";
+    async fn hash_len(conn: &mut RedisConn<'_>, list: &RedisTempList) -> usize {
+        let items_key = conn.final_key(LAYOUT_NS, list.items_key().into());
+        redis::cmd("HLEN")
+            .arg(items_key)
+            .query_async(conn.get_inner_conn().await.unwrap())
+            .await
+            .unwrap()
+    }
+    let layout = templist(
+        LAYOUT_NS,
+        "layout",
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+    );
+    layout
+        .extend(&mut conn, (0..200).map(|i| i.to_string()))
+        .await;
+    assert_eq!(layout.len(&mut conn).await, Some(200));
+    // Individual keys need a key per item, the hash just the list and the hash:
+    let usage = conn.namespace_usage(&[LAYOUT_NS], 0).await.unwrap();
+    assert_eq!(
+        usage[0].approx_keys,
+        match storage {
+            TempListStorage::IndividualKeys => 201,
+            TempListStorage::Hash => 2,
+        }
+    );
+    // The layout is kept through serialization:
+    let deserialized: RedisTempList =
+        serde_json::from_str(&serde_json::to_string(&*layout).change_context(AnyErr)?)
+            .change_context(AnyErr)?;
+    assert_eq!(deserialized.storage, storage);
+    layout.clear(&mut conn).await;
+    assert_eq!(
+        conn.namespace_usage(&[LAYOUT_NS], 0).await.unwrap()[0].approx_keys,
+        match storage {
+            // The values live on until their ttl:
+            TempListStorage::IndividualKeys => 200,
+            TempListStorage::Hash => 0,
+        }
+    );
+
+    // Expired values are gone from reads, and removed from the hash by the next write:
+    let expiring = templist(
+        LAYOUT_NS,
+        "expiring",
+        Duration::from_secs(5),
+        Duration::from_millis(50),
+    );
+    let items = expiring
+        .extend(
+            &mut conn,
+            vec!["e1".to_string(), "e2".to_string(), "e3".to_string()],
+        )
+        .await;
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    let kept = expiring.push(&mut conn, "e4".to_string()).await;
+    assert_eq!(
+        RedisTempListItem::vec_items(expiring.read_multi::<String>(&mut conn, None).await),
+        vec!["e4"]
+    );
+    assert!(expiring
+        .read::<String>(&mut conn, items[0].uid().unwrap())
+        .await
+        .item()
+        .is_none());
+    if storage == TempListStorage::Hash {
+        assert_eq!(hash_len(&mut conn, &expiring).await, 1);
+        // Deleting removes the value from the hash too:
+        expiring.delete(&mut conn, kept.uid().unwrap()).await;
+        assert_eq!(hash_len(&mut conn, &expiring).await, 0);
+    }
+
     Ok(())
 }
@@ -0,0 +1,394 @@
+use std::{collections::BTreeMap, marker::PhantomData, pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// A typed pubsub topic, binding a channel to its payload type at compile time.
+///
+/// Prefer declaring topics with [`crate::redis_topics`], publish with [`super::RedisConn::publish_topic`],
+/// subscribe with [`super::Redis::subscribe_topic`].
+///
+/// A payload can only be published to a topic declaring it, so mismatches won't compile:
+/// ```compile_fail
+/// use bitbazaar::{redis::Redis, redis_topics};
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct UserUpdated { id: u64 }
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct OrderPlaced { id: u64 }
+///
+/// redis_topics! {
+///     UserUpdatedTopic => ("users", "updated", UserUpdated),
+/// }
+///
+/// async fn publish(r: &Redis) {
+///     r.conn().publish_topic::<UserUpdatedTopic>(&OrderPlaced { id: 1 }).await;
+/// }
+/// ```
+pub trait RedisTopic {
+    /// The redis key namespace the channel lives in.
+    const NAMESPACE: &'static str;
+    /// The channel name within the namespace.
+    const CHANNEL: &'static str;
+    /// The type published to and received from the channel, sent as json.
+    type Payload: serde::Serialize + serde::de::DeserializeOwned;
+}
+
+/// Declare [`RedisTopic`]s concisely, each entry generates a unit struct implementing the trait.
+///
+/// ```
+/// use bitbazaar::redis_topics;
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct UserUpdated {
+///     id: u64,
+/// }
+///
+/// redis_topics! {
+///     /// Published whenever a user is changed.
+///     pub UserUpdatedTopic => ("users", "updated", UserUpdated),
+///     UserDeletedTopic => ("users", "deleted", u64),
+/// }
+/// ```
+#[macro_export]
+macro_rules! redis_topics {
+    ($($(#[$meta:meta])* $vis:vis $name:ident => ($namespace:expr, $channel:expr, $payload:ty)),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy)]
+            $vis struct $name;
+
+            impl $crate::redis::RedisTopic for $name {
+                const NAMESPACE: &'static str = $namespace;
+                const CHANNEL: &'static str = $channel;
+                type Payload = $payload;
+            }
+        )*
+    };
+}
+
+/// Debug information about a topic that's been published to or subscribed to in this process, see [`list_topics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisTopicInfo {
+    /// The namespace of the topic.
+    pub namespace: &'static str,
+    /// The channel of the topic.
+    pub channel: &'static str,
+    /// The payload's type name, only for debugging, not guaranteed to be stable.
+    pub payload_type: &'static str,
+}
+
+static TOPIC_REGISTRY: Lazy<Mutex<BTreeMap<(&'static str, &'static str), &'static str>>> =
+    Lazy::new(Mutex::default);
+
+/// Register a topic on use, logging if the channel is being used with different payload types.
+pub(crate) fn register_topic<T: RedisTopic>() {
+    let payload_type = std::any::type_name::<T::Payload>();
+    let mut registry = TOPIC_REGISTRY.lock();
+    if let Some(existing) = registry.insert((T::NAMESPACE, T::CHANNEL), payload_type) {
+        if existing != payload_type {
+            tracing::warn!(
+                "Redis topic '{}:{}' is used with multiple payload types: '{}' and '{}'.",
+                T::NAMESPACE,
+                T::CHANNEL,
+                existing,
+                payload_type
+            );
+        }
+    }
+}
+
+/// All the topics published to or subscribed to in this process so far, sorted by namespace then channel, useful for debugging.
+pub fn list_topics() -> Vec<RedisTopicInfo> {
+    TOPIC_REGISTRY
+        .lock()
+        .iter()
+        .map(|((namespace, channel), payload_type)| RedisTopicInfo {
+            namespace,
+            channel,
+            payload_type,
+        })
+        .collect()
+}
+
+/// The live listeners per channel of a [`super::Redis`] wrapper and its clones, see [`super::Redis::active_subscriptions`].
+#[derive(Debug, Default)]
+pub(crate) struct Subscriptions(Mutex<BTreeMap<String, LocalSubscription>>);
+
+impl Subscriptions {
+    /// Register a new listener, removed again when the returned registration is dropped.
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        namespace: &str,
+        channel: &str,
+        final_channel: &str,
+    ) -> SubscriptionRegistration {
+        self.0
+            .lock()
+            .entry(final_channel.to_string())
+            .or_insert_with(|| LocalSubscription {
+                namespace: namespace.to_string(),
+                channel: channel.to_string(),
+                final_channel: final_channel.to_string(),
+                listeners: 0,
+                subscribed_at_ms: chrono::Utc::now().timestamp_millis(),
+            })
+            .listeners += 1;
+        SubscriptionRegistration {
+            subscriptions: self.clone(),
+            channel: final_channel.to_string(),
+        }
+    }
+
+    pub(crate) fn active(&self) -> Vec<(String, usize)> {
+        self.0
+            .lock()
+            .iter()
+            .map(|(channel, sub)| (channel.clone(), sub.listeners))
+            .collect()
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<LocalSubscription> {
+        self.0.lock().values().cloned().collect()
+    }
+}
+
+/// Held by a listener, unregistering it when dropped.
+pub(crate) struct SubscriptionRegistration {
+    subscriptions: Arc<Subscriptions>,
+    channel: String,
+}
+
+impl Drop for SubscriptionRegistration {
+    fn drop(&mut self) {
+        let mut subscriptions = self.subscriptions.0.lock();
+        if let Some(sub) = subscriptions.get_mut(&self.channel) {
+            sub.listeners -= 1;
+            if sub.listeners == 0 {
+                subscriptions.remove(&self.channel);
+            }
+        }
+    }
+}
+
+/// A snapshot of pubsub state for debugging, see [`super::Redis::pubsub_diagnostics`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PubSubDiagnostics {
+    /// The channels with live listeners from this wrapper or its clones, sorted by final channel.
+    pub local: Vec<LocalSubscription>,
+    /// What the server reports for channels under this wrapper's prefix, None if redis is unavailable or disabled.
+    pub server: Option<ServerPubSub>,
+}
+
+/// A channel with live listeners in this process, see [`PubSubDiagnostics::local`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalSubscription {
+    /// The namespace, without the prefix.
+    pub namespace: String,
+    /// The channel, without the prefix or namespace.
+    pub channel: String,
+    /// The channel as sent to redis.
+    pub final_channel: String,
+    /// The number of live listeners.
+    pub listeners: usize,
+    /// When the first of the current listeners subscribed, as a unix timestamp in milliseconds.
+    pub subscribed_at_ms: i64,
+}
+
+/// The server's view of pubsub, see [`PubSubDiagnostics::server`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerPubSub {
+    /// Channels under the prefix with at least one subscriber from any client, sorted by final channel.
+    pub channels: Vec<ServerChannel>,
+    /// The number of pattern subscriptions across the whole server, redis can't scope these to a prefix.
+    pub patterns: usize,
+}
+
+/// A channel the server has subscribers for, see [`ServerPubSub::channels`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerChannel {
+    /// The namespace, without the prefix.
+    ///
+    /// Parsed up to the first `:` after the prefix, so nested namespaces (e.g. "a:b") end up partly in the channel.
+    pub namespace: String,
+    /// The rest of the channel after the namespace.
+    pub channel: String,
+    /// The channel as sent to redis.
+    pub final_channel: String,
+    /// Subscribers from all clients, including other processes.
+    pub subscribers: usize,
+}
+
+/// A dedicated subscription to a redis channel, receiving decoded json payloads.
+///
+/// Created with [`super::Redis::subscribe_topic`], unsubscribes when dropped, or with [`RedisChannelListener::unsubscribe`].
+pub struct RedisChannelListener<T> {
+    namespace: String,
+    registration: SubscriptionRegistration,
+    messages: Pin<Box<dyn Stream<Item = redis::Msg> + Send>>,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> std::fmt::Debug for RedisChannelListener<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisChannelListener")
+            .field("channel", &self.registration.channel)
+            .finish()
+    }
+}
+
+impl<T: serde::de::DeserializeOwned> RedisChannelListener<T> {
+    pub(crate) fn new(
+        namespace: &str,
+        registration: SubscriptionRegistration,
+        messages: impl Stream<Item = redis::Msg> + Send + 'static,
+    ) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            registration,
+            messages: Box::pin(messages),
+            _payload: PhantomData,
+        }
+    }
+
+    /// The final channel name in redis (including the prefix and namespace).
+    pub fn channel(&self) -> &str {
+        &self.registration.channel
+    }
+
+    /// The namespace the channel was subscribed to in (without the prefix).
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Stop receiving, closing the listener's dedicated connection.
+    ///
+    /// The same as dropping the listener, but explicit, it's no longer in [`super::Redis::active_subscriptions`] once this returns.
+    pub async fn unsubscribe(self) {
+        drop(self);
+    }
+
+    /// Wait for the next message on the channel.
+    ///
+    /// Messages that can't be decoded are logged and skipped.
+    /// Returns `None` once the connection is closed, create a new listener to resubscribe.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.recv_with_envelope().await.map(|(_, payload)| payload)
+    }
+
+    /// Same as [`RedisChannelListener::recv`], also returning the envelope if the message was enveloped.
+    async fn recv_with_envelope(&mut self) -> Option<(Option<(i64, String)>, T)> {
+        while let Some(msg) = self.messages.next().await {
+            match decode_message(msg.get_payload_bytes()) {
+                Ok(decoded) => return Some(decoded),
+                Err(e) => {
+                    tracing::error!(
+                        "Could not decode message on redis channel '{}', skipping. Err: '{}'",
+                        self.registration.channel,
+                        e
+                    );
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The envelope of [`super::RedisBatch::publish_enveloped`] as sent.
+#[derive(serde::Serialize)]
+pub(crate) struct EnvelopeOut<'a, T> {
+    pub ts: i64,
+    pub origin: &'a str,
+    pub payload: &'a T,
+}
+
+/// Only matches messages with exactly the envelope's fields, so plain payloads aren't mistaken for one.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvelopeIn {
+    ts: i64,
+    origin: String,
+    payload: serde_json::Value,
+}
+
+/// Decode a json message, unwrapping it if enveloped, returning the envelope's (ts, origin) alongside.
+fn decode_message<T: serde::de::DeserializeOwned>(
+    bytes: &[u8],
+) -> serde_json::Result<(Option<(i64, String)>, T)> {
+    if let Ok(envelope) = serde_json::from_slice::<EnvelopeIn>(bytes) {
+        return Ok((
+            Some((envelope.ts, envelope.origin)),
+            serde_json::from_value(envelope.payload)?,
+        ));
+    }
+    Ok((None, serde_json::from_slice(bytes)?))
+}
+
+/// A message received by a [`RedisEnvelopedListener`].
+///
+/// The metadata is `None` when the message wasn't published with [`super::RedisBatch::publish_enveloped`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopedMsg<T> {
+    /// When the message was published, by the publisher's clock.
+    pub published_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The [`super::Redis::origin`] of the publisher.
+    pub origin: Option<String>,
+    /// The time from publishing to receipt, affected by clock drift between machines so can even be slightly negative.
+    pub latency: Option<chrono::TimeDelta>,
+    /// The message itself.
+    pub payload: T,
+}
+
+/// A dedicated subscription to a redis channel, receiving messages alongside their envelope metadata.
+///
+/// Created with [`super::Redis::subscribe_enveloped`], unsubscribes when dropped.
+#[derive(Debug)]
+pub struct RedisEnvelopedListener<T> {
+    inner: RedisChannelListener<T>,
+}
+
+impl<T: serde::de::DeserializeOwned> RedisEnvelopedListener<T> {
+    pub(crate) fn new(inner: RedisChannelListener<T>) -> Self {
+        Self { inner }
+    }
+
+    /// The final channel name in redis (including the prefix and namespace).
+    pub fn channel(&self) -> &str {
+        self.inner.channel()
+    }
+
+    /// See [`RedisChannelListener::namespace`].
+    pub fn namespace(&self) -> &str {
+        self.inner.namespace()
+    }
+
+    /// See [`RedisChannelListener::unsubscribe`].
+    pub async fn unsubscribe(self) {
+        self.inner.unsubscribe().await;
+    }
+
+    /// Wait for the next message on the channel, see [`RedisChannelListener::recv`].
+    pub async fn recv(&mut self) -> Option<EnvelopedMsg<T>> {
+        let (envelope, payload) = self.inner.recv_with_envelope().await?;
+        let received_at = chrono::Utc::now();
+        Some(match envelope {
+            Some((ts, origin)) => {
+                let published_at = chrono::DateTime::from_timestamp_millis(ts);
+                EnvelopedMsg {
+                    published_at,
+                    origin: Some(origin),
+                    latency: published_at.map(|published_at| received_at - published_at),
+                    payload,
+                }
+            }
+            None => EnvelopedMsg {
+                published_at: None,
+                origin: None,
+                latency: None,
+                payload,
+            },
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::errors::prelude::*;
+
+/// Randomly spreads ttls, so keys written together (e.g. a cache warm-up after a deploy) don't all expire in the same moment,
+/// causing a stampede on whatever backs them.
+///
+/// Each ttl becomes `base ± uniform(fraction * base)`, see [`super::RedisBatch::ttl_jitter`] and [`super::CacheOpts::ttl_jitter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtlJitter {
+    fraction: f64,
+}
+
+impl TtlJitter {
+    /// Create a new jitter, `fraction` must be in `0.0..=1.0`, e.g. `0.2` for ±20%.
+    pub fn new(fraction: f64) -> RResult<Self, AnyErr> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(anyerr!(
+                "Ttl jitter fraction must be between 0 and 1, got: {}",
+                fraction
+            ));
+        }
+        Ok(Self { fraction })
+    }
+
+    /// The max fraction of the base ttl a ttl can move either way.
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+
+    /// Jitter a ttl, never going below 1ms so the key still expires rather than erroring.
+    pub fn apply(&self, base: Duration) -> Duration {
+        if self.fraction == 0.0 || base.is_zero() {
+            return base;
+        }
+        let spread = base.as_millis() as f64 * self.fraction;
+        let offset = rand::thread_rng().gen_range(-spread..=spread);
+        Duration::from_millis((base.as_millis() as f64 + offset).round().max(1.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_ttl_jitter() -> RResult<(), AnyErr> {
+        let base = Duration::from_secs(1);
+
+        // No jitter is exact:
+        let none = TtlJitter::new(0.0)?;
+        assert!((0..100).all(|_| none.apply(base) == base));
+
+        // Stays in range and actually spreads:
+        let jitter = TtlJitter::new(0.2)?;
+        let ttls = (0..1000).map(|_| jitter.apply(base)).collect::<Vec<_>>();
+        assert!(ttls
+            .iter()
+            .all(|ttl| *ttl >= Duration::from_millis(800) && *ttl <= Duration::from_millis(1200)));
+        assert!(ttls.iter().any(|ttl| *ttl < Duration::from_millis(900)));
+        assert!(ttls.iter().any(|ttl| *ttl > Duration::from_millis(1100)));
+
+        // Full jitter never reaches 0:
+        let full = TtlJitter::new(1.0)?;
+        assert!((0..1000).all(|_| full.apply(Duration::from_millis(2)) >= Duration::from_millis(1)));
+
+        // Validation:
+        for fraction in [-0.1, 1.1, f64::NAN, f64::INFINITY] {
+            assert!(TtlJitter::new(fraction).is_err(), "{}", fraction);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+use std::{future::Future, time::Duration};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{errors::LazyDebug, log::record_exception, misc::with_timeout, prelude::*};
+
+/// The default [`AsyncGuard::with_budget`].
+pub const ASYNC_GUARD_DEFAULT_BUDGET: Duration = Duration::from_secs(10);
+
+type Cleanup = Box<dyn FnOnce() -> BoxFuture<'static, RResult<(), AnyErr>> + Send>;
+
+/// Async cleanup that runs when a scope ends, even if the future owning the guard is cancelled (dropped) before getting there.
+///
+/// Call [`AsyncGuard::disarm_and_run`] at the end of the scope to run the cleanup inline.
+/// If the guard is dropped instead, the cleanup is spawned in the background, limited to the guard's budget:
+/// - Native: onto the tokio runtime the guard was created in.
+/// - Wasm: with `spawn_local`.
+///
+/// When created outside a runtime, the [`AsyncGuard::with_sync_fallback`] runs on drop instead, if set.
+///
+/// The cleanup runs at most once, errors from background runs are recorded with [`record_exception`].
+/// Running consumes the guard, so it can't be run twice:
+/// ```compile_fail
+/// use bitbazaar::threads::AsyncGuard;
+///
+/// async fn twice() {
+///     let guard = AsyncGuard::new(|| async { Ok(()) });
+///     guard.disarm_and_run().await.unwrap();
+///     guard.disarm_and_run().await.unwrap();
+/// }
+/// ```
+pub struct AsyncGuard {
+    cleanup: Option<Cleanup>,
+    // Only used natively, wasm always has somewhere to spawn:
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    sync_fallback: Option<Box<dyn FnOnce() + Send>>,
+    budget: Duration,
+    #[cfg(not(target_arch = "wasm32"))]
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+impl AsyncGuard {
+    /// Create a guard that will run `cleanup` once, either from [`AsyncGuard::disarm_and_run`] or on drop.
+    ///
+    /// Natively, the current tokio runtime (if any) is captured here to spawn onto if dropped.
+    pub fn new<Fut>(cleanup: impl FnOnce() -> Fut + Send + 'static) -> Self
+    where
+        Fut: Future<Output = RResult<(), AnyErr>> + Send + 'static,
+    {
+        Self {
+            cleanup: Some(Box::new(move || cleanup().boxed())),
+            sync_fallback: None,
+            budget: ASYNC_GUARD_DEFAULT_BUDGET,
+            #[cfg(not(target_arch = "wasm32"))]
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        }
+    }
+
+    /// Run this instead of the async cleanup when dropped without a runtime to spawn onto.
+    pub fn with_sync_fallback(mut self, fallback: impl FnOnce() + Send + 'static) -> Self {
+        self.sync_fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// The max time the cleanup can run in the background when dropped before it's abandoned, defaults to [`ASYNC_GUARD_DEFAULT_BUDGET`].
+    ///
+    /// Doesn't apply to [`AsyncGuard::disarm_and_run`], which the caller can time out themselves.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Run the cleanup inline, the normal end of scope path. Nothing runs on drop afterwards.
+    pub async fn disarm_and_run(mut self) -> RResult<(), AnyErr> {
+        match self.cleanup.take() {
+            Some(cleanup) => cleanup().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for AsyncGuard {
+    fn drop(&mut self) {
+        let Some(cleanup) = self.cleanup.take() else {
+            return;
+        };
+        let budget = self.budget;
+        let run = async move {
+            match with_timeout(budget, cleanup()).await {
+                Ok((Ok(()), _)) => {}
+                Ok((Err(e), _)) => record_exception("Async guard cleanup failed.", LazyDebug(&e)),
+                Err(waited) => record_exception(
+                    format!(
+                        "Async guard cleanup exceeded its budget, abandoned after {:?}.",
+                        waited
+                    ),
+                    "",
+                ),
+            }
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(run);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        match &self.runtime {
+            Some(runtime) => {
+                runtime.spawn(run);
+            }
+            None => match self.sync_fallback.take() {
+                Some(fallback) => fallback(),
+                None => record_exception(
+                    "Async guard dropped outside a runtime without a sync fallback, cleanup skipped.",
+                    "",
+                ),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use rstest::*;
+
+    use super::*;
+
+    fn counting_guard(runs: &Arc<AtomicUsize>) -> AsyncGuard {
+        let runs = runs.clone();
+        AsyncGuard::new(move || async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    async fn wait_for_runs(runs: &AtomicUsize, expected: usize) {
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while runs.load(Ordering::SeqCst) < expected {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_async_guard_disarm_and_run() -> RResult<(), AnyErr> {
+        let runs = Arc::new(AtomicUsize::new(0));
+        counting_guard(&runs).disarm_and_run().await?;
+        // Ran inline, so already done:
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        // Nothing else spawned on drop:
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Errors come back to the caller:
+        let failing = AsyncGuard::new(|| async { Err(anyerr!("Cleanup failed.")) });
+        assert!(failing.disarm_and_run().await.is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_async_guard_cancelled() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let task = tokio::spawn({
+            let runs = runs.clone();
+            async move {
+                let guard = counting_guard(&runs);
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                guard.disarm_and_run().await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        task.abort();
+        wait_for_runs(&runs, 1).await;
+
+        // Plain drops run it too, just once:
+        drop(counting_guard(&runs));
+        wait_for_runs(&runs, 2).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_async_guard_budget() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let guard = {
+            let runs = runs.clone();
+            AsyncGuard::new(move || async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                runs.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .with_budget(Duration::from_millis(20))
+        };
+        drop(guard);
+        // Abandoned before it could finish:
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[rstest]
+    fn test_async_guard_no_runtime() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let fallback_runs = Arc::new(AtomicUsize::new(0));
+        let guard = counting_guard(&runs).with_sync_fallback({
+            let fallback_runs = fallback_runs.clone();
+            move || {
+                fallback_runs.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        drop(guard);
+        assert_eq!(fallback_runs.load(Ordering::SeqCst), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        // Without a fallback, it's just skipped:
+        drop(counting_guard(&runs));
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+}
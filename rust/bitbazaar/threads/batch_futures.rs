@@ -6,17 +6,33 @@ use std::sync::Arc;
 
 use async_semaphore::Semaphore;
 use futures::{
+    future::Either,
     select,
     stream::{FuturesOrdered, FuturesUnordered},
-    Future, FutureExt, StreamExt,
+    Future, FutureExt, Stream, StreamExt,
 };
 
-use crate::misc::sleep_compat;
+use crate::misc::{sleep_compat, yield_compat};
+
+/// The flat batchers yield to the executor after this many futures pushed or completed without yielding.
+/// Otherwise many near-instant futures can monopolize the executor, starving unrelated tasks on the same runtime.
+pub const BATCH_YIELD_EVERY: usize = 64;
 
 macro_rules! batch_futures_flat_impl {
-    ($limit:expr, $fut_cbs:expr, |$result:ident| $call_cb:expr) => {{
+    ($limit:expr, $yield_every:expr, $fut_cbs:expr, |$result:ident| $call_cb:expr) => {{
         let mut return_index = 0;
         let mut result_cache: HashMap<usize, R> = HashMap::new();
+        let mut since_yield: usize = 0;
+
+        macro_rules! maybe_yield {
+            () => {
+                since_yield += 1;
+                if since_yield >= $yield_every {
+                    since_yield = 0;
+                    yield_compat().await;
+                }
+            };
+        }
 
         macro_rules! manage_results {
             ($index:expr, $current_result:expr) => {
@@ -50,11 +66,14 @@ macro_rules! batch_futures_flat_impl {
                     manage_results!(index, result);
                 }
             }
+            // Large iterators with a high limit could otherwise push everything without ever yielding:
+            maybe_yield!();
         }
 
         // Wait for the remaining to finish.
         while let Some((index, result)) = stream.next().await {
             manage_results!(index, result);
+            maybe_yield!();
         }
 
         Ok(())
@@ -80,6 +99,31 @@ pub async fn batch_futures_flat<R, Fut: Future<Output = R>>(
     results
 }
 
+/// A [`Stream`] version of [`batch_futures_flat`], for interop with stream combinators.
+/// No background task is needed, the futures are driven whilst the returned stream is polled.
+///
+/// Future creators are only pulled from the source stream when there's capacity under the limit,
+/// meaning a source stream gets natural backpressure.
+/// The returned stream ends once the source stream ends and all results have been yielded.
+///
+/// Arguments:
+/// - `limit`: The maximum number of futures to run concurrently.
+/// - `ordered`: When true, results are yielded in the same order as the source, otherwise as soon as each completes.
+/// - `fut_cbs`: The source of future creators, an iterator can be passed by wrapping in [`futures::stream::iter`].
+pub fn batch_futures_flat_stream<R, Fut: Future<Output = R>>(
+    limit: usize,
+    ordered: bool,
+    fut_cbs: impl Stream<Item = impl FnOnce() -> Fut>,
+) -> impl Stream<Item = R> {
+    // A limit of 0 would never make progress:
+    let limit = limit.max(1);
+    if ordered {
+        Either::Left(fut_cbs.map(|fut_cb| fut_cb()).buffered(limit))
+    } else {
+        Either::Right(fut_cbs.map(|fut_cb| fut_cb()).buffer_unordered(limit))
+    }
+}
+
 /// A more performant version of [`batch_futures_descendants`] due to no descendant requirement.
 /// By removing descendant limiting, semaphores no longer need to be used.
 pub async fn batch_futures_flat_stream_async_cb<
@@ -92,7 +136,7 @@ pub async fn batch_futures_flat_stream_async_cb<
     fut_cbs: impl IntoIterator<Item = impl FnOnce() -> Fut>,
     result_sync_cb: impl Fn(R) -> CbFut,
 ) -> Result<(), E> {
-    batch_futures_flat_impl!(limit, fut_cbs, |result| {
+    batch_futures_flat_impl!(limit, BATCH_YIELD_EVERY, fut_cbs, |result| {
         result_sync_cb(result).await?;
     })
 }
@@ -102,9 +146,18 @@ pub async fn batch_futures_flat_stream_async_cb<
 pub async fn batch_futures_flat_stream_sync_cb<R, Fut: Future<Output = R>, E>(
     limit: usize,
     fut_cbs: impl IntoIterator<Item = impl FnOnce() -> Fut>,
+    result_sync_cb: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E> {
+    batch_futures_flat_stream_sync_cb_inner(limit, BATCH_YIELD_EVERY, fut_cbs, result_sync_cb).await
+}
+
+async fn batch_futures_flat_stream_sync_cb_inner<R, Fut: Future<Output = R>, E>(
+    limit: usize,
+    yield_every: usize,
+    fut_cbs: impl IntoIterator<Item = impl FnOnce() -> Fut>,
     mut result_sync_cb: impl FnMut(R) -> Result<(), E>,
 ) -> Result<(), E> {
-    batch_futures_flat_impl!(limit, fut_cbs, |result| {
+    batch_futures_flat_impl!(limit, yield_every, fut_cbs, |result| {
         result_sync_cb(result)?;
     })
 }
@@ -242,8 +295,14 @@ macro_rules! batch_futures_descendants_impl {
         }
 
         // All the tasks spawned off, process the remaining in the stream:
+        let mut since_yield: usize = 0;
         while let Some($result) = stream.next().await {
             $call_cb
+            since_yield += 1;
+            if since_yield >= BATCH_YIELD_EVERY {
+                since_yield = 0;
+                yield_compat().await;
+            }
         }
 
         Ok(())
@@ -276,3 +335,137 @@ pub async fn batch_futures_descendants_stream_async_cb<
         result_async_cb(result).await?;
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_batch_futures_flat_stream(#[values(true, false)] ordered: bool) {
+        let make_cbs = || {
+            (0..20).map(|i| {
+                move || async move {
+                    // Make earlier futures slower, so unordered would actually come out differently:
+                    sleep_compat(std::time::Duration::from_millis(20 - i)).await;
+                    i
+                }
+            })
+        };
+
+        let expected = batch_futures_flat(5, make_cbs()).await;
+        let mut streamed = batch_futures_flat_stream(5, ordered, futures::stream::iter(make_cbs()))
+            .collect::<Vec<_>>()
+            .await;
+        if !ordered {
+            streamed.sort();
+        }
+        assert_eq!(streamed, expected);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_batch_futures_flat_stream_backpressure() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let pulled = Arc::new(AtomicUsize::new(0));
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        for i in 0..30 {
+            let active = active.clone();
+            let max_active = max_active.clone();
+            tx.unbounded_send(move || async move {
+                let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max_active.fetch_max(now_active, Ordering::SeqCst);
+                sleep_compat(std::time::Duration::from_millis(5)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                i
+            })
+            .unwrap();
+        }
+        // Ending the source should end the output stream once all results are yielded:
+        drop(tx);
+
+        let pulled_inner = pulled.clone();
+        let results = batch_futures_flat_stream(
+            4,
+            true,
+            rx.inspect(move |_| {
+                pulled_inner.fetch_add(1, Ordering::SeqCst);
+            }),
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(results, (0..30).collect::<Vec<_>>());
+        assert_eq!(pulled.load(Ordering::SeqCst), 30);
+        assert_eq!(max_active.load(Ordering::SeqCst), 4);
+    }
+
+    /// Join 50k near-instant futures whilst another task ticks every 1ms, returning the max gap seen between ticks.
+    async fn max_tick_gap_during_join(yield_every: usize) -> std::time::Duration {
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let ticker = tokio::spawn({
+            let stop = stop.clone();
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_millis(1));
+                let mut last = std::time::Instant::now();
+                let mut max_gap = std::time::Duration::ZERO;
+                while !stop.load(Ordering::SeqCst) {
+                    interval.tick().await;
+                    max_gap = max_gap.max(last.elapsed());
+                    last = std::time::Instant::now();
+                }
+                max_gap
+            }
+        });
+        // Let the ticker get going:
+        sleep_compat(std::time::Duration::from_millis(5)).await;
+
+        let mut count = 0;
+        batch_futures_flat_stream_sync_cb_inner(
+            50_000,
+            yield_every,
+            (0..50_000).map(|i| move || async move { i }),
+            |_| {
+                count += 1;
+                Ok::<(), ()>(())
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 50_000);
+
+        stop.store(true, Ordering::SeqCst);
+        ticker.await.unwrap()
+    }
+
+    #[rstest]
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_batch_futures_flat_yields() {
+        let max_gap = max_tick_gap_during_join(BATCH_YIELD_EVERY).await;
+        assert!(
+            max_gap < std::time::Duration::from_millis(5),
+            "{:?}",
+            max_gap
+        );
+    }
+
+    /// For contrast, without yielding the ticker is starved for the whole join.
+    /// Ignored as it's timing dependant and only demonstrates the problem.
+    #[rstest]
+    #[ignore]
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_batch_futures_flat_no_yield_starves() {
+        let max_gap = max_tick_gap_during_join(usize::MAX).await;
+        assert!(
+            max_gap > std::time::Duration::from_millis(5),
+            "{:?}",
+            max_gap
+        );
+    }
+}
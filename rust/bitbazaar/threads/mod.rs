@@ -1,7 +1,11 @@
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+mod async_guard;
 mod batch_futures;
 #[cfg(feature = "rayon")]
 mod run_cpu_intensive;
 
+#[cfg(any(target_arch = "wasm32", feature = "rt"))]
+pub use async_guard::*;
 pub use batch_futures::*;
 #[cfg(feature = "rayon")]
 pub use run_cpu_intensive::*;
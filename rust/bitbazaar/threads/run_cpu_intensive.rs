@@ -1,3 +1,8 @@
+use std::{collections::HashMap, panic::AssertUnwindSafe};
+
+use futures::Stream;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
 use crate::prelude::*;
 
 /// The following link explains the distinction between normal tokio, [`tokio::task::spawn_blocking`], and rayon.
@@ -30,6 +35,172 @@ pub async fn run_cpu_intensive<R: Send + 'static>(
     recv.await.change_context(AnyErr)?
 }
 
+/// Fan `inputs` across the rayon pool from async code, streaming each result back as soon as it's ready, in completion order.
+/// See [`run_cpu_intensive_stream_ordered`] to keep the order of the inputs.
+///
+/// The tokio runtime is never blocked, like [`run_cpu_intensive`] the work runs entirely on rayon inside the active tracing span.
+/// A panic in `f` comes through as an error item for that input, the others still complete.
+/// Dropping the stream skips the inputs that haven't started yet.
+pub fn run_cpu_intensive_stream<I: Send + 'static, R: Send + 'static>(
+    inputs: Vec<I>,
+    f: impl Fn(I) -> R + Send + Sync + 'static,
+) -> impl Stream<Item = RResult<R, AnyErr>> {
+    futures::stream::unfold(spawn_par_map(inputs, f), |mut recv| async move {
+        let (_, result) = recv.recv().await?;
+        Some((result, recv))
+    })
+}
+
+/// [`run_cpu_intensive_stream`], but yielding the results in the same order as the inputs.
+///
+/// Results finishing early are held until those before them are yielded.
+pub fn run_cpu_intensive_stream_ordered<I: Send + 'static, R: Send + 'static>(
+    inputs: Vec<I>,
+    f: impl Fn(I) -> R + Send + Sync + 'static,
+) -> impl Stream<Item = RResult<R, AnyErr>> {
+    futures::stream::unfold(
+        (spawn_par_map(inputs, f), 0, HashMap::new()),
+        |(mut recv, next_index, mut cache)| async move {
+            let result = match cache.remove(&next_index) {
+                Some(result) => result,
+                None => loop {
+                    let (index, result) = recv.recv().await?;
+                    if index == next_index {
+                        break result;
+                    }
+                    cache.insert(index, result);
+                },
+            };
+            Some((result, (recv, next_index + 1, cache)))
+        },
+    )
+}
+
+/// Run `f` over the inputs in parallel on rayon, sending back each result with the index of its input.
+fn spawn_par_map<I: Send + 'static, R: Send + 'static>(
+    inputs: Vec<I>,
+    f: impl Fn(I) -> R + Send + Sync + 'static,
+) -> tokio::sync::mpsc::UnboundedReceiver<(usize, RResult<R, AnyErr>)> {
+    // Same as run_cpu_intensive, connects the rayon work to the current span:
+    let connector_span = tracing::span!(tracing::Level::INFO, "run_cpu_intensive_stream");
+    let (send, recv) = tokio::sync::mpsc::unbounded_channel();
+
+    rayon::spawn(move || {
+        connector_span.in_scope(move || {
+            inputs
+                .into_par_iter()
+                .enumerate()
+                .for_each_with(send, |send, (index, input)| {
+                    // The stream was dropped, nothing to send to:
+                    if send.is_closed() {
+                        return;
+                    }
+                    // Unwinding out of a rayon::spawn would abort the process, so catching per input:
+                    let result =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| f(input))).map_err(|panic| {
+                            let message = panic
+                                .downcast_ref::<&str>()
+                                .map(|s| s.to_string())
+                                .or_else(|| panic.downcast_ref::<String>().cloned())
+                                .unwrap_or_default();
+                            anyerr!("Panicked processing input {}: {}", index, message)
+                        });
+                    let _ = send.send((index, result));
+                });
+        })
+    });
+
+    recv
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use futures::StreamExt;
+    use rstest::*;
+
+    use super::*;
+
+    /// Something slow enough to keep the rayon pool busy for a while.
+    fn slow_hash(input: u64) -> u64 {
+        let mut value = input;
+        for _ in 0..20_000 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            value = hasher.finish();
+        }
+        value
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_run_cpu_intensive_stream(#[values(false, true)] ordered: bool) {
+        let inputs = (0..1000).collect::<Vec<u64>>();
+        let expected = inputs
+            .iter()
+            .map(|input| slow_hash(*input))
+            .collect::<Vec<_>>();
+
+        // The runtime should stay responsive whilst rayon is busy:
+        let timer = tokio::spawn(async {
+            let started = std::time::Instant::now();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            started.elapsed()
+        });
+
+        let stream = if ordered {
+            run_cpu_intensive_stream_ordered(inputs, slow_hash).boxed()
+        } else {
+            run_cpu_intensive_stream(inputs, slow_hash).boxed()
+        };
+        let mut results = stream
+            .map(|result| result.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        let timer_elapsed = timer.await.unwrap();
+        assert!(
+            timer_elapsed < std::time::Duration::from_millis(100),
+            "{:?}",
+            timer_elapsed
+        );
+
+        if !ordered {
+            results.sort();
+            let mut expected = expected.clone();
+            expected.sort();
+            assert_eq!(results, expected);
+        } else {
+            assert_eq!(results, expected);
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_run_cpu_intensive_stream_panics() {
+        let results = run_cpu_intensive_stream_ordered((0..100).collect(), |input: u32| {
+            if input == 50 {
+                panic!("Bad input.");
+            }
+            input * 2
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+        // Only the panicking input errors, the stream still finishes:
+        assert_eq!(results.len(), 100);
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(value) => assert_eq!(value, index as u32 * 2),
+                Err(e) => {
+                    assert_eq!(index, 50);
+                    assert!(format!("{:?}", e).contains("Bad input."));
+                }
+            }
+        }
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use bitbazaar::log::GlobalLog;